@@ -0,0 +1,605 @@
+//! 10x/linked-read 条形码（barcode）质控模块。
+//!
+//! 统计带有有效条形码标签（BX、CB或自定义标签）的读长占比、
+//! 不同条形码数量（HyperLogLog近似计数）以及每个条形码读长数的分布
+//! （有界top-k计数，用于N50与头部1%条形码读长占比）。
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+#[cfg(feature = "serde")]
+use serde::{Serialize, Serializer};
+use thiserror::Error;
+use bamqc_io::bam::{BamReaderOptions, BamError, BamRecord};
+use bamqc_io::lossy::decode_lossy;
+#[cfg(feature = "serde")]
+use bamqc_io::serialize_error;
+use bamqc_io::ErrorCode;
+use crate::formatting::Percent;
+use crate::interim::InterimEmitter;
+use crate::log::{info, debug};
+use crate::metrics::Unit;
+use crate::progress::progress_message;
+use crate::metrics_keys::metric_keys;
+use crate::rng::{derive_seed, DEFAULT_SEED};
+
+/// HyperLogLog默认精度（2^14个寄存器，标准误差约0.81%）。
+pub const DEFAULT_HLL_PRECISION: u8 = 14;
+
+/// top-k计数器默认保留的条形码数量。
+pub const DEFAULT_TOP_K: usize = 100_000;
+
+/// 条形码统计过程中可能发生的错误。
+///
+/// 错误代码与JSON形式见[`bamqc_io::error_code`]：
+///
+/// ```
+/// # #[cfg(feature = "serde")]
+/// # {
+/// use bamqc_core::BarcodeError;
+///
+/// let e = BarcodeError::InvalidTag("BXX".into());
+/// assert_eq!(
+///     serde_json::to_string(&e).unwrap(),
+///     r#"{"code":"invalid_tag","message":"无效的标签名: BXX（必须为两个字符，例如BX或CB）","details":{"tag":"BXX"}}"#
+/// );
+/// # }
+/// ```
+#[derive(Error, Debug)]
+pub enum BarcodeError {
+    /// 标签名不合法。
+    ///
+    /// SAM辅助标签必须是两个字符，例如`BX`或`CB`。
+    #[error("无效的标签名: {0}（必须为两个字符，例如BX或CB）")]
+    InvalidTag(String),
+
+    /// BAM文件IO错误。
+    #[error("BAM文件IO错误: {0}")]
+    BamError(#[from] BamError),
+}
+
+impl ErrorCode for BarcodeError {
+    fn code(&self) -> &'static str {
+        match self {
+            BarcodeError::InvalidTag(_) => "invalid_tag",
+            BarcodeError::BamError(e) => e.code(),
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    fn serialize_details<M: serde::ser::SerializeMap>(&self, map: &mut M) -> Result<(), M::Error> {
+        match self {
+            BarcodeError::InvalidTag(tag) => map.serialize_entry("tag", tag),
+            BarcodeError::BamError(e) => e.serialize_details(map),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for BarcodeError {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_error(self, serializer)
+    }
+}
+
+/// 常用的条形码标签预设。
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BarcodeTag {
+    /// 10x linked-read条形码（`BX:Z`）。
+    Bx,
+    /// 单细胞细胞条形码（`CB:Z`）。
+    Cb,
+    /// 用户指定的任意两字符标签。
+    Custom([u8; 2]),
+}
+
+impl BarcodeTag {
+    /// 返回对应的两字节标签。
+    pub fn as_bytes(&self) -> [u8; 2] {
+        match self {
+            BarcodeTag::Bx => *b"BX",
+            BarcodeTag::Cb => *b"CB",
+            BarcodeTag::Custom(tag) => *tag,
+        }
+    }
+}
+
+impl std::str::FromStr for BarcodeTag {
+    type Err = BarcodeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.as_bytes() {
+            b"BX" => Ok(BarcodeTag::Bx),
+            b"CB" => Ok(BarcodeTag::Cb),
+            [a, b] if a.is_ascii_alphabetic() && b.is_ascii_alphanumeric() => {
+                Ok(BarcodeTag::Custom([*a, *b]))
+            }
+            _ => Err(BarcodeError::InvalidTag(s.to_string())),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for BarcodeTag {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl fmt::Display for BarcodeTag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let tag = self.as_bytes();
+        write!(f, "{}{}", tag[0] as char, tag[1] as char)
+    }
+}
+
+/// 条形码规范化选项。
+#[derive(Clone, Debug)]
+pub struct BarcodeOptions {
+    /// 条形码所在的标签。
+    pub tag: BarcodeTag,
+    /// 去除`-1`之类的GEM well后缀（`ACGT-1` -> `ACGT`）。
+    pub strip_suffix: bool,
+    /// 统一转换为大写后再校验。
+    pub uppercase: bool,
+    /// 允许条形码中出现`N`。
+    pub allow_n: bool,
+    /// top-k计数器保留的条形码数量上限。
+    pub top_k: usize,
+    /// 根种子，不同条形码数的HyperLogLog哈希使用由它派生的子种子（见[`crate::rng`]）。
+    pub seed: u64,
+    /// 打开输入的读取器配置（解压线程数、输入格式、CRAM参考序列等）。
+    pub reader: BamReaderOptions,
+}
+
+impl Default for BarcodeOptions {
+    fn default() -> Self {
+        Self {
+            tag: BarcodeTag::Bx,
+            strip_suffix: true,
+            uppercase: false,
+            allow_n: false,
+            top_k: DEFAULT_TOP_K,
+            seed: DEFAULT_SEED,
+            reader: BamReaderOptions::new(),
+        }
+    }
+}
+
+impl BarcodeOptions {
+    /// 规范化并校验条形码。
+    ///
+    /// 返回规范化后的条形码（不需要改写大小写时借用输入）；若条形码为空或包含
+    /// 非法字符则返回None。
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bamqc_core::BarcodeOptions;
+    ///
+    /// let options = BarcodeOptions::default();
+    /// assert_eq!(options.normalize("ACGT-1").as_deref(), Some("ACGT"));
+    /// assert_eq!(options.normalize("ACNT-1"), None);
+    /// assert_eq!(options.normalize("-1"), None);
+    /// ```
+    pub fn normalize<'a>(&self, raw: &'a str) -> Option<Cow<'a, str>> {
+        let mut barcode = raw.trim();
+        if self.strip_suffix {
+            if let Some((head, suffix)) = barcode.rsplit_once('-') {
+                if !suffix.is_empty() && suffix.bytes().all(|b| b.is_ascii_digit()) {
+                    barcode = head;
+                }
+            }
+        }
+
+        let barcode = if self.uppercase && barcode.bytes().any(|b| b.is_ascii_lowercase()) {
+            Cow::Owned(barcode.to_ascii_uppercase())
+        } else {
+            Cow::Borrowed(barcode)
+        };
+
+        if barcode.is_empty() {
+            return None;
+        }
+
+        let valid = barcode.bytes().all(|b| match b {
+            b'A' | b'C' | b'G' | b'T' => true,
+            b'N' => self.allow_n,
+            _ => false,
+        });
+
+        if valid {
+            Some(barcode)
+        } else {
+            None
+        }
+    }
+}
+
+/// 条形码HyperLogLog哈希的随机数组件名（见[`crate::rng`]）。
+pub const BARCODE_SEED_COMPONENT: &str = "barcode.hll";
+
+/// 带种子的64位哈希（FNV-1a与种子混合后splitmix64终混），保证跨平台、跨版本结果一致。
+fn hash64(bytes: &[u8], seed: u64) -> u64 {
+    let mut h: u64 = 0xcbf29ce484222325;
+    for &b in bytes {
+        h ^= b as u64;
+        h = h.wrapping_mul(0x100000001b3);
+    }
+    h ^= seed;
+    h ^= h >> 30;
+    h = h.wrapping_mul(0xbf58476d1ce4e5b9);
+    h ^= h >> 27;
+    h = h.wrapping_mul(0x94d049bb133111eb);
+    h ^ (h >> 31)
+}
+
+/// HyperLogLog近似基数计数器。
+///
+/// 使用`2^precision`个寄存器，标准误差约为`1.04 / sqrt(2^precision)`。
+///
+/// # Examples
+///
+/// ```
+/// use bamqc_core::HyperLogLog;
+///
+/// let mut hll = HyperLogLog::new(14);
+/// for i in 0..100_000u32 {
+///     hll.insert(format!("BC{}", i).as_bytes());
+/// }
+/// let estimate = hll.estimate();
+/// // 4倍标准误差之内
+/// assert!((estimate - 100_000.0).abs() / 100_000.0 < 4.0 * hll.standard_error());
+/// ```
+#[derive(Clone, Debug)]
+pub struct HyperLogLog {
+    precision: u8,
+    seed: u64,
+    registers: Vec<u8>,
+}
+
+impl HyperLogLog {
+    /// 创建精度为`precision`（4..=18）的计数器。
+    pub fn new(precision: u8) -> Self {
+        Self::with_seed(precision, 0)
+    }
+
+    /// 创建哈希种子为`seed`的计数器：不同种子的估计值各自服从同样的误差分布，
+    /// 相同种子对相同输入的估计值逐位相同。
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bamqc_core::HyperLogLog;
+    ///
+    /// let estimate = |seed| {
+    ///     let mut hll = HyperLogLog::with_seed(10, seed);
+    ///     for i in 0..20_000u32 {
+    ///         hll.insert(format!("BC{}", i).as_bytes());
+    ///     }
+    ///     (hll.estimate(), hll.standard_error())
+    /// };
+    /// let (a, error) = estimate(1);
+    /// assert_eq!(estimate(1).0, a);
+    /// let (b, _) = estimate(2);
+    /// assert_ne!(a, b);
+    /// assert!((b - 20_000.0).abs() / 20_000.0 < 4.0 * error);
+    /// ```
+    pub fn with_seed(precision: u8, seed: u64) -> Self {
+        let precision = precision.clamp(4, 18);
+        Self {
+            precision,
+            seed,
+            registers: vec![0; 1 << precision],
+        }
+    }
+
+    /// 插入一个元素。
+    pub fn insert(&mut self, item: &[u8]) {
+        let hash = hash64(item, self.seed);
+        let index = (hash >> (64 - self.precision)) as usize;
+        let rest = hash << self.precision;
+        let rank = (rest.leading_zeros() as u8).min(64 - self.precision) + 1;
+        if rank > self.registers[index] {
+            self.registers[index] = rank;
+        }
+    }
+
+    /// 理论标准误差。
+    pub fn standard_error(&self) -> f64 {
+        1.04 / (self.registers.len() as f64).sqrt()
+    }
+
+    /// 估计不同元素的数量。
+    ///
+    /// 小基数时使用线性计数修正。
+    pub fn estimate(&self) -> f64 {
+        let m = self.registers.len() as f64;
+        let alpha = match self.registers.len() {
+            16 => 0.673,
+            32 => 0.697,
+            64 => 0.709,
+            _ => 0.7213 / (1.0 + 1.079 / m),
+        };
+
+        let sum: f64 = self.registers.iter().map(|&r| 2f64.powi(-(r as i32))).sum();
+        let raw = alpha * m * m / sum;
+
+        let zeros = self.registers.iter().filter(|&&r| r == 0).count();
+        if raw <= 2.5 * m && zeros > 0 {
+            m * (m / zeros as f64).ln()
+        } else {
+            raw
+        }
+    }
+}
+
+/// 有界top-k计数器。
+///
+/// 精确计数直到不同元素数超过`2 * capacity`，此时裁剪到计数最高的
+/// `capacity`个元素。被裁剪元素再次出现时从0重新计数，因此只有在
+/// 不同元素数超过容量时结果才是近似的（计数偏低）。一个元素每次裁剪
+/// 最多丢失该次被丢弃的最大计数，误差上界是各次裁剪中这一计数之和。
+#[derive(Clone, Debug)]
+pub struct TopKCounter {
+    capacity: usize,
+    counts: HashMap<String, u64>,
+    /// 历次裁剪中各自被丢弃的最大计数之和，作为计数误差上界。
+    pruned_bound: u64,
+}
+
+impl TopKCounter {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            counts: HashMap::new(),
+            pruned_bound: 0,
+        }
+    }
+
+    /// 计数加一。
+    pub fn insert(&mut self, item: &str) {
+        if let Some(count) = self.counts.get_mut(item) {
+            *count += 1;
+            return;
+        }
+        self.counts.insert(item.to_string(), 1);
+        if self.counts.len() > 2 * self.capacity {
+            self.prune();
+        }
+    }
+
+    fn prune(&mut self) {
+        let mut entries: Vec<(String, u64)> = self.counts.drain().collect();
+        entries.sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        let max_pruned = entries.iter().skip(self.capacity).map(|(_, count)| *count).max().unwrap_or(0);
+        self.pruned_bound += max_pruned;
+        entries.truncate(self.capacity);
+        self.counts.extend(entries);
+    }
+
+    /// 计数误差上界（未发生裁剪时为0，即结果精确）。
+    pub fn error_bound(&self) -> u64 {
+        self.pruned_bound
+    }
+
+    /// 按计数降序返回所有被跟踪的元素（计数相同时按名称排序）。
+    pub fn sorted(&self) -> Vec<(String, u64)> {
+        let mut entries: Vec<(String, u64)> =
+            self.counts.iter().map(|(k, v)| (k.clone(), *v)).collect();
+        entries.sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        entries.truncate(self.capacity);
+        entries
+    }
+}
+
+/// 条形码统计收集器。
+#[derive(Debug)]
+pub struct BarcodeCollector {
+    options: BarcodeOptions,
+    total_reads: u64,
+    reads_with_tag: u64,
+    valid_reads: u64,
+    distinct: HyperLogLog,
+    top: TopKCounter,
+}
+
+impl BarcodeCollector {
+    pub fn new(options: BarcodeOptions) -> Self {
+        let top = TopKCounter::new(options.top_k);
+        let distinct = HyperLogLog::with_seed(DEFAULT_HLL_PRECISION, derive_seed(options.seed, BARCODE_SEED_COMPONENT));
+        Self {
+            options,
+            total_reads: 0,
+            reads_with_tag: 0,
+            valid_reads: 0,
+            distinct,
+            top,
+        }
+    }
+
+    /// 处理一条记录。
+    ///
+    /// 仅统计主要比对（跳过secondary/supplementary和QC失败的记录）。
+    pub fn update(&mut self, record: &BamRecord) {
+        if record.is_secondary() || record.is_supplementary() || record.is_qc_fail() {
+            return;
+        }
+        record.with_aux_bytes(self.options.tag.as_bytes(), |raw| {
+            self.add(raw.map(decode_lossy).as_deref())
+        });
+    }
+
+    /// 添加一个（可能不存在的）原始条形码值。
+    pub fn add(&mut self, raw: Option<&str>) {
+        self.total_reads += 1;
+
+        let Some(raw) = raw else {
+            return;
+        };
+        self.reads_with_tag += 1;
+
+        if let Some(barcode) = self.options.normalize(raw) {
+            self.valid_reads += 1;
+            self.distinct.insert(barcode.as_bytes());
+            self.top.insert(&barcode);
+        }
+    }
+
+    /// 汇总统计结果。
+    pub fn summary(&self) -> BarcodeSummary {
+        let sorted = self.top.sorted();
+        let distinct = (self.distinct.estimate().round() as u64).max(sorted.len() as u64);
+
+        // N50：按读长数降序累加，首次覆盖50%有效读长时的条形码读长数
+        let mut n50 = 0;
+        let mut running = 0;
+        for (_, count) in &sorted {
+            running += count;
+            n50 = *count;
+            if running * 2 >= self.valid_reads {
+                break;
+            }
+        }
+
+        // 头部1%条形码的读长占比
+        let top_n = (distinct as f64 * 0.01).ceil().max(1.0) as usize;
+        let top_reads: u64 = sorted.iter().take(top_n).map(|(_, c)| c).sum();
+        let top1pct_fraction = if self.valid_reads == 0 {
+            0.0
+        } else {
+            top_reads as f64 / self.valid_reads as f64
+        };
+
+        BarcodeSummary {
+            tag: self.options.tag,
+            total_reads: self.total_reads,
+            reads_with_tag: self.reads_with_tag,
+            valid_reads: self.valid_reads,
+            distinct_barcodes: distinct,
+            n50_reads_per_barcode: n50,
+            top1pct_fraction,
+            count_error_bound: self.top.error_bound(),
+            top_barcodes: sorted.into_iter().take(10).collect(),
+        }
+    }
+}
+
+/// 条形码统计结果。
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct BarcodeSummary {
+    /// 统计所用的标签。
+    pub tag: BarcodeTag,
+    /// 参与统计的主要比对读长数。
+    pub total_reads: u64,
+    /// 带有该标签的读长数。
+    pub reads_with_tag: u64,
+    /// 规范化后条形码有效的读长数。
+    pub valid_reads: u64,
+    /// 不同条形码数量（HyperLogLog估计值）。
+    pub distinct_barcodes: u64,
+    /// 每个条形码读长数的N50。
+    pub n50_reads_per_barcode: u64,
+    /// 头部1%条形码所含读长占有效读长的比例。
+    pub top1pct_fraction: f64,
+    /// 条形码计数误差上界（0表示精确）。
+    pub count_error_bound: u64,
+    /// 读长数最多的前10个条形码。
+    pub top_barcodes: Vec<(String, u64)>,
+}
+
+impl BarcodeSummary {
+    /// 有效条形码读长占比。
+    pub fn valid_rate(&self) -> f64 {
+        if self.total_reads == 0 {
+            0.0
+        } else {
+            self.valid_reads as f64 / self.total_reads as f64
+        }
+    }
+}
+
+impl fmt::Display for BarcodeSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "tag: {}", self.tag)?;
+        writeln!(f, "total: {}", self.total_reads)?;
+        writeln!(f, "with_tag: {}", self.reads_with_tag)?;
+        writeln!(f, "valid: {} ({})", self.valid_reads, Percent(self.valid_rate()))?;
+        writeln!(f, "distinct_barcodes: {}", self.distinct_barcodes)?;
+        writeln!(f, "n50_reads_per_barcode: {}", self.n50_reads_per_barcode)?;
+        writeln!(f, "top1pct_reads: {}", Percent(self.top1pct_fraction))?;
+        write!(f, "count_error_bound: {}", self.count_error_bound)?;
+        for (barcode, count) in &self.top_barcodes {
+            write!(f, "\ntop_barcode: {}\t{}", barcode, count)?;
+        }
+        Ok(())
+    }
+}
+
+/// 统计BAM文件中的条形码分布。
+///
+/// # Parameters
+///
+/// * `bam_path` - BAM文件路径
+/// * `options` - 标签与规范化选项
+/// * `interim` - 可选的阶段性输出器
+///
+/// # Returns
+///
+/// 成功时返回条形码统计结果，失败时返回相应错误。
+pub fn compute_barcodes(
+    bam_path: &str,
+    options: BarcodeOptions,
+    mut interim: Option<InterimEmitter>,
+) -> Result<BarcodeSummary, BarcodeError> {
+    let mut reader = options.reader.open(bam_path)?;
+    let mut collector = BarcodeCollector::new(options);
+
+    info!("开始统计条形码: {}", bam_path);
+
+    let mut processed_records = 0u64;
+    let mut record = BamRecord::default();
+    while reader.read_into(&mut record)? {
+        processed_records += 1;
+
+        if processed_records.is_multiple_of(1_000_000) {
+            debug!("{}", progress_message(processed_records, reader.progress_fraction()));
+        }
+
+        collector.update(&record);
+        if let Some(interim) = interim.as_mut() {
+            interim.tick(processed_records, || collector.summary());
+        }
+    }
+    if let Some(interim) = interim {
+        interim.finish();
+    }
+
+    let summary = collector.summary();
+    info!(
+        "处理完成：总记录数 {}，有效条形码读长 {}，不同条形码约 {} 个",
+        processed_records, summary.valid_reads, summary.distinct_barcodes
+    );
+
+    Ok(summary)
+}
+
+metric_keys! {
+    /// `barcodes`输出的键（见[`crate::metrics`]）。
+    BarcodeKeys for "barcodes" {
+        TAG = "tag", "条形码标签", Unit::Label, None, "统计所用的SAM标签。";
+        TOTAL_READS = "total_reads", "读长总数", Unit::Reads, None, "参与统计的主要比对读长数。";
+        TOTAL = "total", "读长总数", Unit::Reads, None, "文本输出中的total_reads。";
+        READS_WITH_TAG = "reads_with_tag", "带标签的读长数", Unit::Reads, Some(true), "带有条形码标签的读长数。";
+        WITH_TAG = "with_tag", "带标签的读长数", Unit::Reads, Some(true), "文本输出中的reads_with_tag。";
+        VALID_READS = "valid_reads", "有效条形码读长数", Unit::Reads, Some(true), "规范化后条形码有效的读长数。";
+        VALID = "valid", "有效条形码读长数", Unit::Reads, Some(true), "文本输出中的valid_reads，括号内为占总读长的百分比。";
+        DISTINCT_BARCODES = "distinct_barcodes", "不同条形码数", Unit::Count, None, "不同条形码数量（HyperLogLog估计值，哈希种子由--seed派生，不同种子的估计值在误差范围内不同）。";
+        N50_READS_PER_BARCODE = "n50_reads_per_barcode", "每条形码读长数N50", Unit::Reads, None, "按读长数降序累加到有效读长一半时的条形码读长数。";
+        TOP1PCT_FRACTION = "top1pct_fraction", "头部1%条形码读长占比", Unit::Fraction, None, "读长数最多的1%条形码所含读长占有效读长的比例。";
+        TOP1PCT_READS = "top1pct_reads", "头部1%条形码读长占比", Unit::Percent, None, "文本输出中的top1pct_fraction（百分比）。";
+        COUNT_ERROR_BOUND = "count_error_bound", "计数误差上界", Unit::Reads, Some(false), "top-k计数的误差上界，0表示精确。";
+        TOP_BARCODES = "top_barcodes", "读长数最多的条形码", Unit::List, None, "读长数最多的前10个条形码及其读长数。";
+    }
+}
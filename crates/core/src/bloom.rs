@@ -0,0 +1,98 @@
+//! 固定大小的布隆过滤器。
+//!
+//! 用于在第一遍读取中记录读名集合（例如带duplicate标志的读名），第二遍
+//! 按读名查询。内存在构建时确定，不随插入数增长；假阳性率随插入数上升，
+//! 由[`BloomFilter::false_positive_rate`]按实际插入数估计并报告。
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use crate::rng::SplitMix64;
+
+/// 默认位数（2^28位，即32 MiB）：插入一千万个读名时假阳性率约为3e-5。
+pub const DEFAULT_BLOOM_BITS: u64 = 1 << 28;
+
+/// 默认哈希函数个数。
+pub const DEFAULT_BLOOM_HASHES: u32 = 7;
+
+/// 字节串的布隆过滤器。
+///
+/// # Examples
+///
+/// ```
+/// use bamqc_core::BloomFilter;
+///
+/// let mut filter = BloomFilter::new(1 << 16, 7);
+/// for i in 0..1000 {
+///     filter.insert(format!("read{}", i).as_bytes());
+/// }
+/// assert!((0..1000).all(|i| filter.contains(format!("read{}", i).as_bytes())));
+/// let false_positives = (1000..11000).filter(|i| filter.contains(format!("read{}", i).as_bytes())).count();
+/// assert!(filter.false_positive_rate() < 0.01);
+/// assert!(false_positives < 100, "{}", false_positives);
+/// ```
+#[derive(Clone, Debug)]
+pub struct BloomFilter {
+    words: Vec<u64>,
+    bits: u64,
+    hashes: u32,
+    inserted: u64,
+}
+
+impl BloomFilter {
+    /// 创建`bits`位（向上取整到64的倍数）、`hashes`个哈希函数的过滤器。
+    pub fn new(bits: u64, hashes: u32) -> Self {
+        let words = bits.div_ceil(64).max(1);
+        Self {
+            words: vec![0; words as usize],
+            bits: words * 64,
+            hashes: hashes.max(1),
+            inserted: 0,
+        }
+    }
+
+    /// 双重哈希：第i个位置为`h1 + i × h2`。
+    fn positions(&self, key: &[u8]) -> impl Iterator<Item = u64> {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        let h1 = hasher.finish();
+        let h2 = SplitMix64::new(h1).next_u64() | 1;
+        let bits = self.bits;
+        (0..self.hashes as u64).map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) % bits)
+    }
+
+    /// 插入一个键。
+    pub fn insert(&mut self, key: &[u8]) {
+        for bit in self.positions(key) {
+            self.words[(bit / 64) as usize] |= 1 << (bit % 64);
+        }
+        self.inserted += 1;
+    }
+
+    /// 键可能在集合中时返回true（有假阳性，无假阴性）。
+    pub fn contains(&self, key: &[u8]) -> bool {
+        self.positions(key)
+            .all(|bit| self.words[(bit / 64) as usize] & (1 << (bit % 64)) != 0)
+    }
+
+    /// 已插入的键数（含重复插入）。
+    pub fn inserted(&self) -> u64 {
+        self.inserted
+    }
+
+    /// 位数。
+    pub fn bits(&self) -> u64 {
+        self.bits
+    }
+
+    /// 哈希函数个数。
+    pub fn hashes(&self) -> u32 {
+        self.hashes
+    }
+
+    /// 按插入数估计的假阳性率：(1 − e^(−kn/m))^k。
+    pub fn false_positive_rate(&self) -> f64 {
+        let k = self.hashes as f64;
+        let exponent = -k * self.inserted as f64 / self.bits as f64;
+        (1.0 - exponent.exp()).powf(k)
+    }
+}
@@ -0,0 +1,100 @@
+//! 参考序列边界检查。
+//!
+//! 比对位置或比对终点超出@SQ LN的记录（例如liftover错误的产物）会让按坐标
+//! 索引的累加器越界。所有消费坐标的收集器都通过[`ContigBounds`]判断记录是否
+//! 可用：越界记录被计数并从坐标相关的统计中跳过，但仍计入flag层面的统计。
+//! 第一条越界记录通过[`WARNINGS`]输出详情，总数在运行结束时汇总。
+
+use bamqc_io::bam::BamRecord;
+use bamqc_io::warnings::{WarningClass, WARNINGS};
+use noodles::sam;
+
+/// 各参考序列的长度以及越界记录计数。
+#[derive(Clone, Debug, Default)]
+pub struct ContigBounds {
+    lengths: Vec<usize>,
+    past_end: u64,
+}
+
+impl ContigBounds {
+    /// 从SAM头部的@SQ LN构建。
+    pub fn from_header(header: &sam::Header) -> Self {
+        Self {
+            lengths: header
+                .reference_sequences()
+                .values()
+                .map(|map| map.length().get())
+                .collect(),
+            past_end: 0,
+        }
+    }
+
+    /// 记录的比对区间是否落在其参考序列内。
+    ///
+    /// 未比对的记录不做检查，总是返回true。越界（比对起点或终点超过参考
+    /// 序列长度、或参考序列ID不在头部中）的记录计数后返回false。
+    ///
+    /// # Examples
+    ///
+    /// 10000条越界记录只输出一次详情，运行结束时汇总为一个类别：
+    ///
+    /// ```
+    /// use bamqc_core::compute_flagstat;
+    /// use bamqc_io::bam::BamWriter;
+    /// use bamqc_io::{WarningClass, WarningCount, WARNINGS};
+    /// use noodles::core::Position;
+    /// use noodles::sam::alignment::record::cigar::op::{Kind, Op};
+    /// use noodles::sam::alignment::record::Flags;
+    /// use noodles::sam::alignment::RecordBuf;
+    ///
+    /// let dir = std::env::temp_dir().join(format!("bamqc-past-end-{}", std::process::id()));
+    /// std::fs::create_dir_all(&dir).unwrap();
+    /// let bam = dir.join("past_end.bam").to_string_lossy().to_string();
+    /// let header = "@HD\tVN:1.6\tSO:coordinate\n@SQ\tSN:c1\tLN:100\n".parse().unwrap();
+    /// let mut writer = BamWriter::from_path(&bam, &header).unwrap();
+    /// for i in 0..10000 {
+    ///     let record = RecordBuf::builder()
+    ///         .set_name(format!("r{}", i))
+    ///         .set_flags(Flags::empty())
+    ///         .set_reference_sequence_id(0)
+    ///         .set_alignment_start(Position::try_from(95).unwrap())
+    ///         .set_cigar([Op::new(Kind::Match, 10)].into_iter().collect())
+    ///         .build();
+    ///     writer.write_record_buf(&record).unwrap();
+    /// }
+    /// writer.finish().unwrap();
+    ///
+    /// let stat = compute_flagstat(&bam, None, None).unwrap();
+    /// assert!(stat.to_string().contains("past_contig_end: 10000"));
+    /// assert_eq!(WARNINGS.summary(), [WarningCount { class: WarningClass::PastContigEnd, count: 10000 }]);
+    /// std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    pub fn check(&mut self, record: &BamRecord) -> bool {
+        if record.is_unmapped() {
+            return true;
+        }
+
+        let in_bounds = match usize::try_from(record.tid()).ok().and_then(|tid| self.lengths.get(tid)) {
+            Some(&length) => record.pos() < length as i64 && record.reference_end() <= length as i64,
+            None => false,
+        };
+        if !in_bounds {
+            self.past_end += 1;
+            WARNINGS.warn(WarningClass::PastContigEnd, || {
+                format!(
+                    "{} (tid={} pos={} end={})",
+                    record.name(),
+                    record.tid(),
+                    record.pos() + 1,
+                    record.reference_end()
+                )
+            });
+        }
+        in_bounds
+    }
+
+    /// 越界的记录数。
+    pub fn past_end(&self) -> u64 {
+        self.past_end
+    }
+}
@@ -0,0 +1,319 @@
+//! 按测序循环的soft clip覆盖率。
+//!
+//! 汇总的clip碱基数只说明clip多不多，不说明发生在读长的哪一段。这里对每个
+//! 测序循环统计被soft clip覆盖的读长比例，按read1/read2与clip所在的一端
+//! （测序方向的5'或3'端）分开：3'端clip率在某个循环之后陡增是接头读穿
+//! （插入片段短于读长）的特征，5'端clip多见于接头或UMI残留。
+//!
+//! 循环按测序方向从1开始编号：反向比对的记录在BAM中存的是反向互补序列，
+//! CIGAR左端对应测序的3'端，因此要交换两端并把位置翻转。hard clip的碱基
+//! 不在SEQ中，但仍占用测序循环，计算循环编号时计入（其本身不计为soft clip）。
+//! 未配对的读长计为read1。
+
+use std::fmt;
+use bamqc_io::bam::{BamReaderOptions, BamError, BamRecord};
+use bamqc_io::{Cigar, CigarKind, CigarOp};
+#[cfg(feature = "serde")]
+use serde::Serialize;
+use crate::formatting::PicardFloat;
+use crate::log::{info, warn};
+use crate::metrics::Unit;
+use crate::metrics_keys::metric_keys;
+
+/// 默认的接头读穿阈值：3'端clip率超过该值的循环被标记。
+pub const DEFAULT_ADAPTER_CLIP_RATE: f64 = 0.1;
+
+/// 一个read端的逐循环计数，长度随读长按需增长。
+#[derive(Clone, Debug, Default)]
+struct EndProfile {
+    /// 读长（含hard clip）为n的读数，下标n-1。
+    lengths: Vec<u64>,
+    /// 循环c被5'端soft clip覆盖的读数，下标c-1。
+    five_prime: Vec<u64>,
+    /// 循环c被3'端soft clip覆盖的读数，下标c-1。
+    three_prime: Vec<u64>,
+}
+
+fn add_range(counts: &mut Vec<u64>, start: usize, len: usize) {
+    if counts.len() < start + len {
+        counts.resize(start + len, 0);
+    }
+    for count in &mut counts[start..start + len] {
+        *count += 1;
+    }
+}
+
+/// CIGAR一端的(hard clip, soft clip)长度。
+fn end_clips<'a>(ops: impl Iterator<Item = &'a CigarOp>) -> (usize, usize) {
+    let mut hard = 0;
+    let mut soft = 0;
+    for op in ops {
+        match op.kind {
+            CigarKind::HardClip if soft == 0 => hard += op.len as usize,
+            CigarKind::SoftClip => soft += op.len as usize,
+            _ => break,
+        }
+    }
+    (hard, soft)
+}
+
+impl EndProfile {
+    /// 具有各循环的读数（读长不短于该循环）。
+    fn reads_per_cycle(&self) -> Vec<u64> {
+        let mut reads = vec![0; self.lengths.len()];
+        let mut running = 0;
+        for i in (0..self.lengths.len()).rev() {
+            running += self.lengths[i];
+            reads[i] = running;
+        }
+        reads
+    }
+}
+
+/// 一个测序循环的clip率。
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct ClipCycle {
+    /// 测序循环（从1开始）。
+    pub cycle: usize,
+    /// 读长覆盖该循环的read1数。
+    pub r1_reads: u64,
+    /// 读长覆盖该循环的read2数。
+    pub r2_reads: u64,
+    /// read1中该循环位于5'端soft clip内的比例。
+    pub r1_5p: f64,
+    /// read1中该循环位于3'端soft clip内的比例。
+    pub r1_3p: f64,
+    /// read2中该循环位于5'端soft clip内的比例。
+    pub r2_5p: f64,
+    /// read2中该循环位于3'端soft clip内的比例。
+    pub r2_3p: f64,
+}
+
+/// soft clip报告。
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct ClippingReport {
+    /// 统计的primary比对记录数。
+    pub reads: u64,
+    /// 含有soft clip的记录数。
+    pub soft_clipped_reads: u64,
+    /// soft clip碱基总数。
+    pub soft_clipped_bases: u64,
+    /// 标记接头读穿所用的3'端clip率阈值。
+    pub adapter_clip_rate: f64,
+    /// read1或read2的3'端clip率超过阈值的循环。
+    pub adapter_cycles: Vec<usize>,
+    /// 逐循环的clip率。
+    pub cycles: Vec<ClipCycle>,
+}
+
+impl fmt::Display for ClippingReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "# reads: {}", self.reads)?;
+        writeln!(f, "# soft_clipped_reads: {}", self.soft_clipped_reads)?;
+        writeln!(f, "# soft_clipped_bases: {}", self.soft_clipped_bases)?;
+        writeln!(f, "# adapter_clip_rate: {}", self.adapter_clip_rate)?;
+        let cycles: Vec<String> = self.adapter_cycles.iter().map(|c| c.to_string()).collect();
+        writeln!(f, "# adapter_cycles: {}", if cycles.is_empty() { "NA".to_string() } else { cycles.join(",") })?;
+        write!(f, "cycle\tr1_5p\tr1_3p\tr2_5p\tr2_3p")?;
+        for cycle in &self.cycles {
+            write!(
+                f,
+                "\n{}\t{}\t{}\t{}\t{}",
+                cycle.cycle,
+                PicardFloat(cycle.r1_5p),
+                PicardFloat(cycle.r1_3p),
+                PicardFloat(cycle.r2_5p),
+                PicardFloat(cycle.r2_3p)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// 逐循环soft clip收集器。
+///
+/// # Examples
+///
+/// 正向与反向记录、两端不对称的clip：
+///
+/// ```
+/// use bamqc_core::ClippingCollector;
+/// use bamqc_io::Cigar;
+///
+/// let cigar = |s: &str| s.parse::<Cigar>().unwrap();
+/// let mut collector = ClippingCollector::new(0.5);
+/// // read1正向：CIGAR左端2个clip为5'端（循环1-2），右端3个为3'端（循环8-10）
+/// collector.add_alignment(&cigar("2S5M3S"), false, false);
+/// // read1反向：CIGAR左端2个clip为3'端（循环9-10），右端3个为5'端（循环1-3）
+/// collector.add_alignment(&cigar("2S5M3S"), false, true);
+/// // read2反向，hard clip占用循环：左端1H2S为3'端（循环8-9），右端无clip
+/// collector.add_alignment(&cigar("1H2S7M"), true, true);
+/// let report = collector.report();
+///
+/// let rates = |i: usize| {
+///     let c = &report.cycles[i - 1];
+///     (c.r1_5p, c.r1_3p, c.r2_5p, c.r2_3p)
+/// };
+/// assert_eq!(report.cycles.len(), 10);
+/// assert_eq!(rates(1), (1.0, 0.0, 0.0, 0.0));
+/// assert_eq!(rates(3), (0.5, 0.0, 0.0, 0.0));
+/// assert_eq!(rates(4), (0.0, 0.0, 0.0, 0.0));
+/// assert_eq!(rates(8), (0.0, 0.5, 0.0, 1.0));
+/// assert_eq!(rates(9), (0.0, 1.0, 0.0, 1.0));
+/// assert_eq!(rates(10), (0.0, 1.0, 0.0, 0.0));
+/// assert_eq!(report.cycles[9].r2_reads, 1);
+/// // 3'端clip率超过0.5的循环
+/// assert_eq!(report.adapter_cycles, [8, 9, 10]);
+/// assert_eq!(report.soft_clipped_bases, 12);
+/// ```
+#[derive(Clone, Debug)]
+pub struct ClippingCollector {
+    adapter_clip_rate: f64,
+    reads: u64,
+    soft_clipped_reads: u64,
+    soft_clipped_bases: u64,
+    ends: [EndProfile; 2],
+}
+
+impl ClippingCollector {
+    /// `adapter_clip_rate`为标记接头读穿的3'端clip率阈值。
+    pub fn new(adapter_clip_rate: f64) -> Self {
+        Self {
+            adapter_clip_rate,
+            reads: 0,
+            soft_clipped_reads: 0,
+            soft_clipped_bases: 0,
+            ends: Default::default(),
+        }
+    }
+
+    /// 处理一条记录：只统计primary比对（跳过未比对、secondary与supplementary）。
+    pub fn update(&mut self, record: &BamRecord) {
+        if record.is_unmapped() || record.is_secondary() || record.is_supplementary() {
+            return;
+        }
+        let second = record.is_segmented() && record.is_last_segment() && !record.is_first_segment();
+        self.add_alignment(&record.cigar(), second, record.is_reverse_complemented());
+    }
+
+    /// 计入一条比对的CIGAR：`second`为read2，`reverse`为反向比对。
+    pub fn add_alignment(&mut self, cigar: &Cigar, second: bool, reverse: bool) {
+        let (left_hard, left_soft) = end_clips(cigar.ops().iter());
+        // 只有clip操作的CIGAR（无效比对）全部计为左端，避免两端重复计数
+        let clip_only = cigar
+            .ops()
+            .iter()
+            .all(|op| matches!(op.kind, CigarKind::HardClip | CigarKind::SoftClip));
+        let (right_hard, right_soft) = if clip_only { (0, 0) } else { end_clips(cigar.ops().iter().rev()) };
+        let total = cigar.query_length() as usize + cigar.hard_clipped_bases() as usize;
+        if total == 0 {
+            return;
+        }
+
+        self.reads += 1;
+        if left_soft + right_soft > 0 {
+            self.soft_clipped_reads += 1;
+            self.soft_clipped_bases += (left_soft + right_soft) as u64;
+        }
+
+        // 测序方向上5'端与3'端的(起始下标, 长度)
+        let (five_prime, three_prime) = if reverse {
+            ((right_hard, right_soft), (total - left_hard - left_soft, left_soft))
+        } else {
+            ((left_hard, left_soft), (total - right_hard - right_soft, right_soft))
+        };
+        let end = &mut self.ends[usize::from(second)];
+        add_range(&mut end.lengths, total - 1, 1);
+        add_range(&mut end.five_prime, five_prime.0, five_prime.1);
+        add_range(&mut end.three_prime, three_prime.0, three_prime.1);
+    }
+
+    /// 汇总逐循环的clip率。
+    pub fn report(&self) -> ClippingReport {
+        let reads = [self.ends[0].reads_per_cycle(), self.ends[1].reads_per_cycle()];
+        let cycles = reads[0].len().max(reads[1].len());
+        let rate = |end: usize, counts: &[u64], i: usize| {
+            let total = reads[end].get(i).copied().unwrap_or(0);
+            if total == 0 {
+                0.0
+            } else {
+                counts.get(i).copied().unwrap_or(0) as f64 / total as f64
+            }
+        };
+
+        let cycles: Vec<ClipCycle> = (0..cycles)
+            .map(|i| ClipCycle {
+                cycle: i + 1,
+                r1_reads: reads[0].get(i).copied().unwrap_or(0),
+                r2_reads: reads[1].get(i).copied().unwrap_or(0),
+                r1_5p: rate(0, &self.ends[0].five_prime, i),
+                r1_3p: rate(0, &self.ends[0].three_prime, i),
+                r2_5p: rate(1, &self.ends[1].five_prime, i),
+                r2_3p: rate(1, &self.ends[1].three_prime, i),
+            })
+            .collect();
+        let adapter_cycles = cycles
+            .iter()
+            .filter(|c| c.r1_3p > self.adapter_clip_rate || c.r2_3p > self.adapter_clip_rate)
+            .map(|c| c.cycle)
+            .collect();
+
+        ClippingReport {
+            reads: self.reads,
+            soft_clipped_reads: self.soft_clipped_reads,
+            soft_clipped_bases: self.soft_clipped_bases,
+            adapter_clip_rate: self.adapter_clip_rate,
+            adapter_cycles,
+            cycles,
+        }
+    }
+}
+
+/// 统计逐循环的soft clip覆盖率。
+pub fn compute_clipping(bam_path: &str, reader: &BamReaderOptions, adapter_clip_rate: f64) -> Result<ClippingReport, BamError> {
+    let mut reader = reader.open(bam_path)?;
+    let mut collector = ClippingCollector::new(adapter_clip_rate);
+
+    info!("开始统计逐循环的soft clip: {}", bam_path);
+
+    for result in reader.records() {
+        collector.update(&result?);
+    }
+
+    let report = collector.report();
+    info!(
+        "统计完成：{} 条记录，{} 条含soft clip，{} 个soft clip碱基",
+        report.reads, report.soft_clipped_reads, report.soft_clipped_bases
+    );
+    if let (Some(first), Some(last)) = (report.adapter_cycles.first(), report.adapter_cycles.last()) {
+        warn!(
+            "{} 个循环（{}-{}）的3'端clip率超过 {}，可能存在接头读穿",
+            report.adapter_cycles.len(),
+            first,
+            last,
+            adapter_clip_rate
+        );
+    }
+    Ok(report)
+}
+
+metric_keys! {
+    /// `clipping`输出的键（见[`crate::metrics`]）。
+    ClippingKeys for "clipping" {
+        READS = "reads", "记录数", Unit::Reads, None, "参与统计的primary比对记录数。";
+        SOFT_CLIPPED_READS = "soft_clipped_reads", "含soft clip的记录数", Unit::Reads, Some(false), "CIGAR任一端有soft clip的记录数。";
+        SOFT_CLIPPED_BASES = "soft_clipped_bases", "soft clip碱基数", Unit::Bases, Some(false), "soft clip碱基总数。";
+        ADAPTER_CLIP_RATE = "adapter_clip_rate", "接头读穿阈值", Unit::Fraction, None, "3'端clip率超过该值的循环被标记。";
+        ADAPTER_CYCLES = "adapter_cycles", "疑似接头读穿的循环", Unit::List, Some(false), "read1或read2的3'端clip率超过阈值的测序循环。";
+        CYCLES = "cycles", "逐循环clip率", Unit::List, None, "每个测序循环一行。";
+        CYCLE = "cycle", "测序循环", Unit::Count, None, "测序方向上的循环编号，从1开始，hard clip计入。";
+        R1_READS = "r1_reads", "read1读数", Unit::Reads, None, "读长覆盖该循环的read1（含未配对读长）数。";
+        R2_READS = "r2_reads", "read2读数", Unit::Reads, None, "读长覆盖该循环的read2数。";
+        R1_5P = "r1_5p", "read1 5'端clip率", Unit::Fraction, Some(false), "read1中该循环位于测序方向5'端soft clip内的比例。";
+        R1_3P = "r1_3p", "read1 3'端clip率", Unit::Fraction, Some(false), "read1中该循环位于测序方向3'端soft clip内的比例。";
+        R2_5P = "r2_5p", "read2 5'端clip率", Unit::Fraction, Some(false), "read2中该循环位于测序方向5'端soft clip内的比例。";
+        R2_3P = "r2_3p", "read2 3'端clip率", Unit::Fraction, Some(false), "read2中该循环位于测序方向3'端soft clip内的比例。";
+    }
+}
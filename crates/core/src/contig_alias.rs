@@ -0,0 +1,71 @@
+//! 参考序列命名约定之间的对应。
+//!
+//! 注释文件（cytoBand、BED等）与BAM头部常用不同的命名约定：UCSC风格带`chr`
+//! 前缀、线粒体为`chrM`，Ensembl/NCBI风格不带前缀、线粒体为`MT`。
+//! [`ContigAliasResolver`]先按原名精确匹配头部中的参考序列，找不到时再依次
+//! 尝试另一种约定下的名称。
+
+use std::collections::HashMap;
+use noodles::sam;
+
+/// 把注释文件中的参考序列名解析为头部中的tid。
+///
+/// # Examples
+///
+/// ```
+/// use bamqc_core::ContigAliasResolver;
+///
+/// let resolver = ContigAliasResolver::new(["1", "2", "X", "MT", "chrUn_gl000220"]);
+/// assert_eq!(resolver.resolve("chr1"), Some(0));
+/// assert_eq!(resolver.resolve("2"), Some(1));
+/// assert_eq!(resolver.resolve("chrX"), Some(2));
+/// assert_eq!(resolver.resolve("chrM"), Some(3));
+/// assert_eq!(resolver.resolve("chrUn_gl000220"), Some(4));
+/// assert_eq!(resolver.resolve("chrY"), None);
+///
+/// let resolver = ContigAliasResolver::new(["chr1", "chrM"]);
+/// assert_eq!(resolver.resolve("1"), Some(0));
+/// assert_eq!(resolver.resolve("MT"), Some(1));
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct ContigAliasResolver {
+    tids: HashMap<String, usize>,
+}
+
+impl ContigAliasResolver {
+    /// `names`为头部中的参考序列名，顺序即tid。
+    pub fn new<I, S>(names: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            tids: names.into_iter().enumerate().map(|(tid, name)| (name.into(), tid)).collect(),
+        }
+    }
+
+    /// 从SAM头部的@SQ构建。
+    pub fn from_header(header: &sam::Header) -> Self {
+        Self::new(header.reference_sequences().keys().map(|name| name.to_string()))
+    }
+
+    /// 解析`name`：先精确匹配，再尝试另一种命名约定；都找不到时返回None。
+    pub fn resolve(&self, name: &str) -> Option<usize> {
+        if let Some(&tid) = self.tids.get(name) {
+            return Some(tid);
+        }
+        aliases(name).into_iter().find_map(|alias| self.tids.get(&alias).copied())
+    }
+}
+
+/// `name`在另一种命名约定下可能的名称。
+fn aliases(name: &str) -> Vec<String> {
+    match name {
+        "chrM" | "chrMT" => vec!["MT".to_string(), "M".to_string()],
+        "MT" | "M" => vec!["chrM".to_string(), "chrMT".to_string()],
+        _ => match name.strip_prefix("chr") {
+            Some(stripped) => vec![stripped.to_string()],
+            None => vec![format!("chr{}", name)],
+        },
+    }
+}
@@ -0,0 +1,404 @@
+//! 按固定窗口的平均深度（`coverage --depth-windows`）。
+//!
+//! 全基因组可视化用逐碱基的bedGraph太大，这里把参考序列切成互不重叠的
+//! 固定长度窗口（从每条参考序列的第1个碱基开始），输出每个窗口的平均深度，
+//! 格式为wiggle fixedStep（可直接用wigToBigWig转换）或TSV。
+//!
+//! 窗口内的深度之和等于落在窗口内的比对碱基数（M、=、X；D、N不计深度），
+//! 因此逐窗口累加碱基数即可，内存只与窗口数成正比。平均深度的分母是窗口
+//! 内实际统计的长度：参考序列末端的不完整窗口按实际长度计，指定了区域或
+//! 靶区时只计窗口与区域相交的部分，不与区域相交的窗口不输出。
+//!
+//! 记录的过滤与`samtools depth`的默认值一致：跳过未比对、secondary、
+//! QC失败与duplicate的记录。
+
+use std::fmt;
+use bamqc_io::bam::{BamReaderOptions, BamError, BamRecord};
+use bamqc_io::region::{plan_queries, GenomicRegion};
+use bamqc_io::{Cigar, CigarKind};
+#[cfg(feature = "serde")]
+use serde::Serialize;
+use crate::filter::{ReadFilter, ReadFilterCounts};
+use crate::formatting::Depth;
+use crate::log::{debug, info};
+use crate::metrics::Unit;
+use crate::metrics_keys::metric_keys;
+use crate::sort_order::SortOrderGuard;
+
+/// 一个窗口的平均深度。
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct DepthWindow {
+    /// 参考序列名。
+    pub contig: String,
+    /// 统计范围的起点（1-based，含）。
+    pub start: u64,
+    /// 统计范围的终点（1-based，含）。
+    pub end: u64,
+    /// 实际统计的长度（平均深度的分母）。
+    pub length: u64,
+    /// 窗口内的比对碱基数（深度之和）。
+    pub bases: u64,
+}
+
+impl DepthWindow {
+    /// 平均深度。
+    pub fn mean_depth(&self) -> f64 {
+        if self.length == 0 {
+            0.0
+        } else {
+            self.bases as f64 / self.length as f64
+        }
+    }
+}
+
+/// 逐窗口平均深度。
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct DepthWindowReport {
+    /// 窗口大小（bp）。
+    pub window_size: u64,
+    /// 计入深度的记录数。
+    pub records: u64,
+    /// 按参考序列顺序排列的窗口。
+    pub windows: Vec<DepthWindow>,
+}
+
+impl DepthWindowReport {
+    /// wiggle fixedStep格式的文本。
+    ///
+    /// 位置连续、跨度相同的窗口写在同一个`fixedStep`块中；每条参考序列末端的
+    /// 不完整窗口（以及区域两端被截断的窗口）跨度不同，单独成块，保证每个值
+    /// 覆盖的范围与统计范围一致、不超出参考序列末端。
+    pub fn fixed_step(&self) -> String {
+        let mut out = String::new();
+        let mut previous: Option<&DepthWindow> = None;
+        for window in &self.windows {
+            let span = window.end + 1 - window.start;
+            let continues = previous.is_some_and(|prev| {
+                prev.contig == window.contig
+                    && prev.start + self.window_size == window.start
+                    && prev.end + 1 - prev.start == span
+            });
+            if !continues {
+                out.push_str(&format!(
+                    "fixedStep chrom={} start={} step={} span={}\n",
+                    window.contig, window.start, self.window_size, span
+                ));
+            }
+            out.push_str(&format!("{}\n", Depth(window.mean_depth())));
+            previous = Some(window);
+        }
+        out
+    }
+}
+
+impl fmt::Display for DepthWindowReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "# window_size: {}", self.window_size)?;
+        writeln!(f, "# records: {}", self.records)?;
+        write!(f, "contig\tstart\tend\tlength\tmean_depth")?;
+        for window in &self.windows {
+            write!(
+                f,
+                "\n{}\t{}\t{}\t{}\t{}",
+                window.contig,
+                window.start,
+                window.end,
+                window.length,
+                Depth(window.mean_depth())
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// 一条参考序列的窗口计数。
+#[derive(Clone, Debug)]
+struct ContigWindows {
+    name: String,
+    length: u64,
+    /// 统计范围（1-based闭区间，有序且互不重叠）。
+    scope: Vec<(u64, u64)>,
+    /// 每个窗口的比对碱基数；第一次有碱基落入时才分配。
+    bases: Vec<u64>,
+}
+
+impl ContigWindows {
+    /// 把`[start, end]`内的比对碱基计入窗口，只计统计范围内的部分。
+    fn add_block(&mut self, start: u64, end: u64, window: u64) {
+        let end = end.min(self.length);
+        let first = self.scope.partition_point(|&(_, scope_end)| scope_end < start);
+        for &(scope_start, scope_end) in &self.scope[first..] {
+            if scope_start > end {
+                break;
+            }
+            let mut pos = start.max(scope_start);
+            let stop = end.min(scope_end);
+            if self.bases.is_empty() {
+                self.bases = vec![0; self.length.div_ceil(window) as usize];
+            }
+            while pos <= stop {
+                let index = (pos - 1) / window;
+                let piece_end = stop.min((index + 1) * window);
+                self.bases[index as usize] += piece_end + 1 - pos;
+                pos = piece_end + 1;
+            }
+        }
+    }
+
+    /// 与统计范围相交的窗口，按位置排列。
+    fn windows(&self, window: u64) -> Vec<DepthWindow> {
+        let mut windows: Vec<(u64, DepthWindow)> = Vec::new();
+        for &(scope_start, scope_end) in &self.scope {
+            let mut pos = scope_start;
+            while pos <= scope_end {
+                let index = (pos - 1) / window;
+                let piece_end = scope_end.min((index + 1) * window);
+                match windows.last_mut() {
+                    Some((last, current)) if *last == index => {
+                        current.end = piece_end;
+                        current.length += piece_end + 1 - pos;
+                    }
+                    _ => windows.push((
+                        index,
+                        DepthWindow {
+                            contig: self.name.clone(),
+                            start: pos,
+                            end: piece_end,
+                            length: piece_end + 1 - pos,
+                            bases: self.bases.get(index as usize).copied().unwrap_or(0),
+                        },
+                    )),
+                }
+                pos = piece_end + 1;
+            }
+        }
+        windows.into_iter().map(|(_, window)| window).collect()
+    }
+}
+
+/// 逐窗口深度的收集器。
+///
+/// # Examples
+///
+/// 两条参考序列（25 bp与12 bp）、10 bp窗口，各窗口平均深度手工计算：
+///
+/// ```
+/// use bamqc_core::DepthWindowCollector;
+/// use bamqc_io::GenomicRegion;
+///
+/// let contigs = vec![("c1".to_string(), 25), ("c2".to_string(), 12)];
+/// let reads = [(0, 1, "10M"), (0, 6, "5M2D5M"), (0, 21, "5M"), (1, 8, "2S5M")];
+///
+/// let mut collector = DepthWindowCollector::new(10, contigs.clone());
+/// for (tid, start, cigar) in reads {
+///     collector.add_alignment(tid, start, &cigar.parse().unwrap());
+/// }
+/// let report = collector.report();
+/// let means: Vec<_> = report.windows.iter().map(|w| (w.contig.as_str(), w.start, w.end, w.mean_depth())).collect();
+/// // c1: 1-10为10+5个碱基；11-20为D之后的5个碱基；21-25为不完整窗口，分母为5
+/// // c2: 8-12跨两个窗口，11-12为不完整窗口，分母为2
+/// assert_eq!(means, [("c1", 1, 10, 1.5), ("c1", 11, 20, 0.5), ("c1", 21, 25, 1.0), ("c2", 1, 10, 0.3), ("c2", 11, 12, 1.0)]);
+/// assert_eq!(
+///     report.fixed_step(),
+///     "fixedStep chrom=c1 start=1 step=10 span=10\n1.50\n0.50\n\
+///      fixedStep chrom=c1 start=21 step=10 span=5\n1.00\n\
+///      fixedStep chrom=c2 start=1 step=10 span=10\n0.30\n\
+///      fixedStep chrom=c2 start=11 step=10 span=2\n1.00\n"
+/// );
+///
+/// // 限定区域c1:6-23：两端的窗口按与区域相交的长度计
+/// let region: GenomicRegion = "c1:6-23".parse().unwrap();
+/// let mut collector = DepthWindowCollector::new(10, contigs).with_regions(&[region]).unwrap();
+/// for (tid, start, cigar) in reads {
+///     collector.add_alignment(tid, start, &cigar.parse().unwrap());
+/// }
+/// let report = collector.report();
+/// let means: Vec<_> = report.windows.iter().map(|w| (w.start, w.end, w.length, w.mean_depth())).collect();
+/// assert_eq!(means, [(6, 10, 5, 2.0), (11, 20, 10, 0.5), (21, 23, 3, 1.0)]);
+/// assert_eq!(
+///     report.fixed_step(),
+///     "fixedStep chrom=c1 start=6 step=10 span=5\n2.00\n\
+///      fixedStep chrom=c1 start=11 step=10 span=10\n0.50\n\
+///      fixedStep chrom=c1 start=21 step=10 span=3\n1.00\n"
+/// );
+/// ```
+#[derive(Clone, Debug)]
+pub struct DepthWindowCollector {
+    window: u64,
+    records: u64,
+    contigs: Vec<ContigWindows>,
+    filtered: ReadFilterCounts,
+}
+
+impl DepthWindowCollector {
+    /// `contigs`为头部中的参考序列名与长度，顺序即tid；`window`至少为1。
+    pub fn new(window: u64, contigs: Vec<(String, u64)>) -> Self {
+        Self {
+            window: window.max(1),
+            records: 0,
+            contigs: contigs
+                .into_iter()
+                .map(|(name, length)| ContigWindows {
+                    scope: if length > 0 { vec![(1, length)] } else { Vec::new() },
+                    name,
+                    length,
+                    bases: Vec::new(),
+                })
+                .collect(),
+            filtered: ReadFilterCounts::default(),
+        }
+    }
+
+    /// 未比对、secondary、duplicate、QC失败的记录不计入深度，按原因的计数。
+    pub fn filter_counts(&self) -> &ReadFilterCounts {
+        &self.filtered
+    }
+
+    /// 只统计`regions`（合并重叠部分）；没有区域的参考序列不输出窗口。
+    pub fn with_regions(mut self, regions: &[GenomicRegion]) -> Result<Self, BamError> {
+        let mut scopes: Vec<Vec<(u64, u64)>> = vec![Vec::new(); self.contigs.len()];
+        for region in regions {
+            let tid = self
+                .contigs
+                .iter()
+                .position(|contig| contig.name == region.name)
+                .ok_or_else(|| BamError::RegionError(format!("参考序列不存在: {}", region.name)))?;
+            let length = self.contigs[tid].length;
+            let start = (region.start as u64).max(1);
+            let end = region.end.map_or(length, |end| (end as u64).min(length));
+            if start <= end {
+                scopes[tid].push((start, end));
+            }
+        }
+        for (contig, mut scope) in self.contigs.iter_mut().zip(scopes) {
+            scope.sort_unstable();
+            let mut merged: Vec<(u64, u64)> = Vec::with_capacity(scope.len());
+            for (start, end) in scope {
+                match merged.last_mut() {
+                    Some(last) if start <= last.1 + 1 => last.1 = last.1.max(end),
+                    _ => merged.push((start, end)),
+                }
+            }
+            contig.scope = merged;
+        }
+        Ok(self)
+    }
+
+    /// 处理一条记录，按`samtools depth`的默认值过滤。
+    pub fn update(&mut self, record: &BamRecord) {
+        self.update_within(record, 1, u64::MAX);
+    }
+
+    /// 只计入`[lo, hi]`内的碱基：合并后的查询区间互不重叠，跨两个查询区间的
+    /// 记录会被两次查询都返回，各自只计本区间内的部分即不会重复。
+    fn update_within(&mut self, record: &BamRecord, lo: u64, hi: u64) {
+        let filter = ReadFilter { include_supplementary: true, ..Default::default() };
+        if !filter.count(record, &mut self.filtered) {
+            return;
+        }
+        let (Ok(tid), Ok(pos)) = (usize::try_from(record.tid()), u64::try_from(record.pos())) else {
+            return;
+        };
+        self.records += 1;
+        self.add_alignment_within(tid, pos + 1, &record.cigar(), lo, hi);
+    }
+
+    /// 计入一个比对：`start`为1-based比对起点，M、=、X操作的碱基计入深度。
+    pub fn add_alignment(&mut self, tid: usize, start: u64, cigar: &Cigar) {
+        self.add_alignment_within(tid, start, cigar, 1, u64::MAX);
+    }
+
+    fn add_alignment_within(&mut self, tid: usize, start: u64, cigar: &Cigar, lo: u64, hi: u64) {
+        let window = self.window;
+        let Some(contig) = self.contigs.get_mut(tid) else {
+            return;
+        };
+        let mut pos = start;
+        for op in cigar.ops() {
+            let len = op.len as u64;
+            match op.kind {
+                CigarKind::Match | CigarKind::SequenceMatch | CigarKind::SequenceMismatch => {
+                    let (block_start, block_end) = (pos.max(lo), (pos + len - 1).min(hi));
+                    if len > 0 && block_start <= block_end {
+                        contig.add_block(block_start, block_end, window);
+                    }
+                    pos += len;
+                }
+                kind if kind.consumes_reference() => pos += len,
+                _ => {}
+            }
+        }
+    }
+
+    /// 生成报告。
+    pub fn report(&self) -> DepthWindowReport {
+        DepthWindowReport {
+            window_size: self.window,
+            records: self.records,
+            windows: self.contigs.iter().flat_map(|contig| contig.windows(self.window)).collect(),
+        }
+    }
+}
+
+/// 统计逐窗口平均深度；指定`regions`时通过索引只读取这些区域。
+///
+/// 遍历全文件时用[`SortOrderGuard`]核对坐标顺序，最多允许`tolerate_unsorted`
+/// 条记录乱序；索引查询的结果本身按坐标排列，不做检查。
+pub fn compute_depth_windows(
+    bam_path: &str,
+    reader: &BamReaderOptions,
+    window: u64,
+    regions: Option<&[GenomicRegion]>,
+    tolerate_unsorted: u64,
+) -> Result<DepthWindowReport, BamError> {
+    let mut reader = reader.open(bam_path)?;
+    let contigs = reader.references().map(|(name, length)| (name.to_string(), length)).collect();
+    let mut collector = DepthWindowCollector::new(window, contigs);
+
+    match regions {
+        Some(regions) => {
+            collector = collector.with_regions(regions)?;
+            let plan = plan_queries(regions, reader.header())?;
+            info!("开始统计 {} 个区域（{} 次索引查询）的窗口深度: {}", regions.len(), plan.len(), bam_path);
+            for query in &plan {
+                debug!("查询区域 {}", query.region);
+                let lo = query.region.start as u64;
+                let hi = query.region.end.map_or(u64::MAX, |end| end as u64);
+                for result in reader.query(&query.region)? {
+                    collector.update_within(&result?, lo, hi);
+                }
+            }
+        }
+        None => {
+            info!("开始统计窗口深度: {}", bam_path);
+            let mut guard = SortOrderGuard::from_header(reader.header(), tolerate_unsorted);
+            for result in reader.records() {
+                let record = result?;
+                guard.check(&record)?;
+                collector.update(&record);
+            }
+        }
+    }
+
+    let report = collector.report();
+    debug!("记录过滤: {}", collector.filter_counts());
+    info!("统计完成：{} 条记录，{} 个窗口", report.records, report.windows.len());
+    Ok(report)
+}
+
+metric_keys! {
+    /// `coverage`输出的键（见[`crate::metrics`]）。
+    CoverageKeys for "coverage" {
+        WINDOW_SIZE = "window_size", "窗口大小", Unit::BasePairs, None, "--depth-windows指定的窗口长度，从每条参考序列的第1个碱基起划分。";
+        RECORDS = "records", "计入记录数", Unit::Reads, None, "计入深度的记录数（跳过未比对、secondary、QC失败与duplicate）。";
+        WINDOWS = "windows", "窗口", Unit::List, None, "按参考序列顺序排列的窗口。";
+        CONTIG = "contig", "参考序列", Unit::Label, None, "窗口所在的参考序列名。";
+        START = "start", "起点", Unit::BasePairs, None, "窗口统计范围的起点（1-based，含）。";
+        END = "end", "终点", Unit::BasePairs, None, "窗口统计范围的终点（1-based，含）。";
+        LENGTH = "length", "统计长度", Unit::BasePairs, None, "窗口内实际统计的长度：参考序列末端的不完整窗口为实际长度，限定区域时为与区域相交的长度。";
+        BASES = "bases", "比对碱基数", Unit::Bases, None, "窗口内M、=、X操作的比对碱基数（深度之和）。";
+        MEAN_DEPTH = "mean_depth", "平均深度", Unit::Count, Some(true), "bases / length。";
+    }
+}
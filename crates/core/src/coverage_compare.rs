@@ -0,0 +1,282 @@
+//! 两个BAM之间逐靶区的相对覆盖比较。
+//!
+//! 深度不同的两次测序（例如30x与60x）不能直接比较靶区深度。这里对两个BAM
+//! 分别统计逐靶区平均深度（与[`compute_target_metrics`]相同），各自除以本样本
+//! panel内靶区平均深度的中位数得到归一化深度，再给出`log2(B / A)`：
+//! 超过阈值的靶区标记为gain或loss，用于批次效应与拷贝数变化的粗筛。
+//!
+//! A中深度为0的靶区无法作为分母，单独标记为`zero_depth`；B中深度为0而A不为0
+//! 的靶区标记为loss，log2比值为NA。任一样本的中位深度为0时无法归一化，
+//! 全部靶区标记为`skipped`。
+
+use std::fmt;
+#[cfg(feature = "serde")]
+use serde::Serialize;
+use crate::cytoband::median;
+use crate::formatting::{Depth, OrNa, Rate};
+use crate::log::info;
+use crate::metrics::Unit;
+use crate::metrics_keys::metric_keys;
+use bamqc_io::bam::BamReaderOptions;
+use crate::target::{compute_target_metrics, TargetError, TargetReport};
+
+/// `log2_ratio`的默认阈值：归一化深度相差一倍。
+pub const DEFAULT_LOG2_THRESHOLD: f64 = 1.0;
+
+/// 一个靶区的比较结果。
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize), serde(rename_all = "snake_case"))]
+pub enum RatioFlag {
+    /// |log2_ratio|不超过阈值。
+    Pass,
+    /// B相对A升高超过阈值。
+    Gain,
+    /// B相对A降低超过阈值（含B中深度为0）。
+    Loss,
+    /// A中深度为0，无法计算比值。
+    ZeroDepth,
+    /// 有样本的中位深度为0，无法归一化。
+    Skipped,
+}
+
+impl fmt::Display for RatioFlag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            RatioFlag::Pass => "pass",
+            RatioFlag::Gain => "gain",
+            RatioFlag::Loss => "loss",
+            RatioFlag::ZeroDepth => "zero_depth",
+            RatioFlag::Skipped => "skipped",
+        })
+    }
+}
+
+/// 一个靶区在两个样本中的深度与比值。
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct TargetRatio {
+    /// 靶区名称（BED第4列或`chr:start-end`）。
+    pub target: String,
+    /// A中的平均深度。
+    pub mean_depth_a: f64,
+    /// B中的平均深度。
+    pub mean_depth_b: f64,
+    /// mean_depth_a / median_depth_a；无法归一化时为None。
+    pub normalized_depth_a: Option<f64>,
+    /// mean_depth_b / median_depth_b；无法归一化时为None。
+    pub normalized_depth_b: Option<f64>,
+    /// log2(normalized_depth_b / normalized_depth_a)；任一深度为0时为None。
+    pub log2_ratio: Option<f64>,
+    /// 比较结果。
+    pub flag: RatioFlag,
+}
+
+/// 逐靶区相对覆盖比较的报告。
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct CoverageComparison {
+    /// |log2_ratio|超过该值的靶区标记为gain或loss。
+    pub log2_threshold: f64,
+    /// A中靶区平均深度的中位数。
+    pub median_depth_a: f64,
+    /// B中靶区平均深度的中位数。
+    pub median_depth_b: f64,
+    /// 每个靶区一行（顺序与BED文件一致）。
+    pub targets: Vec<TargetRatio>,
+}
+
+impl CoverageComparison {
+    /// 由两个样本的逐靶区指标（同一BED）计算比较结果。
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bamqc_core::{CoverageComparison, RatioFlag, TargetMetrics, TargetReport};
+    ///
+    /// let report = |depths: &[u64]| {
+    ///     TargetReport::new(
+    ///         depths
+    ///             .iter()
+    ///             .enumerate()
+    ///             .map(|(i, &depth)| TargetMetrics { name: format!("t{}", i), length: 100, bases: depth * 100, ..Default::default() })
+    ///             .collect(),
+    ///     )
+    /// };
+    /// // B的测序深度是A的两倍；t2在B中相对升高一倍，t3在A中没有覆盖，t4在B中没有覆盖
+    /// let a = report(&[30, 30, 30, 0, 30]);
+    /// let b = report(&[60, 60, 240, 60, 0]);
+    /// let comparison = CoverageComparison::new(&a, &b, 1.0);
+    /// assert_eq!((comparison.median_depth_a, comparison.median_depth_b), (30.0, 60.0));
+    /// let flags: Vec<RatioFlag> = comparison.targets.iter().map(|t| t.flag).collect();
+    /// assert_eq!(flags, [RatioFlag::Pass, RatioFlag::Pass, RatioFlag::Gain, RatioFlag::ZeroDepth, RatioFlag::Loss]);
+    /// assert_eq!(comparison.targets[0].log2_ratio, Some(0.0));
+    /// assert_eq!(comparison.targets[2].log2_ratio, Some(2.0));
+    /// assert_eq!(comparison.targets[3].log2_ratio, None);
+    ///
+    /// // 中位深度为0时无法归一化
+    /// let comparison = CoverageComparison::new(&report(&[0, 0, 30]), &b, 1.0);
+    /// assert!(comparison.targets.iter().all(|t| t.flag == RatioFlag::Skipped));
+    /// ```
+    pub fn new(a: &TargetReport, b: &TargetReport, log2_threshold: f64) -> Self {
+        let median_depth = |report: &TargetReport| {
+            let mut depths: Vec<f64> = report.targets.iter().map(|t| t.mean_depth()).collect();
+            median(&mut depths)
+        };
+        let median_depth_a = median_depth(a);
+        let median_depth_b = median_depth(b);
+        let normalize = |depth: f64, median: f64| (median > 0.0).then(|| depth / median);
+
+        let targets = a
+            .targets
+            .iter()
+            .zip(&b.targets)
+            .map(|(ta, tb)| {
+                let (mean_depth_a, mean_depth_b) = (ta.mean_depth(), tb.mean_depth());
+                let normalized_depth_a = normalize(mean_depth_a, median_depth_a);
+                let normalized_depth_b = normalize(mean_depth_b, median_depth_b);
+                let log2_ratio = match (normalized_depth_a, normalized_depth_b) {
+                    (Some(na), Some(nb)) if na > 0.0 && nb > 0.0 => Some((nb / na).log2()),
+                    _ => None,
+                };
+                let flag = match (normalized_depth_a, normalized_depth_b, log2_ratio) {
+                    (None, _, _) | (_, None, _) => RatioFlag::Skipped,
+                    _ if mean_depth_a == 0.0 => RatioFlag::ZeroDepth,
+                    (_, _, None) => RatioFlag::Loss,
+                    (_, _, Some(ratio)) if ratio > log2_threshold => RatioFlag::Gain,
+                    (_, _, Some(ratio)) if ratio < -log2_threshold => RatioFlag::Loss,
+                    _ => RatioFlag::Pass,
+                };
+                TargetRatio {
+                    target: ta.name.clone(),
+                    mean_depth_a,
+                    mean_depth_b,
+                    normalized_depth_a,
+                    normalized_depth_b,
+                    log2_ratio,
+                    flag,
+                }
+            })
+            .collect();
+
+        Self { log2_threshold, median_depth_a, median_depth_b, targets }
+    }
+
+    /// 被标记为gain或loss的靶区。
+    pub fn flagged(&self) -> impl Iterator<Item = &TargetRatio> {
+        self.targets.iter().filter(|t| matches!(t.flag, RatioFlag::Gain | RatioFlag::Loss))
+    }
+}
+
+impl fmt::Display for CoverageComparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "# log2_threshold: {}", Rate(self.log2_threshold))?;
+        writeln!(f, "# median_depth_a: {}", Depth(self.median_depth_a))?;
+        writeln!(f, "# median_depth_b: {}", Depth(self.median_depth_b))?;
+        write!(f, "target\tmean_depth_a\tmean_depth_b\tnormalized_depth_a\tnormalized_depth_b\tlog2_ratio\tflag")?;
+        for t in &self.targets {
+            write!(
+                f,
+                "\n{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                t.target,
+                Depth(t.mean_depth_a),
+                Depth(t.mean_depth_b),
+                OrNa(t.normalized_depth_a.map(Rate)),
+                OrNa(t.normalized_depth_b.map(Rate)),
+                OrNa(t.log2_ratio.map(Rate)),
+                t.flag
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// 按同一BED比较两个BAM的逐靶区相对覆盖，B相对A。
+///
+/// # Examples
+///
+/// 两个深度不同的样本，B中一个靶区的相对深度加倍：
+///
+/// ```
+/// use bamqc_core::{compute_coverage_comparison, RatioFlag};
+/// use bamqc_io::bam::{BamReaderOptions, BamWriter};
+/// use bamqc_io::write_bai;
+/// use noodles::core::Position;
+/// use noodles::sam::alignment::record::cigar::op::{Kind, Op};
+/// use noodles::sam::alignment::record::Flags;
+/// use noodles::sam::alignment::RecordBuf;
+///
+/// let dir = std::env::temp_dir().join(format!("bamqc-coverage-compare-{}", std::process::id()));
+/// std::fs::create_dir_all(&dir).unwrap();
+/// let bed = dir.join("panel.bed");
+/// std::fs::write(&bed, (0..6).map(|i| format!("c1\t{}\t{}\tT{}\n", i * 1000, i * 1000 + 100, i)).collect::<String>()).unwrap();
+///
+/// // 每个靶区放`reads[i]`条完全覆盖靶区的100bp读长
+/// let write = |name: &str, reads: &[usize]| {
+///     let bam = dir.join(name).to_string_lossy().to_string();
+///     let header = "@HD\tVN:1.6\tSO:coordinate\n@SQ\tSN:c1\tLN:10000\n".parse().unwrap();
+///     let mut writer = BamWriter::from_path(&bam, &header).unwrap();
+///     for (i, &n) in reads.iter().enumerate() {
+///         for j in 0..n {
+///             let record = RecordBuf::builder()
+///                 .set_name(format!("t{}r{}", i, j))
+///                 .set_flags(Flags::empty())
+///                 .set_reference_sequence_id(0)
+///                 .set_alignment_start(Position::try_from(i * 1000 + 1).unwrap())
+///                 .set_mapping_quality(60.try_into().unwrap())
+///                 .set_cigar([Op::new(Kind::Match, 100)].into_iter().collect())
+///                 .build();
+///             writer.write_record_buf(&record).unwrap();
+///         }
+///     }
+///     writer.finish().unwrap();
+///     write_bai(&bam).unwrap();
+///     bam
+/// };
+/// let a = write("a.bam", &[30, 30, 30, 30, 0, 30]);
+/// let b = write("b.bam", &[60, 60, 120, 60, 60, 60]);
+///
+/// let comparison = compute_coverage_comparison(&a, &b, &BamReaderOptions::new(), &bed.to_string_lossy(), 0.5).unwrap();
+/// assert_eq!((comparison.median_depth_a, comparison.median_depth_b), (30.0, 60.0));
+/// let flagged: Vec<&str> = comparison.flagged().map(|t| t.target.as_str()).collect();
+/// assert_eq!(flagged, ["T2"]);
+/// assert_eq!(comparison.targets[2].log2_ratio, Some(1.0));
+/// assert_eq!(comparison.targets[4].flag, RatioFlag::ZeroDepth);
+/// assert!(comparison.to_string().lines().any(|line| line == "T2\t30.00\t120.00\t1.0000\t2.0000\t1.0000\tgain"));
+/// std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+pub fn compute_coverage_comparison(
+    bam_a: &str,
+    bam_b: &str,
+    reader: &BamReaderOptions,
+    bed_path: &str,
+    log2_threshold: f64,
+) -> Result<CoverageComparison, TargetError> {
+    info!("统计逐靶区深度: {}", bam_a);
+    let a = compute_target_metrics(bam_a, reader, bed_path, 0)?;
+    info!("统计逐靶区深度: {}", bam_b);
+    let b = compute_target_metrics(bam_b, reader, bed_path, 0)?;
+    let comparison = CoverageComparison::new(&a, &b, log2_threshold);
+    info!(
+        "{} 个靶区中 {} 个的相对深度变化超过阈值",
+        comparison.targets.len(),
+        comparison.flagged().count()
+    );
+    Ok(comparison)
+}
+
+metric_keys! {
+    /// `coverage-compare`输出的键（见[`crate::metrics`]）。
+    CoverageComparisonKeys for "coverage-compare" {
+        LOG2_THRESHOLD = "log2_threshold", "log2比值阈值", Unit::Ratio, None, "--log2-threshold：|log2_ratio|超过该值的靶区标记为gain或loss。";
+        MEDIAN_DEPTH_A = "median_depth_a", "A的中位深度", Unit::Count, None, "A中靶区平均深度的中位数。";
+        MEDIAN_DEPTH_B = "median_depth_b", "B的中位深度", Unit::Count, None, "B中靶区平均深度的中位数。";
+        TARGETS = "targets", "靶区", Unit::List, None, "每个靶区一行，顺序与BED文件一致。";
+        TARGET = "target", "靶区名", Unit::Label, None, "BED第4列，缺失时为坐标。";
+        MEAN_DEPTH_A = "mean_depth_a", "A的平均深度", Unit::Count, None, "靶区在A中的平均深度。";
+        MEAN_DEPTH_B = "mean_depth_b", "B的平均深度", Unit::Count, None, "靶区在B中的平均深度。";
+        NORMALIZED_DEPTH_A = "normalized_depth_a", "A的归一化深度", Unit::Ratio, None, "mean_depth_a / median_depth_a；中位深度为0时为NA。";
+        NORMALIZED_DEPTH_B = "normalized_depth_b", "B的归一化深度", Unit::Ratio, None, "mean_depth_b / median_depth_b；中位深度为0时为NA。";
+        LOG2_RATIO = "log2_ratio", "log2比值", Unit::Ratio, None, "log2(normalized_depth_b / normalized_depth_a)；任一深度为0时为NA。";
+        FLAG = "flag", "比较结果", Unit::Label, None, "pass、gain、loss、zero_depth（A中深度为0）或skipped（中位深度为0）。";
+    }
+}
@@ -0,0 +1,720 @@
+//! 染色体臂水平的深度与插入片段汇总（`coverage --cytobands`）。
+//!
+//! 从UCSC cytoBand.txt（chrom、chromStart、chromEnd、name、gieStain，0-based
+//! 半开区间）推导每条染色体的p臂与q臂：着丝粒由`acen`带给出，p臂止于名称以
+//! `p`开头的acen带的终点；只有q侧acen带时p臂为空。没有acen带时按带名的
+//! p/q前缀划分。近端着丝粒染色体（或注释中没有p带数据的染色体）因此只有q臂。
+//! 注释中的参考序列名通过[`ContigAliasResolver`]与头部对应。
+//!
+//! 每个臂的深度来自[`DepthWindowCollector`]的固定窗口，窗口按中点归入臂：
+//! 平均深度为归入臂的窗口的比对碱基数之和除以统计长度之和，中位深度为这些
+//! 窗口平均深度的中位数。插入片段中位数按左端记录（TLEN>0）的位置把读对
+//! 归入臂，读对的筛选与`insert-size`的默认条件相同（不含duplicate，不区分方向）。
+//!
+//! 基因组中位深度取全部可评估臂的窗口平均深度的中位数；臂的中位深度与之的
+//! 比值（normalized_depth）偏离1超过阈值时标记为gain或loss。只含异染色质带
+//! （acen、gvar、stalk）的臂不参与评估，标记为skipped。这是粗略的非整倍性与
+//! 质量筛查，不能替代CNV分析。
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{BufRead, BufReader};
+use std::fs::File;
+use std::path::Path;
+use bamqc_io::bam::{BamReaderOptions, BamError, BamRecord};
+#[cfg(feature = "serde")]
+use bamqc_io::serialize_error;
+use bamqc_io::{Cigar, ErrorCode};
+#[cfg(feature = "serde")]
+use serde::Serialize;
+use thiserror::Error;
+use crate::contig_alias::ContigAliasResolver;
+use crate::coverage::{DepthWindowCollector, DepthWindowReport};
+use crate::formatting::{Depth, OrNa, Rate};
+use crate::insert_size::{classify_pair, InsertSizeCalculator, PairFilter};
+use crate::log::{info, warn};
+use crate::metrics::Unit;
+use crate::metrics_keys::metric_keys;
+use crate::sort_order::SortOrderGuard;
+
+/// 默认的臂深度偏离阈值：normalized_depth不在[0.7, 1.3]内时标记。
+pub const DEFAULT_ARM_MAX_DEVIATION: f64 = 0.3;
+
+/// 染色体臂汇总过程中可能发生的错误。
+///
+/// ```
+/// # #[cfg(feature = "serde")]
+/// # {
+/// use bamqc_core::CytobandError;
+///
+/// let e = CytobandError::InvalidLine { path: "cytoBand.txt".into(), line: 3, message: "缺少gieStain列".into() };
+/// assert_eq!(
+///     serde_json::to_string(&e).unwrap(),
+///     r#"{"code":"invalid_cytoband","message":"cytoBand.txt:3: 缺少gieStain列","details":{"path":"cytoBand.txt","line":3}}"#
+/// );
+/// # }
+/// ```
+#[derive(Error, Debug)]
+pub enum CytobandError {
+    /// cytoBand文件的某一行格式错误。
+    #[error("{path}:{line}: {message}")]
+    InvalidLine {
+        /// cytoBand文件路径
+        path: String,
+        /// 行号（1-based）
+        line: usize,
+        /// 错误说明
+        message: String,
+    },
+
+    /// 没有任何染色体臂能与头部中的参考序列对应。
+    #[error("cytoBand文件中没有可与BAM头部对应的染色体臂: {path}")]
+    NoArms {
+        /// cytoBand文件路径
+        path: String,
+    },
+
+    /// BAM文件IO错误。
+    #[error("BAM文件IO错误: {0}")]
+    BamError(#[from] BamError),
+}
+
+impl ErrorCode for CytobandError {
+    fn code(&self) -> &'static str {
+        match self {
+            CytobandError::InvalidLine { .. } => "invalid_cytoband",
+            CytobandError::NoArms { .. } => "no_arms",
+            CytobandError::BamError(e) => e.code(),
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    fn serialize_details<M: serde::ser::SerializeMap>(&self, map: &mut M) -> Result<(), M::Error> {
+        match self {
+            CytobandError::InvalidLine { path, line, .. } => {
+                map.serialize_entry("path", path)?;
+                map.serialize_entry("line", line)
+            }
+            CytobandError::NoArms { path } => map.serialize_entry("path", path),
+            CytobandError::BamError(e) => e.serialize_details(map),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for CytobandError {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_error(self, serializer)
+    }
+}
+
+/// cytoBand.txt中的一条带。
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cytoband {
+    /// 参考序列名（注释中的写法）。
+    pub contig: String,
+    /// 起点（0-based，含）。
+    pub start: u64,
+    /// 终点（0-based，不含）。
+    pub end: u64,
+    /// 带名，例如`p36.33`；可能为空。
+    pub name: String,
+    /// Giemsa染色，例如`gneg`、`gpos50`、`acen`、`gvar`、`stalk`。
+    pub stain: String,
+}
+
+impl Cytoband {
+    fn is_acen(&self) -> bool {
+        self.stain == "acen"
+    }
+
+    /// gneg与gposN为常染色质带，其余（acen、gvar、stalk）不参与评估。
+    fn is_euchromatic(&self) -> bool {
+        self.stain.starts_with("gneg") || self.stain.starts_with("gpos")
+    }
+}
+
+/// 解析cytoBand.txt文本；空行与`#`开头的行被跳过，`path`只用于错误信息。
+///
+/// # Examples
+///
+/// ```
+/// use bamqc_core::parse_cytobands;
+///
+/// let bands = parse_cytobands("#chrom\tstart\nchr1\t0\t2300000\tp36.33\tgneg\n", "cytoBand.txt").unwrap();
+/// assert_eq!((bands[0].contig.as_str(), bands[0].start, bands[0].end, bands[0].name.as_str()), ("chr1", 0, 2300000, "p36.33"));
+///
+/// let e = parse_cytobands("chr1\t0\t2300000\tp36.33\n", "cytoBand.txt").unwrap_err();
+/// assert_eq!(e.to_string(), "cytoBand.txt:1: 需要5列（chrom、chromStart、chromEnd、name、gieStain），实际为4列");
+/// let e = parse_cytobands("chr1\t100\t100\tp36.33\tgneg\n", "cytoBand.txt").unwrap_err();
+/// assert_eq!(e.to_string(), "cytoBand.txt:1: 带的终点必须大于起点: 100-100");
+/// ```
+pub fn parse_cytobands(text: &str, path: &str) -> Result<Vec<Cytoband>, CytobandError> {
+    text.lines()
+        .enumerate()
+        .filter_map(|(i, line)| {
+            parse_cytoband_line(line)
+                .map_err(|message| CytobandError::InvalidLine { path: path.to_string(), line: i + 1, message })
+                .transpose()
+        })
+        .collect()
+}
+
+/// 读取cytoBand.txt文件。
+pub fn read_cytobands<P: AsRef<Path>>(path: P) -> Result<Vec<Cytoband>, CytobandError> {
+    let path_str = path.as_ref().to_string_lossy().to_string();
+    if !path.as_ref().exists() {
+        return Err(BamError::FileNotFound { path: path_str }.into());
+    }
+    let reader = BufReader::new(File::open(&path).map_err(BamError::from)?);
+    let mut bands = Vec::new();
+    for (i, line) in reader.lines().enumerate() {
+        let line = line.map_err(BamError::from)?;
+        match parse_cytoband_line(&line) {
+            Ok(Some(band)) => bands.push(band),
+            Ok(None) => {}
+            Err(message) => return Err(CytobandError::InvalidLine { path: path_str, line: i + 1, message }),
+        }
+    }
+    Ok(bands)
+}
+
+fn parse_cytoband_line(line: &str) -> Result<Option<Cytoband>, String> {
+    let line = line.trim_end();
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(None);
+    }
+    let fields: Vec<&str> = line.split('\t').collect();
+    if fields.len() < 5 {
+        return Err(format!("需要5列（chrom、chromStart、chromEnd、name、gieStain），实际为{}列", fields.len()));
+    }
+    let start: u64 = fields[1].parse().map_err(|_| format!("无效的起点: {}", fields[1]))?;
+    let end: u64 = fields[2].parse().map_err(|_| format!("无效的终点: {}", fields[2]))?;
+    if end <= start {
+        return Err(format!("带的终点必须大于起点: {}-{}", start, end));
+    }
+    Ok(Some(Cytoband {
+        contig: fields[0].to_string(),
+        start,
+        end,
+        name: fields[3].to_string(),
+        stain: fields[4].to_string(),
+    }))
+}
+
+/// 染色体臂。
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize), serde(rename_all = "lowercase"))]
+pub enum Arm {
+    /// 短臂。
+    P,
+    /// 长臂。
+    Q,
+}
+
+impl fmt::Display for Arm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Arm::P => "p",
+            Arm::Q => "q",
+        })
+    }
+}
+
+/// 由cytoBand推导的一个染色体臂。
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChromosomeArm {
+    /// 参考序列名（注释中的写法）。
+    pub contig: String,
+    /// 臂。
+    pub arm: Arm,
+    /// 起点（1-based，含）。
+    pub start: u64,
+    /// 终点（1-based，含）。
+    pub end: u64,
+    /// 臂内是否有常染色质带（gneg、gposN）；没有时不参与评估。
+    pub assessable: bool,
+}
+
+/// 按参考序列（首次出现的顺序）由带推导p臂与q臂。
+///
+/// 没有acen带、带名也没有p/q前缀的参考序列（如chrM、未定位片段）不产生臂。
+///
+/// # Examples
+///
+/// ```
+/// use bamqc_core::{derive_arms, parse_cytobands, Arm};
+///
+/// let text = "chr1\t0\t40\tp2\tgneg\nchr1\t40\t50\tp11\tacen\nchr1\t50\t60\tq11\tacen\nchr1\t60\t100\tq2\tgpos50\n\
+///             chr13\t0\t10\tq11\tacen\nchr13\t10\t90\tq12\tgneg\n\
+///             chr21\t0\t20\tp12\tstalk\nchr21\t20\t25\tp11.1\tacen\nchr21\t25\t30\tq11.1\tacen\nchr21\t30\t80\tq21\tgpos100\n\
+///             chrM\t0\t16569\t\tgneg\n";
+/// let arms = derive_arms(&parse_cytobands(text, "cytoBand.txt").unwrap());
+/// let summary: Vec<_> = arms.iter().map(|a| (a.contig.as_str(), a.arm, a.start, a.end, a.assessable)).collect();
+/// assert_eq!(
+///     summary,
+///     [
+///         ("chr1", Arm::P, 1, 50, true),
+///         ("chr1", Arm::Q, 51, 100, true),
+///         // 近端着丝粒、没有p带数据：只有q臂
+///         ("chr13", Arm::Q, 1, 90, true),
+///         // p臂只有stalk与acen：不参与评估
+///         ("chr21", Arm::P, 1, 25, false),
+///         ("chr21", Arm::Q, 26, 80, true),
+///     ]
+/// );
+/// ```
+pub fn derive_arms(bands: &[Cytoband]) -> Vec<ChromosomeArm> {
+    let mut order: Vec<&str> = Vec::new();
+    let mut by_contig: HashMap<&str, Vec<&Cytoband>> = HashMap::new();
+    for band in bands {
+        by_contig
+            .entry(band.contig.as_str())
+            .or_insert_with(|| {
+                order.push(band.contig.as_str());
+                Vec::new()
+            })
+            .push(band);
+    }
+
+    let mut arms = Vec::new();
+    for contig in order {
+        let mut bands = by_contig.remove(contig).unwrap_or_default();
+        bands.sort_by_key(|band| (band.start, band.end));
+        let first = bands.iter().map(|band| band.start).min().unwrap_or(0);
+        let last = bands.iter().map(|band| band.end).max().unwrap_or(0);
+        let acen: Vec<&&Cytoband> = bands.iter().filter(|band| band.is_acen()).collect();
+        let split = if !acen.is_empty() {
+            acen.iter()
+                .filter(|band| band.name.starts_with('p'))
+                .map(|band| band.end)
+                .max()
+                .unwrap_or_else(|| acen[0].start)
+        } else if bands.iter().any(|band| band.name.starts_with('p') || band.name.starts_with('q')) {
+            bands.iter().filter(|band| band.name.starts_with('p')).map(|band| band.end).max().unwrap_or(first)
+        } else {
+            continue;
+        };
+
+        for (arm, start, end) in [(Arm::P, first, split), (Arm::Q, split, last)] {
+            if start >= end {
+                continue;
+            }
+            let assessable = bands
+                .iter()
+                .any(|band| band.start >= start && band.end <= end && band.is_euchromatic());
+            arms.push(ChromosomeArm { contig: contig.to_string(), arm, start: start + 1, end, assessable });
+        }
+    }
+    arms
+}
+
+/// 臂的评估结果。
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize), serde(rename_all = "lowercase"))]
+pub enum ArmFlag {
+    /// 深度在阈值范围内。
+    Pass,
+    /// 深度高于基因组中位数超过阈值。
+    Gain,
+    /// 深度低于基因组中位数超过阈值。
+    Loss,
+    /// 不参与评估（只有异染色质带，或基因组中位深度为0）。
+    Skipped,
+}
+
+impl fmt::Display for ArmFlag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ArmFlag::Pass => "pass",
+            ArmFlag::Gain => "gain",
+            ArmFlag::Loss => "loss",
+            ArmFlag::Skipped => "skipped",
+        })
+    }
+}
+
+/// 一个臂的汇总指标。
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct ArmMetrics {
+    /// 参考序列名（头部中的写法）。
+    pub contig: String,
+    /// 臂。
+    pub arm: Arm,
+    /// 起点（1-based，含）。
+    pub start: u64,
+    /// 终点（1-based，含；截断到参考序列长度）。
+    pub end: u64,
+    /// 归入臂的窗口的统计长度之和。
+    pub length: u64,
+    /// 归入臂的窗口的比对碱基数之和。
+    pub bases: u64,
+    /// 归入臂的窗口平均深度的中位数。
+    pub median_depth: f64,
+    /// median_depth / 基因组中位深度；不参与评估时为None。
+    pub normalized_depth: Option<f64>,
+    /// 归入臂的读对数。
+    pub pairs: u64,
+    /// 插入片段中位数；没有读对时为None。
+    pub insert_size_median: Option<i32>,
+    /// 评估结果。
+    pub flag: ArmFlag,
+}
+
+impl ArmMetrics {
+    /// 平均深度。
+    pub fn mean_depth(&self) -> f64 {
+        if self.length == 0 {
+            0.0
+        } else {
+            self.bases as f64 / self.length as f64
+        }
+    }
+}
+
+/// 逐臂汇总。
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct ArmReport {
+    /// 窗口大小（bp）。
+    pub window_size: u64,
+    /// normalized_depth允许的偏离。
+    pub max_deviation: f64,
+    /// 可评估臂的窗口平均深度的中位数。
+    pub genome_median_depth: f64,
+    /// 按cytoBand中参考序列的顺序、p臂在前排列。
+    pub arms: Vec<ArmMetrics>,
+}
+
+impl ArmReport {
+    /// 被标记为gain或loss的臂。
+    pub fn flagged(&self) -> impl Iterator<Item = &ArmMetrics> {
+        self.arms.iter().filter(|arm| matches!(arm.flag, ArmFlag::Gain | ArmFlag::Loss))
+    }
+}
+
+impl fmt::Display for ArmReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "# window_size: {}", self.window_size)?;
+        writeln!(f, "# max_deviation: {}", Rate(self.max_deviation))?;
+        writeln!(f, "# genome_median_depth: {}", Depth(self.genome_median_depth))?;
+        write!(
+            f,
+            "contig\tarm\tstart\tend\tlength\tmean_depth\tmedian_depth\tnormalized_depth\tpairs\tinsert_size_median\tflag"
+        )?;
+        for arm in &self.arms {
+            write!(
+                f,
+                "\n{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                arm.contig,
+                arm.arm,
+                arm.start,
+                arm.end,
+                arm.length,
+                Depth(arm.mean_depth()),
+                Depth(arm.median_depth),
+                OrNa(arm.normalized_depth.map(Rate)),
+                arm.pairs,
+                OrNa(arm.insert_size_median),
+                arm.flag
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// 解析到头部的臂。
+#[derive(Clone, Debug)]
+struct ResolvedArm {
+    tid: usize,
+    contig: String,
+    arm: Arm,
+    start: u64,
+    end: u64,
+    assessable: bool,
+}
+
+/// 逐臂汇总的收集器，在[`DepthWindowCollector`]之上按臂归并。
+///
+/// # Examples
+///
+/// 模拟1号染色体q臂三体：头部用Ensembl命名，cytoBand用UCSC命名；基线深度2x，
+/// 1q为3x。
+///
+/// ```
+/// use bamqc_core::{derive_arms, parse_cytobands, Arm, ArmAggregator, ArmFlag};
+///
+/// let text = "chr1\t0\t40\tp2\tgneg\nchr1\t40\t50\tp11\tacen\nchr1\t50\t60\tq11\tacen\nchr1\t60\t100\tq2\tgpos50\n\
+///             chr2\t0\t10\tq11\tacen\nchr2\t10\t100\tq12\tgneg\n\
+///             chr3\t0\t20\tp12\tstalk\nchr3\t20\t25\tp11.1\tacen\nchr3\t25\t30\tq11.1\tacen\nchr3\t30\t100\tq21\tgpos100\n\
+///             chrUn\t0\t50\tq1\tgneg\n";
+/// let arms = derive_arms(&parse_cytobands(text, "cytoBand.txt").unwrap());
+/// let contigs = vec![("1".to_string(), 100), ("2".to_string(), 100), ("3".to_string(), 100)];
+/// let mut aggregator = ArmAggregator::new(&arms, contigs, 10);
+/// assert_eq!(aggregator.unresolved(), ["chrUn"]);
+///
+/// let read = "10M".parse().unwrap();
+/// for tid in 0..3 {
+///     for start in (1..100).step_by(10) {
+///         let copies = if tid == 0 && start > 50 { 3 } else { 2 };
+///         for _ in 0..copies {
+///             aggregator.add_alignment(tid, start, &read);
+///         }
+///     }
+/// }
+/// for (tid, pos, size) in [(0, 70, 280), (0, 75, 300), (0, 80, 320), (1, 5, 250)] {
+///     aggregator.add_insert_size(tid, pos, size);
+/// }
+///
+/// let report = aggregator.report(0.3);
+/// assert_eq!(report.genome_median_depth, 2.0);
+/// let summary: Vec<_> = report.arms.iter().map(|a| (a.contig.as_str(), a.arm, a.normalized_depth, a.flag)).collect();
+/// assert_eq!(
+///     summary,
+///     [
+///         ("1", Arm::P, Some(1.0), ArmFlag::Pass),
+///         ("1", Arm::Q, Some(1.5), ArmFlag::Gain),
+///         ("2", Arm::Q, Some(1.0), ArmFlag::Pass),
+///         ("3", Arm::P, None, ArmFlag::Skipped),
+///         ("3", Arm::Q, Some(1.0), ArmFlag::Pass),
+///     ]
+/// );
+/// assert_eq!(report.flagged().count(), 1);
+/// assert_eq!(
+///     report.to_string().lines().skip(3).take(3).collect::<Vec<_>>(),
+///     [
+///         "contig\tarm\tstart\tend\tlength\tmean_depth\tmedian_depth\tnormalized_depth\tpairs\tinsert_size_median\tflag",
+///         "1\tp\t1\t50\t50\t2.00\t2.00\t1.0000\t0\tNA\tpass",
+///         "1\tq\t51\t100\t50\t3.00\t3.00\t1.5000\t3\t300\tgain",
+///     ]
+/// );
+/// ```
+#[derive(Clone, Debug)]
+pub struct ArmAggregator {
+    arms: Vec<ResolvedArm>,
+    unresolved: Vec<String>,
+    depth: DepthWindowCollector,
+    insert_sizes: Vec<HashMap<i32, u32>>,
+    filter: PairFilter,
+}
+
+impl ArmAggregator {
+    /// `contigs`为头部中的参考序列名与长度，顺序即tid；`window`至少为1。
+    /// 参考序列名经[`ContigAliasResolver`]解析，找不到的参考序列的臂被忽略。
+    pub fn new(arms: &[ChromosomeArm], contigs: Vec<(String, u64)>, window: u64) -> Self {
+        let resolver = ContigAliasResolver::new(contigs.iter().map(|(name, _)| name.clone()));
+        let mut resolved = Vec::new();
+        let mut unresolved: Vec<String> = Vec::new();
+        for arm in arms {
+            let Some(tid) = resolver.resolve(&arm.contig) else {
+                if !unresolved.contains(&arm.contig) {
+                    unresolved.push(arm.contig.clone());
+                }
+                continue;
+            };
+            let (name, length) = &contigs[tid];
+            if arm.start > *length {
+                continue;
+            }
+            resolved.push(ResolvedArm {
+                tid,
+                contig: name.clone(),
+                arm: arm.arm,
+                start: arm.start,
+                end: arm.end.min(*length),
+                assessable: arm.assessable,
+            });
+        }
+        Self {
+            insert_sizes: vec![HashMap::new(); resolved.len()],
+            arms: resolved,
+            unresolved,
+            depth: DepthWindowCollector::new(window, contigs),
+            filter: PairFilter::default(),
+        }
+    }
+
+    /// 能与头部对应的臂数。
+    pub fn len(&self) -> usize {
+        self.arms.len()
+    }
+
+    /// 没有任何能与头部对应的臂。
+    pub fn is_empty(&self) -> bool {
+        self.arms.is_empty()
+    }
+
+    /// 在头部中找不到的参考序列名（注释中的写法，按首次出现的顺序）。
+    pub fn unresolved(&self) -> &[String] {
+        &self.unresolved
+    }
+
+    /// 处理一条记录：计入窗口深度，左端记录另计入所在臂的插入片段。
+    pub fn update(&mut self, record: &BamRecord) {
+        self.depth.update(record);
+        if let Some((_, size)) = classify_pair(record, &self.filter) {
+            if let (Ok(tid), Ok(pos)) = (usize::try_from(record.tid()), u64::try_from(record.pos())) {
+                self.add_insert_size(tid, pos + 1, size);
+            }
+        }
+    }
+
+    /// 计入一个比对，见[`DepthWindowCollector::add_alignment`]。
+    pub fn add_alignment(&mut self, tid: usize, start: u64, cigar: &Cigar) {
+        self.depth.add_alignment(tid, start, cigar);
+    }
+
+    /// 计入一个读对：`pos`为左端记录的1-based位置，落在臂外时忽略。
+    pub fn add_insert_size(&mut self, tid: usize, pos: u64, size: i32) {
+        if let Some(index) = self.arm_at(tid, pos) {
+            *self.insert_sizes[index].entry(size).or_insert(0) += 1;
+        }
+    }
+
+    fn arm_at(&self, tid: usize, pos: u64) -> Option<usize> {
+        self.arms.iter().position(|arm| arm.tid == tid && arm.start <= pos && pos <= arm.end)
+    }
+
+    /// 逐窗口深度报告。
+    pub fn depth_report(&self) -> DepthWindowReport {
+        self.depth.report()
+    }
+
+    /// 生成逐臂汇总；`max_deviation`为normalized_depth允许偏离1的幅度。
+    pub fn report(&self, max_deviation: f64) -> ArmReport {
+        let depth = self.depth.report();
+        let mut windows: Vec<Vec<f64>> = vec![Vec::new(); self.arms.len()];
+        let mut totals = vec![(0u64, 0u64); self.arms.len()];
+        for window in &depth.windows {
+            let mid = (window.start + window.end) / 2;
+            let Some(index) = self
+                .arms
+                .iter()
+                .position(|arm| arm.contig == window.contig && arm.start <= mid && mid <= arm.end)
+            else {
+                continue;
+            };
+            windows[index].push(window.mean_depth());
+            totals[index].0 += window.length;
+            totals[index].1 += window.bases;
+        }
+
+        let mut genome: Vec<f64> = self
+            .arms
+            .iter()
+            .zip(&windows)
+            .filter(|(arm, _)| arm.assessable)
+            .flat_map(|(_, depths)| depths.iter().copied())
+            .collect();
+        let genome_median_depth = median(&mut genome);
+
+        let arms = self
+            .arms
+            .iter()
+            .zip(windows)
+            .zip(totals)
+            .zip(&self.insert_sizes)
+            .map(|(((arm, mut depths), (length, bases)), sizes)| {
+                let median_depth = median(&mut depths);
+                let normalized_depth =
+                    (arm.assessable && genome_median_depth > 0.0).then(|| median_depth / genome_median_depth);
+                let flag = match normalized_depth {
+                    None => ArmFlag::Skipped,
+                    Some(ratio) if ratio > 1.0 + max_deviation => ArmFlag::Gain,
+                    Some(ratio) if ratio < 1.0 - max_deviation => ArmFlag::Loss,
+                    Some(_) => ArmFlag::Pass,
+                };
+                let pairs = sizes.values().map(|&count| count as u64).sum();
+                ArmMetrics {
+                    contig: arm.contig.clone(),
+                    arm: arm.arm,
+                    start: arm.start,
+                    end: arm.end,
+                    length,
+                    bases,
+                    median_depth,
+                    normalized_depth,
+                    pairs,
+                    insert_size_median: (pairs > 0).then(|| InsertSizeCalculator::calculate_median_from_counts(sizes)),
+                    flag,
+                }
+            })
+            .collect();
+
+        ArmReport { window_size: depth.window_size, max_deviation, genome_median_depth, arms }
+    }
+}
+
+/// 中位数（偶数个时取中间两个的均值）；为空时为0。
+pub(crate) fn median(values: &mut [f64]) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    values.sort_by(f64::total_cmp);
+    let mid = values.len() / 2;
+    if values.len().is_multiple_of(2) {
+        (values[mid - 1] + values[mid]) / 2.0
+    } else {
+        values[mid]
+    }
+}
+
+/// 一次遍历同时统计逐窗口深度与逐臂汇总。
+///
+/// 坐标顺序的检查与[`compute_depth_windows`](crate::compute_depth_windows)相同。
+pub fn compute_arm_metrics(
+    bam_path: &str,
+    reader: &BamReaderOptions,
+    cytoband_path: &str,
+    window: u64,
+    max_deviation: f64,
+    tolerate_unsorted: u64,
+) -> Result<(DepthWindowReport, ArmReport), CytobandError> {
+    let arms = derive_arms(&read_cytobands(cytoband_path)?);
+    let mut reader = reader.open(bam_path)?;
+    let contigs = reader
+        .header()
+        .reference_sequences()
+        .iter()
+        .map(|(name, map)| (name.to_string(), map.length().get() as u64))
+        .collect();
+    let mut aggregator = ArmAggregator::new(&arms, contigs, window);
+    if aggregator.is_empty() {
+        return Err(CytobandError::NoArms { path: cytoband_path.to_string() });
+    }
+    if !aggregator.unresolved().is_empty() {
+        warn!("cytoBand中以下参考序列不在BAM头部中，已忽略: {}", aggregator.unresolved().join(", "));
+    }
+
+    info!("开始统计窗口深度与 {} 个染色体臂: {}", aggregator.len(), bam_path);
+    let mut guard = SortOrderGuard::from_header(reader.header(), tolerate_unsorted);
+    for result in reader.records() {
+        let record = result?;
+        guard.check(&record)?;
+        aggregator.update(&record);
+    }
+
+    let report = aggregator.report(max_deviation);
+    info!(
+        "统计完成：基因组中位深度 {}，{} 个臂偏离超过阈值",
+        Depth(report.genome_median_depth),
+        report.flagged().count()
+    );
+    Ok((aggregator.depth_report(), report))
+}
+
+metric_keys! {
+    /// `coverage --cytobands`逐臂汇总的键（见[`crate::metrics`]）。
+    ArmKeys for "coverage" {
+        MAX_DEVIATION = "max_deviation", "臂深度允许偏离", Unit::Ratio, None, "--arm-max-deviation：normalized_depth偏离1超过该值的臂标记为gain或loss。";
+        GENOME_MEDIAN_DEPTH = "genome_median_depth", "基因组中位深度", Unit::Count, None, "全部可评估臂的窗口平均深度的中位数。";
+        ARMS = "arms", "染色体臂", Unit::List, None, "由cytoBand推导的染色体臂，按参考序列顺序、p臂在前排列。";
+        ARM = "arm", "臂", Unit::Label, None, "p或q；近端着丝粒或没有p带数据的染色体只有q臂。";
+        MEDIAN_DEPTH = "median_depth", "中位深度", Unit::Count, None, "中点落在臂内的窗口平均深度的中位数。";
+        NORMALIZED_DEPTH = "normalized_depth", "归一化深度", Unit::Ratio, None, "median_depth / genome_median_depth；不参与评估的臂为NA。";
+        PAIRS = "pairs", "读对数", Unit::ReadPairs, None, "左端记录落在臂内、按insert-size默认条件计入的读对数。";
+        INSERT_SIZE_MEDIAN = "insert_size_median", "插入片段中位数", Unit::BasePairs, None, "臂内读对插入片段的中位数（累计频数首次达到50%的取值）。";
+        FLAG = "flag", "评估结果", Unit::Label, None, "pass、gain、loss或skipped（只有异染色质带的臂，或基因组中位深度为0）。";
+    }
+}
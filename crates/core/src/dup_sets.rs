@@ -0,0 +1,427 @@
+//! 重复集合大小的分布。
+//!
+//! 重复率只给出一个数；估计PCR循环数与文库复杂度需要知道每个分子被测到了几次。
+//! 在按坐标排序、已标记duplicate的BAM中，5'端未clip位置、链方向与TLEN都相同的
+//! 模板（读对按靠左的一端计一次，非配对读长与mate未比对的读长各计一次）构成一个
+//! 重复集合：一个非duplicate的代表与k个duplicate，大小为k + 1。
+//!
+//! 同一集合的记录比对起点可能相差clip长度（正向链），或按比对终点而不是起点对齐
+//! （反向链），因此按比对起点遍历时用一个滑动窗口保存未结束的集合：5'端位置
+//! 落后当前比对起点超过`window`的集合不会再有新成员，随即结束并计入直方图。
+//! 开头clip超过窗口的记录（[`DuplicateSetReport::out_of_window_records`]）可能
+//! 与自己的集合分开；未结束的集合超过`max_open_sets`时提前结束5'端位置最小的
+//! 集合（[`DuplicateSetReport::spilled_sets`]），其后到达的成员另成集合。两种情况
+//! 下没有代表的duplicate计入[`DuplicateSetReport::orphan_duplicates`]而不进入直方图。
+
+use std::collections::BTreeMap;
+use std::fmt;
+use bamqc_io::bam::{BamError, BamReaderOptions, BamRecord, SortOrder};
+#[cfg(feature = "serde")]
+use bamqc_io::serialize_error;
+use bamqc_io::ErrorCode;
+#[cfg(feature = "serde")]
+use serde::Serialize;
+use thiserror::Error;
+use crate::duplication::{estimate_library_size, estimate_library_size_from_set_sizes};
+use crate::filter::ReadFilter;
+use crate::formatting::{OrNa, PicardFloat};
+use crate::log::{info, warn};
+use crate::metrics::Unit;
+use crate::metrics_keys::metric_keys;
+use crate::sort_order::SortOrderGuard;
+
+/// 默认窗口（bp）。
+pub const DEFAULT_DUP_SET_WINDOW: u64 = 500;
+
+/// 窗口的上限（bp）。
+pub const MAX_DUP_SET_WINDOW: u64 = 100_000;
+
+/// 默认最多同时保存的未结束集合数。
+pub const DEFAULT_MAX_OPEN_SETS: u64 = 1_000_000;
+
+/// 大小不小于该值的集合计为大集合（见[`DuplicateSetReport::fraction_in_large_sets`]）。
+pub const LARGE_SET_SIZE: u64 = 10;
+
+/// 重复集合统计过程中可能发生的错误。
+///
+/// 错误代码与JSON形式见[`bamqc_io::error_code`]：
+///
+/// ```
+/// # #[cfg(feature = "serde")]
+/// # {
+/// use bamqc_core::DuplicateSetError;
+///
+/// assert_eq!(
+///     serde_json::to_string(&DuplicateSetError::InvalidWindow { window: 0 }).unwrap(),
+///     r#"{"code":"invalid_dup_set_window","message":"窗口必须在[1, 100000] bp之间: 0","details":{"window":0}}"#
+/// );
+/// let e = DuplicateSetError::InvalidMaxOpenSets { max_open_sets: 0 };
+/// assert_eq!(serde_json::to_value(&e).unwrap()["code"], "invalid_max_open_sets");
+/// # }
+/// ```
+#[derive(Error, Debug)]
+pub enum DuplicateSetError {
+    /// 窗口不在`[1, MAX_DUP_SET_WINDOW]`之间。
+    #[error("窗口必须在[1, {}] bp之间: {window}", MAX_DUP_SET_WINDOW)]
+    InvalidWindow { window: u64 },
+
+    /// 未结束集合数的上限为0。
+    #[error("未结束集合数的上限必须大于0: {max_open_sets}")]
+    InvalidMaxOpenSets { max_open_sets: u64 },
+
+    /// BAM文件IO错误。
+    #[error("BAM文件IO错误: {0}")]
+    BamError(#[from] BamError),
+}
+
+impl ErrorCode for DuplicateSetError {
+    fn code(&self) -> &'static str {
+        match self {
+            DuplicateSetError::InvalidWindow { .. } => "invalid_dup_set_window",
+            DuplicateSetError::InvalidMaxOpenSets { .. } => "invalid_max_open_sets",
+            DuplicateSetError::BamError(e) => e.code(),
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    fn serialize_details<M: serde::ser::SerializeMap>(&self, map: &mut M) -> Result<(), M::Error> {
+        match self {
+            DuplicateSetError::InvalidWindow { window } => map.serialize_entry("window", window),
+            DuplicateSetError::InvalidMaxOpenSets { max_open_sets } => map.serialize_entry("max_open_sets", max_open_sets),
+            DuplicateSetError::BamError(e) => e.serialize_details(map),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for DuplicateSetError {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_error(self, serializer)
+    }
+}
+
+/// 集合的键：5'端未clip位置、是否反向链、TLEN。参考序列变化时所有集合结束，不在键中。
+type SetKey = (i64, bool, i64);
+
+/// 未结束集合中的代表数与duplicate数。
+#[derive(Clone, Copy, Debug, Default)]
+struct OpenSet {
+    representatives: u64,
+    duplicates: u64,
+}
+
+/// 逐记录组装重复集合，记录必须按坐标排序。
+///
+/// # Examples
+///
+/// 手工构造的重复簇，窗口为20 bp：
+///
+/// ```
+/// use bamqc_core::DuplicateSetCollector;
+/// use bamqc_io::bam::{BamReader, BamWriter};
+/// use noodles::sam;
+///
+/// let bam = std::env::temp_dir().join(format!("bamqc-dup-sets-{}.bam", std::process::id()));
+/// let text = "@HD\tVN:1.6\tSO:coordinate\n@SQ\tSN:c1\tLN:1000\n@SQ\tSN:c2\tLN:1000\n\
+///             a1\t0\tc1\t100\t60\t10M\t*\t0\t0\t*\t*\n\
+///             a2\t1024\tc1\t103\t60\t3S10M\t*\t0\t0\t*\t*\n\
+///             a3\t1024\tc1\t112\t60\t12S10M\t*\t0\t0\t*\t*\n\
+///             a4\t1024\tc1\t125\t60\t25S10M\t*\t0\t0\t*\t*\n\
+///             b1\t16\tc1\t130\t60\t10M\t*\t0\t0\t*\t*\n\
+///             b2\t1040\tc1\t132\t60\t8M\t*\t0\t0\t*\t*\n\
+///             b3\t1040\tc1\t135\t60\t3M2S\t*\t0\t0\t*\t*\n\
+///             c1\t99\tc1\t200\t60\t10M\t=\t300\t110\t*\t*\n\
+///             c3\t99\tc1\t200\t60\t10M\t=\t310\t120\t*\t*\n\
+///             c2\t1123\tc1\t200\t60\t10M\t=\t300\t110\t*\t*\n\
+///             c1\t147\tc1\t300\t60\t10M\t=\t200\t-110\t*\t*\n\
+///             c2\t1171\tc1\t300\t60\t10M\t=\t200\t-110\t*\t*\n\
+///             c3\t147\tc1\t310\t60\t10M\t=\t200\t-120\t*\t*\n\
+///             s1\t0\tc1\t400\t60\t10M\t*\t0\t0\t*\t*\n\
+///             d1\t0\tc2\t5\t60\t10M\t*\t0\t0\t*\t*\n\
+///             d2\t1024\tc2\t5\t60\t10M\t*\t0\t0\t*\t*\n";
+/// let mut sam_reader = sam::io::Reader::new(text.as_bytes());
+/// let header = sam_reader.read_header().unwrap();
+/// let mut writer = BamWriter::from_path(&bam, &header).unwrap();
+/// for record in sam_reader.record_bufs(&header) {
+///     writer.write_record_buf(&record.unwrap()).unwrap();
+/// }
+/// writer.finish().unwrap();
+///
+/// let collect = |max_open_sets| {
+///     let mut collector = DuplicateSetCollector::new(20, max_open_sets).unwrap();
+///     for record in BamReader::from_path(&bam).unwrap().records() {
+///         collector.update(&record.unwrap());
+///     }
+///     collector.report()
+/// };
+///
+/// // a1-a3（开头clip不超过窗口）与b1-b3（反向链按比对终点对齐）各为大小3的集合；
+/// // a4的clip超过窗口，成为没有代表的duplicate；c3的TLEN不同，自成一个集合
+/// let report = collect(1000);
+/// assert_eq!((report.templates, report.duplicates, report.sets), (13, 7, 6));
+/// assert_eq!(report.set_sizes.iter().collect::<Vec<_>>(), [(&1, &2), (&2, &2), (&3, &2)]);
+/// assert_eq!(report.mean_set_size, Some(2.0));
+/// assert_eq!((report.orphan_duplicates, report.out_of_window_records, report.spilled_sets), (1, 1, 0));
+///
+/// // 只允许1个未结束的集合：c3到达时c1的集合提前结束，之后的c2与代表分开
+/// let report = collect(1);
+/// assert_eq!(report.set_sizes.iter().collect::<Vec<_>>(), [(&1, &3), (&2, &1), (&3, &2)]);
+/// assert_eq!((report.orphan_duplicates, report.spilled_sets), (2, 2));
+/// std::fs::remove_file(&bam).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct DuplicateSetCollector {
+    window: u64,
+    max_open_sets: u64,
+    tid: i32,
+    open: BTreeMap<SetKey, OpenSet>,
+    report: DuplicateSetReport,
+}
+
+impl DuplicateSetCollector {
+    /// `window`须在`[1, MAX_DUP_SET_WINDOW]`之间，`max_open_sets`须大于0。
+    pub fn new(window: u64, max_open_sets: u64) -> Result<Self, DuplicateSetError> {
+        if !(1..=MAX_DUP_SET_WINDOW).contains(&window) {
+            return Err(DuplicateSetError::InvalidWindow { window });
+        }
+        if max_open_sets == 0 {
+            return Err(DuplicateSetError::InvalidMaxOpenSets { max_open_sets });
+        }
+        Ok(Self {
+            window,
+            max_open_sets,
+            tid: -1,
+            open: BTreeMap::new(),
+            report: DuplicateSetReport { window, max_open_sets, ..Default::default() },
+        })
+    }
+
+    /// 计入一条记录；跳过未比对、secondary、supplementary、QC失败的记录与读对中靠右的一端。
+    pub fn update(&mut self, record: &BamRecord) {
+        let filter = ReadFilter { include_duplicates: true, ..Default::default() };
+        if !filter.passes(record) {
+            return;
+        }
+        let (Some(start), Some(five_prime)) = (
+            record.alignment_start(),
+            if record.is_reverse() { record.unclipped_end() } else { record.unclipped_start() },
+        ) else {
+            return;
+        };
+        if record.is_segmented() && !record.is_mate_unmapped() && !Self::is_leftmost(record) {
+            return;
+        }
+
+        if record.tid() != self.tid {
+            self.close_all();
+            self.tid = record.tid();
+        }
+        let horizon = start - self.window as i64;
+        while let Some(entry) = self.open.first_entry() {
+            if entry.key().0 >= horizon {
+                break;
+            }
+            let set = entry.remove();
+            self.close(set);
+        }
+        if five_prime < horizon {
+            self.report.out_of_window_records += 1;
+        }
+
+        self.report.templates += 1;
+        let set = self.open.entry((five_prime, record.is_reverse(), record.insert_size())).or_default();
+        if record.is_duplicate() {
+            self.report.duplicates += 1;
+            set.duplicates += 1;
+        } else {
+            set.representatives += 1;
+        }
+
+        if self.open.len() as u64 > self.max_open_sets {
+            let (_, set) = self.open.pop_first().expect("超过上限时集合非空");
+            self.report.spilled_sets += 1;
+            self.close(set);
+        }
+    }
+
+    /// 结束所有集合并给出报告。
+    pub fn report(mut self) -> DuplicateSetReport {
+        self.close_all();
+        let mut report = self.report;
+        let in_sets = report.templates_in_sets();
+        let in_large_sets: u64 = report.set_sizes.range(LARGE_SET_SIZE..).map(|(size, sets)| size * sets).sum();
+        report.mean_set_size = (report.sets > 0).then(|| in_sets as f64 / report.sets as f64);
+        report.fraction_in_large_sets = (in_sets > 0).then(|| in_large_sets as f64 / in_sets as f64);
+        report.estimated_library_size = estimate_library_size_from_set_sizes(&report.set_sizes);
+        report.lander_waterman_library_size = estimate_library_size(report.templates_in_sets(), report.sets);
+        report
+    }
+
+    /// 读对中靠左的一端；位置相同时取第一读。
+    fn is_leftmost(record: &BamRecord) -> bool {
+        let own = (record.tid(), record.pos());
+        let mate = (record.mtid(), record.mate_position().unwrap_or(-1));
+        own < mate || (own == mate && record.is_first_segment())
+    }
+
+    fn close_all(&mut self) {
+        while let Some((_, set)) = self.open.pop_first() {
+            self.close(set);
+        }
+    }
+
+    /// 一个集合有多个代表时（键相同但MarkDuplicates未判为重复），duplicate归第一个代表，
+    /// 其余代表各为大小1的集合。
+    fn close(&mut self, set: OpenSet) {
+        let report = &mut self.report;
+        if set.representatives == 0 {
+            report.orphan_duplicates += set.duplicates;
+            return;
+        }
+        *report.set_sizes.entry(set.duplicates + 1).or_default() += 1;
+        if set.representatives > 1 {
+            *report.set_sizes.entry(1).or_default() += set.representatives - 1;
+        }
+        report.sets += set.representatives;
+    }
+}
+
+/// 重复集合大小的分布与据此估计的文库大小。
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct DuplicateSetReport {
+    /// BAM文件路径。
+    pub path: String,
+    /// 滑动窗口（bp）。
+    pub window: u64,
+    /// 最多同时保存的未结束集合数。
+    pub max_open_sets: u64,
+    /// 计入的模板数（读对按靠左的一端计一次）。
+    pub templates: u64,
+    /// 其中标记为duplicate的模板数。
+    pub duplicates: u64,
+    /// 有代表的集合数（等于直方图的总计数）。
+    pub sets: u64,
+    /// 集合大小 -> 集合数。
+    pub set_sizes: BTreeMap<u64, u64>,
+    /// 没有代表、不计入直方图的duplicate数。
+    pub orphan_duplicates: u64,
+    /// 因未结束集合超过上限而提前结束的集合数。
+    pub spilled_sets: u64,
+    /// 5'端未clip位置落后比对起点超过窗口的记录数。
+    pub out_of_window_records: u64,
+    /// 平均集合大小：直方图中的模板数 / 集合数，没有集合时为None。
+    pub mean_set_size: Option<f64>,
+    /// 直方图中属于大小不小于[`LARGE_SET_SIZE`]的集合的模板比例，没有集合时为None。
+    pub fraction_in_large_sets: Option<f64>,
+    /// 由集合大小分布估计的文库大小（Chao1，见[`estimate_library_size_from_set_sizes`]）。
+    pub estimated_library_size: Option<u64>,
+    /// 由集合内模板数与集合数按Lander-Waterman估计的文库大小，供比较。
+    pub lander_waterman_library_size: Option<u64>,
+}
+
+impl DuplicateSetReport {
+    /// 直方图中的模板数（不含没有代表的duplicate）。
+    pub fn templates_in_sets(&self) -> u64 {
+        self.set_sizes.iter().map(|(size, sets)| size * sets).sum()
+    }
+
+    fn log_summary(&self) {
+        if self.spilled_sets > 0 {
+            warn!("{} 个集合因未结束集合超过上限 {} 而提前结束", self.spilled_sets, self.max_open_sets);
+        }
+        if self.out_of_window_records > 0 {
+            warn!("{} 条记录的开头clip超过窗口 {} bp，可能与所属集合分开", self.out_of_window_records, self.window);
+        }
+        info!("统计完成：{} 个模板，{} 个重复集合", self.templates, self.sets);
+    }
+}
+
+impl fmt::Display for DuplicateSetReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "path: {}", self.path)?;
+        writeln!(f, "window: {}", self.window)?;
+        writeln!(f, "templates: {}", self.templates)?;
+        writeln!(f, "duplicates: {}", self.duplicates)?;
+        writeln!(f, "sets: {}", self.sets)?;
+        writeln!(f, "mean_set_size: {}", OrNa(self.mean_set_size.map(PicardFloat)))?;
+        writeln!(f, "fraction_in_large_sets: {}", OrNa(self.fraction_in_large_sets.map(PicardFloat)))?;
+        writeln!(f, "estimated_library_size: {}", OrNa(self.estimated_library_size))?;
+        writeln!(f, "lander_waterman_library_size: {}", OrNa(self.lander_waterman_library_size))?;
+        writeln!(f, "orphan_duplicates: {}", self.orphan_duplicates)?;
+        writeln!(f, "spilled_sets: {}", self.spilled_sets)?;
+        write!(f, "out_of_window_records: {}", self.out_of_window_records)?;
+        for (size, sets) in &self.set_sizes {
+            write!(f, "\nset_size: {} ({})", size, sets)?;
+        }
+        Ok(())
+    }
+}
+
+/// 统计按坐标排序的BAM中重复集合大小的分布。
+///
+/// 头部须声明`SO:coordinate`（否则返回[`BamError::WrongSortOrder`]），记录实际乱序时
+/// 返回[`BamError::SortOrderViolation`]。
+///
+/// # Examples
+///
+/// ```
+/// use bamqc_core::{compute_duplicate_sets, generate_test_data, DuplicateSetError, SimulationParams};
+/// use bamqc_io::{BamError, BamReaderOptions};
+///
+/// let bam = std::env::temp_dir().join(format!("bamqc-compute-dup-sets-{}.bam", std::process::id()));
+/// let bam = bam.to_string_lossy().to_string();
+/// generate_test_data(&bam, &SimulationParams { pairs: 2000, ..Default::default() }).unwrap();
+///
+/// let report = compute_duplicate_sets(&bam, &BamReaderOptions::new(), 500, 1000).unwrap();
+/// assert_eq!(report.templates, 2000);
+/// assert_eq!(report.templates_in_sets() + report.orphan_duplicates, report.templates);
+/// assert!(report.to_string().starts_with(&format!("path: {}\nwindow: 500\ntemplates: 2000\n", bam)));
+///
+/// assert!(matches!(compute_duplicate_sets(&bam, &BamReaderOptions::new(), 0, 1000), Err(DuplicateSetError::InvalidWindow { window: 0 })));
+/// std::fs::remove_file(&bam).unwrap();
+/// ```
+pub fn compute_duplicate_sets(
+    bam_path: &str,
+    reader: &BamReaderOptions,
+    window: u64,
+    max_open_sets: u64,
+) -> Result<DuplicateSetReport, DuplicateSetError> {
+    let mut collector = DuplicateSetCollector::new(window, max_open_sets)?;
+    let mut reader = reader.open(bam_path)?.require_sort_order(SortOrder::Coordinate)?;
+    let mut guard = SortOrderGuard::from_header(reader.header(), 0);
+
+    info!("开始统计重复集合: {}", bam_path);
+
+    for result in reader.records() {
+        let record = result?;
+        guard.check(&record)?;
+        collector.update(&record);
+    }
+
+    let mut report = collector.report();
+    report.path = bam_path.to_string();
+    report.log_summary();
+    Ok(report)
+}
+
+metric_keys! {
+    /// `dup-sets`输出的键（见[`crate::metrics`]）。
+    DupSetKeys for "dup-sets" {
+        PATH = "path", "文件路径", Unit::Label, None, "统计的BAM路径。";
+        WINDOW = "window", "窗口", Unit::BasePairs, None, "--window：5'端位置落后当前比对起点超过该值的集合结束。";
+        MAX_OPEN_SETS = "max_open_sets", "未结束集合上限", Unit::Count, None, "--max-open-sets：超过时提前结束5'端位置最小的集合。";
+        TEMPLATES = "templates", "模板数", Unit::Count, None, "计入的模板数：读对按靠左的一端计一次，非配对读长与mate未比对的读长各计一次。";
+        DUPLICATES = "duplicates", "duplicate模板数", Unit::Count, Some(false), "标记为duplicate（0x400）的模板数。";
+        SETS = "sets", "重复集合数", Unit::Count, None, "有非duplicate代表的集合数，包括大小为1的集合。";
+        SET_SIZES = "set_sizes", "集合大小分布", Unit::Map, None, "集合大小（代表加duplicate数）-> 集合数。";
+        SET_SIZE = "set_size", "集合大小", Unit::Label, None, "文本输出中的一个集合大小及其集合数。";
+        MEAN_SET_SIZE = "mean_set_size", "平均集合大小", Unit::Ratio, Some(false), "直方图中的模板数 / 集合数，没有集合时为NA。";
+        FRACTION_IN_LARGE_SETS = "fraction_in_large_sets", "大集合中的模板比例", Unit::Fraction, Some(false), "直方图中属于大小不小于10的集合的模板比例，没有集合时为NA。";
+        ESTIMATED_LIBRARY_SIZE = "estimated_library_size", "估计文库大小", Unit::Count, Some(true), "由集合大小分布按偏差校正的Chao1估计的不同分子数，没有重复时为NA。";
+        LANDER_WATERMAN_LIBRARY_SIZE = "lander_waterman_library_size", "Lander-Waterman文库大小", Unit::Count, Some(true), "由直方图中的模板数与集合数按Lander-Waterman模型估计的不同分子数，供与Chao1比较。";
+        ORPHAN_DUPLICATES = "orphan_duplicates", "没有代表的duplicate数", Unit::Count, Some(false), "所在集合没有非duplicate代表、不计入直方图的duplicate模板数（代表未计入，或与代表被窗口、上限分开）。";
+        SPILLED_SETS = "spilled_sets", "提前结束的集合数", Unit::Count, Some(false), "未结束集合超过--max-open-sets而提前结束的集合数；不为0时可提高上限。";
+        OUT_OF_WINDOW_RECORDS = "out_of_window_records", "超出窗口的记录数", Unit::Count, Some(false), "开头clip超过窗口、5'端位置落后比对起点超过--window的记录数；不为0时可增大窗口。";
+    }
+}
@@ -0,0 +1,392 @@
+//! 基于duplicate标记的重复率统计（与Picard MarkDuplicates的指标定义一致）。
+//!
+//! 重复是文库层面的现象，因此无论其它指标选择何种累积层级，重复率和
+//! 文库大小估计始终按文库（LB）累积：共享同一LB的读组合并统计。
+
+use std::collections::BTreeMap;
+use std::fmt;
+use bamqc_io::bam::{BamReaderOptions, BamError, BamRecord};
+#[cfg(feature = "serde")]
+use bamqc_io::serialize_error;
+use bamqc_io::ErrorCode;
+#[cfg(feature = "serde")]
+use serde::Serialize;
+use thiserror::Error;
+use crate::formatting::PicardFloat;
+use crate::log::info;
+use crate::groups::{AccumulationLevel, GroupInterner, ReadGroupError, UnknownReadGroups, UNKNOWN_GROUP};
+use crate::metrics::Unit;
+use crate::metrics_keys::metric_keys;
+
+/// 重复率统计过程中可能发生的错误。
+///
+/// 错误代码与JSON形式见[`bamqc_io::error_code`]：
+///
+/// ```
+/// # #[cfg(feature = "serde")]
+/// # {
+/// use bamqc_core::{DuplicationError, ReadGroupError};
+/// use bamqc_io::BamError;
+///
+/// let e = DuplicationError::from(ReadGroupError::NotInHeader { read_group: "rg9".into() });
+/// assert_eq!(
+///     serde_json::to_string(&e).unwrap(),
+///     r#"{"code":"read_group_not_in_header","message":"记录中的读组 rg9 未在头部@RG中声明","details":{"read_group":"rg9"}}"#
+/// );
+/// let e = DuplicationError::from(BamError::BgzfChecksum { block_offset: 7 });
+/// assert_eq!(serde_json::to_value(&e).unwrap()["details"]["block_offset"], 7);
+/// # }
+/// ```
+#[derive(Error, Debug)]
+pub enum DuplicationError {
+    /// 严格模式下遇到头部中未声明的读组。
+    #[error("{0}")]
+    ReadGroup(#[from] ReadGroupError),
+
+    /// BAM文件IO错误。
+    #[error("BAM文件IO错误: {0}")]
+    BamError(#[from] BamError),
+}
+
+impl ErrorCode for DuplicationError {
+    fn code(&self) -> &'static str {
+        match self {
+            DuplicationError::ReadGroup(e) => e.code(),
+            DuplicationError::BamError(e) => e.code(),
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    fn serialize_details<M: serde::ser::SerializeMap>(&self, map: &mut M) -> Result<(), M::Error> {
+        match self {
+            DuplicationError::ReadGroup(e) => e.serialize_details(map),
+            DuplicationError::BamError(e) => e.serialize_details(map),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for DuplicationError {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_error(self, serializer)
+    }
+}
+
+/// 单个文库的重复统计。
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct DuplicationMetrics {
+    /// 文库名。
+    pub library: String,
+    /// 文库是否来自头部（false表示由未声明的读组或缺少RG标签的记录产生）。
+    pub in_header: bool,
+    /// 已比对但mate未比对（或非配对）的primary读长数。
+    pub unpaired_reads_examined: u64,
+    /// 两端均已比对的读对数。
+    pub read_pairs_examined: u64,
+    /// secondary与supplementary记录数。
+    pub secondary_or_supplementary_rds: u64,
+    /// 未比对的读长数。
+    pub unmapped_reads: u64,
+    /// 非配对读长中的重复数。
+    pub unpaired_read_duplicates: u64,
+    /// 重复读对数。
+    pub read_pair_duplicates: u64,
+}
+
+impl DuplicationMetrics {
+    fn update(&mut self, record: &BamRecord) {
+        if record.is_secondary() || record.is_supplementary() {
+            self.secondary_or_supplementary_rds += 1;
+            return;
+        }
+        if record.is_unmapped() {
+            self.unmapped_reads += 1;
+            return;
+        }
+
+        if !record.is_segmented() || record.is_mate_unmapped() {
+            self.unpaired_reads_examined += 1;
+            if record.is_duplicate() {
+                self.unpaired_read_duplicates += 1;
+            }
+        } else if record.is_first_segment() {
+            // 每个读对只在第一读处计数一次
+            self.read_pairs_examined += 1;
+            if record.is_duplicate() {
+                self.read_pair_duplicates += 1;
+            }
+        }
+    }
+
+    /// 重复率：(非配对重复 + 2 × 读对重复) / (非配对读长 + 2 × 读对)。
+    pub fn percent_duplication(&self) -> f64 {
+        let examined = self.unpaired_reads_examined + 2 * self.read_pairs_examined;
+        if examined == 0 {
+            0.0
+        } else {
+            (self.unpaired_read_duplicates + 2 * self.read_pair_duplicates) as f64 / examined as f64
+        }
+    }
+
+    /// 估计文库大小（不同分子数），与Picard的`estimateLibrarySize`相同。
+    pub fn estimated_library_size(&self) -> Option<u64> {
+        estimate_library_size(
+            self.read_pairs_examined,
+            self.read_pairs_examined - self.read_pair_duplicates,
+        )
+    }
+}
+
+/// 由读对数与去重后读对数估计文库大小（Lander-Waterman模型）。
+///
+/// # Examples
+///
+/// ```
+/// use bamqc_core::estimate_library_size;
+///
+/// assert_eq!(estimate_library_size(100, 100), None);
+/// let size = estimate_library_size(1_000_000, 900_000).unwrap();
+/// assert!(size > 4_000_000 && size < 5_000_000);
+/// ```
+pub fn estimate_library_size(read_pairs: u64, unique_read_pairs: u64) -> Option<u64> {
+    let n = read_pairs as f64;
+    let c = unique_read_pairs as f64;
+    if read_pairs == 0 || unique_read_pairs == 0 || unique_read_pairs >= read_pairs {
+        return None;
+    }
+
+    let f = |x: f64| c / x - 1.0 + (-n / x).exp();
+
+    let mut m = 1.0;
+    let mut big_m = 100.0;
+    if f(m * c) < 0.0 {
+        return None;
+    }
+    while f(big_m * c) > 0.0 {
+        big_m *= 10.0;
+    }
+
+    for _ in 0..40 {
+        let r = (m + big_m) / 2.0;
+        let u = f(r * c);
+        if u == 0.0 {
+            break;
+        } else if u > 0.0 {
+            m = r;
+        } else {
+            big_m = r;
+        }
+    }
+
+    Some((c * (m + big_m) / 2.0) as u64)
+}
+
+/// 由重复集合大小的分布（集合大小 -> 集合数）估计文库大小（偏差校正的Chao1）。
+///
+/// Lander-Waterman只用读对数与去重后读对数两个数，隐含每个分子被测到的次数服从
+/// 同一Poisson分布；PCR扩增效率不均时低估文库大小。Chao1由观测到的集合数`S`与
+/// 只测到一次、两次的分子数`f1`、`f2`估计未观测到的分子：`S + f1(f1 - 1) / (2(f2 + 1))`，
+/// 对扩增不均更稳健。没有大小不小于2的集合（没有重复）时无法估计，返回None。
+///
+/// # Examples
+///
+/// ```
+/// use std::collections::BTreeMap;
+/// use bamqc_core::estimate_library_size_from_set_sizes;
+///
+/// let sizes = BTreeMap::from([(1, 9_000), (2, 800), (3, 150), (12, 5)]);
+/// assert_eq!(estimate_library_size_from_set_sizes(&sizes), Some(9_955 + 50_556));
+/// assert_eq!(estimate_library_size_from_set_sizes(&BTreeMap::from([(1, 100)])), None);
+/// assert_eq!(estimate_library_size_from_set_sizes(&BTreeMap::new()), None);
+/// ```
+pub fn estimate_library_size_from_set_sizes(set_sizes: &BTreeMap<u64, u64>) -> Option<u64> {
+    if set_sizes.range(2..).all(|(_, &sets)| sets == 0) {
+        return None;
+    }
+    let observed: u64 = set_sizes.values().sum();
+    let f1 = set_sizes.get(&1).copied().unwrap_or(0) as f64;
+    let f2 = set_sizes.get(&2).copied().unwrap_or(0) as f64;
+    let unseen = f1 * (f1 - 1.0).max(0.0) / (2.0 * (f2 + 1.0));
+    Some(observed + unseen.round() as u64)
+}
+
+/// 按文库累积的重复统计报告。
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct DuplicationReport {
+    /// 样本名（按样本分别统计时）。
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub sample: Option<String>,
+    /// 累积层级（始终为LIBRARY）。
+    pub accumulation_level: AccumulationLevel,
+    /// 每个文库一行。
+    pub libraries: Vec<DuplicationMetrics>,
+    /// 记录中出现但头部未声明的读组。
+    pub unknown_read_groups: UnknownReadGroups,
+}
+
+impl fmt::Display for DuplicationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(sample) = &self.sample {
+            writeln!(f, "# sample: {}", sample)?;
+        }
+        writeln!(f, "# accumulation_level: {}（重复是文库层面的现象，始终按文库累积）", self.accumulation_level)?;
+        write!(
+            f,
+            "library\tunpaired_reads_examined\tread_pairs_examined\tsecondary_or_supplementary_rds\t\
+             unmapped_reads\tunpaired_read_duplicates\tread_pair_duplicates\tpercent_duplication\t\
+             estimated_library_size"
+        )?;
+        for m in &self.libraries {
+            write!(
+                f,
+                "\n{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                m.library,
+                m.unpaired_reads_examined,
+                m.read_pairs_examined,
+                m.secondary_or_supplementary_rds,
+                m.unmapped_reads,
+                m.unpaired_read_duplicates,
+                m.read_pair_duplicates,
+                PicardFloat(m.percent_duplication()),
+                m.estimated_library_size().map_or("NA".to_string(), |v| v.to_string())
+            )?;
+        }
+        let not_in_header: Vec<&str> = self
+            .libraries
+            .iter()
+            .filter(|m| !m.in_header)
+            .map(|m| m.library.as_str())
+            .collect();
+        if !not_in_header.is_empty() {
+            write!(f, "\n# not_in_header: {}", not_in_header.join(","))?;
+        }
+        if self.unknown_read_groups.records > 0 {
+            write!(f, "\n# unknown_read_group_records: {}", self.unknown_read_groups.records)?;
+        }
+        Ok(())
+    }
+}
+
+/// 按文库统计重复率与文库大小。
+///
+/// `strict_read_groups`为true时，记录中出现头部未声明的读组直接报错。
+pub fn compute_duplication(bam_path: &str, reader: &BamReaderOptions, strict_read_groups: bool) -> Result<DuplicationReport, DuplicationError> {
+    let mut reader = reader.open(bam_path)?;
+    let mut groups = GroupInterner::from_header(reader.header()).with_strict(strict_read_groups);
+    let level = AccumulationLevel::Library;
+    let mut metrics: Vec<DuplicationMetrics> = Vec::new();
+
+    info!("开始统计重复率: {}", bam_path);
+
+    for result in reader.records() {
+        let record = result?;
+        if record.is_qc_fail() {
+            continue;
+        }
+        let key = groups.record_key(&record, level)?;
+        if key >= metrics.len() {
+            metrics.resize_with(key + 1, DuplicationMetrics::default);
+        }
+        metrics[key].update(&record);
+    }
+
+    groups.warn_unknown(bam_path);
+    Ok(DuplicationReport {
+        sample: None,
+        accumulation_level: level,
+        libraries: finish_libraries(metrics, &groups),
+        unknown_read_groups: groups.unknown_read_groups().clone(),
+    })
+}
+
+/// 按样本分别统计重复率，每个样本内仍按文库累积；顺序与头部中的样本一致。
+///
+/// 记录中出现的未声明读组与缺少RG标签的记录归入`unknown`样本，未声明读组的
+/// 统计只出现在该样本的报告中。
+pub fn compute_duplication_per_sample(
+    bam_path: &str,
+    reader: &BamReaderOptions,
+    strict_read_groups: bool,
+) -> Result<Vec<DuplicationReport>, DuplicationError> {
+    let mut reader = reader.open(bam_path)?;
+    let mut groups = GroupInterner::from_header(reader.header()).with_strict(strict_read_groups);
+    let level = AccumulationLevel::Library;
+    // 样本ID -> 文库ID -> 统计
+    let mut samples: Vec<Vec<DuplicationMetrics>> = vec![Vec::new(); groups.sample_count()];
+
+    info!("开始按样本统计重复率: {}", bam_path);
+
+    for result in reader.records() {
+        let record = result?;
+        if record.is_qc_fail() {
+            continue;
+        }
+        let rg = groups.record_read_group_key(&record)?;
+        let sample = groups.level_key(rg, AccumulationLevel::Sample);
+        let key = groups.level_key(rg, level);
+        if sample >= samples.len() {
+            samples.resize_with(sample + 1, Vec::new);
+        }
+        let metrics = &mut samples[sample];
+        if key >= metrics.len() {
+            metrics.resize_with(key + 1, DuplicationMetrics::default);
+        }
+        metrics[key].update(&record);
+    }
+
+    groups.warn_unknown(bam_path);
+    Ok(samples
+        .into_iter()
+        .enumerate()
+        .map(|(sample, metrics)| {
+            let sample = groups.name(AccumulationLevel::Sample, sample);
+            // 未声明读组的记录都在unknown样本中
+            let unknown_read_groups = if sample == UNKNOWN_GROUP {
+                groups.unknown_read_groups().clone()
+            } else {
+                UnknownReadGroups::default()
+            };
+            DuplicationReport {
+                sample: Some(sample.to_string()),
+                accumulation_level: level,
+                libraries: finish_libraries(metrics, &groups),
+                unknown_read_groups,
+            }
+        })
+        .collect())
+}
+
+/// 填入文库名并去掉没有读长的文库。
+fn finish_libraries(mut metrics: Vec<DuplicationMetrics>, groups: &GroupInterner) -> Vec<DuplicationMetrics> {
+    let level = AccumulationLevel::Library;
+    for (key, m) in metrics.iter_mut().enumerate() {
+        m.library = groups.name(level, key).to_string();
+        m.in_header = groups.in_header(level, key);
+    }
+    metrics.retain(|m| m.unpaired_reads_examined + m.read_pairs_examined + m.unmapped_reads > 0);
+    metrics
+}
+
+metric_keys! {
+    /// `duplication`输出的键（见[`crate::metrics`]）。
+    DuplicationKeys for "duplication" {
+        SAMPLE = "sample", "样本名", Unit::Label, None, "头部@RG的SM；多样本BAM按样本分别输出时出现。";
+        SAMPLES = "samples", "各样本结果", Unit::List, None, "多样本BAM按样本输出到标准输出时，每个样本一份JSON结果。";
+        ACCUMULATION_LEVEL = "accumulation_level", "累积层级", Unit::Label, None, "重复统计的累积层级，始终为LIBRARY。";
+        LIBRARIES = "libraries", "文库", Unit::List, None, "每个文库一行的重复统计。";
+        LIBRARY = "library", "文库名", Unit::Label, None, "头部@RG的LB，未声明的读组以读组ID为文库名。";
+        IN_HEADER = "in_header", "文库是否来自头部", Unit::Boolean, Some(true), "false表示文库由头部未声明的读组或缺少RG标签的记录产生。";
+        UNPAIRED_READS_EXAMINED = "unpaired_reads_examined", "非配对读长数", Unit::Reads, None, "已比对但mate未比对（或非配对）的primary读长数。";
+        READ_PAIRS_EXAMINED = "read_pairs_examined", "读对数", Unit::ReadPairs, None, "两端均已比对的读对数。";
+        SECONDARY_OR_SUPPLEMENTARY_RDS = "secondary_or_supplementary_rds", "secondary与supplementary记录数", Unit::Reads, None, "secondary与supplementary记录数（不参与重复统计）。";
+        UNMAPPED_READS = "unmapped_reads", "未比对读长数", Unit::Reads, Some(false), "未比对的primary读长数。";
+        UNPAIRED_READ_DUPLICATES = "unpaired_read_duplicates", "非配对重复数", Unit::Reads, Some(false), "非配对读长中标记为duplicate的数量。";
+        READ_PAIR_DUPLICATES = "read_pair_duplicates", "重复读对数", Unit::ReadPairs, Some(false), "标记为duplicate的读对数。";
+        PERCENT_DUPLICATION = "percent_duplication", "重复率", Unit::Fraction, Some(false), "(非配对重复 + 2 × 重复读对) / (非配对读长 + 2 × 读对)，与Picard相同（虽然名为percent，取值为0到1）。";
+        ESTIMATED_LIBRARY_SIZE = "estimated_library_size", "估计文库大小", Unit::ReadPairs, Some(true), "按Lander-Waterman模型估计的不同分子数，无法估计时为NA。";
+        NOT_IN_HEADER = "not_in_header", "不在头部的文库", Unit::Label, Some(false), "文本输出末尾的注释行：由未声明读组产生的文库名。";
+        UNKNOWN_READ_GROUP_RECORDS = "unknown_read_group_records", "未声明读组的记录数", Unit::Reads, Some(false), "文本输出末尾的注释行：RG不在头部中的记录数。";
+    }
+}
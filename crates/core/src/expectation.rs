@@ -0,0 +1,181 @@
+//! 插入片段大小的期望值断言，用于流程中的回归门控。
+//!
+//! 在已计算出的中位数上判断是否落在期望范围内，不做额外计算。期望可以写成
+//! 期望值加容差（`--expect 350 --tolerance 50`）或闭区间（`--expect-range 300-450`）；
+//! 未通过时命令行以[`EXIT_QC_FAILED`]退出。
+
+use std::fmt;
+use std::str::FromStr;
+#[cfg(feature = "serde")]
+use serde::Serialize;
+use crate::metrics::Unit;
+use crate::metrics_keys::metric_keys;
+
+/// QC门控未通过时的退出码（1为运行错误，2为命令行参数错误）。
+pub const EXIT_QC_FAILED: i32 = 3;
+
+/// 期望的插入片段范围（闭区间），字符串形式为`300-450`。
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ExpectedRange {
+    /// 下限（含）。
+    pub low: i32,
+    /// 上限（含）。
+    pub high: i32,
+}
+
+impl FromStr for ExpectedRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("无效的期望范围（需要\"下限-上限\"，例如300-450）: {}", s);
+        let (low, high) = s.split_once('-').ok_or_else(invalid)?;
+        let low: i32 = low.trim().parse().map_err(|_| invalid())?;
+        let high: i32 = high.trim().parse().map_err(|_| invalid())?;
+        if low > high {
+            return Err(format!("期望范围的下限 {} 大于上限 {}", low, high));
+        }
+        Ok(Self { low, high })
+    }
+}
+
+impl fmt::Display for ExpectedRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.low, self.high)
+    }
+}
+
+/// 插入片段大小的期望。
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InsertSizeExpectation {
+    /// 期望值与允许的偏差：|中位数 − 期望值| ≤ 容差时通过。
+    Value { expected: i32, tolerance: u32 },
+    /// 中位数落在闭区间内时通过。
+    Range(ExpectedRange),
+}
+
+impl InsertSizeExpectation {
+    /// 对应的闭区间。
+    pub fn range(&self) -> ExpectedRange {
+        match *self {
+            InsertSizeExpectation::Value { expected, tolerance } => ExpectedRange {
+                low: expected.saturating_sub_unsigned(tolerance),
+                high: expected.saturating_add_unsigned(tolerance),
+            },
+            InsertSizeExpectation::Range(range) => range,
+        }
+    }
+
+    /// 判断中位数是否满足期望。
+    ///
+    /// 期望值形式的偏差为`observed − expected`；范围形式的偏差为到最近边界
+    /// 的有符号距离，落在范围内时为0。
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bamqc_core::{ExpectedRange, InsertSizeExpectation};
+    ///
+    /// let value = InsertSizeExpectation::Value { expected: 350, tolerance: 50 };
+    /// // 通过（含边界）
+    /// let pass = value.check(400);
+    /// assert!(pass.pass);
+    /// assert_eq!(pass.delta, 50);
+    /// // 偏高、偏低
+    /// let high = value.check(401);
+    /// assert!(!high.pass);
+    /// assert_eq!(high.delta, 51);
+    /// let low = value.check(280);
+    /// assert!(!low.pass);
+    /// assert_eq!(low.delta, -70);
+    /// assert_eq!(low.to_string(), "expected: 350\ntolerance: 50\nexpected_range: 300-400\nobserved: 280\ndelta: -70\nresult: FAIL");
+    ///
+    /// let range = InsertSizeExpectation::Range("300-450".parse().unwrap());
+    /// assert_eq!((range.check(300).pass, range.check(300).delta), (true, 0));
+    /// assert_eq!(range.check(460).delta, 10);
+    /// assert_eq!(range.check(290).delta, -10);
+    /// assert!(!range.check(290).pass);
+    ///
+    /// // 无效的范围
+    /// assert!("300".parse::<ExpectedRange>().is_err());
+    /// assert!("300-abc".parse::<ExpectedRange>().is_err());
+    /// assert!("450-300".parse::<ExpectedRange>().unwrap_err().contains("大于上限"));
+    /// ```
+    pub fn check(&self, observed: i32) -> ExpectationResult {
+        let range = self.range();
+        let (expected, tolerance, delta) = match *self {
+            InsertSizeExpectation::Value { expected, tolerance } => {
+                (Some(expected), Some(tolerance), observed as i64 - expected as i64)
+            }
+            InsertSizeExpectation::Range(range) => {
+                let delta = if observed < range.low {
+                    observed as i64 - range.low as i64
+                } else if observed > range.high {
+                    observed as i64 - range.high as i64
+                } else {
+                    0
+                };
+                (None, None, delta)
+            }
+        };
+        ExpectationResult {
+            expected,
+            tolerance,
+            low: range.low,
+            high: range.high,
+            observed,
+            delta,
+            pass: (range.low..=range.high).contains(&observed),
+        }
+    }
+}
+
+/// 期望断言的结果。
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct ExpectationResult {
+    /// 期望值（`--expect`时）。
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub expected: Option<i32>,
+    /// 容差（`--expect`时）。
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub tolerance: Option<u32>,
+    /// 期望范围下限（含）。
+    pub low: i32,
+    /// 期望范围上限（含）。
+    pub high: i32,
+    /// 实际的插入片段大小中位数。
+    pub observed: i32,
+    /// 偏差，见[`InsertSizeExpectation::check`]。
+    pub delta: i64,
+    /// 是否通过。
+    pub pass: bool,
+}
+
+impl fmt::Display for ExpectationResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let (Some(expected), Some(tolerance)) = (self.expected, self.tolerance) {
+            writeln!(f, "expected: {}", expected)?;
+            writeln!(f, "tolerance: {}", tolerance)?;
+        }
+        writeln!(f, "expected_range: {}-{}", self.low, self.high)?;
+        writeln!(f, "observed: {}", self.observed)?;
+        writeln!(f, "delta: {}", self.delta)?;
+        write!(f, "result: {}", if self.pass { "PASS" } else { "FAIL" })
+    }
+}
+
+metric_keys! {
+    /// `insert-size`期望断言输出的键（见[`crate::metrics`]）。
+    ExpectationKeys for "insert-size" {
+        EXPECTATION = "expectation", "期望断言", Unit::Map, None, "--expect/--expect-range的断言结果（过滤统计报告中）。";
+        EXPECTED = "expected", "期望值", Unit::BasePairs, None, "--expect给出的期望插入片段大小。";
+        TOLERANCE = "tolerance", "容差", Unit::BasePairs, None, "--tolerance给出的允许偏差。";
+        EXPECTED_RANGE = "expected_range", "期望范围", Unit::Label, None, "文本输出中的期望闭区间（下限-上限）。";
+        LOW = "low", "期望范围下限", Unit::BasePairs, None, "期望闭区间的下限。";
+        HIGH = "high", "期望范围上限", Unit::BasePairs, None, "期望闭区间的上限。";
+        OBSERVED = "observed", "实际值", Unit::BasePairs, None, "计算得到的插入片段大小中位数。";
+        DELTA = "delta", "偏差", Unit::BasePairs, None, "--expect时为observed − expected；--expect-range时为到最近边界的有符号距离，范围内为0。";
+        PASS = "pass", "是否通过", Unit::Boolean, Some(true), "中位数是否落在期望范围内；未通过时以退出码3结束。";
+        RESULT = "result", "断言结果", Unit::Label, None, "文本输出中的PASS或FAIL。";
+    }
+}
@@ -0,0 +1,286 @@
+//! 未排序BAM的外部排序归并读对。
+//!
+//! 读对模式需要把mate放在一起。按坐标或读名排序的BAM中mate相距不远，
+//! 内存中的等待表足够；完全未排序（@HD SO为unsorted、unknown或缺失）时
+//! mate可能相隔整个文件，等待表几乎要容纳全部记录。这里把每条记录压缩为
+//! [`RecordSummary`]，按（读名哈希, 读名）排序后分批写入临时目录中的有序
+//! run（每个run不超过给定条数），再多路归并，使同名记录相邻。run内使用
+//! 稳定排序、归并时键相同的记录先取编号小的run，因此同名记录保持文件中的
+//! 先后顺序。
+//!
+//! 临时目录在[`NameGrouper`]或[`MergedRuns`]drop时删除，出错或panic展开
+//! 时同样会清理。
+
+use std::cmp::Reverse;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BinaryHeap;
+use std::fs::{self, File};
+use std::hash::{Hash, Hasher};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use noodles::sam;
+use noodles::sam::header::record::value::map::header::tag::SORT_ORDER;
+use crate::log::debug;
+
+/// 每个run的默认最大记录数（约几十MB内存）。
+pub const DEFAULT_RUN_RECORDS: usize = 1 << 20;
+
+/// 头部的排序方式是否不足以让mate相邻或相近（SO为unsorted、unknown或缺失）。
+///
+/// # Examples
+///
+/// ```
+/// use bamqc_core::needs_name_grouping;
+///
+/// let parse = |text: &str| text.parse::<noodles::sam::Header>().unwrap();
+/// assert!(!needs_name_grouping(&parse("@HD\tVN:1.6\tSO:coordinate\n")));
+/// assert!(!needs_name_grouping(&parse("@HD\tVN:1.6\tSO:queryname\n")));
+/// assert!(needs_name_grouping(&parse("@HD\tVN:1.6\tSO:unsorted\n")));
+/// assert!(needs_name_grouping(&parse("@HD\tVN:1.6\n")));
+/// assert!(needs_name_grouping(&parse("")));
+/// ```
+pub fn needs_name_grouping(header: &sam::Header) -> bool {
+    let sort_order = header
+        .header()
+        .and_then(|hd| hd.other_fields().get(&SORT_ORDER))
+        .map(|value| value.to_vec());
+    !matches!(sort_order.as_deref(), Some(b"coordinate" | b"queryname"))
+}
+
+/// 归并所需的最小记录摘要。
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RecordSummary {
+    /// 读名哈希（排序主键，使比较多数情况下不必比较读名）。
+    pub name_hash: u64,
+    /// 读名。
+    pub name: Vec<u8>,
+    /// GC碱基数。
+    pub gc: u32,
+    /// 非N碱基数。
+    pub called: u32,
+    /// FLAG。
+    pub flags: u16,
+}
+
+impl RecordSummary {
+    /// 由读名与其余字段构建，计算读名哈希。
+    pub fn new(name: &[u8], gc: u32, called: u32, flags: u16) -> Self {
+        let mut hasher = DefaultHasher::new();
+        name.hash(&mut hasher);
+        Self {
+            name_hash: hasher.finish(),
+            name: name.to_vec(),
+            gc,
+            called,
+            flags,
+        }
+    }
+
+    fn key(&self) -> (u64, &[u8]) {
+        (self.name_hash, &self.name)
+    }
+
+    fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&self.name_hash.to_le_bytes())?;
+        writer.write_all(&(self.name.len() as u32).to_le_bytes())?;
+        writer.write_all(&self.name)?;
+        writer.write_all(&self.gc.to_le_bytes())?;
+        writer.write_all(&self.called.to_le_bytes())?;
+        writer.write_all(&self.flags.to_le_bytes())
+    }
+
+    /// 读取一条摘要，run结束时返回None。
+    fn read_from<R: Read>(reader: &mut R) -> io::Result<Option<Self>> {
+        let mut hash = [0; 8];
+        match reader.read_exact(&mut hash) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e),
+        }
+        let mut u32_buf = [0; 4];
+        reader.read_exact(&mut u32_buf)?;
+        let mut name = vec![0; u32::from_le_bytes(u32_buf) as usize];
+        reader.read_exact(&mut name)?;
+        reader.read_exact(&mut u32_buf)?;
+        let gc = u32::from_le_bytes(u32_buf);
+        reader.read_exact(&mut u32_buf)?;
+        let called = u32::from_le_bytes(u32_buf);
+        let mut flags = [0; 2];
+        reader.read_exact(&mut flags)?;
+        Ok(Some(Self {
+            name_hash: u64::from_le_bytes(hash),
+            name,
+            gc,
+            called,
+            flags: u16::from_le_bytes(flags),
+        }))
+    }
+}
+
+/// 临时run目录，drop时删除。
+#[derive(Debug)]
+struct RunDir {
+    path: PathBuf,
+}
+
+impl RunDir {
+    fn create(parent: &Path) -> io::Result<Self> {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        let path = parent.join(format!(
+            "bamqc-runs-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::create_dir_all(&path)?;
+        Ok(Self { path })
+    }
+}
+
+impl Drop for RunDir {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_dir_all(&self.path) {
+            debug!("删除临时目录失败 {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+/// 把记录摘要写成有序run，最后归并为按读名分组的流。
+///
+/// # Examples
+///
+/// ```
+/// use bamqc_core::{NameGrouper, RecordSummary};
+///
+/// let dir = std::env::temp_dir();
+/// // 每个run最多3条，7条记录写出3个run
+/// let mut grouper = NameGrouper::new(&dir, 3).unwrap();
+/// for name in ["c", "a", "b", "a", "d", "c", "b"] {
+///     grouper.push(RecordSummary::new(name.as_bytes(), 0, 0, 0)).unwrap();
+/// }
+/// assert_eq!(grouper.runs(), 2);
+///
+/// let mut merged = grouper.finish().unwrap();
+/// let run_dir = merged.dir().to_path_buf();
+/// assert!(run_dir.exists());
+/// let names: Vec<Vec<u8>> = merged.by_ref().map(|s| s.unwrap().name).collect();
+/// assert_eq!(names.len(), 7);
+/// // 同名记录相邻：相邻去重后不再有重复的读名
+/// let mut groups = names.clone();
+/// groups.dedup();
+/// let mut unique = groups.clone();
+/// unique.sort();
+/// unique.dedup();
+/// assert_eq!(groups.len(), unique.len());
+/// drop(merged);
+/// assert!(!run_dir.exists());
+/// ```
+#[derive(Debug)]
+pub struct NameGrouper {
+    dir: RunDir,
+    run_records: usize,
+    buffer: Vec<RecordSummary>,
+    runs: Vec<PathBuf>,
+}
+
+impl NameGrouper {
+    /// 在`tmp_dir`下创建临时run目录；每个run最多`run_records`条摘要。
+    pub fn new(tmp_dir: &Path, run_records: usize) -> io::Result<Self> {
+        let dir = RunDir::create(tmp_dir)?;
+        debug!("外部排序临时目录: {}", dir.path.display());
+        Ok(Self {
+            dir,
+            run_records: run_records.max(1),
+            buffer: Vec::new(),
+            runs: Vec::new(),
+        })
+    }
+
+    /// 已写出的run数。
+    pub fn runs(&self) -> usize {
+        self.runs.len()
+    }
+
+    /// 加入一条摘要，缓冲区满时写出一个run。
+    pub fn push(&mut self, summary: RecordSummary) -> io::Result<()> {
+        self.buffer.push(summary);
+        if self.buffer.len() >= self.run_records {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    fn spill(&mut self) -> io::Result<()> {
+        self.buffer.sort_by(|a, b| a.key().cmp(&b.key()));
+        let path = self.dir.path.join(format!("run-{}", self.runs.len()));
+        let mut writer = BufWriter::new(File::create(&path)?);
+        for summary in self.buffer.drain(..) {
+            summary.write_to(&mut writer)?;
+        }
+        writer.flush()?;
+        self.runs.push(path);
+        Ok(())
+    }
+
+    /// 写出剩余的缓冲并开始多路归并。
+    pub fn finish(mut self) -> io::Result<MergedRuns> {
+        if !self.buffer.is_empty() {
+            self.spill()?;
+        }
+        debug!("外部排序: {} 个run", self.runs.len());
+
+        let mut readers = Vec::with_capacity(self.runs.len());
+        let mut heads = Vec::with_capacity(self.runs.len());
+        let mut heap = BinaryHeap::new();
+        for (i, path) in self.runs.iter().enumerate() {
+            let mut reader = BufReader::new(File::open(path)?);
+            let head = RecordSummary::read_from(&mut reader)?;
+            if let Some(head) = &head {
+                heap.push(Reverse((head.name_hash, head.name.clone(), i)));
+            }
+            readers.push(reader);
+            heads.push(head);
+        }
+
+        Ok(MergedRuns {
+            dir: self.dir,
+            readers,
+            heads,
+            heap,
+        })
+    }
+}
+
+/// 归并后的摘要流，按（读名哈希, 读名）排序，同名记录相邻且保持文件顺序。
+#[derive(Debug)]
+pub struct MergedRuns {
+    dir: RunDir,
+    readers: Vec<BufReader<File>>,
+    heads: Vec<Option<RecordSummary>>,
+    heap: BinaryHeap<Reverse<(u64, Vec<u8>, usize)>>,
+}
+
+impl MergedRuns {
+    /// 临时run目录（drop后删除）。
+    pub fn dir(&self) -> &Path {
+        &self.dir.path
+    }
+}
+
+impl Iterator for MergedRuns {
+    type Item = io::Result<RecordSummary>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let Reverse((_, _, i)) = self.heap.pop()?;
+        let summary = self.heads[i].take()?;
+        match RecordSummary::read_from(&mut self.readers[i]) {
+            Ok(Some(head)) => {
+                self.heap.push(Reverse((head.name_hash, head.name.clone(), i)));
+                self.heads[i] = Some(head);
+            }
+            Ok(None) => {}
+            Err(e) => return Some(Err(e)),
+        }
+        Some(Ok(summary))
+    }
+}
@@ -0,0 +1,300 @@
+//! 记录过滤条件（与samtools view的`-f`/`-F`/`-q`语义一致）。
+
+use bamqc_io::bam::{BamError, BamRecord};
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+/// 按FLAG与MAPQ过滤记录。
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct RecordFilter {
+    /// 必须全部置位的FLAG位（`-f`）。
+    pub require_flags: u16,
+    /// 任一置位即排除的FLAG位（`-F`）。
+    pub exclude_flags: u16,
+    /// 最低MAPQ（`-q`）。
+    pub min_mapq: u8,
+}
+
+impl RecordFilter {
+    /// 记录是否通过过滤。
+    pub fn matches(&self, record: &BamRecord) -> bool {
+        let flag = record.flag();
+        if flag & self.require_flags != self.require_flags {
+            return false;
+        }
+        if flag & self.exclude_flags != 0 {
+            return false;
+        }
+        record.mapq() >= self.min_mapq
+    }
+}
+
+/// 按记录类别过滤读段（各统计共用的"跳过未比对、secondary……"条件）。
+///
+/// 默认值只保留已比对的primary、非duplicate、未标记QC失败的记录；各`include_*`
+/// 字段放宽相应条件，`require_*`与`min_mapq`收紧条件。检查按[`RejectReason`]的
+/// 顺序进行，被排除的记录归到第一个不满足的条件。
+///
+/// # Examples
+///
+/// ```
+/// use bamqc_core::{ReadFilter, RejectReason};
+///
+/// let filter = ReadFilter::default();
+/// assert_eq!(filter.check_flags(0x1 | 0x2 | 0x20, 60), Ok(()));
+/// assert_eq!(filter.check_flags(0x4 | 0x400, 0), Err(RejectReason::Duplicate));
+/// assert_eq!(filter.check_flags(0x100, 60), Err(RejectReason::Secondary));
+///
+/// let pairs = ReadFilter { include_duplicates: true, require_paired: true, require_both_mapped: true, ..filter };
+/// assert_eq!(pairs.check_flags(0x1 | 0x400, 60), Ok(()));
+/// assert_eq!(pairs.check_flags(0x0, 60), Err(RejectReason::Unpaired));
+/// assert_eq!(pairs.check_flags(0x1 | 0x8, 60), Err(RejectReason::MateUnmapped));
+/// assert_eq!(ReadFilter { min_mapq: 20, ..filter }.check_flags(0x0, 10), Err(RejectReason::LowMapq));
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct ReadFilter {
+    /// 保留标记为duplicate（0x400）的记录。
+    pub include_duplicates: bool,
+    /// 保留secondary（0x100）记录。
+    pub include_secondary: bool,
+    /// 保留supplementary（0x800）记录。
+    pub include_supplementary: bool,
+    /// 保留QC失败（0x200）的记录。
+    pub include_qc_fail: bool,
+    /// 保留未比对（0x4）的记录。
+    pub include_unmapped: bool,
+    /// 只保留配对测序的记录（0x1）。
+    pub require_paired: bool,
+    /// 只保留proper pair（0x2）。
+    pub require_proper_pair: bool,
+    /// 配对记录的mate也须已比对（排除0x8）。
+    pub require_both_mapped: bool,
+    /// 最低MAPQ（为0时不检查）。
+    pub min_mapq: u8,
+}
+
+/// 记录被[`ReadFilter`]排除的原因，按检查顺序排列。
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize), serde(rename_all = "snake_case"))]
+pub enum RejectReason {
+    Unpaired,
+    Secondary,
+    Supplementary,
+    QcFail,
+    Duplicate,
+    Unmapped,
+    MateUnmapped,
+    ImproperPair,
+    LowMapq,
+}
+
+impl RejectReason {
+    pub const ALL: [RejectReason; 9] = [
+        RejectReason::Unpaired,
+        RejectReason::Secondary,
+        RejectReason::Supplementary,
+        RejectReason::QcFail,
+        RejectReason::Duplicate,
+        RejectReason::Unmapped,
+        RejectReason::MateUnmapped,
+        RejectReason::ImproperPair,
+        RejectReason::LowMapq,
+    ];
+
+    /// 报告与日志中的名称。
+    pub fn name(self) -> &'static str {
+        match self {
+            RejectReason::Unpaired => "unpaired",
+            RejectReason::Secondary => "secondary",
+            RejectReason::Supplementary => "supplementary",
+            RejectReason::QcFail => "qc_fail",
+            RejectReason::Duplicate => "duplicate",
+            RejectReason::Unmapped => "unmapped",
+            RejectReason::MateUnmapped => "mate_unmapped",
+            RejectReason::ImproperPair => "improper_pair",
+            RejectReason::LowMapq => "low_mapq",
+        }
+    }
+}
+
+impl std::fmt::Display for RejectReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl ReadFilter {
+    /// 记录是否通过过滤。
+    pub fn passes(&self, record: &BamRecord) -> bool {
+        self.check(record).is_ok()
+    }
+
+    /// 检查记录，被排除时返回第一个不满足的条件。
+    pub fn check(&self, record: &BamRecord) -> Result<(), RejectReason> {
+        self.check_flags(record.flag(), record.mapq())
+    }
+
+    /// 按FLAG与MAPQ检查，见[`ReadFilter::check`]。
+    pub fn check_flags(&self, flag: u16, mapq: u8) -> Result<(), RejectReason> {
+        let has = |bit: u16| flag & bit != 0;
+        let paired = has(0x1);
+        let rejected = if self.require_paired && !paired {
+            RejectReason::Unpaired
+        } else if !self.include_secondary && has(0x100) {
+            RejectReason::Secondary
+        } else if !self.include_supplementary && has(0x800) {
+            RejectReason::Supplementary
+        } else if !self.include_qc_fail && has(0x200) {
+            RejectReason::QcFail
+        } else if !self.include_duplicates && has(0x400) {
+            RejectReason::Duplicate
+        } else if !self.include_unmapped && has(0x4) {
+            RejectReason::Unmapped
+        } else if self.require_both_mapped && paired && has(0x8) {
+            RejectReason::MateUnmapped
+        } else if self.require_proper_pair && !has(0x2) {
+            RejectReason::ImproperPair
+        } else if mapq < self.min_mapq {
+            RejectReason::LowMapq
+        } else {
+            return Ok(());
+        };
+        Err(rejected)
+    }
+
+    /// 检查记录并计入`counts`，返回是否通过。
+    pub fn count(&self, record: &BamRecord, counts: &mut ReadFilterCounts) -> bool {
+        let result = self.check(record);
+        counts.add(result);
+        result.is_ok()
+    }
+}
+
+/// [`ReadFilter`]通过与按原因排除的记录数。
+///
+/// `Display`输出一行摘要，只列出非零的排除原因，例如
+/// `通过 980，排除 20（secondary 12，duplicate 8）`。
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct ReadFilterCounts {
+    pub passed: u64,
+    /// 按[`RejectReason::ALL`]顺序的排除数。
+    pub rejected: [u64; 9],
+}
+
+impl ReadFilterCounts {
+    pub fn add(&mut self, result: Result<(), RejectReason>) {
+        match result {
+            Ok(()) => self.passed += 1,
+            Err(reason) => self.rejected[reason as usize] += 1,
+        }
+    }
+
+    /// 因`reason`被排除的记录数。
+    pub fn get(&self, reason: RejectReason) -> u64 {
+        self.rejected[reason as usize]
+    }
+
+    /// 被排除的记录总数。
+    pub fn total_rejected(&self) -> u64 {
+        self.rejected.iter().sum()
+    }
+
+    /// 非零的(原因, 排除数)，按检查顺序。
+    pub fn reasons(&self) -> impl Iterator<Item = (RejectReason, u64)> + '_ {
+        RejectReason::ALL.into_iter().map(|reason| (reason, self.get(reason))).filter(|&(_, n)| n > 0)
+    }
+
+    /// 累加另一份计数（多线程或多个输入分别统计后合并）。
+    pub fn merge(&mut self, other: &ReadFilterCounts) {
+        self.passed += other.passed;
+        for (total, n) in self.rejected.iter_mut().zip(other.rejected) {
+            *total += n;
+        }
+    }
+}
+
+impl std::fmt::Display for ReadFilterCounts {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "通过 {}，排除 {}", self.passed, self.total_rejected())?;
+        let reasons: Vec<String> = self.reasons().map(|(reason, n)| format!("{} {}", reason, n)).collect();
+        if !reasons.is_empty() {
+            write!(f, "（{}）", reasons.join("，"))?;
+        }
+        Ok(())
+    }
+}
+
+/// 只产出通过`filter`的记录，同时按原因统计被排除的记录；错误原样传递。
+///
+/// # Examples
+///
+/// ```
+/// use bamqc_core::{filtered, generate_test_data, ReadFilter, RejectReason, SimulationParams};
+/// use bamqc_io::bam::BamReader;
+///
+/// let bam = std::env::temp_dir().join(format!("bamqc-read-filter-{}.bam", std::process::id()));
+/// let bam = bam.to_string_lossy().to_string();
+/// let params = SimulationParams { pairs: 1000, dup_rate: 0.1, secondary_rate: 0.05, ..Default::default() };
+/// generate_test_data(&bam, &params).unwrap();
+///
+/// let mut reader = BamReader::from_path(&bam).unwrap();
+/// let mut records = filtered(reader.records(), &ReadFilter::default());
+/// let kept = records.by_ref().map(|r| r.unwrap()).filter(|r| !r.is_duplicate() && !r.is_secondary()).count() as u64;
+/// let counts = records.counts();
+/// assert_eq!(kept, counts.passed);
+/// assert!(counts.get(RejectReason::Duplicate) > 0 && counts.get(RejectReason::Secondary) > 0);
+/// assert!(counts.to_string().starts_with(&format!("通过 {}，排除 ", kept)));
+/// std::fs::remove_file(&bam).unwrap();
+/// ```
+pub fn filtered<I>(records: I, filter: &ReadFilter) -> ReadFiltered<I>
+where
+    I: Iterator<Item = Result<BamRecord, BamError>>,
+{
+    ReadFiltered { inner: records, filter: *filter, counts: ReadFilterCounts::default() }
+}
+
+/// 见[`filtered`]。
+#[derive(Debug)]
+pub struct ReadFiltered<I> {
+    inner: I,
+    filter: ReadFilter,
+    counts: ReadFilterCounts,
+}
+
+impl<I> ReadFiltered<I> {
+    /// 到目前为止的通过与排除计数。
+    pub fn counts(&self) -> &ReadFilterCounts {
+        &self.counts
+    }
+}
+
+impl<I: Iterator<Item = Result<BamRecord, BamError>>> Iterator for ReadFiltered<I> {
+    type Item = Result<BamRecord, BamError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (filter, counts) = (&self.filter, &mut self.counts);
+        self.inner.find(|result| result.as_ref().map_or(true, |record| filter.count(record, counts)))
+    }
+}
+
+/// 解析FLAG掩码，支持十进制和`0x`开头的十六进制。
+///
+/// # Examples
+///
+/// ```
+/// use bamqc_core::parse_flag_mask;
+///
+/// assert_eq!(parse_flag_mask("1024"), Ok(1024));
+/// assert_eq!(parse_flag_mask("0x904"), Ok(0x904));
+/// assert!(parse_flag_mask("0x10000").is_err());
+/// ```
+pub fn parse_flag_mask(s: &str) -> Result<u16, String> {
+    let parsed = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => s.parse::<u16>(),
+    };
+    parsed.map_err(|_| format!("无效的FLAG掩码: {}", s))
+}
@@ -1,9 +1,142 @@
 //! samtools flagstat 的实现
+//!
+//! 支持全文件统计、单区域统计（`--region`）以及按BED区域逐行输出的
+//! 分区域统计表。区域统计的计数语义：
+//!
+//! * 与区域重叠的所有记录都会被计入（与`samtools view -c <region>`一致），
+//!   再由FlagStat按primary/secondary/supplementary分类；
+//! * 同时与两个请求区域重叠的记录在TOTAL行中只计一次；在各区域行中的归属
+//!   由`--region-overlap`决定（见[`RegionOverlap`]）：默认`all`在两行中各计一次
+//!   （各行之和可能超过TOTAL），`first`只计入BED中靠前的区域，`merge`先把重叠
+//!   区域合并为一行，后两者各行之和等于TOTAL；非默认方式在输出中注明；
+//! * 与mate相关的字段按记录中登记的值使用，不论mate本身是否位于区域内。
+//!
+//! 比对到转录组或片段重复较多的基因组时secondary记录往往比primary还多，
+//! 不区分记录类型的指标会被严重扭曲。输出中的`secondary_ratio`为
+//! secondary/primary记录数之比，超过阈值（默认[`DEFAULT_MAX_SECONDARY_RATIO`]）
+//! 时给出警告；`--by-contig`按参考序列逐行给出该比例。
+//!
+//! 全文件与按参考序列的统计同时检测只有secondary、没有primary记录的读长，
+//! 以及`--promote-best-secondary`下把其最佳secondary记录当作primary计数，
+//! 见[`crate::missing_primary`]。
 
-use bamqc_io::bam::{BamRecord};
+use bamqc_io::bam::{BamReader, BamReaderOptions, BamError, BamRecord, SortOrder};
+use bamqc_io::cigar::CigarSource;
+#[cfg(feature = "serde")]
+use bamqc_io::serialize_error;
+use bamqc_io::ErrorCode;
+use bamqc_io::region::GenomicRegion;
+use bamqc_io::warnings::{WarningClass, WarningCount, WarningSnapshot, WARNINGS};
+#[cfg(feature = "intervals")]
+use bamqc_io::region::{read_bed, RegionOverlap, RegionPlan};
 use std::fmt;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use crate::formatting::{OrNa, Percent, Rate};
+use crate::bounds::ContigBounds;
+use crate::interim::InterimEmitter;
+use crate::log::{info, warn};
+#[cfg(feature = "intervals")]
+use crate::log::debug;
+use crate::metrics::Unit;
+use crate::metrics_keys::metric_keys;
+use crate::missing_primary::{
+    missing_primary_report, MissingPrimaryDetector, MissingPrimaryReport, NameGroupPromoter, PrimarySelection,
+    DEFAULT_MAX_MISSING_PRIMARY_FRACTION, DEFAULT_MISSING_PRIMARY_WINDOW,
+};
 
+/// secondary/primary记录数之比的默认警告阈值。
+pub const DEFAULT_MAX_SECONDARY_RATIO: f64 = 1.0;
+
+/// 全文件flagstat的选项。
+#[derive(Clone, Debug)]
+pub struct FlagStatOptions {
+    /// 把缺少primary的片段的最佳secondary记录当作primary计数（只对按读名排序的输入生效）。
+    pub promote_best_secondary: bool,
+    /// 检测缺少primary的读名的抽样窗口（记录数）。
+    pub missing_primary_window: u64,
+    /// 缺少primary的读名比例超过该值时警告。
+    pub max_missing_primary_fraction: f64,
+    /// 打开输入的读取器配置（解压线程数、输入格式、CRAM参考序列等）。
+    pub reader: BamReaderOptions,
+}
+
+impl Default for FlagStatOptions {
+    fn default() -> Self {
+        Self {
+            promote_best_secondary: false,
+            missing_primary_window: DEFAULT_MISSING_PRIMARY_WINDOW,
+            max_missing_primary_fraction: DEFAULT_MAX_MISSING_PRIMARY_FRACTION,
+            reader: BamReaderOptions::new(),
+        }
+    }
+}
+
+/// flagstat统计过程中可能发生的错误。
+///
+/// 错误代码与JSON形式见[`bamqc_io::error_code`]：
+///
+/// ```
+/// # #[cfg(feature = "serde")]
+/// # {
+/// use bamqc_core::FlagStatError;
+/// use bamqc_io::BamError;
+///
+/// let e = FlagStatError::NoRegions { path: "t.bed".into() };
+/// assert_eq!(serde_json::to_string(&e).unwrap(), r#"{"code":"no_regions","message":"区域文件中没有有效区域: t.bed","details":{"path":"t.bed"}}"#);
+/// // 包装的错误沿用内层的代码与details
+/// let e = FlagStatError::from(BamError::IndexNotFound { path: "a.bam".into() });
+/// assert_eq!(
+///     serde_json::to_string(&e).unwrap(),
+///     r#"{"code":"index_not_found","message":"BAM文件IO错误: 找不到索引文件: a.bam（请先运行samtools index）","details":{"path":"a.bam"}}"#
+/// );
+/// # }
+/// ```
+#[derive(Error, Debug)]
+pub enum FlagStatError {
+    /// BED文件中没有任何区域。
+    #[error("区域文件中没有有效区域: {path}")]
+    NoRegions {
+        /// 区域文件路径
+        path: String,
+    },
+
+    /// BAM文件IO错误。
+    #[error("BAM文件IO错误: {0}")]
+    BamError(#[from] BamError),
+}
+
+impl ErrorCode for FlagStatError {
+    fn code(&self) -> &'static str {
+        match self {
+            FlagStatError::NoRegions { .. } => "no_regions",
+            FlagStatError::BamError(e) => e.code(),
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    fn serialize_details<M: serde::ser::SerializeMap>(&self, map: &mut M) -> Result<(), M::Error> {
+        match self {
+            FlagStatError::NoRegions { path } => map.serialize_entry("path", path),
+            FlagStatError::BamError(e) => e.serialize_details(map),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for FlagStatError {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_error(self, serializer)
+    }
+}
+
+/// FLAG统计结果。
+///
+/// 序列化后可以反序列化回来，与读取器的虚拟偏移（[`BamReader::tell`]）一起
+/// 作为检查点，恢复后从该偏移继续[`FlagStat::update`]。
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct FlagStat {
     total: u64,
     primary: u64,
@@ -12,6 +145,26 @@ pub struct FlagStat {
     duplicate: u64,
     mapped: u64,
     primary_mapped: u64,
+    /// 比对到参考序列末端之外的记录数（仍计入上面的各项）
+    past_contig_end: u64,
+    /// CIGAR超过65535个操作、真实操作取自CG标签的记录数
+    long_cigar: u64,
+    /// CIGAR为长CIGAR占位符但CG标签缺失或无效的记录数
+    invalid_long_cigar: u64,
+    /// 读名含非ASCII字节的记录数
+    non_ascii_names: u64,
+    /// 本次运行中各类逐记录警告的总数（只含出现过的类别）
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Vec::is_empty"))]
+    warnings: Vec<WarningCount>,
+    /// 创建时[`WARNINGS`]的计数，本次运行的警告从这里算起
+    #[cfg_attr(feature = "serde", serde(skip))]
+    warnings_since: WarningSnapshot,
+    /// 输入没有任何记录（只有头部），此时各项计数都为0
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "std::ops::Not::not"))]
+    no_records: bool,
+    /// 检测到缺少primary的读长或要求了提升时的检测结果与处理方式
+    #[cfg_attr(feature = "serde", serde(flatten))]
+    missing_primary: Option<MissingPrimaryReport>,
 }
 
 
@@ -28,9 +181,22 @@ impl FlagStat {
             duplicate: 0,
             mapped: 0,
             primary_mapped: 0,
+            past_contig_end: 0,
+            long_cigar: 0,
+            invalid_long_cigar: 0,
+            non_ascii_names: 0,
+            warnings: Vec::new(),
+            warnings_since: WARNINGS.snapshot(),
+            no_records: false,
+            missing_primary: None,
         }
     }
     pub fn update(&mut self, record: &BamRecord) {
+        self.update_with(record, false);
+    }
+
+    /// 与[`FlagStat::update`]相同；`promoted`为true时把secondary记录当作primary计数。
+    pub fn update_with(&mut self, record: &BamRecord, promoted: bool) {
         
         // QC失败的记录直接跳过，不计入任何统计
         if record.is_qc_fail() {
@@ -38,14 +204,15 @@ impl FlagStat {
         }
 
         self.total += 1;
+        let primary = promoted || !(record.is_secondary() | record.is_supplementary());
 
         // 按记录类型分类统计
-        if !(record.is_secondary() | record.is_supplementary()) {
+        if primary {
             self.primary += 1;
         }
 
     
-        if record.is_secondary() {
+        if record.is_secondary() && !promoted {
             self.secondary += 1;
         }
 
@@ -61,11 +228,47 @@ impl FlagStat {
             self.mapped += 1;
         }
 
-        if !record.is_unmapped() & !(record.is_secondary() | record.is_supplementary()) {
+        if !record.is_unmapped() & primary {
             self.primary_mapped += 1;
         }
 
+        match record.cigar_source() {
+            CigarSource::Inline => {}
+            CigarSource::CgTag => self.long_cigar += 1,
+            CigarSource::InvalidCg => {
+                self.invalid_long_cigar += 1;
+                WARNINGS.warn_since(&self.warnings_since, WarningClass::InvalidLongCigar, || record.name().into_owned());
+            }
+        }
+
+        if !record.qname().is_ascii() {
+            self.non_ascii_names += 1;
+            WARNINGS.warn_since(&self.warnings_since, WarningClass::NonAsciiName, || record.name().into_owned());
+        }
+    }
+
+    /// 记录逐记录警告的总数，并在有长CIGAR记录时输出日志。
+    ///
+    /// 读名含非ASCII字节、CG标签缺失或无效等逐记录问题在本次运行中第一次出现时经
+    /// [`WARNINGS`]输出详情，总数只汇总创建以来的增量，同一进程中先前的运行不计入。
+    fn finish_record_issues(&mut self) {
+        if self.long_cigar > 0 {
+            info!("{} 条记录的CIGAR取自CG标签（超过65535个操作）", self.long_cigar);
+        }
+        self.warnings = WARNINGS.summary_since(&self.warnings_since);
+    }
 
+    /// 输入是否没有任何记录（只有头部）。
+    ///
+    /// 与“有记录但全部是QC失败记录”区分：后者`total`同样为0，但这里为false。
+    /// 区域查询只读到区域内的记录，不能说明整个输入为空，总是为false。
+    pub fn no_records(&self) -> bool {
+        self.no_records
+    }
+
+    /// 缺少primary的读长的检测结果；未检测到且未要求提升时为None。
+    pub fn missing_primary(&self) -> Option<&MissingPrimaryReport> {
+        self.missing_primary.as_ref()
     }
 
     pub fn mapped_rate(&self) -> f64 {
@@ -75,6 +278,81 @@ impl FlagStat {
             self.mapped as f64 / self.total as f64
         }
     }
+
+    /// secondary与primary记录数之比；没有primary记录时为None。
+    pub fn secondary_ratio(&self) -> Option<f64> {
+        (self.primary > 0).then(|| self.secondary as f64 / self.primary as f64)
+    }
+
+    /// secondary/primary比例超过`max_ratio`时输出警告并返回true。
+    ///
+    /// # Examples
+    ///
+    /// 模拟每个读对平均2.5个secondary比对的多重比对数据：
+    ///
+    /// ```
+    /// use bamqc_core::{compute_flagstat, generate_test_data, SimulationParams, DEFAULT_MAX_SECONDARY_RATIO, Rate};
+    ///
+    /// let dir = std::env::temp_dir().join(format!("bamqc-secondary-{}", std::process::id()));
+    /// std::fs::create_dir_all(&dir).unwrap();
+    /// let bam = dir.join("multi.bam").to_string_lossy().to_string();
+    /// let params = SimulationParams { pairs: 2000, dup_rate: 0.0, secondary_rate: 2.5, ..Default::default() };
+    /// let summary = generate_test_data(&bam, &params).unwrap();
+    ///
+    /// let stat = compute_flagstat(&bam, None, None).unwrap();
+    /// let ratio = stat.secondary_ratio().unwrap();
+    /// assert_eq!(ratio, summary.secondary_pairs as f64 / 2000.0);
+    /// assert!((ratio - 2.5).abs() < 0.1);
+    /// assert!(stat.check_secondary_ratio(&bam, DEFAULT_MAX_SECONDARY_RATIO));
+    /// assert!(!stat.check_secondary_ratio(&bam, 3.0));
+    /// assert!(stat.to_string().contains(&format!("secondary_ratio: {}", Rate(ratio))));
+    /// std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    pub fn check_secondary_ratio(&self, bam_path: &str, max_ratio: f64) -> bool {
+        match self.secondary_ratio() {
+            Some(ratio) if ratio > max_ratio => {
+                warn!(
+                    "{} 的secondary/primary记录数之比为 {}（超过 {}），多重比对较多，\
+                     按MAPQ过滤或只统计primary记录的指标更有意义",
+                    bam_path, Rate(ratio), max_ratio
+                );
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// 比例的文本形式，缺失时为NA。
+fn format_ratio(ratio: Option<f64>) -> String {
+    OrNa(ratio.map(Rate)).to_string()
+}
+
+impl FlagStat {
+    /// TSV输出的表头（不含区域列）
+    pub const TSV_HEADER: &'static str =
+        "total\tprimary\tsecondary\tsupplementary\tduplicate\tmapped\tprimary_mapped\tsecondary_ratio";
+
+    /// 以TSV格式输出各计数（与TSV_HEADER列顺序一致）
+    pub fn tsv_row(&self) -> String {
+        format!(
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            self.total,
+            self.primary,
+            self.secondary,
+            self.supplementary,
+            self.duplicate,
+            self.mapped,
+            self.primary_mapped,
+            format_ratio(self.secondary_ratio())
+        )
+    }
+}
+
+impl Default for FlagStat {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl fmt::Display for FlagStat {
@@ -85,6 +363,494 @@ impl fmt::Display for FlagStat {
         writeln!(f, "secondary: {}", self.secondary)?;
         writeln!(f, "supplementary: {}", self.supplementary)?;
         writeln!(f, "duplicate: {}", self.duplicate)?;
-        write!(f, "mapped: {} ({:.2}%)", self.mapped, self.mapped_rate() * 100.0)
+        writeln!(f, "mapped: {} ({})", self.mapped, Percent(self.mapped_rate()))?;
+        write!(f, "secondary_ratio: {}", format_ratio(self.secondary_ratio()))?;
+        if self.past_contig_end > 0 {
+            write!(f, "\npast_contig_end: {}", self.past_contig_end)?;
+        }
+        if self.long_cigar > 0 {
+            write!(f, "\nlong_cigar: {}", self.long_cigar)?;
+        }
+        if self.invalid_long_cigar > 0 {
+            write!(f, "\ninvalid_long_cigar: {}", self.invalid_long_cigar)?;
+        }
+        if self.non_ascii_names > 0 {
+            write!(f, "\nnon_ascii_names: {}", self.non_ascii_names)?;
+        }
+        if self.no_records {
+            write!(f, "\nno_records: true")?;
+        }
+        if let Some(report) = &self.missing_primary {
+            write!(f, "\n{}", report)?;
+        }
+        Ok(())
+    }
+}
+
+/// 按参考序列的flagstat统计表
+#[derive(Debug)]
+pub struct ContigFlagStats {
+    /// 有记录的参考序列各一行（顺序与头部一致）；没有坐标的未比对记录为`*`行
+    pub rows: Vec<(String, FlagStat)>,
+    /// 全文件统计
+    pub total: FlagStat,
+}
+
+impl ContigFlagStats {
+    /// 全文件比例超过`max_ratio`时警告（见[`FlagStat::check_secondary_ratio`]），
+    /// 并在日志中列出比例超过阈值的参考序列数。
+    pub fn check_secondary_ratio(&self, bam_path: &str, max_ratio: f64) -> bool {
+        let contigs = self
+            .rows
+            .iter()
+            .filter(|(_, stat)| stat.secondary_ratio().is_some_and(|ratio| ratio > max_ratio))
+            .count();
+        if contigs > 0 {
+            info!("{} 条参考序列的secondary/primary记录数之比超过 {}", contigs, max_ratio);
+        }
+        self.total.check_secondary_ratio(bam_path, max_ratio)
+    }
+}
+
+impl fmt::Display for ContigFlagStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "contig\t{}", FlagStat::TSV_HEADER)?;
+        for (name, stat) in &self.rows {
+            writeln!(f, "{}\t{}", name, stat.tsv_row())?;
+        }
+        write!(f, "TOTAL\t{}", self.total.tsv_row())
+    }
+}
+
+/// 分区域flagstat统计表
+#[cfg(feature = "intervals")]
+#[derive(Debug)]
+pub struct RegionFlagStats {
+    /// 每个请求区域一行（顺序与BED文件一致）
+    pub rows: Vec<(String, FlagStat)>,
+    /// 所有区域的并集统计（每条记录只计一次）
+    pub total: FlagStat,
+    /// 重叠区域的归属方式
+    pub overlap: RegionOverlap,
+}
+
+#[cfg(feature = "intervals")]
+impl RegionFlagStats {
+    /// 只输出TOTAL时的文本：[`FlagStat`]的文本形式，非默认归属方式时追加`region_overlap`行。
+    pub fn total_summary(&self) -> String {
+        match self.overlap {
+            RegionOverlap::All => self.total.to_string(),
+            overlap => format!("{}\nregion_overlap: {}", self.total, overlap),
+        }
+    }
+}
+
+#[cfg(feature = "intervals")]
+impl fmt::Display for RegionFlagStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.overlap != RegionOverlap::All {
+            writeln!(f, "#region_overlap: {}", self.overlap)?;
+        }
+        writeln!(f, "region\t{}", FlagStat::TSV_HEADER)?;
+        for (label, stat) in &self.rows {
+            writeln!(f, "{}\t{}", label, stat.tsv_row())?;
+        }
+        write!(f, "TOTAL\t{}", self.total.tsv_row())
     }
-}
\ No newline at end of file
+}
+
+/// 统计整个BAM文件或单个区域的flagstat。
+///
+/// # Parameters
+///
+/// * `bam_path` - BAM文件路径
+/// * `region` - 可选区域；指定时需要BAM索引
+/// * `interim` - 可选的阶段性输出器
+///
+/// # Examples
+///
+/// 只有头部的BAM：各项计数为0并带`no_records`标记；全部记录为QC失败时
+/// 计数同样为0，但没有该标记：
+///
+/// ```
+/// use bamqc_core::compute_flagstat;
+/// use bamqc_io::bam::BamWriter;
+/// use noodles::sam::alignment::record::Flags;
+/// use noodles::sam::alignment::RecordBuf;
+///
+/// let dir = std::env::temp_dir().join(format!("bamqc-flagstat-empty-{}", std::process::id()));
+/// std::fs::create_dir_all(&dir).unwrap();
+/// let write = |name: &str, records: usize| {
+///     let bam = dir.join(name).to_string_lossy().to_string();
+///     let header = "@HD\tVN:1.6\n@SQ\tSN:c1\tLN:1000\n".parse().unwrap();
+///     let mut writer = BamWriter::from_path(&bam, &header).unwrap();
+///     for i in 0..records {
+///         let record = RecordBuf::builder().set_name(format!("r{}", i)).set_flags(Flags::UNMAPPED | Flags::QC_FAIL).build();
+///         writer.write_record_buf(&record).unwrap();
+///     }
+///     writer.finish().unwrap();
+///     bam
+/// };
+///
+/// let empty = compute_flagstat(&write("empty.bam", 0), None, None).unwrap();
+/// assert!(empty.no_records());
+/// assert_eq!(empty.to_string(), "total: 0\nprimary: 0\nsecondary: 0\nsupplementary: 0\nduplicate: 0\nmapped: 0 (0.00%)\nsecondary_ratio: NA\nno_records: true");
+/// #[cfg(feature = "serde")]
+/// {
+///     let json = serde_json::to_value(&empty).unwrap();
+///     assert_eq!((json["total"].as_u64(), json["no_records"].as_bool()), (Some(0), Some(true)));
+/// }
+///
+/// let filtered = compute_flagstat(&write("qc_fail.bam", 3), None, None).unwrap();
+/// assert!(!filtered.no_records());
+/// #[cfg(feature = "serde")]
+/// assert!(serde_json::to_value(&filtered).unwrap().get("no_records").is_none());
+/// std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+pub fn compute_flagstat(
+    bam_path: &str,
+    region: Option<&GenomicRegion>,
+    interim: Option<InterimEmitter>,
+) -> Result<FlagStat, FlagStatError> {
+    compute_flagstat_with_options(bam_path, region, interim, &FlagStatOptions::default())
+}
+
+/// 按`options`统计整个BAM文件或单个区域的flagstat。
+///
+/// 全文件统计时检测缺少primary的读长（区域查询只读到部分记录，不检测），
+/// 见[`crate::missing_primary`]。
+///
+/// # Examples
+///
+/// 按读名排序的输入中，r1只有两条secondary记录（MAPQ 3与7）；r2的read1只有
+/// secondary记录、read2有primary记录：
+///
+/// ```
+/// use bamqc_core::{compute_flagstat_with_options, FlagStatOptions, PrimarySelection};
+/// use bamqc_io::BamWriter;
+/// use noodles::sam;
+///
+/// let dir = std::env::temp_dir().join(format!("bamqc-flagstat-promote-{}", std::process::id()));
+/// std::fs::create_dir_all(&dir).unwrap();
+/// let write = |name: &str, sort_order: &str| {
+///     let bam = dir.join(name).to_string_lossy().to_string();
+///     let text = format!(
+///         "@HD\tVN:1.6\tSO:{}\n@SQ\tSN:c1\tLN:1000\n\
+///          r1\t256\tc1\t100\t3\t10M\t*\t0\t0\t*\t*\n\
+///          r1\t256\tc1\t500\t7\t10M\t*\t0\t0\t*\t*\n\
+///          r2\t321\tc1\t100\t5\t10M\t=\t300\t0\t*\t*\n\
+///          r2\t321\tc1\t700\t5\t10M\t=\t300\t0\t*\t*\n\
+///          r2\t145\tc1\t300\t60\t10M\t=\t100\t0\t*\t*\n\
+///          r3\t0\tc1\t800\t60\t10M\t*\t0\t0\t*\t*\n",
+///         sort_order
+///     );
+///     let mut sam_reader = sam::io::Reader::new(text.as_bytes());
+///     let header = sam_reader.read_header().unwrap();
+///     let mut writer = BamWriter::from_path(&bam, &header).unwrap();
+///     for record in sam_reader.record_bufs(&header) {
+///         writer.write_record_buf(&record.unwrap()).unwrap();
+///     }
+///     writer.finish().unwrap();
+///     bam
+/// };
+/// let promote = FlagStatOptions { promote_best_secondary: true, ..Default::default() };
+///
+/// // 只检测：r1没有任何primary记录（r2有），计数不变
+/// let bam = write("queryname.bam", "queryname");
+/// let stat = compute_flagstat_with_options(&bam, None, None, &FlagStatOptions::default()).unwrap();
+/// let report = stat.missing_primary().unwrap();
+/// assert_eq!((report.sampled_names, report.missing_primary_names), (3, 1));
+/// assert_eq!(report.primary_selection, PrimarySelection::DetectOnly);
+/// assert!(stat.to_string().contains("primary: 2\nsecondary: 4\n"));
+///
+/// // 提升：r1的MAPQ 7记录与r2 read1的第一条记录（MAPQ并列）当作primary
+/// let stat = compute_flagstat_with_options(&bam, None, None, &promote).unwrap();
+/// assert_eq!(stat.missing_primary().unwrap().promoted_secondary, 2);
+/// assert!(stat.to_string().contains("primary: 4\nsecondary: 2\n"));
+/// assert!(stat.to_string().ends_with("primary_selection: promote_best_secondary\npromoted_secondary: 2"));
+/// #[cfg(feature = "serde")]
+/// {
+///     let json = serde_json::to_value(&stat).unwrap();
+///     assert_eq!(json["primary_selection"], "promote_best_secondary");
+///     assert_eq!(json["primary_mapped"], 4);
+/// }
+///
+/// // 按坐标排序的输入无法按读名逐组缓存：只检测
+/// let bam = write("unsorted.bam", "unsorted");
+/// let stat = compute_flagstat_with_options(&bam, None, None, &promote).unwrap();
+/// assert_eq!(stat.missing_primary().unwrap().primary_selection, PrimarySelection::DetectOnly);
+/// assert!(stat.to_string().contains("primary: 2\n"));
+/// std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+pub fn compute_flagstat_with_options(
+    bam_path: &str,
+    region: Option<&GenomicRegion>,
+    mut interim: Option<InterimEmitter>,
+    options: &FlagStatOptions,
+) -> Result<FlagStat, FlagStatError> {
+    let mut reader = options.reader.open(bam_path)?;
+    let mut bounds = ContigBounds::from_header(reader.header());
+    let mut stat = FlagStat::new();
+
+    match region {
+        Some(region) => {
+            info!("开始统计区域 {}: {}", region, bam_path);
+            for (i, result) in reader.query(region)?.enumerate() {
+                let record = result?;
+                bounds.check(&record);
+                stat.update(&record);
+                if let Some(interim) = interim.as_mut() {
+                    interim.tick(i as u64 + 1, || &stat);
+                }
+            }
+        }
+        None => {
+            info!("开始统计: {}", bam_path);
+            stat.no_records = true;
+            let mut counted = 0;
+            stat.missing_primary = count_records(&mut reader, bam_path, options, |record, promoted| {
+                stat.no_records = false;
+                bounds.check(record);
+                stat.update_with(record, promoted);
+                counted += 1;
+                if let Some(interim) = interim.as_mut() {
+                    interim.tick(counted, || &stat);
+                }
+            })?;
+        }
+    }
+
+    if let Some(interim) = interim {
+        interim.finish();
+    }
+
+    stat.past_contig_end = bounds.past_end();
+    stat.finish_record_issues();
+    info!("处理完成：计入记录数 {}", stat.total);
+    Ok(stat)
+}
+
+/// 按参考序列逐行统计flagstat。
+///
+/// 记录按其参考序列（未比对但有坐标的记录按所放置的参考序列）归类；
+/// 没有记录的参考序列不输出。
+pub fn compute_flagstat_by_contig(bam_path: &str) -> Result<ContigFlagStats, FlagStatError> {
+    compute_flagstat_by_contig_with_options(bam_path, &FlagStatOptions::default())
+}
+
+/// 按`options`逐参考序列统计flagstat；被提升的记录计入其所在参考序列的行。
+pub fn compute_flagstat_by_contig_with_options(
+    bam_path: &str,
+    options: &FlagStatOptions,
+) -> Result<ContigFlagStats, FlagStatError> {
+    let mut reader = options.reader.open(bam_path)?;
+    let names: Vec<String> = reader.references().map(|(name, _)| name.to_string()).collect();
+    let mut bounds = ContigBounds::from_header(reader.header());
+    // 最后一个元素为没有坐标的记录
+    let mut stats: Vec<FlagStat> = (0..=names.len()).map(|_| FlagStat::new()).collect();
+    let mut total = FlagStat::new();
+
+    info!("开始按参考序列统计: {}", bam_path);
+    total.no_records = true;
+    total.missing_primary = count_records(&mut reader, bam_path, options, |record, promoted| {
+        total.no_records = false;
+        bounds.check(record);
+        total.update_with(record, promoted);
+        let i = usize::try_from(record.tid()).ok().filter(|&i| i < names.len()).unwrap_or(names.len());
+        stats[i].update_with(record, promoted);
+    })?;
+
+    total.past_contig_end = bounds.past_end();
+    total.finish_record_issues();
+    info!("处理完成：计入记录数 {}", total.total);
+    let rows = names
+        .into_iter()
+        .chain(std::iter::once("*".to_string()))
+        .zip(stats)
+        .filter(|(_, stat)| stat.total > 0)
+        .collect();
+    Ok(ContigFlagStats { rows, total })
+}
+
+/// 按文件顺序读取全部记录，以`(记录, 是否提升)`调用`count`，同时检测缺少primary的读长。
+///
+/// 要求提升但输入不是按读名排序时警告并退回只检测。
+fn count_records(
+    reader: &mut BamReader,
+    bam_path: &str,
+    options: &FlagStatOptions,
+    mut count: impl FnMut(&BamRecord, bool),
+) -> Result<Option<MissingPrimaryReport>, BamError> {
+    let selection = options.promote_best_secondary.then(|| {
+        if reader.sort_order() == SortOrder::QueryName {
+            PrimarySelection::PromoteBestSecondary
+        } else {
+            warn!("{} 不是按读名排序（SO:queryname），--promote-best-secondary只做检测（可先运行samtools sort -n）", bam_path);
+            PrimarySelection::DetectOnly
+        }
+    });
+    let mut detector = MissingPrimaryDetector::new(options.missing_primary_window);
+    let mut promoted = 0;
+    if selection == Some(PrimarySelection::PromoteBestSecondary) {
+        let mut promoter = NameGroupPromoter::new();
+        for result in reader.records() {
+            let record = result?;
+            detector.update(&record);
+            promoter.push(record, &mut count);
+        }
+        let overflowed;
+        (promoted, overflowed) = promoter.finish(&mut count);
+        if overflowed > 0 {
+            warn!("{} 个读名的记录超过 {} 条，未做提升", overflowed, crate::missing_primary::MAX_NAME_GROUP_RECORDS);
+        }
+    } else {
+        let mut record = BamRecord::default();
+        while reader.read_into(&mut record)? {
+            detector.update(&record);
+            count(&record, false);
+        }
+    }
+    Ok(missing_primary_report(
+        bam_path,
+        &mut detector,
+        selection,
+        promoted,
+        options.max_missing_primary_fraction,
+    ))
+}
+
+/// 按BED文件中的区域逐行统计flagstat。
+///
+/// 区域按参考序列顺序排序并合并后逐个执行索引查询，每条记录只读取一次
+/// 即可按`overlap`分配到与之重叠的请求区域（见[`RegionPlan`]）。
+///
+/// # Examples
+///
+/// 两个重叠的外显子与一个单独的区域；b同时与前两个区域重叠：
+///
+/// ```
+/// use bamqc_core::compute_flagstat_per_region;
+/// use bamqc_io::{BamReaderOptions, BamWriter, RegionOverlap, write_bai};
+/// use noodles::sam;
+///
+/// let dir = std::env::temp_dir().join(format!("bamqc-flagstat-overlap-{}", std::process::id()));
+/// std::fs::create_dir_all(&dir).unwrap();
+/// let text = "@HD\tVN:1.6\tSO:coordinate\n@SQ\tSN:c1\tLN:1000\n\
+///             a\t0\tc1\t110\t60\t20M\t*\t0\t0\t*\t*\n\
+///             b\t0\tc1\t160\t60\t20M\t*\t0\t0\t*\t*\n\
+///             c\t0\tc1\t230\t60\t10M\t*\t0\t0\t*\t*\n\
+///             d\t0\tc1\t550\t60\t10M\t*\t0\t0\t*\t*\n";
+/// let mut sam_reader = sam::io::Reader::new(text.as_bytes());
+/// let header = sam_reader.read_header().unwrap();
+/// let bam = dir.join("in.bam");
+/// let mut writer = BamWriter::from_path(&bam, &header).unwrap();
+/// for record in sam_reader.record_bufs(&header) {
+///     writer.write_record_buf(&record.unwrap()).unwrap();
+/// }
+/// writer.finish().unwrap();
+/// write_bai(&bam).unwrap();
+/// let bed = dir.join("exons.bed");
+/// std::fs::write(&bed, "c1\t100\t200\tex1\nc1\t150\t250\tex2\nc1\t500\t600\tex3\n").unwrap();
+/// let (bam, bed) = (bam.to_string_lossy(), bed.to_string_lossy());
+///
+/// let rows = |overlap| {
+///     let stats = compute_flagstat_per_region(&bam, &BamReaderOptions::new(), &bed, overlap).unwrap();
+///     let totals: Vec<u64> = stats.rows.iter().map(|(_, s)| s.tsv_row().split('\t').next().unwrap().parse().unwrap()).collect();
+///     let labels: Vec<String> = stats.rows.iter().map(|(label, _)| label.clone()).collect();
+///     (labels, totals, stats)
+/// };
+///
+/// // all：b在ex1与ex2中各计一次，各行之和5超过TOTAL 4
+/// let (_, totals, stats) = rows(RegionOverlap::All);
+/// assert_eq!(totals, [2, 2, 1]);
+/// assert!(stats.to_string().starts_with("region\t") && stats.to_string().ends_with("TOTAL\t4\t4\t0\t0\t0\t4\t4\t0.0000"));
+///
+/// // first：b只计入ex1，各行之和等于TOTAL
+/// let (_, totals, stats) = rows(RegionOverlap::First);
+/// assert_eq!(totals, [2, 1, 1]);
+/// assert!(stats.to_string().starts_with("#region_overlap: first\nregion\t"));
+/// assert!(stats.total_summary().ends_with("\nregion_overlap: first"));
+///
+/// // merge：ex1与ex2合并为一行，以坐标命名
+/// let (labels, totals, _) = rows(RegionOverlap::Merge);
+/// assert_eq!(labels, ["c1:101-250", "c1:501-600"]);
+/// assert_eq!(totals, [3, 1]);
+/// std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+#[cfg(feature = "intervals")]
+pub fn compute_flagstat_per_region(
+    bam_path: &str,
+    reader: &BamReaderOptions,
+    bed_path: &str,
+    overlap: RegionOverlap,
+) -> Result<RegionFlagStats, FlagStatError> {
+    let regions = read_bed(bed_path)?;
+    if regions.is_empty() {
+        return Err(FlagStatError::NoRegions { path: bed_path.to_string() });
+    }
+
+    let mut reader = reader.open(bam_path)?;
+    let plan = RegionPlan::new(regions, reader.header(), overlap)?;
+    info!("{} 个区域合并为 {} 次索引查询（重叠区域归属: {}）", plan.regions.len(), plan.queries.len(), overlap);
+
+    let mut bounds = ContigBounds::from_header(reader.header());
+    let mut rows: Vec<FlagStat> = plan.regions.iter().map(|_| FlagStat::new()).collect();
+    let mut total = FlagStat::new();
+    let mut members = Vec::new();
+
+    for (q, query) in plan.queries.iter().enumerate() {
+        debug!("查询区域 {}", query.region);
+        for result in reader.query(&query.region)? {
+            let record = result?;
+            let start = (record.pos() + 1) as usize;
+            let end = record.reference_end().max(record.pos() + 1) as usize;
+
+            // 合并后的查询区间互不重叠：与上一个查询区间重叠的记录已计入TOTAL
+            if !plan.is_repeat(q, start, end) {
+                bounds.check(&record);
+                total.update(&record);
+            }
+
+            plan.attribute(q, start, end, &mut members);
+            for &i in &members {
+                rows[i].update(&record);
+            }
+        }
+    }
+
+    total.past_contig_end = bounds.past_end();
+    total.finish_record_issues();
+    Ok(RegionFlagStats {
+        rows: plan.regions.iter().map(|r| r.label()).zip(rows).collect(),
+        total,
+        overlap,
+    })
+}
+
+metric_keys! {
+    /// `flagstat`输出的键（见[`crate::metrics`]）。
+    FlagStatKeys for "flagstat" {
+        TOTAL = "total", "记录总数", Unit::Reads, None, "全部记录数（含secondary与supplementary）。";
+        PRIMARY = "primary", "primary记录数", Unit::Reads, None, "既不是secondary也不是supplementary的记录数。";
+        SECONDARY = "secondary", "secondary记录数", Unit::Reads, None, "带0x100标志的记录数。";
+        SUPPLEMENTARY = "supplementary", "supplementary记录数", Unit::Reads, None, "带0x800标志的记录数。";
+        DUPLICATE = "duplicate", "duplicate记录数", Unit::Reads, Some(false), "带0x400标志的记录数。";
+        MAPPED = "mapped", "已比对记录数", Unit::Reads, Some(true), "未带0x4标志的记录数，文本输出括号内为占total的百分比。";
+        PRIMARY_MAPPED = "primary_mapped", "已比对primary记录数", Unit::Reads, Some(true), "已比对的primary记录数。";
+        PAST_CONTIG_END = "past_contig_end", "超出参考序列末端的记录数", Unit::Reads, Some(false), "比对终点超出参考序列长度的记录数（仍计入其它各项）。";
+        LONG_CIGAR = "long_cigar", "长CIGAR记录数", Unit::Reads, None, "CIGAR超过65535个操作、真实操作取自CG标签的记录数。";
+        INVALID_LONG_CIGAR = "invalid_long_cigar", "无效长CIGAR记录数", Unit::Reads, Some(false), "CIGAR为长CIGAR占位符但CG标签缺失或无效的记录数。";
+        NON_ASCII_NAMES = "non_ascii_names", "非ASCII读名记录数", Unit::Reads, Some(false), "读名含非ASCII字节的记录数（文本输出中非UTF-8字节替换为U+FFFD）。";
+        SECONDARY_RATIO = "secondary_ratio", "secondary/primary比例", Unit::Ratio, Some(false), "secondary与primary记录数之比，没有primary记录时为NA；超过--max-secondary-ratio时警告。";
+        WARNINGS = "warnings", "逐记录警告", Unit::List, Some(false), "本次运行中各类逐记录警告（非UTF-8字节、非ASCII读名、无效长CIGAR、未声明读组、超出参考序列末端）的总数，只列出出现过的类别。";
+        CONTIG = "contig", "参考序列", Unit::Label, None, "按参考序列统计表中的参考序列名，`*`为没有坐标的未比对记录。";
+        REGION = "region", "区域", Unit::Label, None, "分区域统计表中的区域名（BED第4列或坐标；--region-overlap merge时为合并区间的坐标）。";
+        REGION_OVERLAP = "region_overlap", "重叠区域归属方式", Unit::Label, None, "分区域统计中与多个请求区域重叠的记录的归属：all（默认，各计一次）、first（只计入BED中靠前的区域）或merge（重叠区域合并为一行）；非默认时在输出中注明。";
+        MISSING_PRIMARY_SAMPLED_NAMES = "missing_primary_sampled_names", "缺少primary检测的抽样读名数", Unit::Reads, None, "检测缺少primary的读长时，抽样窗口（开头的记录）中的读名数；检测到问题或使用--promote-best-secondary时输出。";
+        MISSING_PRIMARY_NAMES = "missing_primary_names", "缺少primary的读名数", Unit::Reads, Some(false), "抽样窗口中有secondary记录、整个文件中都没有primary记录的读名数；文本输出为`缺少数 / 抽样数 (百分比)`。";
+        MISSING_PRIMARY_FRACTION = "missing_primary_fraction", "缺少primary的读名比例", Unit::Fraction, Some(false), "missing_primary_names占抽样读名数的比例，超过1%时警告。";
+        PRIMARY_SELECTION = "primary_selection", "primary选择方式", Unit::Label, None, "`detect_only`（计数按FLAG）或`promote_best_secondary`（缺少primary的片段以MAPQ最高的secondary记录计为primary，影响primary、secondary与primary_mapped）。";
+        PROMOTED_SECONDARY = "promoted_secondary", "被提升的secondary记录数", Unit::Reads, None, "--promote-best-secondary下当作primary计数的secondary记录数。";
+        NO_RECORDS = "no_records", "输入为空", Unit::Boolean, None, "输入只有头部、没有任何记录时为true（各项计数都为0）；有记录时不输出。";
+    }
+}
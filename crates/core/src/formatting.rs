@@ -0,0 +1,155 @@
+//! 浮点数的文本格式。
+//!
+//! 文本与TSV输出中的浮点数都通过本模块的类型格式化，同一种量在各处的精度一致：
+//!
+//! | 类型 | 用途 | 格式 |
+//! |------|------|------|
+//! | [`Percent`] | 与samtools一致的百分比（flagstat的mapped等） | 比例×100，2位小数加`%` |
+//! | [`Rate`] | 键值文本中的比例与比值 | 4位小数 |
+//! | [`Depth`] | 平均深度（与mosdepth一致） | 2位小数 |
+//! | [`Bp`] | 碱基对长度 | 整数原样，否则1位小数 |
+//! | [`PicardFloat`] | 与Picard指标表一致的列 | 整数原样，否则6位有效数字 |
+//!
+//! 非有限值（NaN、无穷）输出为`NA`，缺失值用[`OrNa`]输出为`NA`。
+//! JSON输出直接序列化原始的`f64`，从不预先舍入；这些类型只用于文本。
+//!
+//! # Examples
+//!
+//! ```
+//! use bamqc_core::{Bp, Depth, OrNa, Percent, PicardFloat, Rate};
+//!
+//! assert_eq!(Percent(0.987654).to_string(), "98.77%");
+//! assert_eq!(Percent(0.05).to_string(), "5.00%");
+//! assert_eq!(Rate(2.0 / 3.0).to_string(), "0.6667");
+//! assert_eq!(Depth(30.456).to_string(), "30.46");
+//! assert_eq!(Bp(300.0).to_string(), "300");
+//! assert_eq!(Bp(296.44).to_string(), "296.4");
+//! assert_eq!(PicardFloat(0.123456789).to_string(), "0.123457");
+//! assert_eq!(PicardFloat(0.000012345678).to_string(), "0.0000123457");
+//! assert_eq!(PicardFloat(0.5).to_string(), "0.5");
+//! assert_eq!(PicardFloat(1234567.0).to_string(), "1234567");
+//! assert_eq!(PicardFloat(-1.0 / 3.0).to_string(), "-0.333333");
+//! assert_eq!(Rate(f64::NAN).to_string(), "NA");
+//! assert_eq!(OrNa(None::<Rate>).to_string(), "NA");
+//! assert_eq!(OrNa(Some(Rate(0.25))).to_string(), "0.2500");
+//! ```
+//!
+//! JSON中的浮点数不舍入，文本中按上表舍入：
+//!
+//! ```
+//! # #[cfg(feature = "serde")]
+//! # {
+//! use bamqc_core::{CategoryShare, ClipCycle, LinearFit, PairOrientation};
+//!
+//! let third = 1.0 / 3.0;
+//! let share = CategoryShare { orientation: PairOrientation::Fr, count: 1, pct: third };
+//! let fit = LinearFit { slope: third, intercept: -third };
+//! let cycle = ClipCycle { cycle: 1, r1_reads: 3, r2_reads: 3, r1_5p: third, r1_3p: 0.0, r2_5p: 0.0, r2_3p: 0.0 };
+//! let raw = third.to_string();
+//! for json in [
+//!     serde_json::to_string(&share).unwrap(),
+//!     serde_json::to_string(&fit).unwrap(),
+//!     serde_json::to_string(&cycle).unwrap(),
+//! ] {
+//!     assert!(json.contains(&raw), "{}", json);
+//! }
+//! # }
+//! ```
+
+use std::fmt;
+
+/// 比例的百分比形式（与samtools flagstat一致）：比例×100，2位小数加`%`。
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Percent(pub f64);
+
+/// 比例或比值：4位小数。
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rate(pub f64);
+
+/// 平均深度（与mosdepth一致）：2位小数。
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Depth(pub f64);
+
+/// 碱基对长度：整数原样，否则1位小数（与samtools stats的平均值一致）。
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bp(pub f64);
+
+/// Picard指标表中的浮点列：整数原样，否则6位有效数字并去掉末尾的0。
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PicardFloat(pub f64);
+
+/// 可能缺失的值，缺失时为`NA`。
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OrNa<T>(pub Option<T>);
+
+/// Picard浮点列的有效数字位数。
+pub const PICARD_SIGNIFICANT_DIGITS: usize = 6;
+
+/// 超过该绝对值的整数也按有效数字输出（f64无法精确表示更大的整数）。
+const MAX_EXACT_INTEGER: f64 = (1u64 << 53) as f64;
+
+impl fmt::Display for Percent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.0.is_finite() {
+            return f.write_str("NA");
+        }
+        write!(f, "{:.2}%", self.0 * 100.0)
+    }
+}
+
+impl fmt::Display for Rate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.0.is_finite() {
+            return f.write_str("NA");
+        }
+        write!(f, "{:.4}", self.0)
+    }
+}
+
+impl fmt::Display for Depth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.0.is_finite() {
+            return f.write_str("NA");
+        }
+        write!(f, "{:.2}", self.0)
+    }
+}
+
+impl fmt::Display for Bp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.0.is_finite() {
+            return f.write_str("NA");
+        }
+        if self.0.fract() == 0.0 {
+            write!(f, "{}", self.0)
+        } else {
+            write!(f, "{:.1}", self.0)
+        }
+    }
+}
+
+impl fmt::Display for PicardFloat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value = self.0;
+        if !value.is_finite() {
+            return f.write_str("NA");
+        }
+        if value.fract() == 0.0 && value.abs() < MAX_EXACT_INTEGER {
+            return write!(f, "{}", value);
+        }
+        // 先按科学计数法舍入到有效数字，再用最短的十进制形式输出
+        let rounded: f64 = format!("{:.*e}", PICARD_SIGNIFICANT_DIGITS - 1, value)
+            .parse()
+            .map_err(|_| fmt::Error)?;
+        write!(f, "{}", rounded)
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for OrNa<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.0 {
+            Some(value) => value.fmt(f),
+            None => f.write_str("NA"),
+        }
+    }
+}
@@ -0,0 +1,342 @@
+//! 按GC含量分bin的重复率。
+//!
+//! PCR扩增偏好高GC片段，PCR过度扩增导致的重复率会随GC升高；光学/簇级重复
+//! 与GC无关。统计每个GC bin（0–100%，步长1%）中primary读长（或读对）的
+//! 总数与duplicate数，并对重复率关于GC做最小二乘线性拟合，斜率作为汇总指标。
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+use bamqc_io::bam::{BamReaderOptions, BamError, BamRecord};
+use crate::external_sort::{needs_name_grouping, NameGrouper, RecordSummary, DEFAULT_RUN_RECORDS};
+#[cfg(feature = "serde")]
+use serde::Serialize;
+use crate::filter::ReadFilter;
+use crate::formatting::PicardFloat;
+use crate::log::{info, warn};
+use crate::metrics::Unit;
+use crate::metrics_keys::metric_keys;
+
+/// GC bin数量（0%到100%）。
+pub const GC_BINS: usize = 101;
+
+/// 参与拟合所需的默认最小计数。
+pub const DEFAULT_MIN_BIN_COUNT: u64 = 100;
+
+/// 单个GC bin的计数。
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct GcDupBin {
+    /// GC百分比。
+    pub gc: u8,
+    /// 读长（或读对）数。
+    pub total: u64,
+    /// 其中的duplicate数。
+    pub duplicates: u64,
+    /// 是否参与线性拟合（计数达到最小值）。
+    pub in_fit: bool,
+}
+
+impl GcDupBin {
+    /// 重复率，计数为0时返回None。
+    pub fn duplicate_rate(&self) -> Option<f64> {
+        if self.total == 0 {
+            None
+        } else {
+            Some(self.duplicates as f64 / self.total as f64)
+        }
+    }
+}
+
+/// 最小二乘直线`y = intercept + slope * x`。
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct LinearFit {
+    pub slope: f64,
+    pub intercept: f64,
+}
+
+/// 普通最小二乘线性拟合；少于2个点或x全部相同时返回None。
+///
+/// # Examples
+///
+/// ```
+/// use bamqc_core::linear_fit;
+///
+/// let points: Vec<(f64, f64)> = (20..=80).map(|gc| (gc as f64, 0.02 + 0.003 * gc as f64)).collect();
+/// let fit = linear_fit(&points).unwrap();
+/// assert!((fit.slope - 0.003).abs() < 1e-12);
+/// assert!((fit.intercept - 0.02).abs() < 1e-9);
+///
+/// assert!(linear_fit(&[(50.0, 0.1)]).is_none());
+/// assert!(linear_fit(&[(50.0, 0.1), (50.0, 0.2)]).is_none());
+/// ```
+pub fn linear_fit(points: &[(f64, f64)]) -> Option<LinearFit> {
+    if points.len() < 2 {
+        return None;
+    }
+
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|(x, _)| x).sum::<f64>() / n;
+    let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / n;
+
+    let mut sxx = 0.0;
+    let mut sxy = 0.0;
+    for (x, y) in points {
+        sxx += (x - mean_x) * (x - mean_x);
+        sxy += (x - mean_x) * (y - mean_y);
+    }
+    if sxx == 0.0 {
+        return None;
+    }
+
+    let slope = sxy / sxx;
+    Some(LinearFit {
+        slope,
+        intercept: mean_y - slope * mean_x,
+    })
+}
+
+/// gc-dup统计选项。
+#[derive(Clone, Debug)]
+pub struct GcDupOptions {
+    /// 按读对统计：两端序列合并计算GC，每个读对计一次。
+    pub pairs: bool,
+    /// bin计数低于该值时不参与拟合（仍然输出）。
+    pub min_bin_count: u64,
+    /// 读对模式处理未排序BAM时外部排序的临时目录（None为系统临时目录）。
+    pub tmp_dir: Option<PathBuf>,
+    /// 打开输入的读取器配置（解压线程数、输入格式、CRAM参考序列等）。
+    pub reader: BamReaderOptions,
+}
+
+impl Default for GcDupOptions {
+    fn default() -> Self {
+        Self {
+            pairs: false,
+            min_bin_count: DEFAULT_MIN_BIN_COUNT,
+            tmp_dir: None,
+            reader: BamReaderOptions::new(),
+        }
+    }
+}
+
+/// gc-dup报告。
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct GcDupReport {
+    /// 统计单位：READ或PAIR。
+    pub unit: &'static str,
+    /// 每个GC百分比一行（只含有计数的bin）。
+    pub bins: Vec<GcDupBin>,
+    /// 重复率对GC百分比的线性拟合，可用bin不足时为None。
+    pub fit: Option<LinearFit>,
+}
+
+impl fmt::Display for GcDupReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.fit {
+            Some(fit) => {
+                writeln!(f, "# slope: {}", PicardFloat(fit.slope))?;
+                writeln!(f, "# intercept: {}", PicardFloat(fit.intercept))?;
+            }
+            None => writeln!(f, "# slope: NA")?,
+        }
+        write!(f, "gc\t{}s\tduplicates\tduplicate_rate\tin_fit", self.unit.to_ascii_lowercase())?;
+        for bin in &self.bins {
+            write!(
+                f,
+                "\n{}\t{}\t{}\t{}\t{}",
+                bin.gc,
+                bin.total,
+                bin.duplicates,
+                PicardFloat(bin.duplicate_rate().unwrap_or(0.0)),
+                bin.in_fit
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// FLAG中的duplicate位。
+const DUPLICATE_FLAG: u16 = 0x400;
+
+fn gc_bin(gc: u32, called: u32) -> Option<usize> {
+    if called == 0 {
+        None
+    } else {
+        Some(((gc as f64 / called as f64) * 100.0).round() as usize)
+    }
+}
+
+fn counted(record: &BamRecord) -> bool {
+    ReadFilter { include_duplicates: true, ..Default::default() }.passes(record)
+}
+
+fn add_to_bin(bins: &mut [GcDupBin], gc: u32, called: u32, duplicate: bool) {
+    if let Some(i) = gc_bin(gc, called) {
+        bins[i].total += 1;
+        if duplicate {
+            bins[i].duplicates += 1;
+        }
+    }
+}
+
+/// 按GC bin统计重复率。
+///
+/// 读对模式下，BAM未按坐标或读名排序时（见[`needs_name_grouping`]）改用
+/// 外部排序归并mate，临时文件写入`options.tmp_dir`；结果与排序后的BAM相同。
+///
+/// # Examples
+///
+/// 打乱记录顺序（SO:unsorted）后，读对模式的结果与按坐标排序的原文件一致：
+///
+/// ```
+/// use bamqc_core::*;
+/// use bamqc_io::bam::BamWriter;
+/// use noodles::{bam, sam};
+///
+/// let dir = std::env::temp_dir().join(format!("bamqc-gc-dup-{}", std::process::id()));
+/// std::fs::create_dir_all(&dir).unwrap();
+/// let sorted = dir.join("sorted.bam").to_string_lossy().to_string();
+/// let shuffled = dir.join("shuffled.bam").to_string_lossy().to_string();
+/// generate_test_data(&sorted, &SimulationParams { pairs: 3000, ..Default::default() }).unwrap();
+///
+/// let mut reader = bam::io::reader::Builder.build_from_path(&sorted).unwrap();
+/// let header = reader.read_header().unwrap();
+/// let mut records: Vec<_> = reader.record_bufs(&header).map(|r| r.unwrap()).collect();
+/// let mut rng = SplitMix64::new(7);
+/// for i in (1..records.len()).rev() {
+///     records.swap(i, rng.below(i as u64 + 1) as usize);
+/// }
+/// let mut text = Vec::new();
+/// sam::io::Writer::new(&mut text).write_header(&header).unwrap();
+/// let text = String::from_utf8(text).unwrap().replace("SO:coordinate", "SO:unsorted");
+/// let mut writer = BamWriter::from_path(&shuffled, &text.parse().unwrap()).unwrap();
+/// for record in &records {
+///     writer.write_record_buf(record).unwrap();
+/// }
+/// writer.finish().unwrap();
+///
+/// let options = GcDupOptions { pairs: true, tmp_dir: Some(dir.clone()), ..Default::default() };
+/// let expected = compute_gc_dup(&sorted, &options).unwrap().to_string();
+/// assert_eq!(compute_gc_dup(&shuffled, &options).unwrap().to_string(), expected);
+/// std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+pub fn compute_gc_dup(bam_path: &str, options: &GcDupOptions) -> Result<GcDupReport, BamError> {
+    let mut reader = options.reader.open(bam_path)?;
+    let mut bins: Vec<GcDupBin> = (0..GC_BINS)
+        .map(|gc| GcDupBin {
+            gc: gc as u8,
+            ..Default::default()
+        })
+        .collect();
+    // 读对模式下等待mate的读长：读名 -> (GC碱基数, 非N碱基数)
+    let mut pending: HashMap<Vec<u8>, (u32, u32)> = HashMap::new();
+    // 未排序BAM的读对模式：记录摘要写入外部排序，读完后归并
+    let mut grouper = None;
+    if options.pairs && needs_name_grouping(reader.header()) {
+        let tmp_dir = options.tmp_dir.clone().unwrap_or_else(std::env::temp_dir);
+        warn!(
+            "{} 未按坐标或读名排序，读对模式改用外部排序归并mate，将在 {} 中占用额外磁盘空间（每条读长约22字节加读名长度）",
+            bam_path,
+            tmp_dir.display()
+        );
+        grouper = Some(NameGrouper::new(&tmp_dir, DEFAULT_RUN_RECORDS)?);
+    }
+
+    info!("开始统计GC分bin重复率: {}", bam_path);
+
+    for result in reader.records() {
+        let record = result?;
+        if !counted(&record) {
+            continue;
+        }
+
+        let (mut gc, mut called) = record.gc_count();
+        if options.pairs {
+            if !record.is_segmented() || record.is_mate_unmapped() {
+                continue;
+            }
+            if let Some(grouper) = grouper.as_mut() {
+                grouper.push(RecordSummary::new(record.qname(), gc, called, record.flag()))?;
+                continue;
+            }
+            match pending.remove(record.qname()) {
+                Some((mate_gc, mate_called)) => {
+                    gc += mate_gc;
+                    called += mate_called;
+                }
+                None => {
+                    pending.insert(record.qname().to_vec(), (gc, called));
+                    continue;
+                }
+            }
+        }
+
+        add_to_bin(&mut bins, gc, called, record.is_duplicate());
+    }
+
+    let mut unpaired = pending.len();
+    if let Some(grouper) = grouper {
+        // 同名记录相邻且保持文件顺序，与等待表一样以后出现的mate的duplicate标记为准
+        let mut mate: Option<RecordSummary> = None;
+        for summary in grouper.finish()? {
+            let summary = summary?;
+            match mate.take() {
+                Some(first) if first.name == summary.name => {
+                    let duplicate = summary.flags & DUPLICATE_FLAG != 0;
+                    add_to_bin(&mut bins, first.gc + summary.gc, first.called + summary.called, duplicate);
+                }
+                Some(_) => {
+                    unpaired += 1;
+                    mate = Some(summary);
+                }
+                None => mate = Some(summary),
+            }
+        }
+        unpaired += usize::from(mate.is_some());
+    }
+
+    if unpaired > 0 {
+        warn!("{} 个读对未找到mate，未计入统计", unpaired);
+    }
+
+    let mut points = Vec::new();
+    for bin in &mut bins {
+        if bin.total >= options.min_bin_count && bin.total > 0 {
+            bin.in_fit = true;
+            points.push((bin.gc as f64, bin.duplicate_rate().unwrap_or(0.0)));
+        }
+    }
+    let fit = linear_fit(&points);
+    match fit {
+        Some(fit) => info!("重复率对GC的斜率: {}/%GC（{} 个bin参与拟合）", PicardFloat(fit.slope), points.len()),
+        None => warn!("计数不少于 {} 的GC bin不足2个，无法拟合", options.min_bin_count),
+    }
+
+    bins.retain(|bin| bin.total > 0);
+    Ok(GcDupReport {
+        unit: if options.pairs { "PAIR" } else { "READ" },
+        bins,
+        fit,
+    })
+}
+
+metric_keys! {
+    /// `gc-dup`输出的键（见[`crate::metrics`]）。
+    GcDupKeys for "gc-dup" {
+        UNIT = "unit", "计数单位", Unit::Label, None, "按读长（READ）还是读对（PAIR）计数。";
+        BINS = "bins", "GC bin", Unit::List, None, "每个GC百分比一行。";
+        GC = "gc", "GC含量", Unit::Percent, None, "读长（或读对）序列的GC百分比（取整）。";
+        TOTAL = "total", "总数", Unit::Count, None, "该GC bin中的读长或读对数。";
+        READS = "reads", "读长数", Unit::Reads, None, "文本输出中按读长计数时的total。";
+        PAIRS = "pairs", "读对数", Unit::ReadPairs, None, "文本输出中按读对计数时的total。";
+        DUPLICATES = "duplicates", "重复数", Unit::Count, Some(false), "该GC bin中标记为duplicate的数量。";
+        DUPLICATE_RATE = "duplicate_rate", "重复率", Unit::Fraction, Some(false), "duplicates / total。";
+        IN_FIT = "in_fit", "是否参与拟合", Unit::Boolean, None, "计数达到最小bin计数、参与线性拟合的bin。";
+        FIT = "fit", "线性拟合", Unit::List, None, "重复率对GC的加权线性拟合，bin不足时为null。";
+        SLOPE = "slope", "斜率", Unit::Ratio, None, "重复率对GC百分比的拟合斜率，绝对值越大GC偏好越强。";
+        INTERCEPT = "intercept", "截距", Unit::Fraction, None, "拟合直线在GC为0时的重复率。";
+    }
+}
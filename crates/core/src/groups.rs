@@ -0,0 +1,385 @@
+//! 读组（@RG）分组与指标累积层级。
+//!
+//! 与Picard的`METRIC_ACCUMULATION_LEVEL`一致：指标可以按全部读长、样本
+//! （SM）、文库（LB）或读组（RG）累积。共享同一LB的读组在文库层级合并，
+//! 共享同一SM的文库在样本层级合并。
+//!
+//! 记录中的RG值可能不在头部中（合并BAM时的常见错误）。这样的读组会被动态
+//! 登记为"不在头部"的合成组（文库同名，样本为unknown）并计数；为了应对
+//! 异常文件，单独跟踪的未声明读组数有上限，超出部分合并到
+//! [`UNKNOWN_OVERFLOW_GROUP`]。严格模式下遇到未声明的读组直接报错。
+
+use std::collections::HashMap;
+use std::fmt;
+use bamqc_io::bam::BamRecord;
+use bamqc_io::lossy::decode_lossy;
+use bamqc_io::warnings::{WarningClass, WARNINGS};
+#[cfg(feature = "serde")]
+use bamqc_io::serialize_error;
+use bamqc_io::ErrorCode;
+use noodles::sam;
+#[cfg(feature = "serde")]
+use serde::Serialize;
+use thiserror::Error;
+use crate::log::{info, warn};
+use crate::metrics::Unit;
+use crate::metrics_keys::metric_keys;
+
+/// 未在头部声明或记录中缺少RG标签时使用的组名。
+pub const UNKNOWN_GROUP: &str = "unknown";
+
+/// 未声明读组超过上限后使用的合并组名。
+pub const UNKNOWN_OVERFLOW_GROUP: &str = "unknown_rg_overflow";
+
+/// 默认单独跟踪的未声明读组数上限。
+pub const MAX_UNKNOWN_READ_GROUPS: usize = 256;
+
+/// 读组相关错误。
+///
+/// 错误代码与JSON形式见[`bamqc_io::error_code`]：
+///
+/// ```
+/// use bamqc_core::ReadGroupError;
+/// use bamqc_io::ErrorCode;
+///
+/// let e = ReadGroupError::NotInHeader { read_group: "rg9".into() };
+/// assert_eq!(e.code(), "read_group_not_in_header");
+/// # #[cfg(feature = "serde")]
+/// assert_eq!(serde_json::to_value(&e).unwrap()["details"]["read_group"], "rg9");
+/// ```
+#[derive(Error, Debug)]
+pub enum ReadGroupError {
+    /// 严格模式下遇到头部中没有的读组。
+    #[error("记录中的读组 {read_group} 未在头部@RG中声明")]
+    NotInHeader {
+        /// 读组ID
+        read_group: String,
+    },
+}
+
+impl ErrorCode for ReadGroupError {
+    fn code(&self) -> &'static str {
+        match self {
+            ReadGroupError::NotInHeader { .. } => "read_group_not_in_header",
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    fn serialize_details<M: serde::ser::SerializeMap>(&self, map: &mut M) -> Result<(), M::Error> {
+        match self {
+            ReadGroupError::NotInHeader { read_group } => map.serialize_entry("read_group", read_group),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for ReadGroupError {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_error(self, serializer)
+    }
+}
+
+/// 未声明读组的统计。
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct UnknownReadGroups {
+    /// RG值不在头部中的记录数。
+    pub records: u64,
+    /// 单独跟踪的未声明读组。
+    pub read_groups: Vec<String>,
+    /// 超过上限、合并到溢出组的记录数。
+    pub overflow_records: u64,
+}
+
+/// 指标累积层级。
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[cfg_attr(feature = "serde", serde(rename_all = "SCREAMING_SNAKE_CASE"))]
+pub enum AccumulationLevel {
+    /// 全部读长合并统计。
+    #[default]
+    #[cfg_attr(feature = "clap", clap(name = "all-reads"))]
+    AllReads,
+    /// 按样本（SM）累积。
+    #[cfg_attr(feature = "clap", clap(name = "sample"))]
+    Sample,
+    /// 按文库（LB）累积。
+    #[cfg_attr(feature = "clap", clap(name = "library"))]
+    Library,
+    /// 按读组（RG）累积。
+    #[cfg_attr(feature = "clap", clap(name = "read-group"))]
+    ReadGroup,
+}
+
+impl fmt::Display for AccumulationLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AccumulationLevel::AllReads => write!(f, "ALL_READS"),
+            AccumulationLevel::Sample => write!(f, "SAMPLE"),
+            AccumulationLevel::Library => write!(f, "LIBRARY"),
+            AccumulationLevel::ReadGroup => write!(f, "READ_GROUP"),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Names {
+    names: Vec<String>,
+    ids: HashMap<String, usize>,
+}
+
+impl Names {
+    fn intern(&mut self, name: &str) -> usize {
+        if let Some(&id) = self.ids.get(name) {
+            return id;
+        }
+        let id = self.names.len();
+        self.names.push(name.to_string());
+        self.ids.insert(name.to_string(), id);
+        id
+    }
+}
+
+/// 读组ID到文库ID、样本ID的映射。
+///
+/// 由头部中的@RG行构建，组名被驻留为连续的整数ID，记录循环中只需做
+/// 一次哈希查找即可得到任意层级的组ID。
+#[derive(Debug, Default)]
+pub struct GroupInterner {
+    read_groups: Names,
+    libraries: Names,
+    samples: Names,
+    /// 读组ID -> (文库ID, 样本ID)
+    parents: Vec<(usize, usize)>,
+    /// 头部中声明的读组、文库、样本数：ID小于该值的组来自头部
+    header_counts: (usize, usize, usize),
+    strict: bool,
+    unknown_cap: usize,
+    unknown: UnknownReadGroups,
+}
+
+impl GroupInterner {
+    /// 从SAM头部构建。缺少LB或SM的读组分别使用读组ID或[`UNKNOWN_GROUP`]。
+    pub fn from_header(header: &sam::Header) -> Self {
+        Self::from_headers([header])
+    }
+
+    /// 从多个输入（例如按lane拆分的BAM）的头部构建，各头部声明的读组都视为已声明；
+    /// 同一读组ID重复出现时以第一次声明为准。
+    pub fn from_headers<'a>(headers: impl IntoIterator<Item = &'a sam::Header>) -> Self {
+        let mut interner = Self {
+            unknown_cap: MAX_UNKNOWN_READ_GROUPS,
+            ..Default::default()
+        };
+        for (id, map) in headers.into_iter().flat_map(|header| header.read_groups()) {
+            let id = decode_lossy(id).into_owned();
+            let field = |tag: &[u8; 2]| {
+                map.other_fields()
+                    .iter()
+                    .find(|(t, _)| <_ as AsRef<[u8; 2]>>::as_ref(*t) == tag)
+                    .map(|(_, v)| decode_lossy(v).into_owned())
+            };
+            let library = field(b"LB").unwrap_or_else(|| id.clone());
+            let sample = field(b"SM").unwrap_or_else(|| UNKNOWN_GROUP.to_string());
+            interner.insert(&id, &library, &sample);
+        }
+        interner.header_counts = (
+            interner.read_groups.names.len(),
+            interner.libraries.names.len(),
+            interner.samples.names.len(),
+        );
+        interner
+    }
+
+    /// 严格模式：记录中的读组不在头部中时[`GroupInterner::key`]返回错误。
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// 设置单独跟踪的未声明读组数上限。
+    pub fn with_unknown_cap(mut self, cap: usize) -> Self {
+        self.unknown_cap = cap;
+        self
+    }
+
+    fn insert(&mut self, read_group: &str, library: &str, sample: &str) -> usize {
+        let rg = self.read_groups.intern(read_group);
+        if rg == self.parents.len() {
+            let lb = self.libraries.intern(library);
+            let sm = self.samples.intern(sample);
+            self.parents.push((lb, sm));
+        }
+        rg
+    }
+
+    /// 读组数量。
+    pub fn read_group_count(&self) -> usize {
+        self.read_groups.names.len()
+    }
+
+    /// 返回记录所属的组ID。
+    ///
+    /// 头部中未声明的读组会被登记为新的读组（文库同名，样本为unknown）并计数，
+    /// 超过上限后归入[`UNKNOWN_OVERFLOW_GROUP`]；严格模式下返回错误。
+    /// 缺少RG标签的记录归入[`UNKNOWN_GROUP`]，不计为未声明读组。
+    /// ALL_READS层级不查看读组。
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bamqc_core::{AccumulationLevel, GroupInterner, UNKNOWN_OVERFLOW_GROUP};
+    /// use noodles::sam;
+    ///
+    /// let level = AccumulationLevel::ReadGroup;
+    /// let mut groups = GroupInterner::from_header(&sam::Header::default()).with_unknown_cap(2);
+    /// let a = groups.key(Some("a"), level).unwrap();
+    /// let b = groups.key(Some("b"), level).unwrap();
+    /// let c = groups.key(Some("c"), level).unwrap();
+    /// let d = groups.key(Some("d"), level).unwrap();
+    /// assert_ne!(a, b);
+    /// assert_eq!(c, d);
+    /// assert_eq!(groups.name(level, c), UNKNOWN_OVERFLOW_GROUP);
+    /// assert!(!groups.in_header(level, a));
+    /// assert_eq!(groups.unknown_read_groups().records, 4);
+    /// assert_eq!(groups.unknown_read_groups().read_groups, ["a", "b"]);
+    /// assert_eq!(groups.unknown_read_groups().overflow_records, 2);
+    ///
+    /// let mut strict = GroupInterner::from_header(&sam::Header::default()).with_strict(true);
+    /// assert!(strict.key(Some("a"), level).is_err());
+    /// assert!(strict.key(None, level).is_ok());
+    /// ```
+    pub fn key(&mut self, read_group: Option<&str>, level: AccumulationLevel) -> Result<usize, ReadGroupError> {
+        if level == AccumulationLevel::AllReads {
+            return Ok(0);
+        }
+        let rg = self.read_group_key(read_group)?;
+        Ok(self.level_key(rg, level))
+    }
+
+    /// 返回记录所属读组的ID，规则同[`GroupInterner::key`]；需要同时得到
+    /// 多个层级的组ID时先调用本方法，再用[`GroupInterner::level_key`]换算，
+    /// 未声明读组只计数一次。
+    pub fn read_group_key(&mut self, read_group: Option<&str>) -> Result<usize, ReadGroupError> {
+        Ok(match read_group {
+            None => match self.read_groups.ids.get(UNKNOWN_GROUP) {
+                Some(&rg) => rg,
+                None => self.insert(UNKNOWN_GROUP, UNKNOWN_GROUP, UNKNOWN_GROUP),
+            },
+            Some(read_group) => match self.read_groups.ids.get(read_group) {
+                Some(&rg) if rg < self.header_counts.0 => rg,
+                known => {
+                    if self.strict {
+                        return Err(ReadGroupError::NotInHeader {
+                            read_group: read_group.to_string(),
+                        });
+                    }
+                    self.unknown.records += 1;
+                    WARNINGS.warn(WarningClass::UnknownReadGroup, || read_group.to_string());
+                    match known {
+                        Some(&rg) if self.read_groups.names[rg] != UNKNOWN_OVERFLOW_GROUP => rg,
+                        _ if self.unknown.read_groups.len() < self.unknown_cap => {
+                            self.unknown.read_groups.push(read_group.to_string());
+                            self.insert(read_group, read_group, UNKNOWN_GROUP)
+                        }
+                        _ => {
+                            self.unknown.overflow_records += 1;
+                            self.insert(UNKNOWN_OVERFLOW_GROUP, UNKNOWN_OVERFLOW_GROUP, UNKNOWN_GROUP)
+                        }
+                    }
+                }
+            },
+        })
+    }
+
+    /// 记录的RG标签所属的组ID，规则同[`GroupInterner::key`]。
+    ///
+    /// RG值直接借用记录缓冲区查找，只有首次出现的未声明读组才分配字符串。
+    pub fn record_key(&mut self, record: &BamRecord, level: AccumulationLevel) -> Result<usize, ReadGroupError> {
+        if level == AccumulationLevel::AllReads {
+            return Ok(0);
+        }
+        let rg = self.record_read_group_key(record)?;
+        Ok(self.level_key(rg, level))
+    }
+
+    /// 记录的RG标签所属读组的ID，规则同[`GroupInterner::read_group_key`]。
+    pub fn record_read_group_key(&mut self, record: &BamRecord) -> Result<usize, ReadGroupError> {
+        record.with_aux_bytes(*b"RG", |rg| {
+            self.read_group_key(rg.map(decode_lossy).as_deref())
+        })
+    }
+
+    /// 把读组ID换算为指定层级的组ID。
+    pub fn level_key(&self, read_group_key: usize, level: AccumulationLevel) -> usize {
+        let (lb, sm) = self.parents[read_group_key];
+        match level {
+            AccumulationLevel::AllReads => 0,
+            AccumulationLevel::Sample => sm,
+            AccumulationLevel::Library => lb,
+            AccumulationLevel::ReadGroup => read_group_key,
+        }
+    }
+
+    /// 头部中声明的样本（缺少SM的读组计为[`UNKNOWN_GROUP`]），顺序与头部一致。
+    pub fn header_samples(&self) -> &[String] {
+        &self.samples.names[..self.header_counts.2]
+    }
+
+    /// 当前已登记的样本数（含记录中出现的未声明读组产生的[`UNKNOWN_GROUP`]）。
+    pub fn sample_count(&self) -> usize {
+        self.samples.names.len()
+    }
+
+    /// 组是否来自头部（而不是由记录中未声明的读组或缺少RG标签产生）。
+    pub fn in_header(&self, level: AccumulationLevel, key: usize) -> bool {
+        match level {
+            AccumulationLevel::AllReads => true,
+            AccumulationLevel::Sample => key < self.header_counts.2,
+            AccumulationLevel::Library => key < self.header_counts.1,
+            AccumulationLevel::ReadGroup => key < self.header_counts.0,
+        }
+    }
+
+    /// 未声明读组的统计。
+    pub fn unknown_read_groups(&self) -> &UnknownReadGroups {
+        &self.unknown
+    }
+
+    /// 有未声明的读组时列出这些读组；逐记录的警告经[`WARNINGS`]去重输出。
+    pub fn warn_unknown(&self, bam_path: &str) {
+        let unknown = &self.unknown;
+        if unknown.records == 0 {
+            return;
+        }
+        info!("{} 中未在头部@RG中声明的读组: {}", bam_path, unknown.read_groups.join(", "));
+        if unknown.overflow_records > 0 {
+            warn!(
+                "未声明的读组超过 {} 个，其余 {} 条记录合并到 {}",
+                self.unknown_cap, unknown.overflow_records, UNKNOWN_OVERFLOW_GROUP
+            );
+        }
+    }
+
+    /// 组ID对应的名称。
+    pub fn name(&self, level: AccumulationLevel, key: usize) -> &str {
+        let names = match level {
+            AccumulationLevel::AllReads => return "all",
+            AccumulationLevel::Sample => &self.samples.names,
+            AccumulationLevel::Library => &self.libraries.names,
+            AccumulationLevel::ReadGroup => &self.read_groups.names,
+        };
+        names.get(key).map(String::as_str).unwrap_or(UNKNOWN_GROUP)
+    }
+}
+
+metric_keys! {
+    /// `read-groups`输出的键（见[`crate::metrics`]）。
+    ReadGroupKeys for "read-groups" {
+        UNKNOWN_READ_GROUPS = "unknown_read_groups", "未声明的读组", Unit::List, Some(false), "记录中出现但头部@RG未声明的读组统计。";
+        RECORDS = "records", "未声明读组的记录数", Unit::Reads, Some(false), "RG值不在头部中的记录数。";
+        READ_GROUPS = "read_groups", "未声明的读组", Unit::List, Some(false), "单独跟踪的未声明读组ID。";
+        OVERFLOW_RECORDS = "overflow_records", "溢出记录数", Unit::Reads, Some(false), "未声明读组超过上限后合并到溢出组的记录数。";
+    }
+}
@@ -0,0 +1,295 @@
+//! 比较两个BAM/CRAM文件的头部。
+//!
+//! 比较参考序列字典（@SQ）、读组（@RG，按ID以及按SM/LB内容）和
+//! 程序记录（@PG），输出新增、删除和字段级变化。
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use bamqc_io::bam::{BamReaderOptions, BamError};
+use bamqc_io::lossy::decode_lossy;
+use noodles::sam;
+#[cfg(feature = "serde")]
+use serde::Serialize;
+use crate::log::info;
+use crate::metrics::Unit;
+use crate::metrics_keys::metric_keys;
+
+/// 参考序列字典的比较结论。
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum DictionaryStatus {
+    /// 名称、长度和顺序完全一致。
+    Identical,
+    /// 名称与长度一致，但顺序不同。
+    Reordered,
+    /// 参考序列集合不同或同名序列长度不同。
+    Incompatible,
+}
+
+impl fmt::Display for DictionaryStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DictionaryStatus::Identical => write!(f, "identical"),
+            DictionaryStatus::Reordered => write!(f, "reordered"),
+            DictionaryStatus::Incompatible => write!(f, "incompatible"),
+        }
+    }
+}
+
+/// 某条记录某个字段的变化。
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct FieldChange {
+    /// 记录ID（@SQ为SN，@RG/@PG为ID）。
+    pub id: String,
+    /// 字段标签，例如`LN`、`SM`。
+    pub field: String,
+    /// 第一个文件中的值。
+    pub left: Option<String>,
+    /// 第二个文件中的值。
+    pub right: Option<String>,
+}
+
+/// 按ID比较的记录集合差异。
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct KeyedDiff {
+    /// 仅存在于第二个文件中的ID。
+    pub added: Vec<String>,
+    /// 仅存在于第一个文件中的ID。
+    pub removed: Vec<String>,
+    /// 两个文件都存在但字段不同的记录。
+    pub changed: Vec<FieldChange>,
+}
+
+impl KeyedDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// 头部比较结果。
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct HeaderDiff {
+    /// 参考序列字典的比较结论。
+    pub dictionary_status: DictionaryStatus,
+    /// @SQ差异。
+    pub sequences: KeyedDiff,
+    /// @RG差异（按ID）。
+    pub read_groups: KeyedDiff,
+    /// 仅存在于第二个文件中的`SM/LB`组合。
+    pub samples_added: Vec<String>,
+    /// 仅存在于第一个文件中的`SM/LB`组合。
+    pub samples_removed: Vec<String>,
+    /// @PG差异（按ID，PP字段的变化反映程序链的变化）。
+    pub programs: KeyedDiff,
+}
+
+impl HeaderDiff {
+    /// 参考序列字典是否兼容（一致或仅顺序不同）。
+    pub fn is_compatible(&self) -> bool {
+        self.dictionary_status != DictionaryStatus::Incompatible
+    }
+
+    /// 两个头部在比较范围内是否完全一致。
+    pub fn is_identical(&self) -> bool {
+        self.dictionary_status == DictionaryStatus::Identical
+            && self.sequences.is_empty()
+            && self.read_groups.is_empty()
+            && self.samples_added.is_empty()
+            && self.samples_removed.is_empty()
+            && self.programs.is_empty()
+    }
+}
+
+type Fields = BTreeMap<String, String>;
+
+fn map_fields<I>(map: &sam::header::record::value::Map<I>) -> Fields
+where
+    I: sam::header::record::value::map::Inner,
+{
+    map.other_fields()
+        .iter()
+        .map(|(tag, value)| (tag.to_string(), decode_lossy(value).into_owned()))
+        .collect()
+}
+
+fn sequence_entries(header: &sam::Header) -> Vec<(String, Fields)> {
+    header
+        .reference_sequences()
+        .iter()
+        .map(|(name, map)| {
+            let mut fields = map_fields(map);
+            fields.insert("LN".to_string(), map.length().to_string());
+            (name.to_string(), fields)
+        })
+        .collect()
+}
+
+fn read_group_entries(header: &sam::Header) -> Vec<(String, Fields)> {
+    header
+        .read_groups()
+        .iter()
+        .map(|(id, map)| (id.to_string(), map_fields(map)))
+        .collect()
+}
+
+fn program_entries(header: &sam::Header) -> Vec<(String, Fields)> {
+    header
+        .programs()
+        .as_ref()
+        .iter()
+        .map(|(id, map)| (id.to_string(), map_fields(map)))
+        .collect()
+}
+
+/// 按ID对两组记录做集合比较，并给出字段级变化。
+fn diff_keyed(left: &[(String, Fields)], right: &[(String, Fields)]) -> KeyedDiff {
+    let left_map: BTreeMap<&String, &Fields> = left.iter().map(|(k, v)| (k, v)).collect();
+    let right_map: BTreeMap<&String, &Fields> = right.iter().map(|(k, v)| (k, v)).collect();
+
+    let mut diff = KeyedDiff::default();
+
+    for (id, _) in right {
+        if !left_map.contains_key(id) {
+            diff.added.push(id.clone());
+        }
+    }
+
+    for (id, left_fields) in left {
+        let Some(right_fields) = right_map.get(id) else {
+            diff.removed.push(id.clone());
+            continue;
+        };
+
+        let tags: BTreeSet<&String> = left_fields.keys().chain(right_fields.keys()).collect();
+        for tag in tags {
+            let l = left_fields.get(tag);
+            let r = right_fields.get(tag);
+            if l != r {
+                diff.changed.push(FieldChange {
+                    id: id.clone(),
+                    field: tag.clone(),
+                    left: l.cloned(),
+                    right: r.cloned(),
+                });
+            }
+        }
+    }
+
+    diff
+}
+
+fn sample_keys(entries: &[(String, Fields)]) -> BTreeSet<String> {
+    entries
+        .iter()
+        .map(|(_, fields)| {
+            format!(
+                "{}/{}",
+                fields.get("SM").map(String::as_str).unwrap_or("."),
+                fields.get("LB").map(String::as_str).unwrap_or(".")
+            )
+        })
+        .collect()
+}
+
+/// 比较两个SAM头部。
+pub fn diff_headers(left: &sam::Header, right: &sam::Header) -> HeaderDiff {
+    let left_sq = sequence_entries(left);
+    let right_sq = sequence_entries(right);
+    let sequences = diff_keyed(&left_sq, &right_sq);
+
+    let length_changed = sequences.changed.iter().any(|c| c.field == "LN");
+    let dictionary_status = if !sequences.added.is_empty() || !sequences.removed.is_empty() || length_changed {
+        DictionaryStatus::Incompatible
+    } else if left_sq.iter().map(|(k, _)| k).eq(right_sq.iter().map(|(k, _)| k)) {
+        DictionaryStatus::Identical
+    } else {
+        DictionaryStatus::Reordered
+    };
+
+    let left_rg = read_group_entries(left);
+    let right_rg = read_group_entries(right);
+    let left_samples = sample_keys(&left_rg);
+    let right_samples = sample_keys(&right_rg);
+
+    HeaderDiff {
+        dictionary_status,
+        sequences,
+        read_groups: diff_keyed(&left_rg, &right_rg),
+        samples_added: right_samples.difference(&left_samples).cloned().collect(),
+        samples_removed: left_samples.difference(&right_samples).cloned().collect(),
+        programs: diff_keyed(&program_entries(left), &program_entries(right)),
+    }
+}
+
+/// 读取两个BAM文件的头部并比较。
+pub fn diff_header_files(left_path: &str, right_path: &str, reader: &BamReaderOptions) -> Result<HeaderDiff, BamError> {
+    let left = reader.open(left_path)?;
+    let right = reader.open(right_path)?;
+
+    let diff = diff_headers(left.header(), right.header());
+    info!("参考序列字典: {}", diff.dictionary_status);
+
+    Ok(diff)
+}
+
+fn fmt_keyed(f: &mut fmt::Formatter<'_>, section: &str, diff: &KeyedDiff) -> fmt::Result {
+    for id in &diff.added {
+        writeln!(f, "{}\tadded\t{}", section, id)?;
+    }
+    for id in &diff.removed {
+        writeln!(f, "{}\tremoved\t{}", section, id)?;
+    }
+    for change in &diff.changed {
+        writeln!(
+            f,
+            "{}\tchanged\t{}\t{}\t{}\t{}",
+            section,
+            change.id,
+            change.field,
+            change.left.as_deref().unwrap_or("."),
+            change.right.as_deref().unwrap_or(".")
+        )?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for HeaderDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "dictionary: {}", self.dictionary_status)?;
+        fmt_keyed(f, "@SQ", &self.sequences)?;
+        fmt_keyed(f, "@RG", &self.read_groups)?;
+        for sample in &self.samples_added {
+            writeln!(f, "SM/LB\tadded\t{}", sample)?;
+        }
+        for sample in &self.samples_removed {
+            writeln!(f, "SM/LB\tremoved\t{}", sample)?;
+        }
+        fmt_keyed(f, "@PG", &self.programs)?;
+        write!(f, "identical: {}", self.is_identical())
+    }
+}
+
+metric_keys! {
+    /// `diff-header`输出的键（见[`crate::metrics`]）。
+    HeaderDiffKeys for "diff-header" {
+        DICTIONARY_STATUS = "dictionary_status", "参考序列字典状态", Unit::Label, None, "两个头部的参考序列字典是否相同、兼容或不兼容。";
+        DICTIONARY = "dictionary", "参考序列字典状态", Unit::Label, None, "文本输出中的dictionary_status。";
+        SEQUENCES = "sequences", "@SQ差异", Unit::List, None, "参考序列的新增、删除与字段变化。";
+        READ_GROUPS = "read_groups", "@RG差异", Unit::List, None, "读组的新增、删除与字段变化。";
+        PROGRAMS = "programs", "@PG差异", Unit::List, None, "程序记录的新增、删除与字段变化。";
+        SAMPLES_ADDED = "samples_added", "新增样本/文库", Unit::List, None, "只出现在第二个文件中的SM/LB。";
+        SAMPLES_REMOVED = "samples_removed", "删除的样本/文库", Unit::List, None, "只出现在第一个文件中的SM/LB。";
+        ADDED = "added", "新增", Unit::List, None, "只出现在第二个文件中的ID。";
+        REMOVED = "removed", "删除", Unit::List, None, "只出现在第一个文件中的ID。";
+        CHANGED = "changed", "字段变化", Unit::List, None, "两个文件中都存在但字段值不同的记录。";
+        ID = "id", "ID", Unit::Label, None, "发生变化的头部记录ID。";
+        FIELD = "field", "字段", Unit::Label, None, "发生变化的字段标签。";
+        LEFT = "left", "第一个文件中的值", Unit::Label, None, "字段在第一个文件中的值，缺失为null。";
+        RIGHT = "right", "第二个文件中的值", Unit::Label, None, "字段在第二个文件中的值，缺失为null。";
+        IDENTICAL = "identical", "头部是否相同", Unit::Boolean, None, "两个头部没有任何差异。";
+    }
+}
@@ -0,0 +1,226 @@
+//! 用于绘图输出的直方图，以及平滑与降采样。
+//!
+//! 统计量（中位数等）始终基于原始计数计算；这里的平滑和降采样只作用于
+//! 输出的副本。两种变换都保持总计数不变。
+
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
+#[cfg(feature = "serde")]
+use serde::Serialize;
+use crate::formatting::PicardFloat;
+use crate::metrics::Unit;
+use crate::metrics_keys::metric_keys;
+
+/// 直方图的一个bin，覆盖闭区间`[start, end]`。
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct HistogramBin {
+    /// 起始值（含）。
+    pub start: i32,
+    /// 终止值（含）。
+    pub end: i32,
+    /// 计数（平滑后可能为小数）。
+    pub count: f64,
+}
+
+/// 按值升序排列、互不重叠的直方图。
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Histogram {
+    bins: Vec<HistogramBin>,
+}
+
+impl HistogramBin {
+    /// 以TSV格式输出（与[`Histogram::TSV_HEADER`]列顺序一致），平滑后的小数计数
+    /// 保留6位有效数字（见[`PicardFloat`]）。
+    pub fn tsv_row(&self) -> String {
+        format!("{}\t{}\t{}", self.start, self.end, PicardFloat(self.count))
+    }
+}
+
+impl Histogram {
+    pub const TSV_HEADER: &'static str = "bin_start\tbin_end\tcount";
+
+    /// 由计数映射构建，每个值一个宽度为1的bin。
+    pub fn from_counts(counts: &HashMap<i32, u32>) -> Self {
+        let mut bins: Vec<HistogramBin> = counts
+            .iter()
+            .filter(|(_, &count)| count > 0)
+            .map(|(&value, &count)| HistogramBin {
+                start: value,
+                end: value,
+                count: count as f64,
+            })
+            .collect();
+        bins.sort_by_key(|bin| bin.start);
+        Self { bins }
+    }
+
+    pub fn bins(&self) -> &[HistogramBin] {
+        &self.bins
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bins.is_empty()
+    }
+
+    /// 总计数。
+    pub fn total(&self) -> f64 {
+        self.bins.iter().map(|bin| bin.count).sum()
+    }
+
+    /// 居中滑动平均，窗口宽度为`window`（奇数，偶数时按`window + 1`处理）。
+    ///
+    /// 每个值的计数均分给窗口内落在`[最小值, 最大值]`中的值；内部等价于普通的
+    /// 滑动平均，边界处按实际覆盖的值数归一化，因此总计数守恒。窗口大于取值范围时
+    /// 结果为均匀分布。只展开有计数的值附近的窗口，窗口之间没有计数的值合并为
+    /// 一个计数为0的bin：个别离群值（例如TLEN上亿的嵌合读对）不会使输出随取值范围增长。
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use bamqc_core::Histogram;
+    ///
+    /// let counts: HashMap<i32, u32> = [(100, 10), (101, 30), (103, 5), (110, 1)].into();
+    /// let raw = Histogram::from_counts(&counts);
+    /// let smoothed = raw.smoothed(5);
+    /// // 100-105、106-107（计数为0）、108-110
+    /// assert_eq!(smoothed.bins().len(), 10);
+    /// assert_eq!((smoothed.bins()[6].start, smoothed.bins()[6].end, smoothed.bins()[6].count), (106, 107, 0.0));
+    /// assert!((smoothed.total() - raw.total()).abs() < 1e-9);
+    ///
+    /// // 窗口大于取值范围
+    /// let flat = raw.smoothed(101);
+    /// assert_eq!(flat.bins().len(), 11);
+    /// assert!(flat.bins().iter().all(|b| (b.count - 46.0 / 11.0).abs() < 1e-9));
+    ///
+    /// // 单个bin不受影响
+    /// let single = Histogram::from_counts(&[(250, 7)].into());
+    /// assert_eq!(single.smoothed(5), single);
+    /// ```
+    pub fn smoothed(&self, window: usize) -> Histogram {
+        let (Some(first), Some(last)) = (self.bins.first(), self.bins.last()) else {
+            return Histogram::default();
+        };
+        let min = first.start as i64;
+        let max = last.end as i64;
+        let half = (window / 2) as i64;
+
+        let mut smoothed: BTreeMap<i64, f64> = BTreeMap::new();
+        for bin in self.bins.iter().filter(|bin| bin.count != 0.0) {
+            // 宽于1的bin按值均分后再平滑
+            let width = (bin.end as i64 - bin.start as i64 + 1) as f64;
+            for value in bin.start as i64..=bin.end as i64 {
+                let lo = (value - half).max(min);
+                let hi = (value + half).min(max);
+                let share = bin.count / width / (hi - lo + 1) as f64;
+                for slot in lo..=hi {
+                    *smoothed.entry(slot).or_default() += share;
+                }
+            }
+        }
+
+        let mut bins: Vec<HistogramBin> = Vec::with_capacity(smoothed.len());
+        for (value, count) in smoothed {
+            if let Some(previous) = bins.last() {
+                if previous.end as i64 + 1 < value {
+                    bins.push(HistogramBin {
+                        start: previous.end + 1,
+                        end: value as i32 - 1,
+                        count: 0.0,
+                    });
+                }
+            }
+            bins.push(HistogramBin {
+                start: value as i32,
+                end: value as i32,
+                count,
+            });
+        }
+        Histogram { bins }
+    }
+
+    /// 合并相邻的值，使输出行数不超过`max_points`，总计数守恒。
+    ///
+    /// `[最小值, 最大值]`被划分为等宽的bin（宽度向上取整），没有计数的
+    /// bin也会输出，便于绘图。`max_points`为0或已经足够时原样返回。
+    /// 输出不超过`max_points`行，也不超过输入的bin数，与取值范围无关。
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use bamqc_core::Histogram;
+    ///
+    /// let counts: HashMap<i32, u32> = (1..=1000).map(|v| (v, (v % 7) as u32)).collect();
+    /// let raw = Histogram::from_counts(&counts);
+    /// let down = raw.downsampled(100);
+    /// assert!(down.bins().len() <= 100);
+    /// assert_eq!(down.total(), raw.total());
+    /// assert_eq!(down.bins()[0].start, 1);
+    /// assert_eq!(down.bins()[0].end, 10);
+    ///
+    /// let single = Histogram::from_counts(&[(250, 7)].into());
+    /// assert_eq!(single.downsampled(1), single);
+    /// ```
+    pub fn downsampled(&self, max_points: usize) -> Histogram {
+        let (Some(first), Some(last)) = (self.bins.first(), self.bins.last()) else {
+            return Histogram::default();
+        };
+        if max_points == 0 || self.bins.len() <= max_points {
+            return self.clone();
+        }
+
+        let min = first.start as i64;
+        let max = last.end as i64;
+        let width = ((max - min + 1) as u64).div_ceil(max_points as u64) as i64;
+
+        let mut bins: Vec<HistogramBin> = Vec::new();
+        let mut start = min;
+        while start <= max {
+            let end = (start + width - 1).min(max);
+            bins.push(HistogramBin {
+                start: start as i32,
+                end: end as i32,
+                count: 0.0,
+            });
+            start = end + 1;
+        }
+        for bin in &self.bins {
+            // 输入bin均为宽度1或已对齐的区间，按起始值归入
+            let slot = ((bin.start as i64 - min) / width) as usize;
+            bins[slot].count += bin.count;
+        }
+
+        Histogram { bins }
+    }
+}
+
+/// 直方图平滑参数，字符串形式为`window=5`（也可直接写`5`）。
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HistogramSmoothing {
+    /// 滑动平均的窗口宽度（正奇数）。
+    pub window: usize,
+}
+
+impl FromStr for HistogramSmoothing {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let value = s.strip_prefix("window=").unwrap_or(s);
+        match value.parse::<usize>() {
+            Ok(window) if window % 2 == 1 => Ok(Self { window }),
+            _ => Err(format!("无效的平滑窗口（需要正奇数）: {}", s)),
+        }
+    }
+}
+
+metric_keys! {
+    /// `insert-size`输出的键（见[`crate::metrics`]）。
+    HistogramKeys for "insert-size" {
+        BIN_START = "bin_start", "bin起点", Unit::BasePairs, None, "直方图bin的起点（含）。";
+        BIN_END = "bin_end", "bin终点", Unit::BasePairs, None, "直方图bin的终点（含）。";
+        COUNT = "count", "读对数", Unit::ReadPairs, None, "落入该bin的读对数（平滑后可能为小数）。";
+    }
+}
@@ -0,0 +1,414 @@
+//! 同聚物旁的插入缺失率（测序平台错误模式的提示）。
+//!
+//! ONT数据的主要错误是同聚物中的插入缺失。这里不需要参考序列：对每条
+//! primary记录，沿CIGAR把I/D操作映射到读长序列中的位置，检查事件两侧的
+//! 读长碱基构成的同聚物长度，按同聚物长度分层计数，并以每千个比对碱基
+//! （M、=、X）的事件数报告。
+//!
+//! 同聚物长度只由读长碱基决定：插入的碱基本身不计入（长度与参考序列中的
+//! 同聚物相同），缺失时为读长中剩余的同聚物长度（缺失的是同聚物碱基时比
+//! 参考序列中短）。两侧碱基相同时同聚物跨越事件，长度为两侧之和。N不构成
+//! 同聚物。
+
+use std::fmt;
+use bamqc_io::bam::{BamReaderOptions, BamError, BamRecord};
+use bamqc_io::{Cigar, CigarKind};
+#[cfg(feature = "serde")]
+use serde::Serialize;
+use crate::formatting::PicardFloat;
+use crate::log::info;
+use crate::metrics::Unit;
+use crate::metrics_keys::metric_keys;
+
+/// 默认的同聚物最短长度。
+pub const DEFAULT_MIN_HOMOPOLYMER: usize = 3;
+
+/// 按长度分层时的最大长度，更长的同聚物并入该行。
+pub const MAX_HOMOPOLYMER_ROW: usize = 10;
+
+/// 插入或缺失。
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum IndelKind {
+    /// CIGAR I。
+    Insertion,
+    /// CIGAR D。
+    Deletion,
+}
+
+/// 一个插入缺失事件及其两侧的同聚物。
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IndelContext {
+    /// 事件类型。
+    pub kind: IndelKind,
+    /// 事件长度。
+    pub len: u32,
+    /// 事件在读长序列中的位置：插入为第一个插入碱基，缺失为缺失后的第一个碱基。
+    pub read_offset: usize,
+    /// 事件左侧（紧邻的读长碱基向左）的同聚物长度，没有左侧碱基时为0。
+    pub left_run: usize,
+    /// 事件右侧（紧邻的读长碱基向右）的同聚物长度，没有右侧碱基时为0。
+    pub right_run: usize,
+    /// 事件所在的同聚物长度：两侧碱基相同时为两侧之和，否则取较长的一侧。
+    pub homopolymer: usize,
+    /// 同聚物的碱基（大写），两侧都没有同聚物碱基时为None。
+    pub base: Option<u8>,
+}
+
+/// 从`start`起向`step`方向的相同碱基数（N不计）。
+fn run_from(sequence: &[u8], start: Option<usize>, step: isize) -> (usize, Option<u8>) {
+    let Some(start) = start.filter(|&i| i < sequence.len()) else {
+        return (0, None);
+    };
+    let base = sequence[start].to_ascii_uppercase();
+    if base == b'N' {
+        return (0, None);
+    }
+    let mut run = 0;
+    let mut i = start as isize;
+    while i >= 0 && (i as usize) < sequence.len() && sequence[i as usize].to_ascii_uppercase() == base {
+        run += 1;
+        i += step;
+    }
+    (run, Some(base))
+}
+
+/// 沿CIGAR找出读长中的每个插入缺失及其两侧的同聚物。
+///
+/// 序列为空（SEQ为`*`）或短于CIGAR时，超出序列的一侧按没有碱基处理。
+///
+/// # Examples
+///
+/// ```
+/// use bamqc_core::{indel_contexts, IndelKind};
+/// use bamqc_io::Cigar;
+///
+/// // (CIGAR, 序列, [(类型, 读长位置, 左侧, 右侧, 同聚物长度)])
+/// let cases: &[(&str, &str, &[(IndelKind, usize, usize, usize, usize)])] = &[
+///     // 插入的碱基不计入：AAA之后插入A，同聚物为3
+///     ("3M1I3M", "AAAACGT", &[(IndelKind::Insertion, 3, 3, 1, 3)]),
+///     // 两侧都是A，同聚物跨越插入：2 + 2
+///     ("2M1I2M", "AACAA", &[(IndelKind::Insertion, 2, 2, 2, 4)]),
+///     // 缺失不消耗读长：左侧AA，右侧AAA
+///     ("2M1D3M", "AAAAA", &[(IndelKind::Deletion, 2, 2, 3, 5)]),
+///     // 两侧碱基不同取较长的一侧
+///     ("3M2D2M", "CCCGG", &[(IndelKind::Deletion, 3, 3, 2, 3)]),
+///     // soft clip消耗读长，左侧的TTTT位于clip中
+///     ("2S2M1I2M", "TTTTGAC", &[(IndelKind::Insertion, 4, 4, 1, 4)]),
+///     // hard clip与N（剪接）不消耗读长
+///     ("3H2M100N2M1D2M", "GGGGCCAT", &[(IndelKind::Deletion, 4, 4, 2, 4)]),
+///     // N碱基不构成同聚物
+///     ("3M1I1M", "NNNAC", &[(IndelKind::Insertion, 3, 0, 1, 1)]),
+///     // 读长末端的插入没有右侧碱基
+///     ("3M2I", "GGGTT", &[(IndelKind::Insertion, 3, 3, 0, 3)]),
+///     // 多个事件按CIGAR顺序
+///     ("1M1D2M1I1M", "ATTGC", &[(IndelKind::Deletion, 1, 1, 2, 2), (IndelKind::Insertion, 3, 2, 1, 2)]),
+///     // 小写碱基按大写比较
+///     ("2M1D2M", "aaAA", &[(IndelKind::Deletion, 2, 2, 2, 4)]),
+///     // SEQ为*时没有侧翼碱基
+///     ("2M1D2M", "", &[(IndelKind::Deletion, 2, 0, 0, 0)]),
+/// ];
+/// for (cigar, sequence, expected) in cases {
+///     let contexts = indel_contexts(&cigar.parse::<Cigar>().unwrap(), sequence.as_bytes());
+///     let actual: Vec<_> = contexts
+///         .iter()
+///         .map(|c| (c.kind, c.read_offset, c.left_run, c.right_run, c.homopolymer))
+///         .collect();
+///     assert_eq!(&actual, expected, "{} {}", cigar, sequence);
+/// }
+/// ```
+pub fn indel_contexts(cigar: &Cigar, sequence: &[u8]) -> Vec<IndelContext> {
+    let mut contexts = Vec::new();
+    let mut offset = 0usize;
+    for op in cigar.ops() {
+        let kind = match op.kind {
+            CigarKind::Insertion => IndelKind::Insertion,
+            CigarKind::Deletion => IndelKind::Deletion,
+            kind => {
+                if kind.consumes_read() {
+                    offset += op.len as usize;
+                }
+                continue;
+            }
+        };
+        let after = match kind {
+            IndelKind::Insertion => offset + op.len as usize,
+            IndelKind::Deletion => offset,
+        };
+        let (left_run, left_base) = run_from(sequence, offset.checked_sub(1), -1);
+        let (right_run, right_base) = run_from(sequence, Some(after), 1);
+        let (homopolymer, base) = match (left_base, right_base) {
+            (Some(l), Some(r)) if l == r => (left_run + right_run, Some(l)),
+            _ if left_run >= right_run => (left_run, left_base.or(right_base)),
+            _ => (right_run, right_base),
+        };
+        contexts.push(IndelContext {
+            kind,
+            len: op.len,
+            read_offset: offset,
+            left_run,
+            right_run,
+            homopolymer,
+            base,
+        });
+        offset = after;
+    }
+    contexts
+}
+
+/// 同聚物长度的一行。
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct HomopolymerRow {
+    /// 同聚物长度；最后一行包含所有更长的同聚物。
+    pub run_length: usize,
+    /// 插入事件数。
+    pub insertions: u64,
+    /// 缺失事件数。
+    pub deletions: u64,
+    /// 插入的碱基数。
+    pub inserted_bases: u64,
+    /// 缺失的碱基数。
+    pub deleted_bases: u64,
+}
+
+/// 同聚物插入缺失报告。
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct HomopolymerIndelReport {
+    /// 计为同聚物的最短长度。
+    pub min_homopolymer: usize,
+    /// 统计的primary比对记录数。
+    pub records: u64,
+    /// SEQ为`*`、无法判断同聚物的记录数（不计入统计）。
+    pub no_sequence_records: u64,
+    /// 比对碱基数（M、=、X）。
+    pub aligned_bases: u64,
+    /// 同聚物旁的插入事件数。
+    pub homopolymer_insertions: u64,
+    /// 同聚物旁的缺失事件数。
+    pub homopolymer_deletions: u64,
+    /// 其它位置的插入事件数。
+    pub other_insertions: u64,
+    /// 其它位置的缺失事件数。
+    pub other_deletions: u64,
+    /// 每千个比对碱基中同聚物旁的插入缺失数。
+    pub homopolymer_indels_per_kb: f64,
+    /// 每千个比对碱基中其它位置的插入缺失数。
+    pub other_indels_per_kb: f64,
+    /// 插入缺失中位于同聚物旁的比例，没有插入缺失时为0。
+    pub homopolymer_indel_fraction: f64,
+    /// 按同聚物长度分层（1到[`MAX_HOMOPOLYMER_ROW`]，没有侧翼碱基的事件计入长度1）。
+    pub run_lengths: Vec<HomopolymerRow>,
+}
+
+impl HomopolymerIndelReport {
+    fn new(min_homopolymer: usize) -> Self {
+        Self {
+            min_homopolymer,
+            records: 0,
+            no_sequence_records: 0,
+            aligned_bases: 0,
+            homopolymer_insertions: 0,
+            homopolymer_deletions: 0,
+            other_insertions: 0,
+            other_deletions: 0,
+            homopolymer_indels_per_kb: 0.0,
+            other_indels_per_kb: 0.0,
+            homopolymer_indel_fraction: 0.0,
+            run_lengths: (1..=MAX_HOMOPOLYMER_ROW)
+                .map(|run_length| HomopolymerRow { run_length, ..Default::default() })
+                .collect(),
+        }
+    }
+
+    /// 计入一条记录的CIGAR与序列。
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bamqc_core::HomopolymerIndelReport;
+    /// use bamqc_io::Cigar;
+    ///
+    /// let mut report = HomopolymerIndelReport::default();
+    /// report.add_alignment(&"4M1I4M1D4M".parse::<Cigar>().unwrap(), b"AAAAGCGTACGTA");
+    /// report.finish();
+    /// assert_eq!(report.aligned_bases, 12);
+    /// assert_eq!(report.homopolymer_insertions, 1);
+    /// assert_eq!(report.other_deletions, 1);
+    /// assert_eq!(report.run_lengths[3].insertions, 1);
+    /// assert!((report.homopolymer_indels_per_kb - 1000.0 / 12.0).abs() < 1e-9);
+    /// assert_eq!(report.homopolymer_indel_fraction, 0.5);
+    /// ```
+    pub fn add_alignment(&mut self, cigar: &Cigar, sequence: &[u8]) {
+        self.records += 1;
+        self.aligned_bases += cigar
+            .ops()
+            .iter()
+            .filter(|op| {
+                matches!(
+                    op.kind,
+                    CigarKind::Match | CigarKind::SequenceMatch | CigarKind::SequenceMismatch
+                )
+            })
+            .map(|op| op.len as u64)
+            .sum::<u64>();
+
+        for context in indel_contexts(cigar, sequence) {
+            let in_homopolymer = context.homopolymer >= self.min_homopolymer;
+            let row = &mut self.run_lengths[context.homopolymer.clamp(1, MAX_HOMOPOLYMER_ROW) - 1];
+            match context.kind {
+                IndelKind::Insertion => {
+                    row.insertions += 1;
+                    row.inserted_bases += context.len as u64;
+                    if in_homopolymer {
+                        self.homopolymer_insertions += 1;
+                    } else {
+                        self.other_insertions += 1;
+                    }
+                }
+                IndelKind::Deletion => {
+                    row.deletions += 1;
+                    row.deleted_bases += context.len as u64;
+                    if in_homopolymer {
+                        self.homopolymer_deletions += 1;
+                    } else {
+                        self.other_deletions += 1;
+                    }
+                }
+            }
+        }
+    }
+
+    fn add_record(&mut self, record: &BamRecord) {
+        let sequence = record.sequence();
+        if sequence.is_empty() {
+            self.no_sequence_records += 1;
+            return;
+        }
+        self.add_alignment(&record.cigar(), &sequence);
+    }
+
+    /// 由计数算出比率。
+    pub fn finish(&mut self) {
+        let homopolymer = self.homopolymer_insertions + self.homopolymer_deletions;
+        let other = self.other_insertions + self.other_deletions;
+        let per_kb = |events: u64| {
+            if self.aligned_bases == 0 {
+                0.0
+            } else {
+                events as f64 * 1000.0 / self.aligned_bases as f64
+            }
+        };
+        self.homopolymer_indels_per_kb = per_kb(homopolymer);
+        self.other_indels_per_kb = per_kb(other);
+        self.homopolymer_indel_fraction = if homopolymer + other == 0 {
+            0.0
+        } else {
+            homopolymer as f64 / (homopolymer + other) as f64
+        };
+    }
+}
+
+impl Default for HomopolymerIndelReport {
+    fn default() -> Self {
+        Self::new(DEFAULT_MIN_HOMOPOLYMER)
+    }
+}
+
+impl fmt::Display for HomopolymerIndelReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "# min_homopolymer: {}", self.min_homopolymer)?;
+        writeln!(f, "# records: {}", self.records)?;
+        writeln!(f, "# no_sequence_records: {}", self.no_sequence_records)?;
+        writeln!(f, "# aligned_bases: {}", self.aligned_bases)?;
+        writeln!(f, "# homopolymer_insertions: {}", self.homopolymer_insertions)?;
+        writeln!(f, "# homopolymer_deletions: {}", self.homopolymer_deletions)?;
+        writeln!(f, "# other_insertions: {}", self.other_insertions)?;
+        writeln!(f, "# other_deletions: {}", self.other_deletions)?;
+        writeln!(f, "# homopolymer_indels_per_kb: {}", PicardFloat(self.homopolymer_indels_per_kb))?;
+        writeln!(f, "# other_indels_per_kb: {}", PicardFloat(self.other_indels_per_kb))?;
+        writeln!(f, "# homopolymer_indel_fraction: {}", PicardFloat(self.homopolymer_indel_fraction))?;
+        write!(f, "run_length\tinsertions\tdeletions\tinserted_bases\tdeleted_bases\tindels_per_kb")?;
+        for row in &self.run_lengths {
+            let per_kb = if self.aligned_bases == 0 {
+                0.0
+            } else {
+                (row.insertions + row.deletions) as f64 * 1000.0 / self.aligned_bases as f64
+            };
+            let run_length = if row.run_length == MAX_HOMOPOLYMER_ROW {
+                format!("{}+", row.run_length)
+            } else {
+                row.run_length.to_string()
+            };
+            write!(
+                f,
+                "\n{}\t{}\t{}\t{}\t{}\t{}",
+                run_length,
+                row.insertions,
+                row.deletions,
+                row.inserted_bases,
+                row.deleted_bases,
+                PicardFloat(per_kb)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// 统计primary比对记录中同聚物旁的插入缺失。
+///
+/// 跳过未比对、secondary与supplementary记录；`min_homopolymer`为计作同聚物的
+/// 最短长度（不小于1）。
+pub fn compute_homopolymer_indels(
+    bam_path: &str,
+    reader: &BamReaderOptions,
+    min_homopolymer: usize,
+) -> Result<HomopolymerIndelReport, BamError> {
+    let mut reader = reader.open(bam_path)?;
+    let mut report = HomopolymerIndelReport::new(min_homopolymer.max(1));
+
+    info!("开始统计同聚物旁的插入缺失（同聚物至少 {} bp）: {}", report.min_homopolymer, bam_path);
+
+    for result in reader.records() {
+        let record = result?;
+        if record.is_unmapped() || record.is_secondary() || record.is_supplementary() {
+            continue;
+        }
+        report.add_record(&record);
+    }
+
+    report.finish();
+    info!(
+        "统计完成：{} 条记录，{} 个比对碱基，同聚物旁 {}/kb，其它位置 {}/kb",
+        report.records,
+        report.aligned_bases,
+        PicardFloat(report.homopolymer_indels_per_kb),
+        PicardFloat(report.other_indels_per_kb)
+    );
+    Ok(report)
+}
+
+metric_keys! {
+    /// `homopolymer-indels`输出的键（见[`crate::metrics`]）。
+    HomopolymerKeys for "homopolymer-indels" {
+        MIN_HOMOPOLYMER = "min_homopolymer", "同聚物最短长度", Unit::BasePairs, None, "侧翼同聚物不短于该长度的插入缺失计为同聚物旁。";
+        RECORDS = "records", "记录数", Unit::Reads, None, "参与统计的primary比对记录数。";
+        NO_SEQUENCE_RECORDS = "no_sequence_records", "无序列的记录数", Unit::Reads, Some(false), "SEQ为*、无法判断同聚物而跳过的记录数。";
+        ALIGNED_BASES = "aligned_bases", "比对碱基数", Unit::Bases, None, "CIGAR中M、=、X的碱基数，比率的分母。";
+        HOMOPOLYMER_INSERTIONS = "homopolymer_insertions", "同聚物旁插入数", Unit::Count, Some(false), "侧翼读长碱基构成同聚物的插入事件数。";
+        HOMOPOLYMER_DELETIONS = "homopolymer_deletions", "同聚物旁缺失数", Unit::Count, Some(false), "侧翼读长碱基构成同聚物的缺失事件数。";
+        OTHER_INSERTIONS = "other_insertions", "其它插入数", Unit::Count, Some(false), "不在同聚物旁的插入事件数。";
+        OTHER_DELETIONS = "other_deletions", "其它缺失数", Unit::Count, Some(false), "不在同聚物旁的缺失事件数。";
+        HOMOPOLYMER_INDELS_PER_KB = "homopolymer_indels_per_kb", "同聚物旁插入缺失率", Unit::Ratio, Some(false), "每千个比对碱基中同聚物旁的插入缺失事件数。";
+        OTHER_INDELS_PER_KB = "other_indels_per_kb", "其它插入缺失率", Unit::Ratio, Some(false), "每千个比对碱基中其它位置的插入缺失事件数。";
+        HOMOPOLYMER_INDEL_FRACTION = "homopolymer_indel_fraction", "同聚物旁插入缺失占比", Unit::Fraction, None, "插入缺失事件中位于同聚物旁的比例；ONT数据通常明显偏高。";
+        RUN_LENGTHS = "run_lengths", "按同聚物长度分层", Unit::List, None, "每个同聚物长度一行，最后一行包含更长的同聚物。";
+        RUN_LENGTH = "run_length", "同聚物长度", Unit::BasePairs, None, "事件侧翼读长碱基的同聚物长度。";
+        INSERTIONS = "insertions", "插入数", Unit::Count, None, "该同聚物长度下的插入事件数。";
+        DELETIONS = "deletions", "缺失数", Unit::Count, None, "该同聚物长度下的缺失事件数。";
+        INSERTED_BASES = "inserted_bases", "插入碱基数", Unit::Bases, None, "该同聚物长度下插入的碱基数。";
+        DELETED_BASES = "deleted_bases", "缺失碱基数", Unit::Bases, None, "该同聚物长度下缺失的碱基数。";
+        INDELS_PER_KB = "indels_per_kb", "插入缺失率", Unit::Ratio, Some(false), "该同聚物长度下每千个比对碱基的插入缺失事件数（TSV列）。";
+    }
+}
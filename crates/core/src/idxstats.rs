@@ -0,0 +1,109 @@
+//! 每条参考序列的记录计数（与samtools idxstats一致）。
+//!
+//! 可以直接读取索引元数据伪bin中的计数（`--from-index`，不扫描记录；索引没有
+//! 元数据时改为扫描，见[`BamReader::index_stats`](bamqc_io::BamReader::index_stats)），也可以流式扫描整个BAM。
+
+use std::fmt;
+use bamqc_io::bam::{BamReaderOptions, BamError};
+#[cfg(feature = "serde")]
+use serde::Serialize;
+use crate::log::info;
+use crate::metrics::Unit;
+use crate::metrics_keys::metric_keys;
+
+/// 单条参考序列的记录计数。
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct IdxStatsRow {
+    /// 参考序列名。
+    pub name: String,
+    /// 参考序列长度。
+    pub length: usize,
+    /// 已比对记录数。
+    pub mapped: u64,
+    /// 放置在该参考序列上但未比对的记录数。
+    pub unmapped: u64,
+}
+
+/// idxstats结果。
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct IdxStats {
+    /// 每条参考序列一行（顺序与头部一致）。
+    pub rows: Vec<IdxStatsRow>,
+    /// 没有坐标的未比对记录数（输出中的`*`行）。
+    pub unplaced_unmapped: u64,
+    /// 计数是否来自索引。
+    pub from_index: bool,
+}
+
+impl fmt::Display for IdxStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for row in &self.rows {
+            writeln!(f, "{}\t{}\t{}\t{}", row.name, row.length, row.mapped, row.unmapped)?;
+        }
+        write!(f, "*\t0\t0\t{}", self.unplaced_unmapped)
+    }
+}
+
+/// 统计每条参考序列的记录数；`from_index`为true时读取索引中的计数，
+/// 索引没有计数元数据时仍扫描全部记录（结果的`from_index`为false）。
+pub fn compute_idxstats(bam_path: &str, reader: &BamReaderOptions, from_index: bool) -> Result<IdxStats, BamError> {
+    let mut reader = reader.open(bam_path)?;
+    let mut rows: Vec<IdxStatsRow> = reader
+        .header()
+        .reference_sequences()
+        .iter()
+        .map(|(name, map)| IdxStatsRow {
+            name: name.to_string(),
+            length: map.length().get(),
+            mapped: 0,
+            unmapped: 0,
+        })
+        .collect();
+
+    if from_index {
+        let mut stats = reader.index_stats()?;
+        let unplaced = stats.pop().expect("最后一行为*行");
+        info!("从索引读取记录计数: {}", bam_path);
+        for (row, stats) in rows.iter_mut().zip(&stats) {
+            row.mapped = stats.mapped;
+            row.unmapped = stats.unmapped;
+        }
+        return Ok(IdxStats {
+            rows,
+            unplaced_unmapped: unplaced.unmapped,
+            from_index: unplaced.exact,
+        });
+    }
+
+    info!("扫描统计记录计数: {}", bam_path);
+    let mut unplaced_unmapped = 0;
+    for result in reader.records() {
+        let record = result?;
+        match usize::try_from(record.tid()).ok().and_then(|tid| rows.get_mut(tid)) {
+            Some(row) if record.is_unmapped() => row.unmapped += 1,
+            Some(row) => row.mapped += 1,
+            None => unplaced_unmapped += 1,
+        }
+    }
+
+    Ok(IdxStats {
+        rows,
+        unplaced_unmapped,
+        from_index: false,
+    })
+}
+
+metric_keys! {
+    /// `idxstats`输出的键（见[`crate::metrics`]）。
+    IdxStatsKeys for "idxstats" {
+        ROWS = "rows", "参考序列", Unit::List, None, "每条参考序列一行。";
+        NAME = "name", "参考序列名", Unit::Label, None, "@SQ的SN。";
+        LENGTH = "length", "参考序列长度", Unit::BasePairs, None, "@SQ的LN。";
+        MAPPED = "mapped", "已比对记录数", Unit::Reads, None, "比对到该参考序列的记录数。";
+        UNMAPPED = "unmapped", "未比对记录数", Unit::Reads, None, "放置在该参考序列上但未比对的记录数。";
+        UNPLACED_UNMAPPED = "unplaced_unmapped", "未放置的未比对记录数", Unit::Reads, None, "没有参考序列的未比对记录数（输出中的*行）。";
+        FROM_INDEX = "from_index", "是否来自索引", Unit::Boolean, None, "计数取自索引元数据（true）还是扫描全部记录（false）。";
+    }
+}
@@ -3,27 +3,54 @@
 //! 本模块提供从配对末端测序数据计算插入片段大小的功能，
 //! 支持不同的配对方向和计算策略。
 
-use std::collections::HashMap;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
-use bamqc_io::bam::{BamReader, BamError};
-use tracing::{info, warn, debug};
+use bamqc_io::bam::{BamReader, BamReaderOptions, BamError, BamRecord, SortOrder, check_sample_fraction, keeps_read_name};
+#[cfg(feature = "serde")]
+use bamqc_io::{io_error_kind, serialize_error};
+use bamqc_io::{ErrorCode, MultiBamReader, EXIT_ERROR};
+use crate::bloom::{BloomFilter, DEFAULT_BLOOM_BITS, DEFAULT_BLOOM_HASHES};
+use crate::bounds::ContigBounds;
+use crate::expectation::{ExpectationResult, EXIT_QC_FAILED};
+use crate::filter::{ReadFilter, RejectReason};
+use crate::formatting::{OrNa, Percent, PicardFloat, Rate};
+use crate::groups::{AccumulationLevel, GroupInterner, ReadGroupError, UnknownReadGroups, UNKNOWN_GROUP};
+use crate::histogram::Histogram;
+use crate::interim::InterimEmitter;
+use crate::progress::{LogProgressSink, ProgressReporter, ProgressSink, progress_message};
+use crate::stream_sample::{StreamSampleSpec, StreamSampler};
+use crate::log::{info, warn, debug};
+use crate::metrics::Unit;
+use crate::metrics_keys::metric_keys;
 
 /// 插入片段大小计算的配对方向类型。
 /// 
 /// 表示配对末端读长在参考基因组中相对于彼此的不同方向。
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, clap::ValueEnum)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[cfg_attr(feature = "serde", serde(rename_all = "UPPERCASE"))]
 pub enum PairOrientation {
     /// Forward-Reverse方向（典型的文库制备方式）。
-    #[clap(name = "fr")]
+    #[cfg_attr(feature = "clap", clap(name = "fr"))]
     Fr,
     /// Reverse-Forward方向。
-    #[clap(name = "rf")]
+    #[cfg_attr(feature = "clap", clap(name = "rf"))]
     Rf,
     /// 串联方向。
-    #[clap(name = "tandem")]
+    #[cfg_attr(feature = "clap", clap(name = "tandem"))]
     Tandem,
 }
 
+impl PairOrientation {
+    /// 全部方向，按固定顺序（FR、RF、TANDEM）排列；需要遍历方向时使用，
+    /// 保证输出和并列时的选择与HashMap的迭代顺序无关。
+    pub const ALL: [PairOrientation; 3] = [PairOrientation::Fr, PairOrientation::Rf, PairOrientation::Tandem];
+}
+
 impl std::fmt::Display for PairOrientation {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -34,8 +61,73 @@ impl std::fmt::Display for PairOrientation {
     }
 }
 
+/// 区分TANDEM中++与--的细分配对方向。
+///
+/// 某些jumping library的流程中两种同向读对来源不同（反转假象与真正的串联
+/// 方向），需要分开统计。以[`PairOrientation`]为参数的接口仍看到合并后的
+/// TANDEM，只有显式使用本类型时才区分二者。
+///
+/// # Examples
+///
+/// ```
+/// use bamqc_core::{DetailedOrientation, PairOrientation};
+///
+/// assert_eq!(PairOrientation::from(DetailedOrientation::ForwardForward), PairOrientation::Tandem);
+/// assert_eq!(PairOrientation::from(DetailedOrientation::ReverseReverse), PairOrientation::Tandem);
+/// assert_eq!(PairOrientation::from(DetailedOrientation::Fr), PairOrientation::Fr);
+/// assert_eq!(DetailedOrientation::ReverseReverse.to_string(), "RR");
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum DetailedOrientation {
+    /// Forward-Reverse方向。
+    #[cfg_attr(feature = "serde", serde(rename = "FR"))]
+    Fr,
+    /// Reverse-Forward方向。
+    #[cfg_attr(feature = "serde", serde(rename = "RF"))]
+    Rf,
+    /// 两端都为正链（++）的串联方向。
+    #[cfg_attr(feature = "serde", serde(rename = "FF"))]
+    ForwardForward,
+    /// 两端都为负链（--）的串联方向。
+    #[cfg_attr(feature = "serde", serde(rename = "RR"))]
+    ReverseReverse,
+}
+
+impl DetailedOrientation {
+    /// 全部细分方向，按FR、RF、FF、RR顺序排列。
+    pub const ALL: [DetailedOrientation; 4] = [
+        DetailedOrientation::Fr,
+        DetailedOrientation::Rf,
+        DetailedOrientation::ForwardForward,
+        DetailedOrientation::ReverseReverse,
+    ];
+}
+
+impl From<DetailedOrientation> for PairOrientation {
+    fn from(orientation: DetailedOrientation) -> Self {
+        match orientation {
+            DetailedOrientation::Fr => PairOrientation::Fr,
+            DetailedOrientation::Rf => PairOrientation::Rf,
+            DetailedOrientation::ForwardForward | DetailedOrientation::ReverseReverse => PairOrientation::Tandem,
+        }
+    }
+}
+
+impl std::fmt::Display for DetailedOrientation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DetailedOrientation::Fr => write!(f, "FR"),
+            DetailedOrientation::Rf => write!(f, "RF"),
+            DetailedOrientation::ForwardForward => write!(f, "FF"),
+            DetailedOrientation::ReverseReverse => write!(f, "RR"),
+        }
+    }
+}
+
 /// 选择用于插入片段大小计算的配对方向策略。
-#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
 pub enum Strategy {
     /// 使用指定的配对方向类别。
     Specific,
@@ -43,33 +135,151 @@ pub enum Strategy {
     Dominant,
 }
 
+/// 读对的duplicate判定方式。
+///
+/// 插入片段只在左端记录（TLEN > 0）处计数，记录本身只带有自己的
+/// duplicate标志；BAM中没有mate的FLAG。Picard按读对排除duplicate：
+/// 任一端带duplicate标志，整个读对都不计入。
+///
+/// # Examples
+///
+/// 清除duplicate读对左端记录的标志（只有右端带标志）后，两种方式的结果不同；
+/// `EitherMate`与修改前的文件一致：
+///
+/// ```
+/// use bamqc_core::*;
+/// use bamqc_io::bam::BamWriter;
+/// use noodles::bam;
+/// use noodles::sam::alignment::record::Flags;
+///
+/// let dir = std::env::temp_dir().join(format!("bamqc-pair-dup-{}", std::process::id()));
+/// std::fs::create_dir_all(&dir).unwrap();
+/// let original = dir.join("original.bam").to_string_lossy().to_string();
+/// let right_only = dir.join("right_only.bam").to_string_lossy().to_string();
+/// generate_test_data(&original, &SimulationParams { pairs: 2000, dup_rate: 0.2, ..Default::default() }).unwrap();
+///
+/// let mut reader = bam::io::reader::Builder.build_from_path(&original).unwrap();
+/// let header = reader.read_header().unwrap();
+/// let mut writer = BamWriter::from_path(&right_only, &header).unwrap();
+/// let mut cleared = 0;
+/// for result in reader.record_bufs(&header) {
+///     let mut record = result.unwrap();
+///     if record.flags().is_duplicate() && record.template_length() > 0 {
+///         record.flags_mut().remove(Flags::DUPLICATE);
+///         cleared += 1;
+///     }
+///     writer.write_record_buf(&record).unwrap();
+/// }
+/// writer.finish().unwrap();
+/// assert!(cleared > 0);
+///
+/// let run = |path: &str, pair_duplicate_policy| {
+///     let options = InsertSizeOptions { pair_duplicate_policy, ..Default::default() };
+///     compute_insert_size_with_report(path, &options, None).unwrap()
+/// };
+/// let (expected, baseline) = run(&original, PairDuplicatePolicy::LeftRecord);
+/// let (_, left_record) = run(&right_only, PairDuplicatePolicy::LeftRecord);
+/// let (median, either_mate) = run(&right_only, PairDuplicatePolicy::EitherMate);
+///
+/// assert_eq!(left_record.counted_records, baseline.counted_records + cleared);
+/// assert_eq!(either_mate.counted_records, baseline.counted_records);
+/// assert_eq!(median, expected);
+/// let filter = either_mate.duplicate_name_filter.unwrap();
+/// assert_eq!(filter.mate_duplicate_records, cleared);
+/// assert!(filter.false_positive_rate < 1e-9);
+/// std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum PairDuplicatePolicy {
+    /// 只看左端记录的duplicate标志。只有右端被标记的读对仍被计入，
+    /// 此时与Picard不同（MarkDuplicates总是同时标记两端，正常文件中没有差别）。
+    #[default]
+    LeftRecord,
+    /// 任一端带duplicate标志即排除读对，与Picard一致。需要先读一遍文件，
+    /// 把带duplicate标志的读名记入布隆过滤器；假阳性会多排除少量读对，
+    /// 估计的假阳性率写入过滤统计报告。
+    EitherMate,
+}
+
 /// 插入片段大小计算过程中可能发生的错误。
+///
+/// 错误代码与JSON形式见[`bamqc_io::error_code`]：
+///
+/// ```
+/// # #[cfg(feature = "serde")]
+/// # {
+/// use bamqc_core::{CategoryShare, InsertSizeError, PairOrientation, ReadGroupError};
+/// use bamqc_io::BamError;
+///
+/// let categories = vec![CategoryShare { orientation: PairOrientation::Fr, count: 3, pct: 0.75 }];
+/// let cases = [
+///     (
+///         InsertSizeError::NoValidReads { report: None },
+///         r#"{"code":"no_valid_reads","message":"过滤后没有可用于计算的配对读（TLEN>0的左端记录为空）","details":{}}"#,
+///     ),
+///     (
+///         InsertSizeError::AllCategoriesFiltered { min_pct: 0.8, categories: categories.clone() },
+///         r#"{"code":"all_categories_filtered","message":"所有方向类别占比均 < MINIMUM_PCT=0.8000，无法给出insert_size","details":{"min_pct":0.8,"categories":[{"orientation":"FR","count":3,"pct":0.75}]}}"#,
+///     ),
+///     (
+///         InsertSizeError::OrientationFiltered { orientation: PairOrientation::Rf, min_pct: 0.05, categories },
+///         r#"{"code":"orientation_filtered","message":"所选方向 RF 被MINIMUM_PCT=0.0500丢弃，可降低阈值或改用dominant策略","details":{"orientation":"RF","min_pct":0.05,"categories":[{"orientation":"FR","count":3,"pct":0.75}]}}"#,
+///     ),
+///     (InsertSizeError::InvalidMinPct, r#"{"code":"invalid_min_pct","message":"min_pct必须在[0, 0.5]之间","details":{}}"#),
+///     (
+///         InsertSizeError::from(BamError::FileNotFound { path: "a.bam".into() }),
+///         r#"{"code":"file_not_found","message":"BAM文件IO错误: 文件不存在: a.bam","details":{"path":"a.bam"}}"#,
+///     ),
+///     (
+///         InsertSizeError::from(ReadGroupError::NotInHeader { read_group: "rg9".into() }),
+///         r#"{"code":"read_group_not_in_header","message":"记录中的读组 rg9 未在头部@RG中声明","details":{"read_group":"rg9"}}"#,
+///     ),
+///     (
+///         InsertSizeError::StreamSample { path: "s.jsonl".into(), source: std::io::ErrorKind::NotFound.into() },
+///         r#"{"code":"stream_sample_io","message":"无法创建stream-sample文件 s.jsonl: entity not found","details":{"path":"s.jsonl","kind":"NotFound"}}"#,
+///     ),
+/// ];
+/// for (error, expected) in &cases {
+///     assert_eq!(serde_json::to_string(error).unwrap(), *expected);
+/// }
+/// # }
+/// ```
 #[derive(Error, Debug)]
 pub enum InsertSizeError {
     /// 没有可用于计算的有效配对读长。
     /// 
-    /// 当过滤后没有TLEN > 0的左端记录时发生。
+    /// 当过滤后没有TLEN > 0的左端记录时发生。这是数据本身的结果而不是运行失败，
+    /// 退出码为3（[`EXIT_QC_FAILED`]）；输入为空时报告带`no_records`标记，
+    /// 以区别于有记录但全部被过滤。
     #[error("过滤后没有可用于计算的配对读（TLEN>0的左端记录为空）")]
-    NoValidReads,
+    NoValidReads {
+        /// 各项计数（都来自本次运行）；由[`InsertSizeCalculator::calculate`]直接返回时为None
+        report: Option<Box<FilterReport>>,
+    },
     
     /// 所有方向类别都被最小百分比阈值过滤掉了。
     /// 
     /// 当没有任何配对方向类别满足最小百分比要求时发生。
-    #[error("所有方向类别占比均 < MINIMUM_PCT={min_pct:.3}，无法给出insert_size")]
+    #[error("所有方向类别占比均 < MINIMUM_PCT={}，无法给出insert_size", Rate(*min_pct))]
     AllCategoriesFiltered { 
         /// 应用的最小百分比阈值
-        min_pct: f64 
+        min_pct: f64,
+        /// 各非空方向的读对数与占比
+        categories: Vec<CategoryShare>,
     },
     
     /// 指定的方向被最小百分比阈值过滤掉了。
     /// 
     /// 当使用`Specific`策略且请求的方向不满足最小百分比要求时发生。
-    #[error("所选方向 {orientation} 被MINIMUM_PCT={min_pct:.3}丢弃，可降低阈值或改用dominant策略")]
+    #[error("所选方向 {orientation} 被MINIMUM_PCT={}丢弃，可降低阈值或改用dominant策略", Rate(*min_pct))]
     OrientationFiltered {
         /// 被过滤掉的方向
         orientation: PairOrientation,
         /// 应用的最小百分比阈值
         min_pct: f64,
+        /// 各非空方向的读对数与占比
+        categories: Vec<CategoryShare>,
     },
     
     /// 无效的最小百分比值。
@@ -83,16 +293,224 @@ pub enum InsertSizeError {
     /// 当读取BAM文件时发生IO错误时发生。
     #[error("BAM文件IO错误: {0}")]
     BamError(#[from] BamError),
+
+    /// 严格模式下遇到头部中未声明的读组。
+    #[error("{0}")]
+    ReadGroup(#[from] ReadGroupError),
+
+    /// 无法创建抽样旁路文件。
+    #[error("无法创建stream-sample文件 {path}: {source}")]
+    StreamSample {
+        /// 旁路文件路径
+        path: String,
+        /// 底层IO错误
+        source: std::io::Error,
+    },
+}
+
+impl ErrorCode for InsertSizeError {
+    fn code(&self) -> &'static str {
+        match self {
+            InsertSizeError::NoValidReads { .. } => "no_valid_reads",
+            InsertSizeError::AllCategoriesFiltered { .. } => "all_categories_filtered",
+            InsertSizeError::OrientationFiltered { .. } => "orientation_filtered",
+            InsertSizeError::InvalidMinPct => "invalid_min_pct",
+            InsertSizeError::BamError(e) => e.code(),
+            InsertSizeError::ReadGroup(e) => e.code(),
+            InsertSizeError::StreamSample { .. } => "stream_sample_io",
+        }
+    }
+
+    fn exit_code(&self) -> i32 {
+        match self {
+            InsertSizeError::NoValidReads { .. } => EXIT_QC_FAILED,
+            InsertSizeError::BamError(e) => e.exit_code(),
+            _ => EXIT_ERROR,
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    fn serialize_details<M: serde::ser::SerializeMap>(&self, map: &mut M) -> Result<(), M::Error> {
+        match self {
+            InsertSizeError::NoValidReads { report: Some(report) } => map.serialize_entry("report", report),
+            InsertSizeError::NoValidReads { report: None } | InsertSizeError::InvalidMinPct => Ok(()),
+            InsertSizeError::AllCategoriesFiltered { min_pct, categories } => {
+                map.serialize_entry("min_pct", min_pct)?;
+                map.serialize_entry("categories", categories)
+            }
+            InsertSizeError::OrientationFiltered { orientation, min_pct, categories } => {
+                map.serialize_entry("orientation", orientation)?;
+                map.serialize_entry("min_pct", min_pct)?;
+                map.serialize_entry("categories", categories)
+            }
+            InsertSizeError::BamError(e) => e.serialize_details(map),
+            InsertSizeError::ReadGroup(e) => e.serialize_details(map),
+            InsertSizeError::StreamSample { path, source } => {
+                map.serialize_entry("path", path)?;
+                map.serialize_entry("kind", &io_error_kind(source))
+            }
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for InsertSizeError {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_error(self, serializer)
+    }
+}
+
+impl InsertSizeError {
+    /// 方向类别被阈值过滤时，各非空方向的读对数与占比。
+    pub fn categories(&self) -> Option<&[CategoryShare]> {
+        match self {
+            InsertSizeError::AllCategoriesFiltered { categories, .. }
+            | InsertSizeError::OrientationFiltered { categories, .. } => Some(categories),
+            _ => None,
+        }
+    }
+
+    /// 没有有效读对时各条件排除的记录数（见[`FilterBreakdown`]）。
+    pub fn filter_breakdown(&self) -> Option<&FilterBreakdown> {
+        match self {
+            InsertSizeError::NoValidReads { report: Some(report) } => Some(&report.breakdown),
+            _ => None,
+        }
+    }
+
+    /// 方向类别被阈值过滤时的占比表（orientation、count、pct、kept）与建议，
+    /// 供命令行输出。
+    ///
+    /// 建议的阈值为相关方向的占比向下取整到0.01：全部类别被丢弃时取最大的
+    /// 占比，所选方向被丢弃时取该方向的占比（占比不足1%时只建议改用dominant策略）。
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bamqc_core::{InsertSizeCalculator, InsertSizeStats, PairOrientation, Strategy};
+    ///
+    /// let mut stats = InsertSizeStats::new();
+    /// for (orientation, pairs) in [(PairOrientation::Fr, 96), (PairOrientation::Tandem, 4)] {
+    ///     (0..pairs).for_each(|_| stats.add_insert_size(orientation, 300));
+    /// }
+    /// let err = InsertSizeCalculator::calculate(&stats, 0.05, PairOrientation::Tandem, Strategy::Specific).unwrap_err();
+    /// let table = err.category_table().unwrap();
+    /// let lines: Vec<&str> = table.lines().collect();
+    /// assert_eq!(
+    ///     lines,
+    ///     [
+    ///         "orientation\tcount\tpct\tkept",
+    ///         "FR\t96\t0.9600\tyes",
+    ///         "TANDEM\t4\t0.0400\tno",
+    ///         "# 可将-M降到0.04，或改用--strategy dominant",
+    ///     ]
+    /// );
+    /// ```
+    pub fn category_table(&self) -> Option<String> {
+        let (min_pct, categories, relevant) = match self {
+            InsertSizeError::AllCategoriesFiltered { min_pct, categories } => {
+                (*min_pct, categories, categories.iter().map(|c| c.pct).fold(0.0, f64::max))
+            }
+            InsertSizeError::OrientationFiltered { orientation, min_pct, categories } => {
+                let pct = categories.iter().find(|c| c.orientation == *orientation).map_or(0.0, |c| c.pct);
+                (*min_pct, categories, pct)
+            }
+            _ => return None,
+        };
+        let mut table = String::from("orientation\tcount\tpct\tkept");
+        for category in categories {
+            let kept = if category.pct >= min_pct { "yes" } else { "no" };
+            table.push_str(&format!("\n{}\t{}\t{}\t{}", category.orientation, category.count, Rate(category.pct), kept));
+        }
+        let suggested = (relevant * 100.0).floor() / 100.0;
+        let dominant = matches!(self, InsertSizeError::OrientationFiltered { .. });
+        match (suggested > 0.0, dominant) {
+            (true, true) => table.push_str(&format!("\n# 可将-M降到{:.2}，或改用--strategy dominant", suggested)),
+            (true, false) => table.push_str(&format!("\n# 可将-M降到{:.2}", suggested)),
+            (false, _) => table.push_str("\n# 可改用--strategy dominant"),
+        }
+        Some(table)
+    }
+}
+
+/// 一个方向类别的读对数与占比。
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct CategoryShare {
+    /// 配对方向。
+    pub orientation: PairOrientation,
+    /// 该方向的左端记录数。
+    pub count: u32,
+    /// 占全部左端记录的比例。
+    pub pct: f64,
 }
 
 /// 插入片段大小统计结果。
+///
+/// 序列化后可以反序列化回来，与读取器的虚拟偏移（[`BamReader::tell`]）一起
+/// 作为检查点，恢复后从该偏移继续累积；最大插入片段读对不在检查点中。
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct InsertSizeStats {
-    /// 各方向的插入大小计数直方图。
+    /// 各方向的插入大小计数直方图（序列化时按方向和插入大小排序）。
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_histograms"))]
     pub histograms: HashMap<PairOrientation, HashMap<i32, u32>>,
     
     /// 总的左端记录数。
     pub total_left_records: u32,
+
+    /// 插入片段最大的N个读对（仅在请求时启用）。
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub largest: Option<LargestPairs>,
+
+    /// TANDEM中++与--的子直方图（仅在请求时启用）。
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub tandem_split: Option<TandemSplit>,
+}
+
+/// TANDEM读对按++与--分开的插入片段计数。
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TandemSplit {
+    /// 两端都为正链（++）的读对的插入片段计数。
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_counts"))]
+    pub forward_forward: HashMap<i32, u32>,
+    /// 两端都为负链（--）的读对的插入片段计数。
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_counts"))]
+    pub reverse_reverse: HashMap<i32, u32>,
+}
+
+impl TandemSplit {
+    /// ++与--的读对数及其比例。
+    pub fn report(&self) -> TandemSplitReport {
+        let ff_pairs: u64 = self.forward_forward.values().map(|&count| count as u64).sum();
+        let rr_pairs: u64 = self.reverse_reverse.values().map(|&count| count as u64).sum();
+        TandemSplitReport {
+            ff_pairs,
+            rr_pairs,
+            ff_rr_ratio: (rr_pairs > 0).then(|| ff_pairs as f64 / rr_pairs as f64),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+fn serialize_histograms<S: serde::Serializer>(
+    histograms: &HashMap<PairOrientation, HashMap<i32, u32>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    use std::collections::BTreeMap;
+
+    let sorted: BTreeMap<PairOrientation, BTreeMap<i32, u32>> = histograms
+        .iter()
+        .map(|(orientation, counts)| (*orientation, counts.iter().map(|(k, v)| (*k, *v)).collect()))
+        .collect();
+    sorted.serialize(serializer)
+}
+
+#[cfg(feature = "serde")]
+fn serialize_counts<S: serde::Serializer>(counts: &HashMap<i32, u32>, serializer: S) -> Result<S::Ok, S::Error> {
+    let sorted: std::collections::BTreeMap<i32, u32> = counts.iter().map(|(k, v)| (*k, *v)).collect();
+    sorted.serialize(serializer)
 }
 
 impl InsertSizeStats {
@@ -106,9 +524,23 @@ impl InsertSizeStats {
         Self {
             histograms,
             total_left_records: 0,
+            largest: None,
+            tandem_split: None,
         }
     }
 
+    /// 启用TANDEM的++/--子直方图（`enabled`为false时不启用）。
+    pub fn with_tandem_split(mut self, enabled: bool) -> Self {
+        self.tandem_split = enabled.then(TandemSplit::default);
+        self
+    }
+
+    /// 启用最大插入片段读对的跟踪，保留前`n`个（`n == 0`时不启用）。
+    pub fn with_largest(mut self, n: usize) -> Self {
+        self.largest = (n > 0).then(|| LargestPairs::new(n));
+        self
+    }
+
     /// 添加一个插入大小记录。
     /// 
     /// # Parameters
@@ -123,6 +555,98 @@ impl InsertSizeStats {
         *self.histograms.get_mut(&orientation).unwrap().entry(size).or_insert(0) += 1;
         self.total_left_records += 1;
     }
+
+    /// 按细分方向添加一个插入大小记录。
+    ///
+    /// 合并后的直方图与[`InsertSizeStats::add_insert_size`]完全相同；启用了
+    /// [`InsertSizeStats::with_tandem_split`]时++与--另外计入各自的子直方图。
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bamqc_core::{DetailedOrientation::*, InsertSizeStats, PairOrientation};
+    ///
+    /// let mut merged = InsertSizeStats::new();
+    /// let mut split = InsertSizeStats::new().with_tandem_split(true);
+    /// for (orientation, size) in [(ForwardForward, 300), (ForwardForward, 310), (ReverseReverse, 300), (Fr, 250)] {
+    ///     merged.add_insert_size(orientation.into(), size);
+    ///     split.add_detailed(orientation, size);
+    /// }
+    /// assert_eq!(split.histograms, merged.histograms);
+    /// assert_eq!(split.histograms[&PairOrientation::Tandem].values().sum::<u32>(), 3);
+    /// let report = split.tandem_split.unwrap().report();
+    /// assert_eq!((report.ff_pairs, report.rr_pairs, report.ff_rr_ratio), (2, 1, Some(2.0)));
+    /// ```
+    pub fn add_detailed(&mut self, orientation: DetailedOrientation, size: i32) {
+        self.add_insert_size(orientation.into(), size);
+        if let Some(split) = self.tandem_split.as_mut() {
+            let counts = match orientation {
+                DetailedOrientation::ForwardForward => &mut split.forward_forward,
+                DetailedOrientation::ReverseReverse => &mut split.reverse_reverse,
+                DetailedOrientation::Fr | DetailedOrientation::Rf => return,
+            };
+            *counts.entry(size).or_insert(0) += 1;
+        }
+    }
+}
+
+/// 一个插入片段较大的读对。
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct LargePair {
+    /// 模板长度（排序主键）。
+    pub tlen: i64,
+    /// 读名。
+    pub name: String,
+    /// 参考序列ID。
+    pub tid: i32,
+    /// 左端记录的比对起始位置（0-based）。
+    pub pos: i64,
+}
+
+/// 有界最小堆，保留插入片段最大的N个读对。
+///
+/// 只有当记录可能进入前N时才会复制读名，未进入的记录不产生分配。
+#[derive(Clone, Debug)]
+pub struct LargestPairs {
+    capacity: usize,
+    heap: BinaryHeap<Reverse<LargePair>>,
+}
+
+impl LargestPairs {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            heap: BinaryHeap::with_capacity(capacity + 1),
+        }
+    }
+
+    /// 尝试加入一条左端记录。
+    pub fn offer(&mut self, record: &BamRecord, tlen: i64) {
+        if self.heap.len() >= self.capacity {
+            match self.heap.peek() {
+                Some(Reverse(min)) if tlen <= min.tlen => return,
+                _ => {}
+            }
+        }
+
+        self.heap.push(Reverse(LargePair {
+            tlen,
+            name: record.name().into_owned(),
+            tid: record.tid(),
+            pos: record.pos(),
+        }));
+        if self.heap.len() > self.capacity {
+            self.heap.pop();
+        }
+    }
+
+    /// 按模板长度降序返回。
+    pub fn to_sorted_vec(&self) -> Vec<LargePair> {
+        let mut pairs: Vec<LargePair> = self.heap.iter().map(|Reverse(p)| p.clone()).collect();
+        pairs.sort_unstable_by(|a, b| b.cmp(a));
+        pairs
+    }
 }
 
 impl Default for InsertSizeStats {
@@ -131,6 +655,455 @@ impl Default for InsertSizeStats {
     }
 }
 
+/// 插入片段计算的过滤统计报告。
+///
+/// TLEN符号计数统计的是通过了所有其它过滤条件、但尚未做"TLEN > 0"
+/// 左端记录筛选的记录。符合规范的文件中正负TLEN数量应大致相等；
+/// 明显失衡说明比对软件的TLEN符号约定异常，只计左端记录会低估读对数。
+#[derive(Debug, Default, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct FilterReport {
+    /// 样本名（按样本分别统计时）。
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub sample: Option<String>,
+    /// 读取的记录总数。
+    pub processed_records: u64,
+    /// 输入没有任何记录（只有头部）。与“有记录但全部被过滤”区分：
+    /// 后者`counted_records`同样为0，但这里为false。
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "std::ops::Not::not"))]
+    pub no_records: bool,
+    /// TLEN > 0的候选记录数。
+    pub tlen_positive: u64,
+    /// TLEN < 0的候选记录数。
+    pub tlen_negative: u64,
+    /// TLEN == 0的候选记录数。
+    pub tlen_zero: u64,
+    /// 最终计入直方图的左端记录数。
+    pub counted_records: u64,
+    /// 比对到参考序列末端之外的记录数（插入片段统计不依赖坐标，仍正常计入）。
+    pub past_contig_end: u64,
+    /// 因MAPQ低于`--min-mapq`被跳过的记录数（需通过`--min-mapq`启用）。
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub low_mapq_records: Option<u64>,
+    /// 每条记录的去向：被哪一项条件排除，或计入直方图。
+    pub breakdown: FilterBreakdown,
+    /// 插入片段最大的读对（按模板长度降序，需通过`--report-largest`启用）。
+    pub largest_pairs: Vec<LargePair>,
+    /// 仅因proper pair或最大插入片段条件被排除的同染色体读对的距离分布
+    /// （需通过`--report-excluded-distances`启用）。
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub excluded_distances: Option<ExcludedDistances>,
+    /// 分组统计所用的累积层级。
+    pub accumulation_level: AccumulationLevel,
+    /// 各组的插入片段大小（累积层级不为ALL_READS时）。
+    pub groups: Vec<GroupInsertSize>,
+    /// 记录中出现但头部未声明的读组（累积层级不为ALL_READS时）。
+    pub unknown_read_groups: UnknownReadGroups,
+    /// 按读对排除duplicate时的读名过滤统计（[`PairDuplicatePolicy::EitherMate`]）。
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub duplicate_name_filter: Option<DuplicateNameFilterReport>,
+    /// 插入片段大小的期望断言结果（需通过`--expect`或`--expect-range`启用）。
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub expectation: Option<ExpectationResult>,
+    /// TANDEM读对中++与--的读对数及其比例（需通过`--tandem-split`启用）。
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub tandem_split: Option<TandemSplitReport>,
+    /// 各方向的原始直方图（按FR、RF、TANDEM顺序，只含非空方向），供直方图输出使用。
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub histograms: Vec<(PairOrientation, Histogram)>,
+}
+
+/// 插入片段统计中每条记录的去向。
+///
+/// 检查按字段顺序进行，每条记录只归入第一个不满足的条件，各项之和等于读取的
+/// 记录数。`non_positive_tlen`包括有效读对的右端记录（每个读对只计左端一次），
+/// 因此正常的输入中约占一半；其余各项不为0说明相应条件排除了记录。
+/// 没有有效读对（[`InsertSizeError::NoValidReads`]）时据此判断是哪一项条件
+/// 排除了全部记录。
+///
+/// # Examples
+///
+/// ```
+/// use bamqc_core::*;
+///
+/// let bam = std::env::temp_dir().join(format!("bamqc-breakdown-{}.bam", std::process::id()));
+/// let bam = bam.to_string_lossy().to_string();
+/// generate_test_data(&bam, &SimulationParams { pairs: 500, ..Default::default() }).unwrap();
+///
+/// // 最大插入片段设得过小：错误中的报告指出是哪一项条件排除了全部记录
+/// let options = InsertSizeOptions { max_insert_size: Some(10), ..Default::default() };
+/// let e = compute_insert_size_with_report(&bam, &options, None).unwrap_err();
+/// let breakdown = e.filter_breakdown().unwrap();
+/// assert_eq!(breakdown.total(), 1000);
+/// assert_eq!(breakdown.duplicate + breakdown.above_max_insert_size, 1000);
+/// assert_eq!((breakdown.counted, breakdown.non_positive_tlen), (0, 0));
+/// assert_eq!(breakdown.dominant_rejection(), Some(("above_max_insert_size", breakdown.above_max_insert_size)));
+/// assert!(breakdown.to_string().starts_with("reason\trecords\tpct\nlow_mapq\t0\t0.0000\n"));
+/// std::fs::remove_file(&bam).unwrap();
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct FilterBreakdown {
+    /// MAPQ低于`--min-mapq`。
+    pub low_mapq: u64,
+    /// 不是配对测序的记录。
+    pub unpaired: u64,
+    pub secondary: u64,
+    pub supplementary: u64,
+    /// 标记为duplicate（或按读对排除duplicate时mate为duplicate）。
+    pub duplicate: u64,
+    /// 本端未比对（标志或坐标）。
+    pub unmapped: u64,
+    /// mate未比对（标志或坐标）。
+    pub mate_unmapped: u64,
+    /// 两端比对到不同的参考序列。
+    pub cross_chromosome: u64,
+    /// 不是proper pair（`--require-proper-pair`）。
+    pub improper_pair: u64,
+    /// |TLEN|超过`--max-insert-size`。
+    pub above_max_insert_size: u64,
+    /// 通过全部条件但TLEN ≤ 0（右端记录或TLEN为0）。
+    pub non_positive_tlen: u64,
+    /// 计入直方图的左端记录。
+    pub counted: u64,
+}
+
+impl FilterBreakdown {
+    /// (名称, 记录数)，按检查顺序，最后为`counted`。
+    pub fn rows(&self) -> [(&'static str, u64); 12] {
+        [
+            ("low_mapq", self.low_mapq),
+            ("unpaired", self.unpaired),
+            ("secondary", self.secondary),
+            ("supplementary", self.supplementary),
+            ("duplicate", self.duplicate),
+            ("unmapped", self.unmapped),
+            ("mate_unmapped", self.mate_unmapped),
+            ("cross_chromosome", self.cross_chromosome),
+            ("improper_pair", self.improper_pair),
+            ("above_max_insert_size", self.above_max_insert_size),
+            ("non_positive_tlen", self.non_positive_tlen),
+            ("counted", self.counted),
+        ]
+    }
+
+    /// 记录总数（等于读取的记录数）。
+    pub fn total(&self) -> u64 {
+        self.rows().iter().map(|&(_, n)| n).sum()
+    }
+
+    /// 排除记录最多的条件（不含`non_positive_tlen`）；没有记录被排除时为None。
+    pub fn dominant_rejection(&self) -> Option<(&'static str, u64)> {
+        self.rows()[..10].iter().copied().filter(|&(_, n)| n > 0).max_by_key(|&(_, n)| n)
+    }
+
+    fn reject(&mut self, reason: PairRejection) {
+        let count = match reason {
+            PairRejection::Record(RejectReason::Unpaired) => &mut self.unpaired,
+            PairRejection::Record(RejectReason::Secondary) => &mut self.secondary,
+            PairRejection::Record(RejectReason::Supplementary) => &mut self.supplementary,
+            PairRejection::Record(RejectReason::Duplicate) => &mut self.duplicate,
+            PairRejection::Record(RejectReason::Unmapped) => &mut self.unmapped,
+            PairRejection::Record(RejectReason::MateUnmapped) => &mut self.mate_unmapped,
+            PairRejection::Record(RejectReason::ImproperPair) => &mut self.improper_pair,
+            PairRejection::Record(RejectReason::LowMapq) => &mut self.low_mapq,
+            PairRejection::Record(RejectReason::QcFail) => unreachable!("QC失败的记录不被读对分类排除"),
+            PairRejection::CrossChromosome => &mut self.cross_chromosome,
+            PairRejection::AboveMaxInsertSize => &mut self.above_max_insert_size,
+        };
+        *count += 1;
+    }
+}
+
+/// 表格：表头`reason\trecords\tpct`，每个条件一行，`pct`为占读取记录数的比例。
+impl std::fmt::Display for FilterBreakdown {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let total = self.total();
+        write!(f, "reason\trecords\tpct")?;
+        for (reason, records) in self.rows() {
+            let pct = if total == 0 { 0.0 } else { records as f64 / total as f64 };
+            write!(f, "\n{}\t{}\t{}", reason, records, Rate(pct))?;
+        }
+        Ok(())
+    }
+}
+
+/// TANDEM读对的++/--细分统计。
+///
+/// 真正的串联方向文库中二者应大致相等（比例接近1）；明显偏斜说明存在
+/// 链特异的假象。
+///
+/// # Examples
+///
+/// ++读对是--的3倍；启用细分后合并的统计不变：
+///
+/// ```
+/// use bamqc_core::*;
+/// use bamqc_io::bam::BamWriter;
+/// use noodles::core::Position;
+/// use noodles::sam::alignment::record::cigar::op::{Kind, Op};
+/// use noodles::sam::alignment::record::Flags;
+/// use noodles::sam::alignment::RecordBuf;
+///
+/// let dir = std::env::temp_dir().join(format!("bamqc-tandem-{}", std::process::id()));
+/// std::fs::create_dir_all(&dir).unwrap();
+/// let bam = dir.join("tandem.bam").to_string_lossy().to_string();
+/// let header = "@HD\tVN:1.6\n@SQ\tSN:c1\tLN:10000\n".parse().unwrap();
+/// let mut writer = BamWriter::from_path(&bam, &header).unwrap();
+/// let kinds = [
+///     (600, Flags::MATE_REVERSE_COMPLEMENTED),
+///     (300, Flags::empty()),
+///     (100, Flags::REVERSE_COMPLEMENTED | Flags::MATE_REVERSE_COMPLEMENTED),
+/// ];
+/// for (i, (pairs, strands)) in kinds.into_iter().enumerate() {
+///     for j in 0..pairs {
+///         let record = RecordBuf::builder()
+///             .set_name(format!("p{}_{}", i, j))
+///             .set_flags(Flags::SEGMENTED | strands)
+///             .set_reference_sequence_id(0)
+///             .set_alignment_start(Position::try_from(100).unwrap())
+///             .set_cigar([Op::new(Kind::Match, 50)].into_iter().collect())
+///             .set_mate_reference_sequence_id(0)
+///             .set_mate_alignment_start(Position::try_from(300).unwrap())
+///             .set_template_length(250 + i as i32 * 50 + (j % 7) as i32)
+///             .build();
+///         writer.write_record_buf(&record).unwrap();
+///     }
+/// }
+/// writer.finish().unwrap();
+///
+/// let run = |tandem_split| {
+///     let options = InsertSizeOptions { tandem_split, min_pct: 0.0, ..Default::default() };
+///     compute_insert_size_with_report(&bam, &options, None).unwrap()
+/// };
+/// let (merged_median, merged) = run(false);
+/// let (split_median, split) = run(true);
+/// assert_eq!(split_median, merged_median);
+/// assert_eq!(split.counted_records, merged.counted_records);
+/// assert_eq!(split.histograms, merged.histograms);
+/// assert!(merged.tandem_split.is_none());
+///
+/// let tandem = split.tandem_split.unwrap();
+/// assert_eq!((tandem.ff_pairs, tandem.rr_pairs, tandem.ff_rr_ratio), (300, 100, Some(3.0)));
+/// assert!(split.to_string().contains("tandem_ff_rr_ratio: 3.0000"));
+/// std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct TandemSplitReport {
+    /// 两端都为正链（++）的读对数。
+    pub ff_pairs: u64,
+    /// 两端都为负链（--）的读对数。
+    pub rr_pairs: u64,
+    /// ff_pairs / rr_pairs，没有--读对时为None。
+    pub ff_rr_ratio: Option<f64>,
+}
+
+/// duplicate读名布隆过滤器的统计。
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct DuplicateNameFilterReport {
+    /// 第一遍中带duplicate标志的记录数（插入过滤器的读名数）。
+    pub duplicate_names: u64,
+    /// 过滤器位数。
+    pub filter_bits: u64,
+    /// 哈希函数个数。
+    pub filter_hashes: u32,
+    /// 估计的假阳性率。
+    pub false_positive_rate: f64,
+    /// 本身没有duplicate标志、因读名命中过滤器（mate为duplicate或假阳性）被排除的记录数。
+    pub mate_duplicate_records: u64,
+}
+
+/// 被排除读对的|TLEN|粗分bin计数（按数量级）。
+///
+/// bin为左闭右开区间：恰好1000落在`1-10kb`，恰好1000000落在`>1Mb`。
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ExcludedDistances {
+    /// 各bin的读对数，顺序与[`ExcludedDistances::LABELS`]一致。
+    pub counts: [u64; 5],
+}
+
+impl ExcludedDistances {
+    /// bin标签。
+    pub const LABELS: [&'static str; 5] = ["<1kb", "1-10kb", "10-100kb", "100kb-1Mb", ">1Mb"];
+
+    /// |TLEN|所在的bin下标，即`floor(log10(|TLEN|)) - 2`并截断到`0..=4`。
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bamqc_core::ExcludedDistances;
+    ///
+    /// assert_eq!(ExcludedDistances::bin(999), 0);
+    /// assert_eq!(ExcludedDistances::bin(1_000), 1);
+    /// assert_eq!(ExcludedDistances::bin(9_999), 1);
+    /// assert_eq!(ExcludedDistances::bin(10_000), 2);
+    /// assert_eq!(ExcludedDistances::bin(100_000), 3);
+    /// assert_eq!(ExcludedDistances::bin(999_999), 3);
+    /// assert_eq!(ExcludedDistances::bin(1_000_000), 4);
+    /// assert_eq!(ExcludedDistances::bin(250_000_000), 4);
+    /// ```
+    pub fn bin(distance: u64) -> usize {
+        // 用整数比较而不是浮点log10，保证边界值落在正确的bin
+        let mut bin = 0;
+        let mut bound = 1_000;
+        while bin < Self::LABELS.len() - 1 && distance >= bound {
+            bin += 1;
+            bound *= 10;
+        }
+        bin
+    }
+
+    /// 计入一个读对。
+    pub fn add(&mut self, distance: u64) {
+        self.counts[Self::bin(distance)] += 1;
+    }
+
+    /// 计入的读对总数。
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// (标签, 计数)行。
+    pub fn rows(&self) -> impl Iterator<Item = (&'static str, u64)> + '_ {
+        Self::LABELS.iter().copied().zip(self.counts.iter().copied())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for ExcludedDistances {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+        let mut map = serializer.serialize_map(Some(Self::LABELS.len()))?;
+        for (label, count) in self.rows() {
+            map.serialize_entry(label, &count)?;
+        }
+        map.end()
+    }
+}
+
+/// 单个组（样本、文库或读组）的插入片段大小。
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct GroupInsertSize {
+    /// 组名。
+    pub group: String,
+    /// 组是否来自头部（false表示由未声明的读组或缺少RG标签的记录产生）。
+    pub in_header: bool,
+    /// 计入的左端记录数。
+    pub pairs: u32,
+    /// 插入片段大小中位数；该组无法给出结果时为None。
+    pub insert_size: Option<i32>,
+}
+
+impl FilterReport {
+    /// TLEN正负失衡的告警阈值。
+    pub const TLEN_IMBALANCE_WARN: f64 = 0.05;
+
+    /// 记录一个候选记录的TLEN符号。
+    pub fn add_tlen(&mut self, tlen: i64) {
+        match tlen.signum() {
+            1 => self.tlen_positive += 1,
+            -1 => self.tlen_negative += 1,
+            _ => self.tlen_zero += 1,
+        }
+    }
+
+    /// 正负TLEN数量之比，没有负TLEN时返回None。
+    pub fn tlen_ratio(&self) -> Option<f64> {
+        if self.tlen_negative == 0 {
+            None
+        } else {
+            Some(self.tlen_positive as f64 / self.tlen_negative as f64)
+        }
+    }
+
+    /// TLEN正负失衡度：|positive − negative| / (positive + negative)。
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bamqc_core::FilterReport;
+    ///
+    /// let report = FilterReport { tlen_positive: 100, tlen_negative: 200, ..Default::default() };
+    /// assert!((report.tlen_imbalance() - 1.0 / 3.0).abs() < 1e-9);
+    /// assert!(report.tlen_imbalanced());
+    /// ```
+    pub fn tlen_imbalance(&self) -> f64 {
+        let total = self.tlen_positive + self.tlen_negative;
+        if total == 0 {
+            0.0
+        } else {
+            self.tlen_positive.abs_diff(self.tlen_negative) as f64 / total as f64
+        }
+    }
+
+    /// 失衡度是否超过告警阈值。
+    pub fn tlen_imbalanced(&self) -> bool {
+        self.tlen_imbalance() > Self::TLEN_IMBALANCE_WARN
+    }
+}
+
+impl std::fmt::Display for FilterReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(sample) = &self.sample {
+            writeln!(f, "sample: {}", sample)?;
+        }
+        writeln!(f, "processed: {}", self.processed_records)?;
+        if self.no_records {
+            writeln!(f, "no_records: true")?;
+        }
+        writeln!(f, "tlen_positive: {}", self.tlen_positive)?;
+        writeln!(f, "tlen_negative: {}", self.tlen_negative)?;
+        writeln!(f, "tlen_zero: {}", self.tlen_zero)?;
+        match self.tlen_ratio() {
+            Some(ratio) => writeln!(f, "tlen_pos_neg_ratio: {}", Rate(ratio))?,
+            None => writeln!(f, "tlen_pos_neg_ratio: NA")?,
+        }
+        writeln!(f, "tlen_imbalance: {}", Rate(self.tlen_imbalance()))?;
+        writeln!(f, "past_contig_end: {}", self.past_contig_end)?;
+        if let Some(low_mapq) = self.low_mapq_records {
+            writeln!(f, "low_mapq_records: {}", low_mapq)?;
+        }
+        write!(f, "counted: {}", self.counted_records)?;
+        for (reason, records) in self.breakdown.rows() {
+            if records > 0 && reason != "counted" {
+                write!(f, "\nrejected: {}\t{}", reason, records)?;
+            }
+        }
+        if let Some(split) = &self.tandem_split {
+            write!(f, "\ntandem_ff_pairs: {}", split.ff_pairs)?;
+            write!(f, "\ntandem_rr_pairs: {}", split.rr_pairs)?;
+            match split.ff_rr_ratio {
+                Some(ratio) => write!(f, "\ntandem_ff_rr_ratio: {}", Rate(ratio))?,
+                None => write!(f, "\ntandem_ff_rr_ratio: NA")?,
+            }
+        }
+        if let Some(excluded) = &self.excluded_distances {
+            for (label, count) in excluded.rows() {
+                write!(f, "\nexcluded_distance: {}\t{}", label, count)?;
+            }
+        }
+        if let Some(filter) = &self.duplicate_name_filter {
+            write!(f, "\nduplicate_names: {}", filter.duplicate_names)?;
+            write!(f, "\nfalse_positive_rate: {}", PicardFloat(filter.false_positive_rate))?;
+            write!(f, "\nmate_duplicate_records: {}", filter.mate_duplicate_records)?;
+        }
+        for pair in &self.largest_pairs {
+            write!(f, "\nlargest_pair: {}\t{}\t{}\t{}", pair.name, pair.tid, pair.pos, pair.tlen)?;
+        }
+        for group in &self.groups {
+            let insert_size = group.insert_size.map_or("NA".to_string(), |v| v.to_string());
+            write!(f, "\ngroup: {}\t{}\t{}", group.group, group.pairs, insert_size)?;
+        }
+        if let Some(expectation) = &self.expectation {
+            write!(f, "\n{}", expectation)?;
+        }
+        Ok(())
+    }
+}
+
 /// 确定配对方向（仅在TLEN > 0时调用）。
 /// 
 /// 根据Picard/HTSJDK的FR/RF/TANDEM语义确定配对读长的方向类型。
@@ -160,13 +1133,287 @@ impl Default for InsertSizeStats {
 /// assert_eq!(determine_pair_orientation(false, false), PairOrientation::Tandem);
 /// ```
 pub fn determine_pair_orientation(left_reverse: bool, right_reverse: bool) -> PairOrientation {
-    if left_reverse == right_reverse {
-        PairOrientation::Tandem
-    } else if !left_reverse && right_reverse {
-        PairOrientation::Fr
-    } else {
-        PairOrientation::Rf
+    determine_detailed_orientation(left_reverse, right_reverse).into()
+}
+
+/// 确定细分配对方向（仅在TLEN > 0时调用），同向读对按链区分为FF与RR。
+///
+/// # Examples
+///
+/// ```
+/// use bamqc_core::{determine_detailed_orientation, DetailedOrientation};
+///
+/// assert_eq!(determine_detailed_orientation(false, false), DetailedOrientation::ForwardForward);
+/// assert_eq!(determine_detailed_orientation(true, true), DetailedOrientation::ReverseReverse);
+/// assert_eq!(determine_detailed_orientation(false, true), DetailedOrientation::Fr);
+/// ```
+pub fn determine_detailed_orientation(left_reverse: bool, right_reverse: bool) -> DetailedOrientation {
+    match (left_reverse, right_reverse) {
+        (false, false) => DetailedOrientation::ForwardForward,
+        (true, true) => DetailedOrientation::ReverseReverse,
+        (false, true) => DetailedOrientation::Fr,
+        (true, false) => DetailedOrientation::Rf,
+    }
+}
+
+/// 读对分类的过滤条件。
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PairFilter {
+    /// 是否包含标记为duplicate的读对。
+    pub include_duplicates: bool,
+    /// 是否只使用proper pair。
+    pub require_proper_pair: bool,
+    /// 最大插入片段，|TLEN|超过该值的读对被排除。
+    pub max_insert_size: Option<u64>,
+    /// 最低MAPQ，低于该值的记录在读对分类之前被跳过。
+    pub min_mapq: Option<u8>,
+    /// MAPQ缺失（255）的记录视为未通过`min_mapq`（默认视为通过）。
+    pub strict_mapq: bool,
+}
+
+impl PairFilter {
+    /// 读对分类的记录级条件：配对、primary、两端都已比对，按`include_duplicates`
+    /// 排除duplicate；QC失败的记录不排除，MAPQ由[`PairFilter::passes_mapq`]另行检查。
+    pub fn read_filter(&self) -> ReadFilter {
+        ReadFilter {
+            include_duplicates: self.include_duplicates,
+            include_qc_fail: true,
+            require_paired: true,
+            require_both_mapped: true,
+            ..Default::default()
+        }
+    }
+
+    /// 记录的MAPQ是否通过`min_mapq`条件（未设置`min_mapq`时总是通过）。
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bamqc_core::PairFilter;
+    ///
+    /// let filter = PairFilter { min_mapq: Some(20), ..Default::default() };
+    /// assert!(!filter.passes_mapq(Some(0)));
+    /// assert!(filter.passes_mapq(Some(30)));
+    /// assert!(filter.passes_mapq(None));
+    /// assert!(!PairFilter { strict_mapq: true, ..filter }.passes_mapq(None));
+    /// assert!(PairFilter::default().passes_mapq(Some(0)));
+    /// ```
+    pub fn passes_mapq(&self, mapq: Option<u8>) -> bool {
+        match (self.min_mapq, mapq) {
+            (None, _) => true,
+            (Some(min), Some(mapq)) => mapq >= min,
+            (Some(_), None) => !self.strict_mapq,
+        }
+    }
+}
+
+/// 读对分类用到的记录字段。
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PairFields {
+    /// FLAG。
+    pub flags: u16,
+    /// 参考序列编号，没有时为-1。
+    pub tid: i32,
+    /// mate的参考序列编号，没有时为-1。
+    pub mate_tid: i32,
+    /// TLEN。
+    pub tlen: i64,
+}
+
+impl PairFields {
+    const PROPER_PAIR: u16 = 0x2;
+    const REVERSE: u16 = 0x10;
+    const MATE_REVERSE: u16 = 0x20;
+    const DUPLICATE: u16 = 0x400;
+
+    pub fn from_record(record: &BamRecord) -> Self {
+        Self {
+            flags: record.flag(),
+            tid: record.tid(),
+            mate_tid: record.mtid(),
+            tlen: record.insert_size(),
+        }
+    }
+
+    fn has(&self, flag: u16) -> bool {
+        self.flags & flag != 0
+    }
+}
+
+/// 记录被读对分类排除的原因。
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum PairRejection {
+    Record(RejectReason),
+    CrossChromosome,
+    AboveMaxInsertSize,
+}
+
+/// 读对在插入片段统计中的去向。
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum PairScreen {
+    /// 不是有效的同染色体已比对读对（或被duplicate条件排除）。
+    Invalid(PairRejection),
+    /// 有效读对，但被proper pair或最大插入片段条件排除。
+    Excluded { tlen: i64, reason: PairRejection },
+    /// 计入TLEN符号统计的候选记录（左端与右端都在内）。
+    Candidate { tlen: i64 },
+}
+
+/// 按固定顺序检查配对与比对的有效性，再应用proper pair与最大插入片段条件。
+///
+/// 方向只在全部检查之后由[`classify_fields`]确定：mate已被后处理设为
+/// 未比对、但残留了mate反向标志的记录在这里就被排除，不会被归入FR/RF。
+fn screen_pair(fields: &PairFields, filter: &PairFilter) -> PairScreen {
+    // 1. 配对、primary、duplicate，两端都已比对
+    if let Err(reason) = filter.read_filter().check_flags(fields.flags, u8::MAX) {
+        return PairScreen::Invalid(PairRejection::Record(reason));
+    }
+    // 2. 同时核对标志与坐标字段，二者不一致时视为未比对
+    if fields.tid < 0 {
+        return PairScreen::Invalid(PairRejection::Record(RejectReason::Unmapped));
+    }
+    if fields.mate_tid < 0 {
+        return PairScreen::Invalid(PairRejection::Record(RejectReason::MateUnmapped));
+    }
+    // 3. 同一条参考序列
+    if fields.tid != fields.mate_tid {
+        return PairScreen::Invalid(PairRejection::CrossChromosome);
+    }
+    // 4. 可选条件
+    let tlen = fields.tlen;
+    if filter.require_proper_pair && !fields.has(PairFields::PROPER_PAIR) {
+        return PairScreen::Excluded { tlen, reason: PairRejection::Record(RejectReason::ImproperPair) };
+    }
+    if filter.max_insert_size.is_some_and(|max| tlen.unsigned_abs() > max) {
+        return PairScreen::Excluded { tlen, reason: PairRejection::AboveMaxInsertSize };
+    }
+    PairScreen::Candidate { tlen }
+}
+
+/// 对记录字段做读对分类，返回计入插入片段统计的方向与插入片段大小。
+///
+/// 只有通过全部有效性检查、且TLEN > 0的左端记录返回Some，每个读对只计一次。
+///
+/// # Examples
+///
+/// 真值表（FLAG、tid、mate tid、TLEN → 结果，过滤条件为默认值）：
+///
+/// ```
+/// use bamqc_core::{classify_fields, PairFields, PairFilter, PairOrientation::*};
+///
+/// let rows = [
+///     // 有效的左端记录：方向由两个链标志决定
+///     (0x1 | 0x20, 0, 0, 300, Some((Fr, 300))),
+///     (0x1 | 0x10, 0, 0, 300, Some((Rf, 300))),
+///     (0x1, 0, 0, 300, Some((Tandem, 300))),
+///     (0x1 | 0x10 | 0x20, 0, 0, 300, Some((Tandem, 300))),
+///     (0x1 | 0x2 | 0x20, 0, 0, 300, Some((Fr, 300))),
+///     // 右端记录与TLEN为0的记录不计
+///     (0x1 | 0x10, 0, 0, -300, None),
+///     (0x1 | 0x20, 0, 0, 0, None),
+///     // 非配对、secondary、supplementary、duplicate
+///     (0x20, 0, 0, 300, None),
+///     (0x1 | 0x20 | 0x100, 0, 0, 300, None),
+///     (0x1 | 0x20 | 0x800, 0, 0, 300, None),
+///     (0x1 | 0x20 | 0x400, 0, 0, 300, None),
+///     (0x1 | 0x20 | 0x100 | 0x800, 0, 0, 300, None),
+///     // 本端未比对
+///     (0x1 | 0x4, 0, 0, 300, None),
+///     (0x1 | 0x4 | 0x20, 0, 0, 300, None),
+///     (0x1 | 0x4, -1, 0, 0, None),
+///     // mate未比对但残留mate反向标志
+///     (0x1 | 0x8 | 0x20, 0, 0, 300, None),
+///     (0x1 | 0x8 | 0x10 | 0x20, 0, 0, 300, None),
+///     (0x1 | 0x8 | 0x20, 0, -1, 300, None),
+///     (0x1 | 0x8, 0, 0, 300, None),
+///     (0x1 | 0x4 | 0x8, -1, -1, 0, None),
+///     // 标志未标未比对，但坐标字段缺失
+///     (0x1 | 0x20, 0, -1, 300, None),
+///     (0x1 | 0x20, -1, 0, 300, None),
+///     (0x1 | 0x20, -1, -1, 300, None),
+///     // mate在另一条参考序列
+///     (0x1 | 0x20, 0, 1, 300, None),
+///     (0x1 | 0x10, 1, 0, 300, None),
+///     // proper pair标志不影响默认过滤
+///     (0x1 | 0x2 | 0x10, 0, 0, 300, Some((Rf, 300))),
+///     (0x1 | 0x2, 0, 0, 300, Some((Tandem, 300))),
+///     (0x1 | 0x2 | 0x10 | 0x20, 2, 2, 5000, Some((Tandem, 5000))),
+///     // 第一、第二读标志不影响分类
+///     (0x1 | 0x40 | 0x20, 0, 0, 300, Some((Fr, 300))),
+///     (0x1 | 0x80 | 0x20, 0, 0, 300, Some((Fr, 300))),
+///     (0x1 | 0x40 | 0x10, 0, 0, -300, None),
+///     (0x1 | 0x200 | 0x20, 0, 0, 300, Some((Fr, 300))),
+/// ];
+/// for (flags, tid, mate_tid, tlen, expected) in rows {
+///     let fields = PairFields { flags, tid, mate_tid, tlen };
+///     assert_eq!(classify_fields(&fields, &PairFilter::default()), expected, "{:?}", fields);
+/// }
+///
+/// // 可选过滤条件
+/// let fr = PairFields { flags: 0x1 | 0x20, tid: 0, mate_tid: 0, tlen: 300 };
+/// let proper = PairFilter { require_proper_pair: true, ..Default::default() };
+/// assert_eq!(classify_fields(&fr, &proper), None);
+/// assert!(classify_fields(&PairFields { flags: 0x1 | 0x2 | 0x20, ..fr }, &proper).is_some());
+/// let max = PairFilter { max_insert_size: Some(299), ..Default::default() };
+/// assert_eq!(classify_fields(&fr, &max), None);
+/// let duplicates = PairFilter { include_duplicates: true, ..Default::default() };
+/// assert_eq!(classify_fields(&PairFields { flags: 0x1 | 0x20 | 0x400, ..fr }, &duplicates), Some((Fr, 300)));
+/// ```
+pub fn classify_fields(fields: &PairFields, filter: &PairFilter) -> Option<(PairOrientation, i32)> {
+    classify_fields_detailed(fields, filter).map(|(orientation, tlen)| (orientation.into(), tlen))
+}
+
+/// 同[`classify_fields`]，但返回区分++与--的细分方向。
+pub fn classify_fields_detailed(fields: &PairFields, filter: &PairFilter) -> Option<(DetailedOrientation, i32)> {
+    match screen_pair(fields, filter) {
+        PairScreen::Candidate { tlen } if tlen > 0 => Some((
+            determine_detailed_orientation(fields.has(PairFields::REVERSE), fields.has(PairFields::MATE_REVERSE)),
+            tlen as i32,
+        )),
+        _ => None,
+    }
+}
+
+/// 对记录做读对分类，见[`classify_fields`]；MAPQ未通过`min_mapq`条件的记录返回None。
+///
+/// # Examples
+///
+/// 同一份数据以BAM和SAM文本输入，统计结果相同：
+///
+/// ```
+/// use bamqc_core::{classify_pair, generate_test_data, view_records, InsertSizeStats, PairFilter, SimulationParams, ViewOptions};
+/// use bamqc_io::BamReader;
+///
+/// let dir = std::env::temp_dir().join(format!("bamqc-sam-input-{}", std::process::id()));
+/// std::fs::create_dir_all(&dir).unwrap();
+/// let bam = dir.join("sim.bam").to_string_lossy().to_string();
+/// let params = SimulationParams { pairs: 2000, unmapped_fraction: 0.05, secondary_rate: 0.1, ..Default::default() };
+/// generate_test_data(&bam, &params).unwrap();
+/// let sam = dir.join("sim.sam").to_string_lossy().to_string();
+/// let mut out = std::fs::File::create(&sam).unwrap();
+/// view_records(&bam, &ViewOptions { include_header: true, ..Default::default() }, &mut out).unwrap();
+///
+/// let stats = |path: &str| {
+///     let mut stats = InsertSizeStats::new();
+///     let mut reader = BamReader::from_path(path).unwrap();
+///     for record in reader.records() {
+///         if let Some((orientation, size)) = classify_pair(&record.unwrap(), &PairFilter::default()) {
+///             stats.add_insert_size(orientation, size);
+///         }
+///     }
+///     stats
+/// };
+/// let (from_bam, from_sam) = (stats(&bam), stats(&sam));
+/// assert!(from_bam.total_left_records > 0);
+/// assert_eq!(from_sam.total_left_records, from_bam.total_left_records);
+/// assert_eq!(from_sam.histograms, from_bam.histograms);
+/// std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+pub fn classify_pair(record: &BamRecord, filter: &PairFilter) -> Option<(PairOrientation, i32)> {
+    if !filter.passes_mapq(record.mapping_quality()) {
+        return None;
     }
+    classify_fields(&PairFields::from_record(record), filter)
 }
 
 
@@ -194,7 +1441,7 @@ impl InsertSizeCalculator {
         }
 
         let total: u32 = counts.values().sum();
-        let threshold = (total + 1) / 2; // "上中位"门槛：1-based计数
+        let threshold = total.div_ceil(2); // "上中位"门槛：1-based计数
         
         let mut sorted_sizes: Vec<i32> = counts.keys().copied().collect();
         sorted_sizes.sort();
@@ -215,6 +1462,50 @@ impl InsertSizeCalculator {
     /// 
     /// 根据指定的策略和最小百分比阈值，从统计数据中选择合适的配对方向
     /// 并计算其中位数作为最终的插入片段大小。
+    ///
+    /// 阈值是闭区间：占比恰好等于`min_pct`的类别保留（`-M 0.05`时恰好5%的
+    /// 类别保留）。占比按`count as f64 / total as f64`计算，结果是精确比值
+    /// 最近的双精度数，因此与字面量相同的比值（如1/20与0.05）比较结果相等。
+    ///
+    /// # Examples
+    ///
+    /// 边界用二进制可精确表示的比例验证：
+    ///
+    /// ```
+    /// use bamqc_core::{CategoryShare, InsertSizeCalculator, InsertSizeError, InsertSizeStats, Strategy};
+    /// use bamqc_core::PairOrientation::*;
+    ///
+    /// let stats = |pairs: &[(bamqc_core::PairOrientation, u32, i32)]| {
+    ///     let mut stats = InsertSizeStats::new();
+    ///     for &(orientation, count, size) in pairs {
+    ///         (0..count).for_each(|_| stats.add_insert_size(orientation, size));
+    ///     }
+    ///     stats
+    /// };
+    ///
+    /// // TANDEM恰好占25%：阈值0.25时保留，稍高时丢弃
+    /// let quarter = stats(&[(Fr, 3, 300), (Tandem, 1, 5000)]);
+    /// assert_eq!(InsertSizeCalculator::calculate(&quarter, 0.25, Tandem, Strategy::Specific).unwrap(), 5000);
+    /// let err = InsertSizeCalculator::calculate(&quarter, 0.25 + f64::EPSILON, Tandem, Strategy::Specific).unwrap_err();
+    /// assert!(matches!(err, InsertSizeError::OrientationFiltered { orientation: Tandem, .. }));
+    ///
+    /// // 恰好5%（1/20），-M 0.05
+    /// let five = stats(&[(Fr, 19, 300), (Rf, 1, 2000)]);
+    /// assert_eq!(InsertSizeCalculator::calculate(&five, 0.05, Rf, Strategy::Specific).unwrap(), 2000);
+    ///
+    /// // 3/8、3/8、1/4都低于0.5：错误中带有各类别的占比
+    /// let spread = stats(&[(Fr, 3, 300), (Rf, 3, 2000), (Tandem, 2, 5000)]);
+    /// let err = InsertSizeCalculator::calculate(&spread, 0.5, Fr, Strategy::Dominant).unwrap_err();
+    /// assert_eq!(
+    ///     err.categories().unwrap(),
+    ///     [
+    ///         CategoryShare { orientation: Fr, count: 3, pct: 0.375 },
+    ///         CategoryShare { orientation: Rf, count: 3, pct: 0.375 },
+    ///         CategoryShare { orientation: Tandem, count: 2, pct: 0.25 },
+    ///     ]
+    /// );
+    /// assert!(err.category_table().unwrap().ends_with("# 可将-M降到0.37"));
+    /// ```
     /// 
     /// # Parameters
     /// 
@@ -244,48 +1535,60 @@ impl InsertSizeCalculator {
         }
 
         if stats.total_left_records == 0 {
-            return Err(InsertSizeError::NoValidReads);
+            return Err(InsertSizeError::NoValidReads { report: None });
         }
 
-        // 按最小百分比阈值过滤方向类别
-        let mut kept_categories = HashMap::new();
-        for (orientation, counts) in &stats.histograms {
+        // 按最小百分比阈值过滤方向类别（按固定的方向顺序），阈值为闭区间
+        let mut categories = Vec::new();
+        let mut kept_categories = Vec::new();
+        for orientation in PairOrientation::ALL {
+            let counts = &stats.histograms[&orientation];
             let count: u32 = counts.values().sum();
             if count == 0 {
                 continue;
             }
             let pct = count as f64 / stats.total_left_records as f64;
+            categories.push(CategoryShare { orientation, count, pct });
             if pct >= min_pct {
-                kept_categories.insert(*orientation, (count, counts));
+                kept_categories.push((orientation, count, counts));
             }
         }
 
         if kept_categories.is_empty() {
-            return Err(InsertSizeError::AllCategoriesFiltered { min_pct });
+            return Err(InsertSizeError::AllCategoriesFiltered { min_pct, categories });
         }
 
         match strategy {
             Strategy::Specific => {
-                if let Some((_, counts)) = kept_categories.get(&orientation_pref) {
+                if let Some((_, _, counts)) = kept_categories.iter().find(|(o, _, _)| *o == orientation_pref) {
                     let median = Self::calculate_median_from_counts(counts);
                     Ok(median)
                 } else {
                     Err(InsertSizeError::OrientationFiltered {
                         orientation: orientation_pref,
                         min_pct,
+                        categories,
                     })
                 }
             }
             Strategy::Dominant => {
-                let (_, (_, counts)) = kept_categories
-                    .iter()
-                    .max_by_key(|(_, (count, _))| *count)
-                    .unwrap();
+                // 数量并列时取固定顺序中靠前的方向
+                let (_, _, counts) = Self::dominant(&kept_categories);
                 let median = Self::calculate_median_from_counts(counts);
                 Ok(median)
             }
         }
     }
+
+    fn dominant<T>(categories: &[(PairOrientation, u32, T)]) -> &(PairOrientation, u32, T) {
+        let mut best = &categories[0];
+        for category in &categories[1..] {
+            if category.1 > best.1 {
+                best = category;
+            }
+        }
+        best
+    }
 }
 
 /// 计算插入片段大小。
@@ -313,103 +1616,783 @@ pub fn compute_insert_size(
     orientation_pref: PairOrientation,
     strategy: Strategy,
 ) -> Result<i32, InsertSizeError> {
-    let mut reader = BamReader::from_path(bam_path)?;
-    let mut stats = InsertSizeStats::new();
+    let options = InsertSizeOptions {
+        include_duplicates,
+        require_proper_pair,
+        min_pct,
+        orientation_pref,
+        strategy,
+        report_largest: 0,
+        accumulation_level: AccumulationLevel::AllReads,
+        max_insert_size: None,
+        report_excluded_distances: false,
+        strict_read_groups: false,
+        pair_duplicate_policy: PairDuplicatePolicy::LeftRecord,
+        tandem_split: false,
+        stream_sample: None,
+        min_mapq: None,
+        strict_mapq: false,
+        sample_fraction: None,
+        sample_seed: 0,
+        reader: BamReaderOptions::new(),
+    };
+    compute_insert_size_with_report(bam_path, &options, None).map(|(result, _)| result)
+}
+
+/// 插入片段大小计算选项。
+#[derive(Clone, Debug)]
+pub struct InsertSizeOptions {
+    /// 是否包含标记为duplicate的读对。
+    pub include_duplicates: bool,
+    /// 是否只统计proper pair。
+    pub require_proper_pair: bool,
+    /// 类别最小占比阈值。
+    pub min_pct: f64,
+    /// 首选的配对方向。
+    pub orientation_pref: PairOrientation,
+    /// 输出策略。
+    pub strategy: Strategy,
+    /// 大于0时，报告中包含插入片段最大的`report_largest`个读对。
+    pub report_largest: usize,
+    /// 分组统计的累积层级（ALL_READS时不分组）。
+    pub accumulation_level: AccumulationLevel,
+    /// 最大插入片段，|TLEN|超过该值的读对不计入统计。
+    pub max_insert_size: Option<u64>,
+    /// 统计仅因proper pair或最大插入片段条件被排除的读对的距离分布。
+    pub report_excluded_distances: bool,
+    /// 记录中出现头部未声明的读组时报错（只在累积层级不为ALL_READS时检查）。
+    pub strict_read_groups: bool,
+    /// 读对的duplicate判定方式（包含duplicate时不起作用）。
+    pub pair_duplicate_policy: PairDuplicatePolicy,
+    /// TANDEM另按++与--分别统计，报告二者的读对数与比例（合并后的各项统计不变）。
+    pub tandem_split: bool,
+    /// 每计入N条记录把一条的摘要写到旁路JSONL文件（见[`crate::stream_sample`]）。
+    pub stream_sample: Option<StreamSampleSpec>,
+    /// 最低MAPQ，低于该值的记录在计入TLEN统计之前被跳过。
+    pub min_mapq: Option<u8>,
+    /// MAPQ缺失（255）的记录视为未通过`min_mapq`（默认视为通过）。
+    pub strict_mapq: bool,
+    /// 按读名抽样的比例（(0, 1]），读对的两端同进同出；None时统计全部记录。
+    pub sample_fraction: Option<f64>,
+    /// 抽样的种子，见[`BamReader::records_sampled`]。
+    pub sample_seed: u64,
+    /// 打开输入的读取器配置（解压线程数、输入格式、CRAM参考序列等）。
+    pub reader: BamReaderOptions,
+}
+
+impl Default for InsertSizeOptions {
+    fn default() -> Self {
+        Self {
+            include_duplicates: false,
+            require_proper_pair: false,
+            min_pct: 0.05,
+            orientation_pref: PairOrientation::Fr,
+            strategy: Strategy::Specific,
+            report_largest: 0,
+            accumulation_level: AccumulationLevel::AllReads,
+            max_insert_size: None,
+            report_excluded_distances: false,
+            strict_read_groups: false,
+            pair_duplicate_policy: PairDuplicatePolicy::LeftRecord,
+            tandem_split: false,
+            stream_sample: None,
+            min_mapq: None,
+            strict_mapq: false,
+            sample_fraction: None,
+            sample_seed: 0,
+            reader: BamReaderOptions::new(),
+        }
+    }
+}
+
+/// 计算插入片段大小，并同时返回过滤统计报告。
+///
+/// `interim`不为None时，在记录循环中按其触发条件写出阶段性快照，
+/// 结束后删除interim文件。
+///
+/// # Examples
+///
+/// 三个FR读对的MAPQ分别为0、30与255（缺失）；`min_mapq`跳过低MAPQ的记录，
+/// MAPQ缺失的记录默认通过，`strict_mapq`时同样跳过：
+///
+/// ```
+/// use bamqc_core::*;
+/// use bamqc_io::bam::BamWriter;
+/// use noodles::core::Position;
+/// use noodles::sam::alignment::record::cigar::op::{Kind, Op};
+/// use noodles::sam::alignment::record::{Flags, MappingQuality};
+/// use noodles::sam::alignment::RecordBuf;
+///
+/// let dir = std::env::temp_dir().join(format!("bamqc-min-mapq-{}", std::process::id()));
+/// std::fs::create_dir_all(&dir).unwrap();
+/// let bam = dir.join("mapq.bam").to_string_lossy().to_string();
+/// let header = "@HD\tVN:1.6\n@SQ\tSN:c1\tLN:10000\n".parse().unwrap();
+/// let mut writer = BamWriter::from_path(&bam, &header).unwrap();
+/// for (i, (mapq, tlen)) in [(0, 100), (30, 300), (255, 500)].into_iter().enumerate() {
+///     for (flags, start, sign) in [(Flags::MATE_REVERSE_COMPLEMENTED, 100, 1), (Flags::REVERSE_COMPLEMENTED, 100 + tlen - 50, -1)] {
+///         let mut builder = RecordBuf::builder()
+///             .set_name(format!("p{}", i))
+///             .set_flags(Flags::SEGMENTED | flags)
+///             .set_reference_sequence_id(0)
+///             .set_alignment_start(Position::try_from(start).unwrap())
+///             .set_cigar([Op::new(Kind::Match, 50)].into_iter().collect())
+///             .set_mate_reference_sequence_id(0)
+///             .set_mate_alignment_start(Position::try_from(100).unwrap())
+///             .set_template_length(sign * tlen as i32);
+///         if let Some(mapq) = MappingQuality::new(mapq) {
+///             builder = builder.set_mapping_quality(mapq);
+///         }
+///         writer.write_record_buf(&builder.build()).unwrap();
+///     }
+/// }
+/// writer.finish().unwrap();
+///
+/// let run = |min_mapq, strict_mapq| {
+///     let options = InsertSizeOptions { min_pct: 0.0, min_mapq, strict_mapq, ..Default::default() };
+///     compute_insert_size_with_report(&bam, &options, None).unwrap().1
+/// };
+/// let all = run(None, false);
+/// assert_eq!((all.counted_records, all.low_mapq_records), (3, None));
+/// let filtered = run(Some(20), false);
+/// assert_eq!((filtered.counted_records, filtered.low_mapq_records), (2, Some(2)));
+/// assert!(filtered.to_string().contains("low_mapq_records: 2"));
+/// let strict = run(Some(20), true);
+/// assert_eq!((strict.counted_records, strict.low_mapq_records), (1, Some(4)));
+/// std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+pub fn compute_insert_size_with_report(
+    bam_path: &str,
+    options: &InsertSizeOptions,
+    interim: Option<InterimEmitter>,
+) -> Result<(i32, FilterReport), InsertSizeError> {
+    compute_insert_size_with_progress(bam_path, options, interim, &mut LogProgressSink::default())
+}
+
+/// 与[`compute_insert_size_with_report`]相同，并向`progress`发出进度事件（见[`crate::progress`]）。
+///
+/// 读取阶段依次为`duplicate-names`（只在需要按读对排除duplicate时）与`insert-size`。
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+/// use bamqc_core::*;
+///
+/// let dir = std::env::temp_dir().join(format!("bamqc-progress-{}", std::process::id()));
+/// std::fs::create_dir_all(&dir).unwrap();
+/// let bam = dir.join("sim.bam").to_string_lossy().to_string();
+/// generate_test_data(&bam, &SimulationParams { pairs: 5000, ..Default::default() }).unwrap();
+///
+/// let options = InsertSizeOptions { pair_duplicate_policy: PairDuplicatePolicy::EitherMate, ..Default::default() };
+/// let (mut sink, events) = ChannelProgressSink::with_interval(Duration::ZERO);
+/// compute_insert_size_with_progress(&bam, &options, None, &mut sink).unwrap();
+/// drop(sink);
+/// let events: Vec<ProgressEvent> = events.into_iter().collect();
+///
+/// let size = std::fs::metadata(&bam).unwrap().len();
+/// assert_eq!(events[0], ProgressEvent::Start { total_hint: Some(size) });
+/// let phases: Vec<&str> = events.iter().filter_map(|e| match e {
+///     ProgressEvent::PhaseChange(name) => Some(name.as_str()),
+///     _ => None,
+/// }).collect();
+/// assert_eq!(phases, ["duplicate-names", "insert-size"]);
+/// // 10000条记录：每个阶段在第4096与8192条时各一次（间隔为0），结束时一次
+/// let progress: Vec<u64> = events.iter().filter_map(|e| match e {
+///     ProgressEvent::Progress { records, .. } => Some(*records),
+///     _ => None,
+/// }).collect();
+/// assert_eq!(progress, [4096, 8192, 4096, 8192, 10_000]);
+/// match events.last() {
+///     Some(ProgressEvent::Finish(summary)) => assert_eq!(summary.records, 10_000),
+///     other => panic!("{:?}", other),
+/// }
+/// std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+pub fn compute_insert_size_with_progress(
+    bam_path: &str,
+    options: &InsertSizeOptions,
+    interim: Option<InterimEmitter>,
+    progress: &mut dyn ProgressSink,
+) -> Result<(i32, FilterReport), InsertSizeError> {
+    compute_insert_size_multi(&[bam_path], options, interim, progress)
+}
+
+/// 与[`compute_insert_size_with_progress`]相同，但把多个输入（例如按lane拆分的BAM）
+/// 依次读取、合并为一个结果，见[`MultiBamReader`]。
+///
+/// 各输入的参考序列字典必须相同，否则返回[`BamError::ReferenceMismatch`]；各输入头部
+/// 声明的读组都视为已声明。`on_start`的字节数为各输入大小之和。
+///
+/// # Examples
+///
+/// ```
+/// use bamqc_core::*;
+///
+/// let dir = std::env::temp_dir().join(format!("bamqc-multi-insert-size-{}", std::process::id()));
+/// std::fs::create_dir_all(&dir).unwrap();
+/// let all = dir.join("all.bam").to_string_lossy().to_string();
+/// generate_test_data(&all, &SimulationParams { pairs: 4000, ..Default::default() }).unwrap();
+/// // 按记录顺序拆成两个lane
+/// let lanes = [dir.join("L1.bam").to_string_lossy().to_string(), dir.join("L2.bam").to_string_lossy().to_string()];
+/// let mut reader = bamqc_io::BamReader::from_path(&all).unwrap();
+/// let header = reader.header().clone();
+/// let mut writers: Vec<_> = lanes.iter().map(|lane| bamqc_io::BamWriter::from_path(lane, &header).unwrap()).collect();
+/// for (i, record) in reader.records().enumerate() {
+///     writers[usize::from(i >= 5000)].write_record(&record.unwrap()).unwrap();
+/// }
+/// writers.into_iter().for_each(|writer| writer.finish().unwrap());
+///
+/// let options = InsertSizeOptions::default();
+/// let (median, report) = compute_insert_size_with_report(&all, &options, None).unwrap();
+/// let lanes: Vec<&str> = lanes.iter().map(String::as_str).collect();
+/// let (merged, merged_report) = compute_insert_size_multi(&lanes, &options, None, &mut ()).unwrap();
+/// assert_eq!(merged, median);
+/// assert_eq!(merged_report.processed_records, report.processed_records);
+/// assert_eq!(merged_report.histograms, report.histograms);
+/// std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+pub fn compute_insert_size_multi(
+    bam_paths: &[&str],
+    options: &InsertSizeOptions,
+    mut interim: Option<InterimEmitter>,
+    progress: &mut dyn ProgressSink,
+) -> Result<(i32, FilterReport), InsertSizeError> {
+    let level = options.accumulation_level;
+    let filter = options.filter();
+    options.check_sample_fraction()?;
+    let mut progress = ProgressReporter::new(progress);
+    let readers = bam_paths.iter().map(|path| options.reader.open(path)).collect::<Result<_, _>>()?;
+    let mut reader = MultiBamReader::from_readers(readers)?;
+    progress.start(reader.file_size());
+    let duplicates = collect_duplicate_names(reader.readers(), options, &mut progress)?;
+    reader.readers().iter().for_each(warn_unless_coordinate_sorted);
+    let headers = reader.readers().iter().map(BamReader::header);
+    let mut groups = GroupInterner::from_headers(headers).with_strict(options.strict_read_groups);
+    let mut acc = InsertSizeAccumulator::new(options, ContigBounds::from_header(reader.header()));
+    let mut stream = options.stream_sampler()?;
+    let bam_path = bam_paths.join(", ");
 
     info!("开始处理BAM文件: {}", bam_path);
-    
+    progress.phase("insert-size");
+
     let mut processed_records = 0;
-    let mut filtered_records = 0;
 
-    for result in reader.records() {
-        let record = result?;
+    let mut record = BamRecord::default();
+    while reader.read_into(&mut record)? {
         processed_records += 1;
-
-        if processed_records % 1_000_000 == 0 {
-            debug!("已处理 {} 条记录", processed_records);
-        }
-
-        // 基础过滤
-        if !record.is_segmented() {
-            continue;
+        if let Some(interim) = interim.as_mut() {
+            interim.tick(processed_records, || &acc.stats);
         }
-        if record.is_secondary() || record.is_supplementary() {
+        progress.tick(processed_records, || reader.bytes_read());
+        if !options.sampled(&record) {
             continue;
         }
-        if !include_duplicates && record.is_duplicate() {
+
+        let Some((orientation, insert_size)) = acc.add(&record, &filter, duplicates.as_ref()) else {
             continue;
+        };
+        if let Some(stream) = stream.as_mut() {
+            stream.tap(&record);
         }
-        if record.is_unmapped() || record.is_mate_unmapped() {
-            continue;
+        if level != AccumulationLevel::AllReads {
+            let key = groups.record_key(&record, level)?;
+            acc.add_group(key, orientation, insert_size);
         }
-        if record.tid() != record.mtid() {
-            continue;
+    }
+
+    if let Some(interim) = interim {
+        interim.finish();
+    }
+    if let Some(stream) = stream {
+        stream.finish();
+    }
+
+    groups.warn_unknown(&bam_path);
+    let result = acc.finish(options, &groups, duplicates.as_ref(), processed_records == 0)?;
+    progress.finish(processed_records, reader.bytes_read());
+    Ok(result)
+}
+
+/// 一个样本的插入片段计算结果：(样本名, 中位数与过滤统计报告)。
+pub type SampleInsertSize = (String, Result<(i32, FilterReport), InsertSizeError>);
+
+/// 按样本分别计算插入片段大小，顺序与头部中的样本一致。
+///
+/// 单次读取，每个样本各自累积直方图、过滤统计与分组结果，各样本的结果
+/// 与只含该样本记录的BAM上[`compute_insert_size_with_report`]的结果相同
+/// （未声明读组在读取时即登记，不论记录是否计入）。记录中出现的未声明
+/// 读组与缺少RG标签的记录归入`unknown`样本，未声明读组的统计只出现在该样本的报告中。
+///
+/// 读取错误与严格模式下的读组错误使整个计算失败；某个样本无法给出
+/// 插入片段大小时只影响该样本的结果。
+pub fn compute_insert_size_per_sample(
+    bam_path: &str,
+    options: &InsertSizeOptions,
+) -> Result<Vec<SampleInsertSize>, InsertSizeError> {
+    let level = options.accumulation_level;
+    let filter = options.filter();
+    options.check_sample_fraction()?;
+    let mut reader = options.reader.open(bam_path)?;
+    let duplicates = collect_duplicate_names(std::slice::from_ref(&reader), options, &mut ProgressReporter::new(&mut ()))?;
+    warn_unless_coordinate_sorted(&reader);
+    let mut groups = GroupInterner::from_header(reader.header()).with_strict(options.strict_read_groups);
+    let bounds = ContigBounds::from_header(reader.header());
+    let new_acc = || InsertSizeAccumulator::new(options, bounds.clone());
+    let mut samples: Vec<InsertSizeAccumulator> = (0..groups.sample_count()).map(|_| new_acc()).collect();
+    let mut stream = options.stream_sampler()?;
+
+    info!("开始按样本处理BAM文件: {}", bam_path);
+
+    let mut processed_records: u64 = 0;
+
+    let mut record = BamRecord::default();
+    while reader.read_into(&mut record)? {
+        processed_records += 1;
+        if processed_records.is_multiple_of(1_000_000) {
+            debug!("{}", progress_message(processed_records, reader.progress_fraction()));
         }
-        if require_proper_pair && !record.is_properly_segmented() {
+        if !options.sampled(&record) {
             continue;
         }
 
-        let tlen = record.insert_size();
-        
-        // 只计"左端记录"（TLEN > 0）
-        if tlen <= 0 {
+        let rg = groups.record_read_group_key(&record)?;
+        let sample = groups.level_key(rg, AccumulationLevel::Sample);
+        if sample >= samples.len() {
+            samples.resize_with(sample + 1, new_acc);
+        }
+        let acc = &mut samples[sample];
+        let Some((orientation, insert_size)) = acc.add(&record, &filter, duplicates.as_ref()) else {
             continue;
+        };
+        if let Some(stream) = stream.as_mut() {
+            stream.tap(&record);
         }
+        if level != AccumulationLevel::AllReads {
+            acc.add_group(groups.level_key(rg, level), orientation, insert_size);
+        }
+    }
 
-        let insert_size = tlen.abs() as i32;
-        if insert_size == 0 {
-            continue;
+    if let Some(stream) = stream {
+        stream.finish();
+    }
+    groups.warn_unknown(bam_path);
+    Ok(samples
+        .into_iter()
+        .enumerate()
+        .map(|(key, acc)| {
+            let sample = groups.name(AccumulationLevel::Sample, key).to_string();
+            info!("样本 {}:", sample);
+            let label = |report: &mut FilterReport| {
+                // 未声明读组的记录都在unknown样本中
+                if sample != UNKNOWN_GROUP {
+                    report.unknown_read_groups = UnknownReadGroups::default();
+                }
+                report.sample = Some(sample.clone());
+            };
+            let result = match acc.finish(options, &groups, duplicates.as_ref(), processed_records == 0) {
+                Ok((median, mut report)) => {
+                    label(&mut report);
+                    Ok((median, report))
+                }
+                Err(InsertSizeError::NoValidReads { report: Some(mut report) }) => {
+                    label(&mut report);
+                    Err(InsertSizeError::NoValidReads { report: Some(report) })
+                }
+                Err(e) => Err(e),
+            };
+            (sample, result)
+        })
+        .collect())
+}
+
+/// 头部未声明按坐标排序时给出警告：按读名排序等输入中两个mate相邻，
+/// TLEN的约定也可能不同，"左侧记录"的判定与计数不再可靠。
+fn warn_unless_coordinate_sorted(reader: &BamReader) {
+    let sort_order = reader.sort_order();
+    if sort_order != SortOrder::Coordinate {
+        warn!(
+            "{} 的头部声明的排序为{}，insert_size按坐标排序的输入设计，结果可能不可靠（可用samtools sort重新排序）",
+            reader.path(),
+            sort_order
+        );
+    }
+}
+
+/// [`PairDuplicatePolicy::EitherMate`]且不包含duplicate时，第一遍读取带duplicate
+/// 标志的读名；其它情况返回None。
+///
+/// 第一遍用[`BamReader::try_clone`]重新打开`readers`，共享已解析的头部。
+fn collect_duplicate_names(
+    readers: &[BamReader],
+    options: &InsertSizeOptions,
+    progress: &mut ProgressReporter,
+) -> Result<Option<BloomFilter>, BamError> {
+    if options.include_duplicates || options.pair_duplicate_policy != PairDuplicatePolicy::EitherMate {
+        return Ok(None);
+    }
+    let paths: Vec<&str> = readers.iter().map(BamReader::path).collect();
+    info!("按读对排除duplicate：第一遍收集duplicate读名: {}", paths.join(", "));
+    progress.phase("duplicate-names");
+    let clones = readers.iter().map(BamReader::try_clone).collect::<Result<Vec<_>, _>>()?;
+    let mut reader = MultiBamReader::from_readers(clones)?;
+    let mut names = BloomFilter::new(DEFAULT_BLOOM_BITS, DEFAULT_BLOOM_HASHES);
+    let mut record = BamRecord::default();
+    let mut records = 0;
+    while reader.read_into(&mut record)? {
+        records += 1;
+        progress.tick(records, || reader.bytes_read());
+        if record.is_duplicate() {
+            names.insert(record.qname());
         }
+    }
+    info!(
+        "duplicate读名 {} 个，布隆过滤器估计假阳性率 {}",
+        names.inserted(),
+        PicardFloat(names.false_positive_rate())
+    );
+    Ok(Some(names))
+}
 
-        let orientation = determine_pair_orientation(record.is_reverse(), record.is_mate_reverse());
-        stats.add_insert_size(orientation, insert_size);
-        filtered_records += 1;
+impl InsertSizeOptions {
+    fn filter(&self) -> PairFilter {
+        PairFilter {
+            include_duplicates: self.include_duplicates,
+            require_proper_pair: self.require_proper_pair,
+            max_insert_size: self.max_insert_size,
+            min_mapq: self.min_mapq,
+            strict_mapq: self.strict_mapq,
+        }
     }
 
-    info!("处理完成：总记录数 {}，有效左端记录数 {}", processed_records, filtered_records);
+    fn stream_sampler(&self) -> Result<Option<StreamSampler>, InsertSizeError> {
+        self.stream_sample
+            .clone()
+            .map(|spec| {
+                let path = spec.file.display().to_string();
+                StreamSampler::create(spec).map_err(|source| InsertSizeError::StreamSample { path, source })
+            })
+            .transpose()
+    }
 
-    // 使用 InsertSizeCalculator 来计算最终结果
-    let result = InsertSizeCalculator::calculate(&stats, min_pct, orientation_pref, strategy)?;
-    
-    // 记录保留的类别信息
-    for (orientation, counts) in &stats.histograms {
-        let count: u32 = counts.values().sum();
-        if count == 0 {
-            continue;
+    /// 检查抽样比例，有效时记录日志
+    fn check_sample_fraction(&self) -> Result<(), InsertSizeError> {
+        if let Some(fraction) = self.sample_fraction {
+            check_sample_fraction(fraction)?;
+            info!("按读名抽样{}的读对（种子{}）", fraction, self.sample_seed);
         }
-        let pct = count as f64 / stats.total_left_records as f64;
-        if pct >= min_pct {
-            info!("保留类别 {}: {} 个读对 ({:.2}%)", orientation, count, pct * 100.0);
-        } else {
-            warn!("丢弃类别 {}: {} 个读对 ({:.2}%) < {:.1}%", orientation, count, pct * 100.0, min_pct * 100.0);
+        Ok(())
+    }
+
+    /// 记录是否被抽中；不抽样时总是true
+    fn sampled(&self, record: &BamRecord) -> bool {
+        self.sample_fraction.is_none_or(|fraction| keeps_read_name(record.qname(), fraction, self.sample_seed))
+    }
+}
+
+/// 一组记录（整个文件或一个样本）的插入片段累积状态。
+struct InsertSizeAccumulator {
+    stats: InsertSizeStats,
+    group_stats: Vec<InsertSizeStats>,
+    report: FilterReport,
+    excluded: Option<ExcludedDistances>,
+    bounds: ContigBounds,
+    mate_duplicate_records: u64,
+}
+
+impl InsertSizeAccumulator {
+    fn new(options: &InsertSizeOptions, bounds: ContigBounds) -> Self {
+        Self {
+            stats: InsertSizeStats::new()
+                .with_largest(options.report_largest)
+                .with_tandem_split(options.tandem_split),
+            group_stats: Vec::new(),
+            report: FilterReport { low_mapq_records: options.min_mapq.map(|_| 0), ..Default::default() },
+            excluded: options.report_excluded_distances.then(ExcludedDistances::default),
+            bounds,
+            mate_duplicate_records: 0,
         }
     }
 
-    match strategy {
-        Strategy::Specific => {
-            info!("使用指定方向 {} 的中位数: {}", orientation_pref, result);
+    /// 计入一条记录，返回计入直方图的左端记录的方向与插入片段大小。
+    ///
+    /// `duplicates`为按读对排除duplicate时的读名过滤器：读名命中时视同本记录
+    /// 带duplicate标志，在同一步骤中排除。
+    fn add(
+        &mut self,
+        record: &BamRecord,
+        filter: &PairFilter,
+        duplicates: Option<&BloomFilter>,
+    ) -> Option<(PairOrientation, i32)> {
+        self.report.processed_records += 1;
+        self.bounds.check(record);
+        if !filter.passes_mapq(record.mapping_quality()) {
+            if let Some(low_mapq) = self.report.low_mapq_records.as_mut() {
+                *low_mapq += 1;
+            }
+            self.report.breakdown.low_mapq += 1;
+            return None;
+        }
+
+        let mut fields = PairFields::from_record(record);
+        if let Some(duplicates) = duplicates {
+            if !fields.has(PairFields::DUPLICATE) && duplicates.contains(record.qname()) {
+                fields.flags |= PairFields::DUPLICATE;
+                self.mate_duplicate_records += 1;
+            }
         }
-        Strategy::Dominant => {
-            // 找到最大类别
-            let best_orientation = stats.histograms
-                .iter()
-                .filter_map(|(orientation, counts)| {
-                    let count: u32 = counts.values().sum();
-                    let pct = count as f64 / stats.total_left_records as f64;
-                    if pct >= min_pct {
-                        Some((orientation, count))
-                    } else {
-                        None
+        let tlen = match screen_pair(&fields, filter) {
+            PairScreen::Invalid(reason) => {
+                self.report.breakdown.reject(reason);
+                return None;
+            }
+            PairScreen::Excluded { tlen, reason } => {
+                self.report.breakdown.reject(reason);
+                // 每个读对只计左端记录一次
+                if let Some(excluded) = self.excluded.as_mut() {
+                    if tlen > 0 {
+                        excluded.add(tlen.unsigned_abs());
                     }
-                })
-                .max_by_key(|(_, count)| *count)
-                .map(|(orientation, _)| orientation);
-            
-            if let Some(best_orientation) = best_orientation {
-                info!("使用最大类别 {} 的中位数: {}", best_orientation, result);
+                }
+                return None;
             }
+            PairScreen::Candidate { tlen } => tlen,
+        };
+        self.report.add_tlen(tlen);
+
+        // 只计"左端记录"（TLEN > 0）
+        let Some((orientation, insert_size)) = classify_fields_detailed(&fields, filter) else {
+            self.report.breakdown.non_positive_tlen += 1;
+            return None;
+        };
+        self.report.breakdown.counted += 1;
+        self.stats.add_detailed(orientation, insert_size);
+        if let Some(largest) = self.stats.largest.as_mut() {
+            largest.offer(record, tlen);
         }
+        self.report.counted_records += 1;
+        Some((orientation.into(), insert_size))
     }
 
-    Ok(result)
-}
\ No newline at end of file
+    /// 把已计入的左端记录同时计入所属的组。
+    fn add_group(&mut self, key: usize, orientation: PairOrientation, insert_size: i32) {
+        if key >= self.group_stats.len() {
+            self.group_stats.resize_with(key + 1, InsertSizeStats::new);
+        }
+        self.group_stats[key].add_insert_size(orientation, insert_size);
+    }
+
+    /// 给出中位数与过滤统计报告；`no_records`为整个输入是否没有任何记录。
+    fn finish(
+        self,
+        options: &InsertSizeOptions,
+        groups: &GroupInterner,
+        duplicates: Option<&BloomFilter>,
+        no_records: bool,
+    ) -> Result<(i32, FilterReport), InsertSizeError> {
+        let InsertSizeOptions {
+            min_pct,
+            orientation_pref,
+            strategy,
+            accumulation_level,
+            ..
+        } = *options;
+        let Self {
+            stats,
+            group_stats,
+            mut report,
+            excluded,
+            bounds,
+            mate_duplicate_records,
+        } = self;
+
+        report.duplicate_name_filter = duplicates.map(|names| DuplicateNameFilterReport {
+            duplicate_names: names.inserted(),
+            filter_bits: names.bits(),
+            filter_hashes: names.hashes(),
+            false_positive_rate: names.false_positive_rate(),
+            mate_duplicate_records,
+        });
+        if mate_duplicate_records > 0 {
+            info!("{} 条记录本身没有duplicate标志，因mate为duplicate被排除", mate_duplicate_records);
+        }
+
+        if let Some(excluded) = &excluded {
+            debug!("被proper pair/最大插入片段条件排除的读对: {}", excluded.total());
+            for (label, count) in excluded.rows() {
+                debug!("  {}\t{}", label, count);
+            }
+        }
+        report.no_records = no_records;
+        report.excluded_distances = excluded;
+        report.past_contig_end = bounds.past_end();
+        report.unknown_read_groups = groups.unknown_read_groups().clone();
+        report.tandem_split = stats.tandem_split.as_ref().map(TandemSplit::report);
+        if let Some(split) = &report.tandem_split {
+            info!("TANDEM细分: ++ {}，-- {}，++/--比例 {}", split.ff_pairs, split.rr_pairs, OrNa(split.ff_rr_ratio.map(Rate)));
+        }
+        if let Some(largest) = &stats.largest {
+            report.largest_pairs = largest.to_sorted_vec();
+            for pair in &report.largest_pairs {
+                debug!("大插入片段读对 {}: tid={} pos={} tlen={}", pair.name, pair.tid, pair.pos, pair.tlen);
+            }
+        }
+        info!("处理完成：总记录数 {}，有效左端记录数 {}", report.processed_records, report.counted_records);
+        debug!("各条件排除的记录数：");
+        for line in report.breakdown.to_string().lines() {
+            debug!("  {}", line);
+        }
+        info!(
+            "TLEN符号: 正 {}，负 {}，零 {}",
+            report.tlen_positive, report.tlen_negative, report.tlen_zero
+        );
+        if report.tlen_imbalanced() {
+            warn!(
+                "TLEN正负数量失衡 {} > {}，只计左端记录可能低估读对数，请检查比对软件的TLEN符号约定",
+                Percent(report.tlen_imbalance()),
+                Percent(FilterReport::TLEN_IMBALANCE_WARN)
+            );
+        }
+
+        report.accumulation_level = accumulation_level;
+
+        // 使用 InsertSizeCalculator 来计算最终结果
+        let result = match InsertSizeCalculator::calculate(&stats, min_pct, orientation_pref, strategy) {
+            Err(InsertSizeError::NoValidReads { .. }) => {
+                return Err(InsertSizeError::NoValidReads { report: Some(Box::new(report)) });
+            }
+            result => result?,
+        };
+        for (key, group) in group_stats.iter().enumerate() {
+            if group.total_left_records == 0 {
+                continue;
+            }
+            let name = groups.name(accumulation_level, key).to_string();
+            let insert_size = match InsertSizeCalculator::calculate(group, min_pct, orientation_pref, strategy) {
+                Ok(v) => Some(v),
+                Err(e) => {
+                    warn!("组 {} 无法给出insert_size: {}", name, e);
+                    None
+                }
+            };
+            report.groups.push(GroupInsertSize {
+                group: name,
+                in_header: groups.in_header(accumulation_level, key),
+                pairs: group.total_left_records,
+                insert_size,
+            });
+        }
+    
+        for orientation in PairOrientation::ALL {
+            let histogram = Histogram::from_counts(&stats.histograms[&orientation]);
+            if !histogram.is_empty() {
+                report.histograms.push((orientation, histogram));
+            }
+        }
+
+        // 记录保留的类别信息
+        for orientation in PairOrientation::ALL {
+            let counts = &stats.histograms[&orientation];
+            let count: u32 = counts.values().sum();
+            if count == 0 {
+                continue;
+            }
+            let pct = count as f64 / stats.total_left_records as f64;
+            if pct >= min_pct {
+                info!("保留类别 {}: {} 个读对 ({})", orientation, count, Percent(pct));
+            } else {
+                warn!("丢弃类别 {}: {} 个读对 ({}) < {}", orientation, count, Percent(pct), Percent(min_pct));
+            }
+        }
+
+        match strategy {
+            Strategy::Specific => {
+                info!("使用指定方向 {} 的中位数: {}", orientation_pref, result);
+            }
+            Strategy::Dominant => {
+                // 找到最大类别（与InsertSizeCalculator::calculate的并列规则一致）
+                let kept: Vec<(PairOrientation, u32, ())> = PairOrientation::ALL
+                    .into_iter()
+                    .filter_map(|orientation| {
+                        let count: u32 = stats.histograms[&orientation].values().sum();
+                        let pct = count as f64 / stats.total_left_records as f64;
+                        (count > 0 && pct >= min_pct).then_some((orientation, count, ()))
+                    })
+                    .collect();
+
+                if !kept.is_empty() {
+                    let (best_orientation, _, _) = InsertSizeCalculator::dominant(&kept);
+                    info!("使用最大类别 {} 的中位数: {}", best_orientation, result);
+                }
+            }
+        }
+
+        Ok((result, report))
+    }
+}
+
+metric_keys! {
+    /// `insert-size`输出的键（见[`crate::metrics`]）。
+    InsertSizeKeys for "insert-size" {
+        SAMPLE = "sample", "样本名", Unit::Label, None, "头部@RG的SM；多样本BAM按样本分别输出时出现。";
+        PROCESSED_RECORDS = "processed_records", "读取记录数", Unit::Reads, None, "读取的记录总数。";
+        NO_RECORDS = "no_records", "输入为空", Unit::Boolean, None, "输入只有头部、没有任何记录时为true（此时以no_valid_reads错误、退出码3结束，错误的details.report仍含全0计数）；有记录时不输出。";
+        PROCESSED = "processed", "读取记录数", Unit::Reads, None, "文本过滤报告中的processed_records。";
+        TLEN_POSITIVE = "tlen_positive", "TLEN为正的候选记录数", Unit::Reads, None, "通过过滤条件且TLEN>0的记录数（左端记录）。";
+        TLEN_NEGATIVE = "tlen_negative", "TLEN为负的候选记录数", Unit::Reads, None, "通过过滤条件且TLEN<0的记录数（右端记录）。";
+        TLEN_ZERO = "tlen_zero", "TLEN为0的候选记录数", Unit::Reads, Some(false), "通过过滤条件但TLEN为0的记录数（mate在其它染色体或TLEN未设置）。";
+        TLEN_POS_NEG_RATIO = "tlen_pos_neg_ratio", "TLEN正负比", Unit::Ratio, None, "tlen_positive / tlen_negative，正常应接近1。";
+        TLEN_IMBALANCE = "tlen_imbalance", "TLEN正负失衡度", Unit::Fraction, Some(false), "|正 - 负| / (正 + 负)，超过5%时告警。";
+        COUNTED_RECORDS = "counted_records", "计入的左端记录数", Unit::ReadPairs, None, "最终计入插入片段直方图的左端记录数，每个读对计一次。";
+        COUNTED = "counted", "计入的左端记录数", Unit::ReadPairs, None, "文本过滤报告中的counted_records。";
+        PAST_CONTIG_END = "past_contig_end", "超出参考序列末端的记录数", Unit::Reads, Some(false), "比对终点超出参考序列长度的记录数。";
+        LOW_MAPQ_RECORDS = "low_mapq_records", "低MAPQ记录数", Unit::Reads, None, "--min-mapq：MAPQ低于阈值、在计入TLEN统计之前被跳过的记录数。";
+        BREAKDOWN = "breakdown", "各条件排除的记录数", Unit::Map, None, "每条记录归入第一个不满足的条件或counted，各项之和等于processed。";
+        REJECTED = "rejected", "各条件排除的记录数", Unit::Label, None, "文本过滤报告中的一行：条件与记录数（只列出不为0的条件）。";
+        LOW_MAPQ = "low_mapq", "低MAPQ记录数", Unit::Reads, None, "MAPQ低于--min-mapq的记录数。";
+        UNPAIRED = "unpaired", "非配对记录数", Unit::Reads, None, "不是配对测序（无0x1）的记录数。";
+        MATE_UNMAPPED = "mate_unmapped", "mate未比对的记录数", Unit::Reads, None, "本端已比对、mate未比对（标志或坐标）的记录数。";
+        CROSS_CHROMOSOME = "cross_chromosome", "跨染色体记录数", Unit::Reads, None, "两端比对到不同参考序列的记录数。";
+        IMPROPER_PAIR = "improper_pair", "非proper pair记录数", Unit::Reads, None, "--require-proper-pair：不是proper pair的记录数。";
+        ABOVE_MAX_INSERT_SIZE = "above_max_insert_size", "超过最大插入片段的记录数", Unit::Reads, None, "--max-insert-size：|TLEN|超过上限的记录数。";
+        NON_POSITIVE_TLEN = "non_positive_tlen", "TLEN不为正的记录数", Unit::Reads, None, "通过全部条件但TLEN ≤ 0的记录数，主要是读对的右端记录。";
+        LARGEST_PAIRS = "largest_pairs", "插入片段最大的读对", Unit::List, None, "按|TLEN|降序的读对列表（--report-largest）。";
+        LARGEST_PAIR = "largest_pair", "插入片段最大的读对", Unit::Label, None, "文本过滤报告中的一行：读名、tid、pos、tlen。";
+        TLEN = "tlen", "模板长度", Unit::BasePairs, None, "记录的TLEN字段。";
+        NAME = "name", "读名", Unit::Label, None, "记录的读名（QNAME）。";
+        TID = "tid", "参考序列编号", Unit::Label, None, "参考序列在头部中的下标（从0开始）。";
+        POS = "pos", "比对起点", Unit::BasePairs, None, "比对起点（0-based）。";
+        EXCLUDED_DISTANCES = "excluded_distances", "被排除读对的距离分布", Unit::Map, None, "仅因proper pair或最大插入片段条件被排除的读对按|TLEN|数量级的计数（--report-excluded-distances）。";
+        EXCLUDED_DISTANCE = "excluded_distance", "被排除读对的距离分布", Unit::Label, None, "文本过滤报告中的一行：距离档与读对数。";
+        ACCUMULATION_LEVEL = "accumulation_level", "累积层级", Unit::Label, None, "分组统计所用的层级：ALL_READS、SAMPLE、LIBRARY或READ_GROUP。";
+        GROUPS = "groups", "分组结果", Unit::List, None, "各组的插入片段大小（累积层级不为ALL_READS时）。";
+        GROUP = "group", "组名", Unit::Label, None, "读组、文库或样本名。";
+        IN_HEADER = "in_header", "组是否来自头部", Unit::Boolean, Some(true), "false表示组由头部未声明的读组或缺少RG标签的记录产生。";
+        PAIRS = "pairs", "读对数", Unit::ReadPairs, None, "该组计入的左端记录数。";
+        INSERT_SIZE = "insert_size", "插入片段大小", Unit::BasePairs, None, "按所选方向与策略得到的插入片段大小中位数。";
+        NOT_IN_HEADER = "not_in_header", "不在头部的组", Unit::Label, Some(false), "文本输出末尾的注释行：由未声明读组产生的组名。";
+        UNKNOWN_READ_GROUP_RECORDS = "unknown_read_group_records", "未声明读组的记录数", Unit::Reads, Some(false), "文本输出末尾的注释行：RG不在头部中的记录数。";
+        DUPLICATE_NAME_FILTER = "duplicate_name_filter", "duplicate读名过滤器", Unit::List, None, "按读对排除duplicate（--pair-duplicate-policy either-mate）时的读名布隆过滤器统计。";
+        DUPLICATE_NAMES = "duplicate_names", "duplicate读名数", Unit::Reads, None, "第一遍中带duplicate标志、读名插入过滤器的记录数。";
+        FILTER_BITS = "filter_bits", "过滤器位数", Unit::Count, None, "duplicate读名布隆过滤器的位数。";
+        FILTER_HASHES = "filter_hashes", "过滤器哈希函数数", Unit::Count, None, "duplicate读名布隆过滤器的哈希函数个数。";
+        FALSE_POSITIVE_RATE = "false_positive_rate", "过滤器假阳性率", Unit::Fraction, Some(false), "按插入数估计的布隆过滤器假阳性率，即非duplicate读对被误排除的概率。";
+        MATE_DUPLICATE_RECORDS = "mate_duplicate_records", "因mate为duplicate排除的记录数", Unit::Reads, None, "本身没有duplicate标志、因读名命中过滤器被排除的记录数（含假阳性）。";
+        TANDEM_SPLIT = "tandem_split", "TANDEM细分", Unit::Map, None, "TANDEM读对按++与--分开的读对数与比例（--tandem-split）；interim快照中为两个子直方图。";
+        FF_PAIRS = "ff_pairs", "++读对数", Unit::ReadPairs, None, "两端都为正链的TANDEM读对数。";
+        RR_PAIRS = "rr_pairs", "--读对数", Unit::ReadPairs, None, "两端都为负链的TANDEM读对数。";
+        FF_RR_RATIO = "ff_rr_ratio", "++/--比例", Unit::Ratio, None, "ff_pairs / rr_pairs；真正的串联方向文库应接近1，偏斜说明存在链特异的假象。没有--读对时为NA。";
+        TANDEM_FF_PAIRS = "tandem_ff_pairs", "++读对数", Unit::ReadPairs, None, "文本过滤报告中的ff_pairs。";
+        TANDEM_RR_PAIRS = "tandem_rr_pairs", "--读对数", Unit::ReadPairs, None, "文本过滤报告中的rr_pairs。";
+        TANDEM_FF_RR_RATIO = "tandem_ff_rr_ratio", "++/--比例", Unit::Ratio, None, "文本过滤报告中的ff_rr_ratio。";
+        FORWARD_FORWARD = "forward_forward", "++子直方图", Unit::Map, None, "interim快照中两端都为正链的TANDEM读对的插入片段计数。";
+        REVERSE_REVERSE = "reverse_reverse", "--子直方图", Unit::Map, None, "interim快照中两端都为负链的TANDEM读对的插入片段计数。";
+        MIN_PCT = "min_pct", "类别最小占比阈值", Unit::Fraction, None, "方向类别被阈值丢弃时JSON错误输出中的-M（闭区间，占比等于阈值的类别保留）。";
+        ERROR = "error", "错误信息", Unit::Label, None, "方向类别被阈值丢弃、无法给出insert_size时JSON错误输出中的错误信息。";
+        CATEGORIES = "categories", "方向类别占比", Unit::List, None, "方向类别被阈值丢弃时JSON错误输出中各非空方向的orientation、count与pct。";
+        COUNT = "count", "左端记录数", Unit::ReadPairs, None, "该方向计入的左端记录数。";
+        PCT = "pct", "方向占比", Unit::Fraction, None, "该方向占全部左端记录的比例。";
+        ORIENTATION = "orientation", "配对方向", Unit::Label, None, "直方图TSV中的配对方向：FR、RF或TANDEM。";
+        HISTOGRAMS = "histograms", "插入片段直方图", Unit::Map, None, "interim快照中各方向的插入片段计数（方向 -> 插入片段 -> 读对数）。";
+        TOTAL_LEFT_RECORDS = "total_left_records", "左端记录数", Unit::ReadPairs, None, "interim快照中已计入直方图的左端记录数。";
+    }
+}
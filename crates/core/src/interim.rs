@@ -0,0 +1,248 @@
+//! 长时间运行时的阶段性（interim）输出。
+//!
+//! 在记录循环中按时间间隔或记录数触发，将当前收集器状态序列化为JSON，
+//! 先写入临时文件再原子替换目标文件，便于外部监控指标的收敛过程。
+//! 运行正常结束时删除interim文件，由最终输出取代。
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+#[cfg(feature = "serde")]
+use serde::Serialize;
+use crate::log::{debug, warn};
+use crate::metrics::Unit;
+use crate::metrics_keys::metric_keys;
+
+/// interim输出的触发条件与目标文件。
+///
+/// 字符串形式为空格或逗号分隔的`key=value`：
+/// `every=10m file=interim.json`，也可用`records=1000000`按记录数触发。
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+/// use bamqc_core::InterimSpec;
+///
+/// let spec: InterimSpec = "every=10m file=interim.json".parse().unwrap();
+/// assert_eq!(spec.every, Some(Duration::from_secs(600)));
+/// assert_eq!(spec.records, None);
+///
+/// let spec: InterimSpec = "records=1000,file=x.json".parse().unwrap();
+/// assert_eq!(spec.records, Some(1000));
+/// assert!("every=10m".parse::<InterimSpec>().is_err());
+/// assert!("every=18446744073709551615h file=x".parse::<InterimSpec>().is_err());
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InterimSpec {
+    /// 按时间间隔触发。
+    pub every: Option<Duration>,
+    /// 按处理记录数触发（便于确定性测试）。
+    pub records: Option<u64>,
+    /// interim JSON文件路径。
+    pub file: PathBuf,
+}
+
+/// 解析时长：整数加可选单位`s`、`m`、`h`（默认秒），例如`10m`、`2h`。
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+/// use bamqc_core::parse_duration;
+///
+/// assert_eq!(parse_duration("2h"), Some(Duration::from_secs(7200)));
+/// assert_eq!(parse_duration("90"), Some(Duration::from_secs(90)));
+/// assert_eq!(parse_duration("5d"), None);
+/// ```
+pub fn parse_duration(s: &str) -> Option<Duration> {
+    let (value, unit) = match s.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => s.split_at(i),
+        None => (s, "s"),
+    };
+    let value: u64 = value.parse().ok()?;
+    let secs = match unit {
+        "s" => value,
+        "m" => value.checked_mul(60)?,
+        "h" => value.checked_mul(3600)?,
+        _ => return None,
+    };
+    Some(Duration::from_secs(secs))
+}
+
+impl FromStr for InterimSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut every = None;
+        let mut records = None;
+        let mut file = None;
+
+        for item in s.split([' ', ',']).filter(|item| !item.is_empty()) {
+            let (key, value) = item
+                .split_once('=')
+                .ok_or_else(|| format!("无效的interim参数: {}", item))?;
+            match key {
+                "every" => {
+                    every = Some(parse_duration(value).ok_or_else(|| format!("无效的时间间隔: {}", value))?)
+                }
+                "records" => {
+                    records = Some(
+                        value
+                            .parse::<u64>()
+                            .ok()
+                            .filter(|&n| n > 0)
+                            .ok_or_else(|| format!("无效的记录数: {}", value))?,
+                    )
+                }
+                "file" => file = Some(PathBuf::from(value)),
+                _ => return Err(format!("未知的interim参数: {}", key)),
+            }
+        }
+
+        let file = file.ok_or("interim输出需要指定file=<路径>")?;
+        if every.is_none() && records.is_none() {
+            return Err("interim输出需要指定every=<间隔>或records=<记录数>".to_string());
+        }
+
+        Ok(Self { every, records, file })
+    }
+}
+
+/// 可写入interim快照的收集器状态：启用`serde`特性时即`Serialize`。
+#[cfg(feature = "serde")]
+pub trait SnapshotState: Serialize {}
+
+#[cfg(feature = "serde")]
+impl<T: Serialize> SnapshotState for T {}
+
+/// 可写入interim快照的收集器状态：未启用`serde`特性时不做约束，快照只包含进度。
+#[cfg(not(feature = "serde"))]
+pub trait SnapshotState {}
+
+#[cfg(not(feature = "serde"))]
+impl<T> SnapshotState for T {}
+
+#[cfg(feature = "serde")]
+#[derive(Serialize)]
+struct Snapshot<'a, T: Serialize> {
+    partial: bool,
+    records_processed: u64,
+    elapsed_secs: f64,
+    metrics: &'a T,
+}
+
+/// interim输出器。
+#[derive(Debug)]
+pub struct InterimEmitter {
+    spec: InterimSpec,
+    started: Instant,
+    last_emit: Instant,
+    last_records: u64,
+    emitted: u64,
+}
+
+impl InterimEmitter {
+    pub fn new(spec: InterimSpec) -> Self {
+        let now = Instant::now();
+        Self {
+            spec,
+            started: now,
+            last_emit: now,
+            last_records: 0,
+            emitted: 0,
+        }
+    }
+
+    /// 已写出的interim快照数量。
+    pub fn emitted(&self) -> u64 {
+        self.emitted
+    }
+
+    /// 在记录循环中调用；满足触发条件时调用`snapshot`取得收集器状态并写出快照。
+    ///
+    /// 未触发时只做一次计数比较（时间触发每4096条记录才读取一次时钟），
+    /// `snapshot`也不会被调用，不影响记录循环的性能。写出失败只记录警告，不中断运行。
+    pub fn tick<T, F>(&mut self, records_processed: u64, snapshot: F)
+    where
+        T: SnapshotState,
+        F: FnOnce() -> T,
+    {
+        let by_records = self
+            .spec
+            .records
+            .is_some_and(|n| records_processed - self.last_records >= n);
+        let by_time = !by_records
+            && records_processed.is_multiple_of(4096)
+            && self.spec.every.is_some_and(|every| self.last_emit.elapsed() >= every);
+
+        if by_records || by_time {
+            if let Err(e) = self.write(records_processed, &snapshot()) {
+                warn!("写入interim文件失败 {}: {}", self.spec.file.display(), e);
+            }
+            self.last_emit = Instant::now();
+            self.last_records = records_processed;
+        }
+    }
+
+    fn write<T: SnapshotState>(&mut self, records_processed: u64, metrics: &T) -> std::io::Result<()> {
+        let elapsed_secs = self.started.elapsed().as_secs_f64();
+        let content = render(records_processed, elapsed_secs, metrics)?;
+
+        // 先写临时文件再重命名，保证读取方不会看到写了一半的文件
+        let tmp = tmp_path(&self.spec.file);
+        fs::write(&tmp, content)?;
+        fs::rename(&tmp, &self.spec.file)?;
+
+        self.emitted += 1;
+        debug!("已写出interim快照 #{}: {} 条记录", self.emitted, records_processed);
+        Ok(())
+    }
+
+    /// 运行结束：删除interim文件。
+    pub fn finish(self) {
+        if self.spec.file.exists() {
+            if let Err(e) = fs::remove_file(&self.spec.file) {
+                warn!("删除interim文件失败 {}: {}", self.spec.file.display(), e);
+            }
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+fn render<T: Serialize>(records_processed: u64, elapsed_secs: f64, metrics: &T) -> std::io::Result<Vec<u8>> {
+    let snapshot = Snapshot {
+        partial: true,
+        records_processed,
+        elapsed_secs,
+        metrics,
+    };
+    Ok(serde_json::to_vec_pretty(&snapshot)?)
+}
+
+/// 未启用`serde`特性时，快照只包含进度信息，不含收集器状态。
+#[cfg(not(feature = "serde"))]
+fn render<T>(records_processed: u64, elapsed_secs: f64, _metrics: &T) -> std::io::Result<Vec<u8>> {
+    Ok(format!(
+        "{{\n  \"partial\": true,\n  \"records_processed\": {},\n  \"elapsed_secs\": {}\n}}",
+        records_processed, elapsed_secs
+    )
+    .into_bytes())
+}
+
+fn tmp_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".tmp");
+    path.with_file_name(name)
+}
+
+metric_keys! {
+    /// `interim`输出的键（见[`crate::metrics`]）。
+    InterimKeys for "interim" {
+        PARTIAL = "partial", "部分结果", Unit::Boolean, None, "interim快照总是为true。";
+        RECORDS_PROCESSED = "records_processed", "已处理记录数", Unit::Reads, None, "写出快照时已处理的记录数。";
+        ELAPSED_SECS = "elapsed_secs", "已用时间", Unit::Seconds, None, "写出快照时的运行时间。";
+        METRICS = "metrics", "收集器状态", Unit::List, None, "当前收集器状态，键由各收集器登记。";
+    }
+}
@@ -0,0 +1,499 @@
+//! 由靶区富集模式推断捕获试剂盒（exome kit）。
+//!
+//! 没有元数据说明所用试剂盒的BAM，可以与一组候选试剂盒的BED比较：靶向捕获的
+//! 读长集中在试剂盒的靶区上，真正使用的试剂盒应当明显富集。
+//!
+//! # 评分
+//!
+//! 先从BAM中抽取读长位置样本：primary、已比对、非duplicate、未QC失败的读长，
+//! 取比对区间的中点；`subsample`小于1时每条读长以该概率保留（随机数来自
+//! [`KIT_DETECT_SEED_COMPONENT`]派生的子种子），保留`stop_after`条后停止
+//! （为0时读完整个文件）。坐标排序的BAM只取前若干条会集中在前几条染色体上，
+//! 此时应同时使用`subsample`把样本分散到全基因组。
+//!
+//! 对每个试剂盒（BED中重叠的区间先合并）：
+//!
+//! * `on_target_fraction = on_target_reads / sampled_reads`：中点落在靶区内的样本占比；
+//! * `footprint_fraction = footprint_bases / genome_length`：靶区占头部中参考序列总长的比例，
+//!   即读长均匀分布（没有富集）时期望的`on_target_fraction`；
+//! * `enrichment = on_target_fraction / footprint_fraction`：相对均匀背景的富集倍数，
+//!   背景为1。
+//!
+//! 按`enrichment`从高到低排名。最佳试剂盒的置信差距
+//! `margin = log2(enrichment₁ / max(enrichment₂, 1))`：与第二名（第二名低于背景时与背景）
+//! 相比高出的倍数的log2。最佳试剂盒的`enrichment`低于`min_enrichment`时认为
+//! 没有试剂盒明显高于背景，`confident`为false并给出警告。
+//!
+//! 读长位置样本只抽取一次并按参考序列排序缓存；试剂盒逐个加载、评分后即释放，
+//! 内存只取决于样本大小与最大的单个试剂盒。
+
+use std::fmt;
+use std::path::Path;
+use bamqc_io::bam::{BamError, BamReader, BamReaderOptions, BamRecord};
+use bamqc_io::region::{read_bed, GenomicRegion};
+#[cfg(feature = "serde")]
+use bamqc_io::serialize_error;
+use bamqc_io::ErrorCode;
+#[cfg(feature = "serde")]
+use serde::Serialize;
+use thiserror::Error;
+use crate::filter::ReadFilter;
+use crate::formatting::{OrNa, Rate};
+use crate::log::{info, warn};
+use crate::metrics::Unit;
+use crate::metrics_keys::metric_keys;
+use crate::rng::{SplitMix64, DEFAULT_SEED};
+
+/// 默认抽取的读长数（`--stop-after`）。
+pub const DEFAULT_KIT_SAMPLE_READS: u64 = 100_000;
+
+/// 最佳试剂盒的富集倍数低于该值时认为没有明显高于背景（`--min-enrichment`）。
+pub const DEFAULT_MIN_ENRICHMENT: f64 = 5.0;
+
+/// 读长抽样使用的随机数组件名（见[`crate::rng`]）。
+pub const KIT_DETECT_SEED_COMPONENT: &str = "detect-kit";
+
+/// 试剂盒推断过程中可能发生的错误。
+///
+/// ```
+/// # #[cfg(feature = "serde")]
+/// # {
+/// use bamqc_core::KitDetectError;
+///
+/// let e = KitDetectError::NoKits { path: "kits".into() };
+/// assert_eq!(serde_json::to_string(&e).unwrap(), r#"{"code":"no_kits","message":"试剂盒目录中没有BED文件: kits","details":{"path":"kits"}}"#);
+/// # }
+/// ```
+#[derive(Error, Debug)]
+pub enum KitDetectError {
+    /// 试剂盒目录中没有`.bed`文件。
+    #[error("试剂盒目录中没有BED文件: {path}")]
+    NoKits {
+        /// 试剂盒目录路径
+        path: String,
+    },
+
+    /// BAM或BED文件IO错误。
+    #[error("BAM文件IO错误: {0}")]
+    BamError(#[from] BamError),
+}
+
+impl ErrorCode for KitDetectError {
+    fn code(&self) -> &'static str {
+        match self {
+            KitDetectError::NoKits { .. } => "no_kits",
+            KitDetectError::BamError(e) => e.code(),
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    fn serialize_details<M: serde::ser::SerializeMap>(&self, map: &mut M) -> Result<(), M::Error> {
+        match self {
+            KitDetectError::NoKits { path } => map.serialize_entry("path", path),
+            KitDetectError::BamError(e) => e.serialize_details(map),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for KitDetectError {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_error(self, serializer)
+    }
+}
+
+/// 试剂盒推断的参数。
+#[derive(Clone, Debug)]
+pub struct KitDetectOptions {
+    /// 保留这么多条读长后停止抽样；为0时读完整个文件。
+    pub stop_after: u64,
+    /// 每条符合条件的读长被保留的概率（0到1）。
+    pub subsample: f64,
+    /// 最佳试剂盒的富集倍数低于该值时`confident`为false。
+    pub min_enrichment: f64,
+    /// 根种子（见[`crate::rng`]）。
+    pub seed: u64,
+    /// 打开输入的读取器配置（解压线程数、输入格式、CRAM参考序列等）。
+    pub reader: BamReaderOptions,
+}
+
+impl Default for KitDetectOptions {
+    fn default() -> Self {
+        Self {
+            stop_after: DEFAULT_KIT_SAMPLE_READS,
+            subsample: 1.0,
+            min_enrichment: DEFAULT_MIN_ENRICHMENT,
+            seed: DEFAULT_SEED,
+            reader: BamReaderOptions::new(),
+        }
+    }
+}
+
+/// 缓存的读长位置样本：每条参考序列一组排好序的比对中点（0-based）。
+#[derive(Clone, Debug)]
+pub struct ReadPositionSample {
+    contigs: Vec<(String, u64)>,
+    positions: Vec<Vec<u64>>,
+    reads: u64,
+}
+
+impl ReadPositionSample {
+    /// 按[模块文档](self)中的规则从BAM中抽取样本。
+    pub fn collect(reader: &mut BamReader, options: &KitDetectOptions) -> Result<Self, BamError> {
+        let contigs: Vec<(String, u64)> = reader.references().map(|(name, length)| (name.to_string(), length)).collect();
+        let mut positions = vec![Vec::new(); contigs.len()];
+        let mut rng = SplitMix64::for_component(options.seed, KIT_DETECT_SEED_COMPONENT);
+        let mut reads = 0;
+        for result in reader.records() {
+            let record = result?;
+            if !is_sampled(&record) {
+                continue;
+            }
+            if options.subsample < 1.0 && rng.next_f64() >= options.subsample {
+                continue;
+            }
+            let Some(contig) = positions.get_mut(record.tid() as usize) else {
+                continue;
+            };
+            let (start, end) = (record.pos() as u64, record.reference_end() as u64);
+            contig.push(start + (end - start - 1) / 2);
+            reads += 1;
+            if reads == options.stop_after {
+                break;
+            }
+        }
+        for contig in &mut positions {
+            contig.sort_unstable();
+        }
+        Ok(Self { contigs, positions, reads })
+    }
+
+    /// 样本中的读长数。
+    pub fn reads(&self) -> u64 {
+        self.reads
+    }
+
+    /// 头部中参考序列的总长度。
+    pub fn genome_length(&self) -> u64 {
+        self.contigs.iter().map(|(_, length)| length).sum()
+    }
+
+    /// 用一个试剂盒的靶区为样本评分；不在头部中的参考序列上的靶区被忽略并返回其名称。
+    pub fn score(&self, kit: &str, regions: &[GenomicRegion]) -> (KitScore, Vec<String>) {
+        let mut intervals: Vec<Vec<(u64, u64)>> = vec![Vec::new(); self.contigs.len()];
+        let mut unresolved: Vec<String> = Vec::new();
+        for region in regions {
+            match self.contigs.iter().position(|(name, _)| *name == region.name) {
+                Some(tid) => {
+                    let end = region.end.map_or(self.contigs[tid].1, |end| end as u64);
+                    intervals[tid].push((region.start as u64 - 1, end));
+                }
+                None if !unresolved.contains(&region.name) => unresolved.push(region.name.clone()),
+                None => {}
+            }
+        }
+
+        let (mut resolved, mut footprint_bases, mut on_target_reads) = (0, 0, 0);
+        for (contig, positions) in intervals.iter_mut().zip(&self.positions) {
+            resolved += contig.len() as u64;
+            let merged = merge_intervals(contig);
+            footprint_bases += merged.iter().map(|(start, end)| end - start).sum::<u64>();
+            on_target_reads += positions
+                .iter()
+                .filter(|&&pos| {
+                    let i = merged.partition_point(|&(start, _)| start <= pos);
+                    i > 0 && pos < merged[i - 1].1
+                })
+                .count() as u64;
+        }
+        let score = KitScore::new(kit, resolved, footprint_bases, on_target_reads, self.reads, self.genome_length());
+        (score, unresolved)
+    }
+}
+
+fn is_sampled(record: &BamRecord) -> bool {
+    ReadFilter::default().passes(record) && record.pos() >= 0
+}
+
+/// 排序并合并重叠或相邻的半开区间。
+fn merge_intervals(intervals: &mut [(u64, u64)]) -> Vec<(u64, u64)> {
+    intervals.sort_unstable();
+    let mut merged: Vec<(u64, u64)> = Vec::with_capacity(intervals.len());
+    for &(start, end) in intervals.iter() {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
+
+/// 一个试剂盒的评分。
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct KitScore {
+    /// 试剂盒名称（BED文件名去掉`.bed`）。
+    pub kit: String,
+    /// 位于头部中参考序列上的BED区间数。
+    pub intervals: u64,
+    /// 合并重叠区间后的靶区总长度。
+    pub footprint_bases: u64,
+    /// 中点落在靶区内的样本读长数。
+    pub on_target_reads: u64,
+    /// on_target_reads / sampled_reads；没有样本时为0。
+    pub on_target_fraction: f64,
+    /// footprint_bases / genome_length。
+    pub footprint_fraction: f64,
+    /// on_target_fraction / footprint_fraction；没有样本或靶区时为None。
+    pub enrichment: Option<f64>,
+}
+
+impl KitScore {
+    /// 由计数计算比例与富集倍数。
+    pub fn new(
+        kit: &str,
+        intervals: u64,
+        footprint_bases: u64,
+        on_target_reads: u64,
+        sampled_reads: u64,
+        genome_length: u64,
+    ) -> Self {
+        let ratio = |a: u64, b: u64| if b == 0 { 0.0 } else { a as f64 / b as f64 };
+        let on_target_fraction = ratio(on_target_reads, sampled_reads);
+        let footprint_fraction = ratio(footprint_bases, genome_length);
+        let enrichment = (sampled_reads > 0 && footprint_fraction > 0.0).then(|| on_target_fraction / footprint_fraction);
+        Self {
+            kit: kit.to_string(),
+            intervals,
+            footprint_bases,
+            on_target_reads,
+            on_target_fraction,
+            footprint_fraction,
+            enrichment,
+        }
+    }
+}
+
+/// 试剂盒推断的报告。
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct KitDetection {
+    /// 样本中的读长数。
+    pub sampled_reads: u64,
+    /// 头部中参考序列的总长度。
+    pub genome_length: u64,
+    /// 判定明显高于背景的最低富集倍数。
+    pub min_enrichment: f64,
+    /// 富集倍数最高的试剂盒；没有可评分的试剂盒时为None。
+    pub best_kit: Option<String>,
+    /// log2(enrichment₁ / max(enrichment₂, 1))。
+    pub margin: Option<f64>,
+    /// 最佳试剂盒的富集倍数是否达到min_enrichment。
+    pub confident: bool,
+    /// 按富集倍数从高到低排列（无法评分的在最后，并列时按名称）。
+    pub kits: Vec<KitScore>,
+}
+
+impl KitDetection {
+    /// 对各试剂盒的评分排名并计算置信差距。
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bamqc_core::{KitDetection, KitScore};
+    ///
+    /// // 1000条样本读长，基因组100kb
+    /// let scores = vec![
+    ///     KitScore::new("b", 10, 1_000, 200, 1000, 100_000),
+    ///     KitScore::new("a", 10, 1_000, 800, 1000, 100_000),
+    ///     KitScore::new("empty", 0, 0, 0, 1000, 100_000),
+    /// ];
+    /// let detection = KitDetection::new(1000, 100_000, scores, 5.0);
+    /// let ranked: Vec<&str> = detection.kits.iter().map(|k| k.kit.as_str()).collect();
+    /// assert_eq!(ranked, ["a", "b", "empty"]);
+    /// assert_eq!(detection.kits[0].enrichment, Some(80.0));
+    /// assert_eq!(detection.best_kit.as_deref(), Some("a"));
+    /// assert_eq!(detection.margin, Some(2.0)); // log2(80 / 20)
+    /// assert!(detection.confident);
+    ///
+    /// // 读长在各试剂盒上都只有背景水平
+    /// let scores = vec![KitScore::new("a", 10, 1_000, 12, 1000, 100_000)];
+    /// let detection = KitDetection::new(1000, 100_000, scores, 5.0);
+    /// assert_eq!(detection.best_kit.as_deref(), Some("a"));
+    /// assert!(!detection.confident);
+    /// ```
+    pub fn new(sampled_reads: u64, genome_length: u64, mut kits: Vec<KitScore>, min_enrichment: f64) -> Self {
+        kits.sort_by(|a, b| {
+            let key = |k: &KitScore| k.enrichment.unwrap_or(f64::NEG_INFINITY);
+            key(b).total_cmp(&key(a)).then_with(|| a.kit.cmp(&b.kit))
+        });
+        let best = kits.first().and_then(|k| k.enrichment.map(|e| (k.kit.clone(), e)));
+        let runner_up = kits.get(1).and_then(|k| k.enrichment).unwrap_or(0.0);
+        let margin = best.as_ref().map(|(_, e)| (e / runner_up.max(1.0)).log2());
+        let confident = best.as_ref().is_some_and(|&(_, e)| e >= min_enrichment);
+        Self {
+            sampled_reads,
+            genome_length,
+            min_enrichment,
+            best_kit: best.map(|(kit, _)| kit),
+            margin,
+            confident,
+            kits,
+        }
+    }
+}
+
+impl fmt::Display for KitDetection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "# sampled_reads: {}", self.sampled_reads)?;
+        writeln!(f, "# genome_length: {}", self.genome_length)?;
+        writeln!(f, "# min_enrichment: {}", Rate(self.min_enrichment))?;
+        writeln!(f, "# best_kit: {}", OrNa(self.best_kit.as_deref()))?;
+        writeln!(f, "# margin: {}", OrNa(self.margin.map(Rate)))?;
+        writeln!(f, "# confident: {}", self.confident)?;
+        write!(f, "rank\tkit\tintervals\tfootprint_bases\ton_target_reads\ton_target_fraction\tfootprint_fraction\tenrichment")?;
+        for (i, k) in self.kits.iter().enumerate() {
+            write!(
+                f,
+                "\n{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                i + 1,
+                k.kit,
+                k.intervals,
+                k.footprint_bases,
+                k.on_target_reads,
+                Rate(k.on_target_fraction),
+                Rate(k.footprint_fraction),
+                OrNa(k.enrichment.map(Rate))
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// 试剂盒目录中的BED文件（扩展名`.bed`），按文件名排序。
+fn kit_beds(kit_library: &str) -> Result<Vec<(String, std::path::PathBuf)>, KitDetectError> {
+    if !Path::new(kit_library).is_dir() {
+        return Err(BamError::FileNotFound { path: kit_library.to_string() }.into());
+    }
+    let mut beds = Vec::new();
+    for entry in std::fs::read_dir(kit_library).map_err(BamError::from)? {
+        let path = entry.map_err(BamError::from)?.path();
+        if path.is_file() && path.extension().is_some_and(|ext| ext == "bed") {
+            let name = path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+            beds.push((name, path));
+        }
+    }
+    if beds.is_empty() {
+        return Err(KitDetectError::NoKits { path: kit_library.to_string() });
+    }
+    beds.sort();
+    Ok(beds)
+}
+
+/// 从`kit_library`目录中的候选试剂盒BED推断BAM所用的捕获试剂盒。
+///
+/// # Examples
+///
+/// 两个互不重叠的合成试剂盒，读长全部来自试剂盒A的靶区：
+///
+/// ```
+/// use bamqc_core::{detect_kit, KitDetectOptions};
+/// use bamqc_io::bam::BamWriter;
+/// use noodles::core::Position;
+/// use noodles::sam::alignment::record::cigar::op::{Kind, Op};
+/// use noodles::sam::alignment::record::Flags;
+/// use noodles::sam::alignment::RecordBuf;
+///
+/// let dir = std::env::temp_dir().join(format!("bamqc-detect-kit-{}", std::process::id()));
+/// let kits = dir.join("kits");
+/// std::fs::create_dir_all(&kits).unwrap();
+/// // 试剂盒A的靶区在每个10kb的前100bp，B的靶区在中间的100bp
+/// let bed = |offset: usize| (0..10).map(|i| format!("c1\t{}\t{}\n", i * 10_000 + offset, i * 10_000 + offset + 100)).collect::<String>();
+/// std::fs::write(kits.join("kit_a.bed"), bed(0)).unwrap();
+/// std::fs::write(kits.join("kit_b.bed"), bed(5_000)).unwrap();
+/// std::fs::write(kits.join("README.txt"), "not a kit").unwrap();
+///
+/// let bam = dir.join("sample.bam").to_string_lossy().to_string();
+/// let header = "@HD\tVN:1.6\tSO:coordinate\n@SQ\tSN:c1\tLN:100000\n".parse().unwrap();
+/// let mut writer = BamWriter::from_path(&bam, &header).unwrap();
+/// for i in 0..10 {
+///     for j in 0..20 {
+///         let record = RecordBuf::builder()
+///             .set_name(format!("t{}r{}", i, j))
+///             .set_flags(Flags::empty())
+///             .set_reference_sequence_id(0)
+///             .set_alignment_start(Position::try_from(i * 10_000 + j + 1).unwrap())
+///             .set_mapping_quality(60.try_into().unwrap())
+///             .set_cigar([Op::new(Kind::Match, 50)].into_iter().collect())
+///             .build();
+///         writer.write_record_buf(&record).unwrap();
+///     }
+/// }
+/// writer.finish().unwrap();
+///
+/// let detection = detect_kit(&bam, &kits.to_string_lossy(), &KitDetectOptions::default()).unwrap();
+/// assert_eq!(detection.sampled_reads, 200);
+/// assert_eq!(detection.best_kit.as_deref(), Some("kit_a"));
+/// assert!(detection.confident);
+/// // 全部样本都在A的1%基因组上：富集100倍；B没有样本，与背景比较
+/// assert_eq!(detection.kits[0].enrichment, Some(100.0));
+/// assert_eq!(detection.kits[1].enrichment, Some(0.0));
+/// assert_eq!(detection.margin, Some(100f64.log2()));
+/// assert!(detection.to_string().lines().any(|line| line == "1\tkit_a\t10\t1000\t200\t1.0000\t0.0100\t100.0000"));
+///
+/// // 只抽取一半的读长
+/// let options = KitDetectOptions { stop_after: 100, ..Default::default() };
+/// assert_eq!(detect_kit(&bam, &kits.to_string_lossy(), &options).unwrap().sampled_reads, 100);
+/// std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+pub fn detect_kit(bam_path: &str, kit_library: &str, options: &KitDetectOptions) -> Result<KitDetection, KitDetectError> {
+    let beds = kit_beds(kit_library)?;
+    let mut reader = options.reader.open(bam_path)?;
+    info!("抽取读长位置样本: {}", bam_path);
+    let sample = ReadPositionSample::collect(&mut reader, options)?;
+    if sample.reads() == 0 {
+        warn!("没有可用于推断试剂盒的读长: {}", bam_path);
+    }
+
+    let mut scores = Vec::with_capacity(beds.len());
+    for (name, path) in &beds {
+        let regions = read_bed(path)?;
+        let (score, unresolved) = sample.score(name, &regions);
+        if !unresolved.is_empty() {
+            warn!("试剂盒{}中以下参考序列不在BAM头部中，已忽略: {}", name, unresolved.join(", "));
+        }
+        scores.push(score);
+    }
+
+    let detection = KitDetection::new(sample.reads(), sample.genome_length(), scores, options.min_enrichment);
+    match (&detection.best_kit, detection.margin) {
+        (Some(kit), Some(margin)) if detection.confident => {
+            info!("{} 个候选试剂盒中最匹配的是 {}（margin {}）", beds.len(), kit, Rate(margin));
+        }
+        _ => warn!(
+            "没有试剂盒的富集倍数明显高于背景（最低 {}）",
+            Rate(options.min_enrichment)
+        ),
+    }
+    Ok(detection)
+}
+
+metric_keys! {
+    /// `detect-kit`输出的键（见[`crate::metrics`]）。
+    KitDetectionKeys for "detect-kit" {
+        SAMPLED_READS = "sampled_reads", "样本读长数", Unit::Reads, None, "抽样得到的primary、已比对、非duplicate读长数（受--stop-after与--subsample控制）。";
+        GENOME_LENGTH = "genome_length", "基因组长度", Unit::BasePairs, None, "头部中参考序列的总长度。";
+        MIN_ENRICHMENT = "min_enrichment", "最低富集倍数", Unit::Ratio, None, "--min-enrichment：最佳试剂盒的富集倍数低于该值时confident为false。";
+        BEST_KIT = "best_kit", "最佳试剂盒", Unit::Label, None, "富集倍数最高的试剂盒；没有可评分的试剂盒时为NA。";
+        MARGIN = "margin", "置信差距", Unit::Ratio, Some(true), "log2(第一名富集倍数 / max(第二名富集倍数, 1))。";
+        CONFIDENT = "confident", "明显高于背景", Unit::Boolean, Some(true), "最佳试剂盒的富集倍数是否达到min_enrichment。";
+        KITS = "kits", "候选试剂盒", Unit::List, None, "每个试剂盒一行，按富集倍数从高到低排列。";
+        KIT = "kit", "试剂盒", Unit::Label, None, "BED文件名去掉.bed。";
+        INTERVALS = "intervals", "区间数", Unit::Count, None, "位于头部中参考序列上的BED区间数。";
+        FOOTPRINT_BASES = "footprint_bases", "靶区长度", Unit::BasePairs, None, "合并重叠区间后的靶区总长度。";
+        ON_TARGET_READS = "on_target_reads", "靶区内读长数", Unit::Reads, None, "比对中点落在靶区内的样本读长数。";
+        ON_TARGET_FRACTION = "on_target_fraction", "靶区内读长占比", Unit::Fraction, None, "on_target_reads / sampled_reads。";
+        FOOTPRINT_FRACTION = "footprint_fraction", "靶区占基因组比例", Unit::Fraction, None, "footprint_bases / genome_length，即没有富集时的期望on_target_fraction。";
+        ENRICHMENT = "enrichment", "富集倍数", Unit::Ratio, Some(true), "on_target_fraction / footprint_fraction；背景为1，没有样本或靶区时为NA。";
+    }
+}
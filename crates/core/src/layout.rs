@@ -0,0 +1,287 @@
+//! 输出目录约定。
+//!
+//! 指定`--out-dir`时，每个子命令把结果写到目录中的标准文件名（见
+//! [`OUTPUT_FILES`]，也可以用`bamqc metrics --files`查看），同一目录可以
+//! 依次运行多个子命令收集一个样本的全部指标。显式给出的输出路径（`-o`、
+//! `--filter-report`等）优先于目录约定。
+//!
+//! 为避免混入上一次运行的结果，子命令的标准文件（含按样本输出的
+//! `<名称>.<样本>.<扩展名>`）已经存在时拒绝运行；`--force`时先删除这些
+//! 文件再写出。
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+#[cfg(feature = "serde")]
+use serde::Serialize;
+use thiserror::Error;
+#[cfg(feature = "serde")]
+use bamqc_io::{io_error_kind, serialize_error};
+use bamqc_io::ErrorCode;
+
+/// 一个子命令输出的标准文件名。
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct OutputFile {
+    /// 子命令名。
+    pub collector: &'static str,
+    /// 输出的角色：`main`为主输出，其余为附加输出。
+    pub role: &'static str,
+    /// 文本格式的文件名。
+    pub text: &'static str,
+    /// JSON格式的文件名；没有JSON格式时为None。
+    pub json: Option<&'static str>,
+    /// 内容说明。
+    pub description: &'static str,
+}
+
+impl OutputFile {
+    /// TSV表头，与[`OutputFile::tsv_row`]对应。
+    pub const TSV_HEADER: &'static str = "collector\trole\ttext\tjson\tdescription";
+
+    /// TSV行。
+    pub fn tsv_row(&self) -> String {
+        format!(
+            "{}\t{}\t{}\t{}\t{}",
+            self.collector,
+            self.role,
+            self.text,
+            self.json.unwrap_or("NA"),
+            self.description
+        )
+    }
+
+    /// 按格式选择文件名；没有JSON格式时总是使用文本文件名。
+    pub fn file_name(&self, json: bool) -> &'static str {
+        match self.json {
+            Some(name) if json => name,
+            _ => self.text,
+        }
+    }
+}
+
+const fn output_file(
+    collector: &'static str,
+    role: &'static str,
+    text: &'static str,
+    json: Option<&'static str>,
+    description: &'static str,
+) -> OutputFile {
+    OutputFile {
+        collector,
+        role,
+        text,
+        json,
+        description,
+    }
+}
+
+/// 各子命令的标准文件名，按子命令顺序排列。
+pub const OUTPUT_FILES: &[OutputFile] = &[
+    output_file("insert-size", "main", "insert_size_metrics.tsv", None, "插入片段大小中位数，分组时为group、pairs、insert_size表格。"),
+    output_file("insert-size", "filter_report", "insert_size_filter_report.txt", Some("insert_size_filter_report.json"), "过滤统计报告（TLEN符号计数等）。"),
+    output_file("insert-size", "histogram", "insert_size_histogram.tsv", None, "插入片段直方图（orientation、bin_start、bin_end、count）。"),
+    output_file("barcodes", "main", "barcodes.txt", None, "条形码分布汇总。"),
+    output_file("flagstat", "main", "flagstat.txt", None, "FLAG统计。"),
+    output_file("diff-header", "main", "header_diff.txt", Some("header_diff.json"), "两个头部的差异。"),
+    output_file("duplication", "main", "duplication_metrics.txt", Some("duplication_metrics.json"), "按文库的重复率与估计文库大小。"),
+    output_file("dup-sets", "main", "duplicate_set_sizes.txt", Some("duplicate_set_sizes.json"), "重复集合大小的分布与由其估计的文库大小。"),
+    output_file("targets", "main", "target_metrics.tsv", Some("target_metrics.json"), "逐靶区指标。"),
+    output_file("gc-dup", "main", "gc_dup_metrics.tsv", Some("gc_dup_metrics.json"), "按GC bin的重复率与线性拟合。"),
+    output_file("idxstats", "main", "idxstats.tsv", None, "每条参考序列的已比对与未比对记录数。"),
+    output_file("quality-yield", "main", "quality_yield_metrics.txt", Some("quality_yield_metrics.json"), "碱基质量产出。"),
+    output_file("homopolymer-indels", "main", "homopolymer_indels.tsv", Some("homopolymer_indels.json"), "按同聚物长度分层的插入缺失计数与每千碱基比率。"),
+    output_file("clipping", "main", "clipping_by_cycle.tsv", Some("clipping_by_cycle.json"), "逐测序循环的5'/3'端soft clip率（read1/read2）与疑似接头读穿的循环。"),
+    output_file("coverage", "main", "depth_windows.wig", None, "逐窗口平均深度（wiggle fixedStep）。"),
+    output_file("coverage", "tsv", "depth_windows.tsv", None, "逐窗口平均深度（contig、start、end、length、mean_depth）。"),
+    output_file("coverage", "arms", "chromosome_arms.tsv", None, "按cytoBand染色体臂汇总的深度、插入片段中位数与偏离标记。"),
+    output_file("coverage-compare", "main", "coverage_compare.tsv", Some("coverage_compare.json"), "两个BAM逐靶区归一化深度的log2比值与gain/loss标记。"),
+    output_file("detect-kit", "main", "kit_detection.tsv", Some("kit_detection.json"), "候选捕获试剂盒按靶区富集倍数的排名与置信差距。"),
+    output_file("tag-stats", "main", "tag_stats.txt", Some("tag_stats.json"), "标签值统计。"),
+    output_file("tag-stats", "histogram", "tag_stats_histogram.tsv", None, "标签值直方图。"),
+    output_file("quick-check", "main", "quick_check.txt", Some("quick_check.json"), "快速完整性检查结果。"),
+    output_file("validate", "main", "flag_validation.txt", Some("flag_validation.json"), "FLAG矛盾组合的记录数与FLAG分布。"),
+    output_file("lims", "summary", "summary.json", None, "LIMS交付：带模式版本与判定的汇总JSON。"),
+    output_file("lims", "metrics", "metrics.tsv", None, "LIMS交付：扁平的key、value指标表。"),
+    output_file("lims", "histograms", "histograms.tsv", None, "LIMS交付：长格式直方图（sample、collector、histogram、bin_start、bin_end、value）。"),
+    output_file("lims", "report", "report.html", None, "LIMS交付：单页HTML报告。"),
+];
+
+/// 查找子命令某个角色的标准文件名。
+pub fn lookup_output_file(collector: &str, role: &str) -> Option<&'static OutputFile> {
+    OUTPUT_FILES.iter().find(|file| file.collector == collector && file.role == role)
+}
+
+/// 输出目录错误。
+///
+/// 错误代码与JSON形式见[`bamqc_io::error_code`]：
+///
+/// ```
+/// # #[cfg(feature = "serde")]
+/// # {
+/// use bamqc_core::LayoutError;
+///
+/// let e = LayoutError::Io { path: "out".into(), source: std::io::ErrorKind::PermissionDenied.into() };
+/// let json = serde_json::to_value(&e).unwrap();
+/// assert_eq!(json["code"], "output_io");
+/// assert_eq!(json["details"], serde_json::json!({"path": "out", "kind": "PermissionDenied"}));
+/// let e = LayoutError::Exists { dir: "out".into(), files: vec!["a.txt".into(), "b.json".into()] };
+/// assert_eq!(
+///     serde_json::to_string(&e).unwrap(),
+///     r#"{"code":"output_exists","message":"输出目录 out 中已有 a.txt, b.json（使用--force覆盖）","details":{"dir":"out","files":["a.txt","b.json"]}}"#
+/// );
+/// # }
+/// ```
+#[derive(Error, Debug)]
+pub enum LayoutError {
+    /// 创建目录或删除旧文件失败。
+    #[error("{path}: {source}")]
+    Io {
+        path: String,
+        #[source]
+        source: io::Error,
+    },
+
+    /// 标准文件已存在且没有指定`--force`。
+    #[error("输出目录 {dir} 中已有 {}（使用--force覆盖）", .files.join(", "))]
+    Exists { dir: String, files: Vec<String> },
+}
+
+impl ErrorCode for LayoutError {
+    fn code(&self) -> &'static str {
+        match self {
+            LayoutError::Io { .. } => "output_io",
+            LayoutError::Exists { .. } => "output_exists",
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    fn serialize_details<M: serde::ser::SerializeMap>(&self, map: &mut M) -> Result<(), M::Error> {
+        match self {
+            LayoutError::Io { path, source } => {
+                map.serialize_entry("path", path)?;
+                map.serialize_entry("kind", &io_error_kind(source))
+            }
+            LayoutError::Exists { dir, files } => {
+                map.serialize_entry("dir", dir)?;
+                map.serialize_entry("files", files)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for LayoutError {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_error(self, serializer)
+    }
+}
+
+/// 输出目录：为子命令分配标准路径，并防止覆盖已有结果。
+///
+/// # Examples
+///
+/// ```
+/// use bamqc_core::{LayoutError, OutputLayout};
+///
+/// let dir = std::env::temp_dir().join(format!("bamqc-layout-{}", std::process::id()));
+/// let layout = OutputLayout::create(&dir, false).unwrap();
+/// let path = layout.claim("duplication", "main", false).unwrap();
+/// assert!(path.ends_with("duplication_metrics.txt"));
+/// std::fs::write(&path, "old").unwrap();
+/// std::fs::write(dir.join("duplication_metrics.NA1.txt"), "old").unwrap();
+///
+/// // 再次运行时拒绝覆盖，其它子命令不受影响
+/// let err = OutputLayout::create(&dir, false).unwrap().claim("duplication", "main", false).unwrap_err();
+/// assert!(matches!(err, LayoutError::Exists { ref files, .. } if files.len() == 2));
+/// assert!(layout.claim("flagstat", "main", false).is_ok());
+///
+/// // --force时删除旧文件（含按样本的输出）
+/// let forced = OutputLayout::create(&dir, true).unwrap();
+/// assert_eq!(forced.claim("duplication", "main", false).unwrap(), path);
+/// assert!(!dir.join("duplication_metrics.txt").exists());
+/// assert!(!dir.join("duplication_metrics.NA1.txt").exists());
+/// std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct OutputLayout {
+    dir: PathBuf,
+    force: bool,
+}
+
+impl OutputLayout {
+    /// 使用目录`dir`（不存在时创建）；`force`为true时覆盖已有的标准文件。
+    pub fn create<P: AsRef<Path>>(dir: P, force: bool) -> Result<Self, LayoutError> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir).map_err(|source| LayoutError::Io {
+            path: dir.display().to_string(),
+            source,
+        })?;
+        Ok(Self { dir, force })
+    }
+
+    /// 输出目录。
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// 返回子命令某个角色的标准路径，并检查目录中没有该文件的旧结果。
+    ///
+    /// 未登记的角色会panic（属于程序错误）。
+    pub fn claim(&self, collector: &str, role: &str, json: bool) -> Result<String, LayoutError> {
+        let file = lookup_output_file(collector, role)
+            .unwrap_or_else(|| panic!("未登记的输出文件: {} {}", collector, role));
+        let name = file.file_name(json);
+        let existing = self.existing(name)?;
+        if !existing.is_empty() {
+            if !self.force {
+                return Err(LayoutError::Exists {
+                    dir: self.dir.display().to_string(),
+                    files: existing,
+                });
+            }
+            for existing in &existing {
+                let path = self.dir.join(existing);
+                fs::remove_file(&path).map_err(|source| LayoutError::Io {
+                    path: path.display().to_string(),
+                    source,
+                })?;
+            }
+        }
+        Ok(self.dir.join(name).to_string_lossy().into_owned())
+    }
+
+    /// 目录中属于`name`的文件：`name`本身与按样本输出的`<stem>.<样本>.<ext>`。
+    pub(crate) fn existing(&self, name: &str) -> Result<Vec<String>, LayoutError> {
+        let (stem, ext) = name.rsplit_once('.').unwrap_or((name, ""));
+        let prefix = format!("{}.", stem);
+        let suffix = format!(".{}", ext);
+        let entries = fs::read_dir(&self.dir).map_err(|source| LayoutError::Io {
+            path: self.dir.display().to_string(),
+            source,
+        })?;
+        let mut files: Vec<String> = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.file_name().to_string_lossy().into_owned())
+            .filter(|file| {
+                file == name
+                    || (file.len() > prefix.len() + suffix.len()
+                        && file.starts_with(&prefix)
+                        && file.ends_with(&suffix))
+            })
+            .collect();
+        files.sort();
+        Ok(files)
+    }
+}
+
+impl fmt::Display for OutputFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "collector: {}", self.collector)?;
+        writeln!(f, "role: {}", self.role)?;
+        writeln!(f, "text: {}", self.text)?;
+        writeln!(f, "json: {}", self.json.unwrap_or("NA"))?;
+        write!(f, "description: {}", self.description)
+    }
+}
@@ -1,6 +1,137 @@
+//! BAM质量控制指标的计算。
+//!
+//! # 可重复性
+//!
+//! 相同输入必须得到逐字节相同的输出：
+//!
+//! * 所有指标都在一次按文件顺序的遍历中以整数计数（或计数直方图）累积，
+//!   均值、比例等浮点量只在最后由计数算出，不存在依赖累加顺序的浮点求和；
+//! * 输出中的集合按固定顺序排列（方向按FR、RF、TANDEM，直方图按取值，
+//!   排名按数值再按名称），不依赖HashMap的迭代顺序；
+//! * 并列时的选择规则是确定的（例如dominant策略在读对数相同时取固定顺序中
+//!   靠前的方向）。
+//!
+//! 唯一的并行路径是`--threads`>1时的多线程BGZF解压（见
+//! [`bamqc_io::BamReaderOptions::threads`]）：解压后的数据块仍按文件顺序交给单个
+//! 线程解码记录并累积，因此统计结果与线程数无关，`tests/reproducibility.rs`
+//! 在1、2、8个线程下核对全部输出逐字节相同。统计本身目前没有并行；引入时
+//! 必须按任务下标顺序归并，并保持上述性质。
+//!
+//! # 特性
+//!
+//! 默认启用全部特性。只需要flagstat和插入片段统计的嵌入场景可以使用
+//! `default-features = false, features = ["serde"]`：
+//!
+//! * `serde`：报告类型的`Serialize`实现与interim JSON快照；
+//! * `tracing`：运行日志，关闭时日志宏为空操作；
+//! * `clap`：枚举参数的`clap::ValueEnum`实现；
+//! * `intervals`：基于BED区间的统计（逐靶区指标、分区域flagstat、cytoBand染色体臂汇总、两个BAM的逐靶区相对覆盖比较、由靶区富集推断捕获试剂盒）；
+//! * `approx`：条形码统计（HyperLogLog基数估计与top-k计数）。
+//!
+//! `scripts/check-features.sh`构建并测试重要的特性组合。
+
+mod log;
 
 pub mod insert_size;
 pub mod flag_stat;
+#[cfg(feature = "approx")]
+pub mod barcode;
+pub mod header_diff;
+#[cfg(feature = "intervals")]
+pub mod target;
+pub mod interim;
+pub mod stream_sample;
+pub mod groups;
+pub mod duplication;
+pub mod dup_sets;
+pub mod histogram;
+pub mod formatting;
+pub mod expectation;
+pub mod idxstats;
+pub mod quick_check;
+pub mod validate;
+pub mod bounds;
+pub mod sort_order;
+pub mod gc_dup;
+pub mod external_sort;
+pub mod bloom;
+pub mod quality_yield;
+pub mod homopolymer;
+pub mod clipping;
+pub mod coverage;
+pub mod contig_alias;
+#[cfg(feature = "intervals")]
+pub mod cytoband;
+#[cfg(feature = "intervals")]
+pub mod coverage_compare;
+#[cfg(feature = "intervals")]
+pub mod kit_detect;
+pub mod tag_stats;
+pub mod rng;
+pub mod simulate;
+pub mod filter;
+pub mod view;
+pub mod record_stream;
+pub mod watchdog;
+pub mod progress;
+pub mod missing_primary;
+pub mod metrics;
+pub mod metrics_keys;
+pub mod samples;
+pub mod layout;
+#[cfg(feature = "serde")]
+pub mod run_cache;
+#[cfg(feature = "serde")]
+pub mod lims;
 
 pub use insert_size::*;
-pub use flag_stat::*;
\ No newline at end of file
+pub use flag_stat::*;
+#[cfg(feature = "approx")]
+pub use barcode::*;
+pub use header_diff::*;
+#[cfg(feature = "intervals")]
+pub use target::*;
+pub use interim::*;
+pub use stream_sample::*;
+pub use groups::*;
+pub use duplication::*;
+pub use dup_sets::*;
+pub use histogram::*;
+pub use formatting::*;
+pub use expectation::*;
+pub use idxstats::*;
+pub use quick_check::*;
+pub use validate::*;
+pub use bounds::*;
+pub use sort_order::*;
+pub use gc_dup::*;
+pub use external_sort::*;
+pub use bloom::*;
+pub use quality_yield::*;
+pub use homopolymer::*;
+pub use clipping::*;
+pub use coverage::*;
+pub use contig_alias::*;
+#[cfg(feature = "intervals")]
+pub use cytoband::*;
+#[cfg(feature = "intervals")]
+pub use coverage_compare::*;
+#[cfg(feature = "intervals")]
+pub use kit_detect::*;
+pub use tag_stats::*;
+pub use rng::*;
+pub use simulate::*;
+pub use filter::*;
+pub use view::*;
+pub use record_stream::*;
+pub use watchdog::*;
+pub use progress::*;
+pub use missing_primary::*;
+pub use metrics::*;
+pub use metrics_keys::*;
+pub use samples::*;
+pub use layout::*;
+#[cfg(feature = "serde")]
+pub use run_cache::*;
+#[cfg(feature = "serde")]
+pub use lims::*;
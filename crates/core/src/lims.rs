@@ -0,0 +1,816 @@
+//! LIMS交付模式：一次运行按预设的收集器组合计算指标、给出判定，并写出
+//! LIMS导入所需的四个文件。
+//!
+//! 每个样本的交付物固定为（文件名见[`crate::OUTPUT_FILES`]中的`lims`）：
+//!
+//! * `summary.json`：带[`LIMS_SCHEMA_VERSION`]的汇总，含判定与各收集器的JSON输出；
+//! * `metrics.tsv`：扁平的`key\tvalue`，键为`<收集器>.<JSON路径>`；
+//! * `histograms.tsv`：长格式直方图（sample、collector、histogram、bin_start、bin_end、value）；
+//! * `report.html`：供人工查看的单页报告。
+//!
+//! 四个文件先全部生成再写出，写出后由[`check_lims_artifacts`]检查齐全、可解析
+//! 且引用同一样本名与模式版本；任何一个不满足时整次运行失败。
+//!
+//! 判定只使用各收集器已有的检查（BGZF EOF标记、索引一致性、secondary比例、
+//! TLEN正负失衡、超出参考序列末端、接头读穿），不引入新的阈值。
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use serde::Serialize;
+use serde_json::Value;
+use thiserror::Error;
+use bamqc_io::{io_error_kind, serialize_error, BamReaderOptions, ErrorCode};
+use crate::clipping::{compute_clipping, DEFAULT_ADAPTER_CLIP_RATE};
+use crate::duplication::compute_duplication;
+use crate::flag_stat::{compute_flagstat_with_options, FlagStatOptions, DEFAULT_MAX_SECONDARY_RATIO};
+use crate::formatting::{OrNa, PicardFloat, Rate};
+use crate::gc_dup::{compute_gc_dup, GcDupOptions};
+use crate::insert_size::{compute_insert_size_with_report, InsertSizeError, InsertSizeOptions};
+use crate::layout::lookup_output_file;
+use crate::metrics::Unit;
+use crate::metrics_keys::metric_keys;
+use crate::quality_yield::{compute_quality_yield, QualitySource};
+use crate::quick_check::compute_quick_check;
+
+/// 交付物的模式版本；文件名、列或JSON结构不兼容地变化时递增。
+pub const LIMS_SCHEMA_VERSION: u32 = 1;
+
+/// 交付物在[`crate::OUTPUT_FILES`]中的角色，按写出顺序排列。
+pub const LIMS_ARTIFACTS: [&str; 4] = ["summary", "metrics", "histograms", "report"];
+
+/// 收集器组合的预设。
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Serialize)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum LimsPreset {
+    /// 全基因组：flagstat、插入片段、重复率、碱基质量产出与GC重复率。
+    Wgs,
+    /// 外显子组：flagstat、插入片段、重复率、碱基质量产出与soft clip；
+    /// 给出靶区BED时增加逐靶区指标。
+    Wes,
+}
+
+impl LimsPreset {
+    /// 预设运行的收集器（子命令名），按运行顺序排列。
+    pub fn collectors(&self) -> &'static [&'static str] {
+        match self {
+            LimsPreset::Wgs => &["quick-check", "flagstat", "insert-size", "duplication", "quality-yield", "gc-dup"],
+            LimsPreset::Wes => &["quick-check", "flagstat", "insert-size", "duplication", "quality-yield", "clipping"],
+        }
+    }
+}
+
+impl fmt::Display for LimsPreset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LimsPreset::Wgs => write!(f, "wgs"),
+            LimsPreset::Wes => write!(f, "wes"),
+        }
+    }
+}
+
+/// 判定结果，按严重程度排序。
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum VerdictStatus {
+    Pass,
+    Warn,
+    Fail,
+}
+
+impl fmt::Display for VerdictStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerdictStatus::Pass => write!(f, "PASS"),
+            VerdictStatus::Warn => write!(f, "WARN"),
+            VerdictStatus::Fail => write!(f, "FAIL"),
+        }
+    }
+}
+
+/// 一项检查的判定。
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Verdict {
+    /// 检查名。
+    pub check: &'static str,
+    /// 判定结果。
+    pub status: VerdictStatus,
+    /// 判定依据。
+    pub detail: String,
+}
+
+impl Verdict {
+    fn new(check: &'static str, problem: bool, status: VerdictStatus, detail: String) -> Self {
+        Self {
+            check,
+            status: if problem { status } else { VerdictStatus::Pass },
+            detail,
+        }
+    }
+}
+
+/// 长格式直方图的一行。
+#[derive(Clone, Debug, PartialEq)]
+pub struct HistogramRow {
+    /// 产生该直方图的收集器。
+    pub collector: &'static str,
+    /// 直方图名（插入片段为方向，GC重复率为total/duplicates，soft clip为read与端）。
+    pub histogram: String,
+    /// bin起始值（含）。
+    pub bin_start: i64,
+    /// bin终止值（含）。
+    pub bin_end: i64,
+    /// 计数或比例。
+    pub value: f64,
+}
+
+impl HistogramRow {
+    /// TSV表头，与[`HistogramRow::tsv_row`]对应。
+    pub const TSV_HEADER: &'static str = "sample\tcollector\thistogram\tbin_start\tbin_end\tvalue";
+
+    /// TSV行；值按[`PicardFloat`]输出。
+    pub fn tsv_row(&self, sample: &str) -> String {
+        format!(
+            "{}\t{}\t{}\t{}\t{}\t{}",
+            sample,
+            self.collector,
+            self.histogram,
+            self.bin_start,
+            self.bin_end,
+            PicardFloat(self.value)
+        )
+    }
+}
+
+/// LIMS模式的选项。
+#[derive(Clone, Debug)]
+pub struct LimsOptions {
+    /// 收集器组合。
+    pub preset: LimsPreset,
+    /// 写入全部交付物的样本名。
+    pub sample: String,
+    /// 靶区BED（`wes`预设时计算逐靶区指标，需要`intervals`特性）。
+    pub targets: Option<String>,
+    /// 根种子（见[`crate::rng`]），记录在交付物中。
+    pub seed: u64,
+    /// 打开输入的读取器配置（解压线程数、输入格式、CRAM参考序列等）。
+    pub reader: BamReaderOptions,
+}
+
+/// LIMS模式的汇总，即`summary.json`的内容。
+#[derive(Clone, Debug, Serialize)]
+pub struct LimsSummary {
+    /// 交付物的模式版本（[`LIMS_SCHEMA_VERSION`]）。
+    pub schema_version: u32,
+    /// 样本名。
+    pub sample: String,
+    /// 预设。
+    pub preset: LimsPreset,
+    /// bamqc版本。
+    pub version: &'static str,
+    /// 根种子。
+    pub seed: u64,
+    /// 输入BAM路径。
+    pub input: String,
+    /// 输入没有任何记录（只有头部）：各收集器给出全0的结果，插入片段中位数为null。
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub no_records: bool,
+    /// 总体判定：各项判定中最严重的一个。
+    pub status: VerdictStatus,
+    /// 各项判定。
+    pub verdicts: Vec<Verdict>,
+    /// 各收集器的JSON输出，以子命令名为键。
+    pub collectors: BTreeMap<&'static str, Value>,
+    /// 长格式直方图，写到`histograms.tsv`。
+    #[serde(skip)]
+    pub histograms: Vec<HistogramRow>,
+}
+
+/// LIMS模式的错误。
+///
+/// 错误代码与JSON形式见[`bamqc_io::error_code`]：
+///
+/// ```
+/// use bamqc_core::LimsError;
+///
+/// let cases = [
+///     (
+///         LimsError::Collector { collector: "duplication", message: "BAM格式错误".into() },
+///         r#"{"code":"collector_failed","message":"duplication: BAM格式错误","details":{"collector":"duplication"}}"#,
+///     ),
+///     (LimsError::Options("wgs预设不接受--targets".into()), r#"{"code":"invalid_options","message":"wgs预设不接受--targets","details":{}}"#),
+///     (
+///         LimsError::Io { path: "out/summary.json".into(), source: std::io::ErrorKind::NotFound.into() },
+///         r#"{"code":"output_io","message":"out/summary.json: entity not found","details":{"path":"out/summary.json","kind":"NotFound"}}"#,
+///     ),
+///     (
+///         LimsError::Incomplete { dir: "out".into(), problems: vec!["缺少metrics.tsv".into()] },
+///         r#"{"code":"incomplete_artifacts","message":"out 中的交付物不完整: 缺少metrics.tsv","details":{"dir":"out","problems":["缺少metrics.tsv"]}}"#,
+///     ),
+/// ];
+/// for (error, expected) in &cases {
+///     assert_eq!(serde_json::to_string(error).unwrap(), *expected);
+/// }
+/// ```
+#[derive(Error, Debug)]
+pub enum LimsError {
+    /// 某个收集器运行失败。
+    #[error("{collector}: {message}")]
+    Collector { collector: &'static str, message: String },
+
+    /// 预设不支持给出的选项。
+    #[error("{0}")]
+    Options(String),
+
+    /// 写出交付物失败。
+    #[error("{path}: {source}")]
+    Io {
+        path: String,
+        #[source]
+        source: std::io::Error,
+    },
+
+    /// 交付物缺失、无法解析或相互不一致。
+    #[error("{dir} 中的交付物不完整: {}", .problems.join("; "))]
+    Incomplete { dir: String, problems: Vec<String> },
+}
+
+impl ErrorCode for LimsError {
+    fn code(&self) -> &'static str {
+        match self {
+            LimsError::Collector { .. } => "collector_failed",
+            LimsError::Options(_) => "invalid_options",
+            LimsError::Io { .. } => "output_io",
+            LimsError::Incomplete { .. } => "incomplete_artifacts",
+        }
+    }
+
+    fn serialize_details<M: serde::ser::SerializeMap>(&self, map: &mut M) -> Result<(), M::Error> {
+        match self {
+            LimsError::Collector { collector, .. } => map.serialize_entry("collector", collector),
+            LimsError::Options(_) => Ok(()),
+            LimsError::Io { path, source } => {
+                map.serialize_entry("path", path)?;
+                map.serialize_entry("kind", &io_error_kind(source))
+            }
+            LimsError::Incomplete { dir, problems } => {
+                map.serialize_entry("dir", dir)?;
+                map.serialize_entry("problems", problems)
+            }
+        }
+    }
+}
+
+impl Serialize for LimsError {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_error(self, serializer)
+    }
+}
+
+/// 收集器的错误转为[`LimsError::Collector`]。
+fn collector_error<E: fmt::Display>(collector: &'static str) -> impl Fn(E) -> LimsError {
+    move |e| LimsError::Collector {
+        collector,
+        message: e.to_string(),
+    }
+}
+
+fn to_value<T: Serialize>(collector: &'static str, report: &T) -> Result<Value, LimsError> {
+    serde_json::to_value(report).map_err(collector_error(collector))
+}
+
+/// 运行预设的收集器并给出判定。
+///
+/// # Examples
+///
+/// 同一输入、相同根种子的两次运行得到逐字节相同的交付物；换一个种子只改变
+/// 记录的`seed`，因为预设中的收集器都不做抽样：
+///
+/// ```
+/// use bamqc_core::*;
+///
+/// let dir = std::env::temp_dir().join(format!("bamqc-lims-seed-{}", std::process::id()));
+/// std::fs::create_dir_all(&dir).unwrap();
+/// let bam = dir.join("sim.bam").to_string_lossy().to_string();
+/// generate_test_data(&bam, &SimulationParams { pairs: 1000, ..Default::default() }).unwrap();
+///
+/// let run = |seed: u64, name: &str| {
+///     let options = LimsOptions { preset: LimsPreset::Wgs, sample: "S1".to_string(), targets: None, seed, reader: Default::default() };
+///     let out = dir.join(name);
+///     std::fs::create_dir_all(&out).unwrap();
+///     write_lims_artifacts(&compute_lims(&bam, &options).unwrap(), &out).unwrap();
+///     LIMS_ARTIFACTS
+///         .iter()
+///         .map(|role| std::fs::read_to_string(out.join(lookup_output_file("lims", role).unwrap().text)).unwrap())
+///         .collect::<Vec<_>>()
+/// };
+/// let first = run(7, "a");
+/// assert_eq!(run(7, "b"), first);
+///
+/// let other = run(8, "c");
+/// let metrics = |files: &[String]| files[1].lines().filter(|line| !line.starts_with("seed\t")).map(String::from).collect::<Vec<_>>();
+/// assert_eq!(metrics(&other), metrics(&first));
+/// assert_ne!(other[0], first[0]);
+/// let mut json: serde_json::Value = serde_json::from_str(&other[0]).unwrap();
+/// json["seed"] = 7.into();
+/// assert_eq!(json, serde_json::from_str::<serde_json::Value>(&first[0]).unwrap());
+/// std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+pub fn compute_lims(bam_path: &str, options: &LimsOptions) -> Result<LimsSummary, LimsError> {
+    if options.targets.is_some() && options.preset != LimsPreset::Wes {
+        return Err(LimsError::Options(format!("--targets只用于wes预设（当前为{}）", options.preset)));
+    }
+
+    let mut collectors = BTreeMap::new();
+    let mut verdicts = Vec::new();
+    let mut histograms = Vec::new();
+    let mut no_records = false;
+
+    for &collector in options.preset.collectors() {
+        match collector {
+            "quick-check" => {
+                let report = compute_quick_check(bam_path, &options.reader).map_err(collector_error(collector))?;
+                verdicts.push(Verdict::new(
+                    "eof_marker",
+                    !report.eof_marker,
+                    VerdictStatus::Fail,
+                    format!("eof_marker: {}", report.eof_marker),
+                ));
+                verdicts.push(Verdict::new(
+                    "index",
+                    !report.index_problems.is_empty(),
+                    VerdictStatus::Warn,
+                    format!("index_problems: {}", report.index_problems.len()),
+                ));
+                collectors.insert(collector, to_value(collector, &report)?);
+            }
+            "flagstat" => {
+                let flagstat_options = FlagStatOptions { reader: options.reader.clone(), ..Default::default() };
+                let stat = compute_flagstat_with_options(bam_path, None, None, &flagstat_options).map_err(collector_error(collector))?;
+                no_records |= stat.no_records();
+                let ratio = stat.secondary_ratio();
+                verdicts.push(Verdict::new(
+                    "secondary_ratio",
+                    ratio.is_some_and(|ratio| ratio > DEFAULT_MAX_SECONDARY_RATIO),
+                    VerdictStatus::Warn,
+                    format!(
+                        "secondary_ratio: {}（阈值 {}）",
+                        OrNa(ratio.map(Rate)),
+                        DEFAULT_MAX_SECONDARY_RATIO
+                    ),
+                ));
+                collectors.insert(collector, to_value(collector, &stat)?);
+            }
+            "insert-size" => {
+                let insert_size_options = InsertSizeOptions { reader: options.reader.clone(), ..Default::default() };
+                let (median, report) = match compute_insert_size_with_report(bam_path, &insert_size_options, None) {
+                    Ok((median, report)) => (Some(median), report),
+                    // 输入为空不是失败：照常写出全0的报告，中位数为null
+                    Err(InsertSizeError::NoValidReads { report: Some(report) }) if report.no_records => (None, *report),
+                    Err(e) => return Err(collector_error(collector)(e)),
+                };
+                no_records |= report.no_records;
+                verdicts.push(Verdict::new(
+                    "tlen_balance",
+                    report.tlen_imbalanced(),
+                    VerdictStatus::Warn,
+                    format!("tlen_imbalance: {}", Rate(report.tlen_imbalance())),
+                ));
+                verdicts.push(Verdict::new(
+                    "past_contig_end",
+                    report.past_contig_end > 0,
+                    VerdictStatus::Warn,
+                    format!("past_contig_end: {}", report.past_contig_end),
+                ));
+                for (orientation, histogram) in &report.histograms {
+                    histograms.extend(histogram.bins().iter().map(|bin| HistogramRow {
+                        collector,
+                        histogram: orientation.to_string(),
+                        bin_start: bin.start as i64,
+                        bin_end: bin.end as i64,
+                        value: bin.count,
+                    }));
+                }
+                let mut value = to_value(collector, &report)?;
+                if let Value::Object(map) = &mut value {
+                    map.insert("insert_size".to_string(), Value::from(median));
+                }
+                collectors.insert(collector, value);
+            }
+            "duplication" => {
+                let report = compute_duplication(bam_path, &options.reader, false).map_err(collector_error(collector))?;
+                collectors.insert(collector, to_value(collector, &report)?);
+            }
+            "quality-yield" => {
+                let report = compute_quality_yield(bam_path, &options.reader, QualitySource::Qual).map_err(collector_error(collector))?;
+                collectors.insert(collector, to_value(collector, &report)?);
+            }
+            "gc-dup" => {
+                let gc_dup_options = GcDupOptions { reader: options.reader.clone(), ..Default::default() };
+                let report = compute_gc_dup(bam_path, &gc_dup_options).map_err(collector_error(collector))?;
+                for bin in &report.bins {
+                    for (histogram, value) in [("total", bin.total), ("duplicates", bin.duplicates)] {
+                        histograms.push(HistogramRow {
+                            collector,
+                            histogram: histogram.to_string(),
+                            bin_start: bin.gc as i64,
+                            bin_end: bin.gc as i64,
+                            value: value as f64,
+                        });
+                    }
+                }
+                collectors.insert(collector, to_value(collector, &report)?);
+            }
+            "clipping" => {
+                let report = compute_clipping(bam_path, &options.reader, DEFAULT_ADAPTER_CLIP_RATE).map_err(collector_error(collector))?;
+                let cycles: Vec<String> = report.adapter_cycles.iter().map(|c| c.to_string()).collect();
+                verdicts.push(Verdict::new(
+                    "adapter_read_through",
+                    !cycles.is_empty(),
+                    VerdictStatus::Warn,
+                    format!("adapter_cycles: {}", if cycles.is_empty() { "NA".to_string() } else { cycles.join(",") }),
+                ));
+                for cycle in &report.cycles {
+                    for (histogram, value) in [
+                        ("r1_5p", cycle.r1_5p),
+                        ("r1_3p", cycle.r1_3p),
+                        ("r2_5p", cycle.r2_5p),
+                        ("r2_3p", cycle.r2_3p),
+                    ] {
+                        histograms.push(HistogramRow {
+                            collector,
+                            histogram: histogram.to_string(),
+                            bin_start: cycle.cycle as i64,
+                            bin_end: cycle.cycle as i64,
+                            value,
+                        });
+                    }
+                }
+                collectors.insert(collector, to_value(collector, &report)?);
+            }
+            _ => unreachable!("预设中未知的收集器: {}", collector),
+        }
+    }
+
+    if let Some(bed) = &options.targets {
+        #[cfg(feature = "intervals")]
+        {
+            let report = crate::target::compute_target_metrics(bam_path, &options.reader, bed, crate::target::EXTREME_TARGETS).map_err(collector_error("targets"))?;
+            collectors.insert("targets", to_value("targets", &report)?);
+        }
+        #[cfg(not(feature = "intervals"))]
+        return Err(LimsError::Options(format!("未启用intervals特性，无法统计靶区 {}", bed)));
+    }
+
+    let status = verdicts.iter().map(|verdict| verdict.status).max().unwrap_or(VerdictStatus::Pass);
+    Ok(LimsSummary {
+        schema_version: LIMS_SCHEMA_VERSION,
+        sample: options.sample.clone(),
+        preset: options.preset,
+        version: env!("CARGO_PKG_VERSION"),
+        seed: options.seed,
+        input: bam_path.to_string(),
+        no_records,
+        status,
+        verdicts,
+        collectors,
+        histograms,
+    })
+}
+
+/// 写到`histograms.tsv`、不再展开到`metrics.tsv`的字段。
+const HISTOGRAM_FIELDS: &[(&str, &str)] = &[("gc-dup", "bins"), ("clipping", "cycles")];
+
+/// 把JSON值展开为`key\tvalue`行；数组按下标展开，null为NA。
+fn flatten(prefix: &str, value: &Value, rows: &mut Vec<(String, String)>) {
+    match value {
+        Value::Object(map) => {
+            for (key, child) in map {
+                flatten(&format!("{}.{}", prefix, key), child, rows);
+            }
+        }
+        Value::Array(items) => {
+            for (i, item) in items.iter().enumerate() {
+                flatten(&format!("{}.{}", prefix, i), item, rows);
+            }
+        }
+        Value::Null => rows.push((prefix.to_string(), "NA".to_string())),
+        Value::String(s) => rows.push((prefix.to_string(), s.replace(['\t', '\n', '\r'], " "))),
+        other => rows.push((prefix.to_string(), other.to_string())),
+    }
+}
+
+impl LimsSummary {
+    /// `metrics.tsv`的行：样本信息、判定，然后是各收集器输出展开后的指标。
+    pub fn flat_metrics(&self) -> Vec<(String, String)> {
+        let mut rows = vec![
+            ("schema_version".to_string(), self.schema_version.to_string()),
+            ("sample".to_string(), self.sample.clone()),
+            ("preset".to_string(), self.preset.to_string()),
+            ("version".to_string(), self.version.to_string()),
+            ("seed".to_string(), self.seed.to_string()),
+            ("status".to_string(), self.status.to_string()),
+        ];
+        if self.no_records {
+            rows.push(("no_records".to_string(), "true".to_string()));
+        }
+        for verdict in &self.verdicts {
+            rows.push((format!("verdict.{}", verdict.check), verdict.status.to_string()));
+        }
+        for (&collector, value) in &self.collectors {
+            match value {
+                Value::Object(map) => {
+                    for (key, child) in map {
+                        if !HISTOGRAM_FIELDS.contains(&(collector, key.as_str())) {
+                            flatten(&format!("{}.{}", collector, key), child, &mut rows);
+                        }
+                    }
+                }
+                other => flatten(collector, other, &mut rows),
+            }
+        }
+        rows
+    }
+
+    /// `metrics.tsv`的内容（表头为`key\tvalue`）。
+    pub fn metrics_tsv(&self) -> String {
+        let mut result = String::from("key\tvalue\n");
+        for (key, value) in self.flat_metrics() {
+            result.push_str(&format!("{}\t{}\n", key, value));
+        }
+        result
+    }
+
+    /// `histograms.tsv`的内容。
+    pub fn histograms_tsv(&self) -> String {
+        let mut result = format!("{}\n", HistogramRow::TSV_HEADER);
+        for row in &self.histograms {
+            result.push_str(&row.tsv_row(&self.sample));
+            result.push('\n');
+        }
+        result
+    }
+
+    /// `report.html`的内容；样本名与模式版本同时写在`<meta>`中供检查。
+    ///
+    /// # Examples
+    ///
+    /// 只有头部的BAM：各收集器给出全0的结果，报告顶部显示“empty input”提示：
+    ///
+    /// ```
+    /// use bamqc_core::*;
+    /// use bamqc_io::bam::BamWriter;
+    ///
+    /// let dir = std::env::temp_dir().join(format!("bamqc-lims-empty-{}", std::process::id()));
+    /// std::fs::create_dir_all(&dir).unwrap();
+    /// let bam = dir.join("empty.bam");
+    /// let header = "@HD\tVN:1.6\tSO:coordinate\n@SQ\tSN:c1\tLN:1000\n@RG\tID:rg1\tSM:S1\n".parse().unwrap();
+    /// BamWriter::from_path(&bam, &header).unwrap().finish().unwrap();
+    ///
+    /// let options = LimsOptions { preset: LimsPreset::Wgs, sample: "S1".to_string(), targets: None, seed: DEFAULT_SEED, reader: Default::default() };
+    /// let summary = compute_lims(&bam.to_string_lossy(), &options).unwrap();
+    /// assert!(summary.no_records);
+    /// assert!(summary.report_html().contains("<b>empty input</b>"));
+    /// assert!(summary.flat_metrics().contains(&("insert-size.insert_size".to_string(), "NA".to_string())));
+    ///
+    /// let out = dir.join("lims");
+    /// std::fs::create_dir_all(&out).unwrap();
+    /// write_lims_artifacts(&summary, &out).unwrap();
+    /// let json: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(out.join("summary.json")).unwrap()).unwrap();
+    /// assert_eq!(json["no_records"], true);
+    /// assert_eq!(json["collectors"]["flagstat"]["total"], 0);
+    /// assert_eq!(json["collectors"]["quality-yield"]["no_records"], true);
+    /// assert!(json["collectors"]["insert-size"]["insert_size"].is_null());
+    /// assert!(undocumented_keys(&json).is_empty(), "{:?}", undocumented_keys(&json));
+    /// for output in json["collectors"].as_object().unwrap().values() {
+    ///     assert!(undocumented_keys(output).is_empty(), "{:?}", undocumented_keys(output));
+    /// }
+    ///
+    /// // 有记录时没有提示
+    /// generate_test_data(&bam.to_string_lossy(), &SimulationParams { pairs: 200, ..Default::default() }).unwrap();
+    /// let summary = compute_lims(&bam.to_string_lossy(), &options).unwrap();
+    /// assert!(!summary.no_records);
+    /// assert!(!summary.report_html().contains("empty input"));
+    /// std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    pub fn report_html(&self) -> String {
+        let mut html = String::from("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+        html.push_str(&format!(
+            "<meta name=\"bamqc-schema-version\" content=\"{}\">\n<meta name=\"bamqc-sample\" content=\"{}\">\n",
+            self.schema_version,
+            escape_html(&self.sample)
+        ));
+        html.push_str(&format!("<title>bamqc {}</title>\n", escape_html(&self.sample)));
+        html.push_str("<style>body{font-family:sans-serif}table{border-collapse:collapse}td,th{border:1px solid #ccc;padding:2px 8px;text-align:left}</style>\n");
+        html.push_str("</head>\n<body>\n");
+        html.push_str(&format!("<h1>{}</h1>\n", escape_html(&self.sample)));
+        if self.no_records {
+            html.push_str("<p><b>empty input</b>: 输入只有头部、没有任何记录，各项指标均为0（插入片段中位数为NA）。</p>\n");
+        }
+        html.push_str(&format!(
+            "<p>preset: {} · bamqc {} · schema_version: {} · seed: {} · status: <b>{}</b></p>\n",
+            self.preset, self.version, self.schema_version, self.seed, self.status
+        ));
+        html.push_str("<h2>verdicts</h2>\n<table>\n<tr><th>check</th><th>status</th><th>detail</th></tr>\n");
+        for verdict in &self.verdicts {
+            html.push_str(&format!(
+                "<tr><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                verdict.check,
+                verdict.status,
+                escape_html(&verdict.detail)
+            ));
+        }
+        html.push_str("</table>\n<h2>metrics</h2>\n<table>\n<tr><th>key</th><th>value</th></tr>\n");
+        for (key, value) in self.flat_metrics() {
+            html.push_str(&format!("<tr><td>{}</td><td>{}</td></tr>\n", escape_html(&key), escape_html(&value)));
+        }
+        html.push_str("</table>\n<p>直方图见histograms.tsv。</p>\n</body>\n</html>\n");
+        html
+    }
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+/// 交付物的标准文件名。
+fn artifact_name(role: &str) -> &'static str {
+    lookup_output_file("lims", role)
+        .unwrap_or_else(|| panic!("未登记的LIMS交付物: {}", role))
+        .text
+}
+
+/// 把四个交付物写到`dir`，再检查其完整性。
+///
+/// 所有内容先在内存中生成，全部成功后才写文件。
+pub fn write_lims_artifacts(summary: &LimsSummary, dir: &Path) -> Result<(), LimsError> {
+    let summary_json = serde_json::to_string_pretty(summary).map_err(collector_error("lims"))?;
+    let contents = [
+        summary_json + "\n",
+        summary.metrics_tsv(),
+        summary.histograms_tsv(),
+        summary.report_html(),
+    ];
+    for (role, content) in LIMS_ARTIFACTS.iter().zip(contents) {
+        let path = dir.join(artifact_name(role));
+        fs::write(&path, content).map_err(|source| LimsError::Io {
+            path: path.display().to_string(),
+            source,
+        })?;
+    }
+    check_lims_artifacts(dir, &summary.sample)
+}
+
+/// 检查`dir`中的四个交付物齐全、可解析，并引用同一样本名与模式版本。
+///
+/// # Examples
+///
+/// 在模拟数据上运行wes预设，检查四个文件并相互对照：
+///
+/// ```
+/// use bamqc_core::*;
+///
+/// let dir = std::env::temp_dir().join(format!("bamqc-lims-{}", std::process::id()));
+/// std::fs::create_dir_all(&dir).unwrap();
+/// let bam = dir.join("sim.bam").to_string_lossy().to_string();
+/// generate_test_data(&bam, &SimulationParams { pairs: 2000, ..Default::default() }).unwrap();
+///
+/// let options = LimsOptions { preset: LimsPreset::Wes, sample: "S1".to_string(), targets: None, seed: DEFAULT_SEED, reader: Default::default() };
+/// let summary = compute_lims(&bam, &options).unwrap();
+/// assert_eq!(summary.status, VerdictStatus::Pass);
+/// let out = dir.join("lims");
+/// std::fs::create_dir_all(&out).unwrap();
+/// write_lims_artifacts(&summary, &out).unwrap();
+///
+/// // summary.json：模式版本、样本名与判定，且每个键都已登记
+/// let json: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(out.join("summary.json")).unwrap()).unwrap();
+/// assert_eq!(json["schema_version"], LIMS_SCHEMA_VERSION);
+/// assert_eq!(json["sample"], "S1");
+/// assert_eq!(json["preset"], "wes");
+/// assert!(json["verdicts"].as_array().unwrap().iter().any(|v| v["check"] == "adapter_read_through"));
+/// assert!(json["collectors"]["clipping"].is_object());
+/// assert!(undocumented_keys(&json).is_empty(), "{:?}", undocumented_keys(&json));
+/// for output in json["collectors"].as_object().unwrap().values() {
+///     assert!(undocumented_keys(output).is_empty(), "{:?}", undocumented_keys(output));
+/// }
+///
+/// // metrics.tsv：每行两列，与summary.json一致
+/// let metrics = std::fs::read_to_string(out.join("metrics.tsv")).unwrap();
+/// let rows: Vec<(&str, &str)> = metrics.lines().map(|line| line.split_once('\t').unwrap()).collect();
+/// assert!(metrics.lines().all(|line| line.split('\t').count() == 2));
+/// assert!(rows.contains(&("sample", "S1")));
+/// assert!(rows.contains(&("schema_version", &LIMS_SCHEMA_VERSION.to_string())));
+/// let median = json["collectors"]["insert-size"]["insert_size"].to_string();
+/// assert!(rows.contains(&("insert-size.insert_size", median.as_str())));
+///
+/// // histograms.tsv：长格式，每行都属于同一样本
+/// let histograms = std::fs::read_to_string(out.join("histograms.tsv")).unwrap();
+/// let mut lines = histograms.lines();
+/// assert_eq!(lines.next(), Some(HistogramRow::TSV_HEADER));
+/// let rows: Vec<Vec<&str>> = lines.map(|line| line.split('\t').collect()).collect();
+/// assert!(rows.iter().all(|row| row.len() == 6 && row[0] == "S1"));
+/// assert!(rows.iter().any(|row| row[1] == "insert-size" && row[2] == "FR"));
+/// assert!(rows.iter().any(|row| row[1] == "clipping"));
+///
+/// // report.html
+/// let html = std::fs::read_to_string(out.join("report.html")).unwrap();
+/// assert!(html.contains("<meta name=\"bamqc-sample\" content=\"S1\">"));
+///
+/// // 缺少或不一致的交付物
+/// assert!(check_lims_artifacts(&out, "S2").is_err());
+/// std::fs::remove_file(out.join("report.html")).unwrap();
+/// let err = check_lims_artifacts(&out, "S1").unwrap_err();
+/// assert!(matches!(err, LimsError::Incomplete { ref problems, .. } if problems.len() == 1));
+/// std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+pub fn check_lims_artifacts(dir: &Path, sample: &str) -> Result<(), LimsError> {
+    let mut problems = Vec::new();
+    let version = LIMS_SCHEMA_VERSION.to_string();
+    for role in LIMS_ARTIFACTS {
+        let name = artifact_name(role);
+        let content = match fs::read_to_string(dir.join(name)) {
+            Ok(content) => content,
+            Err(e) => {
+                problems.push(format!("{}: {}", name, e));
+                continue;
+            }
+        };
+        let problem = match role {
+            "summary" => match serde_json::from_str::<Value>(&content) {
+                Ok(json) if json["schema_version"] != LIMS_SCHEMA_VERSION => Some(format!("schema_version为{}", json["schema_version"])),
+                Ok(json) if json["sample"] != sample => Some(format!("sample为{}", json["sample"])),
+                Ok(_) => None,
+                Err(e) => Some(format!("无法解析: {}", e)),
+            },
+            "metrics" => {
+                let rows: Vec<Vec<&str>> = content.lines().map(|line| line.split('\t').collect()).collect();
+                if rows.first() != Some(&vec!["key", "value"]) || rows.iter().any(|row| row.len() != 2) {
+                    Some("不是两列的key\\tvalue表".to_string())
+                } else if !rows.contains(&vec!["sample", sample]) {
+                    Some(format!("缺少sample\\t{}", sample))
+                } else if !rows.contains(&vec!["schema_version", version.as_str()]) {
+                    Some(format!("缺少schema_version\\t{}", version))
+                } else {
+                    None
+                }
+            }
+            "histograms" => {
+                let mut lines = content.lines();
+                if lines.next() != Some(HistogramRow::TSV_HEADER) {
+                    Some("表头不符".to_string())
+                } else if lines.any(|line| {
+                    let row: Vec<&str> = line.split('\t').collect();
+                    row.len() != 6 || row[0] != sample
+                }) {
+                    Some(format!("存在列数不符或样本名不是{}的行", sample))
+                } else {
+                    None
+                }
+            }
+            _ => {
+                let expected = [
+                    format!("<meta name=\"bamqc-schema-version\" content=\"{}\">", version),
+                    format!("<meta name=\"bamqc-sample\" content=\"{}\">", escape_html(sample)),
+                ];
+                (!expected.iter().all(|meta| content.contains(meta.as_str())))
+                    .then(|| "缺少样本名或模式版本的<meta>".to_string())
+            }
+        };
+        if let Some(problem) = problem {
+            problems.push(format!("{}: {}", name, problem));
+        }
+    }
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(LimsError::Incomplete {
+            dir: dir.display().to_string(),
+            problems,
+        })
+    }
+}
+
+metric_keys! {
+    /// `lims`输出（`summary.json`）的键（见[`crate::metrics`]）。
+    LimsKeys for "lims" {
+        SCHEMA_VERSION = "schema_version", "模式版本", Unit::Label, None, "交付物的模式版本，文件名、列或JSON结构不兼容地变化时递增。";
+        SAMPLE = "sample", "样本名", Unit::Label, None, "--sample-name给出（或取自头部@RG SM）的样本名，四个交付物中一致。";
+        PRESET = "preset", "预设", Unit::Label, None, "收集器组合：wgs或wes。";
+        VERSION = "version", "bamqc版本", Unit::Label, None, "生成交付物的bamqc版本。";
+        SEED = "seed", "根种子", Unit::Label, None, "全局选项--seed给出的根种子；当前预设的收集器都不做抽样，不同种子的交付物只有该值不同。";
+        INPUT = "input", "输入文件", Unit::Label, None, "输入BAM路径。";
+        NO_RECORDS = "no_records", "输入为空", Unit::Boolean, None, "输入只有头部、没有任何记录时为true，report.html顶部显示empty input提示；有记录时不输出。";
+        STATUS = "status", "总体判定", Unit::Label, None, "各项判定中最严重的一个：PASS、WARN或FAIL；FAIL时以退出码3结束。";
+        VERDICTS = "verdicts", "判定", Unit::List, None, "各项检查的判定。";
+        CHECK = "check", "检查名", Unit::Label, None, "eof_marker、index、secondary_ratio、tlen_balance、past_contig_end或adapter_read_through。";
+        DETAIL = "detail", "判定依据", Unit::Label, None, "判定所用的指标值。";
+        COLLECTORS = "collectors", "各收集器的输出", Unit::Map, None, "以子命令名为键的各收集器JSON输出；插入片段另含insert_size中位数。";
+    }
+}
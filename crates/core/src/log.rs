@@ -0,0 +1,18 @@
+//! 日志宏：启用`tracing`特性时转发到tracing，否则展开为空操作。
+//!
+//! 空操作版本仍对参数做类型检查，但不会执行格式化。
+
+#[cfg(feature = "tracing")]
+pub(crate) use tracing::{debug, info, warn};
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! noop {
+    ($($arg:tt)*) => {
+        if false {
+            let _ = format!($($arg)*);
+        }
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) use {noop as debug, noop as info, noop as warn};
@@ -0,0 +1,330 @@
+//! 指标字典。
+//!
+//! JSON与文本/TSV输出中出现的每个键都在字典中登记显示名称、定义、单位、
+//! 是否越高越好以及产生它的收集器。各收集器模块用
+//! `metric_keys!`（见[`crate::metrics_keys`]）同时声明键常量（见[`crate::MetricKey`]）与
+//! 本模块的`METRICS`，[`metric_dictionary`]按固定顺序汇总；新增输出键时
+//! 必须同时登记，`undocumented_keys`用于检查遗漏。
+//!
+//! 同一个键可以由多个收集器输出（例如`past_contig_end`），含义相同，各自登记。
+
+use std::fmt;
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+/// 指标的单位。
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Unit {
+    /// 一般计数（记录、块、标签值等）。
+    Count,
+    /// 读长（记录）数。
+    Reads,
+    /// 读对数。
+    ReadPairs,
+    /// 碱基数。
+    Bases,
+    /// 参考序列上的长度或坐标（bp）。
+    BasePairs,
+    /// 0到1之间的比例。
+    Fraction,
+    /// 百分比。
+    Percent,
+    /// 两个计数之比。
+    Ratio,
+    /// Phred质量值。
+    Phred,
+    /// 秒。
+    Seconds,
+    /// 布尔值。
+    Boolean,
+    /// 名称、枚举值或其它文本。
+    Label,
+    /// 列表或表格（元素的键另行登记）。
+    List,
+    /// 以数据取值为键的映射（键不属于字典）。
+    Map,
+}
+
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Unit::Count => "count",
+            Unit::Reads => "reads",
+            Unit::ReadPairs => "read_pairs",
+            Unit::Bases => "bases",
+            Unit::BasePairs => "base_pairs",
+            Unit::Fraction => "fraction",
+            Unit::Percent => "percent",
+            Unit::Ratio => "ratio",
+            Unit::Phred => "phred",
+            Unit::Seconds => "seconds",
+            Unit::Boolean => "boolean",
+            Unit::Label => "label",
+            Unit::List => "list",
+            Unit::Map => "map",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// 一个输出键的定义。
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct MetricDef {
+    /// JSON/TSV中的键。
+    pub key: &'static str,
+    /// 显示名称。
+    pub display_name: &'static str,
+    /// 一句话定义。
+    pub definition: &'static str,
+    /// 单位。
+    pub unit: Unit,
+    /// 越高越好为Some(true)，越低越好为Some(false)，无好坏之分为None。
+    pub higher_is_better: Option<bool>,
+    /// 产生该键的收集器（子命令名）。
+    pub collector: &'static str,
+}
+
+impl fmt::Display for MetricDef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "key: {}", self.key)?;
+        writeln!(f, "display_name: {}", self.display_name)?;
+        writeln!(f, "definition: {}", self.definition)?;
+        writeln!(f, "unit: {}", self.unit)?;
+        match self.higher_is_better {
+            Some(v) => writeln!(f, "higher_is_better: {}", v)?,
+            None => writeln!(f, "higher_is_better: NA")?,
+        }
+        write!(f, "collector: {}", self.collector)
+    }
+}
+
+impl MetricDef {
+    /// TSV表头，与[`MetricDef::tsv_row`]对应。
+    pub const TSV_HEADER: &'static str = "collector\tkey\tdisplay_name\tunit\thigher_is_better\tdefinition";
+
+    /// TSV行。
+    pub fn tsv_row(&self) -> String {
+        format!(
+            "{}\t{}\t{}\t{}\t{}\t{}",
+            self.collector,
+            self.key,
+            self.display_name,
+            self.unit,
+            self.higher_is_better.map_or("NA".to_string(), |v| v.to_string()),
+            self.definition
+        )
+    }
+}
+
+/// 供各模块的`METRICS`常量使用的构造函数。
+pub(crate) const fn metric(
+    collector: &'static str,
+    key: &'static str,
+    display_name: &'static str,
+    unit: Unit,
+    higher_is_better: Option<bool>,
+    definition: &'static str,
+) -> MetricDef {
+    MetricDef {
+        key,
+        display_name,
+        definition,
+        unit,
+        higher_is_better,
+        collector,
+    }
+}
+
+/// 各模块登记的指标，按子命令顺序排列。
+const REGISTRY: &[&[MetricDef]] = &[
+    crate::insert_size::METRICS,
+    crate::histogram::METRICS,
+    crate::expectation::METRICS,
+    crate::groups::METRICS,
+    #[cfg(feature = "approx")]
+    crate::barcode::METRICS,
+    crate::flag_stat::METRICS,
+    crate::header_diff::METRICS,
+    crate::duplication::METRICS,
+    crate::dup_sets::METRICS,
+    #[cfg(feature = "intervals")]
+    crate::target::METRICS,
+    crate::gc_dup::METRICS,
+    crate::quality_yield::METRICS,
+    crate::homopolymer::METRICS,
+    crate::clipping::METRICS,
+    crate::coverage::METRICS,
+    #[cfg(feature = "intervals")]
+    crate::cytoband::METRICS,
+    #[cfg(feature = "intervals")]
+    crate::coverage_compare::METRICS,
+    #[cfg(feature = "intervals")]
+    crate::kit_detect::METRICS,
+    crate::tag_stats::METRICS,
+    crate::simulate::METRICS,
+    crate::idxstats::METRICS,
+    crate::quick_check::METRICS,
+    crate::validate::METRICS,
+    crate::interim::METRICS,
+    crate::stream_sample::METRICS,
+    #[cfg(feature = "serde")]
+    crate::lims::METRICS,
+];
+
+/// 全部指标定义。
+///
+/// # Examples
+///
+/// ```
+/// use bamqc_core::{metric_dictionary, lookup_metric, suggest_metric, Unit};
+///
+/// assert!(metric_dictionary().count() > 100);
+/// let def = lookup_metric("percent_duplication").unwrap();
+/// assert_eq!(def.unit, Unit::Fraction);
+/// assert_eq!(def.higher_is_better, Some(false));
+/// assert!(lookup_metric("fold_80").is_none());
+/// assert_eq!(suggest_metric("percent_dupliction"), Some("percent_duplication"));
+/// assert_eq!(suggest_metric("zzzz"), None);
+/// ```
+pub fn metric_dictionary() -> impl Iterator<Item = &'static MetricDef> {
+    REGISTRY.iter().flat_map(|metrics| metrics.iter())
+}
+
+/// 按键查找指标定义；多个收集器登记了同一个键时返回第一个。
+pub fn lookup_metric(key: &str) -> Option<&'static MetricDef> {
+    metric_dictionary().find(|def| def.key == key)
+}
+
+/// 对未登记的键给出最接近的已登记键（编辑距离不超过键长的1/3且不超过3）。
+pub fn suggest_metric(key: &str) -> Option<&'static str> {
+    let limit = (key.chars().count() / 3).clamp(1, 3);
+    metric_dictionary()
+        .map(|def| (edit_distance(key, def.key), def.key))
+        .filter(|&(distance, _)| distance <= limit)
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, key)| key)
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// 返回JSON中未在字典中登记的键（去重，按首次出现顺序）。
+///
+/// 单位为[`Unit::Map`]的键，其值中的键是数据而不是指标名，不再检查。
+///
+/// # Examples
+///
+/// ```
+/// use bamqc_core::undocumented_keys;
+///
+/// let value = serde_json::json!({
+///     "total_reads": 10,
+///     "excluded_distances": { "<1kb": 1 },
+///     "groups": [{ "group": "rg1", "pairs_typo": 1 }],
+/// });
+/// assert_eq!(undocumented_keys(&value), ["pairs_typo"]);
+/// ```
+///
+/// 在模拟数据上运行全部收集器，输出的每个键都必须已登记（即声明了键常量）：
+///
+/// ```
+/// use bamqc_core::*;
+/// use bamqc_io::BamReaderOptions;
+///
+/// let dir = std::env::temp_dir().join(format!("bamqc-metrics-{}", std::process::id()));
+/// std::fs::create_dir_all(&dir).unwrap();
+/// let bam = dir.join("sim.bam").to_string_lossy().to_string();
+/// let params = SimulationParams { pairs: 2000, ..Default::default() };
+///
+/// let mut outputs = vec![serde_json::to_value(generate_test_data(&bam, &params).unwrap()).unwrap()];
+/// let options = InsertSizeOptions {
+///     report_largest: 2,
+///     report_excluded_distances: true,
+///     accumulation_level: AccumulationLevel::ReadGroup,
+///     pair_duplicate_policy: PairDuplicatePolicy::EitherMate,
+///     tandem_split: true,
+///     min_mapq: Some(0),
+///     ..Default::default()
+/// };
+/// let (median, mut report) = compute_insert_size_with_report(&bam, &options, None).unwrap();
+/// report.expectation = Some(InsertSizeExpectation::Value { expected: 300, tolerance: 50 }.check(median));
+/// outputs.push(serde_json::to_value(report).unwrap());
+/// outputs.push(serde_json::to_value(compute_flagstat(&bam, None, None).unwrap()).unwrap());
+/// outputs.push(serde_json::to_value(diff_header_files(&bam, &bam, &BamReaderOptions::new()).unwrap()).unwrap());
+/// outputs.push(serde_json::to_value(compute_duplication(&bam, &BamReaderOptions::new(), false).unwrap()).unwrap());
+/// outputs.push(serde_json::to_value(compute_gc_dup(&bam, &GcDupOptions::default()).unwrap()).unwrap());
+/// outputs.push(serde_json::to_value(compute_quality_yield(&bam, &BamReaderOptions::new(), QualitySource::Qual).unwrap()).unwrap());
+/// outputs.push(serde_json::to_value(compute_homopolymer_indels(&bam, &BamReaderOptions::new(), DEFAULT_MIN_HOMOPOLYMER).unwrap()).unwrap());
+/// outputs.push(serde_json::to_value(compute_clipping(&bam, &BamReaderOptions::new(), DEFAULT_ADAPTER_CLIP_RATE).unwrap()).unwrap());
+/// outputs.push(serde_json::to_value(compute_depth_windows(&bam, &BamReaderOptions::new(), 1000, None, 0).unwrap()).unwrap());
+/// let specs = vec!["NM:i".parse().unwrap()];
+/// outputs.push(serde_json::to_value(compute_tag_stats(&bam, &BamReaderOptions::new(), specs, DEFAULT_FLOAT_BIN_WIDTH).unwrap()).unwrap());
+/// outputs.push(serde_json::to_value(compute_idxstats(&bam, &BamReaderOptions::new(), true).unwrap()).unwrap());
+/// outputs.push(serde_json::to_value(compute_quick_check(&bam, &BamReaderOptions::new()).unwrap()).unwrap());
+/// outputs.push(serde_json::to_value(compute_flag_validation(&bam, &BamReaderOptions::new()).unwrap()).unwrap());
+/// #[cfg(feature = "intervals")]
+/// {
+///     let bed = dir.join("sim.bed");
+///     std::fs::write(&bed, "sim1\t0\t100000\tt1\n").unwrap();
+///     let bed = bed.to_string_lossy().to_string();
+///     outputs.push(serde_json::to_value(compute_target_metrics(&bam, &BamReaderOptions::new(), &bed, EXTREME_TARGETS).unwrap()).unwrap());
+///     outputs.push(serde_json::to_value(compute_coverage_comparison(&bam, &bam, &BamReaderOptions::new(), &bed, DEFAULT_LOG2_THRESHOLD).unwrap()).unwrap());
+///     let kits = dir.join("kits");
+///     std::fs::create_dir_all(&kits).unwrap();
+///     std::fs::copy(&bed, kits.join("sim.bed")).unwrap();
+///     outputs.push(serde_json::to_value(detect_kit(&bam, &kits.to_string_lossy(), &KitDetectOptions::default()).unwrap()).unwrap());
+/// }
+/// #[cfg(feature = "approx")]
+/// outputs.push(serde_json::to_value(compute_barcodes(&bam, BarcodeOptions::default(), None).unwrap()).unwrap());
+///
+/// for output in &outputs {
+///     assert!(undocumented_keys(output).is_empty(), "{:?}", undocumented_keys(output));
+/// }
+/// std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+#[cfg(feature = "serde")]
+pub fn undocumented_keys(value: &serde_json::Value) -> Vec<String> {
+    fn walk(value: &serde_json::Value, missing: &mut Vec<String>) {
+        match value {
+            serde_json::Value::Object(map) => {
+                for (key, child) in map {
+                    match lookup_metric(key) {
+                        Some(def) if def.unit == Unit::Map => {}
+                        Some(_) => walk(child, missing),
+                        None => {
+                            if !missing.contains(key) {
+                                missing.push(key.clone());
+                            }
+                            walk(child, missing);
+                        }
+                    }
+                }
+            }
+            serde_json::Value::Array(items) => {
+                for item in items {
+                    walk(item, missing);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut missing = Vec::new();
+    walk(value, &mut missing);
+    missing
+}
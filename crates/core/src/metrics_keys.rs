@@ -0,0 +1,94 @@
+//! 类型化的指标键。
+//!
+//! 各收集器用`metric_keys!`在一个表达式中同时声明键常量与字典定义：
+//! 常量挂在收集器的`*Keys`类型上（例如[`crate::FlagStatKeys::TOTAL`]），
+//! 同时生成本模块登记到[`crate::metrics`]的`METRICS`。代码中按常量引用键，
+//! 拼写错误在编译期报错，也不会出现"有键无定义"的情况。
+
+use std::fmt;
+#[cfg(feature = "serde")]
+use serde::Serialize;
+use crate::metrics::{lookup_metric, MetricDef};
+
+/// 指标键，只能通过各收集器的`*Keys`常量获得。
+///
+/// # Examples
+///
+/// ```
+/// use bamqc_core::{metric_dictionary, DuplicationKeys, FlagStatKeys, MetricKey, Unit};
+///
+/// assert_eq!(FlagStatKeys::SECONDARY_RATIO.as_str(), "secondary_ratio");
+/// assert_eq!(DuplicationKeys::PERCENT_DUPLICATION.def().unit, Unit::Fraction);
+/// assert!(FlagStatKeys::ALL.contains(&FlagStatKeys::TOTAL));
+///
+/// // 字典中的每个键都有对应的常量
+/// for def in metric_dictionary() {
+///     assert_eq!(MetricKey::parse(def.key).unwrap().as_str(), def.key);
+/// }
+/// assert!(MetricKey::parse("fold_80").is_none());
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct MetricKey(&'static str);
+
+impl MetricKey {
+    /// 供`metric_keys!`使用的构造函数。
+    #[doc(hidden)]
+    pub const fn declare(key: &'static str) -> Self {
+        Self(key)
+    }
+
+    /// 查找已登记的键；未登记时返回None。
+    pub fn parse(key: &str) -> Option<Self> {
+        lookup_metric(key).map(|def| Self(def.key))
+    }
+
+    /// JSON/TSV中的键。
+    pub const fn as_str(self) -> &'static str {
+        self.0
+    }
+
+    /// 字典中的定义（多个收集器登记了同一个键时为第一个）。
+    pub fn def(self) -> &'static MetricDef {
+        lookup_metric(self.0).expect("MetricKey只由metric_keys!与定义一起声明")
+    }
+}
+
+impl fmt::Display for MetricKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+/// 声明收集器的键常量类型与本模块的`METRICS`。
+///
+/// 每行为`常量名 = 键, 显示名称, 单位, 是否越高越好, 定义;`。
+macro_rules! metric_keys {
+    (
+        $(#[$attr:meta])*
+        $keys:ident for $collector:literal {
+            $($name:ident = $key:literal, $display:literal, $unit:expr, $higher:expr, $definition:literal;)*
+        }
+    ) => {
+        $(#[$attr])*
+        #[derive(Clone, Copy, Debug)]
+        pub struct $keys;
+
+        impl $keys {
+            $(
+                #[doc = $definition]
+                pub const $name: $crate::metrics_keys::MetricKey = $crate::metrics_keys::MetricKey::declare($key);
+            )*
+
+            /// 全部键（顺序与字典一致）。
+            pub const ALL: &'static [$crate::metrics_keys::MetricKey] = &[$(Self::$name),*];
+        }
+
+        /// 本模块输出的键（见[`crate::metrics`]）。
+        pub(crate) const METRICS: &[$crate::metrics::MetricDef] = &[
+            $($crate::metrics::metric($collector, $key, $display, $unit, $higher, $definition),)*
+        ];
+    };
+}
+
+pub(crate) use metric_keys;
@@ -0,0 +1,280 @@
+//! 没有primary记录的读长（all-secondary）的检测与提升。
+//!
+//! 某些比对软件的配置（常见于转录组比对）把多重比对读长的全部比对都标记为
+//! secondary，不输出primary。这些读长在所有“只统计primary”的指标中都会消失，
+//! 比对率等比例也随之偏低。
+//!
+//! # 检测
+//!
+//! 取文件开头[`DEFAULT_MISSING_PRIMARY_WINDOW`]条记录（不含QC失败记录）为抽样窗口，
+//! 窗口中有secondary记录、但没有primary记录的读名为候选；窗口之后读到这些读名的
+//! primary记录时将其移出候选，因此按坐标排序、primary位于窗口之外的读长不会
+//! 被误判。`missing_primary_fraction`为最终的候选数占窗口中读名数的比例，
+//! 超过阈值（默认[`DEFAULT_MAX_MISSING_PRIMARY_FRACTION`]）时给出警告。
+//!
+//! # 提升
+//!
+//! `--promote-best-secondary`只对按读名排序（`SO:queryname`）的输入生效：同名记录
+//! 相邻，按读名逐组缓存（每组最多[`MAX_NAME_GROUP_RECORDS`]条，超过时该读名不再
+//! 提升）。组内某个片段（read1、read2或非配对读）没有primary记录时，把该片段
+//! MAPQ最高（并列时取最先出现，MAPQ缺失的最低）的secondary记录当作primary计数。
+//! 其它排序的输入只做检测并警告，报告中的`primary_selection`为`detect_only`。
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use bamqc_io::bam::BamRecord;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use crate::formatting::Percent;
+use crate::log::warn;
+
+/// 检测使用的抽样窗口（记录数）。
+pub const DEFAULT_MISSING_PRIMARY_WINDOW: u64 = 100_000;
+
+/// 缺少primary的读名比例超过该值时警告。
+pub const DEFAULT_MAX_MISSING_PRIMARY_FRACTION: f64 = 0.01;
+
+/// 提升时单个读名最多缓存的记录数。
+pub const MAX_NAME_GROUP_RECORDS: usize = 10_000;
+
+/// 对缺少primary的读长的处理方式。
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(rename_all = "snake_case"))]
+pub enum PrimarySelection {
+    /// 只检测并警告，计数按FLAG不变。
+    DetectOnly,
+    /// 每个缺少primary的片段把最佳secondary记录当作primary计数。
+    PromoteBestSecondary,
+}
+
+impl fmt::Display for PrimarySelection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            PrimarySelection::DetectOnly => "detect_only",
+            PrimarySelection::PromoteBestSecondary => "promote_best_secondary",
+        })
+    }
+}
+
+/// 缺少primary的读长的检测结果与处理方式。
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MissingPrimaryReport {
+    /// 抽样窗口中的读名数。
+    #[cfg_attr(feature = "serde", serde(rename = "missing_primary_sampled_names"))]
+    pub sampled_names: u64,
+    /// 窗口中有secondary记录、整个文件中都没有primary记录的读名数。
+    pub missing_primary_names: u64,
+    /// missing_primary_names / sampled_names；窗口为空时为0。
+    pub missing_primary_fraction: f64,
+    /// 实际采用的处理方式。
+    pub primary_selection: PrimarySelection,
+    /// 被当作primary计数的secondary记录数。
+    pub promoted_secondary: u64,
+}
+
+impl fmt::Display for MissingPrimaryReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "missing_primary_names: {} / {} ({})",
+            self.missing_primary_names,
+            self.sampled_names,
+            Percent(self.missing_primary_fraction)
+        )?;
+        write!(f, "primary_selection: {}", self.primary_selection)?;
+        if self.primary_selection == PrimarySelection::PromoteBestSecondary {
+            write!(f, "\npromoted_secondary: {}", self.promoted_secondary)?;
+        }
+        Ok(())
+    }
+}
+
+/// 读名的记录类型：bit0为有primary，bit1为有secondary。
+const HAS_PRIMARY: u8 = 1;
+const HAS_SECONDARY: u8 = 2;
+
+/// 缺少primary的读名的抽样检测。
+///
+/// # Examples
+///
+/// ```
+/// use bamqc_core::MissingPrimaryDetector;
+/// use bamqc_io::{BamReader, BamWriter};
+/// use noodles::sam;
+///
+/// // 窗口为前3条记录：a、b有secondary；b的primary在窗口之后
+/// let path = std::env::temp_dir().join(format!("bamqc-missing-primary-{}.bam", std::process::id()));
+/// let text = "@HD\tVN:1.6\n@SQ\tSN:c1\tLN:1000\n\
+///             a\t256\tc1\t100\t1\t10M\t*\t0\t0\t*\t*\n\
+///             b\t256\tc1\t150\t1\t10M\t*\t0\t0\t*\t*\n\
+///             c\t0\tc1\t200\t60\t10M\t*\t0\t0\t*\t*\n\
+///             b\t0\tc1\t900\t1\t10M\t*\t0\t0\t*\t*\n";
+/// let mut sam_reader = sam::io::Reader::new(text.as_bytes());
+/// let header = sam_reader.read_header().unwrap();
+/// let mut writer = BamWriter::from_path(&path, &header).unwrap();
+/// for record in sam_reader.record_bufs(&header) {
+///     writer.write_record_buf(&record.unwrap()).unwrap();
+/// }
+/// writer.finish().unwrap();
+///
+/// let mut detector = MissingPrimaryDetector::new(3);
+/// for record in BamReader::from_path(&path).unwrap().records() {
+///     detector.update(&record.unwrap());
+/// }
+/// assert_eq!(detector.finish(), (3, 1));
+/// std::fs::remove_file(&path).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct MissingPrimaryDetector {
+    window: u64,
+    seen: u64,
+    names: HashMap<Vec<u8>, u8>,
+    sampled_names: u64,
+    candidates: HashSet<Vec<u8>>,
+}
+
+impl MissingPrimaryDetector {
+    /// 以开头`window`条记录为抽样窗口。
+    pub fn new(window: u64) -> Self {
+        Self { window, seen: 0, names: HashMap::new(), sampled_names: 0, candidates: HashSet::new() }
+    }
+
+    pub fn update(&mut self, record: &BamRecord) {
+        if record.is_qc_fail() || record.is_supplementary() {
+            return;
+        }
+        if self.seen < self.window {
+            self.seen += 1;
+            let kind = if record.is_secondary() { HAS_SECONDARY } else { HAS_PRIMARY };
+            *self.names.entry(record.qname().to_vec()).or_default() |= kind;
+            if self.seen == self.window {
+                self.close_window();
+            }
+        } else if !self.candidates.is_empty() && !record.is_secondary() {
+            self.candidates.remove(record.qname());
+        }
+    }
+
+    fn close_window(&mut self) {
+        self.sampled_names = self.names.len() as u64;
+        self.candidates = self
+            .names
+            .drain()
+            .filter(|&(_, kinds)| kinds == HAS_SECONDARY)
+            .map(|(name, _)| name)
+            .collect();
+    }
+
+    /// 结束检测，返回（窗口中的读名数，缺少primary的读名数）。
+    pub fn finish(&mut self) -> (u64, u64) {
+        if self.seen < self.window {
+            self.close_window();
+        }
+        (self.sampled_names, self.candidates.len() as u64)
+    }
+}
+
+/// 按读名排序的输入上，逐组缓存同名记录并选出需要提升的secondary记录。
+#[derive(Debug, Default)]
+pub struct NameGroupPromoter {
+    group: Vec<BamRecord>,
+    /// 超过缓存上限的读名，其后续记录直接输出、不提升
+    overflowed: Option<Vec<u8>>,
+    promoted: u64,
+    overflowed_groups: u64,
+}
+
+impl NameGroupPromoter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 送入下一条记录；读名变化时以`(记录, 是否提升)`输出上一组。
+    pub fn push(&mut self, record: BamRecord, emit: &mut impl FnMut(&BamRecord, bool)) {
+        if self.overflowed.as_deref() == Some(record.qname()) {
+            emit(&record, false);
+            return;
+        }
+        if self.group.first().is_some_and(|first| first.qname() != record.qname()) {
+            self.flush(emit);
+        }
+        if self.group.len() == MAX_NAME_GROUP_RECORDS {
+            for buffered in self.group.drain(..) {
+                emit(&buffered, false);
+            }
+            self.overflowed = Some(record.qname().to_vec());
+            self.overflowed_groups += 1;
+            emit(&record, false);
+            return;
+        }
+        self.overflowed = None;
+        self.group.push(record);
+    }
+
+    /// 输出最后一组，返回（被提升的记录数，超过缓存上限的读名数）。
+    pub fn finish(&mut self, emit: &mut impl FnMut(&BamRecord, bool)) -> (u64, u64) {
+        self.flush(emit);
+        (self.promoted, self.overflowed_groups)
+    }
+
+    fn flush(&mut self, emit: &mut impl FnMut(&BamRecord, bool)) {
+        // 按片段（FLAG的0x40与0x80位）分别选择：片段 -> 最佳secondary的下标，有primary时为None
+        let mut best: HashMap<(bool, bool), Option<usize>> = HashMap::new();
+        for (i, record) in self.group.iter().enumerate() {
+            if record.is_qc_fail() || record.is_supplementary() {
+                continue;
+            }
+            let segment = (record.is_first_segment(), record.is_last_segment());
+            if !record.is_secondary() {
+                best.insert(segment, None);
+                continue;
+            }
+            let slot = best.entry(segment).or_insert(Some(i));
+            if let Some(current) = slot {
+                if record.mapping_quality() > self.group[*current].mapping_quality() {
+                    *current = i;
+                }
+            }
+        }
+        let promoted: Vec<usize> = best.into_values().flatten().collect();
+        self.promoted += promoted.len() as u64;
+        for (i, record) in self.group.drain(..).enumerate() {
+            emit(&record, promoted.contains(&i));
+        }
+    }
+}
+
+/// 汇总检测与提升结果，需要时输出警告；没有检测到问题且未要求提升时返回None。
+pub(crate) fn missing_primary_report(
+    bam_path: &str,
+    detector: &mut MissingPrimaryDetector,
+    selection: Option<PrimarySelection>,
+    promoted_secondary: u64,
+    max_fraction: f64,
+) -> Option<MissingPrimaryReport> {
+    let (sampled_names, missing_primary_names) = detector.finish();
+    let missing_primary_fraction =
+        if sampled_names == 0 { 0.0 } else { missing_primary_names as f64 / sampled_names as f64 };
+    let detected = missing_primary_fraction > max_fraction;
+    if detected {
+        warn!(
+            "{} 中抽样的 {} 个读名里有 {}（{}）只有secondary记录、没有primary记录，\
+             只统计primary的指标会漏掉这些读长；按读名排序后可使用--promote-best-secondary",
+            bam_path,
+            sampled_names,
+            missing_primary_names,
+            Percent(missing_primary_fraction)
+        );
+    }
+    if !detected && selection.is_none() {
+        return None;
+    }
+    Some(MissingPrimaryReport {
+        sampled_names,
+        missing_primary_names,
+        missing_primary_fraction,
+        primary_selection: selection.unwrap_or(PrimarySelection::DetectOnly),
+        promoted_secondary,
+    })
+}
@@ -0,0 +1,272 @@
+//! 进度事件。
+//!
+//! 嵌入bamqc_core的程序（例如桌面GUI）通过实现[`ProgressSink`]接收进度回调，
+//! 计算函数在记录循环中经[`ProgressReporter`]发出事件。限速由`ProgressReporter`
+//! 负责而不是由sink负责：每[`PROGRESS_CHECK_RECORDS`]条记录才读取一次时钟，距上次
+//! 事件不到[`ProgressSink::interval`]时不调用sink，sink的实现不需要自己节流。
+//!
+//! # 线程
+//!
+//! 回调总是在调用计算函数的线程上按顺序调用（方法都取`&mut self`），不会被
+//! 多个线程并发调用，因此sink不需要`Sync`。需要把事件送到其它线程（GUI的主线程、
+//! 异步运行时）时使用[`ChannelProgressSink`]。
+//!
+//! # 事件顺序
+//!
+//! 一次计算依次发出：`on_start`一次；每个读取阶段一次`on_phase_change`，随后
+//! 若干次`on_progress`（记录数从该阶段开始计）；结束时最后一次`on_progress`
+//! （不受限速，报告最终记录数）与`on_finish`。计算出错时不发出`on_finish`。
+//!
+//! 命令行使用[`LogProgressSink`]把进度写入debug日志。
+
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+use crate::log::debug;
+
+/// 默认的最短事件间隔。
+pub const DEFAULT_PROGRESS_INTERVAL: Duration = Duration::from_millis(200);
+
+/// [`LogProgressSink`]的最短事件间隔。
+pub const LOG_PROGRESS_INTERVAL: Duration = Duration::from_secs(10);
+
+/// 每隔这么多条记录检查一次是否需要发出`on_progress`。
+pub const PROGRESS_CHECK_RECORDS: u64 = 4096;
+
+/// 一次计算结束时的汇总。
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ProgressSummary {
+    /// 最后一个阶段读取的记录数。
+    pub records: u64,
+    /// 从`on_start`起经过的时间。
+    pub elapsed: Duration,
+}
+
+/// 进度事件的接收方。所有方法都有空的默认实现。
+pub trait ProgressSink {
+    /// 两次`on_progress`之间的最短间隔，由[`ProgressReporter`]执行。
+    fn interval(&self) -> Duration {
+        DEFAULT_PROGRESS_INTERVAL
+    }
+
+    /// 开始计算；`total_hint`为输入文件的字节数之和（标准输入时为None），
+    /// 可与`on_progress`的`bytes`比较估计完成比例。
+    fn on_start(&mut self, _total_hint: Option<u64>) {}
+
+    /// 当前阶段已读取`records`条记录与`bytes`个压缩字节（多个输入时从第一个输入起累计，
+    /// 无法得知读取位置时为0）。
+    fn on_progress(&mut self, _records: u64, _bytes: u64) {}
+
+    /// 进入新的读取阶段（例如`duplicate-names`、`insert-size`）。
+    fn on_phase_change(&mut self, _name: &str) {}
+
+    /// 计算成功结束。
+    fn on_finish(&mut self, _summary: &ProgressSummary) {}
+}
+
+/// 不接收任何事件。
+impl ProgressSink for () {}
+
+/// 计算函数一侧的事件发送与限速。
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+/// use bamqc_core::{ChannelProgressSink, ProgressEvent, ProgressReporter, ProgressSink};
+///
+/// let run = |interval| {
+///     let (mut sink, events) = ChannelProgressSink::with_interval(interval);
+///     let mut reporter = ProgressReporter::new(&mut sink);
+///     reporter.start(Some(1000));
+///     reporter.phase("scan");
+///     for records in 1..=10_000 {
+///         reporter.tick(records, || records / 10);
+///     }
+///     reporter.finish(10_000, 1000);
+///     drop(sink);
+///     events.into_iter().collect::<Vec<_>>()
+/// };
+///
+/// // 间隔为0：每4096条记录检查一次，每次都发出
+/// let events = run(Duration::ZERO);
+/// assert_eq!(events[..2], [ProgressEvent::Start { total_hint: Some(1000) }, ProgressEvent::PhaseChange("scan".into())]);
+/// let progress: Vec<u64> = events.iter().filter_map(|e| match e {
+///     ProgressEvent::Progress { records, .. } => Some(*records),
+///     _ => None,
+/// }).collect();
+/// assert_eq!(progress, [4096, 8192, 10_000]);
+/// assert!(events.contains(&ProgressEvent::Progress { records: 8192, bytes: 819 }));
+/// assert!(matches!(events.last(), Some(ProgressEvent::Finish(summary)) if summary.records == 10_000));
+///
+/// // 间隔很长：只有结束时的一次
+/// let events = run(Duration::from_secs(3600));
+/// assert_eq!(events.iter().filter(|e| matches!(e, ProgressEvent::Progress { .. })).count(), 1);
+/// ```
+pub struct ProgressReporter<'a> {
+    sink: &'a mut dyn ProgressSink,
+    interval: Duration,
+    started: Instant,
+    last_emit: Instant,
+}
+
+impl<'a> ProgressReporter<'a> {
+    pub fn new(sink: &'a mut dyn ProgressSink) -> Self {
+        let now = Instant::now();
+        let interval = sink.interval();
+        Self { sink, interval, started: now, last_emit: now }
+    }
+
+    /// 发出`on_start`并开始计时。
+    pub fn start(&mut self, total_hint: Option<u64>) {
+        self.started = Instant::now();
+        self.last_emit = self.started;
+        self.sink.on_start(total_hint);
+    }
+
+    /// 发出`on_phase_change`。
+    pub fn phase(&mut self, name: &str) {
+        self.sink.on_phase_change(name);
+    }
+
+    /// 在记录循环中调用；未到检查点或间隔未到时只做一次取模比较。
+    ///
+    /// `bytes`给出正在迭代的读取器的已读字节数（例如[`bamqc_io::MultiBamReader::bytes_read`]），
+    /// 只在发出事件时调用。
+    #[inline]
+    pub fn tick(&mut self, records: u64, bytes: impl FnOnce() -> u64) {
+        if records.is_multiple_of(PROGRESS_CHECK_RECORDS) && self.last_emit.elapsed() >= self.interval {
+            self.emit(records, bytes());
+        }
+    }
+
+    /// 发出最后一次`on_progress`与`on_finish`。
+    pub fn finish(mut self, records: u64, bytes: u64) {
+        self.emit(records, bytes);
+        let summary = ProgressSummary { records, elapsed: self.started.elapsed() };
+        self.sink.on_finish(&summary);
+    }
+
+    fn emit(&mut self, records: u64, bytes: u64) {
+        self.sink.on_progress(records, bytes);
+        self.last_emit = Instant::now();
+    }
+}
+
+/// 经过通道发送的进度事件，与[`ProgressSink`]的方法一一对应。
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ProgressEvent {
+    /// [`ProgressSink::on_start`]
+    Start { total_hint: Option<u64> },
+    /// [`ProgressSink::on_progress`]
+    Progress { records: u64, bytes: u64 },
+    /// [`ProgressSink::on_phase_change`]
+    PhaseChange(String),
+    /// [`ProgressSink::on_finish`]
+    Finish(ProgressSummary),
+}
+
+/// 把事件转发到[`mpsc`]通道的sink，供异步UI在其它线程接收。
+///
+/// 接收端被丢弃后事件被静默丢弃，不影响计算。
+#[derive(Clone, Debug)]
+pub struct ChannelProgressSink {
+    sender: mpsc::Sender<ProgressEvent>,
+    interval: Duration,
+}
+
+impl ChannelProgressSink {
+    /// 使用[`DEFAULT_PROGRESS_INTERVAL`]的sink与对应的接收端。
+    pub fn new() -> (Self, mpsc::Receiver<ProgressEvent>) {
+        Self::with_interval(DEFAULT_PROGRESS_INTERVAL)
+    }
+
+    /// 指定最短事件间隔的sink与对应的接收端。
+    pub fn with_interval(interval: Duration) -> (Self, mpsc::Receiver<ProgressEvent>) {
+        let (sender, receiver) = mpsc::channel();
+        (Self { sender, interval }, receiver)
+    }
+
+    fn send(&self, event: ProgressEvent) {
+        let _ = self.sender.send(event);
+    }
+}
+
+impl ProgressSink for ChannelProgressSink {
+    fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    fn on_start(&mut self, total_hint: Option<u64>) {
+        self.send(ProgressEvent::Start { total_hint });
+    }
+
+    fn on_progress(&mut self, records: u64, bytes: u64) {
+        self.send(ProgressEvent::Progress { records, bytes });
+    }
+
+    fn on_phase_change(&mut self, name: &str) {
+        self.send(ProgressEvent::PhaseChange(name.to_string()));
+    }
+
+    fn on_finish(&mut self, summary: &ProgressSummary) {
+        self.send(ProgressEvent::Finish(*summary));
+    }
+}
+
+/// 进度日志的文本：已知完成比例时为"已处理 45%（123,000,000 条记录）"，
+/// 否则（标准输入等）只有记录数。
+///
+/// # Examples
+///
+/// ```
+/// use bamqc_core::progress_message;
+///
+/// assert_eq!(progress_message(123_000_000, Some(0.4512)), "已处理 45%（123,000,000 条记录）");
+/// assert_eq!(progress_message(999, None), "已处理 999 条记录");
+/// ```
+pub fn progress_message(records: u64, fraction: Option<f64>) -> String {
+    let digits = records.to_string();
+    let mut grouped = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            grouped.push(',');
+        }
+        grouped.push(c);
+    }
+    match fraction {
+        Some(fraction) => format!("已处理 {:.0}%（{} 条记录）", fraction * 100.0, grouped),
+        None => format!("已处理 {} 条记录", grouped),
+    }
+}
+
+/// 把进度写入debug日志的sink（命令行使用）。
+///
+/// `on_start`给出文件字节数时，日志中带有按压缩偏移估计的完成比例。
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LogProgressSink {
+    total: Option<u64>,
+}
+
+impl ProgressSink for LogProgressSink {
+    fn interval(&self) -> Duration {
+        LOG_PROGRESS_INTERVAL
+    }
+
+    fn on_start(&mut self, total_hint: Option<u64>) {
+        self.total = total_hint.filter(|&total| total > 0);
+    }
+
+    fn on_progress(&mut self, records: u64, bytes: u64) {
+        // 无法得知读取位置的输入（SAM文本、CRAM）没有字节数
+        let fraction = self.total.filter(|_| bytes > 0).map(|total| (bytes as f64 / total as f64).min(1.0));
+        debug!("{}", progress_message(records, fraction));
+    }
+
+    fn on_phase_change(&mut self, name: &str) {
+        debug!("开始读取阶段: {}", name);
+    }
+
+    fn on_finish(&mut self, summary: &ProgressSummary) {
+        debug!("读取完成：{} 条记录，耗时 {:.3} 秒", summary.records, summary.elapsed.as_secs_f64());
+    }
+}
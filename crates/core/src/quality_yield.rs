@@ -0,0 +1,278 @@
+//! 碱基质量产出（与Picard CollectQualityYieldMetrics的定义一致）。
+//!
+//! BQSR会改写QUAL，重校准后的Q20/Q30碱基数与BQSR前（例如FastQC）的结果
+//! 不可比。`QualitySource::Oq`时从OQ:Z标签读取原始质量值；记录没有OQ或
+//! OQ无效（非法字符、长度与SEQ不一致）时回退到QUAL并计数。
+
+use std::fmt;
+use bamqc_io::bam::{BamReaderOptions, BamError, BamRecord};
+#[cfg(feature = "serde")]
+use serde::Serialize;
+use crate::formatting::PicardFloat;
+use crate::groups::{AccumulationLevel, GroupInterner};
+use crate::log::{info, warn};
+use crate::metrics::Unit;
+use crate::metrics_keys::metric_keys;
+
+/// 质量值来源。
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "UPPERCASE"))]
+pub enum QualitySource {
+    /// QUAL字段（BQSR后为重校准质量值）。
+    #[default]
+    Qual,
+    /// OQ:Z标签中的原始质量值。
+    Oq,
+}
+
+impl fmt::Display for QualitySource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QualitySource::Qual => write!(f, "QUAL"),
+            QualitySource::Oq => write!(f, "OQ"),
+        }
+    }
+}
+
+/// 碱基质量产出报告。
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct QualityYieldReport {
+    /// 样本名（按样本分别统计时）。
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub sample: Option<String>,
+    /// 质量值来源。
+    pub quality_source: QualitySource,
+    /// 统计的primary读长数。
+    pub total_reads: u64,
+    /// 碱基总数。
+    pub total_bases: u64,
+    /// 质量值不低于20的碱基数。
+    pub q20_bases: u64,
+    /// 质量值不低于30的碱基数。
+    pub q30_bases: u64,
+    /// 所有碱基质量值之和。
+    pub q_sum: u64,
+    /// 没有质量值（QUAL为`*`）的读长数，不计入碱基统计。
+    pub no_quality_reads: u64,
+    /// 请求OQ但记录没有OQ标签、回退到QUAL的读长数。
+    pub oq_missing: u64,
+    /// 请求OQ但OQ无效（非法字符或长度与SEQ不一致）、回退到QUAL的读长数。
+    pub oq_invalid: u64,
+    /// 输入没有任何记录（只有头部）。与“记录全部是secondary/supplementary”区分，
+    /// 后者`total_reads`同样为0，但这里为false。
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "std::ops::Not::not"))]
+    pub no_records: bool,
+}
+
+impl QualityYieldReport {
+    /// 回退到QUAL的读长总数。
+    pub fn fallback_reads(&self) -> u64 {
+        self.oq_missing + self.oq_invalid
+    }
+
+    /// Q30碱基占比，没有碱基时返回0。
+    pub fn q30_fraction(&self) -> f64 {
+        if self.total_bases == 0 {
+            0.0
+        } else {
+            self.q30_bases as f64 / self.total_bases as f64
+        }
+    }
+
+    fn log_summary(&self) {
+        if self.oq_missing > 0 {
+            warn!("{} 条读长没有OQ标签，已改用QUAL", self.oq_missing);
+        }
+        if self.oq_invalid > 0 {
+            warn!("{} 条读长的OQ标签无效（非法字符或长度与SEQ不一致），已改用QUAL", self.oq_invalid);
+        }
+        info!("统计完成：{} 条读长，{} 个碱基，Q30 {}", self.total_reads, self.total_bases, self.q30_bases);
+    }
+
+    /// 计入一条读长的质量值。
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bamqc_core::QualityYieldReport;
+    ///
+    /// let mut report = QualityYieldReport::default();
+    /// report.add_qualities(&[10, 20, 30, 40]);
+    /// assert_eq!(report.total_bases, 4);
+    /// assert_eq!(report.q20_bases, 3);
+    /// assert_eq!(report.q30_bases, 2);
+    /// assert_eq!(report.q_sum, 100);
+    /// ```
+    pub fn add_qualities(&mut self, qualities: &[u8]) {
+        self.total_reads += 1;
+        self.total_bases += qualities.len() as u64;
+        for &q in qualities {
+            if q >= 20 {
+                self.q20_bases += 1;
+            }
+            if q >= 30 {
+                self.q30_bases += 1;
+            }
+            self.q_sum += q as u64;
+        }
+    }
+
+    fn add_record(&mut self, record: &BamRecord) {
+        if self.quality_source == QualitySource::Oq {
+            if let Some(oq) = record.original_qualities() {
+                self.add_qualities(&oq);
+                return;
+            }
+            if record.string_tag(*b"OQ").is_some() {
+                self.oq_invalid += 1;
+            } else {
+                self.oq_missing += 1;
+            }
+        }
+
+        match record.base_qualities() {
+            Some(qualities) => self.add_qualities(&qualities),
+            None => self.no_quality_reads += 1,
+        }
+    }
+}
+
+impl fmt::Display for QualityYieldReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(sample) = &self.sample {
+            writeln!(f, "sample: {}", sample)?;
+        }
+        writeln!(f, "quality_source: {}", self.quality_source)?;
+        writeln!(f, "total_reads: {}", self.total_reads)?;
+        writeln!(f, "total_bases: {}", self.total_bases)?;
+        writeln!(f, "q20_bases: {}", self.q20_bases)?;
+        writeln!(f, "q30_bases: {}", self.q30_bases)?;
+        writeln!(f, "q30_fraction: {}", PicardFloat(self.q30_fraction()))?;
+        writeln!(f, "q_sum: {}", self.q_sum)?;
+        write!(f, "no_quality_reads: {}", self.no_quality_reads)?;
+        if self.quality_source == QualitySource::Oq {
+            write!(f, "\noq_missing: {}", self.oq_missing)?;
+            write!(f, "\noq_invalid: {}", self.oq_invalid)?;
+        }
+        if self.no_records {
+            write!(f, "\nno_records: true")?;
+        }
+        Ok(())
+    }
+}
+
+/// 统计primary读长的碱基质量产出。
+///
+/// # Examples
+///
+/// 只有头部的BAM得到全0的报告，并带`no_records`标记：
+///
+/// ```
+/// use bamqc_core::{compute_quality_yield, QualitySource};
+/// use bamqc_io::bam::{BamReaderOptions, BamWriter};
+///
+/// let bam = std::env::temp_dir().join(format!("bamqc-yield-empty-{}.bam", std::process::id()));
+/// let header = "@HD\tVN:1.6\n@SQ\tSN:c1\tLN:1000\n".parse().unwrap();
+/// BamWriter::from_path(&bam, &header).unwrap().finish().unwrap();
+///
+/// let report = compute_quality_yield(&bam.to_string_lossy(), &BamReaderOptions::new(), QualitySource::Qual).unwrap();
+/// assert!(report.no_records);
+/// assert_eq!((report.total_reads, report.total_bases, report.q30_fraction()), (0, 0, 0.0));
+/// assert!(report.to_string().ends_with("no_quality_reads: 0\nno_records: true"));
+/// std::fs::remove_file(&bam).unwrap();
+/// ```
+pub fn compute_quality_yield(
+    bam_path: &str,
+    reader: &BamReaderOptions,
+    source: QualitySource,
+) -> Result<QualityYieldReport, BamError> {
+    let mut reader = reader.open(bam_path)?;
+    let mut report = QualityYieldReport {
+        quality_source: source,
+        ..Default::default()
+    };
+
+    info!("开始统计碱基质量产出（质量值来源: {}）: {}", source, bam_path);
+
+    report.no_records = true;
+    for result in reader.records() {
+        let record = result?;
+        report.no_records = false;
+        if record.is_secondary() || record.is_supplementary() {
+            continue;
+        }
+        report.add_record(&record);
+    }
+
+    report.log_summary();
+    Ok(report)
+}
+
+/// 按样本分别统计碱基质量产出，顺序与头部中的样本一致。
+///
+/// 头部中声明的每个样本都有一份报告（即使没有读长）；记录中出现的未声明
+/// 读组与缺少RG标签的记录归入`unknown`样本。
+pub fn compute_quality_yield_per_sample(
+    bam_path: &str,
+    reader: &BamReaderOptions,
+    source: QualitySource,
+) -> Result<Vec<QualityYieldReport>, BamError> {
+    let mut reader = reader.open(bam_path)?;
+    let mut groups = GroupInterner::from_header(reader.header());
+    let new_report = || QualityYieldReport {
+        quality_source: source,
+        ..Default::default()
+    };
+    let mut reports: Vec<QualityYieldReport> = (0..groups.sample_count()).map(|_| new_report()).collect();
+
+    info!("开始按样本统计碱基质量产出（质量值来源: {}）: {}", source, bam_path);
+
+    let mut no_records = true;
+    for result in reader.records() {
+        let record = result?;
+        no_records = false;
+        if record.is_secondary() || record.is_supplementary() {
+            continue;
+        }
+        let rg = groups
+            .record_read_group_key(&record)
+            .expect("非严格模式不会报错");
+        let key = groups.level_key(rg, AccumulationLevel::Sample);
+        if key >= reports.len() {
+            reports.resize_with(key + 1, new_report);
+        }
+        reports[key].add_record(&record);
+    }
+
+    groups.warn_unknown(bam_path);
+    for (key, report) in reports.iter_mut().enumerate() {
+        let sample = groups.name(AccumulationLevel::Sample, key).to_string();
+        info!("样本 {}:", sample);
+        report.log_summary();
+        report.sample = Some(sample);
+        report.no_records = no_records;
+    }
+    Ok(reports)
+}
+
+metric_keys! {
+    /// `quality-yield`输出的键（见[`crate::metrics`]）。
+    QualityYieldKeys for "quality-yield" {
+        SAMPLE = "sample", "样本名", Unit::Label, None, "头部@RG的SM；多样本BAM按样本分别输出时出现。";
+        SAMPLES = "samples", "各样本结果", Unit::List, None, "多样本BAM按样本输出到标准输出时，每个样本一份JSON结果。";
+        QUALITY_SOURCE = "quality_source", "质量值来源", Unit::Label, None, "QUAL或BQSR前的OQ原始质量值。";
+        TOTAL_READS = "total_reads", "读长数", Unit::Reads, None, "参与统计的primary读长数。";
+        TOTAL_BASES = "total_bases", "碱基数", Unit::Bases, None, "有质量值的碱基总数。";
+        Q20_BASES = "q20_bases", "Q20碱基数", Unit::Bases, Some(true), "质量值不低于20的碱基数。";
+        Q30_BASES = "q30_bases", "Q30碱基数", Unit::Bases, Some(true), "质量值不低于30的碱基数。";
+        Q30_FRACTION = "q30_fraction", "Q30碱基占比", Unit::Fraction, Some(true), "q30_bases / total_bases。";
+        Q_SUM = "q_sum", "质量值之和", Unit::Phred, None, "所有碱基质量值之和。";
+        NO_QUALITY_READS = "no_quality_reads", "无质量值的读长数", Unit::Reads, Some(false), "QUAL为*的读长数。";
+        OQ_MISSING = "oq_missing", "缺少OQ的读长数", Unit::Reads, Some(false), "选用OQ时缺少OQ标签、回退到QUAL的读长数。";
+        OQ_INVALID = "oq_invalid", "OQ无效的读长数", Unit::Reads, Some(false), "选用OQ时OQ长度与序列不符或含非法字符、回退到QUAL的读长数。";
+        NO_RECORDS = "no_records", "输入为空", Unit::Boolean, None, "输入只有头部、没有任何记录时为true（各项计数都为0）；有记录时不输出。";
+    }
+}
@@ -0,0 +1,152 @@
+//! 快速检查：头部、BGZF EOF标记以及索引是否过期；小BAM还核对索引中的记录计数（见[`BamIndex::check`]）。
+//! CRAM检查EOF容器与`.crai`索引（只比较修改时间，见[`check_crai`]）。
+//!
+//! 有索引时还检查比对超出参考序列末端的记录：只查询每条参考序列的最后一个
+//! 位置及其之后，越界的记录必然在其中（见[`ContigBounds`]）。
+
+use std::fmt;
+use bamqc_io::bam::{BamReader, BamReaderOptions, BamError};
+use bamqc_io::region::GenomicRegion;
+use bamqc_io::format::Format;
+use bamqc_io::index::{BamIndex, IndexFormat, IndexProblem, check_crai, has_bgzf_eof, has_cram_eof};
+#[cfg(feature = "serde")]
+use serde::Serialize;
+use crate::bounds::ContigBounds;
+use crate::log::warn;
+use crate::metrics::Unit;
+use crate::metrics_keys::metric_keys;
+
+/// 快速检查结果。
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct QuickCheckReport {
+    /// BAM文件路径。
+    pub path: String,
+    /// 头部中的参考序列数。
+    pub references: usize,
+    /// 头部中的读组数。
+    pub read_groups: usize,
+    /// 文件是否以BGZF EOF标记（CRAM为EOF容器）结尾。
+    pub eof_marker: bool,
+    /// 找到的索引文件。
+    pub index: Option<String>,
+    /// 索引格式。
+    pub index_format: Option<IndexFormat>,
+    /// 索引与BAM不一致的迹象。
+    pub index_problems: Vec<IndexProblem>,
+    /// 比对超出参考序列末端的记录数；没有可用的索引时不检查，为None。
+    pub past_contig_end: Option<u64>,
+}
+
+impl QuickCheckReport {
+    /// 是否没有发现任何问题。
+    pub fn is_ok(&self) -> bool {
+        self.eof_marker && self.index_problems.is_empty() && self.past_contig_end.unwrap_or(0) == 0
+    }
+}
+
+impl fmt::Display for QuickCheckReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "path: {}", self.path)?;
+        writeln!(f, "references: {}", self.references)?;
+        writeln!(f, "read_groups: {}", self.read_groups)?;
+        writeln!(f, "eof_marker: {}", if self.eof_marker { "ok" } else { "missing" })?;
+        match (&self.index, self.index_format) {
+            (Some(index), Some(format)) => writeln!(f, "index: {} ({})", index, format)?,
+            _ => writeln!(f, "index: none")?,
+        }
+        for problem in &self.index_problems {
+            writeln!(f, "index_problem: {}", problem)?;
+        }
+        match self.past_contig_end {
+            Some(count) => writeln!(f, "past_contig_end: {}", count)?,
+            None => writeln!(f, "past_contig_end: unchecked")?,
+        }
+        write!(f, "status: {}", if self.is_ok() { "ok" } else { "warning" })
+    }
+}
+
+/// 快速检查BAM/CRAM文件，只读取头部、文件末尾和索引；有索引时另外读取各参考序列末端的记录，
+/// BAM较小时还扫描全部记录核对索引的记录计数。
+pub fn compute_quick_check(bam_path: &str, reader: &BamReaderOptions) -> Result<QuickCheckReport, BamError> {
+    let mut reader = reader.open(bam_path)?;
+    let header = reader.header();
+
+    let cram = reader.input_format() == Format::Cram;
+    let eof_marker = if cram { has_cram_eof(bam_path)? } else { has_bgzf_eof(bam_path)? };
+    if !eof_marker {
+        warn!("{} 缺少{}，文件可能被截断", bam_path, if cram { "CRAM EOF容器" } else { "BGZF EOF标记" });
+    }
+
+    let mut report = QuickCheckReport {
+        path: bam_path.to_string(),
+        references: header.reference_sequences().len(),
+        read_groups: header.read_groups().len(),
+        eof_marker,
+        index: None,
+        index_format: None,
+        index_problems: Vec::new(),
+        past_contig_end: None,
+    };
+
+    if cram {
+        if let Some(path) = BamIndex::locate_crai(bam_path) {
+            report.index_problems = check_crai(&path, bam_path)?;
+            for problem in &report.index_problems {
+                warn!("索引 {} 可能已过期: {}", path.display(), problem);
+            }
+            report.index = Some(path.to_string_lossy().to_string());
+            report.index_format = Some(IndexFormat::Crai);
+        }
+    } else if let Some(path) = BamIndex::locate(bam_path) {
+        let index = BamIndex::open(&path)?;
+        report.index_problems = index.check(bam_path, header)?;
+        for problem in &report.index_problems {
+            warn!("索引 {} 可能已过期: {}", path.display(), problem);
+        }
+        report.index = Some(path.to_string_lossy().to_string());
+        report.index_format = Some(index.format);
+    }
+
+    // 索引与BAM不一致时查询结果不可信，不检查
+    if report.index.is_some() && report.index_problems.is_empty() {
+        let past_end = count_past_contig_end(&mut reader)?;
+        if past_end > 0 {
+            warn!("{} 条记录的比对超出参考序列末端", past_end);
+        }
+        report.past_contig_end = Some(past_end);
+    }
+
+    Ok(report)
+}
+
+/// 查询每条参考序列的最后一个位置及其之后：越界的记录起点超出末端或跨过末端，都与之重叠
+fn count_past_contig_end(reader: &mut BamReader) -> Result<u64, BamError> {
+    let mut bounds = ContigBounds::from_header(reader.header());
+    let tails: Vec<GenomicRegion> = reader
+        .references()
+        .map(|(name, length)| GenomicRegion { name: name.to_string(), start: length.max(1) as usize, end: None, label: None })
+        .collect();
+    for region in &tails {
+        for result in reader.query(region)? {
+            bounds.check(&result?);
+        }
+    }
+    Ok(bounds.past_end())
+}
+
+metric_keys! {
+    /// `quick-check`输出的键（见[`crate::metrics`]）。
+    QuickCheckKeys for "quick-check" {
+        PATH = "path", "文件路径", Unit::Label, None, "检查的BAM路径。";
+        REFERENCES = "references", "参考序列数", Unit::Count, None, "头部中@SQ的数量。";
+        READ_GROUPS = "read_groups", "读组数", Unit::Count, None, "头部中@RG的数量。";
+        EOF_MARKER = "eof_marker", "EOF标记", Unit::Boolean, Some(true), "文件末尾是否有BGZF EOF块（CRAM为EOF容器），缺失通常表示文件被截断。";
+        INDEX = "index", "索引路径", Unit::Label, None, "找到的索引文件，没有时为null。";
+        INDEX_FORMAT = "index_format", "索引格式", Unit::Label, None, "BAI、CSI或CRAI。";
+        INDEX_PROBLEMS = "index_problems", "索引问题", Unit::Map, Some(false), "索引与BAM不一致的迹象（过期、参考序列数不符、偏移超出文件、记录数不符）。";
+        INDEX_PROBLEM = "index_problem", "索引问题", Unit::Label, Some(false), "文本输出中的一项索引问题。";
+        PAST_CONTIG_END = "past_contig_end", "超出参考序列末端的记录数", Unit::Reads, Some(false), "通过索引查询各参考序列末端得到；没有索引或索引有问题时不检查（JSON为null，文本为unchecked）。";
+        STATUS = "status", "检查结果", Unit::Label, None, "ok或warning。";
+    }
+}
@@ -0,0 +1,330 @@
+//! 记录流的迭代器组合子。
+//!
+//! [`RecordStream`]为任何产出`Result<BamRecord, BamError>`的迭代器（[`BamReader::records`]、
+//! [`BamReader::query`]以及它们的组合）提供过滤、抽样、取消、进度与限量的适配器。
+//! 每个方法返回具体的适配器类型，组合后仍是静态分发；错误原样向下游传递，
+//! 不被过滤、抽样或计数。
+//!
+//! ```
+//! use bamqc_core::{generate_test_data, FlagStat, RecordFilter, RecordStream, SimulationParams};
+//! use bamqc_io::bam::BamReader;
+//!
+//! let bam = std::env::temp_dir().join(format!("bamqc-record-stream-{}.bam", std::process::id()));
+//! let bam = bam.to_string_lossy().to_string();
+//! generate_test_data(&bam, &SimulationParams { pairs: 500, ..Default::default() }).unwrap();
+//!
+//! let mut reader = BamReader::from_path(&bam).unwrap();
+//! let filter = RecordFilter { exclude_flags: 0x400, ..Default::default() };
+//! let mut stat = FlagStat::new();
+//! for record in reader.records().filtered(filter).sampled(0.5, 7).take_records(200) {
+//!     stat.update(&record.unwrap());
+//! }
+//! assert!(stat.to_string().starts_with("total: 200\n"));
+//! assert!(stat.to_string().contains("duplicate: 0\n"));
+//! std::fs::remove_file(&bam).unwrap();
+//! ```
+//!
+//! [`BamReader::records`]: bamqc_io::bam::BamReader::records
+//! [`BamReader::query`]: bamqc_io::bam::BamReader::query
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use bamqc_io::bam::{BamError, BamRecord, keeps_read_name};
+use crate::filter::RecordFilter;
+use crate::progress::{ProgressReporter, ProgressSink};
+
+/// 记录流上的组合子，对所有产出`Result<BamRecord, BamError>`的迭代器实现。
+pub trait RecordStream: Iterator<Item = Result<BamRecord, BamError>> + Sized {
+    /// 只保留通过`filter`的记录。
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bamqc_core::{generate_test_data, RecordFilter, RecordStream, SimulationParams};
+    /// use bamqc_io::bam::BamReader;
+    ///
+    /// let bam = std::env::temp_dir().join(format!("bamqc-filtered-{}.bam", std::process::id()));
+    /// let bam = bam.to_string_lossy().to_string();
+    /// generate_test_data(&bam, &SimulationParams { pairs: 200, ..Default::default() }).unwrap();
+    ///
+    /// // 只要read1（0x40），排除反向链（0x10）
+    /// let filter = RecordFilter { require_flags: 0x40, exclude_flags: 0x10, min_mapq: 0 };
+    /// let mut reader = BamReader::from_path(&bam).unwrap();
+    /// let kept: Vec<_> = reader.records().filtered(filter).map(|r| r.unwrap()).collect();
+    /// assert!(!kept.is_empty() && kept.len() < 200);
+    /// assert!(kept.iter().all(|r| r.is_first_segment() && !r.is_reverse()));
+    /// std::fs::remove_file(&bam).unwrap();
+    /// ```
+    fn filtered(self, filter: RecordFilter) -> Filtered<Self> {
+        Filtered { inner: self, filter }
+    }
+
+    /// 按读名以概率`rate`抽样，同一读名的记录（读对的两端、secondary等）同进同出。
+    ///
+    /// 是否保留只取决于`seed`与读名，与记录顺序无关：相同种子在同一文件的
+    /// 任何区域上得到一致的抽样。`rate`不小于1时保留全部记录，不大于0时全部丢弃。
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bamqc_core::{generate_test_data, sample_keeps, RecordStream, SimulationParams};
+    /// use bamqc_io::bam::BamReader;
+    ///
+    /// let bam = std::env::temp_dir().join(format!("bamqc-sampled-{}.bam", std::process::id()));
+    /// let bam = bam.to_string_lossy().to_string();
+    /// generate_test_data(&bam, &SimulationParams { pairs: 2000, ..Default::default() }).unwrap();
+    ///
+    /// let sample = |seed| -> Vec<String> {
+    ///     let mut reader = BamReader::from_path(&bam).unwrap();
+    ///     reader.records().sampled(0.25, seed).map(|r| r.unwrap().name().into_owned()).collect()
+    /// };
+    /// let names = sample(1);
+    /// // 两端同进同出，约四分之一的读对
+    /// assert_eq!(names.len() % 2, 0);
+    /// assert!((800..1200).contains(&names.len()), "{}", names.len());
+    /// assert!(names.iter().all(|name| sample_keeps(name.as_bytes(), 0.25, 1)));
+    /// assert_eq!(sample(1), names);
+    /// assert_ne!(sample(2), names);
+    /// std::fs::remove_file(&bam).unwrap();
+    /// ```
+    fn sampled(self, rate: f64, seed: u64) -> Sampled<Self> {
+        Sampled { inner: self, rate, seed }
+    }
+
+    /// `token`被取消后不再读取记录，迭代随即结束；用[`UntilCancelled::cancelled`]
+    /// 区分提前结束与读完。
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bamqc_core::{generate_test_data, CancellationToken, RecordStream, SimulationParams};
+    /// use bamqc_io::bam::BamReader;
+    ///
+    /// let bam = std::env::temp_dir().join(format!("bamqc-cancel-{}.bam", std::process::id()));
+    /// let bam = bam.to_string_lossy().to_string();
+    /// generate_test_data(&bam, &SimulationParams { pairs: 200, ..Default::default() }).unwrap();
+    ///
+    /// let token = CancellationToken::new();
+    /// let mut reader = BamReader::from_path(&bam).unwrap();
+    /// let mut records = reader.records().until_cancelled(token.clone());
+    /// let read = records.by_ref().take(10).count();
+    /// token.cancel();
+    /// assert_eq!((read, records.next().is_none(), records.cancelled()), (10, true, true));
+    ///
+    /// let mut reader = BamReader::from_path(&bam).unwrap();
+    /// let mut records = reader.records().until_cancelled(CancellationToken::new());
+    /// assert_eq!(records.by_ref().count(), 400);
+    /// assert!(!records.cancelled());
+    /// std::fs::remove_file(&bam).unwrap();
+    /// ```
+    fn until_cancelled(self, token: CancellationToken) -> UntilCancelled<Self> {
+        UntilCancelled { inner: self, token, cancelled: false }
+    }
+
+    /// 向`sink`发出进度事件（见[`crate::progress`]），限速由[`ProgressReporter`]负责。
+    ///
+    /// 创建时发出`on_start`（没有字节数提示），流无错误地结束时发出最后一次
+    /// `on_progress`与`on_finish`；计数的是经过本适配器的记录，放在过滤与抽样之前
+    /// 即为读取的记录数。记录流不暴露读取位置，`on_progress`的字节数总是0。
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use bamqc_core::{generate_test_data, ChannelProgressSink, ProgressEvent, RecordStream, SimulationParams};
+    /// use bamqc_io::bam::BamReader;
+    ///
+    /// let bam = std::env::temp_dir().join(format!("bamqc-with-progress-{}.bam", std::process::id()));
+    /// let bam = bam.to_string_lossy().to_string();
+    /// generate_test_data(&bam, &SimulationParams { pairs: 5000, ..Default::default() }).unwrap();
+    ///
+    /// let (mut sink, events) = ChannelProgressSink::with_interval(Duration::ZERO);
+    /// let mut reader = BamReader::from_path(&bam).unwrap();
+    /// assert_eq!(reader.records().with_progress(&mut sink).count(), 10_000);
+    /// drop(sink);
+    /// let events: Vec<ProgressEvent> = events.into_iter().collect();
+    /// assert_eq!(events[0], ProgressEvent::Start { total_hint: None });
+    /// let progress: Vec<u64> = events.iter().filter_map(|e| match e {
+    ///     ProgressEvent::Progress { records, .. } => Some(*records),
+    ///     _ => None,
+    /// }).collect();
+    /// assert_eq!(progress, [4096, 8192, 10_000]);
+    /// assert!(matches!(events.last(), Some(ProgressEvent::Finish(summary)) if summary.records == 10_000));
+    /// std::fs::remove_file(&bam).unwrap();
+    /// ```
+    fn with_progress(self, sink: &mut dyn ProgressSink) -> WithProgress<'_, Self> {
+        let mut reporter = ProgressReporter::new(sink);
+        reporter.start(None);
+        WithProgress { inner: self, reporter: Some(reporter), records: 0, failed: false }
+    }
+
+    /// 最多产出`n`条记录，达到后不再读取；错误不计数。
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bamqc_core::{generate_test_data, RecordStream, SimulationParams};
+    /// use bamqc_io::bam::BamReader;
+    ///
+    /// let bam = std::env::temp_dir().join(format!("bamqc-take-records-{}.bam", std::process::id()));
+    /// let bam = bam.to_string_lossy().to_string();
+    /// generate_test_data(&bam, &SimulationParams { pairs: 200, ..Default::default() }).unwrap();
+    ///
+    /// let mut reader = BamReader::from_path(&bam).unwrap();
+    /// let mut records = reader.records();
+    /// assert_eq!(records.by_ref().take_records(5).count(), 5);
+    /// // 没有多读：剩下的记录仍可继续读取
+    /// assert_eq!(records.count(), 395);
+    /// std::fs::remove_file(&bam).unwrap();
+    /// ```
+    fn take_records(self, n: u64) -> TakeRecords<Self> {
+        TakeRecords { inner: self, remaining: n }
+    }
+}
+
+impl<I: Iterator<Item = Result<BamRecord, BamError>>> RecordStream for I {}
+
+/// 可在其它线程取消的标记，克隆的标记共享状态。
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 取消；所有克隆都看到取消状态。
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// 命令行`--sample-fraction`抽样的种子组件名，见[`crate::rng`]。
+pub const DOWNSAMPLE_SEED_COMPONENT: &str = "downsample";
+
+/// 读名为`name`的记录是否被[`RecordStream::sampled`]以`rate`、`seed`保留。
+///
+/// 与[`BamReader::records_sampled`]的判定相同（[`keeps_read_name`]）。
+///
+/// [`BamReader::records_sampled`]: bamqc_io::bam::BamReader::records_sampled
+pub fn sample_keeps(name: &[u8], rate: f64, seed: u64) -> bool {
+    keeps_read_name(name, rate, seed)
+}
+
+/// 见[`RecordStream::filtered`]。
+#[derive(Debug)]
+pub struct Filtered<I> {
+    inner: I,
+    filter: RecordFilter,
+}
+
+impl<I: RecordStream> Iterator for Filtered<I> {
+    type Item = Result<BamRecord, BamError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.find(|result| result.as_ref().map_or(true, |record| self.filter.matches(record)))
+    }
+}
+
+/// 见[`RecordStream::sampled`]。
+#[derive(Debug)]
+pub struct Sampled<I> {
+    inner: I,
+    rate: f64,
+    seed: u64,
+}
+
+impl<I: RecordStream> Iterator for Sampled<I> {
+    type Item = Result<BamRecord, BamError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (rate, seed) = (self.rate, self.seed);
+        self.inner.find(|result| result.as_ref().map_or(true, |record| sample_keeps(record.qname(), rate, seed)))
+    }
+}
+
+/// 见[`RecordStream::until_cancelled`]。
+#[derive(Debug)]
+pub struct UntilCancelled<I> {
+    inner: I,
+    token: CancellationToken,
+    cancelled: bool,
+}
+
+impl<I> UntilCancelled<I> {
+    /// 迭代是否因取消而提前结束。
+    pub fn cancelled(&self) -> bool {
+        self.cancelled
+    }
+}
+
+impl<I: RecordStream> Iterator for UntilCancelled<I> {
+    type Item = Result<BamRecord, BamError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.cancelled || self.token.is_cancelled() {
+            self.cancelled = true;
+            return None;
+        }
+        self.inner.next()
+    }
+}
+
+/// 见[`RecordStream::with_progress`]。
+pub struct WithProgress<'a, I> {
+    inner: I,
+    /// 流结束并发出`on_finish`后为None
+    reporter: Option<ProgressReporter<'a>>,
+    records: u64,
+    failed: bool,
+}
+
+impl<I: RecordStream> Iterator for WithProgress<'_, I> {
+    type Item = Result<BamRecord, BamError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let next = self.inner.next();
+        match &next {
+            Some(Ok(_)) => {
+                self.records += 1;
+                if let Some(reporter) = self.reporter.as_mut() {
+                    reporter.tick(self.records, || 0);
+                }
+            }
+            Some(Err(_)) => self.failed = true,
+            None => {
+                // 出错的计算不发出on_finish
+                if let Some(reporter) = self.reporter.take().filter(|_| !self.failed) {
+                    reporter.finish(self.records, 0);
+                }
+            }
+        }
+        next
+    }
+}
+
+/// 见[`RecordStream::take_records`]。
+#[derive(Debug)]
+pub struct TakeRecords<I> {
+    inner: I,
+    remaining: u64,
+}
+
+impl<I: RecordStream> Iterator for TakeRecords<I> {
+    type Item = Result<BamRecord, BamError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let next = self.inner.next();
+        if let Some(Ok(_)) = next {
+            self.remaining -= 1;
+        }
+        next
+    }
+}
@@ -0,0 +1,109 @@
+//! 可复现的伪随机数。
+//!
+//! 需要随机性的功能都使用带种子的[`SplitMix64`]，相同种子得到相同结果。
+//!
+//! 整个运行只有一个根种子（命令行的全局选项`--seed`，默认为[`DEFAULT_SEED`]，
+//! 不取自系统熵）。各组件不直接使用根种子，而是用[`derive_seed`]按自己的组件名
+//! 派生子种子：子种子只取决于根种子与组件名，新增一个需要随机性的组件不会改变
+//! 其它组件的随机数序列。目前的组件：
+//!
+//! | 组件名 | 用途 |
+//! |--------|------|
+//! | [`SIMULATE_SEED_COMPONENT`](crate::SIMULATE_SEED_COMPONENT) | `generate-test-data`的模拟数据 |
+//! | `BARCODE_SEED_COMPONENT`（`approx`特性） | 条形码HyperLogLog的哈希 |
+//! | `KIT_DETECT_SEED_COMPONENT`（`intervals`特性） | `detect-kit`的读长抽样 |
+//! | [`DOWNSAMPLE_SEED_COMPONENT`](crate::DOWNSAMPLE_SEED_COMPONENT) | `insert-size --sample-fraction`的按读名抽样 |
+//!
+//! 根种子记录在LIMS交付物（`summary.json`的`seed`）中，并计入`--out-dir`的运行键。
+
+/// 默认的根种子。
+pub const DEFAULT_SEED: u64 = 0x5EED_BA3C;
+
+/// 由根种子与组件名派生组件的子种子。
+///
+/// 组件名的FNV-1a哈希与根种子混合后经SplitMix64终混，结果跨平台、跨版本不变。
+///
+/// # Examples
+///
+/// ```
+/// use bamqc_core::{derive_seed, SplitMix64, DEFAULT_SEED};
+///
+/// let a = derive_seed(DEFAULT_SEED, "simulate");
+/// assert_eq!(a, derive_seed(DEFAULT_SEED, "simulate"));
+/// assert_ne!(a, derive_seed(DEFAULT_SEED, "barcode.hll"));
+/// assert_ne!(a, derive_seed(DEFAULT_SEED + 1, "simulate"));
+///
+/// // 组件各自的随机数序列互不影响
+/// let mut simulate = SplitMix64::for_component(7, "simulate");
+/// assert_eq!(simulate.next_u64(), SplitMix64::new(derive_seed(7, "simulate")).next_u64());
+/// ```
+pub fn derive_seed(root: u64, component: &str) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for &b in component.as_bytes() {
+        hash ^= b as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    SplitMix64::new(root ^ hash).next_u64()
+}
+
+/// SplitMix64伪随机数生成器（Steele等，2014），状态只有一个u64。
+///
+/// # Examples
+///
+/// ```
+/// use bamqc_core::SplitMix64;
+///
+/// let mut a = SplitMix64::new(42);
+/// let mut b = SplitMix64::new(42);
+/// assert_eq!(a.next_u64(), b.next_u64());
+/// assert!((0.0..1.0).contains(&a.next_f64()));
+/// ```
+#[derive(Clone, Debug)]
+pub struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    /// 以[`derive_seed`]由根种子与组件名派生的子种子创建。
+    pub fn for_component(root: u64, component: &str) -> Self {
+        Self::new(derive_seed(root, component))
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// `[0, 1)`上的均匀分布。
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// `[0, n)`上的均匀整数，`n`为0时返回0。
+    pub fn below(&mut self, n: u64) -> u64 {
+        if n == 0 {
+            0
+        } else {
+            ((self.next_u64() as u128 * n as u128) >> 64) as u64
+        }
+    }
+
+    /// 以概率`p`返回true。
+    pub fn chance(&mut self, p: f64) -> bool {
+        self.next_f64() < p
+    }
+
+    /// 正态分布（Box-Muller变换）。
+    pub fn normal(&mut self, mean: f64, sd: f64) -> f64 {
+        let u1 = 1.0 - self.next_f64();
+        let u2 = self.next_f64();
+        mean + sd * (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
+    }
+}
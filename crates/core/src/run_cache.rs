@@ -0,0 +1,191 @@
+//! `--out-dir`中的运行清单与重复运行检测。
+//!
+//! 每次成功运行后在输出目录写出`<子命令>.manifest.json`，记录运行键、版本与
+//! 本子命令输出文件的大小和CRC32。运行键由子命令名、版本、生效选项与输入
+//! 文件指纹（见[`bamqc_io::fingerprint_file`]）哈希得到；再次运行时如果键
+//! 相同且清单中的输出文件都未被改动，可以直接复用上次的结果。
+//!
+//! 清单只登记输出目录中本子命令的标准文件（含按样本的输出）；显式`-o`写到
+//! 目录之外的结果不在清单中。
+
+use std::fs;
+use std::path::PathBuf;
+use bamqc_io::{file_crc32, InputFingerprint, WarningCount, WARNINGS};
+use serde::{Deserialize, Serialize};
+use crate::layout::{LayoutError, OutputLayout, OUTPUT_FILES};
+use crate::log::debug;
+
+/// 计算运行键：子命令、版本、生效选项与各输入指纹的64位FNV-1a哈希（16位十六进制）。
+///
+/// `options`应包含所有影响结果的选项（包括取默认值的），任一选项变化键即变化。
+///
+/// # Examples
+///
+/// ```
+/// use bamqc_core::run_key;
+/// use bamqc_io::InputFingerprint;
+///
+/// let input = InputFingerprint { size: 100, modified_ns: 1, head_crc32: 2, tail_crc32: 3 };
+/// let key = run_key("duplication", "0.1.0", "strict=false", &[input]);
+/// assert_eq!(key.len(), 16);
+/// assert_eq!(run_key("duplication", "0.1.0", "strict=false", &[input]), key);
+/// assert_ne!(run_key("duplication", "0.1.0", "strict=true", &[input]), key);
+/// assert_ne!(run_key("duplication", "0.2.0", "strict=false", &[input]), key);
+/// assert_ne!(run_key("quality-yield", "0.1.0", "strict=false", &[input]), key);
+/// let touched = InputFingerprint { modified_ns: 2, ..input };
+/// assert_ne!(run_key("duplication", "0.1.0", "strict=false", &[touched]), key);
+/// ```
+pub fn run_key(collector: &str, version: &str, options: &str, inputs: &[InputFingerprint]) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    let mut feed = |bytes: &[u8]| {
+        for &b in bytes {
+            hash ^= b as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+        // 字段分隔，避免拼接歧义
+        hash ^= 0xff;
+        hash = hash.wrapping_mul(0x100000001b3);
+    };
+    feed(collector.as_bytes());
+    feed(version.as_bytes());
+    feed(options.as_bytes());
+    for input in inputs {
+        feed(&input.size.to_le_bytes());
+        feed(&input.modified_ns.to_le_bytes());
+        feed(&input.head_crc32.to_le_bytes());
+        feed(&input.tail_crc32.to_le_bytes());
+    }
+    format!("{:016x}", hash)
+}
+
+/// 清单中的一个输出文件。
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OutputChecksum {
+    /// 输出目录中的文件名。
+    pub file: String,
+    /// 文件大小（字节）。
+    pub size: u64,
+    /// 文件内容的CRC32。
+    pub crc32: u32,
+}
+
+/// 一次成功运行的清单。
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RunManifest {
+    /// 运行键，见[`run_key`]。
+    pub key: String,
+    /// bamqc版本。
+    pub version: String,
+    /// 子命令名。
+    pub collector: String,
+    /// 输入文件指纹。
+    pub inputs: Vec<InputFingerprint>,
+    /// 输出文件。
+    pub outputs: Vec<OutputChecksum>,
+    /// 本次运行中各类逐记录警告的总数（只含出现过的类别）。
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<WarningCount>,
+}
+
+impl OutputLayout {
+    /// 子命令的清单路径。
+    pub fn manifest_path(&self, collector: &str) -> PathBuf {
+        self.dir().join(format!("{}.manifest.json", collector))
+    }
+
+    /// 清单的键与`key`相同且登记的输出文件都未改动时，返回这些输出文件的路径。
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bamqc_core::OutputLayout;
+    ///
+    /// let dir = std::env::temp_dir().join(format!("bamqc-cache-{}", std::process::id()));
+    /// let layout = OutputLayout::create(&dir, false).unwrap();
+    /// assert!(layout.cached_run("flagstat", "k1").is_none());
+    ///
+    /// let path = layout.claim("flagstat", "main", false).unwrap();
+    /// std::fs::write(&path, "total: 1\n").unwrap();
+    /// layout.write_manifest("flagstat", "k1", "0.1.0", &[]).unwrap();
+    /// assert_eq!(layout.cached_run("flagstat", "k1").unwrap(), [dir.join("flagstat.txt")]);
+    /// // 选项或输入变化（键不同）
+    /// assert!(layout.cached_run("flagstat", "k2").is_none());
+    /// // 输出被改动
+    /// std::fs::write(&path, "total: 2\n").unwrap();
+    /// assert!(layout.cached_run("flagstat", "k1").is_none());
+    /// std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    pub fn cached_run(&self, collector: &str, key: &str) -> Option<Vec<PathBuf>> {
+        let path = self.manifest_path(collector);
+        let text = fs::read_to_string(&path).ok()?;
+        let manifest: RunManifest = match serde_json::from_str(&text) {
+            Ok(manifest) => manifest,
+            Err(e) => {
+                debug!("无法解析清单 {}: {}", path.display(), e);
+                return None;
+            }
+        };
+        if manifest.key != key || manifest.collector != collector || manifest.outputs.is_empty() {
+            debug!("清单 {} 的运行键不同", path.display());
+            return None;
+        }
+        let mut paths = Vec::with_capacity(manifest.outputs.len());
+        for output in &manifest.outputs {
+            let path = self.dir().join(&output.file);
+            let unchanged = fs::metadata(&path).is_ok_and(|m| m.len() == output.size)
+                && file_crc32(&path).is_ok_and(|crc| crc == output.crc32);
+            if !unchanged {
+                debug!("输出文件已改动或不存在: {}", path.display());
+                return None;
+            }
+            paths.push(path);
+        }
+        Some(paths)
+    }
+
+    /// 登记子命令在输出目录中的全部输出文件，写出清单并返回其路径。
+    pub fn write_manifest(
+        &self,
+        collector: &str,
+        key: &str,
+        version: &str,
+        inputs: &[InputFingerprint],
+    ) -> Result<PathBuf, LayoutError> {
+        let io_error = |path: &std::path::Path| {
+            let path = path.display().to_string();
+            move |source| LayoutError::Io { path, source }
+        };
+        let mut files = Vec::new();
+        for output in OUTPUT_FILES.iter().filter(|file| file.collector == collector) {
+            for name in [Some(output.text), output.json].into_iter().flatten() {
+                for file in self.existing(name)? {
+                    if !files.contains(&file) {
+                        files.push(file);
+                    }
+                }
+            }
+        }
+        files.sort();
+
+        let mut outputs = Vec::with_capacity(files.len());
+        for file in files {
+            let path = self.dir().join(&file);
+            let size = fs::metadata(&path).map_err(io_error(&path))?.len();
+            let crc32 = file_crc32(&path).map_err(io_error(&path))?;
+            outputs.push(OutputChecksum { file, size, crc32 });
+        }
+        let manifest = RunManifest {
+            key: key.to_string(),
+            version: version.to_string(),
+            collector: collector.to_string(),
+            inputs: inputs.to_vec(),
+            outputs,
+            warnings: WARNINGS.summary(),
+        };
+
+        let path = self.manifest_path(collector);
+        let json = serde_json::to_string_pretty(&manifest).expect("清单可以序列化为JSON");
+        fs::write(&path, json + "\n").map_err(io_error(&path))?;
+        Ok(path)
+    }
+}
@@ -0,0 +1,124 @@
+//! 多样本BAM的按样本输出。
+//!
+//! 头部声明了多个SM的合并BAM（例如联合变异检测的队列BAM），对样本敏感的
+//! 指标（插入片段、重复率、质量产出）默认按样本分别统计，每个样本一个
+//! 输出文件，文件名以样本名为后缀。样本名来自头部，可能含有`/`等不能用于
+//! 文件名的字符，这里负责清理并处理清理后的重名。
+
+use std::collections::HashSet;
+use std::path::Path;
+use bamqc_io::bam::{BamReaderOptions, BamError};
+use crate::groups::GroupInterner;
+
+/// 读取BAM头部中声明的样本（按@RG顺序去重，缺少SM的读组计为`unknown`）。
+///
+/// 返回多于一个样本时，对样本敏感的子命令默认按样本分别输出。
+/// 样本名中的非UTF-8字节替换为U+FFFD（见[`bamqc_io::decode_lossy`]）。
+///
+/// # Examples
+///
+/// ```
+/// use bamqc_core::{read_header_samples, sample_file_names};
+/// use bamqc_io::{BamReaderOptions, BamWriter};
+///
+/// let path = std::env::temp_dir().join(format!("bamqc-samples-{}.bam", std::process::id()));
+/// let text = b"@HD\tVN:1.6\n@RG\tID:r1\tSM:S1\xf0\x9f\xa7\xac\n@RG\tID:r2\tSM:S\xfe2\n@RG\tID:r3\tSM:S1/\xf0\x9f\xa7\xac\n";
+/// BamWriter::from_path_with_header_text(&path, &Default::default(), text).unwrap().finish().unwrap();
+///
+/// let samples = read_header_samples(&path.to_string_lossy(), &BamReaderOptions::new()).unwrap();
+/// assert_eq!(samples, ["S1🧬", "S\u{fffd}2", "S1/🧬"]);
+/// assert_eq!(sample_file_names(&samples), ["S1_", "S_2", "S1__"]);
+/// std::fs::remove_file(&path).unwrap();
+/// ```
+pub fn read_header_samples(bam_path: &str, reader: &BamReaderOptions) -> Result<Vec<String>, BamError> {
+    let reader = reader.open(bam_path)?;
+    Ok(GroupInterner::from_header(reader.header()).header_samples().to_vec())
+}
+
+/// 把样本名清理为可用作文件名的片段。
+///
+/// 只保留ASCII字母、数字、`.`、`-`与`_`，其余字符（含`/`、空白与非ASCII）
+/// 替换为`_`；开头的`.`也替换，避免生成隐藏文件或`..`。
+///
+/// # Examples
+///
+/// ```
+/// use bamqc_core::sanitize_sample_name;
+///
+/// assert_eq!(sanitize_sample_name("NA12878"), "NA12878");
+/// assert_eq!(sanitize_sample_name("batch1/NA12878"), "batch1_NA12878");
+/// assert_eq!(sanitize_sample_name(".."), "_.");
+/// assert_eq!(sanitize_sample_name("患者 1"), "___1");
+/// // 表情符号与有损解码产生的U+FFFD
+/// assert_eq!(sanitize_sample_name("S1🧬\u{fffd}"), "S1__");
+/// assert_eq!(sanitize_sample_name(""), "_");
+/// ```
+pub fn sanitize_sample_name(name: &str) -> String {
+    let sanitized: String = name
+        .chars()
+        .enumerate()
+        .map(|(i, c)| match c {
+            '.' if i == 0 => '_',
+            c if c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_') => c,
+            _ => '_',
+        })
+        .collect();
+    if sanitized.is_empty() {
+        "_".to_string()
+    } else {
+        sanitized
+    }
+}
+
+/// 为一组样本生成互不相同的文件名后缀，顺序与输入一致。
+///
+/// 清理后重名的样本（例如`a/b`与`a_b`）按出现顺序加`-2`、`-3`等后缀；
+/// 比较时不区分大小写，避免在大小写不敏感的文件系统上互相覆盖。
+///
+/// # Examples
+///
+/// ```
+/// use bamqc_core::sample_file_names;
+///
+/// let samples = ["a_b", "a/b", "A_B", "c"];
+/// assert_eq!(sample_file_names(&samples), ["a_b", "a_b-2", "A_B-3", "c"]);
+/// ```
+pub fn sample_file_names<S: AsRef<str>>(samples: &[S]) -> Vec<String> {
+    let mut used = HashSet::new();
+    samples
+        .iter()
+        .map(|sample| {
+            let base = sanitize_sample_name(sample.as_ref());
+            let mut name = base.clone();
+            let mut n = 1;
+            while !used.insert(name.to_ascii_lowercase()) {
+                n += 1;
+                name = format!("{}-{}", base, n);
+            }
+            name
+        })
+        .collect()
+}
+
+/// 在输出路径的扩展名前插入样本后缀：`out/metrics.txt` -> `out/metrics.S1.txt`。
+///
+/// # Examples
+///
+/// ```
+/// use bamqc_core::sample_output_path;
+///
+/// assert_eq!(sample_output_path("out/metrics.txt", "S1"), "out/metrics.S1.txt");
+/// assert_eq!(sample_output_path("metrics", "S1"), "metrics.S1");
+/// assert_eq!(sample_output_path("report.dup.json", "S1"), "report.dup.S1.json");
+/// ```
+pub fn sample_output_path(path: &str, suffix: &str) -> String {
+    let p = Path::new(path);
+    let file_name = match (p.file_stem(), p.extension()) {
+        (Some(stem), Some(ext)) => format!("{}.{}.{}", stem.to_string_lossy(), suffix, ext.to_string_lossy()),
+        _ => format!("{}.{}", p.file_name().map_or_else(|| path.into(), |n| n.to_string_lossy()), suffix),
+    };
+    match p.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.join(file_name).to_string_lossy().into_owned(),
+        _ => file_name,
+    }
+}
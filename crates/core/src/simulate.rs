@@ -0,0 +1,428 @@
+//! 生成演示与集成测试用的模拟BAM。
+//!
+//! 参考序列是两条虚拟染色体（只有@SQ，没有序列），读对的插入片段服从
+//! 给定均值与标准差的正态分布；可配置duplicate比例、RF方向污染比例和
+//! 未比对比例。输出按坐标排序并建立BAI索引，参数已知，可以用来核对
+//! bamqc算出的指标。插入片段不超过最短的参考序列，读对总在参考序列之内。
+
+use std::fmt;
+use std::num::NonZeroUsize;
+use bamqc_io::bam::{BamError, BamWriter, write_bai};
+#[cfg(feature = "serde")]
+use bamqc_io::serialize_error;
+use bamqc_io::ErrorCode;
+use noodles::core::Position;
+use noodles::sam;
+use noodles::sam::alignment::record::cigar::op::{Kind, Op};
+use noodles::sam::alignment::record::{Flags, MappingQuality};
+use noodles::sam::alignment::RecordBuf;
+use noodles::sam::header::record::value::map::{self, Map, ReferenceSequence};
+#[cfg(feature = "serde")]
+use serde::Serialize;
+use thiserror::Error;
+use crate::log::info;
+use crate::rng::{SplitMix64, DEFAULT_SEED};
+use crate::metrics::Unit;
+use crate::metrics_keys::metric_keys;
+
+/// 模拟数据的随机数组件名（见[`crate::rng`]）。
+pub const SIMULATE_SEED_COMPONENT: &str = "simulate";
+
+/// 模拟的参考序列（名称，长度）。
+pub const SIMULATED_CONTIGS: [(&str, usize); 2] = [("sim1", 5_000_000), ("sim2", 3_000_000)];
+
+/// 插入片段、插入片段标准差与读长的上限：最短的模拟参考序列长度，
+/// 且不超过TLEN（i32）的最大值。
+pub const MAX_SIMULATED_INSERT: u64 = {
+    let mut max = i32::MAX as u64;
+    let mut i = 0;
+    while i < SIMULATED_CONTIGS.len() {
+        if (SIMULATED_CONTIGS[i].1 as u64) < max {
+            max = SIMULATED_CONTIGS[i].1 as u64;
+        }
+        i += 1;
+    }
+    max
+};
+
+/// 生成模拟数据时可能发生的错误。
+///
+/// 错误代码与JSON形式见[`bamqc_io::error_code`]：
+///
+/// ```
+/// # #[cfg(feature = "serde")]
+/// # {
+/// use bamqc_core::{generate_test_data, SimulationError, SimulationParams};
+///
+/// let params = SimulationParams { insert_mean: 1e10, ..Default::default() };
+/// let e = generate_test_data("never-written.bam", &params).unwrap_err();
+/// assert!(matches!(e, SimulationError::ParameterOutOfRange { name: "insert_mean", .. }));
+/// assert_eq!(
+///     serde_json::to_string(&e).unwrap(),
+///     r#"{"code":"simulation_parameter_out_of_range","message":"模拟参数insert_mean超出范围: 10000000000（应在0到3000000之间）","details":{"name":"insert_mean","value":10000000000.0,"max":3000000}}"#
+/// );
+/// assert!(!std::path::Path::new("never-written.bam").exists());
+/// # }
+/// ```
+#[derive(Error, Debug)]
+pub enum SimulationError {
+    /// 插入片段均值、标准差或读长不是有限值，或超出[`MAX_SIMULATED_INSERT`]。
+    ///
+    /// 插入片段长于参考序列时读对会越过参考序列末端，长于i32时TLEN溢出。
+    #[error("模拟参数{name}超出范围: {value}（应在0到{max}之间）")]
+    ParameterOutOfRange { name: &'static str, value: f64, max: u64 },
+
+    /// BAM文件IO错误。
+    #[error("BAM文件IO错误: {0}")]
+    BamError(#[from] BamError),
+}
+
+impl ErrorCode for SimulationError {
+    fn code(&self) -> &'static str {
+        match self {
+            SimulationError::ParameterOutOfRange { .. } => "simulation_parameter_out_of_range",
+            SimulationError::BamError(e) => e.code(),
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    fn serialize_details<M: serde::ser::SerializeMap>(&self, map: &mut M) -> Result<(), M::Error> {
+        match self {
+            SimulationError::ParameterOutOfRange { name, value, max } => {
+                map.serialize_entry("name", name)?;
+                map.serialize_entry("value", value)?;
+                map.serialize_entry("max", max)
+            }
+            SimulationError::BamError(e) => e.serialize_details(map),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for SimulationError {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_error(self, serializer)
+    }
+}
+
+/// 模拟参数。
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct SimulationParams {
+    /// 读对数（含duplicate与未比对读对）。
+    pub pairs: u64,
+    /// 插入片段均值。
+    pub insert_mean: f64,
+    /// 插入片段标准差。
+    pub insert_sd: f64,
+    /// duplicate读对比例。
+    pub dup_rate: f64,
+    /// RF方向读对比例。
+    pub rf_fraction: f64,
+    /// 两端都未比对的读对比例。
+    pub unmapped_fraction: f64,
+    /// 每个非duplicate比对读对的secondary比对期望数（多重比对，整数部分必定生成，
+    /// 小数部分按概率生成）。
+    pub secondary_rate: f64,
+    /// 读长。
+    pub read_length: u32,
+    /// 根种子，生成器使用由它派生的子种子（见[`crate::rng`]）。
+    pub seed: u64,
+}
+
+impl Default for SimulationParams {
+    fn default() -> Self {
+        Self {
+            pairs: 100_000,
+            insert_mean: 400.0,
+            insert_sd: 60.0,
+            dup_rate: 0.1,
+            rf_fraction: 0.02,
+            unmapped_fraction: 0.0,
+            secondary_rate: 0.0,
+            read_length: 150,
+            seed: DEFAULT_SEED,
+        }
+    }
+}
+
+/// 实际生成的结果（真值）。
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct SimulationSummary {
+    pub params: SimulationParams,
+    /// 生成的读对数。
+    pub pairs: u64,
+    /// 标记为duplicate的读对数。
+    pub duplicate_pairs: u64,
+    /// RF方向的读对数（不含duplicate与未比对读对）。
+    pub rf_pairs: u64,
+    /// 未比对的读对数。
+    pub unmapped_pairs: u64,
+    /// 生成的secondary比对读对数（每对两条secondary记录）。
+    pub secondary_pairs: u64,
+    /// 非duplicate的FR读对插入片段中位数。
+    pub fr_median_insert_size: Option<u32>,
+}
+
+impl fmt::Display for SimulationSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let p = &self.params;
+        writeln!(f, "seed: {}", p.seed)?;
+        writeln!(f, "read_length: {}", p.read_length)?;
+        writeln!(f, "insert_mean: {}", p.insert_mean)?;
+        writeln!(f, "insert_sd: {}", p.insert_sd)?;
+        writeln!(f, "dup_rate: {}", p.dup_rate)?;
+        writeln!(f, "rf_fraction: {}", p.rf_fraction)?;
+        writeln!(f, "unmapped_fraction: {}", p.unmapped_fraction)?;
+        writeln!(f, "secondary_rate: {}", p.secondary_rate)?;
+        writeln!(f, "pairs: {}", self.pairs)?;
+        writeln!(f, "duplicate_pairs: {}", self.duplicate_pairs)?;
+        writeln!(f, "rf_pairs: {}", self.rf_pairs)?;
+        writeln!(f, "unmapped_pairs: {}", self.unmapped_pairs)?;
+        writeln!(f, "secondary_pairs: {}", self.secondary_pairs)?;
+        match self.fr_median_insert_size {
+            Some(median) => write!(f, "fr_median_insert_size: {}", median),
+            None => write!(f, "fr_median_insert_size: NA"),
+        }
+    }
+}
+
+/// 一个已放置的读对：参考序列、左端起点（0-based）、插入片段、是否RF。
+#[derive(Clone, Copy)]
+struct Placement {
+    tid: usize,
+    start: u64,
+    insert: u64,
+    rf: bool,
+}
+
+impl SimulationParams {
+    /// 检查插入片段均值、标准差与读长在0到[`MAX_SIMULATED_INSERT`]之间。
+    pub fn check(&self) -> Result<(), SimulationError> {
+        let fields = [
+            ("insert_mean", self.insert_mean),
+            ("insert_sd", self.insert_sd),
+            ("read_length", self.read_length as f64),
+        ];
+        for (name, value) in fields {
+            if !(0.0..=MAX_SIMULATED_INSERT as f64).contains(&value) {
+                return Err(SimulationError::ParameterOutOfRange { name, value, max: MAX_SIMULATED_INSERT });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 生成模拟BAM并建立索引，返回真值。
+///
+/// 参数先经[`SimulationParams::check`]检查；抽样得到的插入片段截断到
+/// [`MAX_SIMULATED_INSERT`]，读对不会越过参考序列末端。
+pub fn generate_test_data(path: &str, params: &SimulationParams) -> Result<SimulationSummary, SimulationError> {
+    params.check()?;
+    let mut rng = SplitMix64::for_component(params.seed, SIMULATE_SEED_COMPONENT);
+    let read_length = params.read_length.max(1) as u64;
+    let total_length: u64 = SIMULATED_CONTIGS.iter().map(|(_, len)| *len as u64).sum();
+
+    info!("开始生成模拟数据: {}（{} 个读对，种子 {}）", path, params.pairs, params.seed);
+
+    let mut records = Vec::with_capacity(params.pairs as usize * 2);
+    let mut placed: Vec<Placement> = Vec::new();
+    let mut fr_inserts = Vec::new();
+    let mut summary = SimulationSummary {
+        params: params.clone(),
+        pairs: params.pairs,
+        duplicate_pairs: 0,
+        rf_pairs: 0,
+        unmapped_pairs: 0,
+        secondary_pairs: 0,
+        fr_median_insert_size: None,
+    };
+
+    for i in 0..params.pairs {
+        let name = format!("sim:{}", i);
+        if rng.chance(params.unmapped_fraction) {
+            summary.unmapped_pairs += 1;
+            records.extend(unmapped_pair(&name, read_length, &mut rng));
+            continue;
+        }
+
+        // duplicate复制一个已有读对的位置
+        let duplicate = !placed.is_empty() && rng.chance(params.dup_rate);
+        let placement = if duplicate {
+            summary.duplicate_pairs += 1;
+            placed[rng.below(placed.len() as u64) as usize]
+        } else {
+            let insert = rng
+                .normal(params.insert_mean, params.insert_sd)
+                .round()
+                .clamp(read_length as f64, MAX_SIMULATED_INSERT as f64) as u64;
+            let mut placement = random_placement(insert, total_length, &mut rng);
+            placement.rf = rng.chance(params.rf_fraction);
+            if placement.rf {
+                summary.rf_pairs += 1;
+            } else {
+                fr_inserts.push(insert);
+            }
+            placed.push(placement);
+            placement
+        };
+        let flags = if duplicate { Flags::DUPLICATE } else { Flags::empty() };
+        records.extend(mapped_pair(&name, &placement, read_length, flags, &mut rng));
+
+        // secondary_rate为0时不消耗随机数，保证默认参数生成的文件不变
+        if !duplicate && params.secondary_rate > 0.0 {
+            let whole = params.secondary_rate.trunc() as u64;
+            let count = whole + u64::from(rng.chance(params.secondary_rate.fract()));
+            for _ in 0..count {
+                let secondary = Placement {
+                    rf: placement.rf,
+                    ..random_placement(placement.insert, total_length, &mut rng)
+                };
+                records.extend(mapped_pair(&name, &secondary, read_length, Flags::SECONDARY, &mut rng));
+                summary.secondary_pairs += 1;
+            }
+        }
+    }
+
+    fr_inserts.sort_unstable();
+    summary.fr_median_insert_size = fr_inserts.get(fr_inserts.len().saturating_sub(1) / 2).map(|&v| v as u32);
+
+    // 按坐标排序，未比对记录在最后；同一位置按读名保证结果确定
+    records.sort_by(|a, b| {
+        let key = |r: &RecordBuf| {
+            (
+                r.reference_sequence_id().unwrap_or(usize::MAX),
+                r.alignment_start().map(usize::from).unwrap_or(0),
+            )
+        };
+        key(a).cmp(&key(b)).then_with(|| a.name().cmp(&b.name()))
+    });
+
+    let mut writer = BamWriter::from_path(path, &simulated_header())?;
+    for record in &records {
+        writer.write_record_buf(record)?;
+    }
+    writer.finish()?;
+    let index_path = write_bai(path)?;
+    info!("已建立索引: {}", index_path.display());
+
+    Ok(summary)
+}
+
+fn simulated_header() -> sam::Header {
+    let header = Map::<map::Header>::builder()
+        .insert(map::header::tag::SORT_ORDER, "coordinate")
+        .build()
+        .unwrap_or_default();
+    let mut builder = sam::Header::builder().set_header(header);
+    for (name, length) in SIMULATED_CONTIGS {
+        let length = NonZeroUsize::new(length).expect("模拟参考序列长度非0");
+        builder = builder.add_reference_sequence(name, Map::<ReferenceSequence>::new(length));
+    }
+    builder.build()
+}
+
+/// 在全部参考序列上均匀随机放置一个FR方向的插入片段。
+fn random_placement(insert: u64, total_length: u64, rng: &mut SplitMix64) -> Placement {
+    let mut offset = rng.below(total_length);
+    let mut tid = 0;
+    while offset >= SIMULATED_CONTIGS[tid].1 as u64 {
+        offset -= SIMULATED_CONTIGS[tid].1 as u64;
+        tid += 1;
+    }
+    let contig_length = SIMULATED_CONTIGS[tid].1 as u64;
+    Placement {
+        tid,
+        start: offset.min(contig_length.saturating_sub(insert)),
+        insert,
+        rf: false,
+    }
+}
+
+fn random_bases(length: u64, rng: &mut SplitMix64) -> Vec<u8> {
+    (0..length).map(|_| b"ACGT"[rng.below(4) as usize]).collect()
+}
+
+fn mapped_pair(
+    name: &str,
+    placement: &Placement,
+    read_length: u64,
+    extra: Flags,
+    rng: &mut SplitMix64,
+) -> [RecordBuf; 2] {
+    let left = placement.start;
+    let right = placement.start + placement.insert - read_length;
+    let tlen = placement.insert as i32;
+
+    let base = Flags::SEGMENTED | Flags::PROPERLY_SEGMENTED | extra;
+    // 多重比对的secondary记录MAPQ为0
+    let mapq = if extra.contains(Flags::SECONDARY) { 0 } else { 60 };
+    // FR：左端正向、右端反向；RF相反
+    let (left_reverse, right_reverse) = if placement.rf { (true, false) } else { (false, true) };
+    let strand = |reverse: bool, mate_reverse: bool| {
+        let mut flags = base;
+        if reverse {
+            flags |= Flags::REVERSE_COMPLEMENTED;
+        }
+        if mate_reverse {
+            flags |= Flags::MATE_REVERSE_COMPLEMENTED;
+        }
+        flags
+    };
+
+    let record = |flags: Flags, pos: u64, mate_pos: u64, tlen: i32, rng: &mut SplitMix64| {
+        RecordBuf::builder()
+            .set_name(name)
+            .set_flags(flags)
+            .set_reference_sequence_id(placement.tid)
+            .set_alignment_start(Position::try_from(pos as usize + 1).expect("位置从1开始"))
+            .set_mapping_quality(MappingQuality::new(mapq).expect("MAPQ有效"))
+            .set_cigar([Op::new(Kind::Match, read_length as usize)].into_iter().collect())
+            .set_mate_reference_sequence_id(placement.tid)
+            .set_mate_alignment_start(Position::try_from(mate_pos as usize + 1).expect("位置从1开始"))
+            .set_template_length(tlen)
+            .set_sequence(random_bases(read_length, rng).into())
+            .set_quality_scores(vec![30; read_length as usize].into())
+            .build()
+    };
+
+    [
+        record(strand(left_reverse, right_reverse) | Flags::FIRST_SEGMENT, left, right, tlen, rng),
+        record(strand(right_reverse, left_reverse) | Flags::LAST_SEGMENT, right, left, -tlen, rng),
+    ]
+}
+
+fn unmapped_pair(name: &str, read_length: u64, rng: &mut SplitMix64) -> [RecordBuf; 2] {
+    let flags = Flags::SEGMENTED | Flags::UNMAPPED | Flags::MATE_UNMAPPED;
+    let record = |segment: Flags, rng: &mut SplitMix64| {
+        RecordBuf::builder()
+            .set_name(name)
+            .set_flags(flags | segment)
+            .set_sequence(random_bases(read_length, rng).into())
+            .set_quality_scores(vec![30; read_length as usize].into())
+            .build()
+    };
+    [record(Flags::FIRST_SEGMENT, rng), record(Flags::LAST_SEGMENT, rng)]
+}
+
+metric_keys! {
+    /// `generate-test-data`输出的键（见[`crate::metrics`]）。
+    SimulationKeys for "generate-test-data" {
+        PARAMS = "params", "模拟参数", Unit::List, None, "生成模拟数据所用的参数。";
+        PAIRS = "pairs", "读对数", Unit::ReadPairs, None, "生成的读对数（含duplicate与未比对读对）。";
+        INSERT_MEAN = "insert_mean", "插入片段均值", Unit::BasePairs, None, "插入片段正态分布的均值。";
+        INSERT_SD = "insert_sd", "插入片段标准差", Unit::BasePairs, None, "插入片段正态分布的标准差。";
+        DUP_RATE = "dup_rate", "duplicate比例", Unit::Fraction, None, "读对被生成为duplicate的概率。";
+        RF_FRACTION = "rf_fraction", "RF方向比例", Unit::Fraction, None, "读对被生成为RF方向的概率。";
+        UNMAPPED_FRACTION = "unmapped_fraction", "未比对比例", Unit::Fraction, None, "读对两端都未比对的概率。";
+        SECONDARY_RATE = "secondary_rate", "secondary比对率", Unit::Ratio, None, "每个非duplicate比对读对的secondary比对期望数。";
+        READ_LENGTH = "read_length", "读长", Unit::Bases, None, "每条读长的碱基数。";
+        SEED = "seed", "随机数种子", Unit::Label, None, "根种子（全局选项--seed）；相同种子生成逐字节相同的BAM。";
+        DUPLICATE_PAIRS = "duplicate_pairs", "duplicate读对数", Unit::ReadPairs, None, "标记为duplicate的读对数。";
+        RF_PAIRS = "rf_pairs", "RF读对数", Unit::ReadPairs, None, "RF方向的读对数（不含duplicate与未比对读对）。";
+        UNMAPPED_PAIRS = "unmapped_pairs", "未比对读对数", Unit::ReadPairs, None, "两端都未比对的读对数。";
+        SECONDARY_PAIRS = "secondary_pairs", "secondary读对数", Unit::ReadPairs, None, "生成的secondary比对读对数（每对两条secondary记录）。";
+        FR_MEDIAN_INSERT_SIZE = "fr_median_insert_size", "FR插入片段中位数", Unit::BasePairs, None, "非duplicate的FR读对插入片段中位数（真值）。";
+    }
+}
@@ -0,0 +1,161 @@
+//! 坐标排序检查。
+//!
+//! 头部声明`SO:coordinate`但记录实际未按坐标排列时（例如排序后又被其它
+//! 步骤重排），依赖坐标顺序的收集器会悄悄给出错误结果。这些收集器在遍历
+//! 记录时通过[`SortOrderGuard`]核对（参考序列ID, 位置）单调不减：默认第一次
+//! 违反即返回[`BamError::SortOrderViolation`]；允许少量违反时（有的工具会在
+//! 参考序列交界处输出几条乱序记录）逐条计数，通过[`WARNINGS`]输出详情与总数，
+//! 超过允许的条数再报错。头部未声明按坐标排序时不做检查，与顺序无关的收集器
+//! 不使用该检查。
+
+use bamqc_io::bam::{BamError, BamRecord, SortOrder};
+use bamqc_io::warnings::{WarningClass, WARNINGS};
+use noodles::sam;
+
+/// 逐记录核对坐标顺序。
+///
+/// # Examples
+///
+/// 交换一对相邻记录：默认在第一次违反时报错，允许1次违反时结果与排序后的
+/// 文件一致；完全打乱的文件即使允许少量违反也会报错：
+///
+/// ```
+/// use bamqc_core::compute_depth_windows;
+/// use bamqc_io::bam::{BamReaderOptions, BamWriter};
+/// use bamqc_io::{BamError, WarningClass, WARNINGS};
+/// use noodles::core::Position;
+/// use noodles::sam::alignment::record::cigar::op::{Kind, Op};
+/// use noodles::sam::alignment::record::Flags;
+/// use noodles::sam::alignment::RecordBuf;
+///
+/// let dir = std::env::temp_dir().join(format!("bamqc-sort-order-{}", std::process::id()));
+/// std::fs::create_dir_all(&dir).unwrap();
+/// let write = |name: &str, so: &str, order: &[usize]| {
+///     let bam = dir.join(name).to_string_lossy().to_string();
+///     let header = format!("@HD\tVN:1.6\tSO:{}\n@SQ\tSN:c1\tLN:10000\n", so).parse().unwrap();
+///     let mut writer = BamWriter::from_path(&bam, &header).unwrap();
+///     for &i in order {
+///         let record = RecordBuf::builder()
+///             .set_name(format!("r{}", i))
+///             .set_flags(Flags::empty())
+///             .set_reference_sequence_id(0)
+///             .set_alignment_start(Position::try_from(i * 40 + 1).unwrap())
+///             .set_cigar([Op::new(Kind::Match, 50)].into_iter().collect())
+///             .build();
+///         writer.write_record_buf(&record).unwrap();
+///     }
+///     writer.finish().unwrap();
+///     bam
+/// };
+///
+/// let sorted: Vec<usize> = (0..200).collect();
+/// let mut swapped = sorted.clone();
+/// swapped.swap(1, 2);
+/// let shuffled: Vec<usize> = (0..200).map(|i| i * 37 % 200).collect();
+/// let expected = compute_depth_windows(&write("sorted.bam", "coordinate", &sorted), &BamReaderOptions::new(), 1000, None, 0).unwrap().to_string();
+///
+/// let bam = write("swapped.bam", "coordinate", &swapped);
+/// match compute_depth_windows(&bam, &BamReaderOptions::new(), 1000, None, 0) {
+///     Err(BamError::SortOrderViolation { record_index, prev, curr }) => {
+///         assert_eq!((record_index, prev.as_str(), curr.as_str()), (3, "c1:81", "c1:41"));
+///     }
+///     other => panic!("{:?}", other),
+/// }
+/// assert_eq!(compute_depth_windows(&bam, &BamReaderOptions::new(), 1000, None, 1).unwrap().to_string(), expected);
+/// assert_eq!(WARNINGS.count(WarningClass::UnsortedRecord), 1);
+///
+/// let bam = write("shuffled.bam", "coordinate", &shuffled);
+/// let e = compute_depth_windows(&bam, &BamReaderOptions::new(), 1000, None, 10).unwrap_err();
+/// assert!(matches!(e, BamError::SortOrderViolation { .. }), "{}", e);
+/// assert_eq!(compute_depth_windows(&bam, &BamReaderOptions::new(), 1000, None, 200).unwrap().to_string(), expected);
+///
+/// // 头部未声明按坐标排序时不检查
+/// let bam = write("unsorted.bam", "unsorted", &shuffled);
+/// assert_eq!(compute_depth_windows(&bam, &BamReaderOptions::new(), 1000, None, 0).unwrap().to_string(), expected);
+/// std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct SortOrderGuard {
+    /// 头部中的参考序列名（用于错误信息）；头部未声明按坐标排序时为None，不做检查。
+    names: Option<Vec<String>>,
+    tolerance: u64,
+    previous: u64,
+    records: u64,
+    violations: u64,
+}
+
+impl SortOrderGuard {
+    /// 头部@HD SO为coordinate时启用检查，最多允许`tolerance`条记录违反顺序。
+    pub fn from_header(header: &sam::Header, tolerance: u64) -> Self {
+        let coordinate = SortOrder::from_header(header) == SortOrder::Coordinate;
+        Self {
+            names: coordinate
+                .then(|| header.reference_sequences().keys().map(|name| name.to_string()).collect()),
+            tolerance,
+            previous: 0,
+            records: 0,
+            violations: 0,
+        }
+    }
+
+    /// 核对记录不在前一条记录之前。
+    ///
+    /// 未比对且没有参考序列ID的记录排在最后。违反次数超过允许值时返回
+    /// [`BamError::SortOrderViolation`]，`record_index`为记录在遍历中的序号（从1开始）。
+    #[inline]
+    pub fn check(&mut self, record: &BamRecord) -> Result<(), BamError> {
+        if self.names.is_none() {
+            return Ok(());
+        }
+        self.records += 1;
+        let key = sort_key(record.tid(), record.pos());
+        if key < self.previous {
+            self.violation(record, key)?;
+        }
+        self.previous = key;
+        Ok(())
+    }
+
+    #[cold]
+    fn violation(&mut self, record: &BamRecord, key: u64) -> Result<(), BamError> {
+        let prev = self.describe(self.previous);
+        let curr = self.describe(key);
+        self.violations += 1;
+        if self.violations > self.tolerance {
+            return Err(BamError::SortOrderViolation { record_index: self.records, prev, curr });
+        }
+        WARNINGS.warn(WarningClass::UnsortedRecord, || {
+            format!("第{}条记录 {} 位于 {} 之后", self.records, record.name(), prev)
+        });
+        Ok(())
+    }
+
+    /// 把排序键写成`参考序列:位置`（1-based），未比对写作`*`。
+    fn describe(&self, key: u64) -> String {
+        let tid = (key >> 32) as u32;
+        if tid == u32::MAX {
+            return "*".to_string();
+        }
+        let name = self
+            .names
+            .as_ref()
+            .and_then(|names| names.get(tid as usize))
+            .map_or_else(|| tid.to_string(), String::clone);
+        format!("{}:{}", name, key & u64::from(u32::MAX))
+    }
+
+    /// 是否启用了检查（头部声明按坐标排序）。
+    pub fn is_enabled(&self) -> bool {
+        self.names.is_some()
+    }
+
+    /// 违反坐标顺序的记录数。
+    pub fn violations(&self) -> u64 {
+        self.violations
+    }
+}
+
+/// 参考序列ID在高32位（-1映射为最大值），1-based位置在低32位，可以直接比较。
+fn sort_key(tid: i32, pos: i64) -> u64 {
+    (u64::from(tid as u32) << 32) | ((pos + 1) as u64 & u64::from(u32::MAX))
+}
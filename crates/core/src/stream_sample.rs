@@ -0,0 +1,309 @@
+//! 记录流的抽样旁路输出（stream sample）。
+//!
+//! 在记录循环中通过过滤条件之后，每计入N条记录取一条，把最小摘要
+//! （tid、pos、tlen、mapq、flags）以JSON Lines追加到旁路文件，供仪表盘在运行
+//! 结束前tail该文件绘制粗略的分布。写入经过缓冲，按时间间隔刷新，不会阻塞
+//! 记录循环；写入失败时警告一次并停止旁路输出，不影响主计算。文件超过大小
+//! 上限时轮转：当前文件改名为`<file>.1`（覆盖上一次轮转的文件），再新建文件。
+//! 与interim文件不同，运行结束后旁路文件保留。
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use bamqc_io::bam::BamRecord;
+use crate::interim::parse_duration;
+use crate::log::{debug, warn};
+use crate::metrics::Unit;
+use crate::metrics_keys::metric_keys;
+
+/// 默认的刷新间隔。
+pub const DEFAULT_STREAM_FLUSH: Duration = Duration::from_secs(1);
+
+/// 默认的轮转大小上限（64 MiB）。
+pub const DEFAULT_STREAM_MAX_BYTES: u64 = 64 << 20;
+
+/// 旁路文件的写缓冲大小。
+const BUFFER_SIZE: usize = 64 << 10;
+
+/// 抽样旁路输出的设置。
+///
+/// 字符串形式为空格或逗号分隔的`key=value`：`every=10000 file=stream.jsonl`，
+/// 可选`flush=5s`（刷新间隔，默认1s，`0`表示每行都刷新）与`max-bytes=64M`
+/// （轮转上限，支持K、M、G后缀，默认64M）。
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+/// use bamqc_core::{StreamSampleSpec, DEFAULT_STREAM_FLUSH};
+///
+/// let spec: StreamSampleSpec = "every=10000 file=stream.jsonl".parse().unwrap();
+/// assert_eq!(spec.every, 10000);
+/// assert_eq!(spec.flush, DEFAULT_STREAM_FLUSH);
+/// assert_eq!(spec.max_bytes, 64 << 20);
+///
+/// let spec: StreamSampleSpec = "every=1,file=s.jsonl,flush=0,max-bytes=512K".parse().unwrap();
+/// assert_eq!((spec.flush, spec.max_bytes), (Duration::ZERO, 512 << 10));
+/// assert!("file=s.jsonl".parse::<StreamSampleSpec>().is_err());
+/// assert!("every=0 file=s.jsonl".parse::<StreamSampleSpec>().is_err());
+/// assert!("every=10 file=s.jsonl max-bytes=1T".parse::<StreamSampleSpec>().is_err());
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StreamSampleSpec {
+    /// 每计入多少条记录取一条。
+    pub every: u64,
+    /// 旁路JSONL文件路径。
+    pub file: PathBuf,
+    /// 刷新间隔。
+    pub flush: Duration,
+    /// 文件超过该字节数时轮转。
+    pub max_bytes: u64,
+}
+
+/// 解析字节数：整数加可选后缀`K`、`M`、`G`（1024进制）。
+///
+/// ```
+/// use bamqc_core::parse_bytes;
+///
+/// assert_eq!(parse_bytes("512"), Some(512));
+/// assert_eq!(parse_bytes("64M"), Some(64 << 20));
+/// assert_eq!(parse_bytes("8g"), Some(8 << 30));
+/// assert_eq!(parse_bytes("1T"), None);
+/// ```
+pub fn parse_bytes(s: &str) -> Option<u64> {
+    let (value, shift) = match s.strip_suffix(['K', 'k']) {
+        Some(value) => (value, 10),
+        None => match s.strip_suffix(['M', 'm']) {
+            Some(value) => (value, 20),
+            None => match s.strip_suffix(['G', 'g']) {
+                Some(value) => (value, 30),
+                None => (s, 0),
+            },
+        },
+    };
+    value.parse::<u64>().ok()?.checked_mul(1 << shift)
+}
+
+impl FromStr for StreamSampleSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut every = None;
+        let mut file = None;
+        let mut flush = DEFAULT_STREAM_FLUSH;
+        let mut max_bytes = DEFAULT_STREAM_MAX_BYTES;
+
+        for item in s.split([' ', ',']).filter(|item| !item.is_empty()) {
+            let (key, value) = item
+                .split_once('=')
+                .ok_or_else(|| format!("无效的stream-sample参数: {}", item))?;
+            match key {
+                "every" => {
+                    every = Some(
+                        value
+                            .parse::<u64>()
+                            .ok()
+                            .filter(|&n| n > 0)
+                            .ok_or_else(|| format!("无效的抽样间隔: {}", value))?,
+                    )
+                }
+                "file" => file = Some(PathBuf::from(value)),
+                "flush" => flush = parse_duration(value).ok_or_else(|| format!("无效的刷新间隔: {}", value))?,
+                "max-bytes" => {
+                    max_bytes = parse_bytes(value)
+                        .filter(|&n| n > 0)
+                        .ok_or_else(|| format!("无效的大小上限: {}", value))?
+                }
+                _ => return Err(format!("未知的stream-sample参数: {}", key)),
+            }
+        }
+
+        let every = every.ok_or("stream-sample需要指定every=<记录数>")?;
+        let file = file.ok_or("stream-sample需要指定file=<路径>")?;
+        Ok(Self { every, file, flush, max_bytes })
+    }
+}
+
+/// 抽样旁路输出器。
+///
+/// # Examples
+///
+/// 在模拟的按坐标排序数据上运行插入片段计算，另一个线程在运行期间tail旁路文件；
+/// 读到的行数随运行增长，位置单调不减，结束后每个计入的读对都有一行：
+///
+/// ```
+/// use std::io::{BufRead, BufReader};
+/// use bamqc_core::*;
+///
+/// let dir = std::env::temp_dir().join(format!("bamqc-stream-{}", std::process::id()));
+/// std::fs::create_dir_all(&dir).unwrap();
+/// let bam = dir.join("sim.bam").to_string_lossy().to_string();
+/// generate_test_data(&bam, &SimulationParams { pairs: 20000, ..Default::default() }).unwrap();
+/// let file = dir.join("stream.jsonl");
+/// let spec: StreamSampleSpec = format!("every=1 file={} flush=0", file.display()).parse().unwrap();
+/// let options = InsertSizeOptions { stream_sample: Some(spec), min_pct: 0.0, ..Default::default() };
+///
+/// let (lines, report) = std::thread::scope(|scope| {
+///     let run = scope.spawn(|| compute_insert_size_with_report(&bam, &options, None).unwrap().1);
+///     let mut lines = Vec::new();
+///     let mut reader = None;
+///     let mut partial = String::new();
+///     loop {
+///         let finished = run.is_finished();
+///         if reader.is_none() {
+///             reader = std::fs::File::open(&file).ok().map(BufReader::new);
+///         }
+///         if let Some(reader) = reader.as_mut() {
+///             // 像tail -f一样只消费完整的行
+///             while reader.read_line(&mut partial).unwrap() > 0 {
+///                 if partial.ends_with('\n') {
+///                     lines.push(std::mem::take(&mut partial));
+///                 }
+///             }
+///         }
+///         if finished {
+///             break;
+///         }
+///     }
+///     (lines, run.join().unwrap())
+/// });
+///
+/// assert_eq!(lines.len() as u64, report.counted_records);
+/// let field = |line: &str, key: &str| -> i64 {
+///     let value = line.split(&format!("\"{}\":", key)).nth(1).unwrap();
+///     value.split([',', '}']).next().unwrap().parse().unwrap()
+/// };
+/// let positions: Vec<(i64, i64)> = lines.iter().map(|line| (field(line, "tid"), field(line, "pos"))).collect();
+/// assert!(positions.windows(2).all(|pair| pair[0] <= pair[1]));
+/// assert!(lines.iter().all(|line| field(line, "tlen") > 0));
+///
+/// // 超过大小上限时轮转到stream.jsonl.1
+/// let spec: StreamSampleSpec = format!("every=1 file={} max-bytes=4K", file.display()).parse().unwrap();
+/// let options = InsertSizeOptions { stream_sample: Some(spec), min_pct: 0.0, ..Default::default() };
+/// compute_insert_size_with_report(&bam, &options, None).unwrap();
+/// let rotated = std::fs::read_to_string(dir.join("stream.jsonl.1")).unwrap();
+/// assert!(rotated.len() >= 4096 && rotated.ends_with('\n'));
+/// assert!(std::fs::metadata(&file).unwrap().len() < 4096);
+/// std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct StreamSampler {
+    spec: StreamSampleSpec,
+    writer: Option<BufWriter<File>>,
+    counted: u64,
+    samples: u64,
+    bytes: u64,
+    rotations: u64,
+    last_flush: Instant,
+    line: String,
+}
+
+impl StreamSampler {
+    /// 创建（截断）旁路文件。
+    pub fn create(spec: StreamSampleSpec) -> io::Result<Self> {
+        let file = File::create(&spec.file)?;
+        Ok(Self {
+            writer: Some(BufWriter::with_capacity(BUFFER_SIZE, file)),
+            spec,
+            counted: 0,
+            samples: 0,
+            bytes: 0,
+            rotations: 0,
+            last_flush: Instant::now(),
+            line: String::new(),
+        })
+    }
+
+    /// 已写出的抽样行数。
+    pub fn samples(&self) -> u64 {
+        self.samples
+    }
+
+    /// 已轮转的次数。
+    pub fn rotations(&self) -> u64 {
+        self.rotations
+    }
+
+    /// 在过滤条件之后、对每条计入的记录调用。
+    ///
+    /// 未抽中时只做一次计数比较。写出失败时警告一次并停止旁路输出。
+    pub fn tap(&mut self, record: &BamRecord) {
+        self.counted += 1;
+        if !self.counted.is_multiple_of(self.spec.every) || self.writer.is_none() {
+            return;
+        }
+        if let Err(e) = self.write(record) {
+            warn!("写入stream-sample文件失败 {}: {}，停止抽样输出", self.spec.file.display(), e);
+            self.writer = None;
+        }
+    }
+
+    fn write(&mut self, record: &BamRecord) -> io::Result<()> {
+        use std::fmt::Write as _;
+
+        self.line.clear();
+        let _ = writeln!(
+            self.line,
+            "{{\"tid\":{},\"pos\":{},\"tlen\":{},\"mapq\":{},\"flags\":{}}}",
+            record.tid(),
+            record.pos(),
+            record.insert_size(),
+            record.mapq(),
+            record.flag()
+        );
+        let Some(writer) = self.writer.as_mut() else {
+            return Ok(());
+        };
+        writer.write_all(self.line.as_bytes())?;
+        self.samples += 1;
+        self.bytes += self.line.len() as u64;
+
+        if self.bytes >= self.spec.max_bytes {
+            self.rotate()?;
+        } else if self.last_flush.elapsed() >= self.spec.flush {
+            writer.flush()?;
+            self.last_flush = Instant::now();
+        }
+        Ok(())
+    }
+
+    /// 当前文件改名为`<file>.1`，再新建文件。
+    fn rotate(&mut self) -> io::Result<()> {
+        if let Some(mut writer) = self.writer.take() {
+            writer.flush()?;
+        }
+        fs::rename(&self.spec.file, rotated_path(&self.spec.file))?;
+        self.writer = Some(BufWriter::with_capacity(BUFFER_SIZE, File::create(&self.spec.file)?));
+        self.bytes = 0;
+        self.rotations += 1;
+        self.last_flush = Instant::now();
+        debug!("stream-sample文件已轮转 #{}: {}", self.rotations, self.spec.file.display());
+        Ok(())
+    }
+
+    /// 运行结束：刷新缓冲，保留旁路文件。
+    pub fn finish(mut self) {
+        if let Some(writer) = self.writer.as_mut() {
+            if let Err(e) = writer.flush() {
+                warn!("写入stream-sample文件失败 {}: {}", self.spec.file.display(), e);
+            }
+        }
+        debug!("stream-sample共写出 {} 行，轮转 {} 次", self.samples, self.rotations);
+    }
+}
+
+fn rotated_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".1");
+    path.with_file_name(name)
+}
+
+metric_keys! {
+    /// `--stream-sample`旁路文件每行的键（见[`crate::metrics`]；tid、pos、tlen见[`crate::InsertSizeKeys`]）。
+    StreamSampleKeys for "insert-size" {
+        MAPQ = "mapq", "比对质量", Unit::Phred, None, "抽样记录的MAPQ。";
+        FLAGS = "flags", "FLAG", Unit::Label, None, "抽样记录的FLAG。";
+    }
+}
@@ -0,0 +1,398 @@
+//! 任意数值型辅助标签的分布统计。
+//!
+//! 每个请求的标签（`XS:i`、`AS:i`、`xf:i`、`ZS:f`、`XT:A`等）统计出现/缺失
+//! 记录数、最小/最大/均值/中位数和直方图。整数按取值计数，浮点按固定宽度
+//! 分bin，字符按字符计数（不给出数值汇总）。同一标签在不同记录中类型不一致
+//! 时计为类型不符，不做类型转换。
+
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use bamqc_io::bam::{AuxValue, BamReaderOptions, BamError, BamRecord};
+#[cfg(feature = "serde")]
+use serde::Serialize;
+use crate::formatting::{OrNa, PicardFloat};
+use crate::histogram::Histogram;
+use crate::insert_size::InsertSizeCalculator;
+use crate::log::{info, warn};
+use crate::metrics::Unit;
+use crate::metrics_keys::metric_keys;
+
+/// 默认的浮点分bin宽度。
+pub const DEFAULT_FLOAT_BIN_WIDTH: f64 = 1.0;
+
+/// 可统计的标签类型。
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum TagType {
+    /// `A`：单个字符。
+    Char,
+    /// `i`：整数（BAM中的c/C/s/S/i/I）。
+    Int,
+    /// `f`：单精度浮点。
+    Float,
+}
+
+impl TagType {
+    fn code(self) -> char {
+        match self {
+            TagType::Char => 'A',
+            TagType::Int => 'i',
+            TagType::Float => 'f',
+        }
+    }
+}
+
+/// 标签及其期望类型，如`XS:i`。
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TagSpec {
+    pub tag: [u8; 2],
+    pub ty: TagType,
+}
+
+impl fmt::Display for TagSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}:{}", self.tag[0] as char, self.tag[1] as char, self.ty.code())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for TagSpec {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl FromStr for TagSpec {
+    type Err = String;
+
+    /// 解析`TAG:TYPE`，类型只能是A、i或f。
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bamqc_core::{TagSpec, TagType};
+    ///
+    /// let spec: TagSpec = "XS:i".parse().unwrap();
+    /// assert_eq!(spec.tag, *b"XS");
+    /// assert_eq!(spec.ty, TagType::Int);
+    /// assert_eq!(spec.to_string(), "XS:i");
+    ///
+    /// assert!("XS".parse::<TagSpec>().is_err());
+    /// assert!("RG:Z".parse::<TagSpec>().is_err());
+    /// assert!("1X:i".parse::<TagSpec>().is_err());
+    /// ```
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (tag, ty) = s
+            .split_once(':')
+            .ok_or_else(|| format!("标签格式应为TAG:TYPE，例如XS:i: {}", s))?;
+        let tag: [u8; 2] = tag
+            .as_bytes()
+            .try_into()
+            .ok()
+            .filter(|t: &[u8; 2]| t[0].is_ascii_alphabetic() && t[1].is_ascii_alphanumeric())
+            .ok_or_else(|| format!("无效的标签名（两个字符，首字符为字母）: {}", s))?;
+        let ty = match ty {
+            "A" => TagType::Char,
+            "i" => TagType::Int,
+            "f" => TagType::Float,
+            _ => return Err(format!("不支持的标签类型（只支持A、i、f）: {}", s)),
+        };
+        Ok(Self { tag, ty })
+    }
+}
+
+/// 直方图的一行：整数为取值，字符为字符本身，浮点为`[start,end)`区间。
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct TagBin {
+    pub value: String,
+    pub count: u64,
+}
+
+/// 单个标签的统计。
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct TagSummary {
+    pub tag: TagSpec,
+    /// 带有该标签且类型相符的记录数。
+    pub present: u64,
+    /// 没有该标签的记录数。
+    pub absent: u64,
+    /// 带有该标签但类型与请求不符的记录数（不计入分布）。
+    pub type_mismatch: u64,
+    /// 最小值（字符标签或没有值时为None，下同）。
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub mean: Option<f64>,
+    /// 中位数：整数为精确值，浮点为中位数所在bin的中点。
+    pub median: Option<f64>,
+    /// 浮点分bin宽度（只对浮点标签有值）。
+    pub bin_width: Option<f64>,
+    pub histogram: Vec<TagBin>,
+}
+
+impl fmt::Display for TagSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let na = |v: Option<f64>| v.map_or("NA".to_string(), |v| format!("{}", v));
+        writeln!(f, "tag: {}", self.tag)?;
+        writeln!(f, "present: {}", self.present)?;
+        writeln!(f, "absent: {}", self.absent)?;
+        writeln!(f, "type_mismatch: {}", self.type_mismatch)?;
+        writeln!(f, "min: {}", na(self.min))?;
+        writeln!(f, "max: {}", na(self.max))?;
+        writeln!(f, "mean: {}", OrNa(self.mean.map(PicardFloat)))?;
+        write!(f, "median: {}", na(self.median))
+    }
+}
+
+/// tag-stats报告。
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct TagStatsReport {
+    /// 读取的记录数。
+    pub records: u64,
+    /// 按请求顺序排列的各标签统计。
+    pub tags: Vec<TagSummary>,
+}
+
+impl TagStatsReport {
+    pub const TSV_HEADER: &'static str = "tag\tvalue\tcount";
+
+    /// 所有标签的直方图，TSV格式（含表头）。
+    pub fn histogram_tsv(&self) -> String {
+        let mut content = Self::TSV_HEADER.to_string();
+        for summary in &self.tags {
+            for bin in &summary.histogram {
+                content.push_str(&format!("\n{}\t{}\t{}", summary.tag, bin.value, bin.count));
+            }
+        }
+        content
+    }
+}
+
+impl fmt::Display for TagStatsReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "records: {}", self.records)?;
+        for summary in &self.tags {
+            write!(f, "\n\n{}", summary)?;
+        }
+        Ok(())
+    }
+}
+
+/// 单个标签的累加器。
+#[derive(Debug, Default)]
+struct TagAccumulator {
+    present: u64,
+    absent: u64,
+    type_mismatch: u64,
+    /// 整数为取值，浮点为bin下标，字符为字符码（超出i32范围的整数截断到边界）
+    counts: HashMap<i32, u32>,
+    min: f64,
+    max: f64,
+    sum: f64,
+}
+
+/// 按标签列表累积分布的收集器。
+#[derive(Debug)]
+pub struct TagStatsCollector {
+    specs: Vec<TagSpec>,
+    float_bin_width: f64,
+    records: u64,
+    accumulators: Vec<TagAccumulator>,
+}
+
+impl TagStatsCollector {
+    /// `float_bin_width`必须为正数。
+    pub fn new(specs: Vec<TagSpec>, float_bin_width: f64) -> Self {
+        let accumulators = specs.iter().map(|_| TagAccumulator::default()).collect();
+        Self {
+            specs,
+            float_bin_width,
+            records: 0,
+            accumulators,
+        }
+    }
+
+    /// 计入一条记录的全部请求标签。
+    pub fn add(&mut self, record: &BamRecord) {
+        self.records += 1;
+        for i in 0..self.specs.len() {
+            let value = record.aux(self.specs[i].tag);
+            self.add_value(i, value.as_ref());
+        }
+    }
+
+    /// 计入第`index`个标签在一条记录中的值（None表示记录没有该标签）。
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bamqc_core::TagStatsCollector;
+    /// use bamqc_io::AuxValue;
+    ///
+    /// let specs = vec!["AS:i".parse().unwrap(), "ZS:f".parse().unwrap(), "XT:A".parse().unwrap()];
+    /// let mut collector = TagStatsCollector::new(specs, 0.5);
+    /// collector.add_value(0, Some(&AuxValue::Int(10)));
+    /// collector.add_value(0, Some(&AuxValue::Int(30)));
+    /// collector.add_value(0, Some(&AuxValue::Int(20)));
+    /// collector.add_value(0, Some(&AuxValue::Float(20.0)));
+    /// collector.add_value(0, None);
+    /// collector.add_value(1, Some(&AuxValue::Float(0.25)));
+    /// collector.add_value(1, Some(&AuxValue::Float(0.5)));
+    /// collector.add_value(2, Some(&AuxValue::Char(b'U')));
+    /// collector.add_value(2, Some(&AuxValue::Char(b'U')));
+    /// collector.add_value(2, Some(&AuxValue::Char(b'M')));
+    ///
+    /// let report = collector.finish();
+    /// let int = &report.tags[0];
+    /// assert_eq!((int.present, int.absent, int.type_mismatch), (3, 1, 1));
+    /// assert_eq!((int.min, int.max, int.mean, int.median), (Some(10.0), Some(30.0), Some(20.0), Some(20.0)));
+    ///
+    /// let float = &report.tags[1];
+    /// assert_eq!(float.histogram[0].value, "[0,0.5)");
+    /// assert_eq!(float.histogram[1].value, "[0.5,1)");
+    ///
+    /// let char = &report.tags[2];
+    /// assert_eq!(char.mean, None);
+    /// assert_eq!((char.histogram[0].value.as_str(), char.histogram[0].count), ("M", 1));
+    /// assert_eq!((char.histogram[1].value.as_str(), char.histogram[1].count), ("U", 2));
+    /// ```
+    pub fn add_value(&mut self, index: usize, value: Option<&AuxValue>) {
+        let ty = self.specs[index].ty;
+        let width = self.float_bin_width;
+        let acc = &mut self.accumulators[index];
+        let (key, numeric) = match (ty, value) {
+            (_, None) => {
+                acc.absent += 1;
+                return;
+            }
+            (TagType::Int, Some(AuxValue::Int(v))) => {
+                ((*v).clamp(i32::MIN as i64, i32::MAX as i64) as i32, Some(*v as f64))
+            }
+            (TagType::Float, Some(AuxValue::Float(v))) => {
+                let v = *v as f64;
+                ((v / width).floor() as i32, Some(v))
+            }
+            (TagType::Char, Some(AuxValue::Char(c))) => (*c as i32, None),
+            (_, Some(_)) => {
+                acc.type_mismatch += 1;
+                return;
+            }
+        };
+
+        if let Some(v) = numeric {
+            if acc.present == 0 {
+                acc.min = v;
+                acc.max = v;
+            } else {
+                acc.min = acc.min.min(v);
+                acc.max = acc.max.max(v);
+            }
+            acc.sum += v;
+        }
+        acc.present += 1;
+        *acc.counts.entry(key).or_insert(0) += 1;
+    }
+
+    /// 汇总得到报告。
+    pub fn finish(self) -> TagStatsReport {
+        let width = self.float_bin_width;
+        let tags = self
+            .specs
+            .into_iter()
+            .zip(self.accumulators)
+            .map(|(spec, acc)| {
+                let numeric = spec.ty != TagType::Char && acc.present > 0;
+                let median = numeric.then(|| {
+                    let median = InsertSizeCalculator::calculate_median_from_counts(&acc.counts) as f64;
+                    match spec.ty {
+                        TagType::Float => (median + 0.5) * width,
+                        _ => median,
+                    }
+                });
+                let histogram = Histogram::from_counts(&acc.counts)
+                    .bins()
+                    .iter()
+                    .map(|bin| TagBin {
+                        value: match spec.ty {
+                            TagType::Int => bin.start.to_string(),
+                            TagType::Float => format!(
+                                "[{},{})",
+                                bin.start as f64 * width,
+                                (bin.start as f64 + 1.0) * width
+                            ),
+                            TagType::Char => (bin.start as u8 as char).to_string(),
+                        },
+                        count: bin.count as u64,
+                    })
+                    .collect();
+                TagSummary {
+                    tag: spec,
+                    present: acc.present,
+                    absent: acc.absent,
+                    type_mismatch: acc.type_mismatch,
+                    min: numeric.then_some(acc.min),
+                    max: numeric.then_some(acc.max),
+                    mean: numeric.then(|| acc.sum / acc.present as f64),
+                    median,
+                    bin_width: (spec.ty == TagType::Float).then_some(width),
+                    histogram,
+                }
+            })
+            .collect();
+
+        TagStatsReport {
+            records: self.records,
+            tags,
+        }
+    }
+}
+
+/// 统计所有记录中指定标签的分布。
+pub fn compute_tag_stats(
+    bam_path: &str,
+    reader: &BamReaderOptions,
+    specs: Vec<TagSpec>,
+    float_bin_width: f64,
+) -> Result<TagStatsReport, BamError> {
+    let mut reader = reader.open(bam_path)?;
+    let mut collector = TagStatsCollector::new(specs, float_bin_width);
+
+    info!("开始统计标签分布: {}", bam_path);
+
+    for result in reader.records() {
+        collector.add(&result?);
+    }
+
+    let report = collector.finish();
+    for summary in &report.tags {
+        if summary.type_mismatch > 0 {
+            warn!("{} 条记录的 {} 标签类型不符，未计入分布", summary.type_mismatch, summary.tag);
+        }
+        info!("{}: {} 条记录有该标签，{} 条没有", summary.tag, summary.present, summary.absent);
+    }
+
+    Ok(report)
+}
+
+metric_keys! {
+    /// `tag-stats`输出的键（见[`crate::metrics`]）。
+    TagStatsKeys for "tag-stats" {
+        RECORDS = "records", "记录数", Unit::Reads, None, "扫描的记录数。";
+        TAGS = "tags", "标签", Unit::List, None, "每个请求的标签一项。";
+        TAG = "tag", "标签", Unit::Label, None, "标签与类型，例如NM:i。";
+        PRESENT = "present", "出现数", Unit::Reads, None, "带有该标签且类型匹配的记录数。";
+        ABSENT = "absent", "缺失数", Unit::Reads, None, "没有该标签的记录数。";
+        TYPE_MISMATCH = "type_mismatch", "类型不符数", Unit::Reads, Some(false), "带有该标签但类型与请求不符的记录数。";
+        MIN = "min", "最小值", Unit::Count, None, "标签值的最小值。";
+        MAX = "max", "最大值", Unit::Count, None, "标签值的最大值。";
+        MEAN = "mean", "均值", Unit::Count, None, "标签值的均值。";
+        MEDIAN = "median", "中位数", Unit::Count, None, "标签值的中位数（浮点标签为分bin后的bin中心）。";
+        BIN_WIDTH = "bin_width", "bin宽度", Unit::Count, None, "浮点标签直方图的bin宽度，整数标签为null。";
+        HISTOGRAM = "histogram", "直方图", Unit::List, None, "标签值的计数直方图。";
+        VALUE = "value", "取值", Unit::Label, None, "整数值、字符或浮点bin区间。";
+        COUNT = "count", "记录数", Unit::Reads, None, "取该值的记录数。";
+    }
+}
@@ -0,0 +1,585 @@
+//! 靶向panel的逐靶区指标。
+//!
+//! 对BED中的每个靶区统计覆盖碱基数、平均深度、按覆盖碱基加权的平均MAPQ
+//! 以及MAPQ为0的读长占比。碱基数与深度只计M、=、X比对块，缺失（D）与
+//! 跳过（N）的参考位置不计。只统计primary、非duplicate、已比对的读长；
+//! MAPQ为255（不可用）的读长计入深度但不参与MAPQ统计；比对到参考序列
+//! 末端之外的读长被跳过并单独计数。
+//!
+//! 大panel中通常只需要看两端：覆盖最差（深度达到[`COVERED_DEPTH`]的碱基占比
+//! 最低）与覆盖最高（可能是脱靶热点或拷贝数变化）的靶区。逐碱基深度只在靶区
+//! 所在的查询进行期间保留，查询结束即汇总为该靶区的指标并送入两个容量为N的
+//! 堆，不需要事后对整张表排序。
+
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+use std::fmt;
+use bamqc_io::bam::{BamReaderOptions, BamError, BamRecord};
+use bamqc_io::region::{GenomicRegion, plan_queries, read_bed};
+#[cfg(feature = "serde")]
+use bamqc_io::serialize_error;
+use bamqc_io::ErrorCode;
+#[cfg(feature = "serde")]
+use serde::Serialize;
+use thiserror::Error;
+use crate::filter::{ReadFilter, ReadFilterCounts};
+use crate::formatting::{Depth, OrNa, PicardFloat, Rate};
+use crate::log::{info, debug};
+use crate::bounds::ContigBounds;
+use crate::metrics::Unit;
+use crate::metrics_keys::metric_keys;
+
+/// panel级汇总中列出的MAPQ最差靶区数量。
+pub const WORST_TARGETS: usize = 10;
+
+/// 覆盖最差与覆盖最高的靶区各列出的默认数量。
+pub const EXTREME_TARGETS: usize = 10;
+
+/// 覆盖最差靶区的排序依据：深度达到该值的碱基占比。
+pub const COVERED_DEPTH: u32 = 20;
+
+/// 靶区指标统计过程中可能发生的错误。
+///
+/// 错误代码与JSON形式见[`bamqc_io::error_code`]：
+///
+/// ```
+/// # #[cfg(feature = "serde")]
+/// # {
+/// use bamqc_core::TargetError;
+///
+/// let e = TargetError::NoTargets { path: "panel.bed".into() };
+/// assert_eq!(serde_json::to_string(&e).unwrap(), r#"{"code":"no_targets","message":"靶区文件中没有有效区域: panel.bed","details":{"path":"panel.bed"}}"#);
+/// # }
+/// ```
+#[derive(Error, Debug)]
+pub enum TargetError {
+    /// BED文件中没有任何靶区。
+    #[error("靶区文件中没有有效区域: {path}")]
+    NoTargets {
+        /// 靶区文件路径
+        path: String,
+    },
+
+    /// BAM文件IO错误。
+    #[error("BAM文件IO错误: {0}")]
+    BamError(#[from] BamError),
+}
+
+impl ErrorCode for TargetError {
+    fn code(&self) -> &'static str {
+        match self {
+            TargetError::NoTargets { .. } => "no_targets",
+            TargetError::BamError(e) => e.code(),
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    fn serialize_details<M: serde::ser::SerializeMap>(&self, map: &mut M) -> Result<(), M::Error> {
+        match self {
+            TargetError::NoTargets { path } => map.serialize_entry("path", path),
+            TargetError::BamError(e) => e.serialize_details(map),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for TargetError {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_error(self, serializer)
+    }
+}
+
+/// 单个靶区的指标。
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct TargetMetrics {
+    /// 靶区名称（BED第4列或`chr:start-end`）。
+    pub name: String,
+    /// 靶区长度。
+    pub length: u64,
+    /// 有比对碱基落在靶区内的读长数。
+    pub reads: u64,
+    /// 落在靶区内的比对碱基数（只计M、=、X，D与N不计）。
+    pub bases: u64,
+    /// 参与MAPQ统计的碱基数。
+    pub mapq_bases: u64,
+    /// 按覆盖碱基加权的MAPQ总和。
+    pub mapq_weighted_sum: u64,
+    /// MAPQ为0的读长数。
+    pub mapq0_reads: u64,
+    /// 深度达到[`COVERED_DEPTH`]的碱基数。
+    pub bases_20x: u64,
+}
+
+impl TargetMetrics {
+    /// 平均深度。
+    pub fn mean_depth(&self) -> f64 {
+        if self.length == 0 {
+            0.0
+        } else {
+            self.bases as f64 / self.length as f64
+        }
+    }
+
+    /// 按覆盖碱基加权的平均MAPQ，无可用读长时返回None。
+    pub fn mean_mapq(&self) -> Option<f64> {
+        if self.mapq_bases == 0 {
+            None
+        } else {
+            Some(self.mapq_weighted_sum as f64 / self.mapq_bases as f64)
+        }
+    }
+
+    /// MAPQ为0的读长占比。
+    pub fn mapq0_fraction(&self) -> f64 {
+        if self.reads == 0 {
+            0.0
+        } else {
+            self.mapq0_reads as f64 / self.reads as f64
+        }
+    }
+
+    /// 深度达到[`COVERED_DEPTH`]的碱基占比。
+    pub fn fraction_20x(&self) -> f64 {
+        if self.length == 0 {
+            0.0
+        } else {
+            self.bases_20x as f64 / self.length as f64
+        }
+    }
+
+    fn add(&mut self, record: &BamRecord, overlap: u64) {
+        self.reads += 1;
+        self.bases += overlap;
+
+        let mapq = record.mapq();
+        if mapq != 255 {
+            self.mapq_bases += overlap;
+            self.mapq_weighted_sum += overlap * mapq as u64;
+        }
+        if mapq == 0 {
+            self.mapq0_reads += 1;
+        }
+    }
+}
+
+/// 覆盖最差或最高列表中的一个靶区。
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct TargetExtreme {
+    /// 靶区坐标（`chr:start-end`）。
+    pub target: String,
+    /// 基因名（BED第4列），缺失时为None。
+    pub gene: Option<String>,
+    /// 平均深度。
+    pub mean_depth: f64,
+    /// 深度达到[`COVERED_DEPTH`]的碱基占比。
+    pub fraction_20x: f64,
+}
+
+/// 堆中的排序键：依次比较`primary`、`secondary`与BED中的下标，越小越靠前。
+#[derive(Clone, Copy, Debug)]
+struct RankKey {
+    primary: f64,
+    secondary: f64,
+    index: usize,
+}
+
+impl Ord for RankKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.primary
+            .total_cmp(&other.primary)
+            .then_with(|| self.secondary.total_cmp(&other.secondary))
+            .then_with(|| self.index.cmp(&other.index))
+    }
+}
+
+impl PartialOrd for RankKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for RankKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for RankKey {}
+
+/// 只保留排序最靠前的`limit`个键：大顶堆，超出容量时弹出最靠后的一个。
+#[derive(Debug)]
+struct BoundedRanking {
+    limit: usize,
+    heap: BinaryHeap<RankKey>,
+}
+
+impl BoundedRanking {
+    fn new(limit: usize) -> Self {
+        Self { limit, heap: BinaryHeap::with_capacity(limit + 1) }
+    }
+
+    fn push(&mut self, key: RankKey) {
+        if self.limit == 0 {
+            return;
+        }
+        if self.heap.len() < self.limit {
+            self.heap.push(key);
+        } else if self.heap.peek().is_some_and(|last| key < *last) {
+            self.heap.pop();
+            self.heap.push(key);
+        }
+    }
+
+    /// 按排序返回保留的下标。
+    fn into_indices(self) -> Vec<usize> {
+        self.heap.into_sorted_vec().into_iter().map(|key| key.index).collect()
+    }
+}
+
+/// 逐靶区指标收集器。
+#[derive(Debug)]
+pub struct TargetMetricsCollector {
+    targets: Vec<GenomicRegion>,
+    metrics: Vec<TargetMetrics>,
+    /// 尚未汇总的靶区的深度差分数组（长度为靶区长度+1）。
+    depth: HashMap<usize, Vec<i32>>,
+    /// 已汇总并送入排名的靶区。
+    finished: Vec<bool>,
+    /// 覆盖最差：20x占比升序，其次平均深度升序。
+    worst_covered: BoundedRanking,
+    /// 覆盖最高：平均深度降序，其次20x占比降序。
+    most_covered: BoundedRanking,
+    /// 按原因统计的被过滤记录（跨两次查询的记录计两次）。
+    filtered: ReadFilterCounts,
+}
+
+impl TargetMetricsCollector {
+    /// `extremes`为覆盖最差与覆盖最高的靶区各列出的数量。
+    pub fn new(targets: Vec<GenomicRegion>, extremes: usize) -> Self {
+        let metrics = targets
+            .iter()
+            .map(|t| TargetMetrics {
+                name: t.label(),
+                length: t.span().unwrap_or(0) as u64,
+                ..Default::default()
+            })
+            .collect();
+
+        Self {
+            finished: vec![false; targets.len()],
+            targets,
+            metrics,
+            depth: HashMap::new(),
+            worst_covered: BoundedRanking::new(extremes),
+            most_covered: BoundedRanking::new(extremes),
+            filtered: ReadFilterCounts::default(),
+        }
+    }
+
+    /// 未比对、secondary、supplementary、duplicate、QC失败的记录不计入，按原因的计数。
+    pub fn filter_counts(&self) -> &ReadFilterCounts {
+        &self.filtered
+    }
+
+    pub fn targets(&self) -> &[GenomicRegion] {
+        &self.targets
+    }
+
+    /// 将记录计入`members`中与之重叠的靶区。
+    pub fn update(&mut self, record: &BamRecord, members: &[usize]) {
+        if !ReadFilter::default().count(record, &mut self.filtered) {
+            return;
+        }
+
+        // 1-based闭区间；只有M、=、X比对块计入碱基数与深度，D与N跨过的位置不计
+        let start = (record.pos() + 1) as usize;
+        let end = record.reference_end() as usize;
+        if end < start {
+            return;
+        }
+        let cigar = record.cigar();
+
+        for &i in members {
+            let target = &self.targets[i];
+            if !target.overlaps(start, end) {
+                continue;
+            }
+            let target_end = target.end.unwrap_or(usize::MAX);
+            let length = self.metrics[i].length as usize;
+            let mut overlap = 0;
+            for (block_start, block_end) in cigar.aligned_blocks(start as u64) {
+                let (block_start, block_end) = (block_start as usize, block_end as usize);
+                if !target.overlaps(block_start, block_end) {
+                    continue;
+                }
+                let overlap_start = block_start.max(target.start);
+                let overlap_end = block_end.min(target_end);
+                overlap += (overlap_end + 1 - overlap_start) as u64;
+                let diff = self.depth.entry(i).or_insert_with(|| vec![0; length + 1]);
+                diff[overlap_start - target.start] += 1;
+                diff[overlap_end + 1 - target.start] -= 1;
+            }
+            if overlap > 0 {
+                self.metrics[i].add(record, overlap);
+            }
+        }
+    }
+
+    /// 汇总`members`中靶区的逐碱基深度并送入两端的排名，之后这些靶区不再接收记录。
+    ///
+    /// 查询结束后对其成员调用，释放深度数组；已汇总的靶区被忽略。
+    pub fn finish_targets(&mut self, members: &[usize]) {
+        for &i in members {
+            if std::mem::replace(&mut self.finished[i], true) {
+                continue;
+            }
+            let metrics = &mut self.metrics[i];
+            if let Some(diff) = self.depth.remove(&i) {
+                let mut depth = 0i32;
+                metrics.bases_20x = diff[..diff.len() - 1]
+                    .iter()
+                    .filter(|&&delta| {
+                        depth += delta;
+                        depth >= COVERED_DEPTH as i32
+                    })
+                    .count() as u64;
+            }
+            let (mean_depth, fraction_20x) = (metrics.mean_depth(), metrics.fraction_20x());
+            self.worst_covered.push(RankKey { primary: fraction_20x, secondary: mean_depth, index: i });
+            self.most_covered.push(RankKey { primary: -mean_depth, secondary: -fraction_20x, index: i });
+        }
+    }
+
+    fn extremes(&self, ranking: BoundedRanking) -> Vec<TargetExtreme> {
+        ranking
+            .into_indices()
+            .into_iter()
+            .map(|i| {
+                let target = &self.targets[i];
+                TargetExtreme {
+                    target: GenomicRegion { label: None, ..target.clone() }.to_string(),
+                    gene: target.label.clone(),
+                    mean_depth: self.metrics[i].mean_depth(),
+                    fraction_20x: self.metrics[i].fraction_20x(),
+                }
+            })
+            .collect()
+    }
+
+    /// 汇总尚未汇总的靶区并生成报告。
+    pub fn finish(mut self) -> TargetReport {
+        let pending: Vec<usize> = (0..self.metrics.len()).filter(|&i| !self.finished[i]).collect();
+        self.finish_targets(&pending);
+        let worst_covered = std::mem::replace(&mut self.worst_covered, BoundedRanking::new(0));
+        let most_covered = std::mem::replace(&mut self.most_covered, BoundedRanking::new(0));
+        let worst_covered_targets = self.extremes(worst_covered);
+        let most_covered_targets = self.extremes(most_covered);
+        TargetReport {
+            worst_covered_targets,
+            most_covered_targets,
+            ..TargetReport::new(self.metrics)
+        }
+    }
+}
+
+/// 逐靶区指标报告。
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct TargetReport {
+    /// 每个靶区一行（顺序与BED文件一致）。
+    pub targets: Vec<TargetMetrics>,
+    /// 平均MAPQ最低的靶区名称（最多[`WORST_TARGETS`]个，按平均MAPQ升序）。
+    pub worst_mapq_targets: Vec<String>,
+    /// 覆盖最差的靶区：20x占比升序，相同时平均深度升序，再相同时按BED顺序。
+    pub worst_covered_targets: Vec<TargetExtreme>,
+    /// 覆盖最高的靶区：平均深度降序，相同时20x占比降序，再相同时按BED顺序。
+    pub most_covered_targets: Vec<TargetExtreme>,
+    /// 因比对到参考序列末端之外而被跳过的读长数。
+    pub past_contig_end: u64,
+}
+
+impl TargetReport {
+    pub fn new(targets: Vec<TargetMetrics>) -> Self {
+        let mut ranked: Vec<&TargetMetrics> =
+            targets.iter().filter(|t| t.mean_mapq().is_some()).collect();
+        ranked.sort_by(|a, b| {
+            a.mean_mapq()
+                .partial_cmp(&b.mean_mapq())
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then_with(|| a.name.cmp(&b.name))
+        });
+        let worst_mapq_targets = ranked
+            .into_iter()
+            .take(WORST_TARGETS)
+            .map(|t| t.name.clone())
+            .collect();
+
+        Self {
+            targets,
+            worst_mapq_targets,
+            worst_covered_targets: Vec::new(),
+            most_covered_targets: Vec::new(),
+            past_contig_end: 0,
+        }
+    }
+}
+
+impl fmt::Display for TargetReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "target\tlength\treads\tmean_depth\tmean_mapq\tmapq0_fraction")?;
+        for t in &self.targets {
+            writeln!(
+                f,
+                "{}\t{}\t{}\t{}\t{}\t{}",
+                t.name,
+                t.length,
+                t.reads,
+                Depth(t.mean_depth()),
+                OrNa(t.mean_mapq().map(PicardFloat)),
+                Rate(t.mapq0_fraction())
+            )?;
+        }
+        write!(f, "# worst_mapq_targets: {}", self.worst_mapq_targets.join(","))?;
+        let names = |extremes: &[TargetExtreme]| -> String {
+            extremes
+                .iter()
+                .map(|t| t.gene.as_ref().map_or_else(|| t.target.clone(), |gene| format!("{}({})", gene, t.target)))
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        write!(f, "\n# worst_covered_targets: {}", names(&self.worst_covered_targets))?;
+        write!(f, "\n# most_covered_targets: {}", names(&self.most_covered_targets))?;
+        if self.past_contig_end > 0 {
+            write!(f, "\n# past_contig_end: {}", self.past_contig_end)?;
+        }
+        Ok(())
+    }
+}
+
+/// 按BED靶区统计逐靶区指标，并列出覆盖最差与覆盖最高的靶区各`extremes`个。
+///
+/// # Examples
+///
+/// 1000个靶区的合成panel，两端的列表与对整张表排序的结果一致：
+///
+/// ```
+/// use bamqc_core::{compute_target_metrics, TargetMetrics};
+/// use bamqc_io::bam::{BamReaderOptions, BamWriter};
+/// use bamqc_io::write_bai;
+/// use noodles::core::Position;
+/// use noodles::sam::alignment::record::cigar::op::{Kind, Op};
+/// use noodles::sam::alignment::record::Flags;
+/// use noodles::sam::alignment::RecordBuf;
+///
+/// let dir = std::env::temp_dir().join(format!("bamqc-target-extremes-{}", std::process::id()));
+/// std::fs::create_dir_all(&dir).unwrap();
+/// let bam = dir.join("panel.bam").to_string_lossy().to_string();
+/// let bed = dir.join("panel.bed").to_string_lossy().to_string();
+///
+/// // 靶区i位于c1:i*300+1..i*300+100，有(i*37)%45条读长，起点在靶区内错开；
+/// // 每隔一个靶区带基因名
+/// let mut starts = Vec::new();
+/// let mut bed_text = String::new();
+/// for i in 0..1000usize {
+///     let start = i * 300;
+///     bed_text.push_str(&format!("c1\t{}\t{}", start, start + 100));
+///     if i % 2 == 0 {
+///         bed_text.push_str(&format!("\tGENE{}", i));
+///     }
+///     bed_text.push('\n');
+///     for j in 0..(i * 37) % 45 {
+///         starts.push(start + 1 + (j * 13) % 60);
+///     }
+/// }
+/// std::fs::write(&bed, bed_text).unwrap();
+/// starts.sort_unstable();
+/// let header = "@HD\tVN:1.6\tSO:coordinate\n@SQ\tSN:c1\tLN:400000\n".parse().unwrap();
+/// let mut writer = BamWriter::from_path(&bam, &header).unwrap();
+/// for (n, start) in starts.iter().enumerate() {
+///     let record = RecordBuf::builder()
+///         .set_name(format!("r{}", n))
+///         .set_flags(Flags::empty())
+///         .set_reference_sequence_id(0)
+///         .set_alignment_start(Position::try_from(*start).unwrap())
+///         .set_mapping_quality(60.try_into().unwrap())
+///         .set_cigar([Op::new(Kind::Match, 50)].into_iter().collect())
+///         .build();
+///     writer.write_record_buf(&record).unwrap();
+/// }
+/// writer.finish().unwrap();
+/// write_bai(&bam).unwrap();
+///
+/// let report = compute_target_metrics(&bam, &BamReaderOptions::new(), &bed, 15).unwrap();
+/// let mut order: Vec<usize> = (0..report.targets.len()).collect();
+/// let key = |t: &TargetMetrics| (t.fraction_20x(), t.mean_depth());
+/// order.sort_by(|&a, &b| key(&report.targets[a]).partial_cmp(&key(&report.targets[b])).unwrap().then(a.cmp(&b)));
+/// let names = |indices: &[usize]| -> Vec<String> { indices.iter().map(|&i| report.targets[i].name.clone()).collect() };
+/// let label = |t: &bamqc_core::TargetExtreme| t.gene.clone().unwrap_or_else(|| t.target.clone());
+/// assert_eq!(report.worst_covered_targets.iter().map(label).collect::<Vec<_>>(), names(&order[..15]));
+///
+/// let key = |t: &TargetMetrics| (t.mean_depth(), t.fraction_20x());
+/// order.sort_by(|&a, &b| key(&report.targets[b]).partial_cmp(&key(&report.targets[a])).unwrap().then(a.cmp(&b)));
+/// assert_eq!(report.most_covered_targets.iter().map(label).collect::<Vec<_>>(), names(&order[..15]));
+///
+/// // 两端都存在并列，排序由BED顺序决定；基因名与坐标分开给出
+/// assert_eq!(report.worst_covered_targets[0].target, "c1:1-100");
+/// assert_eq!(report.worst_covered_targets[0].gene.as_deref(), Some("GENE0"));
+/// assert_eq!(report.worst_covered_targets[0].mean_depth, 0.0);
+/// assert!(report.most_covered_targets[0].fraction_20x > 0.0);
+/// std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+pub fn compute_target_metrics(bam_path: &str, reader: &BamReaderOptions, bed_path: &str, extremes: usize) -> Result<TargetReport, TargetError> {
+    let targets = read_bed(bed_path)?;
+    if targets.is_empty() {
+        return Err(TargetError::NoTargets { path: bed_path.to_string() });
+    }
+
+    let mut reader = reader.open(bam_path)?;
+    let plan = plan_queries(&targets, reader.header())?;
+    info!("{} 个靶区合并为 {} 次索引查询", targets.len(), plan.len());
+
+    let mut bounds = ContigBounds::from_header(reader.header());
+    let mut collector = TargetMetricsCollector::new(targets, extremes);
+    for query in &plan {
+        debug!("查询区域 {}", query.region);
+        for result in reader.query(&query.region)? {
+            let record = result?;
+            if bounds.check(&record) {
+                collector.update(&record, &query.members);
+            }
+        }
+        collector.finish_targets(&query.members);
+    }
+    debug!("记录过滤: {}", collector.filter_counts());
+
+    let mut report = collector.finish();
+    report.past_contig_end = bounds.past_end();
+    Ok(report)
+}
+
+metric_keys! {
+    /// `targets`输出的键（见[`crate::metrics`]）。
+    TargetKeys for "targets" {
+        TARGETS = "targets", "靶区", Unit::List, None, "每个靶区一行的指标，顺序与BED文件一致。";
+        NAME = "name", "靶区名", Unit::Label, None, "BED第4列，缺失时为坐标。";
+        TARGET = "target", "靶区名", Unit::Label, None, "TSV中的靶区名。";
+        LENGTH = "length", "靶区长度", Unit::BasePairs, None, "靶区长度。";
+        READS = "reads", "读长数", Unit::Reads, None, "有比对碱基落在靶区内的读长数。";
+        BASES = "bases", "覆盖碱基数", Unit::Bases, None, "比对到靶区内的碱基数，只计M、=、X，D与N不计。";
+        MAPQ_BASES = "mapq_bases", "MAPQ有效的碱基数", Unit::Bases, None, "MAPQ不为255的覆盖碱基数，作为加权平均MAPQ的分母。";
+        MAPQ_WEIGHTED_SUM = "mapq_weighted_sum", "MAPQ加权和", Unit::Phred, None, "覆盖碱基数加权的MAPQ之和。";
+        MAPQ0_READS = "mapq0_reads", "MAPQ为0的读长数", Unit::Reads, Some(false), "有比对碱基落在靶区内且MAPQ为0的读长数。";
+        MEAN_DEPTH = "mean_depth", "平均深度", Unit::Count, Some(true), "bases / length。";
+        MEAN_MAPQ = "mean_mapq", "加权平均MAPQ", Unit::Phred, Some(true), "mapq_weighted_sum / mapq_bases。";
+        MAPQ0_FRACTION = "mapq0_fraction", "MAPQ0占比", Unit::Fraction, Some(false), "mapq0_reads / reads。";
+        WORST_MAPQ_TARGETS = "worst_mapq_targets", "平均MAPQ最低的靶区", Unit::List, Some(false), "按加权平均MAPQ升序的靶区名。";
+        BASES_20X = "bases_20x", "20x碱基数", Unit::Bases, Some(true), "深度达到20的靶区碱基数（按M、=、X比对块计）。";
+        FRACTION_20X = "fraction_20x", "20x占比", Unit::Fraction, Some(true), "bases_20x / length。";
+        WORST_COVERED_TARGETS = "worst_covered_targets", "覆盖最差的靶区", Unit::List, Some(false), "按20x占比升序（相同时按平均深度升序）的靶区。";
+        MOST_COVERED_TARGETS = "most_covered_targets", "覆盖最高的靶区", Unit::List, None, "按平均深度降序的靶区，可能是脱靶热点或拷贝数变化。";
+        GENE = "gene", "基因名", Unit::Label, None, "BED第4列，缺失时为null。";
+        PAST_CONTIG_END = "past_contig_end", "超出参考序列末端的记录数", Unit::Reads, Some(false), "比对终点超出参考序列长度的记录数。";
+    }
+}
@@ -0,0 +1,214 @@
+//! 逐记录检查FLAG中互相矛盾的位组合（见[`FlagInconsistency`]），统计各类矛盾的记录数，
+//! 用于发现有缺陷的上游流程。同时统计比对超出参考序列末端的记录（见[`ContigBounds`]）。
+
+use std::collections::BTreeMap;
+use std::fmt;
+use bamqc_io::bam::{BamReaderOptions, BamError, FlagInconsistency};
+#[cfg(feature = "serde")]
+use serde::{Serialize, Serializer, ser::SerializeMap};
+use crate::bounds::ContigBounds;
+use crate::log::{info, warn};
+use crate::metrics::Unit;
+use crate::metrics_keys::metric_keys;
+
+/// 各类矛盾的记录数，按[`FlagInconsistency::CODES`]的顺序。
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct InconsistencyCounts([u64; FlagInconsistency::CODES.len()]);
+
+impl InconsistencyCounts {
+    /// 某一类矛盾的记录数。
+    pub fn get(&self, kind: &FlagInconsistency) -> u64 {
+        self.0[Self::slot(kind)]
+    }
+
+    /// `(代码, 记录数)`，包含计数为0的种类。
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, u64)> + '_ {
+        FlagInconsistency::CODES.iter().copied().zip(self.0.iter().copied())
+    }
+
+    fn add(&mut self, kind: &FlagInconsistency) {
+        self.0[Self::slot(kind)] += 1;
+    }
+
+    fn slot(kind: &FlagInconsistency) -> usize {
+        FlagInconsistency::CODES
+            .iter()
+            .position(|code| *code == kind.code())
+            .expect("CODES包含全部种类")
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for InconsistencyCounts {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (code, count) in self.iter() {
+            map.serialize_entry(code, &count)?;
+        }
+        map.end()
+    }
+}
+
+/// FLAG一致性检查结果。
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct FlagValidationReport {
+    /// BAM文件路径。
+    pub path: String,
+    /// 检查的记录数（包括secondary与supplementary）。
+    pub total_records: u64,
+    /// 至少有一处矛盾的记录数。
+    pub inconsistent_records: u64,
+    /// 各类矛盾的记录数；一条记录可能同时计入多类。
+    pub inconsistencies: InconsistencyCounts,
+    /// 有矛盾的记录按FLAG原始值计数，按FLAG排列。
+    pub inconsistent_flags: BTreeMap<u16, u64>,
+    /// 比对起点或终点超出参考序列长度（或参考序列不在头部中）的记录数。
+    pub past_contig_end: u64,
+    /// 输入没有任何记录（只有头部）。
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "std::ops::Not::not"))]
+    pub no_records: bool,
+}
+
+impl FlagValidationReport {
+    /// 是否没有发现矛盾，也没有超出参考序列末端的记录。
+    pub fn is_ok(&self) -> bool {
+        self.inconsistent_records == 0 && self.past_contig_end == 0
+    }
+
+    /// 计入一条记录的FLAG原始值。
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bamqc_core::FlagValidationReport;
+    /// use bamqc_io::FlagInconsistency;
+    ///
+    /// let mut report = FlagValidationReport::default();
+    /// for flags in [99, 147, 0x1 | 0x2 | 0x8 | 0x20, 0x1 | 0x2 | 0x8 | 0x20, 0x40] {
+    ///     report.add_flags(flags);
+    /// }
+    /// assert_eq!((report.total_records, report.inconsistent_records), (5, 3));
+    /// assert_eq!(report.inconsistencies.get(&FlagInconsistency::ProperPairMateUnmapped), 2);
+    /// assert_eq!(report.inconsistencies.get(&FlagInconsistency::MateReverseMateUnmapped), 2);
+    /// assert_eq!(report.inconsistencies.get(&FlagInconsistency::PairedBitsOnUnpaired { bits: 0 }), 1);
+    /// assert_eq!(report.inconsistent_flags.iter().collect::<Vec<_>>(), [(&0x2b, &2), (&0x40, &1)]);
+    /// assert!(!report.is_ok());
+    /// ```
+    pub fn add_flags(&mut self, flags: u16) {
+        self.total_records += 1;
+        let found = FlagInconsistency::check(flags);
+        if found.is_empty() {
+            return;
+        }
+        self.inconsistent_records += 1;
+        *self.inconsistent_flags.entry(flags).or_default() += 1;
+        for kind in &found {
+            self.inconsistencies.add(kind);
+        }
+    }
+
+    fn log_summary(&self) {
+        for (code, count) in self.inconsistencies.iter().filter(|&(_, count)| count > 0) {
+            warn!("{} 条记录的FLAG矛盾: {}", count, code);
+        }
+        if self.past_contig_end > 0 {
+            warn!("{} 条记录的比对超出参考序列末端", self.past_contig_end);
+        }
+        info!("检查完成：{} 条记录，{} 条FLAG矛盾", self.total_records, self.inconsistent_records);
+    }
+}
+
+impl fmt::Display for FlagValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "path: {}", self.path)?;
+        writeln!(f, "total_records: {}", self.total_records)?;
+        writeln!(f, "inconsistent_records: {}", self.inconsistent_records)?;
+        writeln!(f, "past_contig_end: {}", self.past_contig_end)?;
+        for (code, count) in self.inconsistencies.iter() {
+            writeln!(f, "{}: {}", code, count)?;
+        }
+        for (flags, count) in &self.inconsistent_flags {
+            writeln!(f, "inconsistent_flag: {} ({})", flags, count)?;
+        }
+        if self.no_records {
+            writeln!(f, "no_records: true")?;
+        }
+        write!(f, "status: {}", if self.is_ok() { "ok" } else { "warning" })
+    }
+}
+
+/// 检查BAM中每条记录的FLAG一致性，以及比对是否超出参考序列末端。
+///
+/// # Examples
+///
+/// ```
+/// use bamqc_core::compute_flag_validation;
+/// use bamqc_io::bam::{BamReaderOptions, BamWriter};
+/// use noodles::sam;
+///
+/// let bam = std::env::temp_dir().join(format!("bamqc-validate-{}.bam", std::process::id()));
+/// // 第二对：r2的mate未比对却设置了proper pair与mate反向；u1没有0x1却设置了0x40
+/// let text = "@HD\tVN:1.6\n@SQ\tSN:c1\tLN:1000\n\
+///             r1\t99\tc1\t100\t60\t4M\t=\t200\t104\tACGT\t*\n\
+///             r1\t147\tc1\t200\t60\t4M\t=\t100\t-104\tACGT\t*\n\
+///             r2\t107\tc1\t300\t60\t4M\t=\t300\t0\tACGT\t*\n\
+///             r2\t133\tc1\t300\t0\t*\t=\t300\t0\tACGT\t*\n\
+///             u1\t64\tc1\t400\t60\t4M\t*\t0\t0\tACGT\t*\n";
+/// let mut sam_reader = sam::io::Reader::new(text.as_bytes());
+/// let header = sam_reader.read_header().unwrap();
+/// let mut writer = BamWriter::from_path(&bam, &header).unwrap();
+/// for record in sam_reader.record_bufs(&header) {
+///     writer.write_record_buf(&record.unwrap()).unwrap();
+/// }
+/// writer.finish().unwrap();
+///
+/// let report = compute_flag_validation(&bam.to_string_lossy(), &BamReaderOptions::new()).unwrap();
+/// assert_eq!((report.total_records, report.inconsistent_records), (5, 2));
+/// let text = report.to_string();
+/// assert!(text.contains("proper_pair_mate_unmapped: 1\nmate_reverse_mate_unmapped: 1\npaired_bits_on_unpaired: 1\n"));
+/// assert!(text.contains("inconsistent_flag: 64 (1)\ninconsistent_flag: 107 (1)\n"));
+/// assert!(text.ends_with("status: warning"));
+/// std::fs::remove_file(&bam).unwrap();
+/// ```
+pub fn compute_flag_validation(bam_path: &str, reader: &BamReaderOptions) -> Result<FlagValidationReport, BamError> {
+    let mut reader = reader.open(bam_path)?;
+    let mut report = FlagValidationReport {
+        path: bam_path.to_string(),
+        no_records: true,
+        ..Default::default()
+    };
+
+    info!("开始检查FLAG一致性: {}", bam_path);
+
+    let mut bounds = ContigBounds::from_header(reader.header());
+    for result in reader.records() {
+        let record = result?;
+        report.no_records = false;
+        report.add_flags(record.flags_raw());
+        bounds.check(&record);
+    }
+    report.past_contig_end = bounds.past_end();
+
+    report.log_summary();
+    Ok(report)
+}
+
+metric_keys! {
+    /// `validate`输出的键（见[`crate::metrics`]）。
+    ValidateKeys for "validate" {
+        PATH = "path", "文件路径", Unit::Label, None, "检查的BAM路径。";
+        TOTAL_RECORDS = "total_records", "记录数", Unit::Reads, None, "检查的记录数，包括secondary与supplementary。";
+        INCONSISTENT_RECORDS = "inconsistent_records", "FLAG矛盾的记录数", Unit::Reads, Some(false), "FLAG中至少有一处矛盾组合的记录数。";
+        PAST_CONTIG_END = "past_contig_end", "超出参考序列末端的记录数", Unit::Reads, Some(false), "比对起点或终点超出参考序列长度的记录数，不为0时status为warning。";
+        INCONSISTENCIES = "inconsistencies", "各类FLAG矛盾", Unit::Map, Some(false), "各类矛盾组合的记录数（proper_pair_unmapped、proper_pair_mate_unmapped、mate_reverse_mate_unmapped、paired_bits_on_unpaired），一条记录可能计入多类。";
+        PROPER_PAIR_UNMAPPED = "proper_pair_unmapped", "proper pair但未比对", Unit::Reads, Some(false), "文本输出：设置了0x2但自身未比对（0x4）的记录数。";
+        PROPER_PAIR_MATE_UNMAPPED = "proper_pair_mate_unmapped", "proper pair但mate未比对", Unit::Reads, Some(false), "文本输出：设置了0x2但mate未比对（0x8）的记录数。";
+        MATE_REVERSE_MATE_UNMAPPED = "mate_reverse_mate_unmapped", "mate未比对但mate反向", Unit::Reads, Some(false), "文本输出：mate未比对（0x8）却设置了mate反向（0x20）的记录数。";
+        PAIRED_BITS_ON_UNPAIRED = "paired_bits_on_unpaired", "非配对读设置了配对位", Unit::Reads, Some(false), "文本输出：没有0x1却设置了0x2、0x8、0x20、0x40或0x80的记录数。";
+        INCONSISTENT_FLAGS = "inconsistent_flags", "矛盾记录的FLAG分布", Unit::Map, None, "有矛盾的记录按FLAG原始值（十进制）计数。";
+        INCONSISTENT_FLAG = "inconsistent_flag", "矛盾记录的FLAG", Unit::Label, None, "文本输出中的一个FLAG原始值及其记录数。";
+        NO_RECORDS = "no_records", "输入为空", Unit::Boolean, None, "输入只有头部、没有任何记录时为true；有记录时不输出。";
+        STATUS = "status", "检查结果", Unit::Label, None, "没有FLAG矛盾且没有超出参考序列末端的记录时为ok，否则为warning。";
+    }
+}
@@ -0,0 +1,48 @@
+//! 将通过过滤的记录导出为SAM文本，用于抽查过滤条件和调试。
+
+use std::io::Write;
+use bamqc_io::bam::{BamReaderOptions, BamError};
+use bamqc_io::region::GenomicRegion;
+use crate::filter::RecordFilter;
+use crate::record_stream::RecordStream;
+
+/// view选项。
+#[derive(Clone, Debug, Default)]
+pub struct ViewOptions {
+    /// 记录过滤条件。
+    pub filter: RecordFilter,
+    /// 只输出与该区域重叠的记录（需要索引）。
+    pub region: Option<GenomicRegion>,
+    /// 最多输出的记录数。
+    pub limit: Option<u64>,
+    /// 是否先输出SAM头部。
+    pub include_header: bool,
+    /// 打开输入的读取器配置（解压线程数、输入格式、CRAM参考序列等）。
+    pub reader: BamReaderOptions,
+}
+
+/// 将通过过滤的记录以SAM文本逐行写入`out`，返回写出的记录数。
+pub fn view_records<W: Write>(bam_path: &str, options: &ViewOptions, out: &mut W) -> Result<u64, BamError> {
+    let mut reader = options.reader.open(bam_path)?;
+    let header = reader.header().clone();
+
+    if options.include_header {
+        let mut writer = noodles::sam::io::Writer::new(&mut *out);
+        writer.write_header(&header)?;
+    }
+
+    let limit = options.limit.unwrap_or(u64::MAX);
+    match &options.region {
+        Some(region) => write_sam(reader.query(region)?.filtered(options.filter).take_records(limit), &header, out),
+        None => write_sam(reader.records().filtered(options.filter).take_records(limit), &header, out),
+    }
+}
+
+fn write_sam<W: Write>(records: impl RecordStream, header: &noodles::sam::Header, out: &mut W) -> Result<u64, BamError> {
+    let mut written = 0;
+    for result in records {
+        writeln!(out, "{}", result?.to_sam_string(header)?)?;
+        written += 1;
+    }
+    Ok(written)
+}
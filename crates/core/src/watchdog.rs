@@ -0,0 +1,201 @@
+//! 运行时限看门狗。
+//!
+//! 读取卡在挂死的网络文件系统上时，主线程会一直阻塞在`read()`中，无法在
+//! 记录循环里检查取消标志。看门狗在独立线程中定期检查总运行时间，以及
+//! [`ReadProgress`]（记录数与字节偏移）在一段时间内是否前进，触发时调用
+//! 给定的回调，由调用方报告原因并以[`EXIT_CANCELLED`]退出。
+//!
+//! 取消时不输出部分结果：主线程可能正阻塞在`read()`中，无法回到收集器汇总
+//! 已读取的记录，因此命令行在回调中直接结束进程。支持`--emit-interim`的
+//! 子命令中，被取消前最后一次写出的快照即为可用的中途结果。
+
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+use bamqc_io::{ErrorCode, ReadProgress};
+#[cfg(feature = "serde")]
+use bamqc_io::serialize_error;
+use thiserror::Error;
+use crate::log::debug;
+
+/// 被看门狗取消时的退出码（与coreutils `timeout`一致）。
+pub const EXIT_CANCELLED: i32 = 124;
+
+/// 取消原因。
+///
+/// 取消原因也实现[`ErrorCode`]，退出码为[`EXIT_CANCELLED`]：
+///
+/// ```
+/// use std::time::Duration;
+/// use bamqc_core::{CancelReason, EXIT_CANCELLED};
+/// use bamqc_io::ErrorCode;
+///
+/// let reason = CancelReason::IoStall(Duration::from_secs(300));
+/// assert_eq!((reason.code(), reason.exit_code()), ("io_stall", EXIT_CANCELLED));
+/// # #[cfg(feature = "serde")]
+/// assert_eq!(
+///     serde_json::to_string(&CancelReason::Timeout(Duration::from_secs(7200))).unwrap(),
+///     r#"{"code":"timeout","message":"运行时间超过 7200s（--timeout），已取消","details":{"limit_secs":7200}}"#
+/// );
+/// ```
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CancelReason {
+    /// 总运行时间超过`--timeout`。
+    #[error("运行时间超过 {}s（--timeout），已取消", .0.as_secs())]
+    Timeout(Duration),
+
+    /// 读取在`--io-stall-timeout`内没有任何进展。
+    #[error("读取 {}s 内没有进展（--io-stall-timeout），已取消", .0.as_secs())]
+    IoStall(Duration),
+}
+
+impl ErrorCode for CancelReason {
+    fn code(&self) -> &'static str {
+        match self {
+            CancelReason::Timeout(_) => "timeout",
+            CancelReason::IoStall(_) => "io_stall",
+        }
+    }
+
+    fn exit_code(&self) -> i32 {
+        EXIT_CANCELLED
+    }
+
+    #[cfg(feature = "serde")]
+    fn serialize_details<M: serde::ser::SerializeMap>(&self, map: &mut M) -> Result<(), M::Error> {
+        match self {
+            CancelReason::Timeout(limit) | CancelReason::IoStall(limit) => map.serialize_entry("limit_secs", &limit.as_secs()),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for CancelReason {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_error(self, serializer)
+    }
+}
+
+/// 看门狗的时限配置。
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct WatchdogConfig {
+    /// 总运行时间上限。
+    pub timeout: Option<Duration>,
+    /// 记录数与字节偏移都不变的最长时间。
+    pub io_stall_timeout: Option<Duration>,
+}
+
+impl WatchdogConfig {
+    /// 检查间隔：最短时限的1/10，限制在10ms到1s之间。
+    fn poll_interval(&self) -> Option<Duration> {
+        let shortest = match (self.timeout, self.io_stall_timeout) {
+            (Some(a), Some(b)) => a.min(b),
+            (Some(a), None) | (None, Some(a)) => a,
+            (None, None) => return None,
+        };
+        Some((shortest / 10).clamp(Duration::from_millis(10), Duration::from_secs(1)))
+    }
+}
+
+/// 运行中的看门狗，drop时停止。
+///
+/// # Examples
+///
+/// 读取持续前进时不触发；之后读取阻塞，[`ReadProgress`]停止前进`stall`后触发`IoStall`：
+///
+/// ```
+/// use std::io::Read;
+/// use std::sync::mpsc;
+/// use std::time::{Duration, Instant};
+/// use bamqc_core::{CancelReason, Watchdog, WatchdogConfig};
+/// use bamqc_io::ReadProgress;
+///
+/// static PROGRESS: ReadProgress = ReadProgress::new();
+///
+/// /// 前`chunks`次读取每次间隔`delay`返回16字节，并像读取循环一样更新PROGRESS；
+/// /// 之后永远阻塞，模拟挂死的网络文件系统。
+/// struct Stalling {
+///     chunks: u64,
+///     delay: Duration,
+///     bytes: u64,
+/// }
+///
+/// impl Read for Stalling {
+///     fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+///         if self.chunks == 0 {
+///             loop {
+///                 std::thread::park();
+///             }
+///         }
+///         std::thread::sleep(self.delay);
+///         self.chunks -= 1;
+///         self.bytes += 16;
+///         PROGRESS.update(self.bytes / 16, self.bytes);
+///         Ok(16.min(buf.len()))
+///     }
+/// }
+///
+/// let stall = Duration::from_millis(200);
+/// let config = WatchdogConfig { timeout: None, io_stall_timeout: Some(stall) };
+/// let (tx, rx) = mpsc::channel();
+/// let start = Instant::now();
+/// let _watchdog = Watchdog::spawn(config, &PROGRESS, move |reason| tx.send(reason).unwrap());
+/// // 读取前进600ms（每50ms一次，远超stall），之后阻塞
+/// let mut reader = Stalling { chunks: 12, delay: Duration::from_millis(50), bytes: 0 };
+/// std::thread::spawn(move || std::io::copy(&mut reader, &mut std::io::sink()));
+///
+/// let reason = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+/// assert_eq!(reason, CancelReason::IoStall(stall));
+/// assert_eq!(PROGRESS.snapshot(), (12, 192));
+/// // 前进期间没有触发：触发时间不早于最后一次前进之后的stall
+/// let active = Duration::from_millis(600);
+/// let elapsed = start.elapsed();
+/// assert!(elapsed >= active + stall && elapsed < active + stall * 3, "{:?}", elapsed);
+/// ```
+#[derive(Debug)]
+pub struct Watchdog {
+    _stop: Sender<()>,
+}
+
+impl Watchdog {
+    /// 启动看门狗线程；没有配置任何时限时返回None。
+    ///
+    /// `on_trip`最多调用一次，在看门狗线程中执行。
+    pub fn spawn<F>(config: WatchdogConfig, progress: &'static ReadProgress, on_trip: F) -> Option<Self>
+    where
+        F: FnOnce(CancelReason) + Send + 'static,
+    {
+        let interval = config.poll_interval()?;
+        let (stop, stopped) = mpsc::channel::<()>();
+        debug!("看门狗已启动: {:?}，检查间隔 {:?}", config, interval);
+
+        thread::spawn(move || {
+            let start = Instant::now();
+            let mut last = progress.snapshot();
+            let mut last_change = start;
+            loop {
+                match stopped.recv_timeout(interval) {
+                    Err(RecvTimeoutError::Timeout) => {}
+                    _ => return,
+                }
+                let now = Instant::now();
+                let current = progress.snapshot();
+                if current != last {
+                    last = current;
+                    last_change = now;
+                }
+                let reason = match config {
+                    WatchdogConfig { timeout: Some(t), .. } if now - start >= t => CancelReason::Timeout(t),
+                    WatchdogConfig { io_stall_timeout: Some(t), .. } if now - last_change >= t => {
+                        CancelReason::IoStall(t)
+                    }
+                    _ => continue,
+                };
+                on_trip(reason);
+                return;
+            }
+        });
+
+        Some(Self { _stop: stop })
+    }
+}
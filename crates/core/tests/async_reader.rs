@@ -0,0 +1,69 @@
+//! 异步读取器产出的记录与同步读取器相同：两条路径得到相同的插入片段直方图
+#![cfg(feature = "async")]
+
+use std::future::Future;
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake};
+
+use bamqc_core::{classify_pair, generate_test_data, InsertSizeStats, PairFilter, SimulationParams};
+use bamqc_io::{AsyncBamReader, BamReader};
+use bamqc_io::test_support::temp_path;
+
+/// 最小的执行器：在当前线程上轮询，挂起时park，被唤醒时unpark
+struct Unpark(std::thread::Thread);
+
+impl Wake for Unpark {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+fn block_on<F: Future>(future: F) -> F::Output {
+    let waker = Arc::new(Unpark(std::thread::current())).into();
+    let mut cx = Context::from_waker(&waker);
+    let mut future = std::pin::pin!(future);
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => std::thread::park(),
+        }
+    }
+}
+
+#[test]
+fn async_stream_matches_sync_histograms() {
+    let bam = temp_path("async-reader", "bam");
+    let bam = bam.to_string_lossy().to_string();
+    let params = SimulationParams { pairs: 20_000, rf_fraction: 0.2, ..Default::default() };
+    generate_test_data(&bam, &params).unwrap();
+    let filter = PairFilter::default();
+
+    let mut sync_stats = InsertSizeStats::new();
+    let mut reader = BamReader::from_path(&bam).unwrap();
+    for record in reader.records() {
+        if let Some((orientation, size)) = classify_pair(&record.unwrap(), &filter) {
+            sync_stats.add_insert_size(orientation, size);
+        }
+    }
+
+    let (async_stats, records) = block_on(async {
+        let mut stats = InsertSizeStats::new();
+        let mut reader = AsyncBamReader::from_path(&bam).await.unwrap();
+        let mut stream = reader.records();
+        let mut records = 0;
+        while let Some(record) = stream.next().await {
+            records += 1;
+            if let Some((orientation, size)) = classify_pair(&record.unwrap(), &filter) {
+                stats.add_insert_size(orientation, size);
+            }
+        }
+        (stats, records)
+    });
+
+    assert_eq!(records, 40_000);
+    assert!(!sync_stats.histograms.is_empty());
+    assert_eq!(async_stats.histograms, sync_stats.histograms);
+    assert_eq!(async_stats.total_left_records, sync_stats.total_left_records);
+    std::fs::remove_file(&bam).unwrap();
+    std::fs::remove_file(format!("{}.bai", bam)).unwrap();
+}
@@ -0,0 +1,71 @@
+//! top-k计数的误差上界：已知每个条形码的真实计数，报告的计数不高于真实计数，
+//! 且与真实计数之差不超过`error_bound()`；不同条形码数不超过容量的两倍时计数精确。
+#![cfg(feature = "approx")]
+
+use std::collections::HashMap;
+
+use bamqc_core::TopKCounter;
+
+/// 按`stream`顺序插入，检查每个报告条目满足 计数 ≤ 真实计数 ≤ 计数 + 误差上界
+fn check_bound(capacity: usize, stream: &[String]) -> TopKCounter {
+    let mut counter = TopKCounter::new(capacity);
+    let mut truth: HashMap<&str, u64> = HashMap::new();
+    for item in stream {
+        counter.insert(item);
+        *truth.entry(item.as_str()).or_default() += 1;
+    }
+    let bound = counter.error_bound();
+    for (item, count) in counter.sorted() {
+        let exact = truth[item.as_str()];
+        assert!(count <= exact && exact <= count + bound, "{}: 计数{}，真实{}，误差上界{}", item, count, exact, bound);
+    }
+    counter
+}
+
+#[test]
+fn exact_while_distinct_items_fit() {
+    // 8个条形码，第i个出现i+1次；容量4时最多跟踪8个，不裁剪
+    let stream: Vec<String> = (0..8).flat_map(|i| (0..=i).map(move |_| format!("bc{}", i))).collect();
+    let counter = check_bound(4, &stream);
+    assert_eq!(counter.error_bound(), 0);
+    let top: Vec<(String, u64)> = (4..8).rev().map(|i| (format!("bc{}", i), i as u64 + 1)).collect();
+    assert_eq!(counter.sorted(), top);
+}
+
+#[test]
+fn heavy_hitters_within_bound_on_long_tail() {
+    // 10个高频条形码各出现100次，与2000个只出现一次的条形码交替
+    let mut stream = Vec::new();
+    for round in 0..100 {
+        for heavy in 0..10 {
+            stream.push(format!("heavy{}", heavy));
+        }
+        for tail in 0..20 {
+            stream.push(format!("tail{}", round * 20 + tail));
+        }
+    }
+    let counter = check_bound(16, &stream);
+    assert!(counter.error_bound() > 0);
+    let top: Vec<String> = counter.sorted().into_iter().take(10).map(|(item, _)| item).collect();
+    let mut expected: Vec<String> = (0..10).map(|i| format!("heavy{}", i)).collect();
+    let mut sorted_top = top.clone();
+    sorted_top.sort();
+    expected.sort();
+    assert_eq!(sorted_top, expected);
+}
+
+#[test]
+fn repeatedly_pruned_item_stays_within_bound() {
+    // 容量1：每插入3个不同的条形码裁剪一次。`late`在多次裁剪中各丢弃1次计数，
+    // 之后成为唯一的高频条形码，报告的计数比真实值少了所有被丢弃的部分
+    let mut stream: Vec<String> = vec!["a".into(), "a".into(), "a".into(), "a".into()];
+    for i in 0..5 {
+        stream.push("late".into());
+        stream.push(format!("x{}", i));
+    }
+    stream.extend(std::iter::repeat_n("late".to_string(), 20));
+    let counter = check_bound(1, &stream);
+    let (item, count) = counter.sorted().remove(0);
+    assert_eq!(item, "late");
+    assert!(count < 25);
+}
@@ -0,0 +1,58 @@
+//! 比对超出参考序列末端的记录：validate与quick-check都报告其数量，flag层面的统计照常计入。
+
+use bamqc_core::{compute_flag_validation, compute_flagstat, compute_quick_check};
+use bamqc_io::{write_bai, BamReaderOptions};
+use bamqc_io::test_support::{temp_dir, write_bam};
+
+/// 1000 bp的c1上三条记录，其中`past`从961开始50M，终点1010超出末端10 bp
+fn fixture(name: &str, index: bool) -> (std::path::PathBuf, String) {
+    let dir = temp_dir(&format!("bounds-{}", name));
+    let text = "@HD\tVN:1.6\tSO:coordinate\n@SQ\tSN:c1\tLN:1000\n@SQ\tSN:c2\tLN:1000\n\
+                inside\t0\tc1\t100\t60\t50M\t*\t0\t0\t*\t*\n\
+                at_end\t0\tc1\t951\t60\t50M\t*\t0\t0\t*\t*\n\
+                past\t0\tc1\t961\t60\t50M\t*\t0\t0\t*\t*\n\
+                other\t0\tc2\t951\t60\t50M\t*\t0\t0\t*\t*\n";
+    let bam = dir.join("past_end.bam");
+    write_bam(&bam, text);
+    if index {
+        write_bai(&bam).unwrap();
+    }
+    let path = bam.to_string_lossy().to_string();
+    (dir, path)
+}
+
+#[test]
+fn validate_reports_records_past_the_contig_end() {
+    let (dir, bam) = fixture("validate", false);
+    let report = compute_flag_validation(&bam, &BamReaderOptions::new()).unwrap();
+    assert_eq!((report.total_records, report.inconsistent_records, report.past_contig_end), (4, 0, 1));
+    assert!(!report.is_ok());
+    let text = report.to_string();
+    assert!(text.contains("\npast_contig_end: 1\n"), "{}", text);
+    assert!(text.ends_with("status: warning"), "{}", text);
+
+    // flag层面的统计仍计入越界记录
+    let stat = compute_flagstat(&bam, None, None).unwrap();
+    assert!(stat.tsv_row().starts_with("4\t4\t"), "{}", stat.tsv_row());
+    assert!(stat.to_string().contains("past_contig_end: 1"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn quick_check_reports_records_past_the_contig_end_through_the_index() {
+    let (dir, bam) = fixture("quick-check", true);
+    let report = compute_quick_check(&bam, &BamReaderOptions::new()).unwrap();
+    assert_eq!(report.past_contig_end, Some(1));
+    assert!(!report.is_ok());
+    let text = report.to_string();
+    assert!(text.contains("\npast_contig_end: 1\n"), "{}", text);
+    assert!(text.ends_with("status: warning"), "{}", text);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    // 没有索引时不扫描记录，也就不检查
+    let (dir, bam) = fixture("quick-check-no-index", false);
+    let report = compute_quick_check(&bam, &BamReaderOptions::new()).unwrap();
+    assert_eq!(report.past_contig_end, None);
+    assert!(report.to_string().contains("\npast_contig_end: unchecked\n"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
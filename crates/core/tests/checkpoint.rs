@@ -0,0 +1,95 @@
+//! 检查点与恢复：在任意记录处保存（虚拟偏移, 统计），由新的读取器定位后继续，
+//! 结果与一次遍历相同
+#![cfg(feature = "serde")]
+
+use bamqc_core::{classify_pair, generate_test_data, FlagStat, InsertSizeStats, PairFilter, SimulationParams};
+use bamqc_io::{BamError, BamReader, BamRecord};
+use bamqc_io::test_support::temp_path;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
+struct Checkpoint {
+    offset: u64,
+    flagstat: FlagStat,
+    insert_size: InsertSizeStats,
+}
+
+fn fixture(name: &str) -> String {
+    let bam = temp_path(&format!("checkpoint-{}", name), "bam");
+    let bam = bam.to_string_lossy().to_string();
+    let params = SimulationParams { pairs: 20_000, secondary_rate: 0.1, ..Default::default() };
+    generate_test_data(&bam, &params).unwrap();
+    bam
+}
+
+/// 从`reader`的当前位置读取最多`limit`条记录，累积到两个统计中
+fn accumulate(reader: &mut BamReader, flagstat: &mut FlagStat, insert_size: &mut InsertSizeStats, limit: u64) {
+    let mut record = BamRecord::default();
+    let mut read = 0;
+    while read < limit && reader.read_into(&mut record).unwrap() {
+        flagstat.update(&record);
+        if let Some((orientation, size)) = classify_pair(&record, &PairFilter::default()) {
+            insert_size.add_insert_size(orientation, size);
+        }
+        read += 1;
+    }
+}
+
+#[test]
+fn resumed_stats_equal_single_pass() {
+    let bam = fixture("resume");
+
+    let mut reader = BamReader::from_path(&bam).unwrap();
+    let (mut single_flagstat, mut single_insert_size) = (FlagStat::new(), InsertSizeStats::new());
+    accumulate(&mut reader, &mut single_flagstat, &mut single_insert_size, u64::MAX);
+
+    // 检查点不在BGZF块边界上，也不在读对之间
+    for split in [1, 12_345, 39_999] {
+        let mut reader = BamReader::from_path(&bam).unwrap();
+        let (mut flagstat, mut insert_size) = (FlagStat::new(), InsertSizeStats::new());
+        accumulate(&mut reader, &mut flagstat, &mut insert_size, split);
+        let saved = serde_json::to_string(&Checkpoint { offset: reader.tell().unwrap(), flagstat, insert_size }).unwrap();
+        drop(reader);
+
+        let mut checkpoint: Checkpoint = serde_json::from_str(&saved).unwrap();
+        let mut reader = BamReader::from_path(&bam).unwrap();
+        reader.seek(checkpoint.offset).unwrap();
+        accumulate(&mut reader, &mut checkpoint.flagstat, &mut checkpoint.insert_size, u64::MAX);
+
+        assert_eq!(checkpoint.flagstat.to_string(), single_flagstat.to_string(), "split at {}", split);
+        assert_eq!(checkpoint.insert_size.histograms, single_insert_size.histograms, "split at {}", split);
+        assert_eq!(checkpoint.insert_size.total_left_records, single_insert_size.total_left_records);
+        assert_eq!(
+            serde_json::to_string(&checkpoint.flagstat).unwrap(),
+            serde_json::to_string(&single_flagstat).unwrap()
+        );
+    }
+    std::fs::remove_file(&bam).unwrap();
+}
+
+#[test]
+fn seeking_off_a_record_boundary_is_rejected() {
+    let bam = fixture("boundary");
+    let mut reader = BamReader::from_path(&bam).unwrap();
+    let mut record = BamRecord::default();
+    for _ in 0..1000 {
+        reader.read_into(&mut record).unwrap();
+    }
+    let offset = reader.tell().unwrap();
+    reader.read_into(&mut record).unwrap();
+    let next_name = record.name().into_owned();
+
+    let mut resumed = BamReader::from_path(&bam).unwrap();
+    let compressed = offset >> 16;
+    for bad in [offset + 1, offset + 7, (compressed + 3) << 16, (compressed << 16) | 0xffff, 0] {
+        match resumed.seek(bad) {
+            Err(BamError::NotARecordBoundary { offset, .. }) => assert_eq!(offset, bad),
+            other => panic!("offset {:#x}: {:?}", bad, other),
+        }
+    }
+    // 失败的定位不改变读取位置，之后仍可定位到合法的偏移
+    resumed.seek(offset).unwrap();
+    resumed.read_into(&mut record).unwrap();
+    assert_eq!(record.name(), next_name);
+    std::fs::remove_file(&bam).unwrap();
+}
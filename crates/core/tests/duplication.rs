@@ -0,0 +1,77 @@
+//! 按文库合并读组：同一文库的两个读组合并统计，重复率与文库大小由合并后的计数计算，
+//! 不是各读组结果的平均；与每个读组单独成库（不合并）时的结果逐项对照。
+
+use bamqc_core::{compute_duplication, estimate_library_size, DuplicationMetrics};
+use bamqc_io::BamReaderOptions;
+use bamqc_io::test_support::{temp_path, write_bam};
+
+/// 各读组的(读对数, 重复读对数, 非配对读长数, 非配对重复数)
+const READ_GROUPS: [(&str, u32, u32, u32, u32); 3] = [("rg1", 10, 2, 4, 1), ("rg2", 30, 12, 0, 0), ("rg3", 20, 5, 2, 0)];
+
+/// rg1、rg2属于文库libA，rg3属于libB；`pooled`为false时每个读组各自是一个文库
+fn fixture(name: &str, pooled: bool) -> String {
+    let bam = temp_path(&format!("dup-pooling-{}", name), "bam");
+    let bam = bam.to_string_lossy().to_string();
+    let mut text = String::from("@HD\tVN:1.6\tSO:unsorted\n@SQ\tSN:c1\tLN:100000\n");
+    for (rg, library) in [("rg1", "libA"), ("rg2", "libA"), ("rg3", "libB")] {
+        let library = if pooled { library.to_string() } else { format!("lib_{}", rg) };
+        text.push_str(&format!("@RG\tID:{rg}\tSM:S1\tLB:{library}\n"));
+    }
+    let seq = "A".repeat(50);
+    let qual = "I".repeat(50);
+    let mut start = 1000;
+    for (rg, pairs, pair_dups, fragments, fragment_dups) in READ_GROUPS {
+        for i in 0..pairs {
+            let dup = if i < pair_dups { 1024 } else { 0 };
+            let (first, second) = (99 + dup, 147 + dup);
+            text.push_str(&format!("{rg}p{i}\t{first}\tc1\t{start}\t60\t50M\t=\t{}\t300\t{seq}\t{qual}\tRG:Z:{rg}\n", start + 250));
+            text.push_str(&format!("{rg}p{i}\t{second}\tc1\t{}\t60\t50M\t=\t{start}\t-300\t{seq}\t{qual}\tRG:Z:{rg}\n", start + 250));
+            start += 10;
+        }
+        for i in 0..fragments {
+            let flag = if i < fragment_dups { 1024 } else { 0 };
+            text.push_str(&format!("{rg}f{i}\t{flag}\tc1\t{start}\t60\t50M\t*\t0\t0\t{seq}\t{qual}\tRG:Z:{rg}\n"));
+            start += 10;
+        }
+    }
+    write_bam(&bam, &text);
+    bam
+}
+
+fn counts(m: &DuplicationMetrics) -> (&str, u64, u64, u64, u64) {
+    (m.library.as_str(), m.read_pairs_examined, m.read_pair_duplicates, m.unpaired_reads_examined, m.unpaired_read_duplicates)
+}
+
+#[test]
+fn read_groups_of_one_library_are_pooled() {
+    let bam = fixture("unpooled", false);
+    let unpooled = compute_duplication(&bam, &BamReaderOptions::new(), true).unwrap();
+    std::fs::remove_file(&bam).unwrap();
+    let bam = fixture("pooled", true);
+    let pooled = compute_duplication(&bam, &BamReaderOptions::new(), true).unwrap();
+    std::fs::remove_file(&bam).unwrap();
+
+    let unpooled_counts: Vec<_> = unpooled.libraries.iter().map(counts).collect();
+    assert_eq!(unpooled_counts, [("lib_rg1", 10, 2, 4, 1), ("lib_rg2", 30, 12, 0, 0), ("lib_rg3", 20, 5, 2, 0)]);
+    // libA的各项计数是rg1与rg2之和，libB与rg3相同
+    let pooled_counts: Vec<_> = pooled.libraries.iter().map(counts).collect();
+    assert_eq!(pooled_counts, [("libA", 40, 14, 4, 1), ("libB", 20, 5, 2, 0)]);
+
+    let (rg1, rg2, rg3) = (&unpooled.libraries[0], &unpooled.libraries[1], &unpooled.libraries[2]);
+    let (lib_a, lib_b) = (&pooled.libraries[0], &pooled.libraries[1]);
+    // 合并后的重复率：(1 + 2×14) / (4 + 2×40)，不是rg1（5/24）与rg2（24/60）的平均
+    assert_eq!(lib_a.percent_duplication(), 29.0 / 84.0);
+    assert_eq!((rg1.percent_duplication(), rg2.percent_duplication()), (5.0 / 24.0, 0.4));
+    assert!(lib_a.percent_duplication() != (rg1.percent_duplication() + rg2.percent_duplication()) / 2.0);
+    assert_eq!(lib_b.percent_duplication(), rg3.percent_duplication());
+
+    // 文库大小由合并后的读对数估计，不是各读组估计值之和
+    assert_eq!(lib_a.estimated_library_size(), estimate_library_size(40, 26));
+    let summed = rg1.estimated_library_size().unwrap() + rg2.estimated_library_size().unwrap();
+    assert!(lib_a.estimated_library_size().unwrap() != summed);
+    assert_eq!(lib_b.estimated_library_size(), rg3.estimated_library_size());
+
+    let text = pooled.to_string();
+    assert!(text.contains("\nlibA\t4\t40\t0\t0\t1\t14\t"), "{}", text);
+    assert!(text.contains("\nlibB\t2\t20\t0\t0\t0\t5\t"), "{}", text);
+}
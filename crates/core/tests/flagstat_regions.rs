@@ -0,0 +1,196 @@
+//! 区域flagstat：单个区域的计数与按FLAG过滤、按坐标重叠逐条计数的结果
+//! （即`samtools view -c [-f/-F FLAG] in.bam REGION`）相同；环境中有samtools时
+//! 同时与samtools的输出核对。分区域统计中与两个区域重叠的记录在两行中各计一次，
+//! 在TOTAL中只计一次。
+
+use std::process::Command;
+
+use bamqc_core::{compute_flagstat, generate_test_data, SimulationParams};
+use bamqc_io::{BamReader, GenomicRegion};
+#[cfg(feature = "intervals")]
+use bamqc_io::BamReaderOptions;
+use bamqc_io::test_support::temp_path;
+
+/// `samtools view -c`的过滤条件，顺序与`FlagStat::tsv_row`的前7列相同
+const FILTERS: [(&str, u16, u16); 7] = [
+    ("total", 0, 0),
+    ("primary", 0, 0x900),
+    ("secondary", 0x100, 0),
+    ("supplementary", 0x800, 0),
+    ("duplicate", 0x400, 0),
+    ("mapped", 0, 0x4),
+    ("primary_mapped", 0, 0x904),
+];
+
+fn simulated(name: &str) -> String {
+    let bam = temp_path(&format!("flagstat-region-{}", name), "bam");
+    let bam = bam.to_string_lossy().to_string();
+    let params = SimulationParams { pairs: 20_000, unmapped_fraction: 0.05, secondary_rate: 0.3, ..Default::default() };
+    generate_test_data(&bam, &params).unwrap();
+    bam
+}
+
+/// 逐条扫描全部记录，计数与`regions`中任一区域重叠的记录（每条只计一次）
+fn reference_counts(bam: &str, regions: &[GenomicRegion]) -> Vec<u64> {
+    let mut reader = BamReader::from_path(bam).unwrap();
+    let tids: Vec<i32> = regions
+        .iter()
+        .map(|region| reader.references().position(|(name, _)| name == region.name).unwrap() as i32)
+        .collect();
+    let mut counts = vec![0; FILTERS.len()];
+    for result in reader.records() {
+        let record = result.unwrap();
+        if record.pos() < 0 {
+            continue;
+        }
+        let (start, end) = (record.pos() as usize + 1, record.reference_end() as usize);
+        let overlaps = regions.iter().zip(&tids).any(|(region, &tid)| {
+            record.tid() == tid && start <= region.end.unwrap_or(usize::MAX) && end >= region.start
+        });
+        if !overlaps {
+            continue;
+        }
+        for (count, (_, require, exclude)) in counts.iter_mut().zip(FILTERS) {
+            if record.flag() & require == require && record.flag() & exclude == 0 {
+                *count += 1;
+            }
+        }
+    }
+    counts
+}
+
+/// `samtools view -c`的计数；没有samtools时返回None
+fn samtools_counts(bam: &str, region: &GenomicRegion) -> Option<Vec<u64>> {
+    Command::new("samtools").arg("--version").output().ok().filter(|o| o.status.success())?;
+    let counts = FILTERS
+        .iter()
+        .map(|(_, require, exclude)| {
+            let output = Command::new("samtools")
+                .args(["view", "-c", "-f", &require.to_string(), "-F", &exclude.to_string(), bam, &region.to_string()])
+                .output()
+                .unwrap();
+            assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+            String::from_utf8(output.stdout).unwrap().trim().parse().unwrap()
+        })
+        .collect();
+    Some(counts)
+}
+
+fn row_counts(row: &str) -> Vec<u64> {
+    row.split('\t').take(FILTERS.len()).map(|field| field.parse().unwrap()).collect()
+}
+
+#[test]
+fn single_region_counts_match_view_count() {
+    let bam = simulated("single");
+    for region in ["sim1:1000001-1200000", "sim2:1-50000", "sim2:2999001-3000000", "sim1:4000000-4000000"] {
+        let region: GenomicRegion = region.parse().unwrap();
+        let stat = compute_flagstat(&bam, Some(&region), None).unwrap();
+        let counts = row_counts(&stat.tsv_row());
+        let expected = reference_counts(&bam, std::slice::from_ref(&region));
+        assert_eq!(counts, expected, "{}", region);
+        if let Some(samtools) = samtools_counts(&bam, &region) {
+            assert_eq!(counts, samtools, "samtools view -c {}", region);
+        }
+    }
+    // 较大的区域中primary、secondary与duplicate记录都应出现，避免比较的只是一串0
+    let region: GenomicRegion = "sim1:1000001-1200000".parse().unwrap();
+    let counts = reference_counts(&bam, &[region]);
+    assert!(counts[1] > 0 && counts[2] > 0 && counts[4] > 0, "{:?}", counts);
+    std::fs::remove_file(&bam).unwrap();
+    std::fs::remove_file(format!("{}.bai", bam)).unwrap();
+}
+
+#[cfg(feature = "intervals")]
+#[test]
+fn per_region_rows_count_overlaps_once_in_total() {
+    use bamqc_core::compute_flagstat_per_region;
+    use bamqc_io::RegionOverlap;
+
+    let bam = simulated("per-region");
+    let bed = format!("{}.regions.bed", bam);
+    // ex1与ex2重叠100 kb，跨越两者的记录在两行中各计一次；ex4紧接ex2
+    let lines = ["sim2\t100000\t200000\tex3", "sim1\t1000000\t1300000\tex1", "sim1\t1200000\t1500000\tex2", "sim1\t1500000\t1600000\tex4"];
+    std::fs::write(&bed, lines.join("\n") + "\n").unwrap();
+    let regions: Vec<GenomicRegion> = bamqc_io::read_bed(&bed).unwrap();
+
+    let stats = compute_flagstat_per_region(&bam, &BamReaderOptions::new(), &bed, RegionOverlap::All).unwrap();
+    let labels: Vec<&str> = stats.rows.iter().map(|(label, _)| label.as_str()).collect();
+    assert_eq!(labels, ["ex3", "ex1", "ex2", "ex4"]);
+    for ((label, stat), region) in stats.rows.iter().zip(&regions) {
+        let single = compute_flagstat(&bam, Some(region), None).unwrap();
+        assert_eq!(stat.tsv_row(), single.tsv_row(), "{}", label);
+        assert_eq!(row_counts(&stat.tsv_row()), reference_counts(&bam, std::slice::from_ref(region)), "{}", label);
+    }
+
+    // TOTAL中每条记录只计一次：等于与任一区域重叠的记录数，小于各行之和
+    let total = row_counts(&stats.total.tsv_row());
+    assert_eq!(total, reference_counts(&bam, &regions));
+    let row_sum: u64 = stats.rows.iter().map(|(_, stat)| row_counts(&stat.tsv_row())[0]).sum();
+    let shared = reference_counts(&bam, &["sim1:1200001-1300000".parse().unwrap()])[0];
+    // ex1与ex2的重叠部分中的记录，以及同时覆盖ex2末位与ex4首位的记录，各多计一次
+    let count = |region: &str| reference_counts(&bam, &[region.parse().unwrap()])[0];
+    let boundary = count("sim1:1500000-1500000") + count("sim1:1500001-1500001") - count("sim1:1500000-1500001");
+    assert!(shared > 0);
+    assert_eq!(row_sum - total[0], shared + boundary);
+    std::fs::remove_file(&bed).unwrap();
+    std::fs::remove_file(&bam).unwrap();
+    std::fs::remove_file(format!("{}.bai", bam)).unwrap();
+}
+
+#[cfg(feature = "intervals")]
+#[test]
+fn read_spanning_two_regions_appears_in_both_rows() {
+    use bamqc_core::compute_flagstat_per_region;
+    use bamqc_io::test_support::{temp_dir, write_bam};
+    use bamqc_io::{write_bai, RegionOverlap};
+
+    let dir = temp_dir("flagstat-span");
+    // span横跨两个不重叠的区域（101-200与301-400），left、right各只在一个区域中
+    let text = "@HD\tVN:1.6\tSO:coordinate\n@SQ\tSN:c1\tLN:10000\n\
+                left\t0\tc1\t120\t60\t50M\t*\t0\t0\t*\t*\n\
+                span\t1024\tc1\t150\t60\t300M\t*\t0\t0\t*\t*\n\
+                right\t0\tc1\t350\t60\t20M\t*\t0\t0\t*\t*\n\
+                far\t0\tc1\t5000\t60\t20M\t*\t0\t0\t*\t*\n";
+    let bam = dir.join("span.bam");
+    write_bam(&bam, text);
+    write_bai(&bam).unwrap();
+    let bed = dir.join("regions.bed");
+    std::fs::write(&bed, "c1\t100\t200\ta\nc1\t300\t400\tb\n").unwrap();
+
+    let stats = compute_flagstat_per_region(&bam.to_string_lossy(), &BamReaderOptions::new(), &bed.to_string_lossy(), RegionOverlap::All).unwrap();
+    let rows: Vec<(&str, Vec<u64>)> = stats.rows.iter().map(|(label, stat)| (label.as_str(), row_counts(&stat.tsv_row()))).collect();
+    // total primary secondary supplementary duplicate mapped primary_mapped
+    assert_eq!(rows, [("a", vec![2, 2, 0, 0, 1, 2, 2]), ("b", vec![2, 2, 0, 0, 1, 2, 2])]);
+    assert_eq!(row_counts(&stats.total.tsv_row()), [3, 3, 0, 0, 1, 3, 3]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn query_plan_sorts_by_header_and_merges_overlapping_or_adjacent_regions() {
+    use bamqc_io::plan_queries;
+    use noodles::sam;
+
+    let header: sam::Header = "@HD\tVN:1.6\n@SQ\tSN:c1\tLN:10000\n@SQ\tSN:c2\tLN:10000\n".parse().unwrap();
+    let regions: Vec<GenomicRegion> = ["c2:100-200", "c1:500-600", "c1:100-200", "c1:150-300", "c1:301-400", "c1:402-450", "c2:50"]
+        .iter()
+        .map(|r| r.parse().unwrap())
+        .collect();
+    let plan = plan_queries(&regions, &header).unwrap();
+    let summary: Vec<(String, Vec<usize>)> = plan.iter().map(|q| (q.region.to_string(), q.members.clone())).collect();
+    assert_eq!(
+        summary,
+        [
+            // 重叠（100-200与150-300）与相邻（301-400）的区域合并，402-450之间有空隙不合并
+            ("c1:100-400".to_string(), vec![2, 3, 4]),
+            ("c1:402-450".to_string(), vec![5]),
+            ("c1:500-600".to_string(), vec![1]),
+            // 没有终点的区域延伸到参考序列末端，吸收之后的区域
+            ("c2:50".to_string(), vec![6, 0]),
+        ]
+    );
+    assert!(plan.iter().all(|q| q.region.label.is_none()));
+
+    let unknown = plan_queries(&["chrX:1-10".parse().unwrap()], &header).unwrap_err();
+    assert!(unknown.to_string().contains("chrX"), "{}", unknown);
+}
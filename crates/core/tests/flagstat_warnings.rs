@@ -0,0 +1,36 @@
+//! flagstat的逐记录警告只汇总本次运行：同一进程中重复统计同一文件，
+//! 每次报告的次数相同，不会累加先前运行的警告
+//!
+//! 警告计数是进程级的，这个文件只有一个测试，避免与其它测试并行时互相干扰。
+#![cfg(feature = "serde")]
+
+use bamqc_core::compute_flagstat;
+use bamqc_io::BamWriter;
+use bamqc_io::test_support::temp_path;
+use noodles::sam;
+
+#[test]
+fn repeated_runs_report_their_own_warnings() {
+    let bam = temp_path("flagstat-warnings", "bam");
+    let bam = bam.to_string_lossy().to_string();
+    let mut text = String::from("@HD\tVN:1.6\tSO:unsorted\n@SQ\tSN:c1\tLN:100000\n");
+    for i in 0..5 {
+        text.push_str(&format!("r{i}\t0\tc1\t{}\t60\t8M\t*\t0\t0\tACGTACGT\tIIIIIIII\n", i * 10 + 1));
+    }
+    let mut sam_reader = sam::io::Reader::new(text.as_bytes());
+    let header = sam_reader.read_header().unwrap();
+    let mut writer = BamWriter::from_path(&bam, &header).unwrap();
+    for (i, record) in sam_reader.record_bufs(&header).enumerate() {
+        // 前3条读名含非ASCII字符
+        let name = if i < 3 { format!("读{i}") } else { format!("r{i}") };
+        writer.write_record_buf_with_name(&record.unwrap(), name.as_bytes()).unwrap();
+    }
+    writer.finish().unwrap();
+
+    for _ in 0..2 {
+        let json = serde_json::to_value(compute_flagstat(&bam, None, None).unwrap()).unwrap();
+        assert_eq!(json["non_ascii_names"], 3, "{}", json);
+        assert_eq!(json["warnings"], serde_json::json!([{ "class": "non_ascii_name", "count": 3 }]), "{}", json);
+    }
+    std::fs::remove_file(&bam).unwrap();
+}
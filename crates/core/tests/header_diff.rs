@@ -0,0 +1,106 @@
+//! `diff-header`：相同头部、@SQ顺序不同、参考序列长度不同、一方多出读组
+
+use bamqc_core::{diff_header_files, diff_headers, DictionaryStatus, FieldChange, HeaderDiff};
+use bamqc_io::{BamReaderOptions, BamWriter};
+use bamqc_io::test_support::temp_dir;
+use noodles::sam;
+
+const SQ: &str = "@SQ\tSN:chr1\tLN:1000\n@SQ\tSN:chr2\tLN:2000\n";
+const RG: &str = "@RG\tID:rg1\tSM:s1\tLB:lib1\n";
+const PG: &str = "@PG\tID:bwa\tPN:bwa\tVN:0.7.17\n";
+
+fn header(text: &str) -> sam::Header {
+    format!("@HD\tVN:1.6\n{}", text).parse().unwrap()
+}
+
+fn diff(left: &str, right: &str) -> HeaderDiff {
+    diff_headers(&header(left), &header(right))
+}
+
+#[test]
+fn identical_headers() {
+    let text = format!("{}{}{}", SQ, RG, PG);
+    let diff = diff(&text, &text);
+    assert_eq!(diff.dictionary_status, DictionaryStatus::Identical);
+    assert!(diff.is_compatible() && diff.is_identical());
+    assert!(diff.sequences.is_empty() && diff.read_groups.is_empty() && diff.programs.is_empty());
+    assert!(diff.samples_added.is_empty() && diff.samples_removed.is_empty());
+    assert_eq!(diff.to_string(), "dictionary: identical\nidentical: true");
+}
+
+#[test]
+fn reordered_sequences_are_compatible_but_not_identical() {
+    let reordered = "@SQ\tSN:chr2\tLN:2000\n@SQ\tSN:chr1\tLN:1000\n";
+    let diff = diff(SQ, reordered);
+    assert_eq!(diff.dictionary_status, DictionaryStatus::Reordered);
+    assert!(diff.is_compatible());
+    assert!(!diff.is_identical());
+    assert!(diff.sequences.is_empty());
+    assert!(diff.to_string().starts_with("dictionary: reordered\n"));
+}
+
+#[test]
+fn differing_contig_length_is_incompatible() {
+    let longer = "@SQ\tSN:chr1\tLN:1000\n@SQ\tSN:chr2\tLN:2500\n";
+    let diff = diff(SQ, longer);
+    assert_eq!(diff.dictionary_status, DictionaryStatus::Incompatible);
+    assert!(!diff.is_compatible() && !diff.is_identical());
+    assert_eq!(
+        diff.sequences.changed,
+        [FieldChange { id: "chr2".into(), field: "LN".into(), left: Some("2000".into()), right: Some("2500".into()) }]
+    );
+    assert!(diff.to_string().contains("@SQ\tchanged\tchr2\tLN\t2000\t2500\n"));
+
+    // 缺少参考序列同样不兼容
+    let missing = diff_headers(&header(SQ), &header("@SQ\tSN:chr1\tLN:1000\n"));
+    assert_eq!(missing.dictionary_status, DictionaryStatus::Incompatible);
+    assert_eq!(missing.sequences.removed, ["chr2"]);
+}
+
+#[test]
+fn extra_read_groups_are_reported_by_id_and_sample() {
+    let left = format!("{}{}", SQ, RG);
+    let right = format!("{}{}@RG\tID:rg2\tSM:s2\tLB:lib2\n@RG\tID:rg3\tSM:s1\tLB:lib1\n", SQ, RG);
+    let diff = diff(&left, &right);
+    assert_eq!(diff.dictionary_status, DictionaryStatus::Identical);
+    assert!(diff.is_compatible() && !diff.is_identical());
+    assert_eq!(diff.read_groups.added, ["rg2", "rg3"]);
+    assert!(diff.read_groups.removed.is_empty() && diff.read_groups.changed.is_empty());
+    // rg3与rg1的SM/LB相同，不算新样本
+    assert_eq!(diff.samples_added, ["s2/lib2"]);
+    assert!(diff.samples_removed.is_empty());
+    let text = diff.to_string();
+    assert!(text.contains("@RG\tadded\trg2\n@RG\tadded\trg3\nSM/LB\tadded\ts2/lib2\n"), "{}", text);
+    assert!(text.ends_with("identical: false"));
+
+    // 反方向比较：读组与样本都是删除
+    let reverse = diff_headers(&header(&right), &header(&left));
+    assert_eq!(reverse.read_groups.removed, ["rg2", "rg3"]);
+    assert_eq!(reverse.samples_removed, ["s2/lib2"]);
+}
+
+#[test]
+fn changed_sample_of_the_same_read_group_is_not_identical() {
+    let diff = diff(&format!("{}{}", SQ, RG), &format!("{}@RG\tID:rg1\tSM:s9\tLB:lib1\n", SQ));
+    assert_eq!(diff.read_groups.changed.len(), 1);
+    assert_eq!((diff.samples_added.as_slice(), diff.samples_removed.as_slice()), (&["s9/lib1".to_string()][..], &["s1/lib1".to_string()][..]));
+    assert!(!diff.is_identical());
+}
+
+#[test]
+fn diff_header_files_reads_both_headers() {
+    let dir = temp_dir("diff-header");
+    let write = |name: &str, text: &str| {
+        let path = dir.join(name);
+        BamWriter::from_path(&path, &header(text)).unwrap().finish().unwrap();
+        path.to_string_lossy().to_string()
+    };
+    let a = write("a.bam", &format!("{}{}{}", SQ, RG, PG));
+    let b = write("b.bam", &format!("{}{}{}@PG\tID:samtools\tPN:samtools\tPP:bwa\n", SQ, RG, PG));
+    let diff = diff_header_files(&a, &b, &BamReaderOptions::new()).unwrap();
+    assert_eq!(diff.dictionary_status, DictionaryStatus::Identical);
+    assert_eq!(diff.programs.added, ["samtools"]);
+    assert!(!diff.is_identical());
+    assert!(diff_header_files(&a, &a, &BamReaderOptions::new()).unwrap().is_identical());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
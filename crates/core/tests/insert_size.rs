@@ -0,0 +1,69 @@
+//! TLEN符号诊断：比对软件把读对两端的TLEN都写成负数时，过滤报告给出失衡的正负计数，
+//! 没有任何左端记录时报告随NoValidReads错误返回
+
+use bamqc_core::{compute_insert_size_with_report, FilterReport, InsertSizeError, InsertSizeOptions};
+use bamqc_io::test_support::{temp_path, write_bam};
+
+/// `normal`个TLEN符号正常的FR读对，加上`negative_only`个两端TLEN都为负的读对
+fn fixture(name: &str, normal: u32, negative_only: u32) -> String {
+    let bam = temp_path(&format!("tlen-sign-{}", name), "bam");
+    let bam = bam.to_string_lossy().to_string();
+    let mut text = String::from("@HD\tVN:1.6\tSO:unsorted\n@SQ\tSN:c1\tLN:100000\n");
+    let seq = "A".repeat(50);
+    let qual = "I".repeat(50);
+    for i in 0..normal + negative_only {
+        let (start, mate_start) = (1000 + i * 10, 1250 + i * 10);
+        let left_tlen = if i < normal { 300 } else { -300 };
+        text.push_str(&format!("p{i}\t99\tc1\t{start}\t60\t50M\t=\t{mate_start}\t{left_tlen}\t{seq}\t{qual}\n"));
+        text.push_str(&format!("p{i}\t147\tc1\t{mate_start}\t60\t50M\t=\t{start}\t-300\t{seq}\t{qual}\n"));
+    }
+    write_bam(&bam, &text);
+    bam
+}
+
+#[test]
+fn negatives_outnumbering_positives_two_to_one_are_reported() {
+    // 100个正常读对（100正、100负）加50个两端都为负的读对（100负）
+    let bam = fixture("two-to-one", 100, 50);
+    let options = InsertSizeOptions { min_pct: 0.0, ..Default::default() };
+    let (median, report) = compute_insert_size_with_report(&bam, &options, None).unwrap();
+
+    assert_eq!(median, 300);
+    assert_eq!((report.tlen_positive, report.tlen_negative, report.tlen_zero), (100, 200, 0));
+    assert_eq!(report.tlen_ratio(), Some(0.5));
+    assert!((report.tlen_imbalance() - 1.0 / 3.0).abs() < 1e-12);
+    assert!(report.tlen_imbalanced() && report.tlen_imbalance() > FilterReport::TLEN_IMBALANCE_WARN);
+    assert_eq!(report.counted_records, 100);
+    assert_eq!(report.breakdown.non_positive_tlen, 200);
+    let text = report.to_string();
+    assert!(text.contains("tlen_positive: 100") && text.contains("tlen_negative: 200"), "{}", text);
+    std::fs::remove_file(&bam).unwrap();
+}
+
+#[test]
+fn all_negative_tlen_returns_the_report_with_the_error() {
+    let bam = fixture("all-negative", 0, 40);
+    let options = InsertSizeOptions { min_pct: 0.0, ..Default::default() };
+    match compute_insert_size_with_report(&bam, &options, None) {
+        Err(InsertSizeError::NoValidReads { report: Some(report) }) => {
+            assert_eq!((report.tlen_positive, report.tlen_negative), (0, 80));
+            assert_eq!(report.tlen_ratio(), Some(0.0));
+            assert!(report.tlen_imbalanced());
+            assert_eq!((report.processed_records, report.counted_records), (80, 0));
+            assert!(!report.no_records);
+        }
+        other => panic!("{:?}", other.map(|(median, _)| median)),
+    }
+    std::fs::remove_file(&bam).unwrap();
+}
+
+#[test]
+fn balanced_signs_do_not_warn() {
+    let bam = fixture("balanced", 60, 0);
+    let options = InsertSizeOptions { min_pct: 0.0, ..Default::default() };
+    let (_, report) = compute_insert_size_with_report(&bam, &options, None).unwrap();
+    assert_eq!((report.tlen_positive, report.tlen_negative), (60, 60));
+    assert_eq!(report.tlen_ratio(), Some(1.0));
+    assert!(!report.tlen_imbalanced());
+    std::fs::remove_file(&bam).unwrap();
+}
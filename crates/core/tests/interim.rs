@@ -0,0 +1,57 @@
+//! `--emit-interim records=N`：插入片段计算过程中interim文件被多次替换，
+//! 每个快照的已处理记录数单调递增，运行结束后文件被删除。
+#![cfg(feature = "serde")]
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use bamqc_core::{
+    compute_insert_size_with_progress, generate_test_data, InsertSizeOptions, InterimEmitter, InterimSpec,
+    ProgressSink, SimulationParams, PROGRESS_CHECK_RECORDS,
+};
+use bamqc_io::test_support::temp_dir;
+
+/// 每次进度事件时读取interim文件，记下其中的快照
+///
+/// interim的触发检查在进度检查之前，记录数取[`PROGRESS_CHECK_RECORDS`]的倍数时
+/// 每个快照写出后都能在同一条记录的进度事件中读到。
+struct SnapshotWatcher {
+    file: PathBuf,
+    snapshots: Vec<serde_json::Value>,
+}
+
+impl ProgressSink for SnapshotWatcher {
+    fn interval(&self) -> Duration {
+        Duration::ZERO
+    }
+
+    fn on_progress(&mut self, _records: u64, _bytes: u64) {
+        if let Ok(content) = std::fs::read(&self.file) {
+            self.snapshots.push(serde_json::from_slice(&content).unwrap());
+        }
+    }
+}
+
+#[test]
+fn snapshots_are_replaced_with_increasing_record_counts() {
+    let dir = temp_dir("interim");
+    let bam = dir.join("sim.bam").to_string_lossy().to_string();
+    generate_test_data(&bam, &SimulationParams { pairs: 10_000, ..Default::default() }).unwrap();
+
+    let file = dir.join("interim.json");
+    let spec = InterimSpec { every: None, records: Some(2 * PROGRESS_CHECK_RECORDS), file: file.clone() };
+    let mut watcher = SnapshotWatcher { file: file.clone(), snapshots: Vec::new() };
+    let options = InsertSizeOptions::default();
+    compute_insert_size_with_progress(&bam, &options, Some(InterimEmitter::new(spec)), &mut watcher).unwrap();
+
+    // 20000条记录，每8192条一个快照；同一快照可能被读到两次
+    let mut counts: Vec<u64> = watcher.snapshots.iter().map(|s| s["records_processed"].as_u64().unwrap()).collect();
+    counts.dedup();
+    assert_eq!(counts, [8192, 16384]);
+    for snapshot in &watcher.snapshots {
+        assert_eq!(snapshot["partial"], true);
+        assert!(snapshot["metrics"].is_object(), "{}", snapshot);
+    }
+    assert!(!file.exists());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,62 @@
+//! `--report-largest`：离群的大插入片段以乱序出现在文件中，过滤报告中的前N个读对
+//! 仍是TLEN最大的N个，并按TLEN降序排列。
+
+use bamqc_core::{compute_insert_size_with_report, InsertSizeOptions};
+use bamqc_io::test_support::{temp_path, write_bam};
+
+/// 离群读对的TLEN：2000、3000……13000
+const OUTLIERS: u32 = 12;
+
+/// 200个TLEN为300的FR读对，离群读对按`i * 7 % 12`的顺序穿插其间
+fn fixture(name: &str) -> String {
+    let bam = temp_path(&format!("largest-{}", name), "bam");
+    let bam = bam.to_string_lossy().to_string();
+    let mut text = String::from("@HD\tVN:1.6\tSO:unsorted\n@SQ\tSN:c1\tLN:100000\n");
+    let seq = "A".repeat(50);
+    let qual = "I".repeat(50);
+    let mut pair = |name: String, start: u32, tlen: u32| {
+        let mate_start = start + tlen - 50;
+        text.push_str(&format!("{name}\t99\tc1\t{start}\t60\t50M\t=\t{mate_start}\t{tlen}\t{seq}\t{qual}\n"));
+        text.push_str(&format!("{name}\t147\tc1\t{mate_start}\t60\t50M\t=\t{start}\t-{tlen}\t{seq}\t{qual}\n"));
+    };
+    for i in 0..200 {
+        pair(format!("n{i}"), 1000 + i * 10, 300);
+        if i % 16 == 5 && i / 16 < OUTLIERS {
+            let k = i / 16 * 7 % OUTLIERS;
+            pair(format!("big{k}"), 5000 + i * 10, 2000 + k * 1000);
+        }
+    }
+    write_bam(&bam, &text);
+    bam
+}
+
+#[test]
+fn top_five_outliers_are_reported_in_descending_order() {
+    let bam = fixture("top5");
+    let options = InsertSizeOptions { min_pct: 0.0, report_largest: 5, ..Default::default() };
+    let (median, report) = compute_insert_size_with_report(&bam, &options, None).unwrap();
+    assert_eq!(median, 300);
+    assert_eq!(report.counted_records, 200 + OUTLIERS as u64);
+
+    let largest: Vec<(&str, i64)> = report.largest_pairs.iter().map(|p| (p.name.as_str(), p.tlen)).collect();
+    assert_eq!(largest, [("big11", 13000), ("big10", 12000), ("big9", 11000), ("big8", 10000), ("big7", 9000)]);
+    // 左端记录的位置随读对一起保留
+    assert!(report.largest_pairs.iter().all(|p| p.tid == 0 && p.pos >= 4999));
+    let text = report.to_string();
+    let lines: Vec<&str> = text.lines().filter(|line| line.starts_with("largest_pair: ")).collect();
+    assert_eq!(lines.len(), 5);
+    assert!(lines[0].starts_with("largest_pair: big11\t0\t") && lines[0].ends_with("\t13000"), "{}", lines[0]);
+    std::fs::remove_file(&bam).unwrap();
+}
+
+#[test]
+fn fewer_pairs_than_requested_are_all_reported() {
+    let bam = fixture("all");
+    let options = InsertSizeOptions { min_pct: 0.0, report_largest: 500, ..Default::default() };
+    let (_, report) = compute_insert_size_with_report(&bam, &options, None).unwrap();
+    assert_eq!(report.largest_pairs.len(), 200 + OUTLIERS as usize);
+    assert!(report.largest_pairs.windows(2).all(|w| w[0].tlen >= w[1].tlen));
+    assert_eq!(report.largest_pairs[OUTLIERS as usize - 1].tlen, 2000);
+    assert_eq!(report.largest_pairs[OUTLIERS as usize].tlen, 300);
+    std::fs::remove_file(&bam).unwrap();
+}
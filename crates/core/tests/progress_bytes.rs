@@ -0,0 +1,42 @@
+//! 进度事件的字节数来自正在迭代的读取器：多个输入时从第一个输入起累计，
+//! 切换输入时不会归零，读完时等于`on_start`给出的字节数之和
+
+use std::time::Duration;
+
+use bamqc_core::{compute_insert_size_multi, generate_test_data, ChannelProgressSink, InsertSizeOptions, ProgressEvent, SimulationParams};
+use bamqc_io::test_support::temp_dir;
+
+#[test]
+fn bytes_accumulate_across_inputs() {
+    let dir = temp_dir("core-progress-bytes");
+    let lanes: Vec<String> = (1..=2)
+        .map(|lane| {
+            let bam = dir.join(format!("L{}.bam", lane)).to_string_lossy().to_string();
+            generate_test_data(&bam, &SimulationParams { pairs: 5000, seed: lane, ..Default::default() }).unwrap();
+            bam
+        })
+        .collect();
+    let sizes: Vec<u64> = lanes.iter().map(|bam| std::fs::metadata(bam).unwrap().len()).collect();
+    let paths: Vec<&str> = lanes.iter().map(String::as_str).collect();
+
+    let (mut sink, events) = ChannelProgressSink::with_interval(Duration::ZERO);
+    compute_insert_size_multi(&paths, &InsertSizeOptions::default(), None, &mut sink).unwrap();
+    drop(sink);
+    let events: Vec<ProgressEvent> = events.into_iter().collect();
+    assert_eq!(events[0], ProgressEvent::Start { total_hint: Some(sizes[0] + sizes[1]) });
+    let progress: Vec<(u64, u64)> = events
+        .iter()
+        .filter_map(|e| match e {
+            ProgressEvent::Progress { records, bytes } => Some((*records, *bytes)),
+            _ => None,
+        })
+        .collect();
+    // 每个输入10000条记录：第4096、8192条在第一个输入中，之后在第二个输入中
+    let records: Vec<u64> = progress.iter().map(|(records, _)| *records).collect();
+    assert_eq!(records, [4096, 8192, 12288, 16384, 20000]);
+    assert!(progress.windows(2).all(|pair| pair[0].1 < pair[1].1), "{:?}", progress);
+    assert!(progress[..2].iter().all(|&(_, bytes)| bytes > 0 && bytes <= sizes[0]), "{:?}", progress);
+    assert!(progress[2..].iter().all(|&(_, bytes)| bytes > sizes[0]), "{:?}", progress);
+    assert_eq!(progress.last().unwrap().1, sizes[0] + sizes[1]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
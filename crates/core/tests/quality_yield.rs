@@ -0,0 +1,58 @@
+//! 按OQ:Z统计碱基质量产出：BQSR前后质量不同的记录在两种来源下给出不同的Q30；
+//! OQ长度与SEQ不一致或含非法字符时计入oq_invalid并改用QUAL，不会panic。
+
+use bamqc_core::{compute_quality_yield, QualitySource};
+use bamqc_io::BamReaderOptions;
+use bamqc_io::test_support::{temp_path, write_bam};
+
+fn fixture(name: &str, records: &[&str]) -> String {
+    let bam = temp_path(&format!("oq-{}", name), "bam");
+    let bam = bam.to_string_lossy().to_string();
+    let mut text = String::from("@HD\tVN:1.6\tSO:unsorted\n@SQ\tSN:c1\tLN:100000\n");
+    for record in records {
+        text.push_str(record);
+        text.push('\n');
+    }
+    write_bam(&bam, &text);
+    bam
+}
+
+/// QUAL全为Q40的50bp读长，`tags`为附加的辅助标签
+fn read(name: &str, tags: &str) -> String {
+    format!("{name}\t0\tc1\t1000\t60\t50M\t*\t0\t0\t{}\t{}{tags}", "A".repeat(50), "I".repeat(50))
+}
+
+#[test]
+fn recalibrated_qualities_differ_from_oq() {
+    // BQSR把Q10（`+`）提升到Q40：按QUAL全部达到Q30，按OQ一个也没有
+    let bam = fixture("recalibrated", &[&read("r1", &format!("\tOQ:Z:{}", "+".repeat(50)))]);
+    let reader = BamReaderOptions::new();
+    let qual = compute_quality_yield(&bam, &reader, QualitySource::Qual).unwrap();
+    let oq = compute_quality_yield(&bam, &reader, QualitySource::Oq).unwrap();
+    assert_eq!((qual.total_bases, qual.q30_bases, qual.q_sum), (50, 50, 2000));
+    assert_eq!((oq.total_bases, oq.q30_bases, oq.q_sum), (50, 0, 500));
+    assert_eq!((oq.oq_missing, oq.oq_invalid), (0, 0));
+    std::fs::remove_file(&bam).unwrap();
+}
+
+#[test]
+fn malformed_oq_is_counted_and_falls_back_to_qual() {
+    let bam = fixture(
+        "malformed",
+        &[
+            // OQ比SEQ短
+            &read("short", &format!("\tOQ:Z:{}", "+".repeat(10))),
+            // OQ比SEQ长
+            &read("long", &format!("\tOQ:Z:{}", "+".repeat(60))),
+            // 空格低于Phred+33的范围
+            &read("invalid", &format!("\tOQ:Z:{}", " ".repeat(50))),
+            &read("missing", ""),
+        ],
+    );
+    let report = compute_quality_yield(&bam, &BamReaderOptions::new(), QualitySource::Oq).unwrap();
+    assert_eq!((report.oq_invalid, report.oq_missing), (3, 1), "{}", report);
+    // 全部改用QUAL统计
+    assert_eq!((report.total_reads, report.total_bases, report.q30_bases), (4, 200, 200), "{}", report);
+    assert!(report.to_string().contains("oq_invalid: 3"), "{}", report);
+    std::fs::remove_file(&bam).unwrap();
+}
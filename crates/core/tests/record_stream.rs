@@ -0,0 +1,127 @@
+//! 组合子串联（过滤、抽样、限量）送入FlagStat，与手写循环的结果核对
+
+use bamqc_core::{
+    classify_pair, generate_test_data, sample_keeps, CancellationToken, FlagStat, InsertSizeStats, PairFilter, RecordFilter, RecordStream,
+    SimulationParams,
+};
+use bamqc_io::bam::BamReader;
+use bamqc_io::test_support::temp_path;
+
+fn fixture(name: &str, pairs: u64) -> String {
+    let bam = temp_path(&format!("record-stream-{}", name), "bam");
+    let bam = bam.to_string_lossy().to_string();
+    generate_test_data(&bam, &SimulationParams { pairs, ..Default::default() }).unwrap();
+    bam
+}
+
+#[test]
+fn filter_sample_take_matches_manual_loop() {
+    let bam = fixture("compose", 3000);
+    let filter = RecordFilter { require_flags: 0x1, exclude_flags: 0x400, min_mapq: 20 };
+    let (rate, seed, limit) = (0.3, 42, 1000);
+
+    let mut reader = BamReader::from_path(&bam).unwrap();
+    let mut combined = FlagStat::new();
+    let token = CancellationToken::new();
+    for record in reader.records().until_cancelled(token).filtered(filter).sampled(rate, seed).take_records(limit) {
+        combined.update(&record.unwrap());
+    }
+
+    let mut reader = BamReader::from_path(&bam).unwrap();
+    let mut manual = FlagStat::new();
+    let mut taken = 0;
+    for result in reader.records() {
+        let record = result.unwrap();
+        if !filter.matches(&record) || !sample_keeps(record.qname(), rate, seed) {
+            continue;
+        }
+        if taken == limit {
+            break;
+        }
+        manual.update(&record);
+        taken += 1;
+    }
+
+    assert_eq!(taken, limit);
+    assert_eq!(combined.tsv_row(), manual.tsv_row());
+    assert_eq!(combined.to_string(), manual.to_string());
+    std::fs::remove_file(&bam).unwrap();
+}
+
+#[test]
+fn composition_order_does_not_change_the_sample() {
+    let bam = fixture("order", 1000);
+    let filter = RecordFilter { exclude_flags: 0x10, ..Default::default() };
+
+    let names = |filter_first: bool| -> Vec<String> {
+        let mut reader = BamReader::from_path(&bam).unwrap();
+        let records: Vec<_> = if filter_first {
+            reader.records().filtered(filter).sampled(0.5, 3).collect()
+        } else {
+            reader.records().sampled(0.5, 3).filtered(filter).collect()
+        };
+        records.into_iter().map(|r| r.unwrap().name().into_owned()).collect()
+    };
+    let names_filter_first = names(true);
+    assert!(!names_filter_first.is_empty());
+    assert_eq!(names_filter_first, names(false));
+    std::fs::remove_file(&bam).unwrap();
+}
+
+#[test]
+fn records_sampled_is_reproducible_and_keeps_pairs() {
+    let bam = fixture("downsample", 5000);
+    let stats = |seed| -> (InsertSizeStats, u64) {
+        let mut reader = BamReader::from_path(&bam).unwrap();
+        let mut stats = InsertSizeStats::new();
+        let mut halves = 0;
+        for record in reader.records_sampled(0.2, seed).unwrap() {
+            let record = record.unwrap();
+            if let Some((orientation, size)) = classify_pair(&record, &PairFilter::default()) {
+                stats.add_insert_size(orientation, size);
+                halves += 1;
+            }
+        }
+        (stats, halves)
+    };
+    let (first, halves) = stats(11);
+    let (again, _) = stats(11);
+    assert_eq!(first.histograms, again.histograms);
+    assert_eq!(first.total_left_records, again.total_left_records);
+    assert!((700..1300).contains(&halves), "{}", halves);
+    assert_ne!(stats(12).0.histograms, first.histograms);
+
+    // 与Sampled组合子的判定一致：抽中的读名两端都在
+    let mut reader = BamReader::from_path(&bam).unwrap();
+    let mut names: Vec<String> = reader.records_sampled(0.2, 11).unwrap().map(|r| r.unwrap().name().into_owned()).collect();
+    let mut reader = BamReader::from_path(&bam).unwrap();
+    let mut combinator: Vec<String> = reader.records().sampled(0.2, 11).map(|r| r.unwrap().name().into_owned()).collect();
+    names.sort();
+    combinator.sort();
+    assert_eq!(names, combinator);
+    assert!(names.chunks(2).all(|pair| pair[0] == pair[1]));
+    std::fs::remove_file(&bam).unwrap();
+}
+
+#[test]
+fn unmapped_records_match_full_scan() {
+    let bam = temp_path("record-stream-unmapped", "bam");
+    let bam = bam.to_string_lossy().to_string();
+    let summary = generate_test_data(&bam, &SimulationParams { pairs: 5000, unmapped_fraction: 0.05, ..Default::default() }).unwrap();
+
+    // 相当于samtools view -c -f 4
+    let mut reader = BamReader::from_path(&bam).unwrap();
+    let flagged = reader.records().filter(|r| r.as_ref().unwrap().is_unmapped()).count() as u64;
+    assert_eq!(flagged, summary.unmapped_pairs * 2);
+
+    // generate_test_data写了索引，只读取文件末尾
+    let mut reader = BamReader::from_path(&bam).unwrap();
+    let mut unmapped = reader.unmapped_records().unwrap();
+    assert_eq!(unmapped.by_ref().map(|r| r.unwrap()).filter(|r| r.is_unmapped()).count() as u64, flagged);
+    assert!(unmapped.records_read() < 5000 * 2, "{}", unmapped.records_read());
+
+    std::fs::remove_file(format!("{}.bai", bam)).unwrap();
+    let mut reader = BamReader::from_path(&bam).unwrap();
+    assert_eq!(reader.unmapped_records().unwrap().count() as u64, flagged);
+    std::fs::remove_file(&bam).unwrap();
+}
@@ -0,0 +1,98 @@
+//! 按样本分别统计：两个样本（SM）的BAM中每个样本的结果，与只含该样本读组和记录的
+//! 单样本BAM的结果逐项相同；清理后相同的样本名（`A/x`与`A_x`）得到不同的文件名后缀。
+
+use bamqc_core::{
+    compute_duplication, compute_duplication_per_sample, compute_insert_size_per_sample, compute_insert_size_with_report,
+    compute_quality_yield, compute_quality_yield_per_sample, sample_file_names, InsertSizeOptions, QualitySource,
+};
+use bamqc_io::BamReaderOptions;
+use bamqc_io::test_support::{temp_path, write_bam};
+
+/// 每个样本一个读组：(读组, 样本, 文库, 读对数, 重复读对数, TLEN, 质量字符)
+type Group = (&'static str, &'static str, &'static str, u32, u32, i32, char);
+
+const GROUPS: [Group; 2] = [("rgA", "A/x", "libA", 40, 6, 300, 'I'), ("rgB", "A_x", "libB", 25, 10, 450, '5')];
+
+fn fixture(name: &str, groups: &[Group]) -> String {
+    let bam = temp_path(&format!("samples-{}", name), "bam");
+    let bam = bam.to_string_lossy().to_string();
+    let mut text = String::from("@HD\tVN:1.6\tSO:unsorted\n@SQ\tSN:c1\tLN:100000\n");
+    for (rg, sample, library, ..) in groups {
+        text.push_str(&format!("@RG\tID:{rg}\tSM:{sample}\tLB:{library}\n"));
+    }
+    let seq = "A".repeat(50);
+    for &(rg, _, _, pairs, dups, tlen, qual) in groups {
+        let qual = qual.to_string().repeat(50);
+        for i in 0..pairs {
+            let dup = if i < dups { 1024 } else { 0 };
+            let (start, mate) = (1000 + i * 10, 1000 + i * 10 + tlen as u32 - 50);
+            text.push_str(&format!("{rg}p{i}\t{}\tc1\t{start}\t60\t50M\t=\t{mate}\t{tlen}\t{seq}\t{qual}\tRG:Z:{rg}\n", 99 + dup));
+            text.push_str(&format!("{rg}p{i}\t{}\tc1\t{mate}\t60\t50M\t=\t{start}\t-{tlen}\t{seq}\t{qual}\tRG:Z:{rg}\n", 147 + dup));
+        }
+    }
+    write_bam(&bam, &text);
+    bam
+}
+
+/// 两样本BAM与各样本单独的BAM
+fn fixtures(name: &str) -> (String, Vec<String>) {
+    let singles = GROUPS.iter().enumerate().map(|(i, group)| fixture(&format!("{name}-{i}"), &[*group])).collect();
+    (fixture(name, &GROUPS), singles)
+}
+
+fn cleanup(bam: String, singles: Vec<String>) {
+    for path in singles.into_iter().chain([bam]) {
+        std::fs::remove_file(path).unwrap();
+    }
+}
+
+#[test]
+fn insert_size_per_sample_matches_single_sample_bams() {
+    let (bam, singles) = fixtures("insert-size");
+    let options = InsertSizeOptions::default();
+    let results = compute_insert_size_per_sample(&bam, &options).unwrap();
+    let samples: Vec<&str> = results.iter().map(|(sample, _)| sample.as_str()).collect();
+    assert_eq!(samples, ["A/x", "A_x"]);
+    for ((sample, result), single) in results.into_iter().zip(&singles) {
+        let (median, mut report) = result.unwrap();
+        assert_eq!(report.sample.take().as_deref(), Some(sample.as_str()));
+        let (expected_median, expected) = compute_insert_size_with_report(single, &options, None).unwrap();
+        assert_eq!(median, expected_median, "{}", sample);
+        assert_eq!(report.to_string(), expected.to_string(), "{}", sample);
+    }
+    cleanup(bam, singles);
+}
+
+#[test]
+fn duplication_per_sample_matches_single_sample_bams() {
+    let (bam, singles) = fixtures("duplication");
+    let reader = BamReaderOptions::new();
+    let reports = compute_duplication_per_sample(&bam, &reader, false).unwrap();
+    assert_eq!(reports.len(), 2);
+    for ((mut report, single), (_, sample, ..)) in reports.into_iter().zip(&singles).zip(GROUPS) {
+        assert_eq!(report.sample.take().as_deref(), Some(sample));
+        let expected = compute_duplication(single, &reader, false).unwrap();
+        assert_eq!(report.to_string(), expected.to_string(), "{}", sample);
+    }
+    cleanup(bam, singles);
+}
+
+#[test]
+fn quality_yield_per_sample_matches_single_sample_bams() {
+    let (bam, singles) = fixtures("quality-yield");
+    let reader = BamReaderOptions::new();
+    let reports = compute_quality_yield_per_sample(&bam, &reader, QualitySource::Qual).unwrap();
+    assert_eq!(reports.len(), 2);
+    for ((mut report, single), (_, sample, ..)) in reports.into_iter().zip(&singles).zip(GROUPS) {
+        assert_eq!(report.sample.take().as_deref(), Some(sample));
+        let expected = compute_quality_yield(single, &reader, QualitySource::Qual).unwrap();
+        assert_eq!(report.to_string(), expected.to_string(), "{}", sample);
+    }
+    cleanup(bam, singles);
+}
+
+#[test]
+fn colliding_sample_names_get_distinct_suffixes() {
+    let samples: Vec<&str> = GROUPS.iter().map(|(_, sample, ..)| *sample).collect();
+    assert_eq!(sample_file_names(&samples), ["A_x", "A_x-2"]);
+}
@@ -0,0 +1,79 @@
+//! 模拟数据：bamqc在默认参数生成的数据上算出的插入片段中位数与真值一致；
+//! 插入片段参数超出最短参考序列（或TLEN的i32范围）时报错，不写出文件；
+//! 上限处的插入片段与很大的标准差仍生成在参考序列之内的读对。
+
+use bamqc_core::{
+    compute_flag_validation, compute_insert_size_with_report, generate_test_data, InsertSizeOptions, SimulationError, SimulationParams, MAX_SIMULATED_INSERT,
+    SIMULATED_CONTIGS,
+};
+use bamqc_io::{BamReader, BamReaderOptions};
+use bamqc_io::test_support::temp_path;
+
+#[test]
+fn insert_size_median_matches_the_ground_truth() {
+    let bam = temp_path("simulate-truth", "bam");
+    let bam = bam.to_string_lossy().to_string();
+    let params = SimulationParams { pairs: 20_000, ..Default::default() };
+    assert_eq!(params.insert_mean, 400.0);
+    let summary = generate_test_data(&bam, &params).unwrap();
+    assert!(summary.duplicate_pairs > 0 && summary.rf_pairs > 0);
+
+    // 默认排除duplicate，只取FR方向：与真值的统计口径相同
+    let (median, report) = compute_insert_size_with_report(&bam, &InsertSizeOptions::default(), None).unwrap();
+    assert!((median as f64 - params.insert_mean).abs() <= 2.0, "{}", median);
+    assert_eq!(Some(median as u32), summary.fr_median_insert_size);
+    assert_eq!(report.processed_records, summary.pairs * 2);
+    std::fs::remove_file(format!("{}.bai", bam)).unwrap();
+    std::fs::remove_file(&bam).unwrap();
+}
+
+#[test]
+fn out_of_range_parameters_are_rejected() {
+    let bam = temp_path("simulate-rejected", "bam");
+    let bam = bam.to_string_lossy().to_string();
+    assert_eq!(MAX_SIMULATED_INSERT, SIMULATED_CONTIGS[1].1 as u64);
+    let cases = [
+        ("insert_mean", SimulationParams { insert_mean: MAX_SIMULATED_INSERT as f64 + 1.0, ..Default::default() }),
+        ("insert_mean", SimulationParams { insert_mean: i32::MAX as f64 * 2.0, ..Default::default() }),
+        ("insert_mean", SimulationParams { insert_mean: -1.0, ..Default::default() }),
+        ("insert_mean", SimulationParams { insert_mean: f64::NAN, ..Default::default() }),
+        ("insert_sd", SimulationParams { insert_sd: f64::INFINITY, ..Default::default() }),
+        ("read_length", SimulationParams { read_length: MAX_SIMULATED_INSERT as u32 + 1, ..Default::default() }),
+    ];
+    for (field, params) in cases {
+        match generate_test_data(&bam, &params) {
+            Err(SimulationError::ParameterOutOfRange { name, max, .. }) => {
+                assert_eq!((name, max), (field, MAX_SIMULATED_INSERT));
+            }
+            other => panic!("{}: {:?}", field, other.map(|summary| summary.pairs)),
+        }
+        assert!(!std::path::Path::new(&bam).exists());
+    }
+}
+
+#[test]
+fn inserts_at_the_limit_stay_within_the_contigs() {
+    let bam = temp_path("simulate-limit", "bam");
+    let bam = bam.to_string_lossy().to_string();
+    let params = SimulationParams {
+        pairs: 200,
+        insert_mean: MAX_SIMULATED_INSERT as f64,
+        insert_sd: MAX_SIMULATED_INSERT as f64,
+        dup_rate: 0.0,
+        rf_fraction: 0.0,
+        ..Default::default()
+    };
+    let summary = generate_test_data(&bam, &params).unwrap();
+    assert!(summary.fr_median_insert_size.unwrap() as u64 <= MAX_SIMULATED_INSERT);
+
+    let mut reader = BamReader::from_path(&bam).unwrap();
+    for record in reader.records() {
+        let record = record.unwrap();
+        assert!(record.insert_size().unsigned_abs() <= MAX_SIMULATED_INSERT, "{}", record.insert_size());
+        assert!(record.insert_size().unsigned_abs() >= params.read_length as u64);
+    }
+    let report = compute_flag_validation(&bam, &BamReaderOptions::new()).unwrap();
+    assert_eq!((report.total_records, report.past_contig_end), (400, 0));
+    std::fs::remove_file(format!("{}.bai", bam)).unwrap();
+    std::fs::remove_file(&bam).unwrap();
+}
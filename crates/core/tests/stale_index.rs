@@ -0,0 +1,121 @@
+//! 过期的索引：BAM建立索引后被改动、换成头部不同的BAM的索引、被截断或被重写，
+//! quick-check都报告相应的索引问题，并且不再通过索引查询越界记录。
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use bamqc_core::compute_quick_check;
+use bamqc_io::{write_bai, BamReaderOptions, IndexProblem};
+use bamqc_io::test_support;
+
+const HEADER: &str = "@HD\tVN:1.6\tSO:coordinate\n@SQ\tSN:c1\tLN:100000\n@SQ\tSN:c2\tLN:100000\n";
+
+fn scratch(name: &str) -> PathBuf {
+    let dir = test_support::temp_dir(&format!("stale-index-{}", name));
+    dir
+}
+
+/// c1上`mapped`条已比对记录，其后一条没有坐标的未比对记录
+fn write_bam(path: &Path, header: &str, mapped: usize) {
+    let mut text = header.to_string();
+    for i in 0..mapped {
+        let seq: String = (0..100).map(|j| ['A', 'C', 'G', 'T'][(i * 7 + j * j) % 4]).collect();
+        text.push_str(&format!("r{i}\t0\tc1\t{}\t60\t100M\t*\t0\t0\t{seq}\t{}\n", i + 1, "I".repeat(100)));
+    }
+    text.push_str("unplaced\t4\t*\t0\t0\t*\t*\t0\t0\tACGT\tIIII\n");
+    test_support::write_bam(path, &text);
+}
+
+/// 把文件的修改时间设为当前时间之后若干秒
+fn touch(path: &Path, later: u64) {
+    let file = std::fs::File::options().append(true).open(path).unwrap();
+    file.set_modified(SystemTime::now() + Duration::from_secs(later)).unwrap();
+}
+
+fn index_problems(bam: &Path) -> Vec<IndexProblem> {
+    let report = compute_quick_check(&bam.to_string_lossy(), &BamReaderOptions::new()).unwrap();
+    assert!(report.index.is_some());
+    if !report.index_problems.is_empty() {
+        assert!(!report.is_ok());
+        assert_eq!(report.past_contig_end, None);
+        assert!(report.to_string().contains("\nindex_problem: "), "{}", report);
+    }
+    report.index_problems
+}
+
+#[test]
+fn a_fresh_index_has_no_problems() {
+    let dir = scratch("fresh");
+    let bam = dir.join("reads.bam");
+    write_bam(&bam, HEADER, 10);
+    write_bai(&bam).unwrap();
+    let report = compute_quick_check(&bam.to_string_lossy(), &BamReaderOptions::new()).unwrap();
+    assert!(report.index_problems.is_empty() && report.is_ok(), "{}", report);
+    assert_eq!(report.past_contig_end, Some(0));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn touching_the_bam_makes_the_index_older() {
+    let dir = scratch("touched");
+    let bam = dir.join("reads.bam");
+    write_bam(&bam, HEADER, 10);
+    write_bai(&bam).unwrap();
+    touch(&bam, 10);
+    assert_eq!(index_problems(&bam), [IndexProblem::OlderThanBam]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn rewriting_the_bam_changes_the_record_counts() {
+    let dir = scratch("rewritten");
+    let bam = dir.join("reads.bam");
+    write_bam(&bam, HEADER, 10);
+    let bai = write_bai(&bam).unwrap();
+    // 重写后索引的修改时间仍然较新，只有记录计数能发现
+    write_bam(&bam, HEADER, 12);
+    touch(&bai, 10);
+    assert_eq!(
+        index_problems(&bam),
+        [IndexProblem::RecordCountMismatch { index_mapped: 10, index_unmapped: 1, bam_mapped: 12, bam_unmapped: 1 }]
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn an_index_from_a_bam_with_another_header() {
+    let dir = scratch("other-header");
+    let other = dir.join("other.bam");
+    write_bam(&other, &format!("{HEADER}@SQ\tSN:c3\tLN:100000\n"), 10);
+    let other_bai = write_bai(&other).unwrap();
+    let bam = dir.join("reads.bam");
+    write_bam(&bam, HEADER, 10);
+    std::fs::copy(&other_bai, dir.join("reads.bam.bai")).unwrap();
+    touch(&dir.join("reads.bam.bai"), 10);
+    assert_eq!(index_problems(&bam), [IndexProblem::ReferenceCountMismatch { index: 3, header: 2 }]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn truncating_the_bam_leaves_offsets_past_the_end() {
+    let dir = scratch("truncated");
+    let bam = dir.join("reads.bam");
+    // 足够多的记录，跨越多个BGZF块
+    write_bam(&bam, HEADER, 5000);
+    let bai = write_bai(&bam).unwrap();
+    let file_size = std::fs::metadata(&bam).unwrap().len() / 2;
+    std::fs::File::options().write(true).open(&bam).unwrap().set_len(file_size).unwrap();
+    touch(&bai, 10);
+
+    let report = compute_quick_check(&bam.to_string_lossy(), &BamReaderOptions::new()).unwrap();
+    assert!(!report.eof_marker);
+    match report.index_problems.as_slice() {
+        [IndexProblem::OffsetBeyondEof { offset, file_size: size }] => {
+            assert_eq!(*size, file_size);
+            assert!(*offset > file_size);
+        }
+        problems => panic!("{:?}", problems),
+    }
+    assert_eq!(report.past_contig_end, None);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,85 @@
+//! 逐靶区指标的碱基数只计M、=、X比对块：缺失（D）与跳过（N）不增加平均深度，
+//! 只靠N跨过靶区的读长不计入该靶区。文本与JSON输出逐字核对。
+#![cfg(feature = "intervals")]
+
+use bamqc_core::{compute_target_metrics, TargetReport};
+use bamqc_io::{write_bai, BamReaderOptions};
+use bamqc_io::test_support::{temp_dir, write_bam};
+
+/// 靶区`exon`为c1:101-200，`intron`为c1:1001-1100
+///
+/// | 读长 | CIGAR | 靶区内比对碱基 | MAPQ |
+/// |------|-------|----------------|------|
+/// | a | 101 50M | 101-150，50 | 60 |
+/// | b | 141 20M30D20M | 141-160与191-200，30 | 30 |
+/// | c | 151 10M1000N10M | 151-160，10（N跨过intron） | 0 |
+/// | d | 181 10=2X8= | 181-200，20 | 255 |
+fn report(name: &str) -> TargetReport {
+    let dir = temp_dir(&format!("target-{}", name));
+    let text = "@HD\tVN:1.6\tSO:coordinate\n@SQ\tSN:c1\tLN:10000\n\
+                a\t0\tc1\t101\t60\t50M\t*\t0\t0\t*\t*\n\
+                b\t0\tc1\t141\t30\t20M30D20M\t*\t0\t0\t*\t*\n\
+                c\t0\tc1\t151\t0\t10M1000N10M\t*\t0\t0\t*\t*\n\
+                d\t0\tc1\t181\t255\t10=2X8=\t*\t0\t0\t*\t*\n";
+    let bam = dir.join("panel.bam");
+    write_bam(&bam, text);
+    write_bai(&bam).unwrap();
+    let bed = dir.join("panel.bed");
+    std::fs::write(&bed, "c1\t100\t200\texon\nc1\t1000\t1100\tintron\n").unwrap();
+
+    let report = compute_target_metrics(&bam.to_string_lossy(), &BamReaderOptions::new(), &bed.to_string_lossy(), 2).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    report
+}
+
+#[test]
+fn deletions_and_skips_do_not_count_as_depth() {
+    let report = report("counts");
+    let exon = &report.targets[0];
+    // 按参考跨度计算会是50+60+50+20=180
+    assert_eq!((exon.reads, exon.bases), (4, 110));
+    assert_eq!(exon.mean_depth(), 1.1);
+    // d的MAPQ为255，不参与MAPQ统计：(50*60+30*30+10*0)/90
+    assert_eq!((exon.mapq_bases, exon.mapq_weighted_sum, exon.mapq0_reads), (90, 3900, 1));
+    assert_eq!(exon.mapq0_fraction(), 0.25);
+    // 深度最高处（151-160）为a、b、c三条
+    assert_eq!(exon.bases_20x, 0);
+
+    let intron = &report.targets[1];
+    assert_eq!((intron.reads, intron.bases, intron.mapq_bases), (0, 0, 0));
+    assert_eq!(intron.mean_mapq(), None);
+}
+
+#[test]
+fn text_output() {
+    let expected = "target\tlength\treads\tmean_depth\tmean_mapq\tmapq0_fraction\n\
+                    exon\t100\t4\t1.10\t43.3333\t0.2500\n\
+                    intron\t100\t0\t0.00\tNA\t0.0000\n\
+                    # worst_mapq_targets: exon\n\
+                    # worst_covered_targets: intron(c1:1001-1100),exon(c1:101-200)\n\
+                    # most_covered_targets: exon(c1:101-200),intron(c1:1001-1100)";
+    assert_eq!(report("text").to_string(), expected);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn json_output() {
+    let json = serde_json::to_value(report("json")).unwrap();
+    let expected = serde_json::json!({
+        "targets": [
+            {"name": "exon", "length": 100, "reads": 4, "bases": 110, "mapq_bases": 90, "mapq_weighted_sum": 3900, "mapq0_reads": 1, "bases_20x": 0},
+            {"name": "intron", "length": 100, "reads": 0, "bases": 0, "mapq_bases": 0, "mapq_weighted_sum": 0, "mapq0_reads": 0, "bases_20x": 0},
+        ],
+        "worst_mapq_targets": ["exon"],
+        "worst_covered_targets": [
+            {"target": "c1:1001-1100", "gene": "intron", "mean_depth": 0.0, "fraction_20x": 0.0},
+            {"target": "c1:101-200", "gene": "exon", "mean_depth": 1.1, "fraction_20x": 0.0},
+        ],
+        "most_covered_targets": [
+            {"target": "c1:101-200", "gene": "exon", "mean_depth": 1.1, "fraction_20x": 0.0},
+            {"target": "c1:1001-1100", "gene": "intron", "mean_depth": 0.0, "fraction_20x": 0.0},
+        ],
+        "past_contig_end": 0,
+    });
+    assert_eq!(json, expected);
+}
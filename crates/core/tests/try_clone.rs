@@ -0,0 +1,30 @@
+//! 两遍读取的插入片段统计（按读对排除duplicate）第二遍用`try_clone`重新打开输入，
+//! 每个输入的头部只解析一次
+//!
+//! 头部解析次数是进程级计数，这个文件只有一个测试，避免与其它测试并行时互相干扰。
+
+use bamqc_core::{compute_insert_size_multi, generate_test_data, InsertSizeOptions, PairDuplicatePolicy, SimulationParams};
+use bamqc_io::bam::header_parse_count;
+use bamqc_io::test_support::temp_dir;
+
+#[test]
+fn two_pass_multi_input_parses_each_header_once() {
+    let dir = temp_dir("core-try-clone");
+    let lanes: Vec<String> = (1..=2)
+        .map(|lane| {
+            let bam = dir.join(format!("L{}.bam", lane)).to_string_lossy().to_string();
+            let params = SimulationParams { pairs: 2000, seed: lane, dup_rate: 0.1, ..Default::default() };
+            generate_test_data(&bam, &params).unwrap();
+            bam
+        })
+        .collect();
+    let paths: Vec<&str> = lanes.iter().map(String::as_str).collect();
+
+    let options = InsertSizeOptions { pair_duplicate_policy: PairDuplicatePolicy::EitherMate, ..Default::default() };
+    let before = header_parse_count();
+    let (_, report) = compute_insert_size_multi(&paths, &options, None, &mut ()).unwrap();
+    assert_eq!(header_parse_count() - before, 2);
+    let filter = report.duplicate_name_filter.expect("EitherMate应先收集duplicate读名");
+    assert!(filter.duplicate_names > 0);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
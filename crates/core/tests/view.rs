@@ -0,0 +1,49 @@
+//! view（`BamRecord::to_sam_string`）：SAM文本写成BAM后再导出，逐字还原为原来的头部与记录行。
+//! 覆盖`*`字段（SEQ、QUAL、CIGAR、RNEXT）、各类数组标签、A/f/H标签以及未比对记录。
+
+use bamqc_core::{view_records, RecordFilter, ViewOptions};
+use bamqc_io::test_support::{temp_path, write_bam};
+
+const HEADER: &str = "@HD\tVN:1.6\n@SQ\tSN:c1\tLN:1000\n@SQ\tSN:c2\tLN:1000\n@RG\tID:lane1\n";
+
+const LINES: [&str; 8] = [
+    // 数组标签：B:c（有符号8位）、B:C（无符号8位）、B:i（有符号32位）、B:f
+    "arrays\t99\tc1\t100\t60\t4M1I3M2S\t=\t300\t210\tACGTACGTAC\tIIIIIIIIII\tXa:B:c,-128,0,127\tXb:B:C,0,255\tXi:B:i,-70000,5\tXf:B:f,1.5,-0.25",
+    // 单字符、浮点与十六进制标签
+    "scalars\t147\tc1\t300\t60\t10M\t=\t100\t-210\tACGTACGTAC\tIIIIIIIIII\tXA:A:Z\tXF:f:3.25\tXH:H:1AE301\tNM:i:2",
+    // 没有SEQ与QUAL
+    "no_seq\t0\tc1\t500\t30\t10M\t*\t0\t0\t*\t*",
+    // 有SEQ没有QUAL，没有CIGAR
+    "no_qual\t0\tc1\t600\t0\t*\t*\t0\t0\tACGT\t*",
+    // mate在另一条参考序列上
+    "other_mate\t97\tc1\t700\t60\t4M\tc2\t50\t0\tACGT\tIIII",
+    // 放在mate位置上的未比对记录与其mate
+    "placed\t73\tc2\t200\t60\t4M\t=\t200\t0\tACGT\tIIII",
+    "placed\t133\tc2\t200\t0\t*\t=\t200\t0\tTTGG\t####",
+    // 没有位置的未比对记录
+    "unplaced\t77\t*\t0\t0\t*\t*\t0\t0\tACGT\tIIII\tRG:Z:lane1",
+];
+
+fn fixture() -> String {
+    let bam = temp_path("view", "bam");
+    let text = format!("{}{}\n", HEADER, LINES.join("\n"));
+    write_bam(&bam, &text)
+}
+
+fn view(bam: &str, options: &ViewOptions) -> String {
+    let mut out = Vec::new();
+    view_records(bam, options, &mut out).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn sam_text_survives_a_bam_round_trip() {
+    let bam = fixture();
+    let options = ViewOptions { include_header: true, ..Default::default() };
+    assert_eq!(view(&bam, &options), format!("{}{}\n", HEADER, LINES.join("\n")));
+
+    // -f 4：只有两条未比对记录
+    let options = ViewOptions { filter: RecordFilter { require_flags: 0x4, ..Default::default() }, ..Default::default() };
+    assert_eq!(view(&bam, &options), format!("{}\n{}\n", LINES[6], LINES[7]));
+    std::fs::remove_file(&bam).unwrap();
+}
@@ -0,0 +1,33 @@
+//! 被抑制的警告的开销：`cargo bench -p bamqc-io --bench warnings`
+//!
+//! 与只做同样次数的原子累加相比，`WarningDeduplicator::warn`在第一次之后
+//! 不应有可观的额外开销（不做格式化）。
+
+use std::hint::black_box;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
+use bamqc_io::{WarningClass, WarningDeduplicator};
+
+const CALLS: u64 = 50_000_000;
+
+fn main() {
+    let baseline = AtomicU64::new(0);
+    let start = Instant::now();
+    for i in 0..CALLS {
+        baseline.fetch_add(black_box(i) & 1, Ordering::Relaxed);
+    }
+    let baseline_ns = start.elapsed().as_nanos() as f64 / CALLS as f64;
+
+    let warnings = WarningDeduplicator::new();
+    let start = Instant::now();
+    for i in 0..CALLS {
+        black_box(warnings.warn(WarningClass::PastContigEnd, || format!("read{}", black_box(i))));
+    }
+    let suppressed_ns = start.elapsed().as_nanos() as f64 / CALLS as f64;
+    assert_eq!(warnings.count(WarningClass::PastContigEnd), CALLS);
+
+    println!("atomic_add_ns: {:.2}", baseline_ns);
+    println!("suppressed_warn_ns: {:.2}", suppressed_ns);
+    println!("overhead_ns: {:.2}", suppressed_ns - baseline_ns);
+}
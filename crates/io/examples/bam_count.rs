@@ -0,0 +1,163 @@
+//! 按FLAG快速计数：`cargo run -p bamqc-io --example bam_count -- <input> [--threads N]`
+//!
+//! 只看每条记录的FLAG。`--threads`大于1且BAM有带元数据的BAI/CSI索引时，
+//! 各线程用[`BamReader::try_clone`]得到独立的读取器，按参考序列分别做区域查询，
+//! 没有坐标的未比对记录数取自索引；否则（CRAM、标准输入、无索引）顺序读一遍。
+//! 两种方式的输出相同。
+
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use bamqc_io::{BamError, BamIndex, BamReader, BamRecord, GenomicRegion};
+
+const USAGE: &str = "用法: bam_count <input> [--threads N] [--reference FASTA]";
+
+struct Args {
+    input: String,
+    threads: usize,
+    reference: Option<PathBuf>,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut input = None;
+    let mut threads = 1;
+    let mut reference = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--threads" => {
+                let value = args.next().ok_or("--threads需要一个线程数")?;
+                threads = value
+                    .parse()
+                    .ok()
+                    .filter(|&n| n > 0)
+                    .ok_or_else(|| format!("无效的线程数: {}", value))?;
+            }
+            "--reference" => reference = Some(PathBuf::from(args.next().ok_or("--reference需要FASTA路径")?)),
+            _ if input.is_none() => input = Some(arg),
+            _ => return Err(format!("多余的参数: {}", arg)),
+        }
+    }
+    Ok(Args { input: input.ok_or(USAGE)?, threads, reference })
+}
+
+/// 按FLAG的计数
+#[derive(Clone, Copy, Debug, Default)]
+struct Counts {
+    records: u64,
+    mapped: u64,
+    unmapped: u64,
+    duplicate: u64,
+    qc_fail: u64,
+    secondary: u64,
+    supplementary: u64,
+}
+
+impl Counts {
+    fn add(&mut self, record: &BamRecord) {
+        self.records += 1;
+        if record.is_unmapped() {
+            self.unmapped += 1;
+        } else {
+            self.mapped += 1;
+        }
+        self.duplicate += record.is_duplicate() as u64;
+        self.qc_fail += record.is_qc_fail() as u64;
+        self.secondary += record.is_secondary() as u64;
+        self.supplementary += record.is_supplementary() as u64;
+    }
+
+    fn merge(&mut self, other: &Counts) {
+        self.records += other.records;
+        self.mapped += other.mapped;
+        self.unmapped += other.unmapped;
+        self.duplicate += other.duplicate;
+        self.qc_fail += other.qc_fail;
+        self.secondary += other.secondary;
+        self.supplementary += other.supplementary;
+    }
+}
+
+fn count_sequential(reader: &mut BamReader) -> Result<Counts, BamError> {
+    let mut counts = Counts::default();
+    for result in reader.records() {
+        counts.add(&result?);
+    }
+    Ok(counts)
+}
+
+/// 按参考序列并行计数；索引缺少没有坐标的未比对记录数时返回None
+fn count_parallel(reader: &BamReader, threads: usize) -> Result<Option<Counts>, BamError> {
+    let Some(index) = BamIndex::locate(reader.path()).map(BamIndex::open).transpose()? else {
+        return Ok(None);
+    };
+    let Some(unplaced) = index.unplaced_unmapped else {
+        return Ok(None);
+    };
+
+    let names: Vec<String> = reader.references().map(|(name, _)| name.to_string()).collect();
+    let results: Vec<Result<Counts, BamError>> = std::thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|worker| {
+                let names = &names;
+                scope.spawn(move || {
+                    let mut reader = reader.try_clone()?;
+                    let mut counts = Counts::default();
+                    for name in names.iter().skip(worker).step_by(threads) {
+                        let region = GenomicRegion { name: name.clone(), start: 1, end: None, label: None };
+                        for result in reader.query(&region)? {
+                            counts.add(&result?);
+                        }
+                    }
+                    Ok(counts)
+                })
+            })
+            .collect();
+        workers.into_iter().map(|worker| worker.join().expect("计数线程panic")).collect()
+    });
+
+    let mut counts = Counts { records: unplaced, unmapped: unplaced, ..Default::default() };
+    for result in results {
+        counts.merge(&result?);
+    }
+    Ok(Some(counts))
+}
+
+fn run(args: &Args) -> Result<Counts, BamError> {
+    let mut reader = BamReader::from_path_with_reference(&args.input, args.reference.as_deref())?;
+    if args.threads > 1 && !bamqc_io::is_stdin(&args.input) {
+        match count_parallel(&reader, args.threads) {
+            Ok(Some(counts)) => return Ok(counts),
+            Ok(None) => eprintln!("{}: 没有带元数据的BAI/CSI索引，改为顺序计数", args.input),
+            Err(BamError::IndexNotFound { .. }) => eprintln!("{}: 找不到索引，改为顺序计数", args.input),
+            Err(e) => return Err(e),
+        }
+    }
+    count_sequential(&mut reader)
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::from(2);
+        }
+    };
+    match run(&args) {
+        Ok(counts) => {
+            println!("records: {}", counts.records);
+            println!("mapped: {}", counts.mapped);
+            println!("unmapped: {}", counts.unmapped);
+            println!("duplicate: {}", counts.duplicate);
+            println!("qc_fail: {}", counts.qc_fail);
+            println!("secondary: {}", counts.secondary);
+            println!("supplementary: {}", counts.supplementary);
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("{}: {}", args.input, e);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,67 @@
+//! 输出头部与前N条记录的SAM文本：`cargo run -p bamqc-io --example bam_head -- <input> [-n N]`
+//!
+//! 只依赖bamqc_io，用于确认读取器能打开自己的文件。输入为BAM或CRAM
+//! （需要`cram`特性，参考序列用`--reference <FASTA>`指定），`-`表示标准输入。
+
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use bamqc_io::BamReader;
+use noodles::sam;
+
+const USAGE: &str = "用法: bam_head <input> [-n N] [--reference FASTA]";
+
+struct Args {
+    input: String,
+    records: u64,
+    reference: Option<PathBuf>,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut input = None;
+    let mut records = 10;
+    let mut reference = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-n" => {
+                let value = args.next().ok_or("-n需要一个记录数")?;
+                records = value.parse().map_err(|_| format!("无效的记录数: {}", value))?;
+            }
+            "--reference" => reference = Some(PathBuf::from(args.next().ok_or("--reference需要FASTA路径")?)),
+            _ if input.is_none() => input = Some(arg),
+            _ => return Err(format!("多余的参数: {}", arg)),
+        }
+    }
+    Ok(Args { input: input.ok_or(USAGE)?, records, reference })
+}
+
+fn run(args: &Args) -> Result<(), Box<dyn std::error::Error>> {
+    let mut reader = BamReader::from_path_with_reference(&args.input, args.reference.as_deref())?;
+    let header = reader.header().clone();
+    let mut out = BufWriter::new(std::io::stdout().lock());
+    sam::io::Writer::new(&mut out).write_header(&header)?;
+    for result in reader.records().take(args.records as usize) {
+        writeln!(out, "{}", result?.to_sam_string(&header)?)?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::from(2);
+        }
+    };
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}: {}", args.input, e);
+            ExitCode::FAILURE
+        }
+    }
+}
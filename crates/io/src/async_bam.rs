@@ -0,0 +1,256 @@
+//! 异步读取器（`async`特性）
+//!
+//! [`AsyncBamReader`]与[`BamReader`]的API对应，但打开文件与读取记录都在专用的
+//! 工作线程中进行，异步任务只在记录就绪时被唤醒，不会在运行时的线程上阻塞。
+//! 实现只依赖标准库的[`Future`]与[`Waker`]，可用于任意异步运行时（tokio、async-std等）；
+//! [`AsyncRecords::poll_next`]的签名与`futures::Stream`相同，需要`Stream`时可直接包装。
+//!
+//! 记录在工作线程与异步任务之间经由有界队列传递，队列满时工作线程等待，
+//! 内存占用不随文件大小增长。
+
+use std::collections::VecDeque;
+use std::future::{poll_fn, Future};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
+
+use noodles::sam;
+
+use crate::bam::{BamError, BamReader, BamRecord};
+
+/// 工作线程最多领先异步任务的记录数
+const QUEUE_CAPACITY: usize = 1024;
+
+/// 异步读取BAM/SAM/CRAM的读取器
+///
+/// # Examples
+///
+/// ```
+/// use bamqc_io::{AsyncBamReader, BamWriter};
+/// use noodles::sam::{self, alignment::RecordBuf};
+/// # use std::future::Future;
+/// # use std::sync::Arc;
+/// # use std::task::{Context, Poll, Wake};
+/// # // 最小的执行器：在当前线程上轮询，挂起时park，被唤醒时unpark
+/// # struct Unpark(std::thread::Thread);
+/// # impl Wake for Unpark {
+/// #     fn wake(self: Arc<Self>) {
+/// #         self.0.unpark();
+/// #     }
+/// # }
+/// # fn block_on<F: Future>(future: F) -> F::Output {
+/// #     let waker = Arc::new(Unpark(std::thread::current())).into();
+/// #     let mut cx = Context::from_waker(&waker);
+/// #     let mut future = std::pin::pin!(future);
+/// #     loop {
+/// #         match future.as_mut().poll(&mut cx) {
+/// #             Poll::Ready(output) => return output,
+/// #             Poll::Pending => std::thread::park(),
+/// #         }
+/// #     }
+/// # }
+///
+/// let bam = std::env::temp_dir().join(format!("bamqc-async-reader-{}.bam", std::process::id()));
+/// let header: sam::Header = "@HD\tVN:1.6\n@SQ\tSN:chr1\tLN:1000\n".parse().unwrap();
+/// let mut writer = BamWriter::from_path(&bam, &header).unwrap();
+/// for i in 0..3000 {
+///     writer.write_record_buf_with_name(&RecordBuf::default(), format!("r{}", i).as_bytes()).unwrap();
+/// }
+/// writer.finish().unwrap();
+///
+/// let names = block_on(async {
+///     let mut reader = AsyncBamReader::from_path(&bam).await.unwrap();
+///     assert_eq!(reader.header().reference_sequences().len(), 1);
+///     let mut records = reader.records();
+///     let mut names = Vec::new();
+///     while let Some(record) = records.next().await {
+///         names.push(record.unwrap().name().into_owned());
+///     }
+///     names
+/// });
+/// assert_eq!(names.len(), 3000);
+/// assert_eq!((names[0].as_str(), names[2999].as_str()), ("r0", "r2999"));
+/// std::fs::remove_file(&bam).unwrap();
+/// ```
+pub struct AsyncBamReader {
+    header: sam::Header,
+    path: String,
+    /// 由读取记录的工作线程持有锁
+    inner: Arc<Mutex<BamReader>>,
+}
+
+impl AsyncBamReader {
+    /// 在工作线程中打开文件（自动识别格式），见[`BamReader::from_path`]
+    pub async fn from_path<P: AsRef<Path>>(path: P) -> Result<Self, BamError> {
+        let path: PathBuf = path.as_ref().to_path_buf();
+        Self::from_reader(spawn_blocking(move || BamReader::from_path(path)).await??)
+    }
+
+    /// 包装已打开的读取器，之后的读取在工作线程中进行
+    pub fn from_reader(reader: BamReader) -> Result<Self, BamError> {
+        Ok(Self {
+            header: reader.header().clone(),
+            path: reader.path().to_string(),
+            inner: Arc::new(Mutex::new(reader)),
+        })
+    }
+
+    /// 获取文件路径
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// 获取SAM头部信息
+    pub fn header(&self) -> &sam::Header {
+        &self.header
+    }
+
+    /// 异步迭代记录，与[`BamReader::records`]读取同一记录流
+    ///
+    /// 工作线程在调用时启动；丢弃返回的迭代器后工作线程在读完当前记录时退出，
+    /// 再次调用从该处继续读取。
+    pub fn records(&mut self) -> AsyncRecords {
+        let channel = Arc::new(Channel::default());
+        let worker = Arc::clone(&channel);
+        let inner = Arc::clone(&self.inner);
+        let spawned = std::thread::Builder::new().name("bamqc-async-reader".to_string()).spawn(move || {
+            // 正常结束、迭代器被丢弃或读取时panic，都标记结束并唤醒异步任务
+            let _finish = Finish(&worker);
+            let mut reader = inner.lock().unwrap_or_else(|e| e.into_inner());
+            for result in reader.records() {
+                if !worker.send(result) {
+                    return;
+                }
+            }
+        });
+        if let Err(e) = spawned {
+            channel.send(Err(e.into()));
+            channel.finish();
+        }
+        AsyncRecords { channel }
+    }
+}
+
+/// [`AsyncBamReader::records`]返回的异步迭代器
+pub struct AsyncRecords {
+    channel: Arc<Channel>,
+}
+
+impl AsyncRecords {
+    /// 下一条记录；读到末尾时为None
+    pub async fn next(&mut self) -> Option<Result<BamRecord, BamError>> {
+        poll_fn(|cx| self.poll_next(cx)).await
+    }
+
+    /// 轮询下一条记录（与`futures::Stream::poll_next`相同）：尚未就绪时登记`cx`的waker，
+    /// 返回[`Poll::Pending`]
+    pub fn poll_next(&mut self, cx: &mut Context<'_>) -> Poll<Option<Result<BamRecord, BamError>>> {
+        let mut state = self.channel.lock();
+        if let Some(result) = state.queue.pop_front() {
+            self.channel.space.notify_one();
+            return Poll::Ready(Some(result));
+        }
+        if state.done {
+            return Poll::Ready(None);
+        }
+        state.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl Drop for AsyncRecords {
+    fn drop(&mut self) {
+        self.channel.lock().cancelled = true;
+        self.channel.space.notify_one();
+    }
+}
+
+/// 工作线程与异步任务之间的有界队列
+#[derive(Default)]
+struct Channel {
+    state: Mutex<ChannelState>,
+    /// 队列有空位或迭代器被丢弃时通知工作线程
+    space: Condvar,
+}
+
+#[derive(Default)]
+struct ChannelState {
+    queue: VecDeque<Result<BamRecord, BamError>>,
+    /// 工作线程已读完（或出错后结束）
+    done: bool,
+    /// 迭代器已丢弃，工作线程应退出
+    cancelled: bool,
+    waker: Option<Waker>,
+}
+
+impl Channel {
+    fn lock(&self) -> MutexGuard<'_, ChannelState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// 放入一条结果，队列满时等待；迭代器已丢弃时返回false
+    fn send(&self, result: Result<BamRecord, BamError>) -> bool {
+        let mut state = self.lock();
+        while state.queue.len() >= QUEUE_CAPACITY && !state.cancelled {
+            state = self.space.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+        if state.cancelled {
+            return false;
+        }
+        state.queue.push_back(result);
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+        true
+    }
+
+    fn finish(&self) {
+        let mut state = self.lock();
+        state.done = true;
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// 释放时调用[`Channel::finish`]
+struct Finish<'a>(&'a Channel);
+
+impl Drop for Finish<'_> {
+    fn drop(&mut self) {
+        self.0.finish();
+    }
+}
+
+/// 在新线程中运行`f`，返回等待其结果的future；`f`panic时返回错误
+fn spawn_blocking<T, F>(f: F) -> impl Future<Output = Result<T, BamError>>
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    type Slot<T> = (Option<Result<T, BamError>>, Option<Waker>);
+    let slot: Arc<Mutex<Slot<T>>> = Arc::new(Mutex::new((None, None)));
+    let worker = Arc::clone(&slot);
+    let spawned = std::thread::Builder::new().name("bamqc-async-open".to_string()).spawn(move || {
+        let output = std::panic::catch_unwind(std::panic::AssertUnwindSafe(f))
+            .map_err(|_| BamError::BamError("工作线程panic".to_string()));
+        let mut slot = worker.lock().unwrap_or_else(|e| e.into_inner());
+        slot.0 = Some(output);
+        if let Some(waker) = slot.1.take() {
+            waker.wake();
+        }
+    });
+    if let Err(e) = spawned {
+        slot.lock().unwrap_or_else(|e| e.into_inner()).0 = Some(Err(e.into()));
+    }
+    poll_fn(move |cx| {
+        let mut slot = slot.lock().unwrap_or_else(|e| e.into_inner());
+        match slot.0.take() {
+            Some(output) => Poll::Ready(output),
+            None => {
+                slot.1 = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    })
+}
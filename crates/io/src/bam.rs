@@ -1,10 +1,36 @@
-use noodles::bam::{self, io::Reader};
-use noodles::bgzf::io::Reader as BgzfReader;
+use noodles::bam::{self, bai, io::Reader};
+use noodles::bam::io::reader::Query;
+use noodles::bgzf::io::{MultithreadedReader, Reader as BgzfReader};
+use noodles::bgzf::VirtualPosition;
+use noodles::bgzf::io::{Read as _, Seek as _};
 use noodles::sam::{self};
+use noodles::sam::alignment::record::data::field::{value::Array, Value};
+use crate::cigar::{Cigar, CigarKind, CigarSource, sum_encoded};
+#[cfg(feature = "serde")]
+use crate::error_code::{io_error_kind, serialize_error};
+use crate::error_code::ErrorCode;
+use crate::format::{FileKind, Format, check_sniffed, resolve_format, sniff_head};
+use crate::index::{BamIndex, IndexFormat, ReferenceStats, has_bgzf_eof};
+use crate::lossy::decode_lossy;
+#[cfg(feature = "manifest")]
+use crate::manifest::{InputManifestBuilder, Observed};
+use crate::progress::READ_PROGRESS;
+use crate::retry::{RetryPolicy, is_transient_io_error};
+use crate::region::{GenomicRegion, plan_queries, read_bed, PlannedQuery};
+use noodles::csi::BinningIndex as _;
+use noodles::csi::binning_index::ReferenceSequence as _;
+use noodles::csi::binning_index::index::reference_sequence::bin::Chunk;
+use noodles::csi::binning_index::Indexer;
+use std::collections::{HashMap, VecDeque};
+use std::borrow::Cow;
 use std::fs::File;
-use std::path::Path;
+use std::io::{BufRead, Read, Seek as _, Stdin};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 use thiserror::Error;
-use tracing::info;
+use crate::log::{debug, info, warn};
 
 /// BAM/CRAM文件读取错误
 #[derive(Error, Debug)]
@@ -20,97 +46,3111 @@ pub enum BamError {
     
     #[error("文件不存在: {path}")]
     FileNotFound { path: String },
+
+    #[error("找不到索引文件: {path}（请先运行samtools index）")]
+    IndexNotFound { path: String },
+
+    #[error("区域格式错误: {0}")]
+    RegionError(String),
+
+    #[error("无法识别文件格式 {path}: {found}")]
+    UnknownFormat { path: String, found: String },
+
+    #[error("{path} 不是BAM/SAM/CRAM文件，内容为{detected}：{}", detected.hint())]
+    NotABamFile { path: String, detected: FileKind },
+
+    #[error("{path} 缺少BGZF EOF标记块，文件可能被截断（例如复制或下载未完成），请重新生成或重新传输")]
+    Truncated { path: String },
+
+    #[error("{path}: 期望{expected}格式，实际内容为{found}")]
+    FormatMismatch { path: String, expected: Format, found: String },
+
+    #[error("暂不支持读取{0}格式")]
+    UnsupportedFormat(Format),
+
+    #[error("CRAM解码错误: {0}")]
+    CramError(String),
+
+    #[error("BGZF块校验失败（CRC32或ISIZE不符），块的压缩偏移: {block_offset}")]
+    BgzfChecksum { block_offset: u64 },
+
+    #[error("标准输入不支持{0}")]
+    StdinUnsupported(String),
+
+    #[error("头部声明按坐标排序（SO:coordinate），但第{record_index}条记录 {curr} 位于前一条记录 {prev} 之前")]
+    SortOrderViolation { record_index: u64, prev: String, curr: String },
+
+    #[error("{path}: 需要按{expected}排序的输入，头部声明的排序为{found}（可用samtools sort重新排序）")]
+    WrongSortOrder { path: String, expected: SortOrder, found: SortOrder },
+
+    #[error("读到第{record_index}条记录时等待mate的记录超过上限{limit}条（可先用samtools sort -n按读名排序，或提高上限）")]
+    PendingMatesExceeded { limit: usize, record_index: u64 },
+
+    #[error("辅助标签{tag}的类型为{found}，期望{expected}")]
+    TagTypeMismatch { tag: String, expected: &'static str, found: String },
+
+    #[error("{path}: 虚拟偏移{offset}处不是记录的起点（{reason}），无法从该处继续读取")]
+    NotARecordBoundary { path: String, offset: u64, reason: String },
+
+    #[error("{path}是{format}输入，没有BGZF虚拟偏移，不支持定位")]
+    Unseekable { path: String, format: Format },
+
+    #[error("{path}的参考序列字典与{expected_from}不同（名称、长度与顺序须完全一致），无法合并：\n{diff}")]
+    ReferenceMismatch { path: String, expected_from: String, diff: String },
+
+    #[error("{path}: {source}")]
+    Input { path: String, source: Box<BamError> },
+
+    #[error("抽样比例{fraction}无效，须在(0, 1]内")]
+    InvalidSampleFraction { fraction: f64 },
+
+    #[error("{path}的第{record_index}条记录损坏，无法解码: {reason}（可用samtools quickcheck检查文件完整性）")]
+    RecordReadError { path: String, record_index: u64, reason: String },
+}
+
+impl ErrorCode for BamError {
+    fn code(&self) -> &'static str {
+        match self {
+            BamError::IoError(_) => "io_error",
+            BamError::BamError(_) => "bam_format",
+            BamError::SamError(_) => "sam_header",
+            BamError::FileNotFound { .. } => "file_not_found",
+            BamError::IndexNotFound { .. } => "index_not_found",
+            BamError::RegionError(_) => "invalid_region",
+            BamError::UnknownFormat { .. } => "unknown_format",
+            BamError::NotABamFile { .. } => "not_a_bam_file",
+            BamError::Truncated { .. } => "truncated",
+            BamError::FormatMismatch { .. } => "format_mismatch",
+            BamError::UnsupportedFormat(_) => "unsupported_format",
+            BamError::CramError(_) => "cram_decode",
+            BamError::BgzfChecksum { .. } => "bgzf_checksum",
+            BamError::StdinUnsupported(_) => "stdin_unsupported",
+            BamError::SortOrderViolation { .. } => "sort_order_violation",
+            BamError::WrongSortOrder { .. } => "wrong_sort_order",
+            BamError::PendingMatesExceeded { .. } => "pending_mates_exceeded",
+            BamError::TagTypeMismatch { .. } => "tag_type_mismatch",
+            BamError::NotARecordBoundary { .. } => "not_a_record_boundary",
+            BamError::Unseekable { .. } => "unseekable",
+            BamError::ReferenceMismatch { .. } => "reference_mismatch",
+            BamError::Input { source, .. } => source.code(),
+            BamError::InvalidSampleFraction { .. } => "invalid_sample_fraction",
+            BamError::RecordReadError { .. } => "record_read_error",
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    fn serialize_details<M: serde::ser::SerializeMap>(&self, map: &mut M) -> Result<(), M::Error> {
+        match self {
+            BamError::IoError(e) => map.serialize_entry("kind", &io_error_kind(e)),
+            BamError::FileNotFound { path } | BamError::IndexNotFound { path } | BamError::Truncated { path } => {
+                map.serialize_entry("path", path)
+            }
+            BamError::UnknownFormat { path, found } => {
+                map.serialize_entry("path", path)?;
+                map.serialize_entry("found", found)
+            }
+            BamError::NotABamFile { path, detected } => {
+                map.serialize_entry("path", path)?;
+                map.serialize_entry("detected", &detected.to_string())
+            }
+            BamError::FormatMismatch { path, expected, found } => {
+                map.serialize_entry("path", path)?;
+                map.serialize_entry("expected", &expected.to_string())?;
+                map.serialize_entry("found", found)
+            }
+            BamError::UnsupportedFormat(format) => map.serialize_entry("format", &format.to_string()),
+            BamError::BgzfChecksum { block_offset } => map.serialize_entry("block_offset", block_offset),
+            BamError::SortOrderViolation { record_index, prev, curr } => {
+                map.serialize_entry("record_index", record_index)?;
+                map.serialize_entry("prev", prev)?;
+                map.serialize_entry("curr", curr)
+            }
+            BamError::WrongSortOrder { path, expected, found } => {
+                map.serialize_entry("path", path)?;
+                map.serialize_entry("expected", &expected.to_string())?;
+                map.serialize_entry("found", &found.to_string())
+            }
+            BamError::PendingMatesExceeded { limit, record_index } => {
+                map.serialize_entry("limit", limit)?;
+                map.serialize_entry("record_index", record_index)
+            }
+            BamError::TagTypeMismatch { tag, expected, found } => {
+                map.serialize_entry("tag", tag)?;
+                map.serialize_entry("expected", expected)?;
+                map.serialize_entry("found", found)
+            }
+            BamError::NotARecordBoundary { path, offset, reason } => {
+                map.serialize_entry("path", path)?;
+                map.serialize_entry("offset", offset)?;
+                map.serialize_entry("reason", reason)
+            }
+            BamError::Unseekable { path, format } => {
+                map.serialize_entry("path", path)?;
+                map.serialize_entry("format", &format.to_string())
+            }
+            BamError::ReferenceMismatch { path, expected_from, diff } => {
+                map.serialize_entry("path", path)?;
+                map.serialize_entry("expected_from", expected_from)?;
+                map.serialize_entry("diff", diff)
+            }
+            BamError::Input { path, source } => {
+                source.serialize_details(map)?;
+                map.serialize_entry("input", path)
+            }
+            BamError::InvalidSampleFraction { fraction } => map.serialize_entry("fraction", fraction),
+            BamError::RecordReadError { path, record_index, reason } => {
+                map.serialize_entry("path", path)?;
+                map.serialize_entry("record_index", record_index)?;
+                map.serialize_entry("reason", reason)
+            }
+            BamError::BamError(_)
+            | BamError::SamError(_)
+            | BamError::RegionError(_)
+            | BamError::CramError(_)
+            | BamError::StdinUnsupported(_) => Ok(()),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for BamError {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_error(self, serializer)
+    }
+}
+
+/// 表示标准输入的路径
+pub const STDIN_PATH: &str = "-";
+
+/// 路径是否表示标准输入（[`STDIN_PATH`]）
+///
+/// # Examples
+///
+/// ```
+/// use bamqc_io::is_stdin;
+///
+/// assert!(is_stdin("-"));
+/// assert!(!is_stdin("./-"));
+/// assert!(!is_stdin("in.bam"));
+/// ```
+pub fn is_stdin<P: AsRef<Path>>(path: P) -> bool {
+    path.as_ref() == Path::new(STDIN_PATH)
+}
+
+type StdinReader = Reader<BgzfReader<Stdin>>;
+
+/// 按显式指定的`expected`格式打开失败时的错误：内容与指定格式不符时为
+/// [`BamError::FormatMismatch`]（描述实际内容），否则为原来的错误
+fn explicit_open_error(path: &str, expected: Format, error: BamError) -> BamError {
+    match crate::format::sniff(path) {
+        Ok(sniffed) => check_sniffed(path, expected, sniffed).err().unwrap_or(error),
+        Err(_) => error,
+    }
+}
+
+/// SAM文本没有索引，区域查询返回的错误
+fn sam_query_error() -> BamError {
+    BamError::RegionError("SAM文本没有索引，不支持区域查询（请先转换为BAM并建立索引）".to_string())
+}
+
+/// 本进程中解析头部的次数（BAM、SAM、CRAM与标准输入）
+static HEADER_PARSES: AtomicU64 = AtomicU64::new(0);
+
+/// 本进程中打开输入时解析头部的总次数
+///
+/// 测试用：确认[`BamReader::try_clone`]与重复遍历同一输入的收集器共享已解析的头部。
+#[doc(hidden)]
+pub fn header_parse_count() -> u64 {
+    HEADER_PARSES.load(Ordering::Relaxed)
+}
+
+pub(crate) fn count_header_parse() {
+    HEADER_PARSES.fetch_add(1, Ordering::Relaxed);
+}
+
+/// BAM的原始字节来源：文件，或[`BamReaderOptions::open_with`]提供的自定义来源
+pub trait RawInput: Read + std::io::Seek + Send + Sync {}
+
+impl<R: Read + std::io::Seek + Send + Sync> RawInput for R {}
+
+/// 打开（或重新打开）BAM的原始字节，每次调用都从头开始读
+type Opener = Arc<dyn Fn() -> std::io::Result<Box<dyn RawInput>> + Send + Sync>;
+
+fn file_opener(path: &Path) -> Opener {
+    let path = path.to_path_buf();
+    Arc::new(move || Ok(Box::new(File::open(&path)?) as Box<dyn RawInput>))
+}
+
+/// BAM文件的BGZF解压：单线程，或由工作线程并行解压块
+///
+/// 两者产出的字节流与虚拟偏移相同，上层的记录读取、索引查询不需要区分。
+enum BgzfInput {
+    Single(BgzfReader<Box<dyn RawInput>>),
+    Multi {
+        reader: MultithreadedReader<LatchedInput>,
+        /// 读取线程遇到的原始输入错误
+        error: Arc<Mutex<Option<std::io::Error>>>,
+    },
+}
+
+/// 多线程解压时的原始输入：noodles的读取线程遇到错误时只结束数据流、丢弃错误，
+/// 这里先记下错误，数据流提前结束时由[`BgzfInput`]返回，不会被当作文件末尾
+struct LatchedInput {
+    inner: Box<dyn RawInput>,
+    error: Arc<Mutex<Option<std::io::Error>>>,
+}
+
+impl Read for LatchedInput {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.inner.read(buf).map_err(|e| {
+            if e.kind() == std::io::ErrorKind::Interrupted {
+                return e;
+            }
+            let copy = std::io::Error::new(e.kind(), e.to_string());
+            *self.error.lock().unwrap_or_else(|e| e.into_inner()) = Some(e);
+            copy
+        })
+    }
+}
+
+impl std::io::Seek for LatchedInput {
+    fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+/// 取出读取线程记下的错误
+fn take_latched(error: &Mutex<Option<std::io::Error>>) -> Option<std::io::Error> {
+    error.lock().unwrap_or_else(|e| e.into_inner()).take()
+}
+
+impl BgzfInput {
+    fn new(input: Box<dyn RawInput>, threads: NonZeroUsize) -> Self {
+        if threads.get() > 1 {
+            let error = Arc::new(Mutex::new(None));
+            let input = LatchedInput { inner: input, error: Arc::clone(&error) };
+            BgzfInput::Multi { reader: MultithreadedReader::with_worker_count(threads, input), error }
+        } else {
+            BgzfInput::Single(BgzfReader::new(input))
+        }
+    }
+
+    /// 重新打开字节来源，定位到`pos`
+    fn reopen(opener: &Opener, threads: NonZeroUsize, pos: VirtualPosition) -> std::io::Result<Reader<BgzfInput>> {
+        let mut reader = Reader::from(BgzfInput::new(opener()?, threads));
+        reader.get_mut().seek_to_virtual_position(pos)?;
+        Ok(reader)
+    }
+}
+
+impl Read for BgzfInput {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            BgzfInput::Single(reader) => reader.read(buf),
+            BgzfInput::Multi { reader, error } => match reader.read(buf)? {
+                0 if !buf.is_empty() => take_latched(error).map_or(Ok(0), Err),
+                n => Ok(n),
+            },
+        }
+    }
+}
+
+impl BufRead for BgzfInput {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        match self {
+            BgzfInput::Single(reader) => reader.fill_buf(),
+            BgzfInput::Multi { reader, error } => {
+                let buf = reader.fill_buf()?;
+                if buf.is_empty() {
+                    if let Some(e) = take_latched(error) {
+                        return Err(e);
+                    }
+                }
+                Ok(buf)
+            }
+        }
+    }
+
+    fn consume(&mut self, amt: usize) {
+        match self {
+            BgzfInput::Single(reader) => reader.consume(amt),
+            BgzfInput::Multi { reader, .. } => reader.consume(amt),
+        }
+    }
+}
+
+impl noodles::bgzf::io::Read for BgzfInput {
+    fn virtual_position(&self) -> VirtualPosition {
+        match self {
+            BgzfInput::Single(reader) => reader.virtual_position(),
+            BgzfInput::Multi { reader, .. } => reader.virtual_position(),
+        }
+    }
+}
+
+impl noodles::bgzf::io::BufRead for BgzfInput {}
+
+impl noodles::bgzf::io::Seek for BgzfInput {
+    fn seek_to_virtual_position(&mut self, pos: VirtualPosition) -> std::io::Result<VirtualPosition> {
+        match self {
+            BgzfInput::Single(reader) => reader.seek_to_virtual_position(pos),
+            BgzfInput::Multi { reader, error } => {
+                // 定位时读取线程重新启动，之前记下的错误不再适用
+                take_latched(error);
+                reader.seek_to_virtual_position(pos)
+            }
+        }
+    }
+
+    fn seek_with_index(&mut self, index: &noodles::bgzf::gzi::Index, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+        match self {
+            BgzfInput::Single(reader) => reader.seek_with_index(index, pos),
+            BgzfInput::Multi { reader, error } => {
+                take_latched(error);
+                reader.seek_with_index(index, pos)
+            }
+        }
+    }
+}
+
+/// 已解析的标准输入头部与定位在第一条记录的读取器
+///
+/// 之后打开标准输入的读取器都共享头部，读取器由第一个迭代记录的读取器取走。
+static STDIN: Mutex<StdinState> = Mutex::new(StdinState { header: None, reader: None });
+
+struct StdinState {
+    header: Option<Arc<sam::Header>>,
+    reader: Option<StdinReader>,
+}
+
+/// 取走标准输入的记录流；已被其它读取器取走时返回错误
+fn take_stdin_reader() -> Result<StdinReader, BamError> {
+    let taken = STDIN.lock().unwrap_or_else(|e| e.into_inner()).reader.take();
+    taken.ok_or_else(|| {
+        BamError::StdinUnsupported("多次遍历记录（该分析需要多次读取输入，请先写入文件）".to_string())
+    })
+}
+
+/// 读取器的配置：解压线程数、CRAM参考序列、输入格式、截断检查、重试与BGZF自检
+///
+/// 未设置的项与[`BamReader::from_path`]相同；设置各项的链式方法需要`builder`特性。配置只作用于用它打开的读取器，
+/// 不影响同一进程中其它读取器；命令行把各全局选项汇总为一个`BamReaderOptions`
+/// 交给各收集器。
+///
+/// # Examples
+///
+/// 末尾被截断的BAM（例如复制未完成）仍能打开，启用`check_eof`时
+/// 打开即返回[`BamError::Truncated`]：
+///
+/// ```
+/// use bamqc_io::{BamError, BamReader, BamReaderOptions, BamWriter, Format};
+///
+/// # #[cfg(feature = "builder")] {
+/// let dir = std::env::temp_dir().join(format!("bamqc-truncated-{}", std::process::id()));
+/// std::fs::create_dir_all(&dir).unwrap();
+/// let bam = dir.join("in.bam");
+/// let header = "@HD\tVN:1.6\n@SQ\tSN:c1\tLN:100\n".parse().unwrap();
+/// BamWriter::from_path(&bam, &header).unwrap().finish().unwrap();
+/// let options = BamReaderOptions::new().format(Format::Bam).check_eof(true);
+/// assert!(options.open(&bam).is_ok());
+///
+/// // 截掉末尾30字节：文件不再以EOF标记块结尾
+/// let data = std::fs::read(&bam).unwrap();
+/// std::fs::write(&bam, &data[..data.len() - 30]).unwrap();
+/// assert!(BamReader::from_path(&bam).is_ok());
+/// let e = options.open(&bam).unwrap_err();
+/// assert!(matches!(e, BamError::Truncated { .. }), "{}", e);
+///
+/// // 指定的格式与内容不符
+/// let e = BamReaderOptions::new().format(Format::Sam).open(&bam).unwrap_err();
+/// assert!(matches!(e, BamError::FormatMismatch { .. }), "{}", e);
+/// std::fs::remove_dir_all(&dir).unwrap();
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct BamReaderOptions {
+    threads: NonZeroUsize,
+    // 只在解码CRAM时读取
+    #[cfg_attr(not(feature = "cram"), allow(dead_code))]
+    reference: Option<PathBuf>,
+    format: Format,
+    check_eof: bool,
+    sort_order: Option<SortOrder>,
+    retry: RetryPolicy,
+    verify: bool,
+}
+
+impl Default for BamReaderOptions {
+    fn default() -> Self {
+        Self {
+            threads: NonZeroUsize::MIN,
+            reference: None,
+            format: Format::Auto,
+            check_eof: false,
+            sort_order: None,
+            retry: RetryPolicy::default(),
+            verify: false,
+        }
+    }
+}
+
+impl BamReaderOptions {
+    /// 默认配置
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[cfg(feature = "builder")]
+    /// BAM文件的BGZF解压线程数，默认为1（单线程解压）；
+    /// 标准输入、SAM与CRAM输入忽略该项
+    pub fn threads(mut self, threads: NonZeroUsize) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    #[cfg(feature = "builder")]
+    /// 解码CRAM的参考序列FASTA，默认没有参考（参考序列压缩的CRAM无法解码）；
+    /// 其它格式忽略该项
+    pub fn reference<P: Into<PathBuf>>(mut self, reference: P) -> Self {
+        self.reference = Some(reference.into());
+        self
+    }
+
+    #[cfg(feature = "builder")]
+    /// 输入格式，默认`Format::Auto`自动识别；指定格式时内容不符返回[`BamError::FormatMismatch`]
+    pub fn format(mut self, format: Format) -> Self {
+        self.format = format;
+        self
+    }
+
+    #[cfg(feature = "builder")]
+    /// 打开BAM时核对文件末尾的BGZF EOF标记块（需要一次seek），缺失时返回[`BamError::Truncated`]
+    pub fn check_eof(mut self, check_eof: bool) -> Self {
+        self.check_eof = check_eof;
+        self
+    }
+
+    #[cfg(feature = "builder")]
+    /// 要求头部声明的排序方式为`sort_order`，不符时打开即返回[`BamError::WrongSortOrder`]
+    ///
+    /// 只核对头部声明，不检查记录的实际顺序。
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bamqc_io::{BamError, BamReaderOptions, BamWriter, SortOrder};
+    ///
+    /// let bam = std::env::temp_dir().join(format!("bamqc-require-sort-{}.bam", std::process::id()));
+    /// let header = "@HD\tVN:1.6\tSO:queryname\n@SQ\tSN:c1\tLN:100\n".parse().unwrap();
+    /// BamWriter::from_path(&bam, &header).unwrap().finish().unwrap();
+    ///
+    /// let reader = BamReaderOptions::new().require_sort_order(SortOrder::QueryName).open(&bam).unwrap();
+    /// assert_eq!(reader.sort_order(), SortOrder::QueryName);
+    /// let e = BamReaderOptions::new().require_sort_order(SortOrder::Coordinate).open(&bam).unwrap_err();
+    /// assert!(
+    ///     matches!(e, BamError::WrongSortOrder { expected: SortOrder::Coordinate, found: SortOrder::QueryName, .. }),
+    ///     "{}",
+    ///     e
+    /// );
+    /// std::fs::remove_file(&bam).unwrap();
+    /// ```
+    pub fn require_sort_order(mut self, sort_order: SortOrder) -> Self {
+        self.sort_order = Some(sort_order);
+        self
+    }
+
+    #[cfg(feature = "builder")]
+    /// BAM顺序读取记录时遇到暂时性IO错误（见[`is_transient_io_error`]）的重试策略，
+    /// 默认不重试
+    ///
+    /// 重试时重新打开输入，定位到最后一条完整记录之后的BGZF虚拟偏移继续读取，
+    /// 已产出的记录不会重复，也不会遗漏；每次重试都记一条警告日志。
+    /// 只作用于[`BamReader::records`]（以及基于它的[`BamReader::record_pairs`]），
+    /// 区域查询、标准输入、SAM文本与CRAM不重试。示例见[`BamReaderOptions::open_with`]。
+    pub fn retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    #[cfg(feature = "builder")]
+    /// 读取记录前做BGZF块级自检，默认不自检，见[`BamReader::set_verify`]
+    pub fn verify(mut self, verify: bool) -> Self {
+        self.verify = verify;
+        self
+    }
+
+    /// 按配置打开文件，路径为`-`时读取标准输入
+    pub fn open<P: AsRef<Path>>(&self, path: P) -> Result<BamReader, BamError> {
+        let reader = BamReader::open(path, self)?;
+        self.check_sort_order(reader)
+    }
+
+    /// 从自定义的字节来源打开BAM，`name`用于日志与错误信息
+    ///
+    /// `opener`每次调用都返回从头开始的新来源：打开时、[`BamReader::try_clone`]
+    /// 与重试时各调用一次。只支持BAM（BGZF）内容；索引与BGZF自检仍按`name`查找文件。
+    ///
+    /// # Examples
+    ///
+    /// 在指定的字节偏移注入一次性的EIO/ESTALE，重试后每条记录恰好产出一次；
+    /// 其它错误与重试用尽时仍然返回错误：
+    ///
+    /// ```
+    /// use bamqc_io::{BamError, BamReaderOptions, BamWriter, RetryPolicy};
+    /// use noodles::sam;
+    /// use std::io::{self, Cursor, Read, Seek, SeekFrom};
+    /// use std::num::NonZeroUsize;
+    /// use std::sync::{Arc, Mutex};
+    /// use std::time::Duration;
+    ///
+    /// /// 读到`failures`中的偏移时失败一次（该偏移随之移除），之后重新打开可以读过去
+    /// struct Flaky {
+    ///     data: Cursor<Arc<[u8]>>,
+    ///     failures: Arc<Mutex<Vec<(u64, io::Error)>>>,
+    /// }
+    ///
+    /// impl Read for Flaky {
+    ///     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    ///         let pos = self.data.position();
+    ///         let mut failures = self.failures.lock().unwrap();
+    ///         let next = failures.iter().position(|(offset, _)| (pos..pos + buf.len() as u64).contains(offset));
+    ///         match next {
+    ///             Some(i) if failures[i].0 == pos => return Err(failures.remove(i).1),
+    ///             // 读到失败偏移之前为止
+    ///             Some(i) => {
+    ///                 let len = (failures[i].0 - pos) as usize;
+    ///                 return self.data.read(&mut buf[..len]);
+    ///             }
+    ///             None => {}
+    ///         }
+    ///         drop(failures);
+    ///         self.data.read(buf)
+    ///     }
+    /// }
+    ///
+    /// impl Seek for Flaky {
+    ///     fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+    ///         self.data.seek(pos)
+    ///     }
+    /// }
+    ///
+    /// # #[cfg(feature = "builder")] {
+    /// let path = std::env::temp_dir().join(format!("bamqc-retry-{}.bam", std::process::id()));
+    /// let mut text = String::from("@HD\tVN:1.6\n@SQ\tSN:c1\tLN:100000\n");
+    /// for i in 0..3000 {
+    ///     text.push_str(&format!("r{}\t0\tc1\t{}\t60\t50M\t*\t0\t0\t{}\t*\n", i, i * 30 + 1, "ACGTT".repeat(10)));
+    /// }
+    /// let mut sam_reader = sam::io::Reader::new(text.as_bytes());
+    /// let header = sam_reader.read_header().unwrap();
+    /// let mut writer = BamWriter::from_path(&path, &header).unwrap();
+    /// for record in sam_reader.record_bufs(&header) {
+    ///     writer.write_record_buf(&record.unwrap()).unwrap();
+    /// }
+    /// writer.finish().unwrap();
+    /// let data: Arc<[u8]> = std::fs::read(&path).unwrap().into();
+    /// let expected: Vec<String> = (0..3000).map(|i| format!("r{}", i)).collect();
+    ///
+    /// let eio = || io::Error::from_raw_os_error(5);
+    /// let estale = || io::Error::from(io::ErrorKind::StaleNetworkFileHandle);
+    /// let open = |failures: Vec<(u64, io::Error)>, retries: u32, threads: usize| {
+    ///     let failures = Arc::new(Mutex::new(failures));
+    ///     let data = Arc::clone(&data);
+    ///     let opener = move || Ok(Flaky { data: Cursor::new(Arc::clone(&data)), failures: Arc::clone(&failures) });
+    ///     BamReaderOptions::new()
+    ///         .threads(NonZeroUsize::new(threads).unwrap())
+    ///         .retry(RetryPolicy::new(retries).with_base_delay(Duration::ZERO))
+    ///         .open_with("flaky.bam", opener)
+    ///         .unwrap()
+    /// };
+    /// let size = data.len() as u64;
+    /// for threads in [1, 3] {
+    ///     // 同一偏移连续失败两次，另有一处在文件靠后的位置失败
+    ///     let failures = vec![(size / 3, eio()), (size / 3, estale()), (size * 2 / 3, eio())];
+    ///     let mut reader = open(failures, 3, threads);
+    ///     let names: Vec<String> = reader.records().map(|r| r.unwrap().name().into_owned()).collect();
+    ///     assert_eq!(names, expected);
+    /// }
+    ///
+    /// // 重试用尽
+    /// let failures = vec![(size / 2, eio()), (size / 2, eio())];
+    /// let mut reader = open(failures, 1, 1);
+    /// let error = reader.records().find_map(Result::err).unwrap();
+    /// assert!(matches!(error, BamError::IoError(e) if e.raw_os_error() == Some(5)));
+    ///
+    /// // 不是暂时性错误：不重试
+    /// for threads in [1, 3] {
+    ///     let failures = vec![(size / 2, io::Error::from(io::ErrorKind::PermissionDenied))];
+    ///     let mut reader = open(failures, 3, threads);
+    ///     assert!(reader.records().find_map(Result::err).is_some());
+    /// }
+    /// std::fs::remove_file(&path).unwrap();
+    /// # }
+    /// ```
+    pub fn open_with<R, F>(&self, name: &str, opener: F) -> Result<BamReader, BamError>
+    where
+        R: Read + std::io::Seek + Send + Sync + 'static,
+        F: Fn() -> std::io::Result<R> + Send + Sync + 'static,
+    {
+        let opener: Opener = Arc::new(move || Ok(Box::new(opener()?) as Box<dyn RawInput>));
+        let mut head = Vec::new();
+        opener()?.take(4096).read_to_end(&mut head)?;
+        match check_sniffed(name, self.format, sniff_head(&head))? {
+            Format::Bam => {}
+            other => return Err(BamError::UnsupportedFormat(other)),
+        }
+        let reader = BamReader::open_bgzf(name.to_string(), opener, self)?;
+        self.check_sort_order(reader)
+    }
+
+    fn check_sort_order(&self, reader: BamReader) -> Result<BamReader, BamError> {
+        match self.sort_order {
+            Some(expected) => reader.require_sort_order(expected),
+            None => Ok(reader),
+        }
+    }
+}
+
+/// 头部@HD SO声明的排序方式，见[`BamReader::sort_order`]
+///
+/// # Examples
+///
+/// ```
+/// use bamqc_io::SortOrder;
+///
+/// let parse = |text: &str| SortOrder::from_header(&text.parse().unwrap());
+/// assert_eq!(parse("@HD\tVN:1.6\tSO:coordinate\n"), SortOrder::Coordinate);
+/// assert_eq!(parse("@HD\tVN:1.6\tSO:queryname\n"), SortOrder::QueryName);
+/// assert_eq!(parse("@HD\tVN:1.6\tSO:unsorted\n"), SortOrder::Unsorted);
+/// // 没有SO、SO:unknown或不认识的值
+/// assert_eq!(parse("@HD\tVN:1.6\n"), SortOrder::Unknown);
+/// assert_eq!(parse("@HD\tVN:1.6\tSO:unknown\n"), SortOrder::Unknown);
+/// assert_eq!(parse("@SQ\tSN:c1\tLN:10\n"), SortOrder::Unknown);
+/// assert_eq!(SortOrder::QueryName.to_string(), "queryname");
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SortOrder {
+    /// 按参考序列与位置排序
+    Coordinate,
+    /// 按读名排序
+    QueryName,
+    /// 声明为未排序
+    Unsorted,
+    /// 未声明或无法识别
+    #[default]
+    Unknown,
+}
+
+impl SortOrder {
+    /// 从头部@HD行的SO字段解析
+    pub fn from_header(header: &sam::Header) -> Self {
+        use noodles::sam::header::record::value::map::header::tag::SORT_ORDER;
+
+        let so = header.header().and_then(|hd| hd.other_fields().get(&SORT_ORDER));
+        match so.map(|so| so.as_ref() as &[u8]) {
+            Some(b"coordinate") => SortOrder::Coordinate,
+            Some(b"queryname") => SortOrder::QueryName,
+            Some(b"unsorted") => SortOrder::Unsorted,
+            _ => SortOrder::Unknown,
+        }
+    }
+}
+
+impl std::fmt::Display for SortOrder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SortOrder::Coordinate => write!(f, "coordinate"),
+            SortOrder::QueryName => write!(f, "queryname"),
+            SortOrder::Unsorted => write!(f, "unsorted"),
+            SortOrder::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+/// 头部@RG行中的读组信息，见[`BamReader::read_groups`]
+///
+/// 非UTF-8字节替换为U+FFFD（见[`decode_lossy`]）。
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ReadGroupInfo {
+    /// 读组ID（ID）
+    pub id: String,
+    /// 样本（SM）
+    pub sample: Option<String>,
+    /// 文库（LB）
+    pub library: Option<String>,
+    /// 测序平台（PL）
+    pub platform: Option<String>,
+    /// 平台单元（PU），通常为flowcell-lane-barcode
+    pub platform_unit: Option<String>,
+}
+
+/// BAM/CRAM文件读取器
+///
+/// 头部与索引解析后以`Arc`共享，[`BamReader::try_clone`]得到的读取器
+/// 无需重新解析头部。SAM文本与CRAM输入（`cram`特性）的记录重新编码为BAM记录，
+/// 见[`crate::cram`]；SAM文本没有索引，不支持区域查询。
+pub struct BamReader {
+    source: Source,
+    header: Arc<sam::Header>,
+    path: String,
+    /// 读取记录前是否做BGZF块级自检
+    verify: bool,
+    /// 通过自检时扫描到的BGZF块数，计入输入文件清单；尚未通过自检时为None
+    verified: Option<u64>,
+    /// 是否已迭代过记录（此后的遍历不一定从第一条记录开始）
+    iterated: bool,
+    /// 从第一条记录开始无错误地遍历到末尾时的记录数，计入输入文件清单
+    #[cfg_attr(not(feature = "manifest"), allow(dead_code))]
+    complete_records: Option<u64>,
+    /// [`BamReader::read_into`]的读取进度
+    pass: Option<ReadIntoPass>,
+}
+
+/// [`BamReader::read_into`]跨调用保留的状态，对应[`BamRecordIterator`]的同名字段
+#[derive(Clone, Copy)]
+struct ReadIntoPass {
+    count: u64,
+    /// 从第一条记录开始且尚未出错，读到末尾时登记记录数
+    complete_pass: bool,
+    done: bool,
+}
+
+/// 底层的格式读取器
+enum Source {
+    Bam {
+        reader: Reader<BgzfInput>,
+        /// 已加载或建立的索引及其来源
+        index: Option<(Arc<bai::Index>, IndexSource)>,
+        /// 第一条记录的虚拟偏移（紧随头部之后）
+        records_start: VirtualPosition,
+        /// BGZF解压线程数，重新打开时沿用
+        threads: NonZeroUsize,
+        /// 重新打开字节来源（克隆与重试时）
+        opener: Opener,
+        retry: RetryPolicy,
+        /// 原始字节的长度，无法定位到末尾时为None
+        size: Option<u64>,
+    },
+    /// 标准输入：只能顺序读一遍，首次迭代记录时取走共享的记录流
+    Stdin { reader: Option<StdinReader> },
+    /// SAM文本：没有索引，只能顺序读取
+    Sam(crate::sam_text::SamSource),
+    #[cfg(feature = "cram")]
+    Cram(crate::cram::CramSource),
+}
+
+impl std::fmt::Debug for BamReader {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BamReader")
+            .field("path", &self.path)
+            .field("header", &self.header)
+            .finish()
+    }
+}
+
+impl BamReader {
+    /// 从文件路径创建BAM读取器（自动识别格式），路径为`-`时读取标准输入
+    ///
+    /// BAM文件单线程解压，不重试、不做块级自检。需要其它配置时使用[`BamReaderOptions`]。
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self, BamError> {
+        BamReaderOptions::new().open(path)
+    }
+
+    /// 从文件路径创建读取器（自动识别格式），BAM文件用`threads`个工作线程并行解压BGZF块
+    ///
+    /// `threads`为1时与[`BamReader::from_path`]的单线程解压相同。记录、查询与
+    /// 虚拟偏移都与单线程解压一致；标准输入与CRAM输入忽略`threads`。
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bamqc_io::{BamReader, BamWriter, GenomicRegion, write_bai};
+    /// use noodles::sam;
+    /// use std::num::NonZeroUsize;
+    ///
+    /// let dir = std::env::temp_dir().join(format!("bamqc-threads-{}", std::process::id()));
+    /// std::fs::create_dir_all(&dir).unwrap();
+    /// // 记录足够多，跨越多个BGZF块
+    /// let mut text = String::from("@HD\tVN:1.6\tSO:coordinate\n@SQ\tSN:c1\tLN:100000\n");
+    /// for i in 0..3000 {
+    ///     text.push_str(&format!("r{}\t0\tc1\t{}\t60\t50M\t*\t0\t0\t{}\t{}\n", i, i * 30 + 1, "ACGT".repeat(25).get(..50).unwrap(), "I".repeat(50)));
+    /// }
+    /// let mut sam_reader = sam::io::Reader::new(text.as_bytes());
+    /// let header = sam_reader.read_header().unwrap();
+    /// let bam = dir.join("in.bam");
+    /// let mut writer = BamWriter::from_path(&bam, &header).unwrap();
+    /// for record in sam_reader.record_bufs(&header) {
+    ///     writer.write_record_buf(&record.unwrap()).unwrap();
+    /// }
+    /// writer.finish().unwrap();
+    /// write_bai(&bam).unwrap();
+    ///
+    /// let names = |reader: &mut BamReader| -> Vec<String> {
+    ///     reader.records().map(|r| r.unwrap().name().into_owned()).collect()
+    /// };
+    /// let mut single = BamReader::from_path(&bam).unwrap();
+    /// let mut threaded = BamReader::from_path_with_threads(&bam, NonZeroUsize::new(4).unwrap()).unwrap();
+    /// assert_eq!(names(&mut threaded), names(&mut single));
+    /// assert_eq!(names(&mut threaded.try_clone().unwrap()).len(), 3000);
+    ///
+    /// let region: GenomicRegion = "c1:45001-46000".parse().unwrap();
+    /// let query = |reader: &mut BamReader| -> Vec<String> {
+    ///     reader.query(&region).unwrap().map(|r| r.unwrap().name().into_owned()).collect()
+    /// };
+    /// let expected = query(&mut single);
+    /// assert!(!expected.is_empty());
+    /// assert_eq!(query(&mut threaded), expected);
+    /// std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    pub fn from_path_with_threads<P: AsRef<Path>>(path: P, threads: NonZeroUsize) -> Result<Self, BamError> {
+        BamReaderOptions { threads, ..Default::default() }.open(path)
+    }
+
+    /// 从文件路径创建读取器，`format`不为Auto时跳过自动识别，
+    /// 内容与指定格式不符时返回[`BamError::FormatMismatch`]
+    pub fn from_path_with_format<P: AsRef<Path>>(path: P, format: Format) -> Result<Self, BamError> {
+        BamReaderOptions { format, ..Default::default() }.open(path)
+    }
+
+    /// 从文件路径创建读取器（自动识别格式），CRAM用`reference`指定的FASTA解码
+    ///
+    /// `reference`为None时没有参考，参考序列压缩的CRAM无法解码；BAM输入忽略`reference`。未启用`cram`特性时CRAM输入返回
+    /// [`BamError::UnsupportedFormat`]。示例见[`CramWriter`](crate::CramWriter)。
+    pub fn from_path_with_reference<P: AsRef<Path>>(path: P, reference: Option<&Path>) -> Result<Self, BamError> {
+        BamReaderOptions { reference: reference.map(Path::to_path_buf), ..Default::default() }.open(path)
+    }
+
+    /// 从标准输入读取BAM，用于管道（如`samtools view -u ... | bamqc ... -i -`）
+    ///
+    /// 头部只解析一次：同一进程内多次打开标准输入得到的读取器共享头部，
+    /// 只读头部的读取器（如样本检查）不消耗记录。记录流只能读一遍，由第一个
+    /// 迭代记录的读取器取走，之后其它读取器迭代记录时产出
+    /// [`BamError::StdinUnsupported`]；[`BamReader::try_clone`]与
+    /// [`BamReader::query`]也不支持。只支持BGZF格式的BAM
+    /// （`samtools view -u`输出的未压缩BAM也是BGZF）。
+    pub fn from_stdin() -> Result<Self, BamError> {
+        Self::open_stdin(&BamReaderOptions::new())
+    }
+
+    fn open_stdin(options: &BamReaderOptions) -> Result<Self, BamError> {
+        let mut state = STDIN.lock().unwrap_or_else(|e| e.into_inner());
+        let header = match &state.header {
+            Some(header) => Arc::clone(header),
+            None => {
+                // 只查看标准输入的缓冲区，不消耗数据
+                let sniffed = sniff_head(std::io::stdin().lock().fill_buf()?);
+                let format = check_sniffed(STDIN_PATH, options.format, sniffed)?;
+                if format != Format::Bam {
+                    return Err(BamError::StdinUnsupported(format!("{}格式", format)));
+                }
+
+                let mut reader = Reader::new(std::io::stdin());
+                let header = Arc::new(reader.read_header().map_err(|e| BamError::BamError(e.to_string()))?);
+                count_header_parse();
+                info!("已从标准输入打开BAM");
+                state.header = Some(Arc::clone(&header));
+                state.reader = Some(reader);
+                header
+            }
+        };
+
+        Ok(Self {
+            source: Source::Stdin { reader: None },
+            header,
+            path: STDIN_PATH.to_string(),
+            verify: options.verify,
+            verified: None,
+            iterated: false,
+            complete_records: None,
+            pass: None,
+        })
+    }
+
+    fn open<P: AsRef<Path>>(path: P, options: &BamReaderOptions) -> Result<Self, BamError> {
+        if is_stdin(&path) {
+            return Self::open_stdin(options);
+        }
+        let path_str = path.as_ref().to_string_lossy().to_string();
+        
+        if !path.as_ref().exists() {
+            return Err(BamError::FileNotFound { path: path_str });
+        }
+
+        // 显式指定格式时不预先识别内容，直接按该格式打开；打开失败时才识别内容，
+        // 内容与指定格式不符则返回FormatMismatch
+        let explicit = options.format != Format::Auto;
+        let format = if explicit {
+            options.format
+        } else {
+            let format = resolve_format(&path, Format::Auto)?;
+            debug!("{}: 按内容识别为{}", path_str, format);
+            format
+        };
+        let mismatch = |e: BamError| if explicit { explicit_open_error(&path_str, format, e) } else { e };
+        let (source, header) = match format {
+            Format::Bam => {
+                if options.check_eof && !has_bgzf_eof(&path).map_err(mismatch)? {
+                    return Err(mismatch(BamError::Truncated { path: path_str.clone() }));
+                }
+                return Self::open_bgzf(path_str.clone(), file_opener(path.as_ref()), options).map_err(mismatch);
+            }
+            Format::Sam => {
+                let (mut source, header) = crate::sam_text::SamSource::open(path.as_ref()).map_err(mismatch)?;
+                // 没有头部行时，用读取器已缓冲的开头字节确认内容确实是SAM记录
+                if explicit && header.is_empty() {
+                    check_sniffed(&path_str, format, sniff_head(source.peek()?)).map_err(mismatch)?;
+                }
+                info!("已打开SAM文件: {}", path_str);
+                (Source::Sam(source), header)
+            }
+            #[cfg(feature = "cram")]
+            Format::Cram => {
+                let (source, header) =
+                    crate::cram::CramSource::open(path.as_ref(), options.reference.as_deref()).map_err(mismatch)?;
+                info!("已打开CRAM文件: {}", path_str);
+                (Source::Cram(source), header)
+            }
+            other => return Err(BamError::UnsupportedFormat(other)),
+        };
+
+        Ok(Self {
+            source,
+            header: Arc::new(header),
+            path: path_str,
+            verify: options.verify,
+            verified: None,
+            iterated: false,
+            complete_records: None,
+            pass: None,
+        })
+    }
+
+    fn open_bgzf(path: String, opener: Opener, options: &BamReaderOptions) -> Result<Self, BamError> {
+        let threads = options.threads;
+        let retry = options.retry;
+        let mut input = opener()?;
+        let size = input.seek(std::io::SeekFrom::End(0)).ok();
+        input.seek(std::io::SeekFrom::Start(0))?;
+        let mut reader = Reader::from(BgzfInput::new(input, threads));
+
+        // 读取头部信息
+        let header = reader.read_header().map_err(|e| BamError::BamError(e.to_string()))?;
+        count_header_parse();
+        let records_start = reader.get_ref().virtual_position();
+        info!("已打开BAM文件: {}", path);
+        Ok(Self {
+            source: Source::Bam { reader, index: None, records_start, threads, opener, retry, size },
+            header: Arc::new(header),
+            path,
+            verify: options.verify,
+            verified: None,
+            iterated: false,
+            complete_records: None,
+            pass: None,
+        })
+    }
+
+    /// 重新打开同一文件，得到一个独立的读取器
+    ///
+    /// 新读取器共享已解析的头部（以及已加载的索引），不会重新解析头部；
+    /// 文件句柄是新打开的，读取位置与原读取器互相独立，总是从第一条记录开始。
+    /// 适合按参考序列并行处理或多次遍历同一文件的场景。标准输入无法重新打开。
+    pub fn try_clone(&self) -> Result<Self, BamError> {
+        let source = match &self.source {
+            Source::Stdin { .. } => return Err(BamError::StdinUnsupported("重新打开".to_string())),
+            Source::Bam { index, records_start, threads, opener, retry, size, .. } => Source::Bam {
+                reader: BgzfInput::reopen(opener, *threads, *records_start)?,
+                index: index.clone(),
+                records_start: *records_start,
+                threads: *threads,
+                opener: Arc::clone(opener),
+                retry: *retry,
+                size: *size,
+            },
+            Source::Sam(source) => Source::Sam(source.try_clone(Path::new(&self.path))?),
+            #[cfg(feature = "cram")]
+            Source::Cram(source) => Source::Cram(source.try_clone(Path::new(&self.path))?),
+        };
+
+        Ok(Self {
+            source,
+            header: Arc::clone(&self.header),
+            path: self.path.clone(),
+            verify: self.verify,
+            verified: self.verified,
+            iterated: false,
+            complete_records: self.complete_records,
+            pass: None,
+        })
+    }
+
+    /// 启用或关闭BGZF块级自检
+    ///
+    /// 启用后，首次调用[`BamReader::records`]或[`BamReader::query`]时先用
+    /// [`verify_bgzf_file`](crate::bgzf::verify_bgzf_file)完整扫描一遍文件，
+    /// 发现CRC32或ISIZE不符的块时返回[`BamError::BgzfChecksum`]，不输出任何记录。
+    /// 自检需要额外解压一遍全部数据；区域查询也会扫描整个文件。
+    /// CRAM与SAM文本不是BGZF格式、标准输入无法回退重读，都不做自检。
+    /// 通过与否只记在读取器上（[`BamReader::try_clone`]沿用），重新打开的读取器
+    /// 会重新扫描，文件在同一路径被替换时不会沿用旧的结果。
+    /// 打开时即启用见[`BamReaderOptions::verify`]。
+    pub fn set_verify(&mut self, verify: bool) {
+        self.verify = verify;
+    }
+
+    fn ensure_verified(&mut self) -> Result<(), BamError> {
+        if !self.verify || self.verified.is_some() {
+            return Ok(());
+        }
+        if let Source::Bam { opener, .. } = &self.source {
+            let summary = crate::bgzf::verify_bgzf(opener()?)?;
+            info!("BGZF块级自检通过: {}（{} 个块）", self.path, summary.blocks);
+            self.verified = Some(summary.blocks);
+        }
+        Ok(())
+    }
+
+    /// 获取文件路径
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// 获取SAM头部信息
+    pub fn header(&self) -> &sam::Header {
+        &self.header
+    }
+
+    /// 当前读取位置的BGZF虚拟偏移（压缩偏移左移16位加块内偏移）
+    ///
+    /// 只有BGZF记录流有虚拟偏移：SAM文本与CRAM返回None，标准输入在开始读取记录前返回None。
+    /// 用[`BamReader::read_into`]逐条读取时，返回值即为下一条记录的起点。
+    pub fn virtual_position(&self) -> Option<u64> {
+        match &self.source {
+            Source::Bam { reader, .. } => Some(u64::from(reader.get_ref().virtual_position())),
+            Source::Stdin { reader } => reader.as_ref().map(|reader| u64::from(reader.get_ref().virtual_position())),
+            _ => None,
+        }
+    }
+
+    /// 当前读取位置的BGZF虚拟偏移，用作检查点
+    ///
+    /// 在两次读取之间调用（[`BamReader::read_into`]之间，或[`BamReader::records`]的
+    /// 迭代器释放之后），返回值是下一条记录的起点，之后可用[`BamReader::seek`]
+    /// 回到这里继续读取。SAM文本与CRAM没有虚拟偏移，返回[`BamError::Unseekable`]；
+    /// 标准输入在开始读取记录前返回[`BamError::StdinUnsupported`]。
+    pub fn tell(&self) -> Result<u64, BamError> {
+        match &self.source {
+            Source::Stdin { reader: None } => Err(BamError::StdinUnsupported("读取记录前获取虚拟偏移".to_string())),
+            Source::Bam { .. } | Source::Stdin { .. } => Ok(self.virtual_position().unwrap_or_default()),
+            Source::Sam(_) => Err(self.unseekable(Format::Sam)),
+            #[cfg(feature = "cram")]
+            Source::Cram(_) => Err(self.unseekable(Format::Cram)),
+        }
+    }
+
+    /// 定位到[`BamReader::tell`]返回的虚拟偏移，之后的读取从该处的记录开始
+    ///
+    /// 定位后先解析该处的记录头做检查：偏移落在头部之内、不在记录起点（例如
+    /// 记录中间或超出块的长度）时返回[`BamError::NotARecordBoundary`]，读取位置不变；
+    /// 文件末尾是合法的偏移，之后的读取直接结束。这项检查能发现绝大多数错误的偏移，
+    /// 但不能保证恰好通过检查的任意字节一定是记录起点，应只使用`tell`得到的偏移。
+    /// 定位后的遍历不从第一条记录开始，记录数不计入输入文件清单。
+    ///
+    /// 只支持BAM文件：标准输入返回[`BamError::StdinUnsupported`]，SAM文本与CRAM
+    /// 返回[`BamError::Unseekable`]。
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bamqc_io::{BamError, BamReader, BamRecord};
+    /// # use bamqc_io::BamWriter;
+    /// # use noodles::sam;
+    /// # let bam = std::env::temp_dir().join(format!("bamqc-seek-{}.bam", std::process::id()));
+    /// # let mut text = String::from("@HD\tVN:1.6\tSO:coordinate\n@SQ\tSN:c1\tLN:100000\n");
+    /// # for i in 0..100 {
+    /// #     text.push_str(&format!("r{}\t0\tc1\t{}\t60\t8M\t*\t0\t0\tACGTACGT\tIIIIIIII\n", i, i * 30 + 1));
+    /// # }
+    /// # let mut sam_reader = sam::io::Reader::new(text.as_bytes());
+    /// # let header = sam_reader.read_header().unwrap();
+    /// # let mut writer = BamWriter::from_path(&bam, &header).unwrap();
+    /// # for record in sam_reader.record_bufs(&header) {
+    /// #     writer.write_record_buf(&record.unwrap()).unwrap();
+    /// # }
+    /// # writer.finish().unwrap();
+    /// let mut reader = BamReader::from_path(&bam).unwrap();
+    /// let mut record = BamRecord::default();
+    /// for _ in 0..40 {
+    ///     reader.read_into(&mut record).unwrap();
+    /// }
+    /// let checkpoint = reader.tell().unwrap();
+    ///
+    /// // 另一个读取器从检查点继续
+    /// let mut resumed = BamReader::from_path(&bam).unwrap();
+    /// resumed.seek(checkpoint).unwrap();
+    /// let names: Vec<String> = resumed.records().map(|r| r.unwrap().name().into_owned()).collect();
+    /// assert_eq!(names.len(), 60);
+    /// assert_eq!(names[0], "r40");
+    ///
+    /// // 记录中间的偏移被拒绝，读取位置不变
+    /// let e = resumed.seek(checkpoint + 5).unwrap_err();
+    /// assert!(matches!(e, BamError::NotARecordBoundary { .. }), "{}", e);
+    /// assert!(matches!(resumed.seek(0), Err(BamError::NotARecordBoundary { .. })));
+    /// # std::fs::remove_file(&bam).unwrap();
+    /// ```
+    pub fn seek(&mut self, offset: u64) -> Result<(), BamError> {
+        let reference_count = self.reference_count();
+        let (reader, records_start) = match &mut self.source {
+            Source::Bam { reader, records_start, .. } => (reader, *records_start),
+            Source::Stdin { .. } => return Err(BamError::StdinUnsupported("定位".to_string())),
+            Source::Sam(_) => return Err(self.unseekable(Format::Sam)),
+            #[cfg(feature = "cram")]
+            Source::Cram(_) => return Err(self.unseekable(Format::Cram)),
+        };
+        let not_a_boundary = |reason: String| BamError::NotARecordBoundary { path: self.path.clone(), offset, reason };
+        let pos = VirtualPosition::from(offset);
+        if pos < records_start {
+            return Err(not_a_boundary("位于头部之内".to_string()));
+        }
+
+        let previous = reader.get_ref().virtual_position();
+        let checked = reader
+            .get_mut()
+            .seek_to_virtual_position(pos)
+            .map_err(|e| e.to_string())
+            .and_then(|_| check_record_start(reader.get_mut(), reference_count))
+            .and_then(|_| reader.get_mut().seek_to_virtual_position(pos).map_err(|e| e.to_string()));
+        if let Err(reason) = checked {
+            reader.get_mut().seek_to_virtual_position(previous)?;
+            return Err(not_a_boundary(reason));
+        }
+        self.pass = None;
+        self.iterated = true;
+        Ok(())
+    }
+
+    fn unseekable(&self, format: Format) -> BamError {
+        BamError::Unseekable { path: self.path.clone(), format }
+    }
+
+    /// 输入文件的字节数；标准输入没有长度，返回None
+    pub fn file_size(&self) -> Option<u64> {
+        match &self.source {
+            Source::Bam { size, .. } => *size,
+            Source::Stdin { .. } => None,
+            _ => std::fs::metadata(&self.path).ok().map(|metadata| metadata.len()),
+        }
+    }
+
+    /// 已读到的压缩字节占文件的比例（0到1），用于估计完成进度
+    ///
+    /// 由[`BamReader::virtual_position`]的压缩偏移与[`BamReader::file_size`]算出，
+    /// 两者任一缺失（标准输入、SAM文本、CRAM）时返回None。
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bamqc_io::{BamReader, BamRecord, BamWriter};
+    /// use noodles::sam;
+    ///
+    /// let bam = std::env::temp_dir().join(format!("bamqc-progress-fraction-{}.bam", std::process::id()));
+    /// let mut text = String::from("@HD\tVN:1.6\tSO:coordinate\n@SQ\tSN:c1\tLN:100000\n");
+    /// for i in 0..3000 {
+    ///     text.push_str(&format!("r{}\t0\tc1\t{}\t60\t8M\t*\t0\t0\tACGTACGT\tIIIIIIII\n", i, i * 30 + 1));
+    /// }
+    /// let mut sam_reader = sam::io::Reader::new(text.as_bytes());
+    /// let header = sam_reader.read_header().unwrap();
+    /// let mut writer = BamWriter::from_path(&bam, &header).unwrap();
+    /// for record in sam_reader.record_bufs(&header) {
+    ///     writer.write_record_buf(&record.unwrap()).unwrap();
+    /// }
+    /// writer.finish().unwrap();
+    ///
+    /// let mut reader = BamReader::from_path(&bam).unwrap();
+    /// assert_eq!(reader.file_size(), Some(std::fs::metadata(&bam).unwrap().len()));
+    /// let start = reader.progress_fraction().unwrap();
+    /// let mut record = BamRecord::default();
+    /// let mut fractions = Vec::new();
+    /// while reader.read_into(&mut record).unwrap() {
+    ///     fractions.push(reader.progress_fraction().unwrap());
+    /// }
+    /// assert!(start < 0.1);
+    /// assert!(fractions.windows(2).all(|w| w[0] <= w[1]));
+    /// // 末尾还有EOF标记块
+    /// assert!(*fractions.last().unwrap() > 0.9 && *fractions.last().unwrap() < 1.0);
+    ///
+    /// let mut reader = reader.try_clone().unwrap();
+    /// let mut records = reader.records();
+    /// records.by_ref().take(10).for_each(drop);
+    /// assert_eq!(records.records_read(), 10);
+    /// assert_eq!(records.count(), 2990);
+    /// std::fs::remove_file(&bam).unwrap();
+    /// ```
+    pub fn progress_fraction(&self) -> Option<f64> {
+        let size = self.file_size().filter(|&size| size > 0)?;
+        let compressed = VirtualPosition::from(self.virtual_position()?).compressed();
+        Some((compressed as f64 / size as f64).min(1.0))
+    }
+
+    /// 头部@HD SO声明的排序方式
+    pub fn sort_order(&self) -> SortOrder {
+        SortOrder::from_header(&self.header)
+    }
+
+    /// 头部声明的排序方式为`expected`时返回读取器本身，否则返回[`BamError::WrongSortOrder`]；
+    /// 只核对头部声明，不检查记录的实际顺序
+    pub fn require_sort_order(self, expected: SortOrder) -> Result<Self, BamError> {
+        let found = self.sort_order();
+        if found != expected {
+            return Err(BamError::WrongSortOrder { path: self.path, expected, found });
+        }
+        Ok(self)
+    }
+
+    /// 头部中的参考序列数
+    pub fn reference_count(&self) -> usize {
+        self.header.reference_sequences().len()
+    }
+
+    /// 参考序列ID（与[`BamRecord::tid`]相同，按头部@SQ顺序从0开始）对应的名称；
+    /// `tid`为-1（未比对）或越界时返回None
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bamqc_io::{BamReader, BamWriter};
+    ///
+    /// let bam = std::env::temp_dir().join(format!("bamqc-references-{}.bam", std::process::id()));
+    /// let header = "@HD\tVN:1.6\n@SQ\tSN:chr2\tLN:2000\n@SQ\tSN:chr1\tLN:1000\n".parse().unwrap();
+    /// BamWriter::from_path(&bam, &header).unwrap().finish().unwrap();
+    ///
+    /// let reader = BamReader::from_path(&bam).unwrap();
+    /// assert_eq!(reader.reference_count(), 2);
+    /// assert_eq!(reader.reference_name(1), Some("chr1"));
+    /// assert_eq!(reader.reference_length(0), Some(2000));
+    /// assert_eq!((reader.reference_name(-1), reader.reference_length(-1)), (None, None));
+    /// assert_eq!(reader.reference_name(2), None);
+    /// assert_eq!(reader.references().collect::<Vec<_>>(), [("chr2", 2000), ("chr1", 1000)]);
+    /// std::fs::remove_file(&bam).unwrap();
+    /// ```
+    pub fn reference_name(&self, tid: i32) -> Option<&str> {
+        let (name, _) = self.header.reference_sequences().get_index(usize::try_from(tid).ok()?)?;
+        std::str::from_utf8(name).ok()
+    }
+
+    /// 参考序列ID对应的长度，`tid`为-1或越界时返回None
+    pub fn reference_length(&self, tid: i32) -> Option<u64> {
+        let (_, map) = self.header.reference_sequences().get_index(usize::try_from(tid).ok()?)?;
+        Some(map.length().get() as u64)
+    }
+
+    /// 头部中的读组，按@RG顺序；没有@RG时为空
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bamqc_io::{BamReader, BamWriter, ReadGroupInfo};
+    ///
+    /// let dir = std::env::temp_dir().join(format!("bamqc-read-groups-{}", std::process::id()));
+    /// std::fs::create_dir_all(&dir).unwrap();
+    /// let write = |name: &str, text: &str| {
+    ///     let bam = dir.join(name);
+    ///     BamWriter::from_path(&bam, &text.parse().unwrap()).unwrap().finish().unwrap();
+    ///     BamReader::from_path(&bam).unwrap()
+    /// };
+    ///
+    /// let reader = write(
+    ///     "rg.bam",
+    ///     "@HD\tVN:1.6\n@RG\tID:L1\tSM:NA12878\tLB:lib1\tPL:ILLUMINA\tPU:FC1.1.ACGT\n@RG\tID:L2\tSM:NA12878\n@RG\tID:L3\n@RG\tID:L4\tSM:NA12891\n",
+    /// );
+    /// let groups = reader.read_groups();
+    /// assert_eq!(groups.len(), 4);
+    /// assert_eq!(
+    ///     groups[0],
+    ///     ReadGroupInfo {
+    ///         id: "L1".into(),
+    ///         sample: Some("NA12878".into()),
+    ///         library: Some("lib1".into()),
+    ///         platform: Some("ILLUMINA".into()),
+    ///         platform_unit: Some("FC1.1.ACGT".into()),
+    ///     }
+    /// );
+    /// assert_eq!(groups[2], ReadGroupInfo { id: "L3".into(), ..Default::default() });
+    /// assert_eq!(reader.sample_names(), ["NA12878", "NA12891"]);
+    ///
+    /// let reader = write("no_rg.bam", "@HD\tVN:1.6\n");
+    /// assert!(reader.read_groups().is_empty());
+    /// assert!(reader.sample_names().is_empty());
+    /// std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    pub fn read_groups(&self) -> Vec<ReadGroupInfo> {
+        use noodles::sam::header::record::value::map::read_group::tag::{LIBRARY, PLATFORM, PLATFORM_UNIT, SAMPLE};
+
+        self.header
+            .read_groups()
+            .iter()
+            .map(|(id, map)| {
+                let field = |tag| map.other_fields().get(&tag).map(|value| decode_lossy(value).into_owned());
+                ReadGroupInfo {
+                    id: decode_lossy(id).into_owned(),
+                    sample: field(SAMPLE),
+                    library: field(LIBRARY),
+                    platform: field(PLATFORM),
+                    platform_unit: field(PLATFORM_UNIT),
+                }
+            })
+            .collect()
+    }
+
+    /// 读组中声明的样本名（SM），按@RG顺序去重；缺少SM的读组不计入
+    pub fn sample_names(&self) -> Vec<String> {
+        let mut samples: Vec<String> = Vec::new();
+        for sample in self.read_groups().into_iter().filter_map(|group| group.sample) {
+            if !samples.contains(&sample) {
+                samples.push(sample);
+            }
+        }
+        samples
+    }
+
+    /// 按参考序列ID顺序迭代（名称, 长度）；名称不是UTF-8时为空字符串
+    pub fn references(&self) -> impl Iterator<Item = (&str, u64)> + '_ {
+        self.header
+            .reference_sequences()
+            .iter()
+            .map(|(name, map)| (std::str::from_utf8(name).unwrap_or_default(), map.length().get() as u64))
+    }
+
+    /// 实际打开的输入格式（标准输入为BAM）
+    pub fn input_format(&self) -> Format {
+        match &self.source {
+            Source::Bam { .. } | Source::Stdin { .. } => Format::Bam,
+            Source::Sam(_) => Format::Sam,
+            #[cfg(feature = "cram")]
+            Source::Cram(_) => Format::Cram,
+        }
+    }
+
+    /// 输入文件清单的构造器，已填好路径、大小、头部MD5等廉价字段，见[`crate::manifest`]
+    #[cfg(feature = "manifest")]
+    pub fn manifest(&self) -> Result<InputManifestBuilder, BamError> {
+        let first_record_offset = match &self.source {
+            Source::Bam { records_start, .. } => Some(u64::from(*records_start)),
+            _ => None,
+        };
+        let observed = Observed { records: self.complete_records, bgzf_blocks: self.verified };
+        InputManifestBuilder::new(&self.path, &self.input_format().to_string(), &self.header, first_record_offset, observed)
+    }
+
+    /// 迭代所有记录
+    ///
+    /// 启用了BGZF自检且自检失败时，迭代器只产出该错误。从第一条记录开始
+    /// 无错误地遍历到末尾时，记录数记在读取器上，计入它生成的输入文件清单（[`BamReader::manifest`]）。
+    /// 记录无法解码时产出[`BamError::RecordReadError`]，带有文件路径与出错记录的序号；
+    /// 出错时读取位置没有前进（例如`block_size`超过[`MAX_RECORD_SIZE`]）时迭代随之结束。
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bamqc_io::{BamError, BamReader};
+    ///
+    /// let sam = std::env::temp_dir().join(format!("bamqc-corrupt-{}.sam", std::process::id()));
+    /// let text = "@HD\tVN:1.6\n@SQ\tSN:c1\tLN:1000\n\
+    ///             r1\t0\tc1\t100\t60\t10M\t*\t0\t0\t*\t*\n\
+    ///             r2\t0\tc1\t200\t60\t10M\t*\t0\t0\t*\t*\n\
+    ///             r3\t0\tc1\tnot-a-position\t60\t10M\t*\t0\t0\t*\t*\n";
+    /// std::fs::write(&sam, text).unwrap();
+    ///
+    /// let mut reader = BamReader::from_path(&sam).unwrap();
+    /// let results: Vec<_> = reader.records().collect();
+    /// assert_eq!(results.len(), 3);
+    /// match &results[2] {
+    ///     Err(BamError::RecordReadError { path, record_index, .. }) => {
+    ///         assert_eq!((path.as_str(), *record_index), (sam.to_str().unwrap(), 3));
+    ///     }
+    ///     other => panic!("{:?}", other),
+    /// }
+    /// std::fs::remove_file(&sam).unwrap();
+    /// ```
+    pub fn records(&mut self) -> BamRecordIterator<'_> {
+        self.pass = None;
+        let mut pending = self.ensure_verified().err();
+        let from_start = match &self.source {
+            Source::Bam { reader, records_start, .. } => reader.get_ref().virtual_position() == *records_start,
+            _ => !self.iterated,
+        };
+        self.iterated = true;
+        let source = match &mut self.source {
+            Source::Bam { reader, threads, opener, retry, .. } => RecordSource::Bam(ResumingBam {
+                reader,
+                threads: *threads,
+                opener,
+                retry: *retry,
+                path: &self.path,
+            }),
+            Source::Stdin { reader } => {
+                if reader.is_none() {
+                    match take_stdin_reader() {
+                        Ok(taken) => *reader = Some(taken),
+                        Err(e) => pending = Some(e),
+                    }
+                }
+                RecordSource::Stdin(reader.as_mut())
+            }
+            Source::Sam(source) => RecordSource::Sam(Box::new(source.records(&self.header))),
+            #[cfg(feature = "cram")]
+            Source::Cram(source) => RecordSource::Cram(source.records(&self.header)),
+        };
+        BamRecordIterator {
+            source,
+            path: &self.path,
+            count: 0,
+            pending,
+            done: false,
+            complete_pass: from_start.then_some(&mut self.complete_records),
+        }
+    }
+
+    /// 迭代所有记录，按读名以概率`fraction`抽样（类似Picard DownsampleSam的概率抽样）
+    ///
+    /// 是否保留由读名与`seed`的哈希决定（见[`keeps_read_name`]），读对的两端以及
+    /// secondary、supplementary记录同进同出，按读对的统计不会被抽样拆散；相同种子
+    /// 在同一输入上得到相同的记录。`fraction`须在(0, 1]内，否则返回
+    /// [`BamError::InvalidSampleFraction`]；为1时保留全部记录。
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bamqc_io::{BamError, BamReader, BamWriter};
+    /// use noodles::sam::{self, alignment::RecordBuf};
+    ///
+    /// let bam = std::env::temp_dir().join(format!("bamqc-records-sampled-{}.bam", std::process::id()));
+    /// let header: sam::Header = "@HD\tVN:1.6\n@SQ\tSN:chr1\tLN:1000\n".parse().unwrap();
+    /// let mut writer = BamWriter::from_path(&bam, &header).unwrap();
+    /// for i in 0..1000 {
+    ///     // 每个读名两条记录
+    ///     for _ in 0..2 {
+    ///         writer.write_record_buf_with_name(&RecordBuf::default(), format!("pair{}", i).as_bytes()).unwrap();
+    ///     }
+    /// }
+    /// writer.finish().unwrap();
+    ///
+    /// let sample = |seed| -> Vec<String> {
+    ///     let mut reader = BamReader::from_path(&bam).unwrap();
+    ///     reader.records_sampled(0.1, seed).unwrap().map(|r| r.unwrap().name().into_owned()).collect()
+    /// };
+    /// let names = sample(7);
+    /// assert!((100..300).contains(&names.len()), "{}", names.len());
+    /// assert!(names.chunks(2).all(|pair| pair[0] == pair[1]));
+    /// assert_eq!(sample(7), names);
+    /// assert_ne!(sample(8), names);
+    ///
+    /// let mut reader = BamReader::from_path(&bam).unwrap();
+    /// assert_eq!(reader.records_sampled(1.0, 7).unwrap().count(), 2000);
+    /// for fraction in [0.0, -0.5, 1.5, f64::NAN] {
+    ///     assert!(matches!(reader.records_sampled(fraction, 7), Err(BamError::InvalidSampleFraction { .. })));
+    /// }
+    /// std::fs::remove_file(&bam).unwrap();
+    /// ```
+    pub fn records_sampled(&mut self, fraction: f64, seed: u64) -> Result<SampledRecords<'_>, BamError> {
+        check_sample_fraction(fraction)?;
+        Ok(SampledRecords { records: self.records(), fraction, seed })
+    }
+
+    /// 迭代全部未比对记录（0x4），记录数等于`samtools view -c -f 4`
+    ///
+    /// 包括没有位置的未比对记录（按坐标排序时位于文件末尾），也包括放在mate位置上的
+    /// 未比对记录。BAM有`.bai`索引且索引带有记录计数时，从第一条含未比对记录的
+    /// 参考序列开始扫描；所有未比对记录都没有位置时直接定位到最后一条参考序列的
+    /// 第一条记录，只扫描文件的末尾部分。没有索引或索引没有记录计数时（以及标准输入、
+    /// SAM文本、CRAM）从当前位置起全量扫描并过滤。两种方式产出相同的记录。
+    /// 索引存在但无法读取时返回错误。
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bamqc_io::{BamReader, BamWriter, write_bai};
+    /// use noodles::sam;
+    ///
+    /// let bam = std::env::temp_dir().join(format!("bamqc-unmapped-{}.bam", std::process::id()));
+    /// let mut text = String::from("@HD\tVN:1.6\tSO:coordinate\n@SQ\tSN:c1\tLN:100000\n@SQ\tSN:c2\tLN:100000\n");
+    /// for i in 0..2000 {
+    ///     text.push_str(&format!("m{}\t0\tc{}\t{}\t60\t8M\t*\t0\t0\tACGTACGT\tIIIIIIII\n", i, i / 1000 + 1, i % 1000 * 50 + 1));
+    /// }
+    /// for i in 0..30 {
+    ///     text.push_str(&format!("u{}\t4\t*\t0\t0\t*\t*\t0\t0\tACGTACGT\tIIIIIIII\n", i));
+    /// }
+    /// let mut sam_reader = sam::io::Reader::new(text.as_bytes());
+    /// let header = sam_reader.read_header().unwrap();
+    /// let mut writer = BamWriter::from_path(&bam, &header).unwrap();
+    /// for record in sam_reader.record_bufs(&header) {
+    ///     writer.write_record_buf(&record.unwrap()).unwrap();
+    /// }
+    /// writer.finish().unwrap();
+    ///
+    /// // 没有索引：全量扫描
+    /// let mut reader = BamReader::from_path(&bam).unwrap();
+    /// let scanned: Vec<String> = reader.unmapped_records().unwrap().map(|r| r.unwrap().name().into_owned()).collect();
+    /// assert_eq!(scanned.len(), 30);
+    ///
+    /// // 有索引：从末尾读取，结果相同
+    /// let bai = write_bai(&bam).unwrap();
+    /// let mut reader = BamReader::from_path(&bam).unwrap();
+    /// let mut unmapped = reader.unmapped_records().unwrap();
+    /// let indexed: Vec<String> = unmapped.by_ref().map(|r| r.unwrap().name().into_owned()).collect();
+    /// assert_eq!(indexed, scanned);
+    /// assert!(unmapped.records_read() < 1100, "{}", unmapped.records_read());
+    /// # std::fs::remove_file(&bam).unwrap();
+    /// # std::fs::remove_file(&bai).unwrap();
+    /// ```
+    pub fn unmapped_records(&mut self) -> Result<UnmappedRecords<'_>, BamError> {
+        self.ensure_verified()?;
+        if let Source::Bam { reader, index, records_start, .. } = &mut self.source {
+            if index.is_none() {
+                match load_index(&self.path) {
+                    Ok(loaded) => *index = Some(loaded),
+                    Err(BamError::IndexNotFound { .. }) => {}
+                    Err(e) => return Err(e),
+                }
+            }
+            if let Some((index, _)) = index {
+                let start = first_unmapped_start(index).unwrap_or(*records_start);
+                reader.get_mut().seek_to_virtual_position(start)?;
+            }
+        }
+        Ok(UnmappedRecords { records: self.records() })
+    }
+
+    /// 把下一条记录读入`record`并复用其缓冲区，读到末尾时返回false
+    ///
+    /// 与[`BamReader::records`]读取同一记录流，但BAM输入不再为每条记录分配内存，
+    /// 适合只需逐条查看记录的统计循环。读取进度在调用之间保留，读完后继续返回false；
+    /// 之后调用[`BamReader::records`]会丢弃该进度。从第一条记录开始无错误地
+    /// 读到末尾时，记录数同样计入输入文件清单。
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bamqc_io::{BamReader, BamRecord};
+    /// # use bamqc_io::BamWriter;
+    /// # use noodles::sam::{self, alignment::RecordBuf};
+    /// # use noodles::sam::header::record::value::{map::ReferenceSequence, Map};
+    /// # use std::num::NonZeroUsize;
+    /// # let path = std::env::temp_dir().join(format!("bamqc-read-into-{}.bam", std::process::id()));
+    /// # let header = sam::Header::builder()
+    /// #     .add_reference_sequence("chr1", Map::<ReferenceSequence>::new(NonZeroUsize::new(1000).unwrap()))
+    /// #     .build();
+    /// # let mut writer = BamWriter::from_path(&path, &header).unwrap();
+    /// # for i in 0..3 {
+    /// #     writer.write_record_buf_with_name(&RecordBuf::default(), format!("r{}", i).as_bytes()).unwrap();
+    /// # }
+    /// # writer.finish().unwrap();
+    /// let mut reader = BamReader::from_path(&path).unwrap();
+    /// let mut record = BamRecord::default();
+    /// let mut names = Vec::new();
+    /// while reader.read_into(&mut record).unwrap() {
+    ///     names.push(record.name().into_owned());
+    /// }
+    /// assert_eq!(names, ["r0", "r1", "r2"]);
+    /// assert!(!reader.read_into(&mut record).unwrap());
+    /// # std::fs::remove_file(&path).unwrap();
+    /// ```
+    pub fn read_into(&mut self, record: &mut BamRecord) -> Result<bool, BamError> {
+        let pass = self.pass.take();
+        if pass.is_some_and(|pass| pass.done) {
+            self.pass = pass;
+            return Ok(false);
+        }
+        let mut records = self.records();
+        if let Some(pass) = pass {
+            records.count = pass.count;
+            if !pass.complete_pass {
+                records.complete_pass = None;
+            }
+        }
+        let result = records.read_into(record);
+        let pass = ReadIntoPass {
+            count: records.count,
+            complete_pass: records.complete_pass.is_some(),
+            done: records.done || matches!(result, Ok(false)),
+        };
+        self.pass = Some(pass);
+        result
+    }
+
+    /// 按读名配对primary记录，两端都读到后产出`(先出现的记录, 后出现的记录)`
+    ///
+    /// 只有配对读（0x1）中非secondary、非supplementary且有读名的记录参与配对，
+    /// 其它记录被跳过。尚未读到mate的记录按读名缓存，按读名排序的输入只需缓存
+    /// 一条，按坐标排序的输入需要缓存跨度内的全部读对；用
+    /// [`BamRecordPairs::with_max_pending`]限制缓存的记录数。读完后用
+    /// [`BamRecordPairs::take_unpaired`]取出没有找到mate的记录。
+    ///
+    /// 适用于TLEN不可靠、需要由两端的实际位置计算插入片段的场合。
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bamqc_io::{BamError, BamReader, BamWriter};
+    /// use noodles::sam;
+    ///
+    /// let path = std::env::temp_dir().join(format!("bamqc-record-pairs-{}.bam", std::process::id()));
+    /// let text = "@HD\tVN:1.6\tSO:coordinate\n@SQ\tSN:c1\tLN:10000\n\
+    ///             a\t99\tc1\t100\t60\t50M\t=\t400\t0\t*\t*\n\
+    ///             b\t99\tc1\t150\t60\t50M\t=\t300\t0\t*\t*\n\
+    ///             a\t2147\tc1\t200\t60\t20M\t=\t100\t0\t*\t*\n\
+    ///             single\t0\tc1\t250\t60\t50M\t*\t0\t0\t*\t*\n\
+    ///             b\t147\tc1\t300\t60\t50M\t=\t150\t0\t*\t*\n\
+    ///             b\t403\tc1\t350\t0\t50M\t=\t150\t0\t*\t*\n\
+    ///             a\t147\tc1\t400\t60\t50M\t=\t100\t0\t*\t*\n\
+    ///             orphan\t73\tc1\t500\t60\t50M\t=\t500\t0\t*\t*\n";
+    /// let mut sam_reader = sam::io::Reader::new(text.as_bytes());
+    /// let header = sam_reader.read_header().unwrap();
+    /// let mut writer = BamWriter::from_path(&path, &header).unwrap();
+    /// for record in sam_reader.record_bufs(&header) {
+    ///     writer.write_record_buf(&record.unwrap()).unwrap();
+    /// }
+    /// writer.finish().unwrap();
+    ///
+    /// // supplementary（2147）与secondary（403）不参与配对，TLEN为0也不影响
+    /// let mut reader = BamReader::from_path(&path).unwrap();
+    /// let mut pairs = reader.record_pairs();
+    /// let spans: Vec<_> = pairs
+    ///     .by_ref()
+    ///     .map(|pair| {
+    ///         let (first, second) = pair.unwrap();
+    ///         assert_eq!(first.qname(), second.qname());
+    ///         (first.name().into_owned(), second.reference_end() - first.pos())
+    ///     })
+    ///     .collect();
+    /// assert_eq!(spans, [("b".to_string(), 200), ("a".to_string(), 350)]);
+    /// let unpaired: Vec<_> = pairs.take_unpaired().iter().map(|r| r.name().into_owned()).collect();
+    /// assert_eq!(unpaired, ["orphan"]);
+    ///
+    /// // 缓存上限：a与b同时等待mate时超过1条
+    /// let mut reader = BamReader::from_path(&path).unwrap();
+    /// let error = reader.record_pairs().with_max_pending(1).find_map(Result::err).unwrap();
+    /// assert!(matches!(error, BamError::PendingMatesExceeded { limit: 1, record_index: 2 }));
+    /// std::fs::remove_file(&path).unwrap();
+    /// ```
+    pub fn record_pairs(&mut self) -> BamRecordPairs<'_> {
+        BamRecordPairs {
+            records: self.records(),
+            pending: HashMap::new(),
+            max_pending: None,
+            index: 0,
+            done: false,
+        }
+    }
+
+    /// 是否有可用的索引：已加载或建立过，或者磁盘上有`.bai`（CRAM为`.crai`）文件
+    ///
+    /// 只检查文件是否存在，不读取索引；标准输入与SAM文本总是返回false。
+    pub fn has_index(&self) -> bool {
+        match &self.source {
+            Source::Bam { index, .. } => index.is_some() || bai_candidates(&self.path).iter().any(|path| path.exists()),
+            Source::Stdin { .. } | Source::Sam(_) => false,
+            #[cfg(feature = "cram")]
+            Source::Cram(source) => source.has_index(&self.path),
+        }
+    }
+
+    /// 确保区域查询有索引可用，返回索引的来源
+    ///
+    /// 已加载过索引时直接返回；否则读取磁盘上的`.bai`；都没有时顺序扫描整个BAM
+    /// 在内存中建立BAI索引，`write`为true时同时写到`<input>.bai`（之后的读取器可直接使用）。
+    /// 扫描不改变当前的读取位置。只有头部声明`SO:coordinate`的BAM能建立索引，
+    /// 否则返回[`BamError::WrongSortOrder`]；标准输入返回[`BamError::StdinUnsupported`]，
+    /// SAM文本返回[`BamError::RegionError`]。CRAM只读取已有的`.crai`，没有时返回
+    /// [`BamError::IndexNotFound`]。
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bamqc_io::{BamError, BamReader, BamWriter, GenomicRegion, IndexSource};
+    /// use noodles::sam;
+    ///
+    /// let dir = std::env::temp_dir().join(format!("bamqc-ensure-index-{}", std::process::id()));
+    /// std::fs::create_dir_all(&dir).unwrap();
+    /// let write = |name: &str, sort_order: &str| {
+    ///     let path = dir.join(name);
+    ///     let mut text = format!("@HD\tVN:1.6\tSO:{}\n@SQ\tSN:c1\tLN:100000\n", sort_order);
+    ///     for i in 0..100 {
+    ///         text.push_str(&format!("r{}\t0\tc1\t{}\t60\t8M\t*\t0\t0\tACGTACGT\tIIIIIIII\n", i, i * 30 + 1));
+    ///     }
+    ///     let mut sam_reader = sam::io::Reader::new(text.as_bytes());
+    ///     let header = sam_reader.read_header().unwrap();
+    ///     let mut writer = BamWriter::from_path(&path, &header).unwrap();
+    ///     for record in sam_reader.record_bufs(&header) {
+    ///         writer.write_record_buf(&record.unwrap()).unwrap();
+    ///     }
+    ///     writer.finish().unwrap();
+    ///     path
+    /// };
+    /// let bam = write("sorted.bam", "coordinate");
+    /// let region: GenomicRegion = "c1:301-600".parse().unwrap();
+    ///
+    /// // 只在内存中建立
+    /// let mut reader = BamReader::from_path(&bam).unwrap();
+    /// assert!(!reader.has_index());
+    /// assert_eq!(reader.ensure_index(false).unwrap(), IndexSource::Built);
+    /// assert!(reader.has_index());
+    /// assert_eq!(reader.query(&region).unwrap().count(), 10);
+    ///
+    /// // 写出`<input>.bai`，新的读取器读取该文件
+    /// let mut reader = BamReader::from_path(&bam).unwrap();
+    /// let bai = dir.join("sorted.bam.bai");
+    /// assert_eq!(reader.ensure_index(true).unwrap(), IndexSource::Written(bai.clone()));
+    /// let mut reader = BamReader::from_path(&bam).unwrap();
+    /// assert!(reader.has_index());
+    /// assert_eq!(reader.ensure_index(false).unwrap(), IndexSource::File(bai));
+    /// assert_eq!(reader.query(&region).unwrap().count(), 10);
+    ///
+    /// // 不是按坐标排序
+    /// let mut reader = BamReader::from_path(write("by-name.bam", "queryname")).unwrap();
+    /// assert!(matches!(reader.ensure_index(false), Err(BamError::WrongSortOrder { .. })));
+    /// assert!(!reader.has_index());
+    /// std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    pub fn ensure_index(&mut self, write: bool) -> Result<IndexSource, BamError> {
+        let index = match &mut self.source {
+            Source::Bam { index, .. } => index,
+            Source::Stdin { .. } => return Err(BamError::StdinUnsupported("建立索引".to_string())),
+            Source::Sam(_) => return Err(sam_query_error()),
+            #[cfg(feature = "cram")]
+            Source::Cram(source) => return source.ensure_index(&self.path).map(IndexSource::File),
+        };
+        if index.is_none() {
+            match load_index(&self.path) {
+                Ok(loaded) => *index = Some(loaded),
+                Err(BamError::IndexNotFound { .. }) => self.build_index()?,
+                Err(e) => return Err(e),
+            }
+        }
+        let Source::Bam { index: Some((index, source)), .. } = &mut self.source else {
+            unreachable!("索引已加载或建立");
+        };
+        if write && *source == IndexSource::Built {
+            let [path, _] = bai_candidates(&self.path);
+            bai::fs::write(&path, index)?;
+            info!("索引已写入{}", path.display());
+            *source = IndexSource::Written(path);
+        }
+        Ok(source.clone())
+    }
+
+    /// 每条参考序列的已比对/未比对记录数（与`samtools idxstats`相同），不扫描记录
+    ///
+    /// 计数取自BAI/CSI索引元数据伪bin：优先使用已加载或建立的索引（见
+    /// [`BamReader::ensure_index`]），否则读取磁盘上的索引文件，没有时返回
+    /// [`BamError::IndexNotFound`]。结果按头部顺序每条参考序列一行，最后是没有坐标的
+    /// 未比对记录（`*`行）。索引没有元数据伪bin（CRAM的`.crai`也没有计数）时改为
+    /// 扫描全部记录，各行的`exact`为false；扫描使用新打开的读取器，不改变当前的读取位置。
+    /// 标准输入返回[`BamError::StdinUnsupported`]，SAM文本返回[`BamError::RegionError`]。
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bamqc_io::{BamReader, BamWriter, ReferenceStats, write_bai};
+    /// use noodles::sam;
+    ///
+    /// let bam = std::env::temp_dir().join(format!("bamqc-index-stats-{}.bam", std::process::id()));
+    /// let text = "@HD\tVN:1.6\tSO:coordinate\n@SQ\tSN:c1\tLN:1000\n@SQ\tSN:c2\tLN:500\n\
+    ///             a\t0\tc1\t100\t60\t10M\t*\t0\t0\t*\t*\n\
+    ///             b\t0\tc1\t200\t60\t10M\t*\t0\t0\t*\t*\n\
+    ///             c\t4\tc1\t200\t0\t*\t*\t0\t0\t*\t*\n\
+    ///             d\t4\t*\t0\t0\t*\t*\t0\t0\t*\t*\n";
+    /// let mut sam_reader = sam::io::Reader::new(text.as_bytes());
+    /// let header = sam_reader.read_header().unwrap();
+    /// let mut writer = BamWriter::from_path(&bam, &header).unwrap();
+    /// for record in sam_reader.record_bufs(&header) {
+    ///     writer.write_record_buf(&record.unwrap()).unwrap();
+    /// }
+    /// writer.finish().unwrap();
+    /// let bai = write_bai(&bam).unwrap();
+    ///
+    /// let reader = BamReader::from_path(&bam).unwrap();
+    /// let rows: Vec<_> = reader
+    ///     .index_stats()
+    ///     .unwrap()
+    ///     .into_iter()
+    ///     .map(|ReferenceStats { name, length, mapped, unmapped, exact }| {
+    ///         assert!(exact);
+    ///         format!("{}\t{}\t{}\t{}", name, length, mapped, unmapped)
+    ///     })
+    ///     .collect();
+    /// assert_eq!(rows, ["c1\t1000\t2\t1", "c2\t500\t0\t0", "*\t0\t0\t1"]);
+    /// # std::fs::remove_file(&bam).unwrap();
+    /// # std::fs::remove_file(&bai).unwrap();
+    /// ```
+    pub fn index_stats(&self) -> Result<Vec<ReferenceStats>, BamError> {
+        let index = match &self.source {
+            Source::Stdin { .. } => return Err(BamError::StdinUnsupported("读取索引统计".to_string())),
+            Source::Sam(_) => return Err(sam_query_error()),
+            Source::Bam { index: Some((index, _)), .. } => Some(BamIndex::from_binning_index(index, IndexFormat::Bai)),
+            Source::Bam { .. } => {
+                let path = BamIndex::locate(&self.path).ok_or_else(|| BamError::IndexNotFound {
+                    path: bai_candidates(&self.path)[0].to_string_lossy().to_string(),
+                })?;
+                Some(BamIndex::open(path)?)
+            }
+            #[cfg(feature = "cram")]
+            Source::Cram(_) => None,
+        };
+        let mut rows: Vec<ReferenceStats> = self
+            .header
+            .reference_sequences()
+            .iter()
+            .map(|(name, map)| ReferenceStats {
+                name: decode_lossy(name).into_owned(),
+                length: map.length().get() as u64,
+                mapped: 0,
+                unmapped: 0,
+                exact: true,
+            })
+            .collect();
+        let mut unplaced = ReferenceStats { name: "*".to_string(), length: 0, mapped: 0, unmapped: 0, exact: true };
+
+        match index.filter(BamIndex::has_metadata) {
+            Some(index) => {
+                for (row, stats) in rows.iter_mut().zip(&index.references) {
+                    row.mapped = stats.mapped.unwrap_or(0);
+                    row.unmapped = stats.unmapped.unwrap_or(0);
+                }
+                unplaced.unmapped = index.unplaced_unmapped.unwrap_or(0);
+            }
+            None => {
+                info!("索引中没有记录计数，扫描全部记录: {}", self.path);
+                let mut reader = self.try_clone()?;
+                for result in reader.records() {
+                    let record = result?;
+                    match usize::try_from(record.tid()).ok().and_then(|tid| rows.get_mut(tid)) {
+                        Some(row) if record.is_unmapped() => row.unmapped += 1,
+                        Some(row) => row.mapped += 1,
+                        None => unplaced.unmapped += 1,
+                    }
+                }
+                for row in rows.iter_mut().chain([&mut unplaced]) {
+                    row.exact = false;
+                }
+            }
+        }
+        rows.push(unplaced);
+        Ok(rows)
+    }
+
+    /// 扫描BAM在内存中建立索引，替换已加载的索引
+    fn build_index(&mut self) -> Result<(), BamError> {
+        let sort_order = self.sort_order();
+        if sort_order != SortOrder::Coordinate {
+            return Err(BamError::WrongSortOrder { path: self.path.clone(), expected: SortOrder::Coordinate, found: sort_order });
+        }
+        let reference_count = self.reference_count();
+        match &mut self.source {
+            Source::Bam { index, opener, .. } => {
+                *index = Some((Arc::new(build_bai(opener, reference_count)?), IndexSource::Built));
+                info!("已为{}建立索引", self.path);
+                Ok(())
+            }
+            Source::Stdin { .. } => Err(BamError::StdinUnsupported("建立索引".to_string())),
+            _ => Err(sam_query_error()),
+        }
+    }
+
+    /// 迭代与指定区域重叠的记录（需要`.bai`或`.crai`索引，首次查询时加载；标准输入与SAM文本不支持）
+    pub fn query(&mut self, region: &GenomicRegion) -> Result<BamQueryIterator<'_>, BamError> {
+        self.ensure_verified()?;
+        let inner = match &mut self.source {
+            Source::Stdin { .. } => return Err(BamError::StdinUnsupported("区域查询".to_string())),
+            Source::Sam(_) => return Err(sam_query_error()),
+            Source::Bam { reader, index, .. } => {
+                if index.is_none() {
+                    *index = Some(load_index(&self.path)?);
+                }
+                let index = index.as_ref().map(|(index, _)| &**index).unwrap();
+
+                let region = region.to_noodles()?;
+                let query = reader
+                    .query(&self.header, index, &region)
+                    .map_err(|e| BamError::RegionError(e.to_string()))?;
+                QuerySource::Bam(query)
+            }
+            #[cfg(feature = "cram")]
+            Source::Cram(source) => QuerySource::Cram(source.query(&self.path, &self.header, region)?),
+        };
+
+        Ok(BamQueryIterator { inner, count: 0 })
+    }
+
+    /// 依次迭代BED文件中各区域内的记录（需要索引；标准输入不支持）
+    ///
+    /// BED至少3列。区域按头部中的参考序列顺序排序，重叠或相邻的区间先合并
+    /// （见[`plan_queries`]），再逐个区间查询；与多个区间重叠的记录只在第一个
+    /// 区间中产出一次。BED格式错误时返回包含行号的[`BamError::RegionError`]。
+    ///
+    /// BAM按索引给出的块逐条读取，不缓存记录；CRAM逐区间把记录读入内存后产出。
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bamqc_io::{BamReader, BamWriter, write_bai};
+    /// use noodles::sam;
+    ///
+    /// let dir = std::env::temp_dir().join(format!("bamqc-query-regions-{}", std::process::id()));
+    /// std::fs::create_dir_all(&dir).unwrap();
+    /// let text = "@HD\tVN:1.6\tSO:coordinate\n@SQ\tSN:c1\tLN:1000\n@SQ\tSN:c2\tLN:1000\n\
+    ///             a\t0\tc1\t100\t60\t50M\t*\t0\t0\t*\t*\n\
+    ///             b\t0\tc1\t180\t60\t50M\t*\t0\t0\t*\t*\n\
+    ///             c\t0\tc1\t500\t60\t50M\t*\t0\t0\t*\t*\n\
+    ///             d\t0\tc2\t10\t60\t50M\t*\t0\t0\t*\t*\n";
+    /// let mut sam_reader = sam::io::Reader::new(text.as_bytes());
+    /// let header = sam_reader.read_header().unwrap();
+    /// let bam = dir.join("in.bam");
+    /// let mut writer = BamWriter::from_path(&bam, &header).unwrap();
+    /// for record in sam_reader.record_bufs(&header) {
+    ///     writer.write_record_buf(&record.unwrap()).unwrap();
+    /// }
+    /// writer.finish().unwrap();
+    /// write_bai(&bam).unwrap();
+    ///
+    /// // 按头部顺序先查c1：前两个区间相邻（合并），第三个区间与b重叠，但b已经产出
+    /// let bed = dir.join("targets.bed");
+    /// std::fs::write(&bed, "c2\t0\t100\nc1\t120\t150\nc1\t150\t200\nc1\t220\t240\n").unwrap();
+    /// let mut reader = BamReader::from_path(&bam).unwrap();
+    /// let names: Vec<_> = reader.query_regions(&bed).unwrap().map(|r| r.unwrap().name().into_owned()).collect();
+    /// assert_eq!(names, ["a", "b", "d"]);
+    ///
+    /// std::fs::write(&bed, "c1\t0\t100\nc1\tx\t200\n").unwrap();
+    /// let e = reader.query_regions(&bed).err().unwrap();
+    /// assert!(e.to_string().ends_with("targets.bed:2: c1\tx\t200"), "{}", e);
+    /// std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    pub fn query_regions<P: AsRef<Path>>(&mut self, bed_path: P) -> Result<BamRegionsIterator<'_>, BamError> {
+        let regions = read_bed(bed_path)?;
+        match self.source {
+            Source::Stdin { .. } => return Err(BamError::StdinUnsupported("区域查询".to_string())),
+            Source::Sam(_) => return Err(sam_query_error()),
+            _ => {}
+        }
+        self.ensure_verified()?;
+        let queries = plan_queries(&regions, &self.header)?;
+        if let Source::Bam { index, .. } = &mut self.source {
+            if index.is_none() {
+                *index = Some(load_index(&self.path)?);
+            }
+        }
+
+        Ok(BamRegionsIterator {
+            reader: self,
+            queries: queries.into_iter(),
+            current: None,
+            previous: None,
+            count: 0,
+            done: false,
+        })
+    }
+}
+
+/// [`BamReader::query_regions`]返回的迭代器
+pub struct BamRegionsIterator<'a> {
+    reader: &'a mut BamReader,
+    queries: std::vec::IntoIter<PlannedQuery>,
+    current: Option<RegionCursor>,
+    /// 上一个区间的参考序列编号与终点（1-based，含），起点不超过它的记录已经产出
+    previous: Option<(i32, u64)>,
+    count: u64,
+    done: bool,
+}
+
+/// 当前区间的读取状态
+struct RegionCursor {
+    tid: i32,
+    /// 区间起点（1-based，含）
+    start: u64,
+    /// 区间终点（1-based，含）
+    end: u64,
+    /// BAM：尚未读取的索引块
+    chunks: std::vec::IntoIter<Chunk>,
+    /// BAM：正在读取的块的终点
+    chunk_end: Option<VirtualPosition>,
+    /// CRAM：该区间的全部记录
+    buffered: VecDeque<bam::Record>,
+}
+
+impl RegionCursor {
+    fn overlaps(&self, record: &BamRecord) -> bool {
+        let start = record.pos() + 1;
+        record.tid() == self.tid && start > 0 && start as u64 <= self.end && record.reference_end() as u64 >= self.start
+    }
+}
+
+impl BamRegionsIterator<'_> {
+    fn open(&mut self, query: &PlannedQuery) -> Result<RegionCursor, BamError> {
+        let region = query.region.to_noodles()?;
+        let tid = self
+            .reader
+            .header
+            .reference_sequences()
+            .get_index_of(query.region.name.as_bytes())
+            .ok_or_else(|| BamError::RegionError(format!("参考序列不存在: {}", query.region.name)))?;
+        let mut cursor = RegionCursor {
+            tid: tid as i32,
+            start: query.region.start as u64,
+            end: query.region.end.map_or(u64::MAX, |end| end as u64),
+            chunks: Vec::new().into_iter(),
+            chunk_end: None,
+            buffered: VecDeque::new(),
+        };
+        match &mut self.reader.source {
+            Source::Bam { index, .. } => {
+                let index = index.as_ref().map(|(index, _)| &**index).ok_or_else(|| BamError::IndexNotFound { path: self.reader.path.clone() })?;
+                cursor.chunks = index
+                    .query(tid, region.interval())
+                    .map_err(|e| BamError::RegionError(e.to_string()))?
+                    .into_iter();
+            }
+            Source::Stdin { .. } => return Err(BamError::StdinUnsupported("区域查询".to_string())),
+            Source::Sam(_) => return Err(sam_query_error()),
+            #[cfg(feature = "cram")]
+            Source::Cram(source) => {
+                cursor.buffered = source
+                    .query(&self.reader.path, &self.reader.header, &query.region)?
+                    .collect::<Result<_, _>>()?;
+            }
+        }
+        Ok(cursor)
+    }
+
+    /// 当前区间的下一条重叠记录，区间读完时返回None
+    fn next_in_region(&mut self) -> Result<Option<BamRecord>, BamError> {
+        let Some(cursor) = self.current.as_mut() else {
+            return Ok(None);
+        };
+        let Source::Bam { reader, .. } = &mut self.reader.source else {
+            return Ok(cursor.buffered.pop_front().map(|inner| BamRecord { inner }));
+        };
+        loop {
+            if let Some(end) = cursor.chunk_end {
+                if reader.get_ref().virtual_position() < end {
+                    let mut inner = bam::Record::default();
+                    if reader.read_record(&mut inner)? == 0 {
+                        return Ok(None);
+                    }
+                    let record = BamRecord { inner };
+                    if cursor.overlaps(&record) {
+                        return Ok(Some(record));
+                    }
+                    continue;
+                }
+            }
+            let Some(chunk) = cursor.chunks.next() else {
+                return Ok(None);
+            };
+            reader.get_mut().seek_to_virtual_position(chunk.start())?;
+            cursor.chunk_end = Some(chunk.end());
+        }
+    }
+}
+
+impl Iterator for BamRegionsIterator<'_> {
+    type Item = Result<BamRecord, BamError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            if self.current.is_none() {
+                let query = self.queries.next()?;
+                match self.open(&query) {
+                    Ok(cursor) => self.current = Some(cursor),
+                    Err(e) => {
+                        self.done = true;
+                        return Some(Err(e));
+                    }
+                }
+            }
+            match self.next_in_region() {
+                Ok(Some(record)) => {
+                    let seen = self
+                        .previous
+                        .is_some_and(|(tid, end)| record.tid() == tid && (record.pos() + 1) as u64 <= end);
+                    if seen {
+                        continue;
+                    }
+                    self.count += 1;
+                    READ_PROGRESS.update(self.count, 0);
+                    return Some(Ok(record));
+                }
+                Ok(None) => {
+                    let cursor = self.current.take()?;
+                    self.previous = Some((cursor.tid, cursor.end));
+                }
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+        None
+    }
+}
+
+/// [`BamReader::ensure_index`]得到的索引从何而来
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum IndexSource {
+    /// 读取了已有的索引文件
+    File(PathBuf),
+    /// 扫描BAM在内存中建立，没有写出
+    Built,
+    /// 扫描BAM建立并写到了该路径
+    Written(PathBuf),
+}
+
+/// BAM索引的候选路径：`<path>.bai`与把扩展名替换为`.bai`
+fn bai_candidates(bam_path: &str) -> [PathBuf; 2] {
+    [PathBuf::from(format!("{}.bai", bam_path)), Path::new(bam_path).with_extension("bai")]
+}
+
+/// 查找并读取BAM索引，依次尝试[`bai_candidates`]
+fn load_index(bam_path: &str) -> Result<(Arc<bai::Index>, IndexSource), BamError> {
+    let candidates = bai_candidates(bam_path);
+    for candidate in &candidates {
+        if candidate.exists() {
+            return Ok((Arc::new(bai::fs::read(candidate)?), IndexSource::File(candidate.clone())));
+        }
+    }
+
+    Err(BamError::IndexNotFound { path: candidates[0].to_string_lossy().to_string() })
+}
+
+/// 顺序读取BAM的全部记录建立BAI索引（与`samtools index`相同）
+fn build_bai(opener: &Opener, reference_count: usize) -> Result<bai::Index, BamError> {
+    let mut reader = Reader::from(BgzfReader::new(opener()?));
+    reader.read_header()?;
+    let mut indexer = Indexer::default();
+    let mut record = bam::Record::default();
+    let mut start = reader.get_ref().virtual_position();
+    while reader.read_record(&mut record)? != 0 {
+        let end = reader.get_ref().virtual_position();
+        let context = match (
+            record.reference_sequence_id().transpose()?,
+            record.alignment_start().transpose()?,
+            sam::alignment::Record::alignment_end(&record).transpose()?,
+        ) {
+            (Some(id), Some(alignment_start), Some(alignment_end)) => {
+                Some((id, alignment_start, alignment_end, !record.flags().is_unmapped()))
+            }
+            _ => None,
+        };
+        indexer.add_record(context, Chunk::new(start, end))?;
+        start = end;
+    }
+    Ok(indexer.build(reference_count))
+}
+
+/// BAM文件写出器
+pub struct BamWriter {
+    writer: bam::io::Writer<noodles::bgzf::io::Writer<File>>,
+    header: sam::Header,
+    path: String,
+}
+
+impl BamWriter {
+    /// 创建文件并写入头部
+    pub fn from_path<P: AsRef<Path>>(path: P, header: &sam::Header) -> Result<Self, BamError> {
+        let path_str = path.as_ref().to_string_lossy().to_string();
+        let mut writer = bam::io::Writer::new(File::create(&path)?);
+        writer.write_header(header)?;
+
+        Ok(Self {
+            writer,
+            header: header.clone(),
+            path: path_str,
+        })
+    }
+
+    /// 创建文件并写入原样的头部文本
+    ///
+    /// noodles会拒绝含非ASCII等字节的头部字段；构造这类异常输入时用`text`
+    /// 作为头部文本原样写出，参考序列列表取自`header`（文本中的@SQ应与之一致）。
+    pub fn from_path_with_header_text<P: AsRef<Path>>(
+        path: P,
+        header: &sam::Header,
+        text: &[u8],
+    ) -> Result<Self, BamError> {
+        use std::io::Write as _;
+
+        let path_str = path.as_ref().to_string_lossy().to_string();
+        let mut writer = bam::io::Writer::new(File::create(&path)?);
+        let too_long = |e| BamError::BamError(format!("头部过大: {}", e));
+
+        let mut buf = b"BAM\x01".to_vec();
+        buf.extend_from_slice(&u32::try_from(text.len()).map_err(too_long)?.to_le_bytes());
+        buf.extend_from_slice(text);
+        let references = header.reference_sequences();
+        buf.extend_from_slice(&u32::try_from(references.len()).map_err(too_long)?.to_le_bytes());
+        for (name, map) in references {
+            buf.extend_from_slice(&u32::try_from(name.len() + 1).map_err(too_long)?.to_le_bytes());
+            buf.extend_from_slice(name);
+            buf.push(0);
+            buf.extend_from_slice(&u32::try_from(map.length().get()).map_err(too_long)?.to_le_bytes());
+        }
+        writer.get_mut().write_all(&buf)?;
+
+        Ok(Self {
+            writer,
+            header: header.clone(),
+            path: path_str,
+        })
+    }
+
+    /// 写出一条记录
+    pub fn write_record_buf(&mut self, record: &sam::alignment::RecordBuf) -> Result<(), BamError> {
+        use noodles::sam::alignment::io::Write as _;
+
+        self.writer.write_alignment_record(&self.header, record)?;
+        Ok(())
+    }
+
+    /// 写出一条记录，读名使用原始字节`name`
+    ///
+    /// noodles只接受可打印ASCII的读名；构造含非ASCII等字节的异常输入时，先以
+    /// 等长的占位读名编码，再把读名字节替换为`name`。`name`长度须为1到254且不含NUL。
+    pub fn write_record_buf_with_name(
+        &mut self,
+        record: &sam::alignment::RecordBuf,
+        name: &[u8],
+    ) -> Result<(), BamError> {
+        use noodles::sam::alignment::io::Write as _;
+        use std::io::Write as _;
+
+        // block_size与固定长度字段之后即为读名
+        const NAME_OFFSET: usize = 36;
+
+        if !(1..=254).contains(&name.len()) || name.contains(&0) {
+            return Err(BamError::BamError(format!("无效的读名字节: {:?}", name)));
+        }
+        let mut record = record.clone();
+        *record.name_mut() = Some(vec![b'N'; name.len()].into());
+
+        let mut encoder = bam::io::Writer::from(Vec::new());
+        encoder.write_alignment_record(&self.header, &record)?;
+        let mut buf = encoder.into_inner();
+        buf[NAME_OFFSET..NAME_OFFSET + name.len()].copy_from_slice(name);
+        self.writer.get_mut().write_all(&buf)?;
+        Ok(())
+    }
+
+    /// 原样写出从[`BamReader`]读到的一条记录
+    ///
+    /// 各字段（FLAG、坐标、TLEN、CIGAR、序列、质量与标签）不做任何修改；noodles
+    /// 拒绝的读名（非ASCII字节或`@`，读取时照常接受）按
+    /// [`BamWriter::write_record_buf_with_name`]写出原始字节。头部应与来源
+    /// [`BamReader::header`]一致，参考序列编号才有相同的含义。
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bamqc_io::{BamReader, BamWriter};
+    /// use noodles::sam::{self, alignment::RecordBuf};
+    ///
+    /// let dir = std::env::temp_dir();
+    /// let source = dir.join(format!("bamqc-write-record-src-{}.bam", std::process::id()));
+    /// let copy = dir.join(format!("bamqc-write-record-copy-{}.bam", std::process::id()));
+    /// let text = "@HD\tVN:1.6\tSO:coordinate\n@SQ\tSN:c1\tLN:1000\n@SQ\tSN:c2\tLN:800\n\
+    ///             r1\t99\tc1\t100\t60\t4M\t=\t200\t104\tACGT\tIIII\tRG:Z:g1\tNM:i:1\n\
+    ///             r1\t147\tc1\t200\t60\t4M\t=\t100\t-104\tACGT\tIIII\n\
+    ///             r2\t1153\tc1\t300\t3\t2S2M\tc2\t50\t0\tACGT\t*\n\
+    ///             r3\t2129\tc2\t50\t0\t2M2H\t=\t60\t-15\tAC\t##\n\
+    ///             r4\t77\t*\t0\t0\t*\t*\t0\t0\tACGT\t*\n";
+    /// let mut sam_reader = sam::io::Reader::new(text.as_bytes());
+    /// let header = sam_reader.read_header().unwrap();
+    /// let mut writer = BamWriter::from_path(&source, &header).unwrap();
+    /// for record in sam_reader.record_bufs(&header) {
+    ///     writer.write_record_buf(&record.unwrap()).unwrap();
+    /// }
+    /// writer.write_record_buf_with_name(&RecordBuf::default(), b"caf\xe9@1").unwrap();
+    /// writer.finish().unwrap();
+    ///
+    /// // 读取 -> 写出 -> 再读取
+    /// let mut reader = BamReader::from_path(&source).unwrap();
+    /// let mut writer = BamWriter::from_path(&copy, reader.header()).unwrap();
+    /// let original: Vec<_> = reader.records().map(Result::unwrap).collect();
+    /// for record in &original {
+    ///     writer.write_record(record).unwrap();
+    /// }
+    /// writer.finish().unwrap();
+    ///
+    /// let mut reader = BamReader::from_path(&copy).unwrap();
+    /// let header = reader.header().clone();
+    /// let copied: Vec<_> = reader.records().map(Result::unwrap).collect();
+    /// assert_eq!(copied.len(), 6);
+    /// for (a, b) in original.iter().zip(&copied) {
+    ///     assert_eq!(a.flags_raw(), b.flags_raw());
+    ///     assert_eq!(a.insert_size(), b.insert_size());
+    ///     assert_eq!((a.tid(), a.mtid()), (b.tid(), b.mtid()));
+    ///     assert_eq!(a.qname(), b.qname());
+    /// }
+    /// // SAM文本不能表示最后一条的读名
+    /// for (a, b) in original[..5].iter().zip(&copied) {
+    ///     assert_eq!(a.to_sam_string(&header).unwrap(), b.to_sam_string(&header).unwrap());
+    /// }
+    /// assert_eq!(copied[5].qname(), b"caf\xe9@1");
+    /// assert_eq!(std::fs::read(&source).unwrap(), std::fs::read(&copy).unwrap());
+    /// std::fs::remove_file(&source).unwrap();
+    /// std::fs::remove_file(&copy).unwrap();
+    /// ```
+    pub fn write_record(&mut self, record: &BamRecord) -> Result<(), BamError> {
+        let name = record.qname();
+        let valid_name = name.is_empty() || (name.len() <= 254 && name != b"*" && name.iter().all(|&b| b.is_ascii_graphic() && b != b'@'));
+        if valid_name {
+            self.writer.write_record(&self.header, &record.inner)?;
+            return Ok(());
+        }
+
+        let buf = sam::alignment::RecordBuf::try_from_alignment_record(&self.header, &record.inner)?;
+        self.write_record_buf_with_name(&buf, name)
+    }
+
+    /// 写出BGZF EOF标记块并关闭文件
+    pub fn finish(mut self) -> Result<(), BamError> {
+        self.writer.try_finish()?;
+        info!("已写出BAM文件: {}", self.path);
+        Ok(())
+    }
+}
+
+/// 为按坐标排序的BAM建立BAI索引，写到`<bam>.bai`并返回索引路径
+///
+/// 已有的索引文件被覆盖；不是按坐标排序时返回[`BamError::WrongSortOrder`]，
+/// 见[`BamReader::ensure_index`]。
+pub fn write_bai<P: AsRef<Path>>(bam_path: P) -> Result<std::path::PathBuf, BamError> {
+    let mut reader = BamReader::from_path(bam_path)?;
+    reader.build_index()?;
+    match reader.ensure_index(true)? {
+        IndexSource::Written(index_path) => Ok(index_path),
+        _ => unreachable!("新建立的索引总是写出"),
+    }
+}
+
+/// 区域查询记录迭代器
+pub struct BamQueryIterator<'a> {
+    inner: QuerySource<'a>,
+    count: u64,
+}
+
+enum QuerySource<'a> {
+    Bam(Query<'a, BgzfInput>),
+    #[cfg(feature = "cram")]
+    Cram(crate::cram::CramQuery<'a>),
+}
+
+impl<'a> Iterator for BamQueryIterator<'a> {
+    type Item = Result<BamRecord, BamError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let record = match &mut self.inner {
+            QuerySource::Bam(query) => query.next()?.map_err(|e| BamError::BamError(e.to_string())),
+            #[cfg(feature = "cram")]
+            QuerySource::Cram(query) => query.next()?,
+        };
+        match record {
+            Ok(record) => {
+                self.count += 1;
+                // 查询迭代器不暴露底层偏移，以记录数作为进度
+                READ_PROGRESS.update(self.count, 0);
+                Some(Ok(BamRecord { inner: record }))
+            }
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+/// BAM记录迭代器
+pub struct BamRecordIterator<'a> {
+    source: RecordSource<'a>,
+    /// 输入路径，用于解码错误的位置信息
+    path: &'a str,
+    count: u64,
+    /// 迭代开始前发生的错误（例如BGZF自检失败），产出后迭代结束
+    pending: Option<BamError>,
+    done: bool,
+    /// 从第一条记录开始遍历时指向读取器上的完整遍历记录数，遍历无错误地结束后写入
+    complete_pass: Option<&'a mut Option<u64>>,
+}
+
+enum RecordSource<'a> {
+    Bam(ResumingBam<'a>),
+    /// 记录流已被其它读取器取走时为None
+    Stdin(Option<&'a mut StdinReader>),
+    Sam(Box<crate::sam_text::SamRecords<'a>>),
+    #[cfg(feature = "cram")]
+    Cram(crate::cram::CramRecords<'a>),
+}
+
+impl BamRecordIterator<'_> {
+    /// 迭代器已读出的记录数
+    pub fn records_read(&self) -> u64 {
+        self.count
+    }
+
+    /// 把下一条记录读入`record`，复用其缓冲区；结束时返回false
+    ///
+    /// BAM输入不再为每条记录分配内存，见[`BamReader::read_into`]。
+    pub fn read_into(&mut self, record: &mut BamRecord) -> Result<bool, BamError> {
+        if self.done {
+            return Ok(false);
+        }
+        if let Some(e) = self.pending.take() {
+            self.done = true;
+            return Err(e);
+        }
+
+        let result = match &mut self.source {
+            RecordSource::Bam(bam) => {
+                let start = bam.reader.get_ref().virtual_position();
+                let result = bam.read_into(&mut record.inner, &mut self.count);
+                // 读取位置没有前进的错误（例如记录长度异常）再读只会重复出现
+                self.done = result.is_err() && bam.reader.get_ref().virtual_position() == start;
+                result
+            }
+            RecordSource::Stdin(Some(reader)) => {
+                let start = reader.get_ref().virtual_position();
+                let result = read_bam_record(reader, &mut record.inner, &mut self.count);
+                self.done = result.is_err() && reader.get_ref().virtual_position() == start;
+                result
+            }
+            RecordSource::Stdin(None) => Ok(false),
+            RecordSource::Sam(records) => match records.next() {
+                None => Ok(false),
+                Some(next) => next.map(|inner| {
+                    self.count += 1;
+                    // SAM文本按行解析，以记录数作为进度
+                    READ_PROGRESS.update(self.count, 0);
+                    record.inner = inner;
+                    true
+                }),
+            },
+            #[cfg(feature = "cram")]
+            RecordSource::Cram(records) => match records.next() {
+                None => Ok(false),
+                Some(Ok(inner)) => {
+                    self.count += 1;
+                    // CRAM不暴露底层偏移，以记录数作为进度
+                    READ_PROGRESS.update(self.count, 0);
+                    record.inner = inner;
+                    Ok(true)
+                }
+                Some(Err(e)) => {
+                    // 容器解码失败后无法定位下一条记录
+                    self.done = true;
+                    Err(e)
+                }
+            },
+        };
+        match &result {
+            Ok(true) => {}
+            Err(_) => self.complete_pass = None,
+            Ok(false) => {
+                if let Some(records) = self.complete_pass.take() {
+                    *records = Some(self.count);
+                }
+            }
+        }
+        result.map_err(|e| match e {
+            // 解码错误本身没有位置，加上出错记录的序号（从1开始）
+            BamError::BamError(reason) | BamError::CramError(reason) => {
+                BamError::RecordReadError { path: self.path.to_string(), record_index: self.count + 1, reason }
+            }
+            e => e,
+        })
+    }
+}
+
+impl Iterator for BamRecordIterator<'_> {
+    type Item = Result<BamRecord, BamError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut record = BamRecord { inner: bam::Record::default() };
+        match self.read_into(&mut record) {
+            Ok(true) => Some(Ok(record)),
+            Ok(false) => None,
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+/// 按读名抽样的记录迭代器，见[`BamReader::records_sampled`]
+pub struct SampledRecords<'a> {
+    records: BamRecordIterator<'a>,
+    fraction: f64,
+    seed: u64,
 }
 
-/// BAM/CRAM文件读取器
-pub struct BamReader {
-    reader: Reader<BgzfReader<File>>,
-    header: sam::Header,
-    path: String,
+impl SampledRecords<'_> {
+    /// 已读取的记录数（包括未被抽中的记录）
+    pub fn records_read(&self) -> u64 {
+        self.records.records_read()
+    }
 }
 
-impl std::fmt::Debug for BamReader {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("BamReader")
-            .field("path", &self.path)
-            .field("header", &self.header)
-            .finish()
+impl Iterator for SampledRecords<'_> {
+    type Item = Result<BamRecord, BamError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (fraction, seed) = (self.fraction, self.seed);
+        self.records.find(|result| result.as_ref().map_or(true, |record| keeps_read_name(record.qname(), fraction, seed)))
     }
 }
 
-impl BamReader {
-    /// 从文件路径创建BAM读取器
-    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self, BamError> {
-        let path_str = path.as_ref().to_string_lossy().to_string();
-        
-        if !path.as_ref().exists() {
-            return Err(BamError::FileNotFound { path: path_str });
+/// 按坐标排序的BAM中第一条可能是未比对记录的位置
+///
+/// 第一条含未比对记录的参考序列之前的记录都已比对，从该参考序列的第一条记录开始；
+/// 都不含时从最后一条参考序列的第一条记录开始（其后是没有位置的未比对记录）。
+/// 有记录的参考序列在索引中缺少记录计数时无法判断，返回None，从第一条记录开始。
+fn first_unmapped_start(index: &bai::Index) -> Option<VirtualPosition> {
+    for reference in index.reference_sequences() {
+        match reference.metadata() {
+            Some(metadata) if metadata.unmapped_record_count() > 0 => return Some(metadata.start_position()),
+            Some(_) => {}
+            None if reference.bins().is_empty() => {}
+            None => return None,
         }
+    }
+    // 没有比对记录的索引没有该位置
+    index.last_first_record_start_position()
+}
 
-        let file = File::open(&path)?;
-        let mut reader = Reader::new(file);
-        
-        // 读取头部信息
-        let header = reader.read_header().map_err(|e| BamError::BamError(e.to_string()))?;
-        
-        info!("已打开BAM文件: {}", path_str);
-        
-        Ok(Self {
-            reader,
-            header,
-            path: path_str,
-        })
+/// [`BamReader::unmapped_records`]返回的迭代器
+pub struct UnmappedRecords<'a> {
+    records: BamRecordIterator<'a>,
+}
+
+impl UnmappedRecords<'_> {
+    /// 已读取的记录数（包括被跳过的已比对记录）
+    pub fn records_read(&self) -> u64 {
+        self.records.records_read()
     }
+}
 
-    /// 获取文件路径
-    pub fn path(&self) -> &str {
-        &self.path
+impl Iterator for UnmappedRecords<'_> {
+    type Item = Result<BamRecord, BamError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.records.find(|result| result.as_ref().map_or(true, |record| record.is_unmapped()))
     }
+}
 
-    /// 获取SAM头部信息
-    pub fn header(&self) -> &sam::Header {
-        &self.header
+/// 检查抽样比例在(0, 1]内（NaN无效）
+pub fn check_sample_fraction(fraction: f64) -> Result<f64, BamError> {
+    if fraction > 0.0 && fraction <= 1.0 {
+        Ok(fraction)
+    } else {
+        Err(BamError::InvalidSampleFraction { fraction })
     }
+}
 
-    /// 迭代所有记录
-    pub fn records(&mut self) -> BamRecordIterator<'_> {
-        BamRecordIterator {
-            reader: &mut self.reader,
-            count: 0,
-        }
+/// 读名为`name`的记录是否以概率`fraction`、种子`seed`被抽中
+///
+/// 读名的FNV-1a哈希与种子混合后经SplitMix64终混，取高53位与`fraction`比较；
+/// 结果跨平台、跨版本不变。`fraction`不小于1时总是保留，不大于0时总是丢弃。
+pub fn keeps_read_name(name: &[u8], fraction: f64, seed: u64) -> bool {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for &b in name {
+        hash ^= b as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
     }
+    let mut z = (seed ^ hash).wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^= z >> 31;
+    ((z >> 11) as f64 / (1u64 << 53) as f64) < fraction
 }
 
-/// BAM记录迭代器
-pub struct BamRecordIterator<'a> {
-    reader: &'a mut Reader<BgzfReader<File>>,
-    count: u64,
+/// 按读名配对的记录迭代器，见[`BamReader::record_pairs`]
+pub struct BamRecordPairs<'a> {
+    records: BamRecordIterator<'a>,
+    /// 读名 -> (记录序号, 等待mate的记录)
+    pending: HashMap<Vec<u8>, (u64, BamRecord)>,
+    max_pending: Option<usize>,
+    index: u64,
+    done: bool,
 }
 
-impl<'a> Iterator for BamRecordIterator<'a> {
-    type Item = Result<BamRecord, BamError>;
+impl BamRecordPairs<'_> {
+    /// 等待mate的记录超过`limit`条时产出[`BamError::PendingMatesExceeded`]并结束迭代
+    pub fn with_max_pending(mut self, limit: usize) -> Self {
+        self.max_pending = Some(limit);
+        self
+    }
+
+    /// 当前等待mate的记录数
+    pub fn pending_len(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// 取出等待mate的记录（按文件顺序）；在迭代结束后调用即为没有找到mate的记录
+    pub fn take_unpaired(&mut self) -> Vec<BamRecord> {
+        let mut unpaired: Vec<(u64, BamRecord)> = self.pending.drain().map(|(_, entry)| entry).collect();
+        unpaired.sort_unstable_by_key(|&(index, _)| index);
+        unpaired.into_iter().map(|(_, record)| record).collect()
+    }
+}
+
+impl Iterator for BamRecordPairs<'_> {
+    type Item = Result<(BamRecord, BamRecord), BamError>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let mut record = bam::Record::default();
-        match self.reader.read_record(&mut record) {
-            Ok(0) => None, // EOF
-            Ok(_) => {
-                self.count += 1;
-                Some(Ok(BamRecord { inner: record }))
+        while !self.done {
+            let record = match self.records.next()? {
+                Ok(record) => record,
+                Err(e) => return Some(Err(e)),
+            };
+            self.index += 1;
+            if !record.is_segmented() || record.is_secondary() || record.is_supplementary() || record.qname().is_empty() {
+                continue;
+            }
+            if let Some((_, mate)) = self.pending.remove(record.qname()) {
+                return Some(Ok((mate, record)));
+            }
+            if let Some(limit) = self.max_pending.filter(|&limit| self.pending.len() >= limit) {
+                self.done = true;
+                return Some(Err(BamError::PendingMatesExceeded { limit, record_index: self.index }));
+            }
+            self.pending.insert(record.qname().to_vec(), (self.index, record));
+        }
+        None
+    }
+}
+
+/// 顺序读取BAM文件的记录，暂时性IO错误时按重试策略重新打开并继续
+struct ResumingBam<'a> {
+    reader: &'a mut Reader<BgzfInput>,
+    threads: NonZeroUsize,
+    opener: &'a Opener,
+    retry: RetryPolicy,
+    path: &'a str,
+}
+
+impl ResumingBam<'_> {
+    fn read_into(&mut self, record: &mut bam::Record, count: &mut u64) -> Result<bool, BamError> {
+        // 上一条完整记录结束处：出错的记录从这里重新读取，count只在读完一条记录后增加，
+        // 因此重试前后产出的记录既不重复也不遗漏
+        let checkpoint = self.reader.get_ref().virtual_position();
+        let mut attempt = 0;
+        loop {
+            let mut error = match read_bam_record(self.reader, record, count) {
+                Err(BamError::IoError(e)) if is_transient_io_error(&e) && attempt < self.retry.retries() => e,
+                result => return result,
+            };
+            loop {
+                attempt += 1;
+                let delay = self.retry.delay(attempt);
+                warn!(
+                    "读取{}第{}条记录时出错（{}），{:?}后第{}/{}次重试",
+                    self.path,
+                    *count + 1,
+                    error,
+                    delay,
+                    attempt,
+                    self.retry.retries()
+                );
+                std::thread::sleep(delay);
+                match BgzfInput::reopen(self.opener, self.threads, checkpoint) {
+                    Ok(reader) => {
+                        *self.reader = reader;
+                        break;
+                    }
+                    Err(e) if is_transient_io_error(&e) && attempt < self.retry.retries() => error = e,
+                    Err(e) => return Err(e.into()),
+                }
+            }
+        }
+    }
+}
+
+/// 把下一条记录读入`record`（复用其缓冲区），EOF时返回false
+fn read_bam_record<R: noodles::bgzf::io::BufRead>(
+    reader: &mut Reader<R>,
+    record: &mut bam::Record,
+    count: &mut u64,
+) -> Result<bool, BamError> {
+    check_block_size(reader.get_mut())?;
+
+    match reader.read_record(record) {
+        Ok(0) => Ok(false), // EOF
+        Ok(_) => {
+            *count += 1;
+            READ_PROGRESS.update(*count, reader.get_ref().virtual_position().compressed());
+            Ok(true)
+        }
+        Err(e) if is_transient_io_error(&e) => Err(BamError::IoError(e)),
+        Err(e) => Err(BamError::BamError(e.to_string())),
+    }
+}
+
+/// 单条记录`block_size`的上限。
+///
+/// 损坏或恶意构造的文件可能给出接近4GiB的长度，noodles会先按该长度分配
+/// 缓冲区再读取，因此在解码前检查，超过上限时直接报错。
+pub const MAX_RECORD_SIZE: u32 = 256 << 20;
+
+fn check_block_size<R: std::io::BufRead>(reader: &mut R) -> Result<(), BamError> {
+    let buf = reader.fill_buf()?;
+    // 长度字段跨越BGZF块边界时交给noodles处理
+    if buf.len() >= 4 {
+        let block_size = u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]);
+        if block_size > MAX_RECORD_SIZE {
+            return Err(BamError::BamError(format!("记录长度异常: {} 字节", block_size)));
+        }
+    }
+    Ok(())
+}
+
+/// BAM记录定长部分的字节数（`block_size`之后）
+const RECORD_FIXED_SIZE: usize = 32;
+
+/// 检查当前位置是否像一条记录的起点：读取并解析记录头，检查各字段的取值范围
+/// 与长度是否自洽。当前位置在数据末尾时视为合法。调用后读取位置不确定，需要重新定位。
+fn check_record_start<R: Read>(reader: &mut R, reference_count: usize) -> Result<(), String> {
+    let mut block_size = [0; 4];
+    match reader.read(&mut block_size[..1]) {
+        Ok(0) => return Ok(()),
+        Ok(_) => reader.read_exact(&mut block_size[1..]).map_err(|e| e.to_string())?,
+        Err(e) => return Err(e.to_string()),
+    }
+    let block_size = u32::from_le_bytes(block_size);
+    if block_size as usize <= RECORD_FIXED_SIZE || block_size > MAX_RECORD_SIZE {
+        return Err(format!("记录长度{}不合理", block_size));
+    }
+    let mut fixed = [0; RECORD_FIXED_SIZE];
+    reader.read_exact(&mut fixed).map_err(|e| format!("记录不完整: {}", e))?;
+    let i32_at = |i: usize| i32::from_le_bytes([fixed[i], fixed[i + 1], fixed[i + 2], fixed[i + 3]]);
+    let u16_at = |i: usize| u16::from_le_bytes([fixed[i], fixed[i + 1]]) as usize;
+    let valid_tid = |tid: i32| tid == -1 || usize::try_from(tid).is_ok_and(|tid| tid < reference_count);
+
+    let (tid, pos, mate_tid, mate_pos) = (i32_at(0), i32_at(4), i32_at(20), i32_at(24));
+    if !valid_tid(tid) || !valid_tid(mate_tid) {
+        return Err(format!("参考序列ID {}/{} 超出头部范围", tid, mate_tid));
+    }
+    if pos < -1 || mate_pos < -1 {
+        return Err(format!("位置 {}/{} 无效", pos, mate_pos));
+    }
+    let name_len = fixed[8] as usize;
+    let cigar_ops = u16_at(12);
+    let seq_len = u32::from_le_bytes([fixed[16], fixed[17], fixed[18], fixed[19]]) as usize;
+    let needed = RECORD_FIXED_SIZE + name_len + 4 * cigar_ops + seq_len.div_ceil(2) + seq_len;
+    if name_len == 0 || needed > block_size as usize {
+        return Err(format!("各字段长度之和{}与记录长度{}不符", needed, block_size));
+    }
+    let mut name = vec![0; name_len];
+    reader.read_exact(&mut name).map_err(|e| format!("记录不完整: {}", e))?;
+    if name.pop() != Some(0) || name.contains(&0) {
+        return Err("读名不是以NUL结尾的字符串".to_string());
+    }
+    Ok(())
+}
+
+/// 把ASCII-33编码的质量字符串解码为Phred质量值，含有`!`到`~`之外的字符时返回None
+///
+/// # Examples
+///
+/// ```
+/// use bamqc_io::decode_quality_string;
+///
+/// assert_eq!(decode_quality_string(b"!+5?I"), Some(vec![0, 10, 20, 30, 40]));
+/// assert_eq!(decode_quality_string(b"II I"), None);
+/// ```
+pub fn decode_quality_string(s: &[u8]) -> Option<Vec<u8>> {
+    s.iter()
+        .map(|&c| (b'!'..=b'~').contains(&c).then(|| c - b'!'))
+        .collect()
+}
+
+/// 辅助标签值
+#[derive(Clone, Debug, PartialEq)]
+pub enum AuxValue {
+    /// A
+    Char(u8),
+    /// c/C/s/S/i/I
+    Int(i64),
+    /// f
+    Float(f32),
+    /// Z
+    String(String),
+    /// H
+    Hex(String),
+    /// B（数组内容暂不展开）
+    Array,
+}
+
+impl AuxValue {
+    /// SAM类型字符：A、i、f、Z、H或B
+    pub fn type_code(&self) -> char {
+        match self {
+            AuxValue::Char(_) => 'A',
+            AuxValue::Int(_) => 'i',
+            AuxValue::Float(_) => 'f',
+            AuxValue::String(_) => 'Z',
+            AuxValue::Hex(_) => 'H',
+            AuxValue::Array => 'B',
+        }
+    }
+}
+
+/// FLAG中互相矛盾的位组合，见[`BamRecord::validate_flags`]
+///
+/// 出现这类组合通常说明上游流程（比对、标记重复、改写mate信息的工具）有缺陷。
+///
+/// # Examples
+///
+/// ```
+/// use bamqc_io::FlagInconsistency;
+///
+/// // 99 = 0x1 | 0x2 | 0x20 | 0x40，正常的读对
+/// assert!(FlagInconsistency::check(99).is_empty());
+/// // 0x1 | 0x2 | 0x8 | 0x20：proper pair但mate未比对，且mate未比对时设置了mate反向
+/// assert_eq!(
+///     FlagInconsistency::check(0x1 | 0x2 | 0x8 | 0x20),
+///     [FlagInconsistency::ProperPairMateUnmapped, FlagInconsistency::MateReverseMateUnmapped]
+/// );
+/// assert_eq!(FlagInconsistency::check(0x1 | 0x2 | 0x4), [FlagInconsistency::ProperPairUnmapped]);
+/// // 非配对读只检查配对位
+/// let found = FlagInconsistency::check(0x40 | 0x8 | 0x20 | 0x10);
+/// assert_eq!(found, [FlagInconsistency::PairedBitsOnUnpaired { bits: 0x68 }]);
+/// assert_eq!(found[0].code(), "paired_bits_on_unpaired");
+/// assert_eq!(found[0].to_string(), "非配对读（无0x1）设置了配对位0x68");
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FlagInconsistency {
+    /// 设置了proper pair（0x2），但自身未比对（0x4）
+    ProperPairUnmapped,
+    /// 设置了proper pair（0x2），但mate未比对（0x8）
+    ProperPairMateUnmapped,
+    /// mate未比对（0x8），但设置了mate反向（0x20）
+    MateReverseMateUnmapped,
+    /// 非配对读（无0x1）设置了只对配对读有意义的位（0x2、0x8、0x20、0x40、0x80），
+    /// `bits`为其中被设置的位
+    PairedBitsOnUnpaired { bits: u16 },
+}
+
+impl FlagInconsistency {
+    /// 只对配对读有意义的位
+    pub const PAIRED_BITS: u16 = 0x2 | 0x8 | 0x20 | 0x40 | 0x80;
+
+    /// 全部种类的代码，顺序与[`FlagInconsistency::check`]的检查顺序一致
+    pub const CODES: [&'static str; 4] = [
+        "proper_pair_unmapped",
+        "proper_pair_mate_unmapped",
+        "mate_reverse_mate_unmapped",
+        "paired_bits_on_unpaired",
+    ];
+
+    /// 检查FLAG原始值中的矛盾组合，没有时返回空列表
+    pub fn check(flags: u16) -> Vec<Self> {
+        let mut found = Vec::new();
+        if flags & 0x1 == 0 {
+            let bits = flags & Self::PAIRED_BITS;
+            if bits != 0 {
+                found.push(FlagInconsistency::PairedBitsOnUnpaired { bits });
+            }
+            return found;
+        }
+        if flags & 0x2 != 0 && flags & 0x4 != 0 {
+            found.push(FlagInconsistency::ProperPairUnmapped);
+        }
+        if flags & 0x2 != 0 && flags & 0x8 != 0 {
+            found.push(FlagInconsistency::ProperPairMateUnmapped);
+        }
+        if flags & 0x8 != 0 && flags & 0x20 != 0 {
+            found.push(FlagInconsistency::MateReverseMateUnmapped);
+        }
+        found
+    }
+
+    /// 稳定的代码，用于计数与JSON输出
+    pub fn code(&self) -> &'static str {
+        match self {
+            FlagInconsistency::ProperPairUnmapped => Self::CODES[0],
+            FlagInconsistency::ProperPairMateUnmapped => Self::CODES[1],
+            FlagInconsistency::MateReverseMateUnmapped => Self::CODES[2],
+            FlagInconsistency::PairedBitsOnUnpaired { .. } => Self::CODES[3],
+        }
+    }
+}
+
+impl std::fmt::Display for FlagInconsistency {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FlagInconsistency::ProperPairUnmapped => write!(f, "设置了proper pair（0x2）但自身未比对（0x4）"),
+            FlagInconsistency::ProperPairMateUnmapped => write!(f, "设置了proper pair（0x2）但mate未比对（0x8）"),
+            FlagInconsistency::MateReverseMateUnmapped => write!(f, "mate未比对（0x8）但设置了mate反向（0x20）"),
+            FlagInconsistency::PairedBitsOnUnpaired { bits } => write!(f, "非配对读（无0x1）设置了配对位{:#x}", bits),
+        }
+    }
+}
+
+/// 辅助标签值的SAM类型，数组为`B:`加元素类型
+fn aux_type_code(value: &Value<'_>) -> String {
+    let code = match value {
+        Value::Character(_) => "A",
+        Value::Float(_) => "f",
+        Value::String(_) => "Z",
+        Value::Hex(_) => "H",
+        Value::Array(array) => match array {
+            Array::Int8(_) => "B:c",
+            Array::UInt8(_) => "B:C",
+            Array::Int16(_) => "B:s",
+            Array::UInt16(_) => "B:S",
+            Array::Int32(_) => "B:i",
+            Array::UInt32(_) => "B:I",
+            Array::Float(_) => "B:f",
+        },
+        _ => "i",
+    };
+    code.to_string()
+}
+
+/// BAM编码的CIGAR是否为长CIGAR占位符`kSmN`（k为读长）
+fn is_encoded_placeholder(raw: &[u8], read_length: u64) -> bool {
+    let op = |i: usize| u32::from_le_bytes([raw[i], raw[i + 1], raw[i + 2], raw[i + 3]]);
+    // 操作代码：S为4，N为3
+    raw.len() == 8 && op(0) & 0x0f == 4 && (op(0) >> 4) as u64 == read_length && op(4) & 0x0f == 3
+}
+
+/// 辅助字段中的`CG:B`数组
+#[derive(Clone, Copy)]
+enum CgArray<'a> {
+    /// 没有CG数组，或在找到之前遇到无法解码的字段（noodles此时不替换占位符）
+    Absent,
+    /// 第一个CG数组的元素类型与元素的原始字节
+    Found(u8, &'a [u8]),
+    /// 在找到CG之前遇到声明长度超出记录的数组：noodles查找CG时会panic
+    Truncated,
+}
+
+/// 按noodles查找替换用CG数组的方式，在辅助字段的原始编码中查找第一个`CG:B`数组
+fn raw_cg_array(mut data: &[u8]) -> CgArray<'_> {
+    fn width(ty: u8) -> Option<usize> {
+        match ty {
+            b'A' | b'c' | b'C' => Some(1),
+            b's' | b'S' => Some(2),
+            b'i' | b'I' | b'f' => Some(4),
+            _ => None,
+        }
+    }
+
+    while data.len() >= 3 {
+        let (tag, ty) = ([data[0], data[1]], data[2]);
+        data = &data[3..];
+        let len = match ty {
+            b'Z' | b'H' => data.iter().position(|&b| b == 0).map(|nul| nul + 1),
+            b'B' => {
+                let (Some(subtype), Some(count)) = (data.first().filter(|&&s| s != b'A'), data.get(1..5)) else {
+                    return CgArray::Absent;
+                };
+                let Some(width) = width(*subtype) else {
+                    return CgArray::Absent;
+                };
+                let count = u32::from_le_bytes([count[0], count[1], count[2], count[3]]) as usize;
+                let Some(bytes) = count.checked_mul(width).and_then(|n| data.get(5..5 + n)) else {
+                    return CgArray::Truncated;
+                };
+                if &tag == b"CG" {
+                    return CgArray::Found(*subtype, bytes);
+                }
+                Some(5 + bytes.len())
             }
-            Err(e) => Some(Err(BamError::BamError(e.to_string()))),
+            ty => width(ty),
+        };
+        match len.and_then(|len| data.get(len..)) {
+            Some(rest) => data = rest,
+            None => return CgArray::Absent,
         }
     }
+    CgArray::Absent
+}
+
+fn aux_decode_error(tag: [u8; 2], e: std::io::Error) -> BamError {
+    BamError::BamError(format!("辅助标签{}无法解析: {}", String::from_utf8_lossy(&tag), e))
 }
 
 /// BAM记录封装
-#[derive(Debug)]
+///
+/// `BamRecord::default()`为空记录，可作为[`BamReader::read_into`]反复使用的缓冲区。
+#[derive(Debug, Default)]
 pub struct BamRecord {
     inner: bam::Record,
 }
 
 impl BamRecord {
 
+    /// CIGAR操作及其来源
+    ///
+    /// 超长比对的`kSmN`占位符会被替换为`CG:B,I`标签中的真实操作，见[`CigarSource`]。
+    pub fn cigar_with_source(&self) -> (Cigar, CigarSource) {
+        self.with_raw_cigar(|raw, source| (raw.and_then(Cigar::decode).unwrap_or_default(), source))
+    }
+
+    /// CIGAR的来源；不解码CIGAR，也不分配内存
+    pub fn cigar_source(&self) -> CigarSource {
+        self.with_raw_cigar(|_, source| source)
+    }
+
+    /// 以CIGAR的BAM编码及其来源调用`f`，不分配内存
+    ///
+    /// noodles在记录的CIGAR为占位符且数据区有CG数组时直接返回该数组的原始字节。
+    /// 是否发生了替换由`from_cg`显式记录：CG数组存在且字节与返回的CIGAR相同（记录本身的
+    /// CIGAR恰好与CG数组相同时两者的操作一致，按取自CG计）。
+    /// 替换后的内容无效时占位符已无法取回，此时传入None（CIGAR为空）；辅助字段中
+    /// 有声明长度超出记录的数组时noodles读取CIGAR会panic，同样传入None。
+    fn with_raw_cigar<R>(&self, f: impl FnOnce(Option<&[u8]>, CigarSource) -> R) -> R {
+        let data = self.inner.data();
+        let cg = match raw_cg_array(data.as_ref()) {
+            // 不能调用noodles读取CIGAR，按CG无效处理
+            CgArray::Truncated => return f(None, CigarSource::InvalidCg),
+            CgArray::Found(subtype, bytes) => Some((subtype, bytes)),
+            CgArray::Absent => None,
+        };
+        let cigar = self.inner.cigar();
+        let raw: &[u8] = cigar.as_ref();
+        let from_cg = !raw.is_empty() && cg.is_some_and(|(_, bytes)| bytes == raw);
+        let read_length = self.sequence_len() as u64;
+
+        let source = if from_cg {
+            let is_u32_array = cg.is_some_and(|(subtype, _)| subtype == b'I');
+            match sum_encoded(raw, CigarKind::consumes_read) {
+                Some(query_length) if is_u32_array && (read_length == 0 || query_length == read_length) => {
+                    CigarSource::CgTag
+                }
+                _ => CigarSource::InvalidCg,
+            }
+        } else if read_length > 0 && is_encoded_placeholder(raw, read_length) {
+            CigarSource::InvalidCg
+        } else {
+            CigarSource::Inline
+        };
+        let usable = !(from_cg && source == CigarSource::InvalidCg);
+        f(usable.then_some(raw), source)
+    }
+
+    /// CIGAR操作（超长比对使用CG标签中的真实操作）
+    pub fn cigar(&self) -> Cigar {
+        self.cigar_with_source().0
+    }
+
+    /// FLAG字段的原始值
+    pub fn flag(&self) -> u16 {
+        u16::from(self.inner.flags())
+    }
+
+    /// FLAG字段的原始值（与[`BamRecord::flag`]相同），用于按完整的标志组合记录日志或分组
+    pub fn flags_raw(&self) -> u16 {
+        self.flag()
+    }
+
+    /// FLAG中互相矛盾的位组合，见[`FlagInconsistency::check`]
+    pub fn validate_flags(&self) -> Vec<FlagInconsistency> {
+        FlagInconsistency::check(self.flags_raw())
+    }
+
     /// 是否为配对读; 对应flag: 0x1
     pub fn is_segmented(&self) -> bool {
         self.inner.flags().is_segmented()
@@ -197,7 +3237,698 @@ impl BamRecord {
     }
 
 
+    /// 读名（QNAME），缺失时返回空切片
+    pub fn qname(&self) -> &[u8] {
+        self.inner.name().map(|name| name.as_ref()).unwrap_or(b"")
+    }
+
+    /// 读名的文本形式，非UTF-8字节按[`decode_lossy`]替换为U+FFFD
+    ///
+    /// 分组、哈希等只需比较读名的场合应使用[`BamRecord::qname`]的原始字节。
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bamqc_io::{BamReader, BamWriter, lossy_decodes};
+    /// use noodles::sam::{self, alignment::RecordBuf};
+    ///
+    /// let path = std::env::temp_dir().join(format!("bamqc-name-{}.bam", std::process::id()));
+    /// let header = sam::Header::default();
+    /// let mut writer = BamWriter::from_path(&path, &header).unwrap();
+    /// for name in [&b"plain"[..], b"caf\xe9:1", "读🧬".as_bytes()] {
+    ///     writer.write_record_buf_with_name(&RecordBuf::default(), name).unwrap();
+    /// }
+    /// writer.finish().unwrap();
+    ///
+    /// let mut reader = BamReader::from_path(&path).unwrap();
+    /// let records: Vec<_> = reader.records().map(Result::unwrap).collect();
+    /// let before = lossy_decodes();
+    /// assert_eq!(records[0].name(), "plain");
+    /// assert_eq!(records[1].qname(), b"caf\xe9:1");
+    /// assert_eq!(records[1].name(), "caf\u{fffd}:1");
+    /// assert_eq!(records[2].name(), "读🧬");
+    /// assert_eq!(lossy_decodes(), before + 1);
+    /// std::fs::remove_file(&path).unwrap();
+    /// ```
+    pub fn name(&self) -> Cow<'_, str> {
+        decode_lossy(self.qname())
+    }
+
+    /// 比对质量（MAPQ），缺失时返回255
+    pub fn mapq(&self) -> u8 {
+        self.inner.mapping_quality().map(u8::from).unwrap_or(255)
+    }
+
+    /// 比对质量（MAPQ），255（不可用）时返回None
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bamqc_io::bam::{BamReader, BamWriter};
+    /// use noodles::sam::alignment::record::Flags;
+    /// use noodles::sam::alignment::record::MappingQuality;
+    /// use noodles::sam::alignment::RecordBuf;
+    ///
+    /// let path = std::env::temp_dir().join(format!("bamqc-mapq-{}.bam", std::process::id()));
+    /// let header = "@HD\tVN:1.6\n".parse().unwrap();
+    /// let mut writer = BamWriter::from_path(&path, &header).unwrap();
+    /// for mapq in [Some(0), Some(30), None] {
+    ///     let mut builder = RecordBuf::builder().set_flags(Flags::UNMAPPED);
+    ///     if let Some(mapq) = mapq.and_then(MappingQuality::new) {
+    ///         builder = builder.set_mapping_quality(mapq);
+    ///     }
+    ///     writer.write_record_buf(&builder.build()).unwrap();
+    /// }
+    /// writer.finish().unwrap();
+    ///
+    /// let mut reader = BamReader::from_path(&path).unwrap();
+    /// let records: Vec<_> = reader.records().map(|r| r.unwrap()).collect();
+    /// let mapqs: Vec<_> = records.iter().map(|r| r.mapping_quality()).collect();
+    /// assert_eq!(mapqs, [Some(0), Some(30), None]);
+    /// assert_eq!(records[2].mapq(), 255);
+    /// std::fs::remove_file(&path).unwrap();
+    /// ```
+    pub fn mapping_quality(&self) -> Option<u8> {
+        self.inner.mapping_quality().map(u8::from)
+    }
+
+    /// 比对起始位置（0-based），无位置时返回-1
+    pub fn pos(&self) -> i64 {
+        match self.inner.alignment_start() {
+            Some(Ok(pos)) => usize::from(pos) as i64 - 1,
+            _ => -1,
+        }
+    }
+
+    /// 比对起始位置（0-based），未比对或POS未设置时返回None
+    ///
+    /// 比对记录与`samtools view`显示的POS减一一致。放置在mate位置上的未比对读长
+    /// （placed unmapped）的POS只用于排序与索引，不是比对位置，同样返回None；
+    /// 需要原始POS字段时用[`BamRecord::pos`]。
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bamqc_io::{BamReader, BamWriter};
+    /// use noodles::sam;
+    ///
+    /// let path = std::env::temp_dir().join(format!("bamqc-position-{}.bam", std::process::id()));
+    /// let text = "@HD\tVN:1.6\n@SQ\tSN:c1\tLN:1000\n\
+    ///             mapped\t99\tc1\t100\t60\t10M2D5M\t=\t300\t215\t*\t*\n\
+    ///             placed\t69\tc1\t300\t0\t*\t=\t300\t0\t*\t*\n\
+    ///             unplaced\t77\t*\t0\t0\t*\t*\t0\t0\t*\t*\n";
+    /// let mut sam_reader = sam::io::Reader::new(text.as_bytes());
+    /// let header = sam_reader.read_header().unwrap();
+    /// let mut writer = BamWriter::from_path(&path, &header).unwrap();
+    /// for record in sam_reader.record_bufs(&header) {
+    ///     writer.write_record_buf(&record.unwrap()).unwrap();
+    /// }
+    /// writer.finish().unwrap();
+    ///
+    /// let mut reader = BamReader::from_path(&path).unwrap();
+    /// let records: Vec<_> = reader.records().map(|r| r.unwrap()).collect();
+    /// let (mapped, placed, unplaced) = (&records[0], &records[1], &records[2]);
+    /// assert_eq!((mapped.position(), mapped.mate_position()), (Some(99), Some(299)));
+    /// assert_eq!((mapped.alignment_start(), mapped.alignment_span()), (Some(100), Some((100, 116))));
+    /// // 未比对但有位置：没有比对位置与跨度，原始POS字段仍可读取
+    /// assert!(placed.is_unmapped());
+    /// assert_eq!((placed.position(), placed.mate_position()), (None, Some(299)));
+    /// assert_eq!((placed.alignment_start(), placed.alignment_span()), (None, None));
+    /// assert_eq!(placed.pos(), 299);
+    /// assert_eq!((unplaced.position(), unplaced.mate_position()), (None, None));
+    /// assert_eq!((unplaced.alignment_start(), unplaced.alignment_span()), (None, None));
+    /// assert_eq!(unplaced.pos(), -1);
+    /// std::fs::remove_file(&path).unwrap();
+    /// ```
+    pub fn position(&self) -> Option<i64> {
+        Some(self.pos()).filter(|&pos| pos >= 0 && !self.is_unmapped())
+    }
+
+    /// mate的比对起始位置（0-based），PNEXT未设置时返回None
+    pub fn mate_position(&self) -> Option<i64> {
+        match self.inner.mate_alignment_start() {
+            Some(Ok(pos)) => Some(usize::from(pos) as i64 - 1),
+            _ => None,
+        }
+    }
+
+    /// 比对起始位置（1-based，与SAM文本的POS相同），未比对或POS未设置时返回None
+    pub fn alignment_start(&self) -> Option<i64> {
+        self.position().map(|pos| pos + 1)
+    }
+
+    /// 比对在参考序列上的闭区间（1-based，起止都包含），未比对或POS未设置时返回None
+    ///
+    /// 终止位置按[`BamRecord::reference_end`]计算，没有CIGAR的比对记录按长度1计。
+    pub fn alignment_span(&self) -> Option<(i64, i64)> {
+        self.alignment_start().map(|start| (start, self.reference_end()))
+    }
+
+    /// 比对终止位置（1-based，包含），未比对或POS未设置时返回None；比对记录与
+    /// `samtools view`中`endpos`一致
+    ///
+    /// # Examples
+    ///
+    /// 以下比对记录的终止位置与`samtools view -e 'endpos'`的输出相同：
+    ///
+    /// ```
+    /// use bamqc_io::{BamReader, BamWriter};
+    /// use noodles::sam;
+    ///
+    /// let path = std::env::temp_dir().join(format!("bamqc-alignment-end-{}.bam", std::process::id()));
+    /// let text = "@HD\tVN:1.6\n@SQ\tSN:c1\tLN:1000\n\
+    ///             r1\t0\tc1\t100\t60\t10M2D5M\t*\t0\t0\t*\t*\n\
+    ///             r2\t16\tc1\t200\t60\t3S7M1I4M2H\t*\t0\t0\t*\t*\n\
+    ///             r3\t0\tc1\t50\t60\t5=1X20N4M\t*\t0\t0\t*\t*\n\
+    ///             r4\t4\tc1\t300\t0\t*\t*\t0\t0\t*\t*\n\
+    ///             r5\t4\t*\t0\t0\t*\t*\t0\t0\t*\t*\n";
+    /// let mut sam_reader = sam::io::Reader::new(text.as_bytes());
+    /// let header = sam_reader.read_header().unwrap();
+    /// let mut writer = BamWriter::from_path(&path, &header).unwrap();
+    /// for record in sam_reader.record_bufs(&header) {
+    ///     writer.write_record_buf(&record.unwrap()).unwrap();
+    /// }
+    /// writer.finish().unwrap();
+    ///
+    /// let mut reader = BamReader::from_path(&path).unwrap();
+    /// let records: Vec<_> = reader.records().map(|r| r.unwrap()).collect();
+    /// let ends: Vec<_> = records.iter().map(|r| r.alignment_end()).collect();
+    /// assert_eq!(ends, [Some(116), Some(210), Some(79), None, None]);
+    ///
+    /// let r2 = &records[1];
+    /// assert_eq!(r2.reference_length(), 11);
+    /// assert_eq!(r2.query_alignment_length(), 12);
+    /// assert_eq!((r2.soft_clipped_bases(), r2.hard_clipped_bases()), (3, 2));
+    /// // 未比对且没有CIGAR：各跨度为0而不是panic
+    /// for unmapped in &records[3..] {
+    ///     assert_eq!(unmapped.reference_length(), 0);
+    ///     assert_eq!(unmapped.query_alignment_length(), 0);
+    ///     assert_eq!(unmapped.soft_clipped_bases() + unmapped.hard_clipped_bases(), 0);
+    /// }
+    /// std::fs::remove_file(&path).unwrap();
+    /// ```
+    pub fn alignment_end(&self) -> Option<i64> {
+        self.alignment_span().map(|(_, end)| end)
+    }
+
+    /// 未clip时的比对起始位置（1-based）：[`BamRecord::alignment_start`]减去开头的
+    /// soft与hard clip，未比对或POS未设置时返回None
+    ///
+    /// 与Picard相同，重复判定按读长5'端的未clip位置比较：正向链为`unclipped_start`，
+    /// 反向链为[`BamRecord::unclipped_end`]。靠近参考序列起点时结果可能小于1。
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bamqc_io::{BamReader, BamWriter};
+    /// use noodles::sam;
+    ///
+    /// let path = std::env::temp_dir().join(format!("bamqc-unclipped-{}.bam", std::process::id()));
+    /// let text = "@HD\tVN:1.6\n@SQ\tSN:c1\tLN:1000\n\
+    ///             r1\t0\tc1\t100\t60\t2H3S10M\t*\t0\t0\t*\t*\n\
+    ///             r2\t16\tc1\t200\t60\t5M1D5M4S1H\t*\t0\t0\t*\t*\n\
+    ///             r3\t0\tc1\t2\t60\t5S5M\t*\t0\t0\t*\t*\n\
+    ///             r4\t4\t*\t0\t0\t*\t*\t0\t0\t*\t*\n";
+    /// let mut sam_reader = sam::io::Reader::new(text.as_bytes());
+    /// let header = sam_reader.read_header().unwrap();
+    /// let mut writer = BamWriter::from_path(&path, &header).unwrap();
+    /// for record in sam_reader.record_bufs(&header) {
+    ///     writer.write_record_buf(&record.unwrap()).unwrap();
+    /// }
+    /// writer.finish().unwrap();
+    ///
+    /// let mut reader = BamReader::from_path(&path).unwrap();
+    /// let spans: Vec<_> = reader.records().map(|r| {
+    ///     let r = r.unwrap();
+    ///     (r.unclipped_start(), r.unclipped_end())
+    /// }).collect();
+    /// assert_eq!(spans, [(Some(95), Some(109)), (Some(200), Some(215)), (Some(-3), Some(6)), (None, None)]);
+    /// std::fs::remove_file(&path).unwrap();
+    /// ```
+    pub fn unclipped_start(&self) -> Option<i64> {
+        self.alignment_start().map(|start| start - self.cigar().leading_clipped_bases() as i64)
+    }
+
+    /// 未clip时的比对终止位置（1-based，包含）：[`BamRecord::alignment_end`]加上末尾的
+    /// soft与hard clip，未比对或POS未设置时返回None
+    pub fn unclipped_end(&self) -> Option<i64> {
+        self.alignment_end().map(|end| end + self.cigar().trailing_clipped_bases() as i64)
+    }
+
+    /// CIGAR的参考序列跨度（M、D、N、=、X之和），没有CIGAR时为0
+    pub fn reference_length(&self) -> u64 {
+        // 直接累加编码中的操作长度，不解码出操作列表
+        self.with_raw_cigar(|raw, _| raw.and_then(|raw| sum_encoded(raw, CigarKind::consumes_reference)).unwrap_or(0))
+    }
+
+    /// 参与比对的读长碱基数（M、I、=、X之和），没有CIGAR时为0
+    pub fn query_alignment_length(&self) -> u64 {
+        self.cigar().query_alignment_length()
+    }
+
+    /// soft clip碱基数
+    pub fn soft_clipped_bases(&self) -> u64 {
+        self.cigar().soft_clipped_bases()
+    }
+
+    /// hard clip碱基数
+    pub fn hard_clipped_bases(&self) -> u64 {
+        self.cigar().hard_clipped_bases()
+    }
+
+    /// 比对在参考序列上的终止位置（0-based，不含），无位置时返回-1
+    ///
+    /// 根据[`BamRecord::cigar`]中消耗参考序列的操作计算（不分配内存）；没有CIGAR时等于`pos() + 1`，
+    /// 没有比对位置时为-1。
+    pub fn reference_end(&self) -> i64 {
+        let pos = self.pos();
+        if pos < 0 {
+            return -1;
+        }
+        pos + (self.reference_length() as i64).max(1)
+    }
+
+    /// 读长序列中G/C碱基数与非N碱基数
+    pub fn gc_count(&self) -> (u32, u32) {
+        let mut gc = 0;
+        let mut called = 0;
+        for base in self.inner.sequence().iter() {
+            match base.to_ascii_uppercase() {
+                b'G' | b'C' | b'S' => {
+                    gc += 1;
+                    called += 1;
+                }
+                b'A' | b'T' | b'W' => called += 1,
+                _ => {}
+            }
+        }
+        (gc, called)
+    }
+
+    /// 读长序列的碱基字符（SEQ为`*`时为空）
+    pub fn sequence(&self) -> Vec<u8> {
+        self.inner.sequence().iter().collect()
+    }
+
+    /// 读长序列长度（SEQ为`*`时为0）
+    pub fn sequence_len(&self) -> usize {
+        self.inner.sequence().len()
+    }
+
+    /// 读长：SEQ的长度；SEQ为`*`时按CIGAR中消耗读长的操作（M、I、S、=、X）计算
+    pub fn read_length(&self) -> usize {
+        match self.sequence_len() {
+            0 => self.cigar().query_length() as usize,
+            len => len,
+        }
+    }
+
+    /// QUAL字段的Phred质量值；QUAL为`*`时返回None
+    pub fn base_qualities(&self) -> Option<Vec<u8>> {
+        self.with_quality_scores(|scores| (!scores.is_empty()).then(|| scores.to_vec()))
+    }
+
+    /// 以QUAL字段的原始Phred质量值调用`f`，质量值直接借用记录缓冲区，不分配内存
+    ///
+    /// 与[`BamRecord::with_aux_bytes`]相同，noodles的质量值只在临时借用内有效，
+    /// 因此以闭包的形式提供。QUAL或SEQ为`*`时`f`收到空切片。
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::alloc::{GlobalAlloc, Layout, System};
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    /// use bamqc_io::{BamReader, BamWriter};
+    /// use noodles::sam;
+    ///
+    /// struct Counting;
+    /// static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+    /// unsafe impl GlobalAlloc for Counting {
+    ///     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+    ///         ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+    ///         System.alloc(layout)
+    ///     }
+    ///     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+    ///         System.dealloc(ptr, layout)
+    ///     }
+    /// }
+    /// #[global_allocator]
+    /// static GLOBAL: Counting = Counting;
+    ///
+    /// fn main() {
+    ///     let path = std::env::temp_dir().join(format!("bamqc-quality-{}.bam", std::process::id()));
+    ///     let text = "@HD\tVN:1.6\n@SQ\tSN:c1\tLN:1000\n\
+    ///                 full\t0\tc1\t100\t60\t4M\t*\t0\t0\tACGT\t#+5?\n\
+    ///                 no_qual\t0\tc1\t100\t60\t4M\t*\t0\t0\tGGCN\t*\n\
+    ///                 no_seq\t0\tc1\t100\t60\t3S5M2H\t*\t0\t0\t*\t*\n";
+    ///     let mut sam_reader = sam::io::Reader::new(text.as_bytes());
+    ///     let header = sam_reader.read_header().unwrap();
+    ///     let mut writer = BamWriter::from_path(&path, &header).unwrap();
+    ///     for record in sam_reader.record_bufs(&header) {
+    ///         writer.write_record_buf(&record.unwrap()).unwrap();
+    ///     }
+    ///     writer.finish().unwrap();
+    ///
+    ///     let mut reader = BamReader::from_path(&path).unwrap();
+    ///     let records: Vec<_> = reader.records().map(|r| r.unwrap()).collect();
+    ///     let (full, no_qual, no_seq) = (&records[0], &records[1], &records[2]);
+    ///
+    ///     let before = ALLOCATIONS.load(Ordering::Relaxed);
+    ///     assert!(full.with_quality_scores(|q| q == [2, 10, 20, 30]));
+    ///     assert_eq!(full.mean_base_quality(), Some(15.5));
+    ///     assert_eq!(full.gc_fraction(), Some(0.5));
+    ///     assert_eq!(full.read_length(), 4);
+    ///     assert!(no_qual.with_quality_scores(|q| q.is_empty()));
+    ///     assert_eq!((no_qual.mean_base_quality(), no_qual.gc_fraction()), (None, Some(1.0)));
+    ///     assert_eq!(ALLOCATIONS.load(Ordering::Relaxed), before);
+    ///
+    ///     // SEQ为`*`：质量值为空，读长按CIGAR计算（hard clip不计）
+    ///     assert!(no_seq.with_quality_scores(|q| q.is_empty()));
+    ///     assert_eq!((no_seq.mean_base_quality(), no_seq.gc_fraction()), (None, None));
+    ///     assert_eq!((no_seq.sequence_len(), no_seq.read_length()), (0, 8));
+    ///     assert_eq!(full.sequence(), b"ACGT");
+    ///     std::fs::remove_file(&path).unwrap();
+    /// }
+    /// ```
+    pub fn with_quality_scores<R>(&self, f: impl FnOnce(&[u8]) -> R) -> R {
+        let scores = self.inner.quality_scores();
+        let scores: &[u8] = scores.as_ref();
+        // BAM中缺失的QUAL以0xFF填充
+        if scores.first() == Some(&0xff) {
+            f(&[])
+        } else {
+            f(scores)
+        }
+    }
+
+    /// 平均碱基质量（Phred）；QUAL或SEQ为`*`时返回None
+    pub fn mean_base_quality(&self) -> Option<f64> {
+        self.with_quality_scores(|scores| {
+            let total: u64 = scores.iter().map(|&q| q as u64).sum();
+            (!scores.is_empty()).then(|| total as f64 / scores.len() as f64)
+        })
+    }
+
+    /// G/C碱基占非N碱基的比例（计数规则见[`BamRecord::gc_count`]）；没有非N碱基时返回None
+    pub fn gc_fraction(&self) -> Option<f64> {
+        let (gc, called) = self.gc_count();
+        (called > 0).then(|| gc as f64 / called as f64)
+    }
+
+    /// BQSR前的原始质量值（OQ:Z标签），解码为Phred质量值
+    ///
+    /// 标签不存在、含有非法字符或长度与SEQ不一致时返回None。
+    pub fn original_qualities(&self) -> Option<Vec<u8>> {
+        let oq = match self.inner.data().get(b"OQ") {
+            Some(Ok(Value::String(s))) => decode_quality_string(s)?,
+            _ => return None,
+        };
+        (oq.len() == self.sequence_len()).then_some(oq)
+    }
+
     pub fn insert_size(&self) -> i64 {
         self.inner.template_length() as i64
     }
-}
\ No newline at end of file
+
+    /// 获取字符串类型(Z)的辅助标签值，标签不存在或类型不符时返回None
+    pub fn string_tag(&self, tag: [u8; 2]) -> Option<String> {
+        self.with_aux_bytes(tag, |s| s.map(|s| decode_lossy(s).into_owned()))
+    }
+
+    /// 以字符串类型(Z)辅助标签值的原始字节调用`f`，字节直接借用记录缓冲区，不分配内存
+    ///
+    /// 按读组、条形码等标签分组时，每条记录都要取一次标签值；用借用的字节查找
+    /// 已有的组，只在首次出现时才需要构造`String`。noodles的标签值只在解码时的
+    /// 临时借用内有效，因此以闭包的形式提供，字节不能带出闭包。标签不存在或
+    /// 类型不符时`f`收到None。
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::alloc::{GlobalAlloc, Layout, System};
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    /// use bamqc_io::{BamReader, BamWriter};
+    /// use noodles::sam::{self, alignment::RecordBuf};
+    /// use noodles::sam::alignment::record::data::field::Tag;
+    /// use noodles::sam::alignment::record_buf::data::field::Value;
+    ///
+    /// struct Counting;
+    /// static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+    /// unsafe impl GlobalAlloc for Counting {
+    ///     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+    ///         ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+    ///         System.alloc(layout)
+    ///     }
+    ///     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+    ///         System.dealloc(ptr, layout)
+    ///     }
+    /// }
+    /// #[global_allocator]
+    /// static GLOBAL: Counting = Counting;
+    ///
+    /// fn main() {
+    ///     let path = std::env::temp_dir().join(format!("bamqc-aux-{}.bam", std::process::id()));
+    ///     let header = sam::Header::default();
+    ///     let mut writer = BamWriter::from_path(&path, &header).unwrap();
+    ///     for i in 0..100 {
+    ///         let data = [
+    ///             (Tag::READ_GROUP, Value::from(if i % 2 == 0 { "rg1" } else { "rg2" })),
+    ///             (Tag::new(b'X', b'I'), Value::from(i)),
+    ///         ];
+    ///         let record = RecordBuf::builder().set_data(data.into_iter().collect()).build();
+    ///         writer.write_record_buf(&record).unwrap();
+    ///     }
+    ///     writer.finish().unwrap();
+    ///
+    ///     let mut reader = BamReader::from_path(&path).unwrap();
+    ///     let records: Vec<_> = reader.records().map(|r| r.unwrap()).collect();
+    ///     let before = ALLOCATIONS.load(Ordering::Relaxed);
+    ///     let rg1 = records
+    ///         .iter()
+    ///         .filter(|r| r.with_aux_bytes(*b"RG", |rg| rg == Some(b"rg1".as_slice())))
+    ///         .count();
+    ///     assert!(records.iter().all(|r| r.with_aux_bytes(*b"XI", |v| v.is_none())));
+    ///     assert!(records.iter().all(|r| r.with_aux_bytes(*b"BX", |v| v.is_none())));
+    ///     assert_eq!(ALLOCATIONS.load(Ordering::Relaxed), before);
+    ///     assert_eq!(rg1, 50);
+    ///     assert_eq!(records[1].string_tag(*b"RG").as_deref(), Some("rg2"));
+    ///     std::fs::remove_file(&path).unwrap();
+    /// }
+    /// ```
+    pub fn with_aux_bytes<R>(&self, tag: [u8; 2], f: impl FnOnce(Option<&[u8]>) -> R) -> R {
+        match self.inner.data().get(&tag) {
+            Some(Ok(Value::String(s))) => f(Some(s)),
+            _ => f(None),
+        }
+    }
+
+    /// 获取辅助标签值，标签不存在或无法解析时返回None
+    ///
+    /// 各种宽度的整数类型（c/C/s/S/i/I）统一为[`AuxValue::Int`]。
+    pub fn aux(&self, tag: [u8; 2]) -> Option<AuxValue> {
+        let data = self.inner.data();
+        let value = match data.get(&tag)? {
+            Ok(value) => value,
+            Err(_) => return None,
+        };
+        Some(match value {
+            Value::Character(c) => AuxValue::Char(c),
+            Value::Float(f) => AuxValue::Float(f),
+            Value::String(s) => AuxValue::String(decode_lossy(s).into_owned()),
+            Value::Hex(s) => AuxValue::Hex(decode_lossy(s).into_owned()),
+            Value::Array(_) => AuxValue::Array,
+            other => AuxValue::Int(other.as_int()?),
+        })
+    }
+
+    /// 整数类型（c/C/s/S/i/I）辅助标签的值；标签不存在、类型不符或无法解析时返回None
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bamqc_io::{BamError, BamReader, BamWriter};
+    /// use noodles::sam;
+    ///
+    /// let path = std::env::temp_dir().join(format!("bamqc-typed-aux-{}.bam", std::process::id()));
+    /// let text = "@HD\tVN:1.6\n@SQ\tSN:c1\tLN:1000\n@RG\tID:rg1\n\
+    ///             r1\t0\tc1\t100\t60\t4M\t*\t0\t0\tACGT\t*\tNM:i:2\tAS:i:-3\tXC:A:x\t\
+    ///             ZF:f:0.5\tMD:Z:1A0C1\tRG:Z:rg1\tRX:Z:ACGT-TTGA\tZB:B:C,1,2,255\tZI:B:i,1\n\
+    ///             r2\t4\t*\t0\t0\t*\t*\t0\t0\t*\t*\n";
+    /// let mut sam_reader = sam::io::Reader::new(text.as_bytes());
+    /// let header = sam_reader.read_header().unwrap();
+    /// let mut writer = BamWriter::from_path(&path, &header).unwrap();
+    /// for record in sam_reader.record_bufs(&header) {
+    ///     writer.write_record_buf(&record.unwrap()).unwrap();
+    /// }
+    /// writer.finish().unwrap();
+    /// let records: Vec<_> = BamReader::from_path(&path).unwrap().records().map(|r| r.unwrap()).collect();
+    /// let r = &records[0];
+    ///
+    /// // BAM中NM:i:2以最窄的类型（C）存储，AS:i:-3以c存储，都统一为i64
+    /// assert_eq!((r.aux_i64(*b"NM"), r.aux_i64(*b"AS")), (Some(2), Some(-3)));
+    /// assert_eq!(r.aux_f32(*b"ZF"), Some(0.5));
+    /// assert_eq!(r.aux_str(*b"MD").as_deref(), Some("1A0C1"));
+    /// assert_eq!(r.aux_str(*b"RX").as_deref(), Some("ACGT-TTGA"));
+    /// assert_eq!(r.aux_u8_array(*b"ZB"), Some(vec![1, 2, 255]));
+    /// assert_eq!(r.read_group_id().as_deref(), Some("rg1"));
+    /// assert_eq!(records[1].read_group_id(), None);
+    ///
+    /// // 类型不符：aux_*返回None，try_aux_*返回错误；标签不存在时两者都不是错误
+    /// assert_eq!(r.aux_i64(*b"MD"), None);
+    /// assert_eq!(r.aux_u8_array(*b"ZI"), None);
+    /// let e = r.try_aux_i64(*b"MD").unwrap_err();
+    /// assert!(matches!(&e, BamError::TagTypeMismatch { tag, expected: "i", found } if tag == "MD" && found == "Z"));
+    /// assert_eq!(e.to_string(), "辅助标签MD的类型为Z，期望i");
+    /// let e = r.try_aux_u8_array(*b"ZI").unwrap_err();
+    /// assert!(matches!(e, BamError::TagTypeMismatch { expected: "B:C", ref found, .. } if found == "B:i"));
+    /// assert!(matches!(r.try_aux_f32(*b"XC"), Err(BamError::TagTypeMismatch { .. })));
+    /// assert!(matches!(r.try_aux_str(*b"NM"), Err(BamError::TagTypeMismatch { .. })));
+    /// assert_eq!(r.try_aux_i64(*b"XX").unwrap(), None);
+    /// std::fs::remove_file(&path).unwrap();
+    /// ```
+    pub fn aux_i64(&self, tag: [u8; 2]) -> Option<i64> {
+        self.try_aux_i64(tag).ok().flatten()
+    }
+
+    /// 与[`BamRecord::aux_i64`]相同，但类型不符时返回[`BamError::TagTypeMismatch`]
+    pub fn try_aux_i64(&self, tag: [u8; 2]) -> Result<Option<i64>, BamError> {
+        self.try_aux_as(tag, "i", |value| match value {
+            Value::Character(_) | Value::Float(_) | Value::String(_) | Value::Hex(_) | Value::Array(_) => None,
+            other => other.as_int(),
+        })
+    }
+
+    /// 浮点类型（f）辅助标签的值；标签不存在、类型不符或无法解析时返回None
+    pub fn aux_f32(&self, tag: [u8; 2]) -> Option<f32> {
+        self.try_aux_f32(tag).ok().flatten()
+    }
+
+    /// 与[`BamRecord::aux_f32`]相同，但类型不符时返回[`BamError::TagTypeMismatch`]
+    pub fn try_aux_f32(&self, tag: [u8; 2]) -> Result<Option<f32>, BamError> {
+        self.try_aux_as(tag, "f", |value| match value {
+            Value::Float(f) => Some(*f),
+            _ => None,
+        })
+    }
+
+    /// 字符串类型（Z）辅助标签的值，非UTF-8字节按[`decode_lossy`]替换；
+    /// 标签不存在、类型不符或无法解析时返回None
+    ///
+    /// 只需比较或查找时用[`BamRecord::with_aux_bytes`]避免分配。
+    pub fn aux_str(&self, tag: [u8; 2]) -> Option<String> {
+        self.try_aux_str(tag).ok().flatten()
+    }
+
+    /// 与[`BamRecord::aux_str`]相同，但类型不符时返回[`BamError::TagTypeMismatch`]
+    pub fn try_aux_str(&self, tag: [u8; 2]) -> Result<Option<String>, BamError> {
+        self.try_aux_as(tag, "Z", |value| match value {
+            Value::String(s) => Some(decode_lossy(s).into_owned()),
+            _ => None,
+        })
+    }
+
+    /// `B:C`（u8数组）类型辅助标签的值；标签不存在、类型不符或无法解析时返回None
+    pub fn aux_u8_array(&self, tag: [u8; 2]) -> Option<Vec<u8>> {
+        self.try_aux_u8_array(tag).ok().flatten()
+    }
+
+    /// 与[`BamRecord::aux_u8_array`]相同，但类型不符时返回[`BamError::TagTypeMismatch`]
+    pub fn try_aux_u8_array(&self, tag: [u8; 2]) -> Result<Option<Vec<u8>>, BamError> {
+        let values = self.try_aux_as(tag, "B:C", |value| match value {
+            Value::Array(Array::UInt8(values)) => Some(values.iter().collect::<std::io::Result<Vec<u8>>>()),
+            _ => None,
+        })?;
+        values.transpose().map_err(|e| aux_decode_error(tag, e))
+    }
+
+    /// 读组ID（`RG:Z`）；没有RG标签或类型不符时返回None
+    pub fn read_group_id(&self) -> Option<String> {
+        self.aux_str(*b"RG")
+    }
+
+    /// 取辅助标签并用`f`转换；`f`返回None时为类型不符
+    fn try_aux_as<T>(
+        &self,
+        tag: [u8; 2],
+        expected: &'static str,
+        f: impl FnOnce(&Value<'_>) -> Option<T>,
+    ) -> Result<Option<T>, BamError> {
+        let data = self.inner.data();
+        let value = match data.get(&tag) {
+            None => return Ok(None),
+            Some(value) => value.map_err(|e| aux_decode_error(tag, e))?,
+        };
+        match f(&value) {
+            Some(value) => Ok(Some(value)),
+            None => Err(BamError::TagTypeMismatch {
+                tag: String::from_utf8_lossy(&tag).into_owned(),
+                expected,
+                found: aux_type_code(&value),
+            }),
+        }
+    }
+
+    /// 以SAM文本格式输出一行（11列必需字段加辅助标签，不含换行）
+    ///
+    /// 参考序列ID通过`header`转换为名称，mate在同一参考序列上时RNEXT为`=`；
+    /// 没有坐标的未比对记录RNAME为`*`、POS为0。读名与标签中的非UTF-8字节按
+    /// [`decode_lossy`]替换。只需在日志中标识记录时用[`Display`](std::fmt::Display)的摘要。
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bamqc_io::{BamReader, BamWriter};
+    /// use noodles::sam;
+    ///
+    /// let bam = std::env::temp_dir().join(format!("bamqc-to-sam-string-{}.bam", std::process::id()));
+    /// let lines = [
+    ///     "r1\t99\tc2\t100\t60\t10M\t=\t300\t210\tACGTACGTAC\tIIIIIIIIII\tNM:i:1\tRG:Z:lane1",
+    ///     "r2\t77\t*\t0\t0\t*\t*\t0\t0\tACGT\tIIII",
+    /// ];
+    /// let text = format!("@HD\tVN:1.6\n@SQ\tSN:c1\tLN:1000\n@SQ\tSN:c2\tLN:1000\n{}\n", lines.join("\n"));
+    /// let mut sam_reader = sam::io::Reader::new(text.as_bytes());
+    /// let header = sam_reader.read_header().unwrap();
+    /// let mut writer = BamWriter::from_path(&bam, &header).unwrap();
+    /// for record in sam_reader.record_bufs(&header) {
+    ///     writer.write_record_buf(&record.unwrap()).unwrap();
+    /// }
+    /// writer.finish().unwrap();
+    ///
+    /// let mut reader = BamReader::from_path(&bam).unwrap();
+    /// let records: Vec<_> = reader.records().map(Result::unwrap).collect();
+    /// for (record, line) in records.iter().zip(lines) {
+    ///     assert_eq!(record.to_sam_string(reader.header()).unwrap(), line);
+    /// }
+    /// assert_eq!(records[0].to_string(), "r1 flag=99 1:100 tlen=210");
+    /// assert_eq!(records[1].to_string(), "r2 flag=77 *:0 tlen=0");
+    /// # std::fs::remove_file(&bam).unwrap();
+    /// ```
+    pub fn to_sam_string(&self, header: &sam::Header) -> Result<String, BamError> {
+        use noodles::sam::alignment::io::Write as _;
+
+        let mut writer = sam::io::Writer::new(Vec::new());
+        writer.write_alignment_record(header, &self.inner)?;
+        let mut line = writer.into_inner();
+        if line.last() == Some(&b'\n') {
+            line.pop();
+        }
+        Ok(decode_lossy(&line).into_owned())
+    }
+}
+
+/// 日志用的摘要：`读名 flag=FLAG 参考序列ID:POS tlen=TLEN`
+///
+/// 没有头部，参考序列显示为ID（从0开始），POS从1开始；没有坐标时为`*:0`，
+/// 没有读名时为`*`。完整的SAM行见[`BamRecord::to_sam_string`]。
+impl std::fmt::Display for BamRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = self.name();
+        let name = if name.is_empty() { "*" } else { &name };
+        write!(f, "{} flag={} ", name, self.flag())?;
+        match self.tid() {
+            tid if tid < 0 => write!(f, "*:0")?,
+            tid => write!(f, "{}:{}", tid, self.pos() + 1)?,
+        }
+        write!(f, " tlen={}", self.insert_size())
+    }
+}
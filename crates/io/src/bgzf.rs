@@ -0,0 +1,133 @@
+//! BGZF块级自检
+//!
+//! 按规范自行解析每个BGZF块的框架（gzip头部、BC子字段、BSIZE、CRC32、ISIZE），
+//! 解压后核对CRC32与ISIZE，在解码任何记录之前定位损坏块的压缩偏移。
+//!
+//! noodles在正常读取时同样会核对CRC32，但错误信息不含块偏移，且可能在已经
+//! 输出部分结果之后才报错；自检模式在读取记录前完整扫描一遍文件。
+
+use crate::bam::BamError;
+use crate::progress::READ_PROGRESS;
+use flate2::read::DeflateDecoder;
+use flate2::Crc;
+use std::fs::File;
+use std::io::{BufReader, ErrorKind, Read};
+use std::path::Path;
+
+/// BGZF块头部长度（含6字节的BC额外字段）
+const HEADER_SIZE: usize = 18;
+/// BGZF块尾部长度（CRC32与ISIZE）
+const TRAILER_SIZE: usize = 8;
+/// BGZF块的最大长度
+const MAX_BLOCK_SIZE: usize = 1 << 16;
+
+/// 自检结果
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BgzfVerifySummary {
+    /// 块数（含EOF标记块）
+    pub blocks: u64,
+    /// 解压后的总字节数
+    pub uncompressed_bytes: u64,
+}
+
+/// 逐块核对CRC32与ISIZE
+///
+/// 块框架不合法或无法解压时返回[`BamError::BamError`]；解压成功但CRC32或
+/// ISIZE与尾部记录不一致时返回[`BamError::BgzfChecksum`]，带有该块的压缩偏移。
+///
+/// # Examples
+///
+/// ```
+/// use bamqc_io::{BamError, verify_bgzf};
+///
+/// // BGZF EOF标记块：空数据，CRC32为0
+/// let mut block = vec![
+///     0x1f, 0x8b, 0x08, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0x06, 0x00, 0x42, 0x43, 0x02,
+///     0x00, 0x1b, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+/// ];
+/// let summary = verify_bgzf(&block[..]).unwrap();
+/// assert_eq!(summary.blocks, 1);
+///
+/// // 改写第二个块的CRC32：deflate数据仍然合法，只有校验和不符
+/// let mut file = block.clone();
+/// block[20] = 0x01;
+/// file.extend_from_slice(&block);
+/// assert!(matches!(verify_bgzf(&file[..]), Err(BamError::BgzfChecksum { block_offset: 28 })));
+/// ```
+pub fn verify_bgzf<R: Read>(reader: R) -> Result<BgzfVerifySummary, BamError> {
+    let mut reader = reader;
+    let mut summary = BgzfVerifySummary::default();
+    let mut offset = 0u64;
+    let mut block = vec![0u8; MAX_BLOCK_SIZE];
+    let mut data = Vec::with_capacity(MAX_BLOCK_SIZE);
+
+    loop {
+        let header = &mut block[..HEADER_SIZE];
+        match read_or_eof(&mut reader, header)? {
+            0 => break,
+            n if n < HEADER_SIZE => return Err(invalid(offset, "块头部被截断")),
+            _ => {}
+        }
+        if header[..4] != [0x1f, 0x8b, 0x08, 0x04]
+            || header[10..12] != [0x06, 0x00]
+            || header[12..16] != [b'B', b'C', 0x02, 0x00]
+        {
+            return Err(invalid(offset, "不是BGZF块头部"));
+        }
+
+        let block_size = u16::from_le_bytes([header[16], header[17]]) as usize + 1;
+        if block_size < HEADER_SIZE + TRAILER_SIZE {
+            return Err(invalid(offset, "BSIZE过小"));
+        }
+        reader
+            .read_exact(&mut block[HEADER_SIZE..block_size])
+            .map_err(|_| invalid(offset, "块数据被截断"))?;
+
+        let (cdata, trailer) = block[HEADER_SIZE..block_size].split_at(block_size - HEADER_SIZE - TRAILER_SIZE);
+        let crc32 = u32::from_le_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+        let isize = u32::from_le_bytes([trailer[4], trailer[5], trailer[6], trailer[7]]);
+
+        data.clear();
+        DeflateDecoder::new(cdata)
+            .take(MAX_BLOCK_SIZE as u64 + 1)
+            .read_to_end(&mut data)
+            .map_err(|e| invalid(offset, &format!("无法解压: {}", e)))?;
+
+        let mut crc = Crc::new();
+        crc.update(&data);
+        if data.len() != isize as usize || crc.sum() != crc32 {
+            return Err(BamError::BgzfChecksum { block_offset: offset });
+        }
+
+        summary.blocks += 1;
+        summary.uncompressed_bytes += data.len() as u64;
+        offset += block_size as u64;
+        READ_PROGRESS.update(summary.blocks, offset);
+    }
+
+    Ok(summary)
+}
+
+/// 对文件做BGZF块级自检
+pub fn verify_bgzf_file<P: AsRef<Path>>(path: P) -> Result<BgzfVerifySummary, BamError> {
+    let file = File::open(path.as_ref())?;
+    verify_bgzf(BufReader::new(file))
+}
+
+/// 尽量填满`buf`，返回读到的字节数（在文件末尾时小于`buf.len()`）
+fn read_or_eof<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<usize, BamError> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(filled)
+}
+
+fn invalid(offset: u64, reason: &str) -> BamError {
+    BamError::BamError(format!("偏移 {} 处的BGZF块无效: {}", offset, reason))
+}
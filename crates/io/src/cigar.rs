@@ -0,0 +1,364 @@
+//! CIGAR操作
+//!
+//! 超过65535个操作的比对（超长nanopore读长）在BAM中无法写入16位的操作数字段，
+//! 按SAM规范，记录中的CIGAR改为占位符`kSmN`（k为读长，m为参考序列跨度），
+//! 真实CIGAR存放在`CG:B,I`标签中。[`BamRecord::cigar`](crate::BamRecord::cigar)
+//! 透明地使用CG中的真实操作，并通过[`CigarSource`]报告来源。
+
+use std::str::FromStr;
+#[cfg(feature = "serde")]
+use serde::Serialize;
+use thiserror::Error;
+use crate::error_code::ErrorCode;
+
+/// CIGAR操作类型
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CigarKind {
+    /// M
+    Match,
+    /// I
+    Insertion,
+    /// D
+    Deletion,
+    /// N
+    Skip,
+    /// S
+    SoftClip,
+    /// H
+    HardClip,
+    /// P
+    Pad,
+    /// =
+    SequenceMatch,
+    /// X
+    SequenceMismatch,
+}
+
+impl CigarKind {
+    fn from_symbol(symbol: char) -> Option<Self> {
+        Some(match symbol {
+            'M' => CigarKind::Match,
+            'I' => CigarKind::Insertion,
+            'D' => CigarKind::Deletion,
+            'N' => CigarKind::Skip,
+            'S' => CigarKind::SoftClip,
+            'H' => CigarKind::HardClip,
+            'P' => CigarKind::Pad,
+            '=' => CigarKind::SequenceMatch,
+            'X' => CigarKind::SequenceMismatch,
+            _ => return None,
+        })
+    }
+
+    fn from_code(code: u32) -> Option<Self> {
+        Some(match code {
+            0 => CigarKind::Match,
+            1 => CigarKind::Insertion,
+            2 => CigarKind::Deletion,
+            3 => CigarKind::Skip,
+            4 => CigarKind::SoftClip,
+            5 => CigarKind::HardClip,
+            6 => CigarKind::Pad,
+            7 => CigarKind::SequenceMatch,
+            8 => CigarKind::SequenceMismatch,
+            _ => return None,
+        })
+    }
+
+    /// 是否消耗参考序列（M、D、N、=、X）
+    pub fn consumes_reference(self) -> bool {
+        matches!(
+            self,
+            CigarKind::Match
+                | CigarKind::Deletion
+                | CigarKind::Skip
+                | CigarKind::SequenceMatch
+                | CigarKind::SequenceMismatch
+        )
+    }
+
+    /// 是否消耗读长序列（M、I、S、=、X）
+    pub fn consumes_read(self) -> bool {
+        matches!(
+            self,
+            CigarKind::Match
+                | CigarKind::Insertion
+                | CigarKind::SoftClip
+                | CigarKind::SequenceMatch
+                | CigarKind::SequenceMismatch
+        )
+    }
+
+    /// SAM文本中的操作字符
+    pub fn symbol(self) -> char {
+        match self {
+            CigarKind::Match => 'M',
+            CigarKind::Insertion => 'I',
+            CigarKind::Deletion => 'D',
+            CigarKind::Skip => 'N',
+            CigarKind::SoftClip => 'S',
+            CigarKind::HardClip => 'H',
+            CigarKind::Pad => 'P',
+            CigarKind::SequenceMatch => '=',
+            CigarKind::SequenceMismatch => 'X',
+        }
+    }
+}
+
+/// 单个CIGAR操作
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CigarOp {
+    pub kind: CigarKind,
+    pub len: u32,
+}
+
+/// 记录的CIGAR（拥有所有权的操作列表）
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Cigar {
+    ops: Vec<CigarOp>,
+}
+
+impl Cigar {
+    pub fn new(ops: Vec<CigarOp>) -> Self {
+        Self { ops }
+    }
+
+    /// 从BAM编码（每个操作一个小端u32：`len << 4 | op`）解码，含有非法操作时返回None
+    pub(crate) fn decode(raw: &[u8]) -> Option<Self> {
+        decode_ops(raw)?.collect::<Option<Vec<_>>>().map(Self::new)
+    }
+
+    pub fn ops(&self) -> &[CigarOp] {
+        &self.ops
+    }
+
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    /// 参考序列跨度（M、D、N、=、X之和）
+    pub fn reference_length(&self) -> u64 {
+        self.sum(CigarKind::consumes_reference)
+    }
+
+    /// 消耗的读长碱基数（M、I、S、=、X之和），应等于SEQ长度
+    pub fn query_length(&self) -> u64 {
+        self.sum(CigarKind::consumes_read)
+    }
+
+    /// 参与比对的读长碱基数（M、I、=、X之和），即[`Cigar::query_length`]去掉soft clip
+    pub fn query_alignment_length(&self) -> u64 {
+        self.sum(|kind| kind.consumes_read() && kind != CigarKind::SoftClip)
+    }
+
+    /// soft clip碱基数
+    pub fn soft_clipped_bases(&self) -> u64 {
+        self.sum(|kind| kind == CigarKind::SoftClip)
+    }
+
+    /// hard clip碱基数
+    pub fn hard_clipped_bases(&self) -> u64 {
+        self.sum(|kind| kind == CigarKind::HardClip)
+    }
+
+    /// 开头连续的soft与hard clip碱基数
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bamqc_io::Cigar;
+    ///
+    /// let cigar: Cigar = "2H3S10M4S".parse().unwrap();
+    /// assert_eq!((cigar.leading_clipped_bases(), cigar.trailing_clipped_bases()), (5, 4));
+    /// let cigar: Cigar = "10M".parse().unwrap();
+    /// assert_eq!((cigar.leading_clipped_bases(), cigar.trailing_clipped_bases()), (0, 0));
+    /// ```
+    pub fn leading_clipped_bases(&self) -> u64 {
+        Self::clipped(self.ops.iter())
+    }
+
+    /// 末尾连续的soft与hard clip碱基数
+    pub fn trailing_clipped_bases(&self) -> u64 {
+        Self::clipped(self.ops.iter().rev())
+    }
+
+    fn clipped<'a>(ops: impl Iterator<Item = &'a CigarOp>) -> u64 {
+        ops.take_while(|op| matches!(op.kind, CigarKind::SoftClip | CigarKind::HardClip))
+            .map(|op| u64::from(op.len))
+            .sum()
+    }
+
+    /// 比对到参考序列上的碱基块（M、=、X），`start`为比对起点（1-based），
+    /// 依次返回各块的1-based闭区间；D与N跳过参考序列但不产生块
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bamqc_io::Cigar;
+    ///
+    /// let cigar: Cigar = "5S10M3D5=100N2X4I3M".parse().unwrap();
+    /// let blocks: Vec<(u64, u64)> = cigar.aligned_blocks(101).collect();
+    /// assert_eq!(blocks, [(101, 110), (114, 118), (219, 220), (221, 223)]);
+    /// ```
+    pub fn aligned_blocks(&self, start: u64) -> impl Iterator<Item = (u64, u64)> + '_ {
+        let mut position = start;
+        self.ops.iter().filter_map(move |op| {
+            if !op.kind.consumes_reference() || op.len == 0 {
+                return None;
+            }
+            let block = (position, position + op.len as u64 - 1);
+            position += op.len as u64;
+            match op.kind {
+                CigarKind::Deletion | CigarKind::Skip => None,
+                _ => Some(block),
+            }
+        })
+    }
+
+    /// 是否为长CIGAR占位符`kSmN`（k为读长）
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bamqc_io::{Cigar, CigarKind, CigarOp};
+    ///
+    /// let placeholder = Cigar::new(vec![
+    ///     CigarOp { kind: CigarKind::SoftClip, len: 150 },
+    ///     CigarOp { kind: CigarKind::Skip, len: 1200 },
+    /// ]);
+    /// assert!(placeholder.is_long_placeholder(150));
+    /// assert!(!placeholder.is_long_placeholder(151));
+    /// ```
+    pub fn is_long_placeholder(&self, read_length: usize) -> bool {
+        matches!(
+            self.ops.as_slice(),
+            [CigarOp { kind: CigarKind::SoftClip, len: k }, CigarOp { kind: CigarKind::Skip, .. }]
+                if *k as usize == read_length
+        )
+    }
+
+    fn sum(&self, pred: impl Fn(CigarKind) -> bool) -> u64 {
+        self.ops
+            .iter()
+            .filter(|op| pred(op.kind))
+            .map(|op| op.len as u64)
+            .sum()
+    }
+}
+
+/// 逐个解码BAM编码的CIGAR操作，不分配内存；长度不是4的倍数时返回None，
+/// 非法的操作类型解码为None
+pub(crate) fn decode_ops(raw: &[u8]) -> Option<impl Iterator<Item = Option<CigarOp>> + '_> {
+    if !raw.len().is_multiple_of(4) {
+        return None;
+    }
+    Some(raw.chunks_exact(4).map(|chunk| {
+        let n = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        CigarKind::from_code(n & 0x0f).map(|kind| CigarOp { kind, len: n >> 4 })
+    }))
+}
+
+/// BAM编码的CIGAR中满足`pred`的操作长度之和，含有非法操作时返回None
+pub(crate) fn sum_encoded(raw: &[u8], pred: impl Fn(CigarKind) -> bool) -> Option<u64> {
+    decode_ops(raw)?.try_fold(0, |sum, op| {
+        let op = op?;
+        Some(if pred(op.kind) { sum + op.len as u64 } else { sum })
+    })
+}
+
+impl std::fmt::Display for Cigar {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.ops.is_empty() {
+            return write!(f, "*");
+        }
+        for op in &self.ops {
+            write!(f, "{}{}", op.len, op.kind.symbol())?;
+        }
+        Ok(())
+    }
+}
+
+/// CIGAR文本解析错误
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("无效的CIGAR: {0}")]
+pub struct ParseCigarError(pub String);
+
+impl ErrorCode for ParseCigarError {
+    fn code(&self) -> &'static str {
+        "invalid_cigar"
+    }
+
+    #[cfg(feature = "serde")]
+    fn serialize_details<M: serde::ser::SerializeMap>(&self, map: &mut M) -> Result<(), M::Error> {
+        map.serialize_entry("cigar", &self.0)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for ParseCigarError {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        crate::error_code::serialize_error(self, serializer)
+    }
+}
+
+/// 解析SAM文本形式的CIGAR，`*`为空CIGAR
+///
+/// # Examples
+///
+/// ```
+/// use bamqc_io::{Cigar, CigarKind};
+///
+/// let cigar: Cigar = "5S10M2I3D".parse().unwrap();
+/// assert_eq!(cigar.ops()[1].kind, CigarKind::Match);
+/// assert_eq!(cigar.query_length(), 17);
+/// assert_eq!(cigar.to_string(), "5S10M2I3D");
+/// assert!("*".parse::<Cigar>().unwrap().is_empty());
+/// assert!("10".parse::<Cigar>().is_err());
+/// assert!("M".parse::<Cigar>().is_err());
+/// ```
+impl FromStr for Cigar {
+    type Err = ParseCigarError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "*" {
+            return Ok(Self::default());
+        }
+        let invalid = || ParseCigarError(s.to_string());
+        let mut ops = Vec::new();
+        let mut len: Option<u32> = None;
+        for c in s.chars() {
+            match c.to_digit(10) {
+                Some(digit) => {
+                    let n = len.unwrap_or(0).checked_mul(10).and_then(|n| n.checked_add(digit));
+                    len = Some(n.ok_or_else(invalid)?);
+                }
+                None => {
+                    let kind = CigarKind::from_symbol(c).ok_or_else(invalid)?;
+                    ops.push(CigarOp { kind, len: len.take().ok_or_else(invalid)? });
+                }
+            }
+        }
+        if len.is_some() || ops.is_empty() {
+            return Err(invalid());
+        }
+        Ok(Self::new(ops))
+    }
+}
+
+/// CIGAR的来源
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum CigarSource {
+    /// 记录本身的CIGAR字段
+    Inline,
+    /// 记录中为`kSmN`占位符，真实操作取自`CG:B,I`标签
+    CgTag,
+    /// 记录中为占位符，但CG标签缺失或无效（类型不是`B,I`、含非法操作或
+    /// 读长与SEQ不符）。CG无法解析时使用占位符本身（比对终点仍然正确）；
+    /// CG可以解析但内容无效、或辅助字段中有长度超出记录的数组时，占位符无法取回，CIGAR为空
+    InvalidCg,
+}
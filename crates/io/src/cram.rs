@@ -0,0 +1,528 @@
+//! CRAM输入
+//!
+//! CRAM记录由noodles解码为`RecordBuf`后重新编码为BAM记录，因此上层看到的
+//! 仍是[`BamRecord`](crate::BamRecord)，各收集器无需区分输入格式。
+//!
+//! 参考序列压缩的CRAM必须提供参考FASTA才能解码：可以在打开时用
+//! [`BamReader::from_path_with_reference`](crate::BamReader::from_path_with_reference)
+//! 或[`BamReaderOptions::reference`](crate::BamReaderOptions::reference)指定。
+//! FASTA有`.fai`索引时按需读取，否则整个读入内存。缺少参考或参考中没有
+//! 对应序列时返回错误，而不是让noodles panic。
+
+use noodles::bam;
+use noodles::cram::{self, crai};
+use noodles::fasta::{self, repository::Adapter};
+use noodles::sam::{self, alignment::RecordBuf};
+use std::fs::File;
+use std::io::{self, BufReader, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::vec;
+use crate::log::info;
+
+use crate::bam::BamError;
+use crate::region::GenomicRegion;
+use crate::sam_text::RecordConverter;
+
+/// 没有参考：解码参考序列压缩的CRAM时返回错误
+fn no_reference() -> fasta::Repository {
+    fasta::Repository::new(ReferenceAdapter { inner: None, path: None })
+}
+
+/// 读取参考FASTA：有`.fai`索引时按需读取，否则整个读入内存
+fn load_reference<P: AsRef<Path>>(path: P) -> Result<fasta::Repository, BamError> {
+    let path = path.as_ref();
+    let path_str = path.to_string_lossy().to_string();
+    if !path.exists() {
+        return Err(BamError::FileNotFound { path: path_str });
+    }
+
+    let mut fai = path.as_os_str().to_os_string();
+    fai.push(".fai");
+    let inner: Box<dyn Adapter> = if Path::new(&fai).exists() {
+        let reader = fasta::io::indexed_reader::Builder::default().build_from_path(path)?;
+        Box::new(fasta::repository::adapters::IndexedReader::new(reader))
+    } else {
+        let records = fasta::io::reader::Builder
+            .build_from_path(path)?
+            .records()
+            .collect::<io::Result<Vec<_>>>()?;
+        info!("参考序列 {} 没有.fai索引，已整个读入内存（{} 条序列）", path_str, records.len());
+        Box::new(records)
+    };
+
+    Ok(fasta::Repository::new(ReferenceAdapter {
+        inner: Some(inner),
+        path: Some(path_str),
+    }))
+}
+
+/// 找不到参考序列时返回错误而不是None（noodles对None直接panic）
+struct ReferenceAdapter {
+    inner: Option<Box<dyn Adapter>>,
+    path: Option<String>,
+}
+
+impl Adapter for ReferenceAdapter {
+    fn get(&mut self, name: &[u8]) -> Option<io::Result<fasta::Record>> {
+        let found = self.inner.as_mut().and_then(|inner| inner.get(name));
+        Some(found.unwrap_or_else(|| {
+            let name = String::from_utf8_lossy(name);
+            let message = match &self.path {
+                Some(path) => format!("参考序列 {} 中没有 {}", path, name),
+                None => format!("解码CRAM需要参考序列 {}（请用--reference指定FASTA）", name),
+            };
+            Err(io::Error::new(io::ErrorKind::NotFound, message))
+        }))
+    }
+}
+
+fn cram_error(e: io::Error) -> BamError {
+    BamError::CramError(e.to_string())
+}
+
+/// 一个打开的CRAM文件
+pub(crate) struct CramSource {
+    reader: cram::io::Reader<BufReader<File>>,
+    repository: fasta::Repository,
+    /// 第一个数据容器的文件偏移（紧随头部容器之后）
+    records_start: u64,
+    index: Option<Arc<crai::Index>>,
+    /// 当前容器与其中尚未产出的记录：noodles按容器整体解码，缓存放在这里而不是
+    /// 迭代器中，中途丢弃迭代器后重新创建不会丢失记录
+    container: cram::io::reader::Container,
+    buffered: vec::IntoIter<RecordBuf>,
+    converter: RecordConverter,
+}
+
+impl CramSource {
+    /// 打开CRAM文件并读取头部；`reference`为None时没有参考
+    pub(crate) fn open(path: &Path, reference: Option<&Path>) -> Result<(Self, sam::Header), BamError> {
+        let repository = match reference {
+            Some(reference) => load_reference(reference)?,
+            None => no_reference(),
+        };
+        let mut reader = cram::io::reader::Builder::default()
+            .set_reference_sequence_repository(repository.clone())
+            .build_from_reader(BufReader::new(File::open(path)?));
+        let header = reader.read_header().map_err(cram_error)?;
+        crate::bam::count_header_parse();
+        let records_start = reader.position()?;
+
+        Ok((
+            Self {
+                reader,
+                repository,
+                records_start,
+                index: None,
+                container: Default::default(),
+                buffered: Vec::new().into_iter(),
+                converter: RecordConverter::default(),
+            },
+            header,
+        ))
+    }
+
+    /// 重新打开同一文件，从第一条记录开始读取
+    pub(crate) fn try_clone(&self, path: &Path) -> Result<Self, BamError> {
+        let mut reader = cram::io::reader::Builder::default()
+            .set_reference_sequence_repository(self.repository.clone())
+            .build_from_reader(BufReader::new(File::open(path)?));
+        reader.seek(SeekFrom::Start(self.records_start))?;
+
+        Ok(Self {
+            reader,
+            repository: self.repository.clone(),
+            records_start: self.records_start,
+            index: self.index.clone(),
+            container: Default::default(),
+            buffered: Vec::new().into_iter(),
+            converter: RecordConverter::default(),
+        })
+    }
+
+    pub(crate) fn records<'a>(&'a mut self, header: &'a sam::Header) -> CramRecords<'a> {
+        CramRecords { source: self, header }
+    }
+
+    /// 下一条记录，当前容器读完时解码下一个容器（与noodles的`Records`相同）
+    fn next_record(&mut self, header: &sam::Header) -> Option<Result<bam::Record, BamError>> {
+        loop {
+            if let Some(record) = self.buffered.next() {
+                return Some(self.converter.convert(header, &record));
+            }
+            match self.read_container_records(header) {
+                Ok(true) => return None,
+                Ok(false) => {}
+                Err(e) => return Some(Err(cram_error(e))),
+            }
+        }
+    }
+
+    /// 读取并解码下一个容器，文件结束时返回true
+    fn read_container_records(&mut self, header: &sam::Header) -> io::Result<bool> {
+        if self.reader.read_container(&mut self.container)? == 0 {
+            return Ok(true);
+        }
+        let compression_header = self.container.compression_header()?;
+        let mut records = Vec::new();
+        for slice in self.container.slices() {
+            let slice = slice?;
+            let (core_data_src, external_data_srcs) = slice.decode_blocks()?;
+            for record in slice.records(
+                self.repository.clone(),
+                header,
+                &compression_header,
+                &core_data_src,
+                &external_data_srcs,
+            )? {
+                records.push(RecordBuf::try_from_alignment_record(header, &record)?);
+            }
+        }
+        self.buffered = records.into_iter();
+        Ok(false)
+    }
+
+    /// 是否已加载索引或磁盘上有`.crai`文件
+    pub(crate) fn has_index(&self, path: &str) -> bool {
+        self.index.is_some() || crai_candidates(path).iter().any(|candidate| candidate.exists())
+    }
+
+    /// 加载`.crai`索引（已加载时不重复读取），返回索引文件的路径
+    pub(crate) fn ensure_index(&mut self, path: &str) -> Result<PathBuf, BamError> {
+        if self.index.is_some() {
+            if let Some(index_path) = crai_candidates(path).into_iter().find(|candidate| candidate.exists()) {
+                return Ok(index_path);
+            }
+        }
+        let (index, index_path) = load_index(path)?;
+        self.index = Some(Arc::new(index));
+        Ok(index_path)
+    }
+
+    /// 区域查询（需要`.crai`索引，首次查询时加载）
+    pub(crate) fn query<'a>(
+        &'a mut self,
+        path: &str,
+        header: &'a sam::Header,
+        region: &GenomicRegion,
+    ) -> Result<CramQuery<'a>, BamError> {
+        if self.index.is_none() {
+            self.index = Some(Arc::new(load_index(path)?.0));
+        }
+        let index = self.index.as_deref().unwrap();
+        // 查询会移动读取位置，顺序读取缓存的记录不再接续
+        self.buffered = Vec::new().into_iter();
+
+        let region = region.to_noodles()?;
+        let inner = self
+            .reader
+            .query(header, index, &region)
+            .map_err(|e| BamError::RegionError(e.to_string()))?;
+
+        Ok(CramQuery {
+            inner,
+            header,
+            converter: RecordConverter::default(),
+        })
+    }
+}
+
+/// CRAM索引的候选路径，见[`BamIndex::locate_crai`](crate::BamIndex::locate_crai)
+fn crai_candidates(cram_path: &str) -> [PathBuf; 2] {
+    crate::index::crai_candidates(Path::new(cram_path))
+}
+
+/// 查找并读取CRAM索引，依次尝试[`crai_candidates`]
+fn load_index(cram_path: &str) -> Result<(crai::Index, PathBuf), BamError> {
+    let candidates = crai_candidates(cram_path);
+    for candidate in &candidates {
+        if candidate.exists() {
+            return Ok((crai::fs::read(candidate)?, candidate.clone()));
+        }
+    }
+
+    Err(BamError::IndexNotFound { path: candidates[0].to_string_lossy().to_string() })
+}
+
+/// CRAM记录迭代器
+pub(crate) struct CramRecords<'a> {
+    source: &'a mut CramSource,
+    header: &'a sam::Header,
+}
+
+impl Iterator for CramRecords<'_> {
+    type Item = Result<bam::Record, BamError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.source.next_record(self.header)
+    }
+}
+
+/// CRAM区域查询迭代器
+pub(crate) struct CramQuery<'a> {
+    inner: cram::io::reader::Query<'a, 'a, 'a, BufReader<File>>,
+    header: &'a sam::Header,
+    converter: RecordConverter,
+}
+
+impl Iterator for CramQuery<'_> {
+    type Item = Result<bam::Record, BamError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(
+            self.inner
+                .next()?
+                .map_err(cram_error)
+                .and_then(|record| self.converter.convert(self.header, &record)),
+        )
+    }
+}
+
+/// CRAM文件写出器
+///
+/// 整个文件先在内存中组装，[`CramWriter::finish`]时一次写出，适合构造测试数据。
+/// noodles-cram 0.86写出的容器头部中切片偏移（landmark）有误，按它解码会报
+/// 压缩方法或校验和错误；写出前按实际的切片头部块位置修正，并重新计算容器头部的CRC32。
+///
+/// # Examples
+///
+/// 同样的记录分别写成BAM与CRAM，读出的内容一致：
+///
+/// ```
+/// use bamqc_io::{BamReader, BamWriter, CramWriter};
+/// use noodles::core::Position;
+/// use noodles::sam::{self, alignment::RecordBuf};
+/// use noodles::sam::alignment::record::{Flags, MappingQuality, cigar::{op::Kind, Op}};
+/// use noodles::sam::header::record::value::{map::ReferenceSequence, Map};
+/// use std::num::NonZeroUsize;
+///
+/// let dir = std::env::temp_dir().join(format!("bamqc-cram-{}", std::process::id()));
+/// std::fs::create_dir_all(&dir).unwrap();
+/// let fasta = dir.join("ref.fa");
+/// let sequence: String = (0..1000).map(|i| b"ACGGTCAT"[i * 7 % 8] as char).collect();
+/// std::fs::write(&fasta, format!(">c1\n{}\n", sequence)).unwrap();
+///
+/// let header = sam::Header::builder()
+///     .add_reference_sequence("c1", Map::<ReferenceSequence>::new(NonZeroUsize::new(1000).unwrap()))
+///     .build();
+/// let record = |name: &str, flags: u16, start: usize, mate: usize, tlen: i32| {
+///     let start = Position::try_from(start).unwrap();
+///     RecordBuf::builder()
+///         .set_name(name)
+///         .set_flags(Flags::from(flags))
+///         .set_reference_sequence_id(0)
+///         .set_alignment_start(start)
+///         .set_mapping_quality(MappingQuality::new(60).unwrap())
+///         .set_cigar([Op::new(Kind::Match, 50)].into_iter().collect())
+///         .set_mate_reference_sequence_id(0)
+///         .set_mate_alignment_start(Position::try_from(mate).unwrap())
+///         .set_template_length(tlen)
+///         .set_sequence(sequence.as_bytes()[usize::from(start) - 1..][..50].to_vec().into())
+///         .set_quality_scores(vec![30; 50].into())
+///         .build()
+/// };
+/// let records = [
+///     record("p1", 0x63, 101, 301, 250),
+///     record("p2", 0x63, 201, 451, 300),
+///     record("p1", 0x93, 301, 101, -250),
+///     record("p2", 0x93, 451, 201, -300),
+/// ];
+///
+/// let bam = dir.join("s.bam");
+/// let cram = dir.join("s.cram");
+/// let mut bam_writer = BamWriter::from_path(&bam, &header).unwrap();
+/// let mut cram_writer = CramWriter::from_path(&cram, &header, &fasta).unwrap();
+/// for record in &records {
+///     bam_writer.write_record_buf(record).unwrap();
+///     cram_writer.write_record_buf(record).unwrap();
+/// }
+/// bam_writer.finish().unwrap();
+/// cram_writer.finish().unwrap();
+///
+/// let to_sam = |mut reader: BamReader| {
+///     let header = reader.header().clone();
+///     reader.records().map(|r| r.unwrap().to_sam_string(&header).unwrap()).collect::<Vec<_>>()
+/// };
+/// let from_bam = to_sam(BamReader::from_path(&bam).unwrap());
+/// let from_cram = to_sam(BamReader::from_path_with_reference(&cram, Some(fasta.as_path())).unwrap());
+/// assert_eq!(from_bam.len(), 4);
+/// assert_eq!(from_cram, from_bam);
+///
+/// // 没有参考序列时报错而不是panic
+/// let mut reader = BamReader::from_path(&cram).unwrap();
+/// let error = reader.records().find_map(Result::err).unwrap();
+/// assert!(error.to_string().contains("--reference"), "{}", error);
+/// std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+pub struct CramWriter {
+    writer: cram::io::Writer<Vec<u8>>,
+    header: sam::Header,
+    path: String,
+}
+
+impl CramWriter {
+    /// 创建写出器并写入头部；参考序列压缩需要`reference`中包含头部的全部@SQ
+    pub fn from_path<P: AsRef<Path>, R: AsRef<Path>>(
+        path: P,
+        header: &sam::Header,
+        reference: R,
+    ) -> Result<Self, BamError> {
+        let path_str = path.as_ref().to_string_lossy().to_string();
+        File::create(&path)?;
+        let mut writer = cram::io::writer::Builder::default()
+            .set_reference_sequence_repository(load_reference(reference)?)
+            .build_from_writer(Vec::new());
+        writer.write_header(header).map_err(cram_error)?;
+
+        Ok(Self {
+            writer,
+            header: header.clone(),
+            path: path_str,
+        })
+    }
+
+    /// 写出一条记录
+    pub fn write_record_buf(&mut self, record: &RecordBuf) -> Result<(), BamError> {
+        use noodles::sam::alignment::io::Write as _;
+
+        self.writer.write_alignment_record(&self.header, record).map_err(cram_error)
+    }
+
+    /// 写出剩余记录与EOF容器并关闭文件
+    pub fn finish(mut self) -> Result<(), BamError> {
+        self.writer.try_finish(&self.header).map_err(cram_error)?;
+        let data = repair_landmarks(&self.writer.into_inner()).map_err(cram_error)?;
+        std::fs::write(&self.path, data)?;
+        info!("已写出CRAM文件: {}", self.path);
+        Ok(())
+    }
+}
+
+/// CRAM文件定义（magic、版本与文件ID）的长度
+const FILE_DEFINITION_LEN: usize = 26;
+
+/// 切片头部块的内容类型
+const SLICE_HEADER_CONTENT_TYPE: u8 = 2;
+
+/// 把每个容器头部的landmark改为其切片头部块在容器数据中的实际偏移
+fn repair_landmarks(data: &[u8]) -> io::Result<Vec<u8>> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+    if data.len() < FILE_DEFINITION_LEN {
+        return Err(invalid("CRAM文件定义不完整"));
+    }
+
+    let mut out = data[..FILE_DEFINITION_LEN].to_vec();
+    let mut pos = FILE_DEFINITION_LEN;
+    while pos < data.len() {
+        let start = pos;
+        let length = data
+            .get(pos..pos + 4)
+            .map(|b| i32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .and_then(|length| usize::try_from(length).ok())
+            .ok_or_else(|| invalid("容器长度无效"))?;
+        pos += 4;
+        // 参考序列ID、起点、跨度、记录数
+        for _ in 0..4 {
+            read_itf8(data, &mut pos)?;
+        }
+        // 记录计数器、碱基数
+        for _ in 0..2 {
+            skip_ltf8(data, &mut pos)?;
+        }
+        let block_count = read_itf8(data, &mut pos)?;
+        let prefix_end = pos;
+        let landmark_count = read_itf8(data, &mut pos)?;
+        let landmarks = (0..landmark_count)
+            .map(|_| read_itf8(data, &mut pos))
+            .collect::<io::Result<Vec<_>>>()?;
+        let header_end = pos + 4;
+        let body = data
+            .get(header_end..header_end + length)
+            .ok_or_else(|| invalid("容器数据不完整"))?;
+
+        let mut slice_offsets = Vec::new();
+        let mut offset = 0;
+        for _ in 0..block_count {
+            let content_type = *body.get(offset + 1).ok_or_else(|| invalid("块头部不完整"))?;
+            if content_type == SLICE_HEADER_CONTENT_TYPE {
+                slice_offsets.push(offset as u32);
+            }
+            let mut cursor = offset + 2;
+            read_itf8(body, &mut cursor)?;
+            let compressed_size = read_itf8(body, &mut cursor)?;
+            read_itf8(body, &mut cursor)?;
+            // 压缩数据之后是4字节CRC32
+            offset = cursor + compressed_size as usize + 4;
+        }
+
+        if slice_offsets.is_empty() || slice_offsets == landmarks {
+            out.extend_from_slice(&data[start..header_end]);
+        } else {
+            let mut header = data[start..prefix_end].to_vec();
+            write_itf8(&mut header, slice_offsets.len() as u32);
+            for &landmark in &slice_offsets {
+                write_itf8(&mut header, landmark);
+            }
+            let mut crc = flate2::Crc::new();
+            crc.update(&header);
+            header.extend_from_slice(&crc.sum().to_le_bytes());
+            out.extend_from_slice(&header);
+        }
+        out.extend_from_slice(body);
+        pos = header_end + length;
+    }
+
+    Ok(out)
+}
+
+/// 读取ITF8整数（按无符号解释）
+fn read_itf8(data: &[u8], pos: &mut usize) -> io::Result<u32> {
+    let truncated = || io::Error::new(io::ErrorKind::UnexpectedEof, "ITF8整数不完整");
+    let first = *data.get(*pos).ok_or_else(truncated)?;
+    let extra = first.leading_ones().min(4) as usize;
+    let bytes = data.get(*pos + 1..*pos + 1 + extra).ok_or_else(truncated)?;
+    *pos += 1 + extra;
+
+    let value = match extra {
+        0 => u32::from(first),
+        4 => {
+            let high = u32::from(first & 0x0f) << 28;
+            high | u32::from(bytes[0]) << 20 | u32::from(bytes[1]) << 12 | u32::from(bytes[2]) << 4 | u32::from(bytes[3] & 0x0f)
+        }
+        _ => bytes
+            .iter()
+            .fold(u32::from(first & (0x7f >> extra)), |value, &b| value << 8 | u32::from(b)),
+    };
+    Ok(value)
+}
+
+/// 跳过LTF8整数
+fn skip_ltf8(data: &[u8], pos: &mut usize) -> io::Result<()> {
+    let first = *data
+        .get(*pos)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "LTF8整数不完整"))?;
+    *pos += 1 + first.leading_ones() as usize;
+    Ok(())
+}
+
+fn write_itf8(buf: &mut Vec<u8>, value: u32) {
+    match value {
+        0..0x80 => buf.push(value as u8),
+        0x80..0x4000 => buf.extend_from_slice(&[0x80 | (value >> 8) as u8, value as u8]),
+        0x4000..0x20_0000 => buf.extend_from_slice(&[0xc0 | (value >> 16) as u8, (value >> 8) as u8, value as u8]),
+        0x20_0000..0x1000_0000 => buf.extend_from_slice(&[
+            0xe0 | (value >> 24) as u8,
+            (value >> 16) as u8,
+            (value >> 8) as u8,
+            value as u8,
+        ]),
+        _ => buf.extend_from_slice(&[
+            0xf0 | (value >> 28) as u8,
+            (value >> 20) as u8,
+            (value >> 12) as u8,
+            (value >> 4) as u8,
+            (value & 0x0f) as u8,
+        ]),
+    }
+}
@@ -0,0 +1,161 @@
+//! 错误的稳定代码与结构化形式
+//!
+//! 公开的错误类型都实现[`ErrorCode`]：每个变体有一个稳定的代码（snake_case），
+//! 版本之间不会改变，调用方应按代码而不是按消息文本分支。启用`serde`特性时，
+//! 错误序列化为`{"code", "message", "details"}`对象：`message`是显示文本，
+//! `details`是变体携带的结构化字段（路径、方向、阈值、偏移等），没有字段时为`{}`。
+//! 包装其它错误的变体（如`InsertSizeError::BamError`）沿用内层错误的代码与
+//! `details`，`message`为外层的显示文本。
+//!
+//! 代码与进程退出码的对应（[`ErrorCode::exit_code`]）：
+//!
+//! | 退出码 | 含义 |
+//! |--------|------|
+//! | 0 | 成功 |
+//! | 1 | 出错（[`EXIT_ERROR`]，绝大多数代码） |
+//! | 3 | QC门控未通过（不是错误，没有代码），或没有可用于计算的数据（代码`no_valid_reads`） |
+//! | 124 | 被看门狗取消（代码`timeout`、`io_stall`） |
+//!
+//! # Examples
+//!
+//! ```
+//! use bamqc_io::{BamError, ErrorCode, EXIT_ERROR};
+//!
+//! let e = BamError::FileNotFound { path: "in.bam".to_string() };
+//! assert_eq!(e.code(), "file_not_found");
+//! assert_eq!(e.exit_code(), EXIT_ERROR);
+//! ```
+//!
+//! `BamError`每个变体的JSON形式：
+//!
+//! ```
+//! # #[cfg(feature = "serde")]
+//! # {
+//! use bamqc_io::{BamError, Compression, Content, FileKind, Format, ParseCigarError};
+//!
+//! let not_found = std::io::Error::new(std::io::ErrorKind::NotFound, "没有该文件");
+//! let header = "@HD\tVN:x".parse::<noodles::sam::Header>().unwrap_err();
+//! let cases = [
+//!     (BamError::IoError(not_found), r#"{"code":"io_error","message":"IO错误: 没有该文件","details":{"kind":"NotFound"}}"#),
+//!     (BamError::BamError("截断".into()), r#"{"code":"bam_format","message":"BAM格式错误: 截断","details":{}}"#),
+//!     (BamError::FileNotFound { path: "a.bam".into() }, r#"{"code":"file_not_found","message":"文件不存在: a.bam","details":{"path":"a.bam"}}"#),
+//!     (
+//!         BamError::IndexNotFound { path: "a.bam".into() },
+//!         r#"{"code":"index_not_found","message":"找不到索引文件: a.bam（请先运行samtools index）","details":{"path":"a.bam"}}"#,
+//!     ),
+//!     (BamError::RegionError("chr1:x".into()), r#"{"code":"invalid_region","message":"区域格式错误: chr1:x","details":{}}"#),
+//!     (
+//!         BamError::UnknownFormat { path: "a.txt".into(), found: "空文件".into() },
+//!         r#"{"code":"unknown_format","message":"无法识别文件格式 a.txt: 空文件","details":{"path":"a.txt","found":"空文件"}}"#,
+//!     ),
+//!     (
+//!         BamError::NotABamFile { path: "r.fq".into(), detected: FileKind { content: Content::Fastq, compression: Compression::None } },
+//!         r#"{"code":"not_a_bam_file","message":"r.fq 不是BAM/SAM/CRAM文件，内容为FASTQ：FASTQ是未比对的读长，请先比对（如bwa mem）并排序生成BAM","details":{"path":"r.fq","detected":"FASTQ"}}"#,
+//!     ),
+//!     (
+//!         BamError::Truncated { path: "a.bam".into() },
+//!         r#"{"code":"truncated","message":"a.bam 缺少BGZF EOF标记块，文件可能被截断（例如复制或下载未完成），请重新生成或重新传输","details":{"path":"a.bam"}}"#,
+//!     ),
+//!     (
+//!         BamError::FormatMismatch { path: "a.bam".into(), expected: Format::Cram, found: "BAM magic".into() },
+//!         r#"{"code":"format_mismatch","message":"a.bam: 期望CRAM格式，实际内容为BAM magic","details":{"path":"a.bam","expected":"CRAM","found":"BAM magic"}}"#,
+//!     ),
+//!     (BamError::UnsupportedFormat(Format::Cram), r#"{"code":"unsupported_format","message":"暂不支持读取CRAM格式","details":{"format":"CRAM"}}"#),
+//!     (BamError::CramError("缺少参考".into()), r#"{"code":"cram_decode","message":"CRAM解码错误: 缺少参考","details":{}}"#),
+//!     (
+//!         BamError::BgzfChecksum { block_offset: 65536 },
+//!         r#"{"code":"bgzf_checksum","message":"BGZF块校验失败（CRC32或ISIZE不符），块的压缩偏移: 65536","details":{"block_offset":65536}}"#,
+//!     ),
+//!     (BamError::StdinUnsupported("区域查询".into()), r#"{"code":"stdin_unsupported","message":"标准输入不支持区域查询","details":{}}"#),
+//!     (
+//!         BamError::PendingMatesExceeded { limit: 1000, record_index: 5000 },
+//!         r#"{"code":"pending_mates_exceeded","message":"读到第5000条记录时等待mate的记录超过上限1000条（可先用samtools sort -n按读名排序，或提高上限）","details":{"limit":1000,"record_index":5000}}"#,
+//!     ),
+//!     (
+//!         BamError::TagTypeMismatch { tag: "NM".into(), expected: "i", found: "Z".into() },
+//!         r#"{"code":"tag_type_mismatch","message":"辅助标签NM的类型为Z，期望i","details":{"tag":"NM","expected":"i","found":"Z"}}"#,
+//!     ),
+//!     (
+//!         BamError::NotARecordBoundary { path: "a.bam".into(), offset: 65541, reason: "记录长度0不合理".into() },
+//!         r#"{"code":"not_a_record_boundary","message":"a.bam: 虚拟偏移65541处不是记录的起点（记录长度0不合理），无法从该处继续读取","details":{"path":"a.bam","offset":65541,"reason":"记录长度0不合理"}}"#,
+//!     ),
+//!     (
+//!         BamError::Unseekable { path: "a.sam".into(), format: Format::Sam },
+//!         r#"{"code":"unseekable","message":"a.sam是SAM输入，没有BGZF虚拟偏移，不支持定位","details":{"path":"a.sam","format":"SAM"}}"#,
+//!     ),
+//!     (
+//!         BamError::ReferenceMismatch { path: "L2.bam".into(), expected_from: "L1.bam".into(), diff: "- #2 chr2 500\n+ #2 chr2 501".into() },
+//!         r#"{"code":"reference_mismatch","message":"L2.bam的参考序列字典与L1.bam不同（名称、长度与顺序须完全一致），无法合并：\n- #2 chr2 500\n+ #2 chr2 501","details":{"path":"L2.bam","expected_from":"L1.bam","diff":"- #2 chr2 500\n+ #2 chr2 501"}}"#,
+//!     ),
+//!     (
+//!         BamError::Input { path: "L2.bam".into(), source: Box::new(BamError::BgzfChecksum { block_offset: 0 }) },
+//!         r#"{"code":"bgzf_checksum","message":"L2.bam: BGZF块校验失败（CRC32或ISIZE不符），块的压缩偏移: 0","details":{"block_offset":0,"input":"L2.bam"}}"#,
+//!     ),
+//!     (
+//!         BamError::InvalidSampleFraction { fraction: 1.5 },
+//!         r#"{"code":"invalid_sample_fraction","message":"抽样比例1.5无效，须在(0, 1]内","details":{"fraction":1.5}}"#,
+//!     ),
+//!     (
+//!         BamError::RecordReadError { path: "a.bam".into(), record_index: 183_002_114, reason: "invalid record length".into() },
+//!         r#"{"code":"record_read_error","message":"a.bam的第183002114条记录损坏，无法解码: invalid record length（可用samtools quickcheck检查文件完整性）","details":{"path":"a.bam","record_index":183002114,"reason":"invalid record length"}}"#,
+//!     ),
+//! ];
+//! for (error, expected) in &cases {
+//!     assert_eq!(serde_json::to_string(error).unwrap(), *expected);
+//! }
+//! let json = serde_json::to_value(BamError::SamError(header)).unwrap();
+//! assert_eq!((json["code"].as_str(), json["details"].as_object().map(|d| d.len())), (Some("sam_header"), Some(0)));
+//!
+//! let cigar = ParseCigarError("10Q".into());
+//! assert_eq!(serde_json::to_string(&cigar).unwrap(), r#"{"code":"invalid_cigar","message":"无效的CIGAR: 10Q","details":{"cigar":"10Q"}}"#);
+//! # }
+//! ```
+
+/// 出错时的进程退出码。
+pub const EXIT_ERROR: i32 = 1;
+
+/// 错误的稳定代码与结构化字段
+pub trait ErrorCode: std::fmt::Display {
+    /// 稳定的错误代码（snake_case）
+    fn code(&self) -> &'static str;
+
+    /// 该错误对应的进程退出码，默认为[`EXIT_ERROR`]
+    fn exit_code(&self) -> i32 {
+        EXIT_ERROR
+    }
+
+    /// 把变体携带的结构化字段写入`details`对象
+    #[cfg(feature = "serde")]
+    fn serialize_details<M: serde::ser::SerializeMap>(&self, map: &mut M) -> Result<(), M::Error>;
+}
+
+/// 按`{"code", "message", "details"}`序列化错误，供各错误类型的`Serialize`实现调用
+#[cfg(feature = "serde")]
+pub fn serialize_error<E: ErrorCode, S: serde::Serializer>(error: &E, serializer: S) -> Result<S::Ok, S::Error> {
+    use serde::ser::SerializeStruct;
+
+    let mut object = serializer.serialize_struct("Error", 3)?;
+    object.serialize_field("code", error.code())?;
+    object.serialize_field("message", &error.to_string())?;
+    object.serialize_field("details", &Details(error))?;
+    object.end()
+}
+
+#[cfg(feature = "serde")]
+struct Details<'a, E>(&'a E);
+
+#[cfg(feature = "serde")]
+impl<E: ErrorCode> serde::Serialize for Details<'_, E> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+
+        let mut map = serializer.serialize_map(None)?;
+        self.0.serialize_details(&mut map)?;
+        map.end()
+    }
+}
+
+/// IO错误的`kind`（`std::io::ErrorKind`的变体名），用于`details`
+pub fn io_error_kind(error: &std::io::Error) -> String {
+    format!("{:?}", error.kind())
+}
@@ -0,0 +1,106 @@
+//! 输入文件的廉价指纹
+//!
+//! 用于判断输入自上次运行以来是否变化，不读取整个文件：文件大小、修改时间，
+//! 以及开头与末尾各64 KiB原始字节的CRC32。BGZF块不超过64 KiB，因此首尾两段
+//! 分别覆盖第一个数据块（紧随头部）与EOF标记之前的最后一个数据块。原地改写
+//! 中间内容且保持大小与修改时间不变的情况检测不到。
+
+use flate2::Crc;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// 首尾各取的字节数
+const EDGE_BYTES: u64 = 1 << 16;
+
+/// 输入文件指纹
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct InputFingerprint {
+    /// 文件大小（字节）
+    pub size: u64,
+    /// 修改时间（Unix纪元以来的纳秒数，无法获取时为0）
+    pub modified_ns: u64,
+    /// 开头64 KiB的CRC32
+    pub head_crc32: u32,
+    /// 末尾64 KiB的CRC32
+    pub tail_crc32: u32,
+}
+
+fn crc32_of<R: Read>(reader: R, len: u64) -> io::Result<u32> {
+    let mut buf = Vec::with_capacity(len as usize);
+    reader.take(len).read_to_end(&mut buf)?;
+    let mut crc = Crc::new();
+    crc.update(&buf);
+    Ok(crc.sum())
+}
+
+/// 计算文件指纹，最多读取128 KiB
+///
+/// # Examples
+///
+/// ```
+/// use std::time::{Duration, SystemTime};
+/// use bamqc_io::fingerprint_file;
+///
+/// let path = std::env::temp_dir().join(format!("bamqc-fingerprint-{}", std::process::id()));
+/// std::fs::write(&path, vec![7u8; 200_000]).unwrap();
+/// let file = std::fs::File::options().write(true).open(&path).unwrap();
+/// file.set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000)).unwrap();
+/// let original = fingerprint_file(&path).unwrap();
+/// // 内容与时间不变时稳定
+/// assert_eq!(fingerprint_file(&path).unwrap(), original);
+///
+/// // touch：只改修改时间
+/// file.set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_001)).unwrap();
+/// assert_ne!(fingerprint_file(&path).unwrap(), original);
+///
+/// // 替换为等长的不同内容，并恢复修改时间：末尾的CRC32不同
+/// let mut content = vec![7u8; 200_000];
+/// content[199_999] = 8;
+/// std::fs::write(&path, &content).unwrap();
+/// file.set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000)).unwrap();
+/// let replaced = fingerprint_file(&path).unwrap();
+/// assert_eq!((replaced.size, replaced.modified_ns, replaced.head_crc32), (original.size, original.modified_ns, original.head_crc32));
+/// assert_ne!(replaced.tail_crc32, original.tail_crc32);
+/// std::fs::remove_file(&path).unwrap();
+/// ```
+pub fn fingerprint_file<P: AsRef<Path>>(path: P) -> io::Result<InputFingerprint> {
+    let mut file = File::open(path)?;
+    let metadata = file.metadata()?;
+    let size = metadata.len();
+    let modified_ns = metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |elapsed| elapsed.as_nanos() as u64);
+
+    let head_crc32 = crc32_of(&mut file, EDGE_BYTES)?;
+    file.seek(SeekFrom::Start(size.saturating_sub(EDGE_BYTES)))?;
+    let tail_crc32 = crc32_of(&mut file, EDGE_BYTES)?;
+
+    Ok(InputFingerprint {
+        size,
+        modified_ns,
+        head_crc32,
+        tail_crc32,
+    })
+}
+
+/// 整个文件的CRC32（流式读取）
+pub fn file_crc32<P: AsRef<Path>>(path: P) -> io::Result<u32> {
+    let mut file = File::open(path)?;
+    let mut crc = Crc::new();
+    let mut buf = vec![0; 1 << 16];
+    loop {
+        match file.read(&mut buf) {
+            Ok(0) => return Ok(crc.sum()),
+            Ok(n) => crc.update(&buf[..n]),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+}
@@ -0,0 +1,321 @@
+//! 输入文件格式识别
+//!
+//! 通过文件开头的字节判断BAM/SAM/CRAM格式，并在显式指定的格式与
+//! 实际内容不符时给出具体的诊断信息。
+
+use crate::bam::BamError;
+use flate2::read::MultiGzDecoder;
+use noodles::bgzf::io::Reader as BgzfReader;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const BAM_MAGIC: &[u8; 4] = b"BAM\x01";
+const CRAM_MAGIC: &[u8; 4] = b"CRAM";
+
+/// 输入文件格式
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Default)]
+pub enum Format {
+    /// 根据文件内容自动识别
+    #[default]
+    Auto,
+    /// BAM（BGZF压缩的二进制格式）
+    Bam,
+    /// SAM文本
+    Sam,
+    /// CRAM
+    Cram,
+}
+
+impl std::fmt::Display for Format {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Format::Auto => write!(f, "auto"),
+            Format::Bam => write!(f, "BAM"),
+            Format::Sam => write!(f, "SAM"),
+            Format::Cram => write!(f, "CRAM"),
+        }
+    }
+}
+
+impl std::str::FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "auto" => Ok(Format::Auto),
+            "bam" => Ok(Format::Bam),
+            "sam" => Ok(Format::Sam),
+            "cram" => Ok(Format::Cram),
+            _ => Err(format!("未知格式: {}（可选: auto, bam, sam, cram）", s)),
+        }
+    }
+}
+
+/// 根据文件开头字节识别出的内容类型
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Sniffed {
+    /// BGZF压缩且解压后以`BAM\1`开头
+    Bam,
+    /// CRAM
+    Cram,
+    /// SAM文本
+    Sam,
+    /// 无法作为比对文件读取的内容（空文件、FASTQ、压缩的SAM等）
+    Other(FileKind),
+}
+
+impl Sniffed {
+    /// 对识别结果的描述，用于错误信息
+    pub fn describe(&self) -> String {
+        match self {
+            Sniffed::Bam => "BAM magic".to_string(),
+            Sniffed::Cram => "CRAM magic".to_string(),
+            Sniffed::Sam => "SAM文本".to_string(),
+            Sniffed::Other(kind) => kind.to_string(),
+        }
+    }
+
+    /// 对应的格式（无法直接读取的内容返回None）
+    pub fn format(&self) -> Option<Format> {
+        match self {
+            Sniffed::Bam => Some(Format::Bam),
+            Sniffed::Sam => Some(Format::Sam),
+            Sniffed::Cram => Some(Format::Cram),
+            Sniffed::Other(_) => None,
+        }
+    }
+}
+
+/// 压缩方式
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    /// 未压缩
+    None,
+    /// 普通gzip（非BGZF）
+    Gzip,
+    /// BGZF
+    Bgzf,
+}
+
+/// 非比对文件的内容
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Content {
+    /// 空文件
+    Empty,
+    /// SAM文本（压缩的SAM无法直接读取）
+    Sam,
+    /// FASTQ
+    Fastq,
+    /// FASTA
+    Fasta,
+    /// VCF
+    Vcf,
+    /// 其它文本
+    Text,
+    /// 无法识别的二进制内容
+    Binary,
+}
+
+/// 无法作为比对文件读取的输入实际是什么，见[`BamError::NotABamFile`]
+///
+/// gzip与BGZF压缩的内容按解压后的开头字节识别。
+///
+/// # Examples
+///
+/// ```
+/// use bamqc_io::{BamError, BamReader, Compression, Content, FileKind};
+/// use std::io::Write;
+///
+/// let dir = std::env::temp_dir().join(format!("bamqc-file-kind-{}", std::process::id()));
+/// std::fs::create_dir_all(&dir).unwrap();
+/// let fastq = dir.join("reads.fq.gz");
+/// let mut gz = flate2::write::GzEncoder::new(std::fs::File::create(&fastq).unwrap(), flate2::Compression::default());
+/// gz.write_all(b"@read1\nACGT\n+\nIIII\n").unwrap();
+/// gz.finish().unwrap();
+///
+/// match BamReader::from_path(&fastq).unwrap_err() {
+///     BamError::NotABamFile { detected, .. } => {
+///         assert_eq!(detected, FileKind { content: Content::Fastq, compression: Compression::Gzip });
+///         assert_eq!(detected.to_string(), "gzip压缩的FASTQ");
+///     }
+///     e => panic!("{}", e),
+/// }
+///
+/// let fasta = dir.join("ref.fa");
+/// std::fs::write(&fasta, ">c1\nACGT\n").unwrap();
+/// let e = BamReader::from_path(&fasta).unwrap_err();
+/// assert!(e.to_string().contains("内容为FASTA"), "{}", e);
+/// std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FileKind {
+    pub content: Content,
+    pub compression: Compression,
+}
+
+impl FileKind {
+    /// 针对该类型的处理建议
+    pub fn hint(&self) -> &'static str {
+        match (self.content, self.compression) {
+            (Content::Empty, _) => "文件为空，请检查上游步骤是否成功完成",
+            (Content::Sam, _) => "压缩的SAM无法直接读取，请先解压，或用samtools view -b转换为BAM",
+            (Content::Fastq, _) => "FASTQ是未比对的读长，请先比对（如bwa mem）并排序生成BAM",
+            (Content::Fasta, _) => "这是参考序列FASTA，读取CRAM时用--reference指定",
+            (Content::Vcf, _) => "这是变异文件VCF，请输入比对结果BAM/SAM/CRAM",
+            (Content::Text, _) | (Content::Binary, _) => "请确认输入为BAM、SAM或CRAM文件",
+        }
+    }
+}
+
+impl std::fmt::Display for FileKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.compression {
+            Compression::None => {}
+            Compression::Gzip => write!(f, "gzip压缩的")?,
+            Compression::Bgzf => write!(f, "BGZF压缩的")?,
+        }
+        match self.content {
+            Content::Empty => write!(f, "空文件"),
+            Content::Sam => write!(f, "SAM文本"),
+            Content::Fastq => write!(f, "FASTQ"),
+            Content::Fasta => write!(f, "FASTA"),
+            Content::Vcf => write!(f, "VCF"),
+            Content::Text => write!(f, "无法识别的文本"),
+            Content::Binary => write!(f, "无法识别的二进制内容"),
+        }
+    }
+}
+
+fn looks_like_sam(bytes: &[u8]) -> bool {
+    // 头部行：@与两个字母的记录类型后接制表符（FASTQ的读名行不会这样开头）
+    if bytes.len() >= 4 && bytes[0] == b'@' && bytes[1..3].iter().all(u8::is_ascii_alphabetic) && bytes[3] == b'\t' {
+        return true;
+    }
+    // 无头部的SAM：第一行至少包含11个制表符分隔的字段
+    let line = bytes.split(|&b| b == b'\n').next().unwrap_or(bytes);
+    line.iter().all(|&b| b == b'\t' || b == b'\r' || (0x20..0x7f).contains(&b))
+        && line.iter().filter(|&&b| b == b'\t').count() >= 10
+}
+
+/// 识别解压后（或未压缩）的文本内容
+fn classify_content(bytes: &[u8]) -> Content {
+    if bytes.is_empty() {
+        return Content::Empty;
+    }
+    if looks_like_sam(bytes) {
+        return Content::Sam;
+    }
+    let mut lines = bytes.split(|&b| b == b'\n');
+    match bytes[0] {
+        b'@' if lines.nth(2).is_some_and(|line| line.starts_with(b"+")) => return Content::Fastq,
+        b'>' => return Content::Fasta,
+        _ if bytes.starts_with(b"##fileformat=VCF") => return Content::Vcf,
+        _ => {}
+    }
+    let text = bytes.iter().all(|&b| b.is_ascii_graphic() || b.is_ascii_whitespace() || b >= 0x80);
+    if text { Content::Text } else { Content::Binary }
+}
+
+/// 读取文件开头的字节并识别内容类型
+pub fn sniff<P: AsRef<Path>>(path: P) -> Result<Sniffed, BamError> {
+    let mut file = File::open(path.as_ref())?;
+    let mut head = [0u8; 512];
+    let n = read_up_to(&mut file, &mut head)?;
+    let sniffed = sniff_head(&head[..n]);
+    if sniffed != Sniffed::Bam {
+        return Ok(sniffed);
+    }
+
+    // BGZF：解压后确认是BAM还是其它压缩内容（如bgzip压缩的SAM或FASTQ）
+    let mut reader = BgzfReader::new(File::open(path.as_ref())?);
+    let mut inner = [0u8; 512];
+    let n = read_prefix(&mut reader, &mut inner);
+    if inner[..n].starts_with(BAM_MAGIC) {
+        Ok(Sniffed::Bam)
+    } else {
+        Ok(Sniffed::Other(FileKind { content: classify_content(&inner[..n]), compression: Compression::Bgzf }))
+    }
+}
+
+/// 只根据开头的字节识别内容类型，BGZF压缩的内容不解压、一律视为BAM
+///
+/// 用于无法回退重读的输入（标准输入）。
+pub(crate) fn sniff_head(head: &[u8]) -> Sniffed {
+    if head.starts_with(CRAM_MAGIC) {
+        return Sniffed::Cram;
+    }
+
+    if head.starts_with(&GZIP_MAGIC) {
+        // BGZF：FLG.FEXTRA置位且extra子字段为"BC"
+        let is_bgzf = head.len() >= 14 && head[3] & 0x04 != 0 && &head[12..14] == b"BC";
+        if is_bgzf {
+            return Sniffed::Bam;
+        }
+        // 普通gzip：尽量解压开头的字节（数据不完整时解压到出错为止）
+        let mut inner = [0u8; 512];
+        let n = read_prefix(&mut MultiGzDecoder::new(head), &mut inner);
+        let content = if n == 0 { Content::Binary } else { classify_content(&inner[..n]) };
+        return Sniffed::Other(FileKind { content, compression: Compression::Gzip });
+    }
+
+    match classify_content(head) {
+        Content::Sam => Sniffed::Sam,
+        content => Sniffed::Other(FileKind { content, compression: Compression::None }),
+    }
+}
+
+fn read_up_to<R: Read>(reader: &mut R, buf: &mut [u8]) -> std::io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..])? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    Ok(filled)
+}
+
+/// 同[`read_up_to`]，但出错时（例如只取了压缩流的开头）返回已读到的字节数
+fn read_prefix<R: Read>(reader: &mut R, buf: &mut [u8]) -> usize {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) | Err(_) => break,
+            Ok(n) => filled += n,
+        }
+    }
+    filled
+}
+
+/// 解析实际要使用的格式
+///
+/// `Format::Auto`时返回识别结果；显式指定格式时若内容与指定格式不符，返回描述
+/// 实际内容的错误。[`BamReader`](crate::BamReader)打开显式指定格式的文件时不调用
+/// 这里，只在按该格式打开失败后识别内容以给出诊断。
+pub fn resolve_format<P: AsRef<Path>>(path: P, format: Format) -> Result<Format, BamError> {
+    let path_str = path.as_ref().to_string_lossy().to_string();
+    if !path.as_ref().exists() {
+        return Err(BamError::FileNotFound { path: path_str });
+    }
+
+    let sniffed = sniff(path.as_ref())?;
+    check_sniffed(&path_str, format, sniffed)
+}
+
+/// 把识别结果与指定的格式比对，规则同[`resolve_format`]
+pub(crate) fn check_sniffed(path: &str, format: Format, sniffed: Sniffed) -> Result<Format, BamError> {
+    if let (Format::Auto, Sniffed::Other(detected)) = (format, sniffed) {
+        return Err(BamError::NotABamFile { path: path.to_string(), detected });
+    }
+    match (format, sniffed.format()) {
+        (Format::Auto, Some(detected)) => Ok(detected),
+        (expected, Some(detected)) if expected == detected => Ok(expected),
+        (expected, _) => Err(BamError::FormatMismatch {
+            path: path.to_string(),
+            expected,
+            found: sniffed.describe(),
+        }),
+    }
+}
@@ -0,0 +1,342 @@
+//! BAI/CSI索引检查
+//!
+//! 统一封装noodles的BAI与CSI读取器，提取每条参考序列的bin/chunk数量以及
+//! 元数据伪bin中的已比对/未比对记录数，并与BAM文件本身做快速比对，
+//! 发现过期（stale）的索引。CRAM的`.crai`没有这些统计，只比较修改时间。
+
+use crate::bam::{BamError, BamReader};
+use noodles::bam::bai;
+use noodles::csi::{self, BinningIndex as _};
+use noodles::csi::binning_index::ReferenceSequence as _;
+use noodles::csi::binning_index::index::reference_sequence::Index as LinearIndex;
+use noodles::sam;
+#[cfg(feature = "serde")]
+use serde::Serialize;
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// BAM不大于此大小时，[`BamIndex::check`]另外扫描全部记录，核对索引中的记录计数
+pub const RECORD_COUNT_CHECK_MAX_BYTES: u64 = 16 * 1024 * 1024;
+
+const BAI_MAGIC: &[u8; 4] = b"BAI\x01";
+const CSI_MAGIC: &[u8; 4] = b"CSI\x01";
+
+/// BGZF文件末尾的空块（EOF标记）
+const BGZF_EOF: [u8; 28] = [
+    0x1f, 0x8b, 0x08, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0x06, 0x00, 0x42, 0x43, 0x02,
+    0x00, 0x1b, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
+
+/// CRAM 3.x文件末尾的EOF容器
+const CRAM_EOF: [u8; 38] = [
+    0x0f, 0x00, 0x00, 0x00, 0xff, 0xff, 0xff, 0xff, 0x0f, 0xe0, 0x45, 0x4f, 0x46, 0x00, 0x00, 0x00,
+    0x00, 0x01, 0x00, 0x05, 0xbd, 0xd9, 0x4f, 0x00, 0x01, 0x00, 0x06, 0x06, 0x01, 0x00, 0x01, 0x00,
+    0x01, 0x00, 0xee, 0x63, 0x01, 0x4b,
+];
+
+/// 索引格式
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "UPPERCASE"))]
+pub enum IndexFormat {
+    Bai,
+    Csi,
+    /// CRAM索引
+    Crai,
+}
+
+impl std::fmt::Display for IndexFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IndexFormat::Bai => write!(f, "BAI"),
+            IndexFormat::Csi => write!(f, "CSI"),
+            IndexFormat::Crai => write!(f, "CRAI"),
+        }
+    }
+}
+
+/// 索引中单条参考序列的统计
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct IndexReferenceStats {
+    /// bin数量（不含元数据伪bin）
+    pub bins: usize,
+    /// chunk数量
+    pub chunks: usize,
+    /// 已比对记录数；索引没有元数据伪bin时为None
+    pub mapped: Option<u64>,
+    /// 有坐标但未比对的记录数；索引没有元数据伪bin时为None
+    pub unmapped: Option<u64>,
+}
+
+/// 单条参考序列的记录计数（samtools idxstats的一行），见[`crate::BamReader::index_stats`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct ReferenceStats {
+    /// 参考序列名；最后一行为`*`，对应没有坐标的未比对记录
+    pub name: String,
+    /// 参考序列长度；`*`行为0
+    pub length: u64,
+    /// 已比对记录数
+    pub mapped: u64,
+    /// 未比对记录数（放置在该参考序列上的，或`*`行中没有坐标的）
+    pub unmapped: u64,
+    /// 计数取自索引元数据时为true；索引没有元数据、改为扫描全部记录时为false
+    pub exact: bool,
+}
+
+/// 索引与BAM不一致的迹象
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum IndexProblem {
+    /// 索引文件的修改时间早于BAM
+    OlderThanBam,
+    /// 索引中的参考序列数与BAM头部不一致
+    ReferenceCountMismatch { index: usize, header: usize },
+    /// 索引引用的压缩偏移超出BAM文件大小（BAM被截断或重写过）
+    OffsetBeyondEof { offset: u64, file_size: u64 },
+    /// 索引元数据中的已比对/未比对记录总数与BAM中的记录不符（BAM被重写过）
+    RecordCountMismatch { index_mapped: u64, index_unmapped: u64, bam_mapped: u64, bam_unmapped: u64 },
+}
+
+impl std::fmt::Display for IndexProblem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IndexProblem::OlderThanBam => write!(f, "索引文件比BAM旧"),
+            IndexProblem::ReferenceCountMismatch { index, header } => {
+                write!(f, "索引中有 {} 条参考序列，BAM头部有 {} 条", index, header)
+            }
+            IndexProblem::OffsetBeyondEof { offset, file_size } => write!(
+                f,
+                "索引引用的压缩偏移 {} 超出BAM文件大小 {}",
+                offset, file_size
+            ),
+            IndexProblem::RecordCountMismatch { index_mapped, index_unmapped, bam_mapped, bam_unmapped } => write!(
+                f,
+                "索引中有 {} 条已比对、{} 条未比对记录，BAM中为 {} 条与 {} 条",
+                index_mapped, index_unmapped, bam_mapped, bam_unmapped
+            ),
+        }
+    }
+}
+
+/// 已解析的BAI或CSI索引
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct BamIndex {
+    /// 索引文件路径
+    pub path: PathBuf,
+    /// 索引格式
+    pub format: IndexFormat,
+    /// 按参考序列ID排列的统计
+    pub references: Vec<IndexReferenceStats>,
+    /// 没有坐标的未比对记录数（索引末尾的可选字段）
+    pub unplaced_unmapped: Option<u64>,
+    /// 所有chunk中最大的压缩偏移
+    pub max_compressed_offset: u64,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    modified: Option<SystemTime>,
+}
+
+impl BamIndex {
+    /// 读取索引文件，按文件开头的magic区分BAI与CSI
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, BamError> {
+        let path = path.as_ref();
+        let path_str = path.to_string_lossy().to_string();
+        if !path.exists() {
+            return Err(BamError::IndexNotFound { path: path_str });
+        }
+
+        let mut magic = [0u8; 4];
+        File::open(path)?.read_exact(&mut magic).map_err(|_| BamError::UnknownFormat {
+            path: path_str.clone(),
+            found: "文件过短".to_string(),
+        })?;
+        let modified = fs::metadata(path)?.modified().ok();
+
+        let mut index = match &magic {
+            m if m == BAI_MAGIC => Self::from_binning_index(&bai::fs::read(path)?, IndexFormat::Bai),
+            m if m == CSI_MAGIC => Self::from_binning_index(&csi::fs::read(path)?, IndexFormat::Csi),
+            _ => {
+                return Err(BamError::UnknownFormat {
+                    path: path_str,
+                    found: "既不是BAI也不是CSI索引".to_string(),
+                })
+            }
+        };
+        index.path = path.to_path_buf();
+        index.modified = modified;
+        Ok(index)
+    }
+
+    /// 查找BAM对应的索引：依次尝试`<bam>.bai`、`<bam>.csi`和替换扩展名的`.bai`
+    pub fn locate<P: AsRef<Path>>(bam_path: P) -> Option<PathBuf> {
+        let bam_path = bam_path.as_ref();
+        let mut candidates = Vec::new();
+        for ext in ["bai", "csi"] {
+            let mut name = bam_path.as_os_str().to_os_string();
+            name.push(".");
+            name.push(ext);
+            candidates.push(PathBuf::from(name));
+        }
+        candidates.push(bam_path.with_extension("bai"));
+        candidates.into_iter().find(|candidate| candidate.exists())
+    }
+
+    /// 查找CRAM对应的索引：依次尝试`<cram>.crai`和替换扩展名的`.crai`
+    pub fn locate_crai<P: AsRef<Path>>(cram_path: P) -> Option<PathBuf> {
+        crai_candidates(cram_path.as_ref()).into_iter().find(|candidate| candidate.exists())
+    }
+
+    pub(crate) fn from_binning_index<I: LinearIndex>(
+        index: &csi::binning_index::Index<I>,
+        format: IndexFormat,
+    ) -> Self {
+        let mut max_compressed_offset = 0;
+        let references = index
+            .reference_sequences()
+            .iter()
+            .map(|reference| {
+                let mut chunks = 0;
+                for bin in reference.bins().values() {
+                    chunks += bin.chunks().len();
+                    for chunk in bin.chunks() {
+                        max_compressed_offset = max_compressed_offset.max(chunk.end().compressed());
+                    }
+                }
+                let metadata = reference.metadata();
+                IndexReferenceStats {
+                    bins: reference.bins().len(),
+                    chunks,
+                    mapped: metadata.map(|m| m.mapped_record_count()),
+                    unmapped: metadata.map(|m| m.unmapped_record_count()),
+                }
+            })
+            .collect();
+
+        Self {
+            path: PathBuf::new(),
+            format,
+            references,
+            unplaced_unmapped: index.unplaced_unmapped_record_count(),
+            max_compressed_offset,
+            modified: None,
+        }
+    }
+
+    /// 索引是否包含每条参考序列的元数据伪bin（记录计数）
+    pub fn has_metadata(&self) -> bool {
+        self.references
+            .iter()
+            .all(|r| r.bins == 0 || r.mapped.is_some())
+    }
+
+    /// 与BAM文件做快速比对：修改时间、参考序列数、索引偏移是否超出文件末尾
+    ///
+    /// 以上都没有问题、索引带有记录计数且BAM不大于[`RECORD_COUNT_CHECK_MAX_BYTES`]时，
+    /// 另外扫描全部记录，核对已比对/未比对记录总数；更大的BAM只读取文件元数据和已解析的头部。
+    pub fn check<P: AsRef<Path>>(
+        &self,
+        bam_path: P,
+        header: &sam::Header,
+    ) -> Result<Vec<IndexProblem>, BamError> {
+        let metadata = fs::metadata(bam_path.as_ref())?;
+        let mut problems = Vec::new();
+
+        if let (Some(index_time), Ok(bam_time)) = (self.modified, metadata.modified()) {
+            if index_time < bam_time {
+                problems.push(IndexProblem::OlderThanBam);
+            }
+        }
+
+        let header_count = header.reference_sequences().len();
+        if self.references.len() != header_count {
+            problems.push(IndexProblem::ReferenceCountMismatch {
+                index: self.references.len(),
+                header: header_count,
+            });
+        }
+
+        if self.max_compressed_offset > metadata.len() {
+            problems.push(IndexProblem::OffsetBeyondEof {
+                offset: self.max_compressed_offset,
+                file_size: metadata.len(),
+            });
+        }
+
+        if problems.is_empty() && self.has_metadata() && metadata.len() <= RECORD_COUNT_CHECK_MAX_BYTES {
+            problems.extend(self.check_record_counts(bam_path.as_ref())?);
+        }
+
+        Ok(problems)
+    }
+
+    /// 扫描BAM的全部记录，与索引元数据中的记录总数比较
+    fn check_record_counts(&self, bam_path: &Path) -> Result<Option<IndexProblem>, BamError> {
+        let (mut bam_mapped, mut bam_placed_unmapped, mut bam_unplaced) = (0u64, 0u64, 0u64);
+        let mut reader = BamReader::from_path(bam_path)?;
+        for result in reader.records() {
+            let record = result?;
+            match (record.tid() >= 0, record.is_unmapped()) {
+                (false, _) => bam_unplaced += 1,
+                (true, true) => bam_placed_unmapped += 1,
+                (true, false) => bam_mapped += 1,
+            }
+        }
+
+        let index_mapped = self.references.iter().filter_map(|r| r.mapped).sum();
+        let index_placed_unmapped: u64 = self.references.iter().filter_map(|r| r.unmapped).sum();
+        // 索引末尾没有无坐标记录数时不核对这一部分
+        let index_unmapped = index_placed_unmapped + self.unplaced_unmapped.unwrap_or(bam_unplaced);
+        let bam_unmapped = bam_placed_unmapped + bam_unplaced;
+        Ok((index_mapped != bam_mapped || index_unmapped != bam_unmapped).then_some(IndexProblem::RecordCountMismatch {
+            index_mapped,
+            index_unmapped,
+            bam_mapped,
+            bam_unmapped,
+        }))
+    }
+}
+
+/// CRAM索引的候选路径：`<cram>.crai`与把扩展名替换为`.crai`
+pub(crate) fn crai_candidates(cram_path: &Path) -> [PathBuf; 2] {
+    let mut name = cram_path.as_os_str().to_os_string();
+    name.push(".crai");
+    [PathBuf::from(name), cram_path.with_extension("crai")]
+}
+
+/// 与CRAM比对`.crai`索引：`.crai`只记录容器与切片的偏移，没有bin与记录计数，
+/// 只检查索引是否比CRAM旧
+pub fn check_crai<P: AsRef<Path>, Q: AsRef<Path>>(index_path: P, cram_path: Q) -> Result<Vec<IndexProblem>, BamError> {
+    let index_time = fs::metadata(index_path.as_ref())?.modified().ok();
+    let cram_time = fs::metadata(cram_path.as_ref())?.modified().ok();
+    Ok(match (index_time, cram_time) {
+        (Some(index_time), Some(cram_time)) if index_time < cram_time => vec![IndexProblem::OlderThanBam],
+        _ => Vec::new(),
+    })
+}
+
+/// BGZF文件是否以EOF标记块结尾（缺失通常意味着文件被截断）
+pub fn has_bgzf_eof<P: AsRef<Path>>(path: P) -> Result<bool, BamError> {
+    ends_with(path.as_ref(), &BGZF_EOF)
+}
+
+/// CRAM文件是否以EOF容器结尾（缺失通常意味着文件被截断）；只识别CRAM 3.x的EOF容器
+pub fn has_cram_eof<P: AsRef<Path>>(path: P) -> Result<bool, BamError> {
+    ends_with(path.as_ref(), &CRAM_EOF)
+}
+
+fn ends_with(path: &Path, marker: &[u8]) -> Result<bool, BamError> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    if len < marker.len() as u64 {
+        return Ok(false);
+    }
+
+    let mut tail = vec![0u8; marker.len()];
+    file.seek(SeekFrom::End(-(marker.len() as i64)))?;
+    file.read_exact(&mut tail)?;
+    Ok(tail == marker)
+}
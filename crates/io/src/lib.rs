@@ -1,11 +1,82 @@
 //! BAM/CRAM文件IO适配子库
 //!
-//! 提供统一的BAM/CRAM文件读取API，封装rust-htslib的复杂性
+//! 提供统一的BAM/SAM/CRAM文件读取API，封装rust-htslib的复杂性
+//!
+//! # 示例程序
+//!
+//! `examples/`中的程序只依赖本库，可用来确认读取器能打开自己的文件：
+//!
+//! - `bam_head`：输出头部与前N条记录的SAM文本，
+//!   `cargo run -p bamqc-io --example bam_head -- in.bam -n 5`
+//! - `bam_count`：按FLAG计数，`--threads N`在BAM有索引时按参考序列并行，
+//!   `cargo run -p bamqc-io --example bam_count -- in.bam --threads 4`
+//!
+//! CRAM输入需要启用`cram`特性（`--features cram`），并用`--reference`指定FASTA。
+//! `tests/examples.rs`在生成的小文件上运行这两个程序并核对输出。
 
+#[cfg(feature = "async")]
+pub mod async_bam;
 pub mod bam;
+pub mod bgzf;
+pub mod cigar;
+#[cfg(feature = "cram")]
+pub mod cram;
+pub mod error_code;
+pub mod fingerprint;
+pub mod format;
+pub mod index;
+pub(crate) mod log;
+pub mod lossy;
+#[cfg(feature = "manifest")]
+pub mod manifest;
+pub mod multi;
+pub mod progress;
+pub mod region;
+pub mod retry;
+pub(crate) mod sam_text;
+#[cfg(feature = "test-support")]
+pub mod test_support;
+pub mod warnings;
 
 // 重新导出主要类型
-pub use bam::{BamError, BamReader, BamRecord, BamRecordIterator};
+pub use bam::{
+    AuxValue, BamError, BamQueryIterator, BamReader, BamReaderOptions, BamRecord, BamRecordIterator, BamRecordPairs, BamRegionsIterator, BamWriter,
+    FlagInconsistency, IndexSource, RawInput, ReadGroupInfo, STDIN_PATH, SampledRecords, SortOrder, UnmappedRecords, check_sample_fraction, decode_quality_string, is_stdin,
+    keeps_read_name, write_bai,
+};
+#[cfg(feature = "async")]
+pub use async_bam::{AsyncBamReader, AsyncRecords};
+#[cfg(feature = "cram")]
+pub use cram::CramWriter;
+#[cfg(feature = "serde")]
+pub use error_code::serialize_error;
+pub use error_code::{ErrorCode, EXIT_ERROR, io_error_kind};
+pub use cigar::{Cigar, CigarKind, CigarOp, CigarSource, ParseCigarError};
+pub use bgzf::{BgzfVerifySummary, verify_bgzf, verify_bgzf_file};
+pub use fingerprint::{InputFingerprint, file_crc32, fingerprint_file};
+pub use format::{Compression, Content, FileKind, Format, Sniffed, resolve_format, sniff};
+pub use progress::{READ_PROGRESS, ReadProgress};
+#[cfg(feature = "manifest")]
+pub use manifest::{InputManifest, InputManifestBuilder, ReferenceMd5, header_md5};
+pub use multi::{MultiBamReader, MultiBamRecords, reference_dictionary_diff};
+pub use lossy::{decode_lossy, lossy_decodes};
+pub use index::{BamIndex, IndexFormat, IndexProblem, IndexReferenceStats, ReferenceStats, check_crai, has_bgzf_eof, has_cram_eof};
+pub use region::{GenomicRegion, PlannedQuery, RegionOverlap, RegionPlan, parse_bed_line, plan_queries, read_bed};
+pub use retry::{RetryPolicy, is_transient_io_error};
+pub use warnings::{WARNINGS, WarningClass, WarningCount, WarningDeduplicator, WarningSnapshot};
+
+/// 打开BAM/CRAM文件，自动识别格式；路径为`-`时读取标准输入
+pub fn open_bam<P: AsRef<std::path::Path>>(path: P) -> Result<BamReader, BamError> {
+    BamReader::from_path(path)
+}
+
+/// 按指定格式打开文件；`Format::Auto`时自动识别
+pub fn open_bam_with_format<P: AsRef<std::path::Path>>(
+    path: P,
+    format: Format,
+) -> Result<BamReader, BamError> {
+    BamReader::from_path_with_format(path, format)
+}
 
 /// 库版本信息
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
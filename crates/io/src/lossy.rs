@@ -0,0 +1,39 @@
+//! 非UTF-8字节的有损解码
+//!
+//! 读名、标签值与头部字段按规范应为ASCII，但实际文件中可能出现非UTF-8
+//! 字节（例如厂商流程写入的其它编码样本名）。所有需要把这些字节当作文本
+//! 使用的地方都通过[`decode_lossy`]解码：无效字节替换为U+FFFD，不会panic，
+//! 由此产生的JSON也总是有效的UTF-8。发生替换的次数累计在[`WARNINGS`]中，
+//! 运行结束时可以用[`lossy_decodes`]报告。
+
+use std::borrow::Cow;
+
+use crate::warnings::{WarningClass, WARNINGS};
+
+/// 把字节解码为文本，无效的UTF-8序列替换为U+FFFD并计数
+///
+/// 有效UTF-8时不复制；第一次替换时输出原始字节。
+///
+/// # Examples
+///
+/// ```
+/// use bamqc_io::{decode_lossy, lossy_decodes};
+///
+/// let before = lossy_decodes();
+/// assert_eq!(decode_lossy("样本🧬".as_bytes()), "样本🧬");
+/// assert_eq!(lossy_decodes(), before);
+/// assert_eq!(decode_lossy(b"read\xff1"), "read\u{fffd}1");
+/// assert_eq!(lossy_decodes(), before + 1);
+/// ```
+pub fn decode_lossy(bytes: &[u8]) -> Cow<'_, str> {
+    let text = String::from_utf8_lossy(bytes);
+    if let Cow::Owned(_) = text {
+        WARNINGS.warn(WarningClass::LossyDecode, || bytes.escape_ascii());
+    }
+    text
+}
+
+/// 本进程中[`decode_lossy`]替换过无效字节的次数
+pub fn lossy_decodes() -> u64 {
+    WARNINGS.count(WarningClass::LossyDecode)
+}
@@ -0,0 +1,198 @@
+//! 输入文件的可复现清单
+//!
+//! 记录一次运行所用输入的特征，便于事后核对结果来自哪个文件：绝对路径、
+//! 大小、修改时间、头部MD5（头部重新序列化为SAM文本后计算，与原始字节的
+//! 换行、空白无关）、@SQ M5、读组ID以及第一条记录的虚拟偏移。这些字段在打开
+//! 读取器后即可得到（[`BamReader::manifest`](crate::BamReader::manifest)）；
+//! 记录数与BGZF块数要完整读一遍才知道，取自生成清单的读取器自己做过的完整遍历
+//! 与块级自检（其它读取器的结果不计入），没有做过且未显式给出时为None。
+
+use md5::{Digest, Md5};
+use noodles::sam;
+use noodles::sam::header::record::value::map::reference_sequence::tag::MD5_CHECKSUM;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+use crate::bam::{BamError, is_stdin};
+
+/// 输入文件清单
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct InputManifest {
+    /// 绝对路径（标准输入为`-`）
+    pub path: String,
+    /// 格式（BAM、SAM或CRAM）
+    pub format: String,
+    /// 文件大小（字节），标准输入为None
+    pub size: Option<u64>,
+    /// 修改时间（Unix纪元以来的纳秒数），标准输入或无法获取时为None
+    pub modified_ns: Option<u64>,
+    /// BGZF块数，本次运行做过块级自检时才有
+    pub bgzf_blocks: Option<u64>,
+    /// 头部SAM文本的MD5（十六进制）
+    pub header_md5: String,
+    /// @SQ中声明的M5，按头部顺序，未声明M5的参考序列不列出
+    pub reference_md5s: Vec<ReferenceMd5>,
+    /// @RG ID，按头部顺序
+    pub read_groups: Vec<String>,
+    /// 第一条记录的BGZF虚拟偏移（只有BAM文件有）
+    pub first_record_offset: Option<u64>,
+    /// 记录数，本次运行完整遍历过一遍时才有
+    pub records: Option<u64>,
+}
+
+/// 参考序列的M5
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ReferenceMd5 {
+    pub name: String,
+    pub md5: String,
+}
+
+/// 读取器观察到的、需要完整读一遍才知道的特征
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct Observed {
+    /// 从第一条记录开始的完整遍历得到的记录数
+    pub(crate) records: Option<u64>,
+    /// 块级自检扫描到的BGZF块数
+    pub(crate) bgzf_blocks: Option<u64>,
+}
+
+/// 头部重新序列化为SAM文本后的MD5
+pub fn header_md5(header: &sam::Header) -> Result<String, BamError> {
+    let mut writer = sam::io::Writer::new(Vec::new());
+    writer.write_header(header)?;
+    Ok(hex(&Md5::digest(writer.get_ref())))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// [`InputManifest`]的构造器：打开时填好廉价字段，运行结束后调用[`finish`](Self::finish)
+#[derive(Clone, Debug)]
+pub struct InputManifestBuilder {
+    /// 生成清单的读取器的观察结果
+    observed: Observed,
+    manifest: InputManifest,
+}
+
+impl InputManifestBuilder {
+    /// 从已解析的头部与文件元数据填充廉价字段
+    pub(crate) fn new(
+        path: &str,
+        format: &str,
+        header: &sam::Header,
+        first_record_offset: Option<u64>,
+        observed: Observed,
+    ) -> Result<Self, BamError> {
+        let (absolute, size, modified_ns) = if is_stdin(path) {
+            (path.to_string(), None, None)
+        } else {
+            let metadata = std::fs::metadata(path)?;
+            let modified_ns = metadata
+                .modified()
+                .ok()
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map(|elapsed| elapsed.as_nanos() as u64);
+            let absolute = std::path::absolute(Path::new(path))?;
+            (absolute.to_string_lossy().to_string(), Some(metadata.len()), modified_ns)
+        };
+
+        let reference_md5s = header
+            .reference_sequences()
+            .iter()
+            .filter_map(|(name, map)| {
+                map.other_fields().get(&MD5_CHECKSUM).map(|md5| ReferenceMd5 {
+                    name: name.to_string(),
+                    md5: md5.to_string(),
+                })
+            })
+            .collect();
+
+        Ok(Self {
+            observed,
+            manifest: InputManifest {
+                path: absolute,
+                format: format.to_string(),
+                size,
+                modified_ns,
+                bgzf_blocks: None,
+                header_md5: header_md5(header)?,
+                reference_md5s,
+                read_groups: header.read_groups().keys().map(|id| id.to_string()).collect(),
+                first_record_offset,
+                records: None,
+            },
+        })
+    }
+
+    /// 显式给出记录数（例如收集器自己统计的总数）
+    pub fn records(mut self, records: u64) -> Self {
+        self.manifest.records = Some(records);
+        self
+    }
+
+    /// 显式给出BGZF块数
+    pub fn bgzf_blocks(mut self, blocks: u64) -> Self {
+        self.manifest.bgzf_blocks = Some(blocks);
+        self
+    }
+
+    /// 补上生成清单的读取器观察到的记录数与BGZF块数（未显式给出时），得到清单
+    ///
+    /// # Examples
+    ///
+    /// 同一文件重复运行得到相同的清单；文件被改写后清单不同：
+    ///
+    /// ```
+    /// use bamqc_io::bam::{BamReader, BamWriter};
+    /// use noodles::sam::alignment::record::Flags;
+    /// use noodles::sam::alignment::RecordBuf;
+    ///
+    /// let path = std::env::temp_dir().join(format!("bamqc-manifest-{}.bam", std::process::id()));
+    /// let path = path.to_string_lossy().to_string();
+    /// let write = |records: usize| {
+    ///     let header = "@HD\tVN:1.6\n@SQ\tSN:c1\tLN:1000\tM5:0123456789abcdef0123456789abcdef\n@RG\tID:rg1\tSM:S1\n"
+    ///         .parse()
+    ///         .unwrap();
+    ///     let mut writer = BamWriter::from_path(&path, &header).unwrap();
+    ///     for i in 0..records {
+    ///         let record = RecordBuf::builder().set_name(format!("r{}", i)).set_flags(Flags::UNMAPPED).build();
+    ///         writer.write_record_buf(&record).unwrap();
+    ///     }
+    ///     writer.finish().unwrap();
+    /// };
+    /// let run = || {
+    ///     let mut reader = BamReader::from_path(&path).unwrap();
+    ///     reader.records().for_each(|record| drop(record.unwrap()));
+    ///     reader.manifest().unwrap().finish()
+    /// };
+    ///
+    /// write(3);
+    /// let manifest = run();
+    /// assert!(std::path::Path::new(&manifest.path).is_absolute());
+    /// assert_eq!(manifest.format, "BAM");
+    /// assert_eq!(manifest.size, Some(std::fs::metadata(&path).unwrap().len()));
+    /// assert_eq!(manifest.header_md5.len(), 32);
+    /// assert_eq!(manifest.reference_md5s[0].md5, "0123456789abcdef0123456789abcdef");
+    /// assert_eq!(manifest.read_groups, ["rg1"]);
+    /// assert!(manifest.first_record_offset.is_some());
+    /// assert_eq!(manifest.records, Some(3));
+    /// assert_eq!(run(), manifest);
+    ///
+    /// write(5);
+    /// let modified = run();
+    /// assert_ne!(modified, manifest);
+    /// assert_eq!(modified.header_md5, manifest.header_md5);
+    /// assert_eq!(modified.records, Some(5));
+    /// std::fs::remove_file(&path).unwrap();
+    /// ```
+    pub fn finish(mut self) -> InputManifest {
+        self.manifest.records = self.manifest.records.or(self.observed.records);
+        self.manifest.bgzf_blocks = self.manifest.bgzf_blocks.or(self.observed.bgzf_blocks);
+        self.manifest
+    }
+}
@@ -0,0 +1,216 @@
+//! 把多个输入串联成一条记录流
+//!
+//! 比对结果常按lane拆成多个BAM。[`MultiBamReader`]按给定顺序依次读取各文件的记录，
+//! 打开时检查各文件的参考序列字典（名称、长度与顺序）完全相同，否则记录中的
+//! 参考序列ID在不同文件间含义不同，合并统计没有意义。有多个输入时读取错误
+//! 包装为[`BamError::Input`]，指明出错的文件。
+
+use std::path::Path;
+use noodles::sam;
+use crate::bam::{BamError, BamReader, BamRecord};
+use crate::lossy::decode_lossy;
+
+/// 参考序列字典不同时最多列出的差异行数
+const MAX_DIFF_LINES: usize = 10;
+
+/// 依次读取多个输入的读取器
+///
+/// # Examples
+///
+/// ```
+/// use bamqc_io::{BamError, BamWriter, MultiBamReader};
+/// use noodles::sam::{self, alignment::RecordBuf};
+///
+/// let dir = std::env::temp_dir().join(format!("bamqc-multi-{}", std::process::id()));
+/// std::fs::create_dir_all(&dir).unwrap();
+/// let write = |name: &str, header: &str, records: usize| {
+///     let path = dir.join(name);
+///     let header: sam::Header = header.parse().unwrap();
+///     let mut writer = BamWriter::from_path(&path, &header).unwrap();
+///     for i in 0..records {
+///         writer.write_record_buf_with_name(&RecordBuf::default(), format!("{}_{}", name, i).as_bytes()).unwrap();
+///     }
+///     writer.finish().unwrap();
+///     path
+/// };
+/// let header = "@HD\tVN:1.6\n@SQ\tSN:chr1\tLN:1000\n@SQ\tSN:chr2\tLN:500\n";
+/// let lanes = [write("L1.bam", header, 2), write("L2.bam", header, 3)];
+///
+/// let mut reader = MultiBamReader::from_paths(&lanes).unwrap();
+/// let names: Vec<String> = reader.records().map(|r| r.unwrap().name().into_owned()).collect();
+/// assert_eq!(names, ["L1.bam_0", "L1.bam_1", "L2.bam_0", "L2.bam_1", "L2.bam_2"]);
+/// // 读完后已读字节数为各输入大小之和，不会在切换输入时归零
+/// assert_eq!(Some(reader.bytes_read()), reader.file_size());
+///
+/// // 参考序列长度不同：打开时即失败，消息列出差异
+/// let other = write("other.bam", "@HD\tVN:1.6\n@SQ\tSN:chr1\tLN:1000\n@SQ\tSN:chr2\tLN:501\n", 1);
+/// let e = MultiBamReader::from_paths(&[lanes[0].clone(), other]).unwrap_err();
+/// assert!(matches!(e, BamError::ReferenceMismatch { .. }));
+/// assert!(e.to_string().contains("- #2 chr2 500\n+ #2 chr2 501"), "{}", e);
+/// std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct MultiBamReader {
+    readers: Vec<BamReader>,
+    /// 正在读取的输入下标，全部读完时等于输入数
+    current: usize,
+}
+
+impl MultiBamReader {
+    /// 按顺序打开各输入（路径为`-`时读取标准输入），检查参考序列字典相同
+    ///
+    /// 没有输入时返回错误；参考序列字典与第一个输入不同时返回
+    /// [`BamError::ReferenceMismatch`]。
+    pub fn from_paths<P: AsRef<Path>>(paths: &[P]) -> Result<Self, BamError> {
+        let readers = paths.iter().map(BamReader::from_path).collect::<Result<Vec<_>, _>>()?;
+        Self::from_readers(readers)
+    }
+
+    /// 由已打开的读取器构造，检查同[`MultiBamReader::from_paths`]
+    pub fn from_readers(readers: Vec<BamReader>) -> Result<Self, BamError> {
+        let Some(first) = readers.first() else {
+            return Err(BamError::BamError("没有输入文件".to_string()));
+        };
+        for reader in &readers[1..] {
+            if let Some(diff) = reference_dictionary_diff(first.header(), reader.header()) {
+                return Err(BamError::ReferenceMismatch {
+                    path: reader.path().to_string(),
+                    expected_from: first.path().to_string(),
+                    diff,
+                });
+            }
+        }
+        Ok(Self { readers, current: 0 })
+    }
+
+    /// 第一个输入的头部；各输入的参考序列字典相同，其它头部行（如@RG）可能不同
+    pub fn header(&self) -> &sam::Header {
+        self.readers[0].header()
+    }
+
+    /// 各输入的读取器，按读取顺序
+    pub fn readers(&self) -> &[BamReader] {
+        &self.readers
+    }
+
+    /// 各输入的路径，按读取顺序
+    pub fn paths(&self) -> impl Iterator<Item = &str> + '_ {
+        self.readers.iter().map(BamReader::path)
+    }
+
+    /// 正在读取的输入的路径；全部读完时为None
+    pub fn current_path(&self) -> Option<&str> {
+        self.readers.get(self.current).map(BamReader::path)
+    }
+
+    /// 各输入的字节数之和；任一输入没有长度（标准输入）时为None
+    pub fn file_size(&self) -> Option<u64> {
+        self.readers.iter().map(BamReader::file_size).sum()
+    }
+
+    /// 已读取的压缩字节数：已读完的输入按文件大小计，加上正在读取的输入的压缩偏移
+    ///
+    /// 可与[`MultiBamReader::file_size`]比较估计完成比例。正在读取的输入没有虚拟偏移
+    /// （SAM文本、CRAM）时只计已读完的输入；已读完的输入没有长度（标准输入）时计为0。
+    pub fn bytes_read(&self) -> u64 {
+        let finished: u64 = self.readers[..self.current].iter().filter_map(BamReader::file_size).sum();
+        let current = self.readers.get(self.current).and_then(BamReader::virtual_position).unwrap_or(0);
+        finished + (current >> 16)
+    }
+
+    /// 把下一条记录读入`record`，所有输入都读完时返回false，见[`BamReader::read_into`]
+    ///
+    /// 有多个输入时，读取错误包装为[`BamError::Input`]，带有出错的文件路径。
+    pub fn read_into(&mut self, record: &mut BamRecord) -> Result<bool, BamError> {
+        let wrap = self.readers.len() > 1;
+        while let Some(reader) = self.readers.get_mut(self.current) {
+            match reader.read_into(record) {
+                Ok(true) => return Ok(true),
+                Ok(false) => self.current += 1,
+                Err(e) if !wrap => return Err(e),
+                Err(e) => {
+                    return Err(BamError::Input { path: reader.path().to_string(), source: Box::new(e) });
+                }
+            }
+        }
+        Ok(false)
+    }
+
+    /// 依次迭代各输入的全部记录
+    pub fn records(&mut self) -> MultiBamRecords<'_> {
+        MultiBamRecords { reader: self }
+    }
+}
+
+/// [`MultiBamReader::records`]返回的迭代器
+pub struct MultiBamRecords<'a> {
+    reader: &'a mut MultiBamReader,
+}
+
+impl Iterator for MultiBamRecords<'_> {
+    type Item = Result<BamRecord, BamError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut record = BamRecord::default();
+        match self.reader.read_into(&mut record) {
+            Ok(true) => Some(Ok(record)),
+            Ok(false) => None,
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+/// 两个头部的参考序列字典的差异（类似diff的逐行形式），相同时返回None
+///
+/// `-`行为`expected`中的参考序列，`+`行为`found`中的参考序列，`#`后为从1开始的序号；
+/// 最多列出10处差异。
+///
+/// # Examples
+///
+/// ```
+/// use bamqc_io::reference_dictionary_diff;
+///
+/// let a = "@SQ\tSN:chr1\tLN:1000\n@SQ\tSN:chr2\tLN:500\n".parse().unwrap();
+/// let b = "@SQ\tSN:chr1\tLN:1000\n@SQ\tSN:chrM\tLN:16569\n@SQ\tSN:chr2\tLN:500\n".parse().unwrap();
+/// assert_eq!(reference_dictionary_diff(&a, &a), None);
+/// assert_eq!(
+///     reference_dictionary_diff(&a, &b).unwrap(),
+///     "- #2 chr2 500\n+ #2 chrM 16569\n+ #3 chr2 500"
+/// );
+/// ```
+pub fn reference_dictionary_diff(expected: &sam::Header, found: &sam::Header) -> Option<String> {
+    let dictionary = |header: &sam::Header| -> Vec<(String, u64)> {
+        header
+            .reference_sequences()
+            .iter()
+            .map(|(name, map)| (decode_lossy(name).into_owned(), map.length().get() as u64))
+            .collect()
+    };
+    let (expected, found) = (dictionary(expected), dictionary(found));
+    if expected == found {
+        return None;
+    }
+
+    let mut lines = Vec::new();
+    let mut differing = 0;
+    for i in 0..expected.len().max(found.len()) {
+        let (left, right) = (expected.get(i), found.get(i));
+        if left == right {
+            continue;
+        }
+        differing += 1;
+        if differing > MAX_DIFF_LINES {
+            continue;
+        }
+        if let Some((name, length)) = left {
+            lines.push(format!("- #{} {} {}", i + 1, name, length));
+        }
+        if let Some((name, length)) = right {
+            lines.push(format!("+ #{} {} {}", i + 1, name, length));
+        }
+    }
+    if differing > MAX_DIFF_LINES {
+        lines.push(format!("……另有{}处不同", differing - MAX_DIFF_LINES));
+    }
+    Some(lines.join("\n"))
+}
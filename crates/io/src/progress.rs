@@ -0,0 +1,53 @@
+//! 读取进度
+//!
+//! 记录迭代器在每条记录后把已读记录数与当前压缩偏移写入进程级的
+//! [`READ_PROGRESS`]（Relaxed原子写，开销可以忽略），供看门狗等外部线程
+//! 判断读取是否仍在前进。数值只用于比较是否变化，不保证跨读取器可加。
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// 进程级读取进度
+pub static READ_PROGRESS: ReadProgress = ReadProgress::new();
+
+/// 已读记录数与压缩字节偏移
+///
+/// # Examples
+///
+/// ```
+/// use bamqc_io::ReadProgress;
+///
+/// static PROGRESS: ReadProgress = ReadProgress::new();
+/// let before = PROGRESS.snapshot();
+/// PROGRESS.update(10, 4096);
+/// assert_ne!(PROGRESS.snapshot(), before);
+/// assert_eq!(PROGRESS.snapshot(), (10, 4096));
+/// ```
+#[derive(Debug, Default)]
+pub struct ReadProgress {
+    records: AtomicU64,
+    bytes: AtomicU64,
+}
+
+impl ReadProgress {
+    pub const fn new() -> Self {
+        Self {
+            records: AtomicU64::new(0),
+            bytes: AtomicU64::new(0),
+        }
+    }
+
+    /// 在读取循环中更新进度
+    #[inline]
+    pub fn update(&self, records: u64, bytes: u64) {
+        self.records.store(records, Ordering::Relaxed);
+        self.bytes.store(bytes, Ordering::Relaxed);
+    }
+
+    /// 当前的（记录数，字节偏移）
+    pub fn snapshot(&self) -> (u64, u64) {
+        (
+            self.records.load(Ordering::Relaxed),
+            self.bytes.load(Ordering::Relaxed),
+        )
+    }
+}
@@ -0,0 +1,375 @@
+//! 基因组区域解析与索引查询规划
+//!
+//! 区域统一使用1-based闭区间坐标（与samtools的`chr:start-end`写法一致），
+//! BED文件的0-based半开区间在读取时完成转换。
+
+use crate::bam::BamError;
+use noodles::core::{Position, Region};
+use noodles::sam;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::str::FromStr;
+
+/// 基因组区域（1-based闭区间）
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GenomicRegion {
+    /// 参考序列名称
+    pub name: String,
+    /// 起始位置（1-based，含）
+    pub start: usize,
+    /// 终止位置（1-based，含）；None表示直到参考序列末端
+    pub end: Option<usize>,
+    /// 区域标签（BED第4列），缺省时使用`chr:start-end`
+    pub label: Option<String>,
+}
+
+impl GenomicRegion {
+    /// 区域显示名称
+    pub fn label(&self) -> String {
+        match &self.label {
+            Some(label) => label.clone(),
+            None => self.to_string(),
+        }
+    }
+
+    /// 区域长度；没有终止位置时返回None
+    pub fn span(&self) -> Option<usize> {
+        self.end.map(|end| end.saturating_add(1).saturating_sub(self.start))
+    }
+
+    /// 是否与1-based闭区间`[start, end]`重叠
+    pub fn overlaps(&self, start: usize, end: usize) -> bool {
+        start <= self.end.unwrap_or(usize::MAX) && end >= self.start
+    }
+
+    /// 转换为noodles的区域类型
+    pub fn to_noodles(&self) -> Result<Region, BamError> {
+        let start = Position::try_from(self.start)
+            .map_err(|e| BamError::RegionError(format!("{}: {}", self, e)))?;
+        match self.end {
+            Some(end) => {
+                let end = Position::try_from(end)
+                    .map_err(|e| BamError::RegionError(format!("{}: {}", self, e)))?;
+                Ok(Region::new(self.name.as_str(), start..=end))
+            }
+            None => Ok(Region::new(self.name.as_str(), start..)),
+        }
+    }
+}
+
+impl std::fmt::Display for GenomicRegion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.end {
+            Some(end) => write!(f, "{}:{}-{}", self.name, self.start, end),
+            None if self.start > 1 => write!(f, "{}:{}", self.name, self.start),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+impl FromStr for GenomicRegion {
+    type Err = BamError;
+
+    /// 解析`chr`、`chr:start`或`chr:start-end`，数字中的千分位逗号会被忽略
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let invalid = || BamError::RegionError(s.chars().take(80).collect());
+
+        let (name, interval) = match s.rsplit_once(':') {
+            Some((name, interval)) if !name.is_empty() => (name, Some(interval)),
+            _ => (s, None),
+        };
+        if name.is_empty() {
+            return Err(invalid());
+        }
+
+        let parse_pos = |v: &str| -> Result<usize, BamError> {
+            let v: String = v.chars().filter(|&c| c != ',').collect();
+            match v.parse::<usize>() {
+                Ok(p) if p > 0 => Ok(p),
+                _ => Err(invalid()),
+            }
+        };
+
+        let (start, end) = match interval {
+            None => (1, None),
+            Some(interval) => match interval.split_once('-') {
+                Some((start, end)) => (parse_pos(start)?, Some(parse_pos(end)?)),
+                None => (parse_pos(interval)?, None),
+            },
+        };
+
+        if let Some(end) = end {
+            if end < start {
+                return Err(invalid());
+            }
+        }
+
+        Ok(Self {
+            name: name.to_string(),
+            start,
+            end,
+            label: None,
+        })
+    }
+}
+
+/// 读取BED文件中的区域
+///
+/// 跳过空行以及`#`、`track`、`browser`开头的行；第4列（若存在）作为区域标签。
+pub fn read_bed<P: AsRef<Path>>(path: P) -> Result<Vec<GenomicRegion>, BamError> {
+    let path_str = path.as_ref().to_string_lossy().to_string();
+    if !path.as_ref().exists() {
+        return Err(BamError::FileNotFound { path: path_str });
+    }
+
+    let reader = BufReader::new(File::open(&path)?);
+    let mut regions = Vec::new();
+
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        match parse_bed_line(&line) {
+            Ok(Some(region)) => regions.push(region),
+            Ok(None) => {}
+            Err(e) => return Err(BamError::RegionError(format!("{}:{}: {}", path_str, i + 1, e))),
+        }
+    }
+
+    Ok(regions)
+}
+
+/// 解析一行BED
+///
+/// 空行以及`#`、`track`、`browser`开头的行返回`Ok(None)`；格式错误时
+/// 返回包含该行内容的错误信息。
+pub fn parse_bed_line(line: &str) -> Result<Option<GenomicRegion>, String> {
+    let line = line.trim_end();
+    if line.is_empty()
+        || line.starts_with('#')
+        || line.starts_with("track")
+        || line.starts_with("browser")
+    {
+        return Ok(None);
+    }
+
+    let fields: Vec<&str> = line.split('\t').collect();
+    let invalid = || line.chars().take(80).collect::<String>();
+    if fields.len() < 3 || fields[0].is_empty() {
+        return Err(invalid());
+    }
+
+    let start: usize = fields[1].parse().map_err(|_| invalid())?;
+    let end: usize = fields[2].parse().map_err(|_| invalid())?;
+    if end <= start {
+        return Err(invalid());
+    }
+
+    Ok(Some(GenomicRegion {
+        name: fields[0].to_string(),
+        start: start + 1,
+        end: Some(end),
+        label: fields.get(3).map(|s| s.to_string()),
+    }))
+}
+
+/// 一次索引查询及其覆盖的原始区域
+#[derive(Clone, Debug)]
+pub struct PlannedQuery {
+    /// 合并后的查询区间
+    pub region: GenomicRegion,
+    /// 落在该查询区间内的原始区域下标
+    pub members: Vec<usize>,
+}
+
+/// 规划索引查询：按头部中的参考序列顺序排序，并合并重叠或相邻的区域
+///
+/// 合并后的查询区间互不重叠，因此同一条记录在相邻查询中被重复返回时，
+/// 调用方可以通过与上一个查询区间比较来去重。
+pub fn plan_queries(
+    regions: &[GenomicRegion],
+    header: &sam::Header,
+) -> Result<Vec<PlannedQuery>, BamError> {
+    let mut order = Vec::with_capacity(regions.len());
+    for (i, region) in regions.iter().enumerate() {
+        let tid = header
+            .reference_sequences()
+            .get_index_of(region.name.as_bytes())
+            .ok_or_else(|| BamError::RegionError(format!("参考序列不存在: {}", region.name)))?;
+        order.push((tid, region.start, i));
+    }
+    order.sort_unstable();
+
+    let mut plan: Vec<(usize, PlannedQuery)> = Vec::new();
+    for (tid, _, i) in order {
+        let region = &regions[i];
+        if let Some((last_tid, last)) = plan.last_mut() {
+            let last_end = last.region.end.unwrap_or(usize::MAX);
+            if *last_tid == tid && region.start <= last_end.saturating_add(1) {
+                last.region.end = match (last.region.end, region.end) {
+                    (Some(a), Some(b)) => Some(a.max(b)),
+                    _ => None,
+                };
+                last.members.push(i);
+                continue;
+            }
+        }
+
+        plan.push((
+            tid,
+            PlannedQuery {
+                region: GenomicRegion {
+                    label: None,
+                    ..region.clone()
+                },
+                members: vec![i],
+            },
+        ));
+    }
+
+    Ok(plan.into_iter().map(|(_, query)| query).collect())
+}
+
+/// 请求区域相互重叠时，分区域统计中记录的归属方式
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RegionOverlap {
+    /// 重叠或相邻的区域先合并，每个合并区间一行（以坐标命名）；记录只计入
+    /// 与之重叠的第一个合并区间，各行之和等于总计
+    Merge,
+    /// 记录只计入与之重叠的第一个请求区域（按BED中的顺序），各行之和等于总计
+    First,
+    /// 记录计入所有与之重叠的请求区域，重叠区域的各行之和可能超过总计
+    #[default]
+    All,
+}
+
+impl std::fmt::Display for RegionOverlap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            RegionOverlap::Merge => "merge",
+            RegionOverlap::First => "first",
+            RegionOverlap::All => "all",
+        })
+    }
+}
+
+impl FromStr for RegionOverlap {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "merge" => Ok(RegionOverlap::Merge),
+            "first" => Ok(RegionOverlap::First),
+            "all" => Ok(RegionOverlap::All),
+            _ => Err(format!("未知的重叠处理方式: {}（可选: merge, first, all）", s)),
+        }
+    }
+}
+
+/// 分区域统计的查询计划：输出行、索引查询，以及记录到输出行的归属
+///
+/// 按[`plan_queries`]逐个执行`queries`中的查询，对读到的每条记录调用
+/// [`RegionPlan::is_repeat`]判断它是否已在上一个查询中读到（只计入总计一次），
+/// 再调用[`RegionPlan::attribute`]取得应计入的行。
+///
+/// # Examples
+///
+/// 区域0与1重叠，区域2与它们相邻（三者合并为一次查询），区域3单独查询：
+///
+/// ```
+/// use bamqc_io::{GenomicRegion, RegionOverlap, RegionPlan};
+///
+/// let header = "@HD\tVN:1.6\n@SQ\tSN:c1\tLN:1000\n".parse().unwrap();
+/// let region = |start, end| GenomicRegion { name: "c1".into(), start, end: Some(end), label: None };
+/// let regions = vec![region(101, 200), region(151, 250), region(251, 300), region(501, 600)];
+/// // 记录的1-based闭区间
+/// let records = [(90, 110), (160, 170), (240, 260), (290, 520), (700, 710)];
+///
+/// let count = |mode| {
+///     let plan = RegionPlan::new(regions.clone(), &header, mode).unwrap();
+///     let mut rows = vec![0; plan.regions.len()];
+///     let mut total = 0;
+///     let mut members = Vec::new();
+///     for (q, query) in plan.queries.iter().enumerate() {
+///         for &(start, end) in records.iter().filter(|&&(s, e)| query.region.overlaps(s, e)) {
+///             if !plan.is_repeat(q, start, end) {
+///                 total += 1;
+///             }
+///             plan.attribute(q, start, end, &mut members);
+///             for &i in &members {
+///                 rows[i] += 1;
+///             }
+///         }
+///     }
+///     (rows, total)
+/// };
+///
+/// assert_eq!(count(RegionOverlap::All), (vec![2, 2, 2, 1], 4));
+/// assert_eq!(count(RegionOverlap::First), (vec![2, 1, 1, 0], 4));
+/// let plan = RegionPlan::new(regions.clone(), &header, RegionOverlap::Merge).unwrap();
+/// assert_eq!(plan.regions.iter().map(|r| r.label()).collect::<Vec<_>>(), ["c1:101-300", "c1:501-600"]);
+/// assert_eq!(count(RegionOverlap::Merge), (vec![4, 0], 4));
+/// ```
+#[derive(Clone, Debug)]
+pub struct RegionPlan {
+    /// 输出行对应的区域：`Merge`时为合并区间，否则为请求区域（顺序与BED一致）
+    pub regions: Vec<GenomicRegion>,
+    /// 按参考序列顺序排列、互不重叠的索引查询；`members`为`regions`的下标
+    pub queries: Vec<PlannedQuery>,
+    /// 重叠处理方式
+    pub mode: RegionOverlap,
+}
+
+impl RegionPlan {
+    pub fn new(regions: Vec<GenomicRegion>, header: &sam::Header, mode: RegionOverlap) -> Result<Self, BamError> {
+        let queries = plan_queries(&regions, header)?;
+        if mode != RegionOverlap::Merge {
+            return Ok(Self { regions, queries, mode });
+        }
+        let regions = queries.iter().map(|query| query.region.clone()).collect();
+        let queries = queries
+            .into_iter()
+            .enumerate()
+            .map(|(i, query)| PlannedQuery { members: vec![i], ..query })
+            .collect();
+        Ok(Self { regions, queries, mode })
+    }
+
+    /// 第`query`个查询读到的记录`[start, end]`是否已由上一个查询读到
+    ///
+    /// 查询区间互不重叠且按坐标排列，跨越多个查询区间的记录必然与上一个区间重叠。
+    pub fn is_repeat(&self, query: usize, start: usize, end: usize) -> bool {
+        let current = &self.queries[query].region;
+        query.checked_sub(1).is_some_and(|prev| {
+            let prev = &self.queries[prev].region;
+            prev.name == current.name && prev.overlaps(start, end)
+        })
+    }
+
+    /// 把第`query`个查询读到的记录`[start, end]`应计入的行写入`members`（先清空）
+    ///
+    /// `All`时为当前查询中与记录重叠的全部区域；`First`与`Merge`时只在记录第一次
+    /// 读到时给出一行：与记录重叠的、BED中最靠前的区域（记录跨越后续查询区间时
+    /// 一并比较）。
+    pub fn attribute(&self, query: usize, start: usize, end: usize, members: &mut Vec<usize>) {
+        members.clear();
+        if self.mode == RegionOverlap::All {
+            members.extend(self.overlapping(&self.queries[query], start, end));
+            return;
+        }
+        if self.is_repeat(query, start, end) {
+            return;
+        }
+        let name = &self.queries[query].region.name;
+        let first = self.queries[query..]
+            .iter()
+            .take_while(|q| q.region.name == *name && q.region.overlaps(start, end))
+            .flat_map(|q| self.overlapping(q, start, end))
+            .min();
+        members.extend(first);
+    }
+
+    fn overlapping<'a>(&'a self, query: &'a PlannedQuery, start: usize, end: usize) -> impl Iterator<Item = usize> + 'a {
+        query.members.iter().copied().filter(move |&i| self.regions[i].overlaps(start, end))
+    }
+}
@@ -0,0 +1,91 @@
+//! 暂时性IO错误的重试
+//!
+//! Lustre/NFS等网络文件系统在长时间读取中偶尔返回EIO或ESTALE，重新打开文件
+//! 后通常就能继续读。BAM记录迭代遇到这类错误时，按[`RetryPolicy`]重新打开输入、
+//! 定位到最后一条完整记录之后的BGZF虚拟偏移继续读取（见
+//! [`BamReaderOptions::retry`](crate::BamReaderOptions::retry)）；其它错误仍然立即返回。
+//! 只处理本地（含网络挂载的）文件系统的错误，bamqc-io不支持远程URL输入。
+
+use std::io;
+use std::time::Duration;
+
+/// 第一次重试前的默认等待时间，之后每次加倍
+pub const DEFAULT_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// 两次重试之间的最长等待时间
+pub const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// 重试次数与指数退避的等待时间
+///
+/// # Examples
+///
+/// ```
+/// use bamqc_io::RetryPolicy;
+/// use std::time::Duration;
+///
+/// let policy = RetryPolicy::new(8);
+/// assert_eq!(policy.retries(), 8);
+/// assert_eq!(policy.delay(1), Duration::from_secs(1));
+/// assert_eq!(policy.delay(3), Duration::from_secs(4));
+/// // 不超过MAX_RETRY_DELAY
+/// assert_eq!(policy.delay(8), Duration::from_secs(60));
+/// assert_eq!(RetryPolicy::new(2).with_base_delay(Duration::ZERO).delay(2), Duration::ZERO);
+/// assert_eq!(RetryPolicy::default().retries(), 0);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    retries: u32,
+    base_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+impl RetryPolicy {
+    /// 最多重试`retries`次，0表示不重试
+    pub fn new(retries: u32) -> Self {
+        Self { retries, base_delay: DEFAULT_RETRY_DELAY }
+    }
+
+    /// 第一次重试前的等待时间，默认[`DEFAULT_RETRY_DELAY`]
+    pub fn with_base_delay(mut self, delay: Duration) -> Self {
+        self.base_delay = delay;
+        self
+    }
+
+    /// 最多重试的次数
+    pub fn retries(&self) -> u32 {
+        self.retries
+    }
+
+    /// 第`attempt`次（从1开始）重试前的等待时间
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let factor = 1u32.checked_shl(attempt.saturating_sub(1)).unwrap_or(u32::MAX);
+        self.base_delay.saturating_mul(factor).min(MAX_RETRY_DELAY)
+    }
+}
+
+/// 是否为重新打开后可能恢复的暂时性错误：EIO、ESTALE与EAGAIN
+///
+/// # Examples
+///
+/// ```
+/// use bamqc_io::is_transient_io_error;
+/// use std::io::{Error, ErrorKind};
+///
+/// assert!(is_transient_io_error(&Error::from(ErrorKind::StaleNetworkFileHandle)));
+/// assert!(is_transient_io_error(&Error::from(ErrorKind::WouldBlock)));
+/// #[cfg(unix)]
+/// assert!(is_transient_io_error(&Error::from_raw_os_error(5)));
+/// assert!(!is_transient_io_error(&Error::from(ErrorKind::UnexpectedEof)));
+/// assert!(!is_transient_io_error(&Error::from(ErrorKind::PermissionDenied)));
+/// ```
+pub fn is_transient_io_error(e: &io::Error) -> bool {
+    // EIO没有对应的ErrorKind
+    const EIO: i32 = 5;
+    matches!(e.kind(), io::ErrorKind::StaleNetworkFileHandle | io::ErrorKind::WouldBlock)
+        || (cfg!(unix) && e.raw_os_error() == Some(EIO))
+}
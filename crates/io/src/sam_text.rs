@@ -0,0 +1,106 @@
+//! SAM文本输入
+//!
+//! 未压缩的SAM（例如`samtools view -h`的输出）由noodles逐行解析为`RecordBuf`，
+//! 与CRAM输入一样重新编码为BAM记录，因此上层看到的仍是
+//! [`BamRecord`](crate::BamRecord)，各收集器无需区分输入格式。
+//! SAM文本没有索引，不支持区域查询。
+
+use noodles::bam;
+use noodles::sam::{self, alignment::RecordBuf};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::bam::BamError;
+
+/// 一个打开的SAM文本文件
+pub(crate) struct SamSource {
+    reader: sam::io::Reader<BufReader<File>>,
+    /// 第一条记录的文件偏移（紧随头部之后）
+    records_start: u64,
+    record: RecordBuf,
+    converter: RecordConverter,
+}
+
+impl SamSource {
+    /// 打开SAM文件并读取头部
+    pub(crate) fn open(path: &Path) -> Result<(Self, sam::Header), BamError> {
+        let mut reader = sam::io::Reader::new(BufReader::new(File::open(path)?));
+        let header = reader.read_header().map_err(|e| BamError::BamError(e.to_string()))?;
+        crate::bam::count_header_parse();
+        let records_start = reader.get_mut().stream_position()?;
+        Ok((
+            Self {
+                reader,
+                records_start,
+                record: RecordBuf::default(),
+                converter: RecordConverter::default(),
+            },
+            header,
+        ))
+    }
+
+    /// 重新打开同一文件，直接定位到第一条记录，不重新解析头部
+    pub(crate) fn try_clone(&self, path: &Path) -> Result<Self, BamError> {
+        let mut file = BufReader::new(File::open(path)?);
+        file.seek(SeekFrom::Start(self.records_start))?;
+        Ok(Self {
+            reader: sam::io::Reader::new(file),
+            records_start: self.records_start,
+            record: RecordBuf::default(),
+            converter: RecordConverter::default(),
+        })
+    }
+
+    /// 头部之后已缓冲的开头字节（不消耗）
+    pub(crate) fn peek(&mut self) -> Result<&[u8], BamError> {
+        Ok(self.reader.get_mut().fill_buf()?)
+    }
+
+    pub(crate) fn records<'a>(&'a mut self, header: &'a sam::Header) -> SamRecords<'a> {
+        SamRecords { source: self, header }
+    }
+}
+
+/// 把解析出的`RecordBuf`重新编码为BAM记录
+#[derive(Default)]
+pub(crate) struct RecordConverter {
+    buf: Vec<u8>,
+}
+
+impl RecordConverter {
+    pub(crate) fn convert(&mut self, header: &sam::Header, record: &RecordBuf) -> Result<bam::Record, BamError> {
+        use noodles::sam::alignment::io::Write as _;
+
+        let mut encoder = bam::io::Writer::from(std::mem::take(&mut self.buf));
+        encoder.write_alignment_record(header, record)?;
+        self.buf = encoder.into_inner();
+
+        let mut record = bam::Record::default();
+        bam::io::Reader::from(&self.buf[..]).read_record(&mut record)?;
+        self.buf.clear();
+        Ok(record)
+    }
+}
+
+/// SAM记录迭代器
+///
+/// 解析状态保存在[`SamSource`]中，中途丢弃迭代器后重新创建可接着读取。
+pub(crate) struct SamRecords<'a> {
+    source: &'a mut SamSource,
+    header: &'a sam::Header,
+}
+
+impl Iterator for SamRecords<'_> {
+    type Item = Result<bam::Record, BamError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let source = &mut *self.source;
+        match source.reader.read_record_buf(self.header, &mut source.record) {
+            Ok(0) => None,
+            Ok(_) => Some(source.converter.convert(self.header, &source.record)),
+            // 记录序号由BamRecordIterator加上
+            Err(e) => Some(Err(BamError::BamError(e.to_string()))),
+        }
+    }
+}
@@ -0,0 +1,43 @@
+//! 测试夹具：进程专用的临时路径，以及把SAM文本写成BAM
+//!
+//! 只在`test-support`特性下编译，供各crate的集成测试作为dev-dependency启用；
+//! 出错时直接panic。
+
+use std::path::{Path, PathBuf};
+
+use noodles::sam;
+
+use crate::BamWriter;
+
+/// 清空并重新创建临时目录`bamqc-<name>-<pid>`
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("bamqc-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// 临时目录下的文件路径`bamqc-<name>-<pid>.<extension>`，不创建文件
+pub fn temp_path(name: &str, extension: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("bamqc-{}-{}.{}", name, std::process::id(), extension))
+}
+
+/// 解析带头部的SAM文本
+pub fn parse_sam(text: &str) -> (sam::Header, Vec<sam::alignment::RecordBuf>) {
+    let mut reader = sam::io::Reader::new(text.as_bytes());
+    let header = reader.read_header().unwrap();
+    let records = reader.record_bufs(&header).map(Result::unwrap).collect();
+    (header, records)
+}
+
+/// 把带头部的SAM文本写成BAM，返回路径字符串
+pub fn write_bam<P: AsRef<Path>>(path: P, text: &str) -> String {
+    let (header, records) = parse_sam(text);
+    let path = path.as_ref();
+    let mut writer = BamWriter::from_path(path, &header).unwrap();
+    for record in &records {
+        writer.write_record_buf(record).unwrap();
+    }
+    writer.finish().unwrap();
+    path.to_string_lossy().into_owned()
+}
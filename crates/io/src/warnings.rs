@@ -0,0 +1,233 @@
+//! 逐记录警告的去重
+//!
+//! 有问题的文件可能让同一种逐记录警告出现上百万次，逐条输出会拖慢处理并
+//! 写满日志。所有逐记录的警告都通过[`WARNINGS`]输出：每个类别第一次出现时
+//! 输出带详情的警告，之后只累加计数；运行结束时[`WarningDeduplicator::log_summary`]
+//! 为每个出现过的类别输出一行总数。计数是按类别索引的原子变量，被抑制的
+//! 警告不做任何格式化，可以在并行路径中共享。
+//!
+//! 同一进程中做多次统计时（例如库调用方反复计算），开始时取一份
+//! [`WarningDeduplicator::snapshot`]：[`WarningDeduplicator::warn_since`]在本次统计中
+//! 第一次出现时输出详情，[`WarningDeduplicator::summary_since`]只给出本次统计的增量。
+
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// 逐记录警告的类别
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(rename_all = "snake_case"))]
+pub enum WarningClass {
+    /// 读名、标签值或头部字段含非UTF-8字节
+    LossyDecode,
+    /// 读名含非ASCII字节
+    NonAsciiName,
+    /// CIGAR为长CIGAR占位符但CG标签缺失或无效
+    InvalidLongCigar,
+    /// 读组未在头部@RG中声明
+    UnknownReadGroup,
+    /// 比对到参考序列末端之外
+    PastContigEnd,
+    /// 头部声明按坐标排序，但记录位于前一条记录之前
+    UnsortedRecord,
+}
+
+impl WarningClass {
+    /// 全部类别
+    pub const ALL: [WarningClass; 6] = [
+        WarningClass::LossyDecode,
+        WarningClass::NonAsciiName,
+        WarningClass::InvalidLongCigar,
+        WarningClass::UnknownReadGroup,
+        WarningClass::PastContigEnd,
+        WarningClass::UnsortedRecord,
+    ];
+
+    /// JSON中的名称
+    pub fn as_str(self) -> &'static str {
+        match self {
+            WarningClass::LossyDecode => "lossy_decode",
+            WarningClass::NonAsciiName => "non_ascii_name",
+            WarningClass::InvalidLongCigar => "invalid_long_cigar",
+            WarningClass::UnknownReadGroup => "unknown_read_group",
+            WarningClass::PastContigEnd => "past_contig_end",
+            WarningClass::UnsortedRecord => "unsorted_record",
+        }
+    }
+
+    /// 日志中的说明
+    fn description(self) -> &'static str {
+        match self {
+            WarningClass::LossyDecode => "遇到非UTF-8字节，已替换为U+FFFD",
+            WarningClass::NonAsciiName => "读名含非ASCII字节",
+            WarningClass::InvalidLongCigar => "CIGAR为长CIGAR占位符但CG标签缺失或无效，clip等统计不可用",
+            WarningClass::UnknownReadGroup => "读组未在头部@RG中声明",
+            WarningClass::PastContigEnd => "比对到参考序列末端之外（超出@SQ LN），按坐标的统计不计入该记录",
+            WarningClass::UnsortedRecord => "头部声明按坐标排序（SO:coordinate），但记录位于前一条记录之前",
+        }
+    }
+}
+
+impl fmt::Display for WarningClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// 一个类别的警告总数
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct WarningCount {
+    pub class: WarningClass,
+    pub count: u64,
+}
+
+impl fmt::Display for WarningCount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: 共 {} 次（{}，只输出了第一次的详情）",
+            self.class,
+            self.count,
+            self.class.description()
+        )
+    }
+}
+
+/// 某一时刻各类别的累计次数，见[`WarningDeduplicator::snapshot`]；默认值为全0
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct WarningSnapshot {
+    counts: [u64; WarningClass::ALL.len()],
+}
+
+/// 按类别去重的警告计数器
+#[derive(Debug)]
+pub struct WarningDeduplicator {
+    counts: [AtomicU64; WarningClass::ALL.len()],
+}
+
+impl WarningDeduplicator {
+    pub const fn new() -> Self {
+        Self {
+            counts: [const { AtomicU64::new(0) }; WarningClass::ALL.len()],
+        }
+    }
+
+    /// 记录一次警告；该类别第一次出现时输出`detail`并返回true。
+    ///
+    /// 之后的调用只累加计数，不调用`detail`。
+    ///
+    /// # Examples
+    ///
+    /// 多个线程共触发10000次同类警告，只有一次输出详情，总结只有一行：
+    ///
+    /// ```
+    /// use bamqc_io::{WarningClass, WarningCount, WarningDeduplicator};
+    ///
+    /// let warnings = WarningDeduplicator::new();
+    /// let detailed: usize = std::thread::scope(|scope| {
+    ///     let workers: Vec<_> = (0..4)
+    ///         .map(|worker| {
+    ///             let warnings = &warnings;
+    ///             scope.spawn(move || {
+    ///                 (0..2500)
+    ///                     .filter(|i| warnings.warn(WarningClass::PastContigEnd, || format!("worker {} read{}", worker, i)))
+    ///                     .count()
+    ///             })
+    ///         })
+    ///         .collect();
+    ///     workers.into_iter().map(|worker| worker.join().unwrap()).sum()
+    /// });
+    ///
+    /// assert_eq!(detailed, 1);
+    /// assert_eq!(warnings.count(WarningClass::PastContigEnd), 10000);
+    /// assert_eq!(warnings.count(WarningClass::LossyDecode), 0);
+    /// let summary = warnings.summary();
+    /// assert_eq!(summary, [WarningCount { class: WarningClass::PastContigEnd, count: 10000 }]);
+    /// assert!(summary[0].to_string().starts_with("past_contig_end: 共 10000 次"));
+    /// ```
+    #[inline]
+    pub fn warn<D: fmt::Display>(&self, class: WarningClass, detail: impl FnOnce() -> D) -> bool {
+        self.warn_since(&WarningSnapshot::default(), class, detail)
+    }
+
+    /// 与[`WarningDeduplicator::warn`]相同，但以`since`之后第一次出现为准输出`detail`。
+    ///
+    /// # Examples
+    ///
+    /// 第二次统计开始时取快照：详情重新输出一次，总数只含第二次统计的警告：
+    ///
+    /// ```
+    /// use bamqc_io::{WarningClass, WarningCount, WarningDeduplicator};
+    ///
+    /// let warnings = WarningDeduplicator::new();
+    /// assert!(warnings.warn(WarningClass::NonAsciiName, || "r1"));
+    /// assert!(!warnings.warn(WarningClass::NonAsciiName, || "r2"));
+    ///
+    /// let since = warnings.snapshot();
+    /// assert!(warnings.warn_since(&since, WarningClass::NonAsciiName, || "r3"));
+    /// assert!(!warnings.warn_since(&since, WarningClass::NonAsciiName, || "r4"));
+    /// assert!(!warnings.warn_since(&since, WarningClass::NonAsciiName, || "r5"));
+    /// assert_eq!(warnings.summary_since(&since), [WarningCount { class: WarningClass::NonAsciiName, count: 3 }]);
+    /// assert_eq!(warnings.count(WarningClass::NonAsciiName), 5);
+    /// ```
+    #[inline]
+    pub fn warn_since<D: fmt::Display>(
+        &self,
+        since: &WarningSnapshot,
+        class: WarningClass,
+        detail: impl FnOnce() -> D,
+    ) -> bool {
+        let index = class as usize;
+        let first = self.counts[index].fetch_add(1, Ordering::Relaxed) == since.counts[index];
+        if first {
+            crate::log::warn!("{}: {}（之后同类警告只计数）", class.description(), detail());
+        }
+        first
+    }
+
+    /// 类别的累计次数
+    pub fn count(&self, class: WarningClass) -> u64 {
+        self.counts[class as usize].load(Ordering::Relaxed)
+    }
+
+    /// 当前各类别的累计次数
+    pub fn snapshot(&self) -> WarningSnapshot {
+        WarningSnapshot { counts: WarningClass::ALL.map(|class| self.count(class)) }
+    }
+
+    /// 出现过的类别及其总数（按[`WarningClass::ALL`]的顺序）
+    pub fn summary(&self) -> Vec<WarningCount> {
+        self.summary_since(&WarningSnapshot::default())
+    }
+
+    /// `since`之后出现过的类别及其次数（按[`WarningClass::ALL`]的顺序）
+    pub fn summary_since(&self, since: &WarningSnapshot) -> Vec<WarningCount> {
+        WarningClass::ALL
+            .into_iter()
+            .map(|class| WarningCount {
+                class,
+                count: self.count(class).saturating_sub(since.counts[class as usize]),
+            })
+            .filter(|warning| warning.count > 0)
+            .collect()
+    }
+
+    /// 每个出现过的类别输出一行总数
+    pub fn log_summary(&self) {
+        for warning in self.summary() {
+            crate::log::warn!("{}", warning);
+        }
+    }
+}
+
+impl Default for WarningDeduplicator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 进程级的逐记录警告计数
+pub static WARNINGS: WarningDeduplicator = WarningDeduplicator::new();
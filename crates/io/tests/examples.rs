@@ -0,0 +1,156 @@
+//! 在生成的小文件（BAM、SAM文本与CRAM）上运行`examples/`中的bam_head与bam_count并核对输出
+//!
+//! `cargo test`会先构建examples，这里直接调用构建出的可执行文件。
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use bamqc_io::{write_bai, BamWriter};
+use bamqc_io::test_support;
+
+const HEADER: &str = "@HD\tVN:1.6\tSO:coordinate\n@SQ\tSN:c1\tLN:100\n@SQ\tSN:c2\tLN:80\n";
+
+/// 按坐标排序：两个正常读对（一个为duplicate）、一个只比对上一端的读对、
+/// 一条secondary，以及没有坐标的未比对读对
+const RECORDS: &[&str] = &[
+    "r1\t99\tc1\t10\t60\t10M\t=\t40\t40\tACGTACGTAC\tIIIIIIIIII",
+    "r2\t1123\tc1\t10\t60\t10M\t=\t40\t40\tACGTACGTAC\tIIIIIIIIII",
+    "r1\t147\tc1\t40\t60\t10M\t=\t10\t-40\tGTACGTACGT\tIIIIIIIIII",
+    "r2\t1171\tc1\t40\t60\t10M\t=\t10\t-40\tGTACGTACGT\tIIIIIIIIII",
+    "r3\t73\tc2\t5\t60\t10M\t=\t5\t0\tACGTACGTAC\tIIIIIIIIII",
+    "r3\t133\tc2\t5\t0\t*\t=\t5\t0\tTTTTTTTTTT\tIIIIIIIIII",
+    "r1\t355\tc2\t30\t3\t10M\tc1\t40\t0\tACGTACGTAC\tIIIIIIIIII",
+    "r4\t77\t*\t0\t0\t*\t*\t0\t0\tAAAAAAAAAA\tIIIIIIIIII",
+    "r4\t141\t*\t0\t0\t*\t*\t0\t0\tCCCCCCCCCC\tIIIIIIIIII",
+];
+
+const EXPECTED_COUNTS: &str = "records: 9\nmapped: 6\nunmapped: 3\nduplicate: 2\nqc_fail: 0\nsecondary: 1\nsupplementary: 0\n";
+
+fn example(name: &str) -> PathBuf {
+    let exe = std::env::current_exe().unwrap();
+    // target/<profile>/deps/<test> -> target/<profile>/examples/<name>
+    let dir = exe.parent().unwrap().parent().unwrap().join("examples");
+    dir.join(format!("{}{}", name, std::env::consts::EXE_SUFFIX))
+}
+
+fn run(name: &str, args: &[&str]) -> String {
+    let output = Command::new(example(name)).args(args).output().unwrap();
+    assert!(
+        output.status.success(),
+        "{} {:?} 失败: {}",
+        name,
+        args,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+fn fixture_dir(name: &str) -> PathBuf {
+    test_support::temp_dir(&format!("examples-{}", name))
+}
+
+fn fixture_text() -> String {
+    format!("{}{}\n", HEADER, RECORDS.join("\n"))
+}
+
+fn write_bam(path: &Path) {
+    test_support::write_bam(path, &fixture_text());
+}
+
+/// 记录行的前4列（QNAME、FLAG、RNAME、POS）
+#[cfg(feature = "cram")]
+fn leading_columns(line: &str) -> Vec<&str> {
+    line.split('\t').take(4).collect()
+}
+
+#[test]
+fn bam_head_prints_header_and_records() {
+    let dir = fixture_dir("head");
+    let bam = dir.join("fixture.bam");
+    write_bam(&bam);
+
+    let out = run("bam_head", &[bam.to_str().unwrap(), "-n", "3"]);
+    let (header, records): (Vec<&str>, Vec<&str>) = out.lines().partition(|line| line.starts_with('@'));
+    assert_eq!(header, HEADER.lines().collect::<Vec<_>>());
+    assert_eq!(records, RECORDS[..3]);
+
+    let out = run("bam_head", &[bam.to_str().unwrap(), "-n", "100"]);
+    assert_eq!(out.lines().filter(|line| !line.starts_with('@')).count(), RECORDS.len());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn bam_count_sequential_and_parallel_agree() {
+    let dir = fixture_dir("count");
+    let bam = dir.join("fixture.bam");
+    write_bam(&bam);
+
+    // 没有索引时--threads退回顺序计数
+    assert_eq!(run("bam_count", &[bam.to_str().unwrap(), "--threads", "2"]), EXPECTED_COUNTS);
+
+    write_bai(&bam).unwrap();
+    assert_eq!(run("bam_count", &[bam.to_str().unwrap()]), EXPECTED_COUNTS);
+    for threads in ["2", "3"] {
+        assert_eq!(run("bam_count", &[bam.to_str().unwrap(), "--threads", threads]), EXPECTED_COUNTS);
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn examples_read_header_only_bam() {
+    let dir = fixture_dir("header-only");
+    let bam = dir.join("empty.bam");
+    let (header, _) = test_support::parse_sam(&fixture_text());
+    BamWriter::from_path(&bam, &header).unwrap().finish().unwrap();
+    let empty = "records: 0\nmapped: 0\nunmapped: 0\nduplicate: 0\nqc_fail: 0\nsecondary: 0\nsupplementary: 0\n";
+
+    assert_eq!(run("bam_count", &[bam.to_str().unwrap()]), empty);
+    write_bai(&bam).unwrap();
+    assert_eq!(run("bam_count", &[bam.to_str().unwrap(), "--threads", "2"]), empty);
+
+    let out = run("bam_head", &[bam.to_str().unwrap(), "-n", "3"]);
+    assert_eq!(out.lines().collect::<Vec<_>>(), HEADER.lines().collect::<Vec<_>>());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn examples_read_sam() {
+    let dir = fixture_dir("sam");
+    let sam = dir.join("fixture.sam");
+    std::fs::write(&sam, format!("{}{}\n", HEADER, RECORDS.join("\n"))).unwrap();
+    let sam = sam.to_str().unwrap();
+
+    // SAM没有索引，--threads退回顺序计数
+    assert_eq!(run("bam_count", &[sam, "--threads", "2"]), EXPECTED_COUNTS);
+
+    let out = run("bam_head", &[sam, "-n", "100"]);
+    let (header, records): (Vec<&str>, Vec<&str>) = out.lines().partition(|line| line.starts_with('@'));
+    assert_eq!(header, HEADER.lines().collect::<Vec<_>>());
+    assert_eq!(records, RECORDS);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(feature = "cram")]
+#[test]
+fn examples_read_cram() {
+    use bamqc_io::CramWriter;
+
+    let dir = fixture_dir("cram");
+    let reference = dir.join("ref.fa");
+    std::fs::write(&reference, format!(">c1\n{}\n>c2\n{}\n", "ACGT".repeat(25), "ACGT".repeat(20))).unwrap();
+    let cram = dir.join("fixture.cram");
+    let (header, records) = test_support::parse_sam(&fixture_text());
+    let mut writer = CramWriter::from_path(&cram, &header, &reference).unwrap();
+    for record in &records {
+        writer.write_record_buf(record).unwrap();
+    }
+    writer.finish().unwrap();
+
+    let (cram, reference) = (cram.to_str().unwrap(), reference.to_str().unwrap());
+    assert_eq!(run("bam_count", &[cram, "--reference", reference, "--threads", "2"]), EXPECTED_COUNTS);
+
+    let out = run("bam_head", &[cram, "--reference", reference, "-n", "100"]);
+    let records: Vec<_> = out.lines().filter(|line| !line.starts_with('@')).map(leading_columns).collect();
+    assert_eq!(records, RECORDS.iter().map(|line| leading_columns(line)).collect::<Vec<_>>());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,117 @@
+//! 显式指定输入格式：与内容相符时不论扩展名都能打开，不符时对每种组合返回
+//! 描述实际内容的`FormatMismatch`
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use bamqc_io::{BamError, BamReader, Format};
+use bamqc_io::test_support::{temp_dir, write_bam};
+use noodles::bgzf;
+
+const SAM: &str = "@HD\tVN:1.6\n@SQ\tSN:c1\tLN:1000\nr1\t0\tc1\t10\t60\t4M\t*\t0\t0\tACGT\tIIII\n";
+
+/// 测试用的内容：文件名（扩展名故意与内容不符）、内容对应的格式、`FormatMismatch`中的描述
+struct Fixture {
+    path: PathBuf,
+    format: Option<Format>,
+    found: &'static str,
+}
+
+fn fixtures(dir: &Path) -> Vec<Fixture> {
+    let bam = dir.join("bam-named.sam");
+    write_bam(&bam, SAM);
+
+    let sam = dir.join("sam-named.bam");
+    std::fs::write(&sam, SAM).unwrap();
+    let headerless = dir.join("headerless-sam-named.cram");
+    std::fs::write(&headerless, SAM.lines().last().unwrap().to_string() + "\n").unwrap();
+    // 只有CRAM magic的流（没有扩展名）
+    let cram = dir.join("cram-stream");
+    std::fs::write(&cram, b"CRAM\x03\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00").unwrap();
+
+    let gzip_sam = dir.join("gzip-sam-named.bam");
+    let mut gz = flate2::write::GzEncoder::new(std::fs::File::create(&gzip_sam).unwrap(), flate2::Compression::default());
+    gz.write_all(SAM.as_bytes()).unwrap();
+    gz.finish().unwrap();
+    let bgzf_sam = dir.join("bgzf-sam-named.bam");
+    let mut bgzf_writer = bgzf::io::Writer::new(std::fs::File::create(&bgzf_sam).unwrap());
+    bgzf_writer.write_all(SAM.as_bytes()).unwrap();
+    bgzf_writer.finish().unwrap();
+
+    let fastq = dir.join("reads-named.sam");
+    std::fs::write(&fastq, "@read1\nACGT\n+\nIIII\n").unwrap();
+    let empty = dir.join("empty.bam");
+    std::fs::write(&empty, "").unwrap();
+
+    vec![
+        Fixture { path: bam, format: Some(Format::Bam), found: "BAM magic" },
+        Fixture { path: sam, format: Some(Format::Sam), found: "SAM文本" },
+        Fixture { path: headerless, format: Some(Format::Sam), found: "SAM文本" },
+        Fixture { path: cram, format: Some(Format::Cram), found: "CRAM magic" },
+        Fixture { path: gzip_sam, format: None, found: "gzip压缩的SAM文本" },
+        Fixture { path: bgzf_sam, format: None, found: "BGZF压缩的SAM文本" },
+        Fixture { path: fastq, format: None, found: "FASTQ" },
+        Fixture { path: empty, format: None, found: "空文件" },
+    ]
+}
+
+#[test]
+fn every_explicit_format_and_content_combination() {
+    let dir = temp_dir("format-mismatch");
+    let explicit: &[Format] = if cfg!(feature = "cram") { &[Format::Bam, Format::Sam, Format::Cram] } else { &[Format::Bam, Format::Sam] };
+
+    for fixture in fixtures(&dir) {
+        let name = fixture.path.file_name().unwrap().to_string_lossy().to_string();
+        for &expected in explicit {
+            let result = BamReader::from_path_with_format(&fixture.path, expected);
+            if fixture.format == Some(expected) {
+                // 只有magic的CRAM流无法解码，其余内容与格式相符时都能打开
+                if expected != Format::Cram {
+                    let mut reader = result.unwrap_or_else(|e| panic!("{} as {}: {}", name, expected, e));
+                    assert_eq!(reader.records().count(), 1, "{} as {}", name, expected);
+                }
+                continue;
+            }
+            match result {
+                Err(BamError::FormatMismatch { path, expected: reported, found }) => {
+                    assert_eq!(path, fixture.path.to_string_lossy());
+                    assert_eq!((reported, found.as_str()), (expected, fixture.found), "{} as {}", name, expected);
+                }
+                other => panic!("{} as {}: {:?}", name, expected, other.map(|_| ())),
+            }
+        }
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn mismatch_message_names_both_formats() {
+    let dir = temp_dir("format-message");
+    let fixtures = fixtures(&dir);
+    let gzip_sam = &fixtures.iter().find(|f| f.found == "gzip压缩的SAM文本").unwrap().path;
+    let e = BamReader::from_path_with_format(gzip_sam, Format::Bam).unwrap_err();
+    assert_eq!(e.to_string(), format!("{}: 期望BAM格式，实际内容为gzip压缩的SAM文本", gzip_sam.display()));
+
+    // 自动识别时，无法读取的内容仍是NotABamFile，不是FormatMismatch
+    let e = BamReader::from_path(gzip_sam).unwrap_err();
+    assert!(matches!(e, BamError::NotABamFile { .. }), "{}", e);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(feature = "cram")]
+#[test]
+fn explicit_cram_opens_a_cram_without_extension() {
+    use bamqc_io::test_support::parse_sam;
+    use bamqc_io::CramWriter;
+
+    let dir = temp_dir("format-cram");
+    let fasta = dir.join("ref.fa");
+    std::fs::write(&fasta, format!(">c1\n{}\n", "ACGT".repeat(250))).unwrap();
+    let (header, _) = parse_sam(SAM);
+    let cram = dir.join("stream");
+    CramWriter::from_path(&cram, &header, &fasta).unwrap().finish().unwrap();
+
+    let reader = BamReader::from_path_with_format(&cram, Format::Cram).unwrap();
+    assert_eq!(reader.header().reference_sequences().len(), 1);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
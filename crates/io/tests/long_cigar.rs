@@ -0,0 +1,162 @@
+//! 超过65535个操作的CIGAR：BAM中写为占位符`kSmN`，真实操作存放在`CG:B,I`标签中。
+//! 读取时透明地使用CG中的操作；CG无效时安全地回退，不会panic，比对终点也不越界。
+
+use bamqc_io::{BamReader, BamRecord, BamWriter, CigarKind, CigarSource};
+use bamqc_io::test_support::temp_path;
+use noodles::core::Position;
+use noodles::sam::alignment::record::cigar::op::{Kind, Op};
+use noodles::sam::alignment::record::Flags;
+use noodles::sam::alignment::RecordBuf;
+use noodles::sam;
+
+fn write_and_read(name: &str, header: &sam::Header, records: &[RecordBuf]) -> Vec<BamRecord> {
+    let path = temp_path(&format!("long-cigar-{}", name), "bam");
+    let mut writer = BamWriter::from_path(&path, header).unwrap();
+    for record in records {
+        writer.write_record_buf(record).unwrap();
+    }
+    writer.finish().unwrap();
+    let records = BamReader::from_path(&path).unwrap().records().map(|r| r.unwrap()).collect();
+    std::fs::remove_file(&path).unwrap();
+    records
+}
+
+#[test]
+fn more_than_65535_ops_come_from_the_cg_tag() {
+    // 70000个操作：1M1D交替，读长35000，参考跨度70000
+    let ops = 70_000;
+    let cigar: Vec<Op> = (0..ops).map(|i| Op::new(if i % 2 == 0 { Kind::Match } else { Kind::Deletion }, 1)).collect();
+    let header: sam::Header = "@HD\tVN:1.6\n@SQ\tSN:c1\tLN:1000000\n".parse().unwrap();
+    let record = RecordBuf::builder()
+        .set_name("long")
+        .set_flags(Flags::empty())
+        .set_reference_sequence_id(0)
+        .set_alignment_start(Position::try_from(1001).unwrap())
+        .set_mapping_quality(60.try_into().unwrap())
+        .set_cigar(cigar.into_iter().collect())
+        .set_sequence(vec![b'A'; ops / 2].into())
+        .build();
+    let records = write_and_read("ops", &header, &[record]);
+    let record = &records[0];
+
+    assert_eq!(record.cigar_source(), CigarSource::CgTag);
+    let (cigar, source) = record.cigar_with_source();
+    assert_eq!(source, CigarSource::CgTag);
+    assert_eq!(cigar.len(), ops);
+    assert_eq!(cigar.ops()[1].kind, CigarKind::Deletion);
+    assert_eq!((cigar.reference_length(), cigar.query_length()), (70_000, 35_000));
+    assert_eq!(record.reference_length(), 70_000);
+    // POS为1000（0-based），不含的终点为1000+70000
+    assert_eq!(record.reference_end(), 71_000);
+}
+
+/// 一条BAM记录的原始编码：c1上POS为`pos`（0-based），CIGAR为`cigar`的编码，SEQ为ACGT
+///
+/// noodles写入时会丢弃CG标签，无效的CG只能直接构造记录的字节。
+fn raw_record(name: &str, pos: i32, cigar: &[u32], data: &[u8]) -> Vec<u8> {
+    let mut fields = Vec::new();
+    fields.extend(0i32.to_le_bytes());
+    fields.extend(pos.to_le_bytes());
+    fields.extend([name.len() as u8 + 1, 60]);
+    fields.extend(4681u16.to_le_bytes());
+    fields.extend((cigar.len() as u16).to_le_bytes());
+    fields.extend(0u16.to_le_bytes());
+    fields.extend(4u32.to_le_bytes());
+    fields.extend((-1i32).to_le_bytes());
+    fields.extend((-1i32).to_le_bytes());
+    fields.extend(0i32.to_le_bytes());
+    fields.extend(name.as_bytes());
+    fields.push(0);
+    for op in cigar {
+        fields.extend(op.to_le_bytes());
+    }
+    // ACGT：A=1、C=2、G=4、T=8，每字节两个碱基；QUAL缺失为0xFF
+    fields.extend([0x12, 0x48, 0xff, 0xff, 0xff, 0xff]);
+    fields.extend(data);
+
+    let mut record = (fields.len() as u32).to_le_bytes().to_vec();
+    record.extend(fields);
+    record
+}
+
+/// `CG:B,<subtype>`数组字段的编码
+fn cg_array(subtype: u8, values: &[&[u8]]) -> Vec<u8> {
+    let mut field = vec![b'C', b'G', b'B', subtype];
+    field.extend((values.len() as u32).to_le_bytes());
+    for value in values {
+        field.extend(*value);
+    }
+    field
+}
+
+fn read_raw(name: &str, records: &[Vec<u8>]) -> Vec<BamRecord> {
+    use std::io::Write;
+
+    let path = temp_path(&format!("long-cigar-{}", name), "bam");
+    let text = b"@HD\tVN:1.6\n@SQ\tSN:c1\tLN:100000\n";
+    let mut writer = noodles::bgzf::io::Writer::new(std::fs::File::create(&path).unwrap());
+    writer.write_all(b"BAM\x01").unwrap();
+    writer.write_all(&(text.len() as u32).to_le_bytes()).unwrap();
+    writer.write_all(text).unwrap();
+    writer.write_all(&1u32.to_le_bytes()).unwrap();
+    writer.write_all(&3u32.to_le_bytes()).unwrap();
+    writer.write_all(b"c1\0").unwrap();
+    writer.write_all(&100_000u32.to_le_bytes()).unwrap();
+    for record in records {
+        writer.write_all(record).unwrap();
+    }
+    writer.finish().unwrap();
+    let records = BamReader::from_path(&path).unwrap().records().map(|r| r.unwrap()).collect();
+    std::fs::remove_file(&path).unwrap();
+    records
+}
+
+#[test]
+fn malformed_cg_falls_back_safely() {
+    // 占位符4S50N：4S = 4 << 4 | 4，50N = 50 << 4 | 3
+    let placeholder = [4 << 4 | 4, 50 << 4 | 3];
+    let (m2, m4): (u32, u32) = (2 << 4, 4 << 4);
+    let records = [
+        raw_record("inline", 100, &[m4], &[]),
+        raw_record("valid", 100, &placeholder, &cg_array(b'I', &[&m4.to_le_bytes()])),
+        raw_record("signed", 100, &placeholder, &cg_array(b'i', &[&m4.to_le_bytes()])),
+        // 操作代码15不存在
+        raw_record("bad_op", 100, &placeholder, &cg_array(b'I', &[&(4u32 << 4 | 15).to_le_bytes()])),
+        // 6M与SEQ长度4不符
+        raw_record("wrong_length", 100, &placeholder, &cg_array(b'I', &[&(6u32 << 4).to_le_bytes()])),
+        raw_record("bytes", 100, &placeholder, &cg_array(b'C', &[&[m4 as u8], &[0], &[0], &[0]])),
+        raw_record("not_array", 100, &placeholder, b"CGZ4M\0"),
+        raw_record("missing", 100, &placeholder, b"NMC\0"),
+        // 数组声明的长度超出记录：noodles查找CG时会panic，不能读取CIGAR
+        raw_record("truncated", 100, &placeholder, &[b'C', b'G', b'B', b'I', 0xff, 0xff, 0, 0, 0x40, 0, 0, 0]),
+        raw_record("other_tag", 100, &[m4], &[b"XCZx\0".as_slice(), &cg_array(b'I', &[&m2.to_le_bytes(), &m2.to_le_bytes()])].concat()),
+    ];
+    let records = read_raw("malformed", &records);
+    let summary: Vec<(String, CigarSource, String, i64)> = records
+        .iter()
+        .map(|r| {
+            let (cigar, source) = r.cigar_with_source();
+            assert_eq!(r.cigar_source(), source, "{}", r.name());
+            assert_eq!(r.cigar(), cigar);
+            (r.name().into_owned(), source, cigar.to_string(), r.reference_end())
+        })
+        .collect();
+    let expected = [
+        ("inline", CigarSource::Inline, "4M", 104),
+        ("valid", CigarSource::CgTag, "4M", 104),
+        // 替换后的内容无效：占位符已无法取回，CIGAR为空，终点为POS+1
+        ("signed", CigarSource::InvalidCg, "*", 101),
+        ("bad_op", CigarSource::InvalidCg, "*", 101),
+        ("wrong_length", CigarSource::InvalidCg, "*", 101),
+        ("bytes", CigarSource::InvalidCg, "*", 101),
+        // CG不是数组或缺失：noodles不替换，使用占位符本身，终点仍然正确
+        ("not_array", CigarSource::InvalidCg, "4S50N", 150),
+        ("missing", CigarSource::InvalidCg, "4S50N", 150),
+        ("truncated", CigarSource::InvalidCg, "*", 101),
+        // 记录本身的CIGAR不是占位符时，CG标签不影响CIGAR
+        ("other_tag", CigarSource::Inline, "4M", 104),
+    ];
+    let expected: Vec<(String, CigarSource, String, i64)> =
+        expected.iter().map(|&(name, source, cigar, end)| (name.to_string(), source, cigar.to_string(), end)).collect();
+    assert_eq!(summary, expected);
+}
@@ -0,0 +1,46 @@
+//! 输入文件清单的记录数与BGZF块数只来自生成清单的读取器：同一路径上其它读取器的
+//! 完整遍历或块级自检不会计入。
+#![cfg(feature = "manifest")]
+
+use bamqc_io::{verify_bgzf_file, BamReader};
+use bamqc_io::test_support::{temp_path, write_bam};
+
+fn fixture(name: &str, records: usize) -> String {
+    let path = temp_path(&format!("manifest-{}", name), "bam");
+    let mut text = String::from("@HD\tVN:1.6\tSO:unsorted\n@SQ\tSN:c1\tLN:100000\n");
+    for i in 0..records {
+        text.push_str(&format!("r{i}\t0\tc1\t{}\t60\t8M\t*\t0\t0\tACGTACGT\tIIIIIIII\n", i + 1));
+    }
+    write_bam(&path, &text)
+}
+
+#[test]
+fn another_readers_pass_is_not_counted() {
+    let bam = fixture("other-reader", 20);
+    let mut read = BamReader::from_path(&bam).unwrap();
+    assert_eq!(read.records().count(), 20);
+    assert_eq!(read.manifest().unwrap().finish().records, Some(20));
+
+    let fresh = BamReader::from_path(&bam).unwrap();
+    let manifest = fresh.manifest().unwrap().finish();
+    assert_eq!((manifest.records, manifest.bgzf_blocks), (None, None));
+    // 显式给出的记录数仍然有效
+    assert_eq!(fresh.manifest().unwrap().records(7).finish().records, Some(7));
+    std::fs::remove_file(&bam).unwrap();
+}
+
+#[test]
+fn partial_pass_and_verification_stay_on_their_reader() {
+    let bam = fixture("verify", 20);
+    let mut verified = BamReader::from_path(&bam).unwrap();
+    verified.set_verify(true);
+    assert_eq!(verified.records().take(5).count(), 5);
+    let manifest = verified.manifest().unwrap().finish();
+    // 没有读到末尾，不知道记录数
+    assert_eq!(manifest.records, None);
+    assert_eq!(manifest.bgzf_blocks, Some(verify_bgzf_file(&bam).unwrap().blocks));
+
+    let unverified = BamReader::from_path(&bam).unwrap();
+    assert_eq!(unverified.manifest().unwrap().finish().bgzf_blocks, None);
+    std::fs::remove_file(&bam).unwrap();
+}
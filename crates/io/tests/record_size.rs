@@ -0,0 +1,72 @@
+//! 损坏的`block_size`：超过`MAX_RECORD_SIZE`的记录长度在解码前即报错，
+//! 不会按接近4GiB的长度分配缓冲区
+
+use std::io::Write;
+
+use bamqc_io::bam::MAX_RECORD_SIZE;
+use bamqc_io::{BamError, BamReader};
+use bamqc_io::test_support::temp_path;
+
+/// 头部只有c1的BAM，之后依次写入`records`的原始字节
+fn write_raw(name: &str, records: &[&[u8]]) -> String {
+    let path = temp_path(&format!("record-size-{}", name), "bam");
+    let text = b"@HD\tVN:1.6\n@SQ\tSN:c1\tLN:100000\n";
+    let mut writer = noodles::bgzf::io::Writer::new(std::fs::File::create(&path).unwrap());
+    writer.write_all(b"BAM\x01").unwrap();
+    writer.write_all(&(text.len() as u32).to_le_bytes()).unwrap();
+    writer.write_all(text).unwrap();
+    writer.write_all(&1u32.to_le_bytes()).unwrap();
+    writer.write_all(&3u32.to_le_bytes()).unwrap();
+    writer.write_all(b"c1\0").unwrap();
+    writer.write_all(&100_000u32.to_le_bytes()).unwrap();
+    for record in records {
+        writer.write_all(record).unwrap();
+    }
+    writer.finish().unwrap();
+    path.to_string_lossy().into_owned()
+}
+
+/// 名为r1、没有CIGAR与序列的未比对记录
+fn unmapped_record() -> Vec<u8> {
+    let mut fields = Vec::new();
+    fields.extend((-1i32).to_le_bytes());
+    fields.extend((-1i32).to_le_bytes());
+    fields.extend([3, 0]);
+    fields.extend(4680u16.to_le_bytes());
+    fields.extend(0u16.to_le_bytes());
+    fields.extend(4u16.to_le_bytes());
+    fields.extend(0u32.to_le_bytes());
+    fields.extend((-1i32).to_le_bytes());
+    fields.extend((-1i32).to_le_bytes());
+    fields.extend(0i32.to_le_bytes());
+    fields.extend(b"r1\0");
+    let mut record = (fields.len() as u32).to_le_bytes().to_vec();
+    record.extend(fields);
+    record
+}
+
+fn read_all(path: &str) -> Vec<Result<String, BamError>> {
+    let mut reader = BamReader::from_path(path).unwrap();
+    reader.records().map(|result| result.map(|record| record.name().into_owned())).collect()
+}
+
+#[test]
+fn oversized_block_size_is_rejected_before_decoding() {
+    for (name, block_size) in [("corrupt", 0xe918_1818u32), ("just-over", MAX_RECORD_SIZE + 1)] {
+        // 长度字段之后只有几个字节：按声明的长度读取会先分配缓冲区
+        let mut corrupt = block_size.to_le_bytes().to_vec();
+        corrupt.extend([0x18; 12]);
+        let bam = write_raw(name, &[&unmapped_record(), &corrupt]);
+        let results = read_all(&bam);
+        assert_eq!(results.len(), 2, "{}: {:?}", name, results);
+        assert_eq!(results[0].as_deref().unwrap(), "r1");
+        match &results[1] {
+            Err(BamError::RecordReadError { record_index, reason, .. }) => {
+                assert_eq!(*record_index, 2);
+                assert!(reason.contains(&format!("记录长度异常: {} 字节", block_size)), "{}", reason);
+            }
+            other => panic!("{}: {:?}", name, other),
+        }
+        std::fs::remove_file(&bam).unwrap();
+    }
+}
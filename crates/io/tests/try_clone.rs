@@ -0,0 +1,58 @@
+//! `BamReader::try_clone`共享已解析的头部：克隆不增加头部解析次数，各克隆的读取位置独立
+//!
+//! 头部解析次数是进程级计数，这个文件只有一个测试，避免与其它测试并行时互相干扰。
+
+use bamqc_io::bam::header_parse_count;
+use bamqc_io::BamReader;
+use bamqc_io::test_support::{temp_dir, write_bam};
+
+/// 有`contigs`条@SQ的头部与`records`条记录的SAM文本
+fn sam_text(contigs: usize, records: usize) -> String {
+    let mut text = String::from("@HD\tVN:1.6\tSO:unsorted\n");
+    for i in 0..contigs {
+        text.push_str(&format!("@SQ\tSN:contig{}\tLN:100000\n", i));
+    }
+    for i in 0..records {
+        text.push_str(&format!("r{}\t0\tcontig{}\t{}\t60\t4M\t*\t0\t0\tACGT\tIIII\n", i, i % contigs, i + 1));
+    }
+    text
+}
+
+fn names(reader: &mut BamReader, limit: usize) -> Vec<String> {
+    reader.records().take(limit).map(|r| r.unwrap().name().into_owned()).collect()
+}
+
+#[test]
+fn clones_share_the_parsed_header() {
+    let dir = temp_dir("try-clone");
+    let text = sam_text(20_000, 50);
+    let sam_path = dir.join("big.sam");
+    std::fs::write(&sam_path, &text).unwrap();
+    let bam_path = dir.join("big.bam");
+    write_bam(&bam_path, &text);
+
+    for path in [&bam_path, &sam_path] {
+        let before = header_parse_count();
+        let mut original = BamReader::from_path(path).unwrap();
+        assert_eq!(header_parse_count(), before + 1, "{}", path.display());
+
+        let clones: Vec<BamReader> = (0..8).map(|_| original.try_clone().unwrap()).collect();
+        assert_eq!(header_parse_count(), before + 1, "克隆重新解析了头部: {}", path.display());
+        for clone in &clones {
+            assert!(std::ptr::eq(clone.header(), original.header()));
+            assert_eq!(clone.header().reference_sequences().len(), 20_000);
+        }
+
+        // 读取位置互相独立：原读取器读过的记录不影响克隆，克隆之间也互不影响
+        assert_eq!(names(&mut original, 10), (0..10).map(|i| format!("r{}", i)).collect::<Vec<_>>());
+        let mut clones = clones.into_iter();
+        let (mut a, mut b) = (clones.next().unwrap(), clones.next().unwrap());
+        assert_eq!(names(&mut a, 3), ["r0", "r1", "r2"]);
+        assert_eq!(names(&mut b, 50).len(), 50);
+        assert_eq!(names(&mut a, 50).len(), 47);
+        // 克隆的克隆仍从第一条记录开始
+        assert_eq!(names(&mut a.try_clone().unwrap(), 1), ["r0"]);
+        assert_eq!(header_parse_count(), before + 1);
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
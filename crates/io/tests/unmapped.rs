@@ -0,0 +1,91 @@
+//! `unmapped_records`产出全部0x4记录：放在mate位置上的未比对记录与末尾没有位置的
+//! 未比对记录都计入，与按flag过滤全部记录（`samtools view -c -f 4`）的结果相同。
+
+use bamqc_io::{write_bai, BamReader};
+use bamqc_io::test_support::{temp_path, write_bam};
+
+/// c1上1000条已比对记录；c2上500个读对，其中每50个有一个mate未比对、放在mate位置上；
+/// c3上200条已比对记录；末尾20条没有位置的未比对记录
+fn fixture(name: &str) -> String {
+    let bam = temp_path(&format!("unmapped-{}", name), "bam");
+    let bam = bam.to_string_lossy().to_string();
+    let mut text = String::from("@HD\tVN:1.6\tSO:coordinate\n");
+    for contig in ["c1", "c2", "c3"] {
+        text.push_str(&format!("@SQ\tSN:{}\tLN:100000\n", contig));
+    }
+    let read = "ACGTACGT\tIIIIIIII";
+    for i in 0..1000 {
+        text.push_str(&format!("a{i}\t0\tc1\t{}\t60\t8M\t*\t0\t0\t{read}\n", i * 50 + 1));
+    }
+    for i in 0..500 {
+        let pos = i * 100 + 1;
+        if i % 50 == 0 {
+            text.push_str(&format!("p{i}\t73\tc2\t{pos}\t60\t8M\t=\t{pos}\t0\t{read}\n"));
+            text.push_str(&format!("p{i}\t133\tc2\t{pos}\t0\t*\t=\t{pos}\t0\t{read}\n"));
+        } else {
+            text.push_str(&format!("p{i}\t99\tc2\t{pos}\t60\t8M\t=\t{}\t58\t{read}\n", pos + 50));
+            text.push_str(&format!("p{i}\t147\tc2\t{}\t60\t8M\t=\t{pos}\t-58\t{read}\n", pos + 50));
+        }
+    }
+    for i in 0..200 {
+        text.push_str(&format!("b{i}\t0\tc3\t{}\t60\t8M\t*\t0\t0\t{read}\n", i * 50 + 1));
+    }
+    for i in 0..20 {
+        text.push_str(&format!("u{i}\t4\t*\t0\t0\t*\t*\t0\t0\t{read}\n"));
+    }
+    write_bam(&bam, &text);
+    bam
+}
+
+fn unmapped_names(bam: &str) -> (Vec<String>, u64) {
+    let mut reader = BamReader::from_path(bam).unwrap();
+    let mut unmapped = reader.unmapped_records().unwrap();
+    let names = unmapped.by_ref().map(|r| r.unwrap().name().into_owned()).collect();
+    (names, unmapped.records_read())
+}
+
+#[test]
+fn placed_unmapped_mates_are_included() {
+    let bam = fixture("placed");
+    // 相当于samtools view -c -f 4
+    let mut reader = BamReader::from_path(&bam).unwrap();
+    let flagged: Vec<String> =
+        reader.records().map(|r| r.unwrap()).filter(|r| r.is_unmapped()).map(|r| r.name().into_owned()).collect();
+    assert_eq!(flagged.len(), 10 + 20);
+    assert_eq!(&flagged[..2], ["p0", "p50"]);
+
+    // 没有索引：全量扫描
+    let (scanned, read) = unmapped_names(&bam);
+    assert_eq!(scanned, flagged);
+    assert_eq!(read, 1000 + 1000 + 200 + 20);
+
+    // 有索引：跳过c1，从c2的第一条记录开始
+    let bai = write_bai(&bam).unwrap();
+    let (indexed, read) = unmapped_names(&bam);
+    assert_eq!(indexed, flagged);
+    assert_eq!(read, 1000 + 200 + 20);
+    std::fs::remove_file(&bai).unwrap();
+    std::fs::remove_file(&bam).unwrap();
+}
+
+#[test]
+fn unmapped_records_have_no_alignment_position() {
+    let bam = fixture("position");
+    let mut reader = BamReader::from_path(&bam).unwrap();
+    let mut unmapped = 0;
+    for record in reader.records().map(|r| r.unwrap()) {
+        if !record.is_unmapped() {
+            assert_eq!(record.position(), Some(record.pos()), "{}", record.name());
+            continue;
+        }
+        unmapped += 1;
+        let name = record.name();
+        assert_eq!((record.position(), record.alignment_start()), (None, None), "{}", name);
+        assert_eq!((record.alignment_span(), record.alignment_end()), (None, None), "{}", name);
+        assert_eq!((record.unclipped_start(), record.unclipped_end()), (None, None), "{}", name);
+        // 放在mate位置上的记录保留原始POS
+        assert_eq!(record.pos() >= 0, name.starts_with('p'), "{}", name);
+    }
+    assert_eq!(unmapped, 10 + 20);
+    std::fs::remove_file(&bam).unwrap();
+}
@@ -0,0 +1,70 @@
+//! BGZF块级自检：改写某个块的CRC32后，启用自检的读取器在输出任何记录之前
+//! 报告该块的压缩偏移。
+
+use std::path::{Path, PathBuf};
+
+use bamqc_io::{BamError, BamReader};
+use bamqc_io::test_support::{temp_path, write_bam};
+
+/// 写出跨越多个BGZF块的BAM，返回路径与各块的压缩偏移
+fn fixture(name: &str) -> (PathBuf, Vec<u64>) {
+    let path = temp_path(&format!("verify-{}", name), "bam");
+    let mut text = String::from("@HD\tVN:1.6\tSO:unsorted\n@SQ\tSN:c1\tLN:100000\n");
+    for i in 0..3000 {
+        let seq: String = (0..100).map(|j| ['A', 'C', 'G', 'T'][(i * 7 + j * j) % 4]).collect();
+        text.push_str(&format!("r{i}\t0\tc1\t{}\t60\t100M\t*\t0\t0\t{seq}\t{}\n", i + 1, "I".repeat(100)));
+    }
+    write_bam(&path, &text);
+
+    // 块头第16-17字节为BSIZE（块长度减1）
+    let bytes = std::fs::read(&path).unwrap();
+    let mut offsets = Vec::new();
+    let mut offset = 0;
+    while offset < bytes.len() {
+        offsets.push(offset as u64);
+        offset += u16::from_le_bytes([bytes[offset + 16], bytes[offset + 17]]) as usize + 1;
+    }
+    (path, offsets)
+}
+
+/// 翻转`block`号块的CRC32（位于块末尾的ISIZE之前）中的一个字节
+fn corrupt_crc(path: &Path, offsets: &[u64], block: usize) {
+    let mut bytes = std::fs::read(path).unwrap();
+    let end = offsets[block + 1] as usize;
+    bytes[end - 8] ^= 0xff;
+    std::fs::write(path, bytes).unwrap();
+}
+
+#[test]
+fn a_corrupted_crc_is_reported_before_any_record() {
+    let (path, offsets) = fixture("records");
+    assert!(offsets.len() > 3, "{:?}", offsets);
+    corrupt_crc(&path, &offsets, 2);
+
+    let mut reader = BamReader::from_path(&path).unwrap();
+    reader.set_verify(true);
+    let results: Vec<_> = reader.records().collect();
+    assert_eq!(results.len(), 1);
+    match &results[0] {
+        Err(BamError::BgzfChecksum { block_offset }) => assert_eq!(*block_offset, offsets[2]),
+        other => panic!("{:?}", other),
+    }
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn a_file_replaced_at_the_same_path_is_verified_again() {
+    let (path, offsets) = fixture("replaced");
+    let mut reader = BamReader::from_path(&path).unwrap();
+    reader.set_verify(true);
+    assert_eq!(reader.records().count(), 3000);
+
+    corrupt_crc(&path, &offsets, 1);
+    let mut reader = BamReader::from_path(&path).unwrap();
+    reader.set_verify(true);
+    match reader.records().next() {
+        Some(Err(BamError::BgzfChecksum { block_offset })) => assert_eq!(block_offset, offsets[1]),
+        other => panic!("{:?}", other),
+    }
+    std::fs::remove_file(&path).unwrap();
+}
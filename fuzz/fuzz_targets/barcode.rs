@@ -0,0 +1,17 @@
+#![no_main]
+
+use bamqc_core::{BarcodeOptions, BarcodeTag};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &str| {
+    let _ = data.parse::<BarcodeTag>();
+
+    let options = BarcodeOptions {
+        uppercase: true,
+        allow_n: true,
+        ..Default::default()
+    };
+    if let Some(barcode) = options.normalize(data) {
+        assert!(!barcode.is_empty());
+    }
+});
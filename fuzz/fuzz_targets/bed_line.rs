@@ -0,0 +1,12 @@
+#![no_main]
+
+use bamqc_io::parse_bed_line;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &str| {
+    if let Ok(Some(region)) = parse_bed_line(data) {
+        assert!(region.start >= 1);
+        let _ = region.span();
+        let _ = region.label();
+    }
+});
@@ -0,0 +1,34 @@
+#![no_main]
+
+//! 在合法的BAM头部之后拼接任意字节作为记录区，验证记录解码的错误路径
+//! 只返回错误而不会panic。
+
+use std::io::Write;
+
+use bamqc_io::BamReader;
+use libfuzzer_sys::fuzz_target;
+use noodles::bgzf;
+
+const HEADER: &[u8] = b"BAM\x01\
+\x0c\x00\x00\x00@HD\tVN:1.6\n\x00\
+\x01\x00\x00\x00\
+\x05\x00\x00\x00chr1\x00\xe8\x03\x00\x00";
+
+fuzz_target!(|data: &[u8]| {
+    let path = std::env::temp_dir().join(format!("bamqc-fuzz-{}.bam", std::process::id()));
+
+    let mut writer = bgzf::io::Writer::new(std::fs::File::create(&path).unwrap());
+    writer.write_all(HEADER).unwrap();
+    writer.write_all(data).unwrap();
+    writer.finish().unwrap();
+
+    if let Ok(mut reader) = BamReader::from_path(&path) {
+        for result in reader.records() {
+            let Ok(record) = result else { break };
+            let _ = record.qname();
+            let _ = record.pos();
+            let _ = record.reference_end();
+            let _ = record.string_tag(*b"RG");
+        }
+    }
+});
@@ -0,0 +1,13 @@
+#![no_main]
+
+use bamqc_io::GenomicRegion;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &str| {
+    if let Ok(region) = data.parse::<GenomicRegion>() {
+        // 解析成功的区域必须能再次格式化并转换为noodles区域而不panic
+        let _ = region.to_string();
+        let _ = region.span();
+        let _ = region.to_noodles();
+    }
+});
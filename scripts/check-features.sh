@@ -0,0 +1,42 @@
+#!/usr/bin/env bash
+# 构建并测试重要的特性组合，供CI调用：scripts/check-features.sh
+set -euo pipefail
+cd "$(dirname "$0")/.."
+
+core_combos=(
+    ""
+    "serde"
+    "tracing"
+    "serde,tracing"
+    "serde,intervals"
+    "serde,approx"
+    "clap"
+    "serde,tracing,clap,intervals,approx"
+)
+
+for features in "${core_combos[@]}"; do
+    echo "==> bamqc-core --no-default-features --features '${features}'"
+    cargo clippy -p bamqc-core --no-default-features --features "${features}" --all-targets -- -D warnings
+    cargo test -p bamqc-core --no-default-features --features "${features}"
+done
+
+io_combos=(
+    ""
+    "serde"
+    "tracing"
+    "manifest"
+    "cram"
+    "async"
+    "builder"
+    "serde,tracing,manifest,cram,async,builder"
+)
+
+for features in "${io_combos[@]}"; do
+    echo "==> bamqc-io --no-default-features --features '${features}'"
+    cargo clippy -p bamqc-io --no-default-features --features "${features}" --all-targets -- -D warnings
+    cargo test -p bamqc-io --no-default-features --features "${features}"
+done
+
+echo "==> workspace（全部特性）"
+cargo clippy --workspace --all-targets -- -D warnings
+cargo test --workspace
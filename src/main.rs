@@ -1,10 +1,36 @@
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 use bamqc_core::{
-    PairOrientation, Strategy, compute_insert_size
+    PairOrientation, Strategy, InsertSizeError, InsertSizeOptions, InsertSizeExpectation, ExpectedRange,
+    ExpectationResult, EXIT_QC_FAILED, PairDuplicatePolicy, compute_insert_size_multi, LogProgressSink,
+    InterimEmitter, InterimSpec, StreamSampleSpec, AccumulationLevel, compute_duplication,
+    compute_duplicate_sets, DEFAULT_DUP_SET_WINDOW, DEFAULT_MAX_OPEN_SETS, MAX_DUP_SET_WINDOW,
+    BarcodeOptions, BarcodeTag, DEFAULT_TOP_K, compute_barcodes,
+    compute_flagstat_with_options, compute_flagstat_per_region, compute_flagstat_by_contig_with_options, FlagStatOptions,
+    DEFAULT_MAX_SECONDARY_RATIO, diff_header_files,
+    compute_target_metrics, EXTREME_TARGETS, compute_coverage_comparison, DEFAULT_LOG2_THRESHOLD, detect_kit, KitDetectOptions,
+    DEFAULT_KIT_SAMPLE_READS, DEFAULT_MIN_ENRICHMENT, Histogram, HistogramSmoothing, compute_idxstats,
+    compute_quick_check, compute_flag_validation, GcDupOptions, DEFAULT_MIN_BIN_COUNT, compute_gc_dup,
+    RecordFilter, ViewOptions, parse_flag_mask, view_records, QualitySource,
+    compute_quality_yield, compute_quality_yield_per_sample, compute_duplication_per_sample,
+    compute_insert_size_per_sample, FilterReport, read_header_samples, sample_file_names,
+    sample_output_path, TagSpec, DEFAULT_FLOAT_BIN_WIDTH, compute_tag_stats,
+    SimulationParams, DEFAULT_SEED, generate_test_data, Watchdog, WatchdogConfig,
+    EXIT_CANCELLED, parse_bytes, parse_duration, MetricDef, metric_dictionary, suggest_metric,
+    OutputFile, OutputLayout, OUTPUT_FILES, DEFAULT_MIN_HOMOPOLYMER, compute_homopolymer_indels,
+    DEFAULT_ADAPTER_CLIP_RATE, compute_clipping, run_key, compute_depth_windows, compute_arm_metrics,
+    DEFAULT_ARM_MAX_DEVIATION, DOWNSAMPLE_SEED_COMPONENT, derive_seed,
+    LimsOptions, LimsPreset, VerdictStatus, LIMS_ARTIFACTS, compute_lims, write_lims_artifacts,
 };
-use std::path::Path;
+use bamqc_io::{ErrorCode, check_sample_fraction, InputFingerprint, InputManifest, fingerprint_file, is_stdin, read_bed, RegionOverlap, WARNINGS, Format, GenomicRegion, READ_PROGRESS, BamReaderOptions, RetryPolicy};
+use std::time::Duration;
+use std::path::{Path, PathBuf};
 use std::fs::write;
 use tracing::error;
+use threads::Threads;
+use planner::{Planner, Severity};
+
+mod planner;
+mod threads;
 
 /// BAM/CRAM文件质量控制工具组
 #[derive(Parser)]
@@ -14,17 +40,82 @@ struct Cli {
     #[arg(short, long, global = true)]
     verbose: bool,
 
+    /// 输入文件格式（auto、bam、sam、cram），显式指定时跳过自动识别并校验文件内容
+    #[arg(long, global = true, default_value = "auto")]
+    input_format: Format,
+
+    /// 参考序列FASTA，解码参考序列压缩的CRAM时需要（有.fai索引时按需读取）
+    #[arg(long, global = true, value_name = "FASTA")]
+    reference: Option<PathBuf>,
+
+    /// 线程数：正整数，或auto按可用CPU数与cgroup配额自动确定；用于BAM的BGZF并行解压
+    #[arg(long, global = true, default_value = "auto")]
+    threads: Threads,
+
+    /// 读取记录前逐块核对BGZF的CRC32与ISIZE，定位损坏块的压缩偏移（额外解压一遍数据）
+    #[arg(long, global = true)]
+    verify_bgzf: bool,
+
+    /// 总运行时间上限，例如"2h"、"30m"；超时后报错并以退出码124退出（不输出部分结果）
+    #[arg(long, global = true, value_parser = parse_timeout)]
+    timeout: Option<Duration>,
+
+    /// 读取无进展（记录数与字节偏移都不变）的时间上限，例如"5m"；超时处理同--timeout
+    #[arg(long, global = true, value_parser = parse_timeout)]
+    io_stall_timeout: Option<Duration>,
+
+    /// 读取BAM时遇到暂时性IO错误（EIO、ESTALE、EAGAIN，常见于Lustre/NFS）的重试次数：
+    /// 重新打开文件并从最后一条完整记录之后继续，等待时间从1秒起逐次加倍；0表示不重试
+    #[arg(long, global = true, default_value_t = 0, value_name = "N")]
+    io_retries: u32,
+
+    /// 输出目录：未指定输出路径的结果写到目录中的标准文件名（见`bamqc metrics --files`），
+    /// 目录不存在时创建；显式的-o等路径优先
+    #[arg(long, global = true)]
+    out_dir: Option<PathBuf>,
+
+    /// 覆盖--out-dir中本子命令已有的结果（默认拒绝运行）
+    #[arg(long, global = true, requires = "out_dir")]
+    force: bool,
+
+    /// 不复用--out-dir中输入与选项都未变化的上次结果，总是重新计算
+    #[arg(long, global = true)]
+    no_cache: bool,
+
+    /// 根随机数种子：模拟数据、条形码HyperLogLog等需要随机性的组件各自由它派生子种子
+    #[arg(long, global = true, default_value_t = DEFAULT_SEED)]
+    seed: u64,
+
+    /// 只检查输入、索引、参考序列、BED与输出目录并输出执行计划（收集器、过滤条件、
+    /// 读取遍数、内存估计），不读取记录；检查全部通过时以0退出，否则以1退出
+    #[arg(long, global = true)]
+    dry_run: bool,
+
+    /// 内存预算，例如"8G"（K、M、G为1024进制）；执行计划的内存估计超过预算时拒绝运行
+    #[arg(long, global = true, value_name = "SIZE", value_parser = parse_memory_budget)]
+    memory_budget: Option<u64>,
+
     #[command(subcommand)]
     command: Commands,
 }
 
-#[derive(Subcommand)]
+/// 结构化报告的输出格式
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    /// 文本格式
+    Text,
+    /// JSON格式
+    Json,
+}
+
+#[derive(Subcommand, Debug)]
 enum Commands {
     /// 计算插入片段长度（与Picard CollectInsertSizeMetrics一致）
     InsertSize {
-        /// 输入BAM/CRAM文件路径
-        #[arg(short, long)]
-        input: String,
+        /// 输入BAM/CRAM文件路径，`-`表示从标准输入读取BAM；可重复指定多个输入
+        /// （例如按lane拆分的BAM，参考序列字典须相同），依次读取并合并为一个结果
+        #[arg(short, long, required = true)]
+        input: Vec<String>,
 
         /// 输出文件路径（可选，如果不指定则输出到标准输出）
         #[arg(short, long)]
@@ -34,6 +125,11 @@ enum Commands {
         #[arg(long)]
         include_duplicates: bool,
 
+        /// 读对的duplicate判定：left-record只看左端记录的标志；either-mate任一端为duplicate
+        /// 即排除读对（与Picard一致，需要额外读一遍文件收集duplicate读名）
+        #[arg(long, value_enum, default_value = "left-record")]
+        pair_duplicate_policy: PairDuplicatePolicy,
+
         /// 只统计proper pair
         #[arg(long)]
         require_proper_pair: bool,
@@ -49,93 +145,2283 @@ enum Commands {
         /// 输出策略
         #[arg(long, value_enum, default_value = "specific")]
         strategy: Strategy,
+
+        /// 过滤统计报告输出路径（TLEN符号计数等诊断信息）
+        #[arg(long)]
+        filter_report: Option<String>,
+
+        /// 过滤统计报告的格式
+        #[arg(long, value_enum, default_value = "text")]
+        report_format: OutputFormat,
+
+        /// 跟踪插入片段最大的N个读对，写入过滤统计报告并在详细日志中输出（0表示不跟踪）
+        #[arg(long, default_value_t = 0)]
+        report_largest: usize,
+
+        /// 最大插入片段，|TLEN|超过该值的读对不计入统计
+        #[arg(long)]
+        max_insert_size: Option<u64>,
+
+        /// 最低MAPQ，低于该值的记录在计入TLEN统计之前被跳过
+        #[arg(long)]
+        min_mapq: Option<u8>,
+
+        /// MAPQ缺失（255）的记录视为未通过--min-mapq（默认视为通过）
+        #[arg(long, requires = "min_mapq")]
+        strict_mapq: bool,
+
+        /// 统计仅因proper pair或最大插入片段条件被排除的读对的|TLEN|分布（<1kb到>1Mb五档），
+        /// 写入过滤统计报告并在详细日志中输出
+        #[arg(long)]
+        report_excluded_distances: bool,
+
+        /// 指标累积层级；不为all-reads时按组输出TSV（group、pairs、insert_size），末行为ALL
+        #[arg(long, value_enum, default_value = "all-reads")]
+        accumulation_level: AccumulationLevel,
+
+        /// 记录中出现头部@RG未声明的读组时报错退出（默认登记为不在头部的组并警告）
+        #[arg(long)]
+        strict_read_groups: bool,
+
+        /// 头部声明了多个样本（SM）时仍合并所有样本统计（默认按样本分别输出，文件名加样本后缀）；
+        /// 多个输入共声明了多个样本时必须指定
+        #[arg(long)]
+        merge_samples: bool,
+
+        /// 阶段性输出，例如"every=10m file=interim.json"或"records=1000000 file=interim.json"
+        #[arg(long)]
+        emit_interim: Option<InterimSpec>,
+
+        /// 每计入N条记录把一条的摘要（tid、pos、tlen、mapq、flags）追加到JSONL旁路文件，
+        /// 例如"every=10000 file=stream.jsonl"，可选flush=1s、max-bytes=64M（超过时轮转为<file>.1）
+        #[arg(long)]
+        stream_sample: Option<StreamSampleSpec>,
+
+        /// 插入片段直方图输出路径（TSV：orientation、bin_start、bin_end、count）
+        #[arg(long)]
+        histogram: Option<String>,
+
+        /// 输出直方图前做居中滑动平均，例如"window=5"（只影响输出，不影响统计量）
+        #[arg(long, requires = "histogram")]
+        histogram_smooth: Option<HistogramSmoothing>,
+
+        /// 合并相邻bin使每个方向的直方图不超过该行数，总计数不变
+        #[arg(long, requires = "histogram", value_parser = clap::value_parser!(u64).range(1..))]
+        histogram_max_points: Option<u64>,
+
+        /// TANDEM另按++与--分别统计，过滤统计报告中给出二者的读对数与比例
+        /// （真正的串联方向文库应接近1）；其它统计与输出不变
+        #[arg(long)]
+        tandem_split: bool,
+
+        /// 按读名以该比例（(0, 1]）抽样读对后再统计，读对的两端同进同出；
+        /// 种子由全局--seed派生，相同种子得到相同结果
+        #[arg(long, value_parser = parse_sample_fraction)]
+        sample_fraction: Option<f64>,
+
+        /// 期望的插入片段大小：与--tolerance一起使用，|中位数 − 期望值|超过容差时
+        /// 以退出码3结束（输出照常写出），过滤统计报告中包含断言结果
+        #[arg(long, requires = "tolerance", conflicts_with = "expect_range", allow_hyphen_values = true)]
+        expect: Option<i32>,
+
+        /// --expect允许的偏差（bp）
+        #[arg(long, requires = "expect")]
+        tolerance: Option<u32>,
+
+        /// 期望的插入片段范围（闭区间），例如300-450；中位数不在范围内时以退出码3结束
+        #[arg(long)]
+        expect_range: Option<ExpectedRange>,
     },
-}
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let cli = Cli::parse();
+    /// 统计10x/linked-read条形码分布（有效占比、不同条形码数、N50）
+    Barcodes {
+        /// 输入BAM/CRAM文件路径，`-`表示从标准输入读取BAM
+        #[arg(short, long)]
+        input: String,
 
-    // 初始化日志
-    let log_level = if cli.verbose { "debug" } else { "info" };
-    tracing_subscriber::fmt()
-        .with_env_filter(format!("bamqc={}", log_level))
-        .init();
+        /// 输出文件路径（可选，如果不指定则输出到标准输出）
+        #[arg(short, long)]
+        output: Option<String>,
 
-    match cli.command {
-        Commands::InsertSize {
-            input,
-            output,
-            include_duplicates,
-            require_proper_pair,
-            min_pct,
-            pair_orientation,
-            strategy,
-        } => {
-            handle_insert_size_command(
-                &input,
-                output,
-                include_duplicates,
-                require_proper_pair,
-                min_pct,
-                pair_orientation,
-                strategy,
-            )
-        }
-    }
-}
+        /// 条形码标签（BX为10x linked-read，CB为单细胞，也可指定任意两字符标签）
+        #[arg(long, default_value = "BX")]
+        tag: BarcodeTag,
 
-/// 处理insert_size子命令
-fn handle_insert_size_command(
-    input: &str,
-    output: Option<String>,
-    include_duplicates: bool,
-    require_proper_pair: bool,
-    min_pct: f64,
-    pair_orientation: PairOrientation,
-    strategy: Strategy,
-) -> Result<(), Box<dyn std::error::Error>> {
-    // 验证输入文件存在
-    if !Path::new(input).exists() {
-        error!("输入文件不存在: {}", input);
-        std::process::exit(1);
-    }
+        /// 保留条形码的"-1"等数字后缀（默认去除）
+        #[arg(long)]
+        keep_suffix: bool,
 
-    match compute_insert_size(
-        input,
-        include_duplicates,
-        require_proper_pair,
-        min_pct,
-        pair_orientation,
-        strategy,
-    ) {
-        Ok(median_size) => {
-            let result = median_size.to_string();
-            
-            // 根据是否提供输出文件决定输出方式
-            match output {
-                Some(output_path) => {
-                    // 写入文件
-                    match write(&output_path, &result) {
-                        Ok(_) => {
-                            println!("结果已保存到文件: {}", output_path);
-                            Ok(())
-                        }
-                        Err(e) => {
-                            error!("写入文件失败 {}: {}", output_path, e);
-                            std::process::exit(1);
-                        }
-                    }
-                }
-                None => {
-                    // 输出到标准输出
-                    println!("{}", result);
-                    Ok(())
-                }
-            }
-        }
-        Err(e) => {
-            error!("{}", e);
-            std::process::exit(1);
+        /// 校验前将条形码转换为大写
+        #[arg(long)]
+        uppercase: bool,
+
+        /// 允许条形码中包含N
+        #[arg(long)]
+        allow_n: bool,
+
+        /// top-k计数器保留的条形码数量上限
+        #[arg(long, default_value_t = DEFAULT_TOP_K)]
+        top_k: usize,
+
+        /// 阶段性输出，例如"every=10m file=interim.json"或"records=1000000 file=interim.json"
+        #[arg(long)]
+        emit_interim: Option<InterimSpec>,
+    },
+
+    /// 统计比对标志（与samtools flagstat类似，支持区域限定）
+    Flagstat {
+        /// 输入BAM/CRAM文件路径，`-`表示从标准输入读取BAM
+        #[arg(short, long)]
+        input: String,
+
+        /// 输出文件路径（可选，如果不指定则输出到标准输出）
+        #[arg(short, long)]
+        output: Option<String>,
+
+        /// 只统计与该区域重叠的记录，例如chr6:28,477,797-33,448,354（需要索引）
+        #[arg(long, conflicts_with = "regions_file")]
+        region: Option<GenomicRegion>,
+
+        /// BED区域文件（需要索引）
+        #[arg(long)]
+        regions_file: Option<String>,
+
+        /// 按区域逐行输出TSV（需要--regions-file），末行为所有区域的TOTAL
+        #[arg(long, requires = "regions_file")]
+        per_region: bool,
+
+        /// 与多个请求区域重叠的记录的归属：all（各计一次，各行之和可能超过TOTAL）、
+        /// first（只计入BED中靠前的区域）、merge（重叠区域合并为一行）
+        #[arg(long, default_value = "all", requires = "regions_file")]
+        region_overlap: RegionOverlap,
+
+        /// 按参考序列逐行输出TSV（含每条参考序列的secondary_ratio），末行为全文件的TOTAL
+        #[arg(long, conflicts_with_all = ["region", "regions_file"])]
+        by_contig: bool,
+
+        /// secondary/primary记录数之比超过该值时警告多重比对过多
+        #[arg(long, default_value_t = DEFAULT_MAX_SECONDARY_RATIO)]
+        max_secondary_ratio: f64,
+
+        /// 阶段性输出，例如"every=10m file=interim.json"或"records=1000000 file=interim.json"；
+        /// 只用于整个文件或--region的统计
+        #[arg(long, conflicts_with_all = ["regions_file", "by_contig"])]
+        emit_interim: Option<InterimSpec>,
+
+        /// 只有secondary、没有primary记录的读长（片段）把MAPQ最高的secondary记录当作primary计数；
+        /// 需要按读名排序的输入（samtools sort -n），其它排序只检测并警告
+        #[arg(long, conflicts_with_all = ["region", "regions_file"])]
+        promote_best_secondary: bool,
+    },
+
+    /// 比较两个BAM/CRAM文件的头部（@SQ、@RG、@PG），参考序列字典不兼容时返回非零退出码
+    DiffHeader {
+        /// 第一个BAM/CRAM文件路径
+        left: String,
+
+        /// 第二个BAM/CRAM文件路径
+        right: String,
+
+        /// 输出文件路径（可选，如果不指定则输出到标准输出）
+        #[arg(short, long)]
+        output: Option<String>,
+
+        /// 输出格式
+        #[arg(long, value_enum, default_value = "text")]
+        format: OutputFormat,
+    },
+
+    /// 按文库统计重复率与估计文库大小（重复始终按文库累积，与Picard一致）
+    Duplication {
+        /// 输入BAM/CRAM文件路径，`-`表示从标准输入读取BAM
+        #[arg(short, long)]
+        input: String,
+
+        /// 输出文件路径（可选，如果不指定则输出到标准输出）
+        #[arg(short, long)]
+        output: Option<String>,
+
+        /// 记录中出现头部@RG未声明的读组时报错退出（默认登记为不在头部的文库并警告）
+        #[arg(long)]
+        strict_read_groups: bool,
+
+        /// 头部声明了多个样本（SM）时仍合并所有样本统计（默认按样本分别输出，文件名加样本后缀）
+        #[arg(long)]
+        merge_samples: bool,
+
+        /// 输出格式
+        #[arg(long, value_enum, default_value = "text")]
+        format: OutputFormat,
+    },
+
+    /// 重复集合大小的分布（按坐标排序、已标记duplicate的BAM），以及由其估计的文库大小
+    DupSets {
+        /// 输入BAM/CRAM文件路径（头部须声明SO:coordinate），`-`表示从标准输入读取BAM
+        #[arg(short, long)]
+        input: String,
+
+        /// 输出文件路径（可选，如果不指定则输出到标准输出）
+        #[arg(short, long)]
+        output: Option<String>,
+
+        /// 滑动窗口（bp）：5'端未clip位置落后当前比对起点超过该值的集合结束，应不小于最长的clip
+        #[arg(long, default_value_t = DEFAULT_DUP_SET_WINDOW, value_parser = clap::value_parser!(u64).range(1..=MAX_DUP_SET_WINDOW))]
+        window: u64,
+
+        /// 最多同时保存的未结束集合数，超过时提前结束5'端位置最小的集合并计入spilled_sets
+        #[arg(long, default_value_t = DEFAULT_MAX_OPEN_SETS, value_parser = clap::value_parser!(u64).range(1..))]
+        max_open_sets: u64,
+
+        /// 输出格式
+        #[arg(long, value_enum, default_value = "text")]
+        format: OutputFormat,
+    },
+
+    /// 靶向panel逐靶区指标（平均深度、加权平均MAPQ、MAPQ0占比）
+    Targets {
+        /// 输入BAM/CRAM文件路径（需要索引）
+        #[arg(short, long)]
+        input: String,
+
+        /// 靶区BED文件
+        #[arg(short, long)]
+        targets: String,
+
+        /// 覆盖最差（20x占比最低）与覆盖最高（平均深度最高）的靶区各列出的数量
+        #[arg(long, default_value_t = EXTREME_TARGETS)]
+        extreme_targets: usize,
+
+        /// 输出文件路径（可选，如果不指定则输出到标准输出）
+        #[arg(short, long)]
+        output: Option<String>,
+
+        /// 输出格式
+        #[arg(long, value_enum, default_value = "text")]
+        format: OutputFormat,
+    },
+
+    /// 按同一BED比较两个BAM的逐靶区相对覆盖：各自按靶区中位深度归一化后计算log2(B / A)
+    CoverageCompare {
+        /// 作为分母的BAM/CRAM文件路径（需要索引）
+        a: String,
+
+        /// 作为分子的BAM/CRAM文件路径（需要索引）
+        b: String,
+
+        /// 靶区BED文件
+        #[arg(short, long)]
+        targets: String,
+
+        /// |log2_ratio|超过该值的靶区标记为gain或loss
+        #[arg(long, default_value_t = DEFAULT_LOG2_THRESHOLD)]
+        log2_threshold: f64,
+
+        /// 输出文件路径（可选，如果不指定则输出到标准输出）
+        #[arg(short, long)]
+        output: Option<String>,
+
+        /// 输出格式
+        #[arg(long, value_enum, default_value = "text")]
+        format: OutputFormat,
+    },
+
+    /// 由靶区富集推断捕获试剂盒：按抽样读长在各候选BED上的富集倍数排名
+    DetectKit {
+        /// 输入BAM/CRAM文件路径
+        #[arg(short, long)]
+        input: String,
+
+        /// 候选试剂盒BED所在目录（每个`.bed`文件一个试剂盒，文件名即试剂盒名）
+        #[arg(long)]
+        kit_library: String,
+
+        /// 抽取这么多条读长后停止，0表示读完整个文件
+        #[arg(long, default_value_t = DEFAULT_KIT_SAMPLE_READS)]
+        stop_after: u64,
+
+        /// 每条读长被抽取的概率（0到1），坐标排序的BAM用它把样本分散到全基因组
+        #[arg(long, default_value_t = 1.0)]
+        subsample: f64,
+
+        /// 最佳试剂盒的富集倍数低于该值时警告没有试剂盒明显高于背景
+        #[arg(long, default_value_t = DEFAULT_MIN_ENRICHMENT)]
+        min_enrichment: f64,
+
+        /// 输出文件路径（可选，如果不指定则输出到标准输出）
+        #[arg(short, long)]
+        output: Option<String>,
+
+        /// 输出格式
+        #[arg(long, value_enum, default_value = "text")]
+        format: OutputFormat,
+    },
+
+    /// 按GC含量分bin的重复率，以及重复率对GC的线性拟合斜率
+    GcDup {
+        /// 输入BAM/CRAM文件路径，`-`表示从标准输入读取BAM
+        #[arg(short, long)]
+        input: String,
+
+        /// 输出文件路径（可选，如果不指定则输出到标准输出）
+        #[arg(short, long)]
+        output: Option<String>,
+
+        /// 按读对统计（两端序列合并计算GC）
+        #[arg(long)]
+        pairs: bool,
+
+        /// 计数低于该值的GC bin照常输出，但不参与拟合
+        #[arg(long, default_value_t = DEFAULT_MIN_BIN_COUNT)]
+        min_bin_count: u64,
+
+        /// 外部排序的临时目录（读对模式处理未排序BAM时使用，默认为系统临时目录）
+        #[arg(long)]
+        tmp_dir: Option<PathBuf>,
+
+        /// 输出格式
+        #[arg(long, value_enum, default_value = "text")]
+        format: OutputFormat,
+    },
+
+    /// 碱基质量产出（Q20/Q30碱基数），可选用BQSR前的OQ原始质量值
+    QualityYield {
+        /// 输入BAM/CRAM文件路径，`-`表示从标准输入读取BAM
+        #[arg(short, long)]
+        input: String,
+
+        /// 输出文件路径（可选，如果不指定则输出到标准输出）
+        #[arg(short, long)]
+        output: Option<String>,
+
+        /// 质量值来源：qual为QUAL字段，oq为OQ:Z标签（缺失或无效时回退到QUAL并计数）
+        #[arg(long, value_enum, default_value = "qual")]
+        quality_source: QualitySource,
+
+        /// 头部声明了多个样本（SM）时仍合并所有样本统计（默认按样本分别输出，文件名加样本后缀）
+        #[arg(long)]
+        merge_samples: bool,
+
+        /// 输出格式
+        #[arg(long, value_enum, default_value = "text")]
+        format: OutputFormat,
+    },
+
+    /// 同聚物旁的插入缺失率（ONT等平台错误模式的提示），文本格式为按同聚物长度分层的TSV
+    HomopolymerIndels {
+        /// 输入BAM/CRAM文件路径，`-`表示从标准输入读取BAM
+        #[arg(short, long)]
+        input: String,
+
+        /// 输出文件路径（可选，如果不指定则输出到标准输出）
+        #[arg(short, long)]
+        output: Option<String>,
+
+        /// 侧翼读长碱基的同聚物不短于该长度时计为同聚物旁的插入缺失
+        #[arg(long, default_value_t = DEFAULT_MIN_HOMOPOLYMER as u64, value_parser = clap::value_parser!(u64).range(1..))]
+        min_homopolymer: u64,
+
+        /// 输出格式
+        #[arg(long, value_enum, default_value = "text")]
+        format: OutputFormat,
+    },
+
+    /// 逐测序循环的soft clip率（read1/read2、测序方向5'/3'端），标记疑似接头读穿的循环
+    Clipping {
+        /// 输入BAM/CRAM文件路径，`-`表示从标准输入读取BAM
+        #[arg(short, long)]
+        input: String,
+
+        /// 输出文件路径（可选，如果不指定则输出到标准输出）
+        #[arg(short, long)]
+        output: Option<String>,
+
+        /// 3'端clip率超过该值的循环标记为疑似接头读穿
+        #[arg(long, default_value_t = DEFAULT_ADAPTER_CLIP_RATE)]
+        adapter_clip_rate: f64,
+
+        /// 输出格式
+        #[arg(long, value_enum, default_value = "text")]
+        format: OutputFormat,
+    },
+
+    /// 逐窗口平均深度，输出wiggle fixedStep（可用wigToBigWig转为bigWig）
+    Coverage {
+        /// 输入BAM/CRAM文件路径（指定--region或--targets时需要索引）
+        #[arg(short, long)]
+        input: String,
+
+        /// fixedStep输出路径（可选，如果不指定则输出到标准输出）
+        #[arg(short, long)]
+        output: Option<String>,
+
+        /// 窗口大小（bp），从每条参考序列的第1个碱基起划分为互不重叠的窗口
+        #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+        depth_windows: u64,
+
+        /// 同时把逐窗口平均深度写成TSV（contig、start、end、length、mean_depth）
+        #[arg(long)]
+        tsv: Option<String>,
+
+        /// 只统计该区域，例如chr1:1,000,001-2,000,000
+        #[arg(long, conflicts_with_all = ["targets", "cytobands"])]
+        region: Option<GenomicRegion>,
+
+        /// 只统计BED文件中的靶区
+        #[arg(long, conflicts_with = "cytobands")]
+        targets: Option<String>,
+
+        /// UCSC cytoBand.txt：按染色体臂汇总深度与插入片段中位数，写到--arm-tsv
+        #[arg(long)]
+        cytobands: Option<String>,
+
+        /// 逐臂汇总的TSV输出路径（需要--cytobands）
+        #[arg(long, requires = "cytobands")]
+        arm_tsv: Option<String>,
+
+        /// 臂的归一化深度偏离1超过该值时标记为gain或loss
+        #[arg(long, default_value_t = DEFAULT_ARM_MAX_DEVIATION)]
+        arm_max_deviation: f64,
+
+        /// 头部声明按坐标排序时最多允许的乱序记录数，超过时报错（默认第一条乱序记录即报错）
+        #[arg(long, value_name = "N", default_value_t = 0)]
+        tolerate_unsorted_records: u64,
+    },
+
+    /// 任意数值型辅助标签的分布（出现/缺失数、最小/最大/均值/中位数与直方图）
+    TagStats {
+        /// 输入BAM/CRAM文件路径，`-`表示从标准输入读取BAM
+        #[arg(short, long)]
+        input: String,
+
+        /// 输出文件路径（可选，如果不指定则输出到标准输出）
+        #[arg(short, long)]
+        output: Option<String>,
+
+        /// 要统计的标签及类型（A、i或f），例如"XS:i"，可重复指定
+        #[arg(long = "tag", required = true)]
+        tags: Vec<TagSpec>,
+
+        /// 浮点标签的分bin宽度
+        #[arg(long, default_value_t = DEFAULT_FLOAT_BIN_WIDTH, value_parser = parse_bin_width)]
+        float_bin_width: f64,
+
+        /// 直方图输出路径（TSV：tag、value、count）
+        #[arg(long)]
+        histogram: Option<String>,
+
+        /// 输出格式
+        #[arg(long, value_enum, default_value = "text")]
+        format: OutputFormat,
+    },
+
+    /// 生成参数已知的模拟BAM（按坐标排序并建立索引），输出真值参数
+    GenerateTestData {
+        /// 输出BAM路径（索引写到同目录的.bai）
+        #[arg(long)]
+        out: String,
+
+        /// 读对数（含duplicate与未比对读对）
+        #[arg(long, default_value_t = 100_000)]
+        pairs: u64,
+
+        /// 插入片段均值
+        #[arg(long, default_value_t = 400.0)]
+        insert_mean: f64,
+
+        /// 插入片段标准差
+        #[arg(long, default_value_t = 60.0)]
+        insert_sd: f64,
+
+        /// duplicate读对比例
+        #[arg(long, default_value_t = 0.1)]
+        dup_rate: f64,
+
+        /// RF方向读对比例
+        #[arg(long, default_value_t = 0.02)]
+        rf_fraction: f64,
+
+        /// 未比对读对比例
+        #[arg(long, default_value_t = 0.0)]
+        unmapped_fraction: f64,
+
+        /// 每个比对读对的secondary比对期望数（模拟多重比对）
+        #[arg(long, default_value_t = 0.0)]
+        secondary_rate: f64,
+
+        /// 读长
+        #[arg(long, default_value_t = 150, value_parser = clap::value_parser!(u32).range(1..))]
+        read_length: u32,
+
+        /// 真值参数的输出格式
+        #[arg(long, value_enum, default_value = "text")]
+        format: OutputFormat,
+    },
+
+    /// 以SAM文本输出通过过滤的记录（samtools view的精简版，用于抽查过滤条件）
+    View {
+        /// 输入BAM/CRAM文件路径，`-`表示从标准输入读取BAM
+        #[arg(short, long)]
+        input: String,
+
+        /// 输出文件路径（可选，如果不指定则输出到标准输出）
+        #[arg(short, long)]
+        output: Option<String>,
+
+        /// 只输出FLAG中这些位全部置位的记录（十进制或0x十六进制）
+        #[arg(short = 'f', long, default_value = "0", value_parser = parse_flag_mask)]
+        require_flags: u16,
+
+        /// 排除FLAG中这些位任一置位的记录（十进制或0x十六进制）
+        #[arg(short = 'F', long, default_value = "0", value_parser = parse_flag_mask)]
+        exclude_flags: u16,
+
+        /// 最低MAPQ
+        #[arg(short = 'q', long, default_value_t = 0)]
+        min_mapq: u8,
+
+        /// 只输出与该区域重叠的记录（需要索引）
+        #[arg(long)]
+        region: Option<GenomicRegion>,
+
+        /// 最多输出的记录数
+        #[arg(long)]
+        limit: Option<u64>,
+
+        /// 先输出SAM头部
+        #[arg(long)]
+        header: bool,
+    },
+
+    /// 每条参考序列的已比对/未比对记录数（与samtools idxstats一致）
+    Idxstats {
+        /// 输入BAM文件路径
+        #[arg(short, long)]
+        input: String,
+
+        /// 输出文件路径（可选，如果不指定则输出到标准输出）
+        #[arg(short, long)]
+        output: Option<String>,
+
+        /// 直接读取BAI/CSI索引中的计数，不扫描记录（索引没有计数元数据时仍扫描）
+        #[arg(long)]
+        from_index: bool,
+    },
+
+    /// 快速检查：头部、BGZF EOF标记以及索引是否过期（不大于16 MiB的BAM另外扫描记录，核对索引中的记录计数）
+    QuickCheck {
+        /// 输入BAM文件路径
+        #[arg(short, long)]
+        input: String,
+
+        /// 输出文件路径（可选，如果不指定则输出到标准输出）
+        #[arg(short, long)]
+        output: Option<String>,
+
+        /// 输出格式
+        #[arg(long, value_enum, default_value = "text")]
+        format: OutputFormat,
+    },
+
+    /// 检查每条记录FLAG中互相矛盾的位组合（如mate未比对却设置了mate反向），按种类与FLAG计数
+    Validate {
+        /// 输入BAM/CRAM文件路径，`-`表示从标准输入读取BAM
+        #[arg(short, long)]
+        input: String,
+
+        /// 输出文件路径（可选，如果不指定则输出到标准输出）
+        #[arg(short, long)]
+        output: Option<String>,
+
+        /// 输出格式
+        #[arg(long, value_enum, default_value = "text")]
+        format: OutputFormat,
+    },
+
+    /// LIMS交付：按预设运行收集器组合并给出判定，在--out-dir中写出summary.json、
+    /// metrics.tsv、histograms.tsv与report.html
+    Lims {
+        /// 输入BAM文件路径
+        #[arg(short, long)]
+        input: String,
+
+        /// 收集器组合
+        #[arg(long, value_enum)]
+        preset: LimsPreset,
+
+        /// 写入交付物的样本名（默认取自头部@RG SM，头部须只有一个样本）
+        #[arg(long)]
+        sample_name: Option<String>,
+
+        /// 靶区BED（wes预设时增加逐靶区指标）
+        #[arg(long)]
+        targets: Option<String>,
+    },
+
+    /// 指标字典：输出中每个键的显示名称、定义、单位与好坏方向
+    Metrics {
+        /// 列出全部指标（TSV：collector、key、display_name、unit、higher_is_better、definition）
+        #[arg(long, conflicts_with_all = ["key", "files"], required_unless_present_any = ["key", "files"])]
+        list: bool,
+
+        /// 列出--out-dir中各子命令的标准文件名（TSV：collector、role、text、json、description）
+        #[arg(long, conflicts_with = "key")]
+        files: bool,
+
+        /// 查询单个键的定义
+        key: Option<String>,
+
+        /// 输出文件路径（可选，如果不指定则输出到标准输出）
+        #[arg(short, long)]
+        output: Option<String>,
+
+        /// 输出格式
+        #[arg(long, value_enum, default_value = "text")]
+        format: OutputFormat,
+    },
+}
+
+impl Commands {
+    /// 子命令名
+    fn name(&self) -> &'static str {
+        match self {
+            Commands::View { .. } => "view",
+            Commands::GenerateTestData { .. } => "generate-test-data",
+            Commands::Metrics { .. } => "metrics",
+            _ => self.collector().unwrap_or_default(),
+        }
+    }
+
+    /// 子命令的全部输入文件
+    fn inputs(&self) -> Vec<&str> {
+        match self {
+            Commands::InsertSize { input, .. } => input.iter().map(String::as_str).collect(),
+            Commands::Barcodes { input, .. }
+            | Commands::Flagstat { input, .. }
+            | Commands::Targets { input, .. }
+            | Commands::DetectKit { input, .. }
+            | Commands::Duplication { input, .. }
+            | Commands::DupSets { input, .. }
+            | Commands::GcDup { input, .. }
+            | Commands::QualityYield { input, .. }
+            | Commands::HomopolymerIndels { input, .. }
+            | Commands::Clipping { input, .. }
+            | Commands::Coverage { input, .. }
+            | Commands::TagStats { input, .. }
+            | Commands::View { input, .. }
+            | Commands::Idxstats { input, .. }
+            | Commands::QuickCheck { input, .. }
+            | Commands::Validate { input, .. }
+            | Commands::Lims { input, .. } => vec![input],
+            Commands::DiffHeader { left, right, .. } => vec![left, right],
+            Commands::CoverageCompare { a, b, .. } => vec![a, b],
+            Commands::GenerateTestData { .. } | Commands::Metrics { .. } => vec![],
+        }
+    }
+
+    /// 写入--out-dir标准文件的子命令名（与`bamqc metrics --files`一致）
+    fn collector(&self) -> Option<&'static str> {
+        Some(match self {
+            Commands::InsertSize { .. } => "insert-size",
+            Commands::Barcodes { .. } => "barcodes",
+            Commands::Flagstat { .. } => "flagstat",
+            Commands::DiffHeader { .. } => "diff-header",
+            Commands::Duplication { .. } => "duplication",
+            Commands::DupSets { .. } => "dup-sets",
+            Commands::Targets { .. } => "targets",
+            Commands::CoverageCompare { .. } => "coverage-compare",
+            Commands::DetectKit { .. } => "detect-kit",
+            Commands::GcDup { .. } => "gc-dup",
+            Commands::Idxstats { .. } => "idxstats",
+            Commands::QualityYield { .. } => "quality-yield",
+            Commands::HomopolymerIndels { .. } => "homopolymer-indels",
+            Commands::Clipping { .. } => "clipping",
+            Commands::Coverage { .. } => "coverage",
+            Commands::TagStats { .. } => "tag-stats",
+            Commands::QuickCheck { .. } => "quick-check",
+            Commands::Validate { .. } => "validate",
+            Commands::Lims { .. } => "lims",
+            Commands::View { .. } | Commands::GenerateTestData { .. } | Commands::Metrics { .. } => return None,
+        })
+    }
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let cli = Cli::parse();
+
+    // 初始化日志
+    let log_level = if cli.verbose { "debug" } else { "info" };
+    let subscriber = tracing_subscriber::fmt().with_env_filter(format!("bamqc={}", log_level));
+    if matches!(cli.command, Commands::View { output: None, .. }) {
+        // SAM文本写到标准输出时，日志改写到标准错误
+        subscriber.with_writer(std::io::stderr).init();
+    } else {
+        subscriber.init();
+    }
+
+    let (threads, reason) = threads::resolve(cli.threads);
+    tracing::debug!("线程数: {}（{}）", threads, reason);
+
+    let watchdog = WatchdogConfig {
+        timeout: cli.timeout,
+        io_stall_timeout: cli.io_stall_timeout,
+    };
+    // 主线程可能阻塞在read()中，由看门狗线程直接退出进程；不输出部分结果
+    // （见bamqc_core::watchdog）；支持--emit-interim的子命令保留最后写出的快照
+    let _watchdog = Watchdog::spawn(watchdog, &READ_PROGRESS, |reason| {
+        error!("{}", reason);
+        std::process::exit(EXIT_CANCELLED);
+    });
+
+    // 读取记录前检查输入、索引与输出；--dry-run时输出计划后退出
+    let plan = Planner::new(&cli).plan();
+    if cli.dry_run {
+        print!("{}", plan);
+        std::process::exit(if plan.has_errors() { 1 } else { 0 });
+    }
+    for finding in &plan.findings {
+        match finding.severity {
+            Severity::Error => error!("{}: {}", finding.subject, finding.message),
+            // 警告由收集器在运行中照常报告
+            _ => tracing::debug!("{} {}: {}", finding.check, finding.subject, finding.message),
+        }
+    }
+    if plan.has_errors() {
+        std::process::exit(1);
+    }
+
+    // 各收集器打开输入时使用的读取器配置（含--input-format）；参考FASTA已由计划检查
+    let mut reader = BamReaderOptions::new()
+        .threads(threads)
+        .format(cli.input_format)
+        .retry(RetryPolicy::new(cli.io_retries))
+        .verify(cli.verify_bgzf);
+    if let Some(reference) = &cli.reference {
+        reader = reader.reference(reference);
+    }
+
+    // 块级自检在各输入首次读取记录前进行，失败时收集器返回BgzfChecksum错误
+    if cli.verify_bgzf {
+        for input in &plan.inputs {
+            if is_stdin(&input.path) {
+                tracing::info!("标准输入无法回退重读，跳过块级自检");
+            } else if input.format != Format::Bam {
+                tracing::info!("{}: {}不是BGZF格式，跳过块级自检", input.path, input.format);
+            }
+        }
+    }
+
+    let layout = cli.out_dir.as_ref().map(|dir| {
+        OutputLayout::create(dir, cli.force).unwrap_or_else(|e| {
+            error!("无法创建输出目录 {}", e);
+            std::process::exit(1);
+        })
+    });
+    let layout = layout.as_ref();
+
+    // 输入与生效选项都与上次相同时直接复用--out-dir中的结果
+    let cache = match (layout, cli.command.collector()) {
+        (Some(layout), Some(collector)) => {
+            // --reference改变CRAM解码出的序列，--input-format改变输入的解析方式
+            let options = format!(
+                "{:?} seed={} input_format={:?} reference={:?}",
+                cli.command, cli.seed, cli.input_format, cli.reference
+            );
+            match fingerprint_inputs(&cli.command, cli.reference.as_deref()) {
+                Some(inputs) => {
+                    let key = run_key(collector, env!("CARGO_PKG_VERSION"), &options, &inputs);
+                    if !cli.no_cache {
+                        if let Some(outputs) = layout.cached_run(collector, &key) {
+                            tracing::info!(
+                                "输入与选项与上次运行相同（运行键 {}），复用 {} 中的结果；重新计算请使用--no-cache",
+                                key,
+                                layout.dir().display()
+                            );
+                            for output in outputs {
+                                println!("{}", output.display());
+                            }
+                            return Ok(());
+                        }
+                    }
+                    Some((layout, collector, key, inputs))
+                }
+                None => None,
+            }
+        }
+        _ => None,
+    };
+
+    let result = match cli.command {
+        Commands::InsertSize {
+            input,
+            output,
+            include_duplicates,
+            pair_duplicate_policy,
+            require_proper_pair,
+            min_pct,
+            pair_orientation,
+            strategy,
+            filter_report,
+            report_format,
+            report_largest,
+            max_insert_size,
+            min_mapq,
+            strict_mapq,
+            report_excluded_distances,
+            accumulation_level,
+            strict_read_groups,
+            merge_samples,
+            emit_interim,
+            stream_sample,
+            histogram,
+            histogram_smooth,
+            histogram_max_points,
+            tandem_split,
+            sample_fraction,
+            expect,
+            tolerance,
+            expect_range,
+        } => {
+            let options = InsertSizeOptions {
+                include_duplicates,
+                require_proper_pair,
+                min_pct,
+                orientation_pref: pair_orientation,
+                strategy,
+                report_largest,
+                accumulation_level,
+                max_insert_size,
+                report_excluded_distances,
+                strict_read_groups,
+                pair_duplicate_policy,
+                tandem_split,
+                stream_sample,
+                min_mapq,
+                strict_mapq,
+                sample_fraction,
+                sample_seed: derive_seed(cli.seed, DOWNSAMPLE_SEED_COMPONENT),
+                reader: reader.clone(),
+            };
+            let output = layout_path(layout, output, "insert-size", "main", false);
+            let filter_report = layout_path(
+                layout,
+                filter_report,
+                "insert-size",
+                "filter_report",
+                report_format == OutputFormat::Json,
+            );
+            let histogram = layout_path(layout, histogram, "insert-size", "histogram", false);
+            let report = filter_report.map(|path| (path, report_format));
+            let histogram = histogram.map(|path| HistogramOutput {
+                path,
+                smooth: histogram_smooth,
+                max_points: histogram_max_points.map(|n| n as usize),
+            });
+            let expectation = match (expect, tolerance, expect_range) {
+                (Some(expected), Some(tolerance), _) => Some(InsertSizeExpectation::Value { expected, tolerance }),
+                (_, _, Some(range)) => Some(InsertSizeExpectation::Range(range)),
+                _ => None,
+            };
+            let inputs: Vec<&str> = input.iter().map(String::as_str).collect();
+            match inputs.as_slice() {
+                [input] if stratify_samples(input, &reader, merge_samples) => {
+                    handle_insert_size_per_sample_command(input, output, &options, report, histogram, expectation, emit_interim)
+                }
+                [_] => handle_insert_size_command(&inputs, output, &options, report, histogram, expectation, emit_interim),
+                _ => {
+                    check_merged_samples(&inputs, &reader, merge_samples);
+                    handle_insert_size_command(&inputs, output, &options, report, histogram, expectation, emit_interim)
+                }
+            }
+        }
+        Commands::Barcodes {
+            input,
+            output,
+            tag,
+            keep_suffix,
+            uppercase,
+            allow_n,
+            top_k,
+            emit_interim,
+        } => {
+            let options = BarcodeOptions {
+                tag,
+                strip_suffix: !keep_suffix,
+                uppercase,
+                allow_n,
+                top_k,
+                seed: cli.seed,
+                reader: reader.clone(),
+            };
+            let output = layout_path(layout, output, "barcodes", "main", false);
+            handle_barcodes_command(&input, output, options, emit_interim)
+        }
+        Commands::Flagstat {
+            input,
+            output,
+            region,
+            regions_file,
+            per_region,
+            region_overlap,
+            by_contig,
+            max_secondary_ratio,
+            emit_interim,
+            promote_best_secondary,
+        } => {
+            let output = layout_path(layout, output, "flagstat", "main", false);
+            let scope = match regions_file {
+                Some(bed) => FlagstatScope::Regions { bed, per_region, overlap: region_overlap },
+                None if by_contig => FlagstatScope::ByContig,
+                None => FlagstatScope::Whole(region),
+            };
+            let options = FlagStatOptions { promote_best_secondary, reader: reader.clone(), ..Default::default() };
+            handle_flagstat_command(&input, output, scope, max_secondary_ratio, emit_interim, &options)
+        }
+        Commands::DiffHeader {
+            left,
+            right,
+            output,
+            format,
+        } => {
+            let output = layout_path(layout, output, "diff-header", "main", format == OutputFormat::Json);
+            handle_diff_header_command(&left, &right, &reader, output, format)
+        }
+        Commands::Duplication {
+            input,
+            output,
+            strict_read_groups,
+            merge_samples,
+            format,
+        } => {
+            let output = layout_path(layout, output, "duplication", "main", format == OutputFormat::Json);
+            if stratify_samples(&input, &reader, merge_samples) {
+                handle_duplication_per_sample_command(&input, &reader, output, strict_read_groups, format)
+            } else {
+                handle_duplication_command(&input, &reader, output, strict_read_groups, format)
+            }
+        }
+        Commands::DupSets {
+            input,
+            output,
+            window,
+            max_open_sets,
+            format,
+        } => {
+            let output = layout_path(layout, output, "dup-sets", "main", format == OutputFormat::Json);
+            handle_dup_sets_command(&input, &reader, output, window, max_open_sets, format)
+        }
+        Commands::Targets {
+            input,
+            targets,
+            extreme_targets,
+            output,
+            format,
+        } => {
+            let output = layout_path(layout, output, "targets", "main", format == OutputFormat::Json);
+            handle_targets_command(&input, &reader, &targets, extreme_targets, output, format)
+        }
+        Commands::CoverageCompare {
+            a,
+            b,
+            targets,
+            log2_threshold,
+            output,
+            format,
+        } => {
+            let output = layout_path(layout, output, "coverage-compare", "main", format == OutputFormat::Json);
+            handle_coverage_compare_command(&a, &b, &reader, &targets, log2_threshold, output, format)
+        }
+        Commands::DetectKit {
+            input,
+            kit_library,
+            stop_after,
+            subsample,
+            min_enrichment,
+            output,
+            format,
+        } => {
+            let options = KitDetectOptions { stop_after, subsample, min_enrichment, seed: cli.seed, reader: reader.clone() };
+            let output = layout_path(layout, output, "detect-kit", "main", format == OutputFormat::Json);
+            handle_detect_kit_command(&input, &kit_library, &options, output, format)
+        }
+        Commands::GcDup {
+            input,
+            output,
+            pairs,
+            min_bin_count,
+            tmp_dir,
+            format,
+        } => {
+            let output = layout_path(layout, output, "gc-dup", "main", format == OutputFormat::Json);
+            let options = GcDupOptions { pairs, min_bin_count, tmp_dir, reader: reader.clone() };
+            handle_gc_dup_command(&input, output, &options, format)
+        }
+        Commands::View {
+            input,
+            output,
+            require_flags,
+            exclude_flags,
+            min_mapq,
+            region,
+            limit,
+            header,
+        } => {
+            let options = ViewOptions {
+                filter: RecordFilter {
+                    require_flags,
+                    exclude_flags,
+                    min_mapq,
+                },
+                region,
+                limit,
+                include_header: header,
+                reader: reader.clone(),
+            };
+            handle_view_command(&input, output, &options)
+        }
+        Commands::Idxstats {
+            input,
+            output,
+            from_index,
+        } => {
+            let output = layout_path(layout, output, "idxstats", "main", false);
+            handle_idxstats_command(&input, &reader, output, from_index)
+        }
+        Commands::QualityYield {
+            input,
+            output,
+            quality_source,
+            merge_samples,
+            format,
+        } => {
+            let output = layout_path(layout, output, "quality-yield", "main", format == OutputFormat::Json);
+            if stratify_samples(&input, &reader, merge_samples) {
+                handle_quality_yield_per_sample_command(&input, &reader, output, quality_source, format)
+            } else {
+                handle_quality_yield_command(&input, &reader, output, quality_source, format)
+            }
+        }
+        Commands::HomopolymerIndels {
+            input,
+            output,
+            min_homopolymer,
+            format,
+        } => {
+            let output = layout_path(layout, output, "homopolymer-indels", "main", format == OutputFormat::Json);
+            handle_homopolymer_indels_command(&input, &reader, output, min_homopolymer as usize, format)
+        }
+        Commands::Clipping {
+            input,
+            output,
+            adapter_clip_rate,
+            format,
+        } => {
+            let output = layout_path(layout, output, "clipping", "main", format == OutputFormat::Json);
+            handle_clipping_command(&input, &reader, output, adapter_clip_rate, format)
+        }
+        Commands::Coverage {
+            input,
+            output,
+            depth_windows,
+            tsv,
+            region,
+            targets,
+            cytobands,
+            arm_tsv,
+            arm_max_deviation,
+            tolerate_unsorted_records,
+        } => {
+            let output = layout_path(layout, output, "coverage", "main", false);
+            let tsv = layout_path(layout, tsv, "coverage", "tsv", false);
+            let scope = match (cytobands, targets) {
+                (Some(cytobands), _) => CoverageScope::Arms {
+                    cytobands,
+                    arm_tsv: layout_path(layout, arm_tsv, "coverage", "arms", false),
+                    max_deviation: arm_max_deviation,
+                },
+                (None, Some(bed)) => CoverageScope::Targets(bed),
+                (None, None) => CoverageScope::Whole(region),
+            };
+            handle_coverage_command(&input, &reader, output, depth_windows, tsv, scope, tolerate_unsorted_records)
+        }
+        Commands::TagStats {
+            input,
+            output,
+            tags,
+            float_bin_width,
+            histogram,
+            format,
+        } => {
+            let output = layout_path(layout, output, "tag-stats", "main", format == OutputFormat::Json);
+            let histogram = layout_path(layout, histogram, "tag-stats", "histogram", false);
+            handle_tag_stats_command(&input, &reader, output, tags, float_bin_width, histogram, format)
+        }
+        Commands::GenerateTestData {
+            out,
+            pairs,
+            insert_mean,
+            insert_sd,
+            dup_rate,
+            rf_fraction,
+            unmapped_fraction,
+            secondary_rate,
+            read_length,
+            format,
+        } => {
+            let params = SimulationParams {
+                pairs,
+                insert_mean,
+                insert_sd,
+                dup_rate,
+                rf_fraction,
+                unmapped_fraction,
+                secondary_rate,
+                read_length,
+                seed: cli.seed,
+            };
+            handle_generate_test_data_command(&out, &params, format)
+        }
+        Commands::QuickCheck {
+            input,
+            output,
+            format,
+        } => {
+            let output = layout_path(layout, output, "quick-check", "main", format == OutputFormat::Json);
+            handle_quick_check_command(&input, &reader, output, format)
+        }
+        Commands::Validate {
+            input,
+            output,
+            format,
+        } => {
+            let output = layout_path(layout, output, "validate", "main", format == OutputFormat::Json);
+            handle_validate_command(&input, &reader, output, format)
+        }
+        Commands::Lims {
+            input,
+            preset,
+            sample_name,
+            targets,
+        } => {
+            let Some(layout) = layout else {
+                error!("lims需要--out-dir");
+                std::process::exit(1);
+            };
+            handle_lims_command(&input, &reader, layout, preset, sample_name, targets, cli.seed)
+        }
+        Commands::Metrics {
+            list: _,
+            files,
+            key,
+            output,
+            format,
+        } => {
+            if files {
+                handle_metrics_files_command(output, format)
+            } else {
+                handle_metrics_command(key.as_deref(), output, format)
+            }
+        }
+    };
+
+    WARNINGS.log_summary();
+
+    if let (Ok(()), Some((layout, collector, key, inputs))) = (&result, cache) {
+        match layout.write_manifest(collector, &key, env!("CARGO_PKG_VERSION"), &inputs) {
+            Ok(path) => tracing::debug!("已写出运行清单: {}（运行键 {}）", path.display(), key),
+            Err(e) => tracing::warn!("写出运行清单失败，下次运行不能复用结果: {}", e),
+        }
+    }
+    result
+}
+
+/// 输入文件与参考FASTA的指纹；读取失败时返回None（不使用缓存）
+fn fingerprint_inputs(command: &Commands, reference: Option<&Path>) -> Option<Vec<InputFingerprint>> {
+    let reference = reference.map(|path| path.to_string_lossy().to_string());
+    command
+        .inputs()
+        .into_iter()
+        .chain(reference.as_deref())
+        .map(|input| {
+            if is_stdin(input) {
+                tracing::info!("输入来自标准输入，不复用上次结果");
+                return None;
+            }
+            fingerprint_file(input)
+                .map_err(|e| tracing::warn!("无法计算输入指纹 {}: {}，不复用上次结果", input, e))
+                .ok()
+        })
+        .collect()
+}
+
+/// 直方图输出设置
+struct HistogramOutput {
+    path: String,
+    smooth: Option<HistogramSmoothing>,
+    max_points: Option<usize>,
+}
+
+impl HistogramOutput {
+    /// 先平滑再降采样，返回TSV内容
+    fn render(&self, histograms: &[(PairOrientation, Histogram)]) -> String {
+        let mut content = format!("orientation\t{}", Histogram::TSV_HEADER);
+        for (orientation, histogram) in histograms {
+            let mut histogram = histogram.clone();
+            if let Some(smooth) = self.smooth {
+                histogram = histogram.smoothed(smooth.window);
+            }
+            if let Some(max_points) = self.max_points {
+                histogram = histogram.downsampled(max_points);
+            }
+            for bin in histogram.bins() {
+                content.push_str(&format!("\n{}\t{}", orientation, bin.tsv_row()));
+            }
+        }
+        content
+    }
+}
+
+/// 处理insert_size子命令
+fn handle_insert_size_command(
+    inputs: &[&str],
+    output: Option<String>,
+    options: &InsertSizeOptions,
+    filter_report: Option<(String, OutputFormat)>,
+    histogram: Option<HistogramOutput>,
+    expectation: Option<InsertSizeExpectation>,
+    emit_interim: Option<InterimSpec>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let interim = emit_interim.map(InterimEmitter::new);
+    match compute_insert_size_multi(inputs, options, interim, &mut LogProgressSink::default()) {
+        Ok((median_size, mut report)) => {
+            tracing::debug!("{}", report.breakdown);
+            report.expectation = expectation.map(|expectation| expectation.check(median_size));
+            write_insert_size_reports(inputs, &options.reader, &report, filter_report.as_ref(), histogram.as_ref(), None)?;
+            write_result(output, &insert_size_result(median_size, &report))?;
+            if report.expectation.is_some_and(|result| !check_expectation(&result, None)) {
+                std::process::exit(EXIT_QC_FAILED);
+            }
+            Ok(())
+        }
+        Err(e) => {
+            error!("{}", e);
+            report_insert_size_error(&e, filter_report.as_ref(), None)?;
+            std::process::exit(e.exit_code());
+        }
+    }
+}
+
+/// 按样本处理insert_size子命令：主输出、过滤统计报告与直方图的路径都加样本后缀，
+/// 某个样本无法给出结果时继续输出其它样本，最后以非0状态退出
+fn handle_insert_size_per_sample_command(
+    input: &str,
+    output: Option<String>,
+    options: &InsertSizeOptions,
+    filter_report: Option<(String, OutputFormat)>,
+    histogram: Option<HistogramOutput>,
+    expectation: Option<InsertSizeExpectation>,
+    emit_interim: Option<InterimSpec>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if emit_interim.is_some() {
+        tracing::warn!("按样本输出时不支持--emit-interim，已忽略");
+    }
+    let mut results = match compute_insert_size_per_sample(input, options) {
+        Ok(results) => results,
+        Err(e) => {
+            error!("{}", e);
+            report_insert_size_error(&e, filter_report.as_ref(), None)?;
+            std::process::exit(e.exit_code());
+        }
+    };
+
+    if let Some(expectation) = expectation {
+        for (_, result) in results.iter_mut() {
+            if let Ok((median_size, report)) = result {
+                report.expectation = Some(expectation.check(*median_size));
+            }
+        }
+    }
+    let samples: Vec<&str> = results.iter().map(|(sample, _)| sample.as_str()).collect();
+    let mut failed = false;
+    let mut gate_failed = false;
+    let mut sections = Vec::new();
+    for ((sample, result), suffix) in results.iter().zip(sample_file_names(&samples)) {
+        let (median_size, report) = match result {
+            Ok(result) => result,
+            Err(e) => {
+                error!("样本 {}: {}", sample, e);
+                report_insert_size_error(e, filter_report.as_ref(), Some(&suffix))?;
+                if e.exit_code() == EXIT_QC_FAILED {
+                    gate_failed = true;
+                } else {
+                    failed = true;
+                }
+                continue;
+            }
+        };
+        tracing::debug!("样本 {}:\n{}", sample, report.breakdown);
+        write_insert_size_reports(&[input], &options.reader, report, filter_report.as_ref(), histogram.as_ref(), Some(&suffix))?;
+        if let Some(result) = &report.expectation {
+            gate_failed |= !check_expectation(result, Some(sample));
+        }
+        let result = insert_size_result(*median_size, report);
+        match &output {
+            Some(output) => write_result(Some(sample_output_path(output, &suffix)), &result)?,
+            None => sections.push(format!("# sample: {}\n{}", sample, result)),
+        }
+    }
+    if !sections.is_empty() {
+        write_result(None, &sections.join("\n\n"))?;
+    }
+    if failed {
+        std::process::exit(1);
+    }
+    if gate_failed {
+        std::process::exit(EXIT_QC_FAILED);
+    }
+    Ok(())
+}
+
+/// 输出期望断言的结果（实际值、期望与偏差），返回是否通过
+fn check_expectation(result: &ExpectationResult, sample: Option<&str>) -> bool {
+    let prefix = sample.map_or_else(String::new, |sample| format!("样本 {}: ", sample));
+    let expected = match (result.expected, result.tolerance) {
+        (Some(expected), Some(tolerance)) => format!("期望 {}±{}", expected, tolerance),
+        _ => format!("期望范围 {}-{}", result.low, result.high),
+    };
+    if result.pass {
+        tracing::info!("{}insert_size {} 满足{}（偏差 {:+}）", prefix, result.observed, expected, result.delta);
+    } else {
+        error!(
+            "{}insert_size {} 不满足{}（偏差 {:+}），QC门控未通过",
+            prefix, result.observed, expected, result.delta
+        );
+    }
+    result.pass
+}
+
+/// 写出过滤统计报告与直方图；`suffix`不为None时路径加样本后缀
+fn write_insert_size_reports(
+    inputs: &[&str],
+    reader: &BamReaderOptions,
+    report: &FilterReport,
+    filter_report: Option<&(String, OutputFormat)>,
+    histogram: Option<&HistogramOutput>,
+    suffix: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let path = |path: &str| suffix.map_or_else(|| path.to_string(), |suffix| sample_output_path(path, suffix));
+    if let Some((report_path, report_format)) = filter_report {
+        let report_path = path(report_path);
+        let content = match report_format {
+            OutputFormat::Text => report.to_string(),
+            OutputFormat::Json => report_json_inputs(report, inputs, reader)?,
+        };
+        if let Err(e) = write(&report_path, content) {
+            error!("写入文件失败 {}: {}", report_path, e);
+            std::process::exit(1);
+        }
+    }
+    if let Some(histogram) = histogram {
+        let histogram_path = path(&histogram.path);
+        if let Err(e) = write(&histogram_path, histogram.render(&report.histograms)) {
+            error!("写入文件失败 {}: {}", histogram_path, e);
+            std::process::exit(1);
+        }
+    }
+    Ok(())
+}
+
+/// 无法给出insert_size时的附加输出：方向类别被阈值过滤时在标准错误打印占比表，
+/// 没有有效读对时打印各条件排除的记录数表；`--report-format json`时把结构化错误
+/// 对象（见[`bamqc_io::error_code`]）写到过滤统计报告的位置，没有有效读对时文本
+/// 格式照常写出（全0的）过滤统计报告
+fn report_insert_size_error(
+    e: &InsertSizeError,
+    filter_report: Option<&(String, OutputFormat)>,
+    suffix: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(table) = e.category_table() {
+        eprintln!("{}", table);
+    }
+    if let Some(breakdown) = e.filter_breakdown() {
+        eprintln!("{}", breakdown);
+        if let Some((reason, records)) = breakdown.dominant_rejection() {
+            eprintln!("# 排除记录最多的条件: {}（{}条）", reason, records);
+        }
+    }
+    if let Some((report_path, format)) = filter_report {
+        let report_path = suffix.map_or_else(|| report_path.clone(), |suffix| sample_output_path(report_path, suffix));
+        let content = match (format, e) {
+            (OutputFormat::Json, _) => serde_json::to_string_pretty(e)?,
+            (OutputFormat::Text, InsertSizeError::NoValidReads { report: Some(report) }) => report.to_string(),
+            (OutputFormat::Text, _) => return Ok(()),
+        };
+        if let Err(e) = write(&report_path, content) {
+            error!("写入文件失败 {}: {}", report_path, e);
+            std::process::exit(1);
+        }
+    }
+    Ok(())
+}
+
+/// insert_size的主输出：不分组时为中位数，否则为分组TSV
+fn insert_size_result(median_size: i32, report: &FilterReport) -> String {
+    if report.groups.is_empty() {
+        return median_size.to_string();
+    }
+    let mut result = String::from("group\tpairs\tinsert_size\n");
+    for group in &report.groups {
+        let insert_size = group.insert_size.map_or("NA".to_string(), |v| v.to_string());
+        result.push_str(&format!("{}\t{}\t{}\n", group.group, group.pairs, insert_size));
+    }
+    result.push_str(&format!("ALL\t{}\t{}", report.counted_records, median_size));
+    let not_in_header: Vec<&str> = report
+        .groups
+        .iter()
+        .filter(|group| !group.in_header)
+        .map(|group| group.group.as_str())
+        .collect();
+    if !not_in_header.is_empty() {
+        result.push_str(&format!("\n# not_in_header: {}", not_in_header.join(",")));
+    }
+    if report.unknown_read_groups.records > 0 {
+        result.push_str(&format!(
+            "\n# unknown_read_group_records: {}",
+            report.unknown_read_groups.records
+        ));
+    }
+    result
+}
+
+/// 处理barcodes子命令
+fn handle_barcodes_command(
+    input: &str,
+    output: Option<String>,
+    options: BarcodeOptions,
+    emit_interim: Option<InterimSpec>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let interim = emit_interim.map(InterimEmitter::new);
+    match compute_barcodes(input, options, interim) {
+        Ok(summary) => write_result(output, &summary.to_string()),
+        Err(e) => {
+            error!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// flagstat的统计范围
+enum FlagstatScope {
+    /// 整个文件或单个区域
+    Whole(Option<GenomicRegion>),
+    /// BED区域文件；`per_region`时逐区域输出，`overlap`为重叠区域的归属方式
+    Regions { bed: String, per_region: bool, overlap: RegionOverlap },
+    /// 按参考序列逐行输出
+    ByContig,
+}
+
+/// 处理flagstat子命令
+fn handle_flagstat_command(
+    input: &str,
+    output: Option<String>,
+    scope: FlagstatScope,
+    max_secondary_ratio: f64,
+    emit_interim: Option<InterimSpec>,
+    options: &FlagStatOptions,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let interim = emit_interim.map(InterimEmitter::new);
+    let result = match scope {
+        FlagstatScope::Regions { bed, per_region, overlap } => compute_flagstat_per_region(input, &options.reader, &bed, overlap).map(|stats| {
+            stats.total.check_secondary_ratio(input, max_secondary_ratio);
+            if per_region {
+                stats.to_string()
+            } else {
+                stats.total_summary()
+            }
+        }),
+        FlagstatScope::ByContig => compute_flagstat_by_contig_with_options(input, options).map(|stats| {
+            stats.check_secondary_ratio(input, max_secondary_ratio);
+            stats.to_string()
+        }),
+        FlagstatScope::Whole(region) => compute_flagstat_with_options(input, region.as_ref(), interim, options).map(|stat| {
+            stat.check_secondary_ratio(input, max_secondary_ratio);
+            stat.to_string()
+        }),
+    };
+
+    match result {
+        Ok(result) => write_result(output, &result),
+        Err(e) => {
+            error!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// 处理diff-header子命令
+fn handle_diff_header_command(
+    left: &str,
+    right: &str,
+    reader: &BamReaderOptions,
+    output: Option<String>,
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match diff_header_files(left, right, reader) {
+        Ok(diff) => {
+            let result = match format {
+                OutputFormat::Text => diff.to_string(),
+                OutputFormat::Json => serde_json::to_string_pretty(&WithInput {
+                    report: &diff,
+                    input: [input_manifest(left, reader), input_manifest(right, reader)],
+                })?,
+            };
+            write_result(output, &result)?;
+            if !diff.is_compatible() {
+                error!("参考序列字典不兼容: {} vs {}", left, right);
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        Err(e) => exit_with_error(&e, output, format),
+    }
+}
+
+/// 处理duplication子命令
+fn handle_duplication_command(
+    input: &str,
+    reader: &BamReaderOptions,
+    output: Option<String>,
+    strict_read_groups: bool,
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match compute_duplication(input, reader, strict_read_groups) {
+        Ok(report) => {
+            let result = match format {
+                OutputFormat::Text => report.to_string(),
+                OutputFormat::Json => report_json(&report, input, reader)?,
+            };
+            write_result(output, &result)
+        }
+        Err(e) => exit_with_error(&e, output, format),
+    }
+}
+
+/// 按样本处理duplication子命令
+fn handle_duplication_per_sample_command(
+    input: &str,
+    reader: &BamReaderOptions,
+    output: Option<String>,
+    strict_read_groups: bool,
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match compute_duplication_per_sample(input, reader, strict_read_groups) {
+        Ok(reports) => {
+            let samples: Vec<String> = reports.iter().map(|r| r.sample.clone().unwrap_or_default()).collect();
+            write_sample_results(input, reader, output, &samples, &reports, format)
+        }
+        Err(e) => exit_with_error(&e, output, format),
+    }
+}
+
+/// 处理dup-sets子命令
+fn handle_dup_sets_command(
+    input: &str,
+    reader: &BamReaderOptions,
+    output: Option<String>,
+    window: u64,
+    max_open_sets: u64,
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match compute_duplicate_sets(input, reader, window, max_open_sets) {
+        Ok(report) => {
+            let result = match format {
+                OutputFormat::Text => report.to_string(),
+                OutputFormat::Json => report_json(&report, input, reader)?,
+            };
+            write_result(output, &result)
+        }
+        Err(e) => exit_with_error(&e, output, format),
+    }
+}
+
+/// 处理targets子命令
+fn handle_targets_command(
+    input: &str,
+    reader: &BamReaderOptions,
+    targets: &str,
+    extreme_targets: usize,
+    output: Option<String>,
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match compute_target_metrics(input, reader, targets, extreme_targets) {
+        Ok(report) => {
+            let result = match format {
+                OutputFormat::Text => report.to_string(),
+                OutputFormat::Json => report_json(&report, input, reader)?,
+            };
+            write_result(output, &result)
+        }
+        Err(e) => exit_with_error(&e, output, format),
+    }
+}
+
+/// 处理coverage-compare子命令
+fn handle_coverage_compare_command(
+    a: &str,
+    b: &str,
+    reader: &BamReaderOptions,
+    targets: &str,
+    log2_threshold: f64,
+    output: Option<String>,
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match compute_coverage_comparison(a, b, reader, targets, log2_threshold) {
+        Ok(comparison) => {
+            let result = match format {
+                OutputFormat::Text => comparison.to_string(),
+                OutputFormat::Json => serde_json::to_string_pretty(&WithInput {
+                    report: &comparison,
+                    input: [input_manifest(a, reader), input_manifest(b, reader)],
+                })?,
+            };
+            write_result(output, &result)
+        }
+        Err(e) => exit_with_error(&e, output, format),
+    }
+}
+
+/// 处理detect-kit子命令
+fn handle_detect_kit_command(
+    input: &str,
+    kit_library: &str,
+    options: &KitDetectOptions,
+    output: Option<String>,
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match detect_kit(input, kit_library, options) {
+        Ok(detection) => {
+            let result = match format {
+                OutputFormat::Text => detection.to_string(),
+                OutputFormat::Json => report_json(&detection, input, &options.reader)?,
+            };
+            write_result(output, &result)
+        }
+        Err(e) => exit_with_error(&e, output, format),
+    }
+}
+
+/// 处理gc-dup子命令
+fn handle_gc_dup_command(
+    input: &str,
+    output: Option<String>,
+    options: &GcDupOptions,
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match compute_gc_dup(input, options) {
+        Ok(report) => {
+            let result = match format {
+                OutputFormat::Text => report.to_string(),
+                OutputFormat::Json => report_json(&report, input, &options.reader)?,
+            };
+            write_result(output, &result)
+        }
+        Err(e) => exit_with_error(&e, output, format),
+    }
+}
+
+/// 处理quality-yield子命令
+fn handle_quality_yield_command(
+    input: &str,
+    reader: &BamReaderOptions,
+    output: Option<String>,
+    source: QualitySource,
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match compute_quality_yield(input, reader, source) {
+        Ok(report) => {
+            let result = match format {
+                OutputFormat::Text => report.to_string(),
+                OutputFormat::Json => report_json(&report, input, reader)?,
+            };
+            write_result(output, &result)
+        }
+        Err(e) => exit_with_error(&e, output, format),
+    }
+}
+
+/// 按样本处理quality-yield子命令
+fn handle_quality_yield_per_sample_command(
+    input: &str,
+    reader: &BamReaderOptions,
+    output: Option<String>,
+    source: QualitySource,
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match compute_quality_yield_per_sample(input, reader, source) {
+        Ok(reports) => {
+            let samples: Vec<String> = reports.iter().map(|r| r.sample.clone().unwrap_or_default()).collect();
+            write_sample_results(input, reader, output, &samples, &reports, format)
+        }
+        Err(e) => exit_with_error(&e, output, format),
+    }
+}
+
+/// 处理homopolymer-indels子命令
+fn handle_homopolymer_indels_command(
+    input: &str,
+    reader: &BamReaderOptions,
+    output: Option<String>,
+    min_homopolymer: usize,
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match compute_homopolymer_indels(input, reader, min_homopolymer) {
+        Ok(report) => {
+            let result = match format {
+                OutputFormat::Text => report.to_string(),
+                OutputFormat::Json => report_json(&report, input, reader)?,
+            };
+            write_result(output, &result)
+        }
+        Err(e) => exit_with_error(&e, output, format),
+    }
+}
+
+/// 处理clipping子命令
+fn handle_clipping_command(
+    input: &str,
+    reader: &BamReaderOptions,
+    output: Option<String>,
+    adapter_clip_rate: f64,
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match compute_clipping(input, reader, adapter_clip_rate) {
+        Ok(report) => {
+            let result = match format {
+                OutputFormat::Text => report.to_string(),
+                OutputFormat::Json => report_json(&report, input, reader)?,
+            };
+            write_result(output, &result)
+        }
+        Err(e) => exit_with_error(&e, output, format),
+    }
+}
+
+/// coverage的统计范围
+enum CoverageScope {
+    /// 整个文件或单个区域
+    Whole(Option<GenomicRegion>),
+    /// BED靶区文件
+    Targets(String),
+    /// 按cytoBand的染色体臂汇总：cytoBand路径、逐臂TSV路径与允许偏离
+    Arms { cytobands: String, arm_tsv: Option<String>, max_deviation: f64 },
+}
+
+/// 处理coverage子命令
+fn handle_coverage_command(
+    input: &str,
+    reader: &BamReaderOptions,
+    output: Option<String>,
+    depth_windows: u64,
+    tsv: Option<String>,
+    scope: CoverageScope,
+    tolerate_unsorted: u64,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let regions = match scope {
+        CoverageScope::Arms { cytobands, arm_tsv, max_deviation } => {
+            let Some(arm_tsv) = arm_tsv else {
+                error!("--cytobands需要用--arm-tsv（或--layout）指定逐臂汇总的输出路径");
+                std::process::exit(1);
+            };
+            let (report, arm_report) =
+                match compute_arm_metrics(input, reader, &cytobands, depth_windows, max_deviation, tolerate_unsorted) {
+                    Ok(reports) => reports,
+                    Err(e) => exit_with_error(&e, None, OutputFormat::Text),
+                };
+            if let Some(path) = tsv {
+                if let Err(e) = write(&path, report.to_string()) {
+                    error!("写入文件失败 {}: {}", path, e);
+                    std::process::exit(1);
+                }
+            }
+            if let Err(e) = write(&arm_tsv, arm_report.to_string()) {
+                error!("写入文件失败 {}: {}", arm_tsv, e);
+                std::process::exit(1);
+            }
+            return write_result(output, report.fixed_step().trim_end_matches('\n'));
+        }
+        CoverageScope::Whole(region) => region.map(|region| vec![region]),
+        CoverageScope::Targets(bed) => match read_bed(&bed) {
+            Ok(regions) if regions.is_empty() => {
+                error!("靶区文件中没有有效区域: {}", bed);
+                std::process::exit(1);
+            }
+            Ok(regions) => Some(regions),
+            Err(e) => {
+                error!("{}", e);
+                std::process::exit(1);
+            }
+        },
+    };
+
+    match compute_depth_windows(input, reader, depth_windows, regions.as_deref(), tolerate_unsorted) {
+        Ok(report) => {
+            if let Some(path) = tsv {
+                if let Err(e) = write(&path, report.to_string()) {
+                    error!("写入文件失败 {}: {}", path, e);
+                    std::process::exit(1);
+                }
+            }
+            write_result(output, report.fixed_step().trim_end_matches('\n'))
+        }
+        Err(e) => {
+            error!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// 处理tag-stats子命令
+fn handle_tag_stats_command(
+    input: &str,
+    reader: &BamReaderOptions,
+    output: Option<String>,
+    tags: Vec<TagSpec>,
+    float_bin_width: f64,
+    histogram: Option<String>,
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match compute_tag_stats(input, reader, tags, float_bin_width) {
+        Ok(report) => {
+            if let Some(path) = histogram {
+                if let Err(e) = write(&path, report.histogram_tsv()) {
+                    error!("写入文件失败 {}: {}", path, e);
+                    std::process::exit(1);
+                }
+            }
+            let result = match format {
+                OutputFormat::Text => report.to_string(),
+                OutputFormat::Json => report_json(&report, input, reader)?,
+            };
+            write_result(output, &result)
+        }
+        Err(e) => exit_with_error(&e, output, format),
+    }
+}
+
+/// 解析正的分bin宽度
+fn parse_bin_width(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(width) if width > 0.0 && width.is_finite() => Ok(width),
+        _ => Err(format!("分bin宽度必须为正数: {}", s)),
+    }
+}
+
+/// 解析--sample-fraction
+fn parse_sample_fraction(s: &str) -> Result<f64, String> {
+    let fraction = s.parse::<f64>().map_err(|_| format!("抽样比例必须为数字: {}", s))?;
+    check_sample_fraction(fraction).map_err(|e| e.to_string())
+}
+
+/// 解析--memory-budget
+fn parse_memory_budget(s: &str) -> Result<u64, String> {
+    match parse_bytes(s) {
+        Some(bytes) if bytes > 0 => Ok(bytes),
+        _ => Err(format!("无效的内存预算（正整数加可选的K、M、G）: {}", s)),
+    }
+}
+
+/// 解析--timeout与--io-stall-timeout
+fn parse_timeout(s: &str) -> Result<Duration, String> {
+    match parse_duration(s) {
+        Some(d) if !d.is_zero() => Ok(d),
+        _ => Err(format!("无效的时长（正整数加s、m、h）: {}", s)),
+    }
+}
+
+/// 处理generate-test-data子命令
+fn handle_generate_test_data_command(
+    out: &str,
+    params: &SimulationParams,
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match generate_test_data(out, params) {
+        Ok(summary) => {
+            let result = match format {
+                OutputFormat::Text => summary.to_string(),
+                OutputFormat::Json => serde_json::to_string_pretty(&summary)?,
+            };
+            write_result(None, &result)
+        }
+        Err(e) => exit_with_error(&e, None, format),
+    }
+}
+
+/// 处理view子命令
+fn handle_view_command(
+    input: &str,
+    output: Option<String>,
+    options: &ViewOptions,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let result = match &output {
+        Some(path) => std::fs::File::create(path)
+            .map_err(bamqc_io::BamError::from)
+            .and_then(|file| view_records(input, options, &mut std::io::BufWriter::new(file))),
+        None => view_records(input, options, &mut std::io::stdout().lock()),
+    };
+
+    match result {
+        Ok(written) => {
+            tracing::info!("输出 {} 条记录", written);
+            Ok(())
+        }
+        Err(e) => {
+            error!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// 处理idxstats子命令
+fn handle_idxstats_command(
+    input: &str,
+    reader: &BamReaderOptions,
+    output: Option<String>,
+    from_index: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match compute_idxstats(input, reader, from_index) {
+        Ok(stats) => write_result(output, &stats.to_string()),
+        Err(e) => {
+            error!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// 处理quick-check子命令
+fn handle_quick_check_command(
+    input: &str,
+    reader: &BamReaderOptions,
+    output: Option<String>,
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match compute_quick_check(input, reader) {
+        Ok(report) => {
+            let result = match format {
+                OutputFormat::Text => report.to_string(),
+                OutputFormat::Json => report_json(&report, input, reader)?,
+            };
+            write_result(output, &result)
+        }
+        Err(e) => exit_with_error(&e, output, format),
+    }
+}
+
+/// 处理validate子命令
+fn handle_validate_command(
+    input: &str,
+    reader: &BamReaderOptions,
+    output: Option<String>,
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match compute_flag_validation(input, reader) {
+        Ok(report) => {
+            let result = match format {
+                OutputFormat::Text => report.to_string(),
+                OutputFormat::Json => report_json(&report, input, reader)?,
+            };
+            write_result(output, &result)
+        }
+        Err(e) => exit_with_error(&e, output, format),
+    }
+}
+
+/// 处理lims子命令：四个交付物任一无法生成时以1退出，总体判定为FAIL时以3退出
+fn handle_lims_command(
+    input: &str,
+    reader: &BamReaderOptions,
+    layout: &OutputLayout,
+    preset: LimsPreset,
+    sample_name: Option<String>,
+    targets: Option<String>,
+    seed: u64,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let sample = sample_name.unwrap_or_else(|| match read_header_samples(input, reader) {
+        Ok(samples) if samples.len() == 1 => samples[0].clone(),
+        Ok(samples) if samples.is_empty() => {
+            error!("头部没有声明样本（@RG SM），请用--sample-name指定样本名");
+            std::process::exit(1);
+        }
+        Ok(samples) => {
+            error!("头部声明了 {} 个样本（{}），请用--sample-name指定样本名", samples.len(), samples.join(","));
+            std::process::exit(1);
+        }
+        Err(e) => {
+            error!("{}", e);
+            std::process::exit(1);
+        }
+    });
+    for role in LIMS_ARTIFACTS {
+        if let Err(e) = layout.claim("lims", role, false) {
+            error!("{}", e);
+            std::process::exit(1);
+        }
+    }
+
+    let options = LimsOptions { preset, sample, targets, seed, reader: reader.clone() };
+    let summary = compute_lims(input, &options).unwrap_or_else(|e| {
+        error!("{}", e);
+        std::process::exit(1);
+    });
+    if let Err(e) = write_lims_artifacts(&summary, layout.dir()) {
+        error!("{}", e);
+        std::process::exit(1);
+    }
+    for verdict in &summary.verdicts {
+        if verdict.status != VerdictStatus::Pass {
+            tracing::warn!("判定{}: {}", verdict.status, verdict.detail);
+        }
+    }
+    tracing::info!("样本 {} 的总体判定: {}，交付物已写到 {}", summary.sample, summary.status, layout.dir().display());
+    if summary.status == VerdictStatus::Fail {
+        std::process::exit(EXIT_QC_FAILED);
+    }
+    Ok(())
+}
+
+/// 处理metrics子命令：`key`为None时列出全部指标
+fn handle_metrics_command(
+    key: Option<&str>,
+    output: Option<String>,
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let defs: Vec<&MetricDef> = metric_dictionary()
+        .filter(|def| key.is_none_or(|key| def.key == key))
+        .collect();
+    if let (Some(key), true) = (key, defs.is_empty()) {
+        match suggest_metric(key) {
+            Some(suggestion) => error!("未知指标: {}（是否为 {}？）", key, suggestion),
+            None => error!("未知指标: {}", key),
+        }
+        std::process::exit(1);
+    }
+
+    let result = match (format, key) {
+        (OutputFormat::Json, _) => serde_json::to_string_pretty(&defs)?,
+        (OutputFormat::Text, None) => {
+            let mut result = String::from(MetricDef::TSV_HEADER);
+            for def in &defs {
+                result.push('\n');
+                result.push_str(&def.tsv_row());
+            }
+            result
+        }
+        (OutputFormat::Text, Some(_)) => defs
+            .iter()
+            .map(|def| def.to_string())
+            .collect::<Vec<_>>()
+            .join("\n\n"),
+    };
+    write_result(output, &result)
+}
+
+/// 处理metrics --files：列出--out-dir的标准文件名
+fn handle_metrics_files_command(
+    output: Option<String>,
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let result = match format {
+        OutputFormat::Json => serde_json::to_string_pretty(OUTPUT_FILES)?,
+        OutputFormat::Text => {
+            let mut result = String::from(OutputFile::TSV_HEADER);
+            for file in OUTPUT_FILES {
+                result.push('\n');
+                result.push_str(&file.tsv_row());
+            }
+            result
+        }
+    };
+    write_result(output, &result)
+}
+
+/// 输出路径：显式指定的路径优先，否则为--out-dir中的标准文件（已有结果且未指定--force时退出）
+fn layout_path(
+    layout: Option<&OutputLayout>,
+    explicit: Option<String>,
+    collector: &str,
+    role: &str,
+    json: bool,
+) -> Option<String> {
+    explicit.or_else(|| {
+        layout.map(|layout| {
+            layout.claim(collector, role, json).unwrap_or_else(|e| {
+                error!("{}", e);
+                std::process::exit(1);
+            })
+        })
+    })
+}
+
+/// 多样本BAM是否按样本分别输出：头部声明了多个样本且未指定`--merge-samples`
+fn stratify_samples(input: &str, reader: &BamReaderOptions, merge_samples: bool) -> bool {
+    // 头部读取失败时走原来的流程，由其报告错误
+    let samples = read_header_samples(input, reader).unwrap_or_default();
+    if samples.len() <= 1 {
+        return false;
+    }
+    if merge_samples {
+        tracing::warn!("头部声明了 {} 个样本（{}），--merge-samples合并统计", samples.len(), samples.join(","));
+        false
+    } else {
+        tracing::info!(
+            "头部声明了 {} 个样本（{}），按样本分别输出；合并统计请使用--merge-samples",
+            samples.len(),
+            samples.join(",")
+        );
+        true
+    }
+}
+
+/// 多个输入合并统计前检查样本：各输入头部共声明了多个样本时，未指定`--merge-samples`
+/// 则报错退出（多个输入不支持按样本分别输出），指定时与单个输入一样警告
+fn check_merged_samples(inputs: &[&str], reader: &BamReaderOptions, merge_samples: bool) {
+    let mut samples: Vec<String> = Vec::new();
+    // 标准输入无法预先读取头部；头部读取失败时由收集器报告错误
+    for input in inputs.iter().filter(|input| !is_stdin(input)) {
+        for sample in read_header_samples(input, reader).unwrap_or_default() {
+            if !samples.contains(&sample) {
+                samples.push(sample);
+            }
+        }
+    }
+    if samples.len() <= 1 {
+        return;
+    }
+    if merge_samples {
+        tracing::warn!("{} 个输入的头部共声明了 {} 个样本（{}），--merge-samples合并统计", inputs.len(), samples.len(), samples.join(","));
+    } else {
+        error!(
+            "{} 个输入的头部共声明了 {} 个样本（{}），多个输入不支持按样本分别输出；合并统计请使用--merge-samples",
+            inputs.len(),
+            samples.len(),
+            samples.join(",")
+        );
+        std::process::exit(1);
+    }
+}
+
+/// 按样本输出到标准输出的JSON
+#[derive(serde::Serialize)]
+struct SampleResults<'a, T> {
+    samples: &'a [T],
+}
+
+/// 在结果JSON的顶层加入`input`一节
+#[derive(serde::Serialize)]
+struct WithInput<'a, T, I> {
+    #[serde(flatten)]
+    report: &'a T,
+    input: I,
+}
+
+/// 输入文件清单（见[`bamqc_io::manifest`]），由新打开的读取器生成，记录数与BGZF块数
+/// 只来自该读取器，因此为None；无法生成时（例如输入已被删除）给出警告并返回None
+fn input_manifest(input: &str, reader: &BamReaderOptions) -> Option<InputManifest> {
+    match reader.open(input).and_then(|reader| reader.manifest()) {
+        Ok(builder) => Some(builder.finish()),
+        Err(e) => {
+            tracing::warn!("无法生成输入文件清单 {}: {}", input, e);
+            None
+        }
+    }
+}
+
+/// 结果JSON，顶层加入输入文件清单`input`
+fn report_json<T: serde::Serialize>(report: &T, input: &str, reader: &BamReaderOptions) -> serde_json::Result<String> {
+    serde_json::to_string_pretty(&WithInput { report, input: input_manifest(input, reader) })
+}
+
+/// 多个输入合并为一个结果时的结果JSON，顶层`input`为各输入的清单；只有一个输入时同[`report_json`]
+fn report_json_inputs<T: serde::Serialize>(
+    report: &T,
+    inputs: &[&str],
+    reader: &BamReaderOptions,
+) -> serde_json::Result<String> {
+    match inputs {
+        [input] => report_json(report, input, reader),
+        _ => {
+            let manifests: Vec<Option<InputManifest>> = inputs.iter().map(|input| input_manifest(input, reader)).collect();
+            serde_json::to_string_pretty(&WithInput { report, input: manifests })
+        }
+    }
+}
+
+/// 写出按样本的结果：指定输出路径时每个样本一个文件（路径加样本后缀），
+/// 否则依次输出到标准输出，JSON格式时合并为`{"samples": [...]}`
+fn write_sample_results<T: serde::Serialize + std::fmt::Display>(
+    input: &str,
+    reader: &BamReaderOptions,
+    output: Option<String>,
+    samples: &[String],
+    reports: &[T],
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match output {
+        Some(output) => {
+            for (suffix, report) in sample_file_names(samples).iter().zip(reports) {
+                let result = match format {
+                    OutputFormat::Text => report.to_string(),
+                    OutputFormat::Json => report_json(report, input, reader)?,
+                };
+                write_result(Some(sample_output_path(&output, suffix)), &result)?;
+            }
+            Ok(())
+        }
+        None => {
+            let result = match format {
+                OutputFormat::Text => reports.iter().map(|r| r.to_string()).collect::<Vec<_>>().join("\n\n"),
+                OutputFormat::Json => report_json(&SampleResults { samples: reports }, input, reader)?,
+            };
+            write_result(None, &result)
+        }
+    }
+}
+
+/// 报告错误并以错误对应的退出码退出
+///
+/// `--format json`时同时把结构化错误对象（`{"code", "message", "details"}`，
+/// 见[`bamqc_io::error_code`]）写到结果位置，供自动化解析。
+fn exit_with_error<E: ErrorCode + serde::Serialize>(e: &E, output: Option<String>, format: OutputFormat) -> ! {
+    error!("{}", e);
+    if format == OutputFormat::Json {
+        match serde_json::to_string_pretty(e) {
+            Ok(json) => {
+                let _ = write_result(output, &json);
+            }
+            Err(json_error) => error!("无法序列化错误: {}", json_error),
+        }
+    }
+    std::process::exit(e.exit_code())
+}
+
+/// 根据是否提供输出文件决定输出方式
+fn write_result(output: Option<String>, result: &str) -> Result<(), Box<dyn std::error::Error>> {
+    match output {
+        Some(output_path) => {
+            // 写入文件
+            match write(&output_path, result) {
+                Ok(_) => {
+                    println!("结果已保存到文件: {}", output_path);
+                    Ok(())
+                }
+                Err(e) => {
+                    error!("写入文件失败 {}: {}", output_path, e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        None => {
+            // 输出到标准输出
+            println!("{}", result);
+            Ok(())
         }
     }
 }
@@ -0,0 +1,812 @@
+//! 执行计划与运行前检查
+//!
+//! [`Planner`]在读取任何记录之前走一遍子命令的输入与选项：输入能否打开、头部能否
+//! 解析，头部声明的排序是否满足各收集器，需要索引时索引是否存在，参考序列与BED等
+//! 附属文件能否解析、其中的参考序列名能否对应到头部，输出目录是否可写，以及内存
+//! 估计是否在`--memory-budget`之内。每项检查产生一条[`Finding`]。
+//!
+//! `--dry-run`输出[`Plan`]后退出，不读取记录。正常运行时同一组检查在读取记录前
+//! 执行，出现错误即退出，避免遍历整个文件之后才发现缺少索引或输出路径不可写；
+//! 警告只写入调试日志，由收集器在运行中照常报告。
+
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use bamqc_core::{
+    ContigAliasResolver, LimsPreset, PairDuplicatePolicy, DEFAULT_BLOOM_BITS, DEFAULT_RUN_RECORDS,
+    MAX_NAME_GROUP_RECORDS, needs_name_grouping, read_cytobands,
+};
+use bamqc_io::{
+    BamError, BamIndex, BamReader, Format, SortOrder, has_bgzf_eof, is_stdin, read_bed, reference_dictionary_diff, resolve_format,
+};
+use noodles::sam;
+
+use crate::{Cli, Commands};
+
+/// 进程本身与BGZF解压缓冲的内存
+const BASE_MEMORY: u64 = 16 << 20;
+/// 只保存计数与小型直方图的收集器
+const SMALL_COLLECTOR_MEMORY: u64 = 4 << 20;
+/// 插入片段直方图与按组统计
+const INSERT_SIZE_MEMORY: u64 = 16 << 20;
+/// 每个未结束的重复集合（键、计数与BTreeMap节点）
+const DUP_SET_BYTES: u64 = 128;
+/// 按读名缓存的每条记录（--promote-best-secondary）
+const NAME_GROUP_RECORD_BYTES: u64 = 1 << 10;
+/// top-k计数器中的每个条形码
+const BARCODE_BYTES: u64 = 64;
+/// 外部排序中每条记录摘要
+const SORT_RECORD_BYTES: u64 = 64;
+/// 每个深度窗口
+const DEPTH_WINDOW_BYTES: u64 = 32;
+/// 每个靶区（区间、深度与MAPQ累计）
+const TARGET_BYTES: u64 = 256;
+
+/// 检查结果的严重程度
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// 通过
+    Ok,
+    /// 可以运行，但结果可能不可靠
+    Warning,
+    /// 实际运行会失败
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Ok => write!(f, "ok"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// 一项检查的结果
+#[derive(Clone, Debug)]
+pub struct Finding {
+    /// 检查项，例如`index`、`sort_order`
+    pub check: &'static str,
+    pub severity: Severity,
+    /// 被检查的对象（文件路径或收集器名）
+    pub subject: String,
+    pub message: String,
+}
+
+/// 收集器对头部声明的排序的要求
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SortRequirement {
+    /// 不满足时收集器拒绝运行
+    Required(SortOrder),
+    /// 不满足时结果可能不可靠
+    Preferred(SortOrder),
+}
+
+impl fmt::Display for SortRequirement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SortRequirement::Required(order) => write!(f, "{}（必需）", order),
+            SortRequirement::Preferred(order) => write!(f, "{}（建议）", order),
+        }
+    }
+}
+
+/// 计划中的一个收集器
+#[derive(Clone, Debug)]
+pub struct PlannedStep {
+    pub collector: &'static str,
+    /// 每个输入读取记录的遍数（只读头部或索引时为0）
+    pub passes: u32,
+    pub sort_order: Option<SortRequirement>,
+    pub needs_index: bool,
+    /// 估计的内存（字节，不含[`BASE_MEMORY`]）
+    pub memory: u64,
+}
+
+impl PlannedStep {
+    fn new(collector: &'static str, passes: u32) -> Self {
+        Self {
+            collector,
+            passes,
+            sort_order: None,
+            needs_index: false,
+            memory: if passes > 0 { SMALL_COLLECTOR_MEMORY } else { 0 },
+        }
+    }
+
+    fn sorted(mut self, requirement: SortRequirement) -> Self {
+        self.sort_order = Some(requirement);
+        self
+    }
+
+    fn indexed(mut self, needs_index: bool) -> Self {
+        self.needs_index = needs_index;
+        self
+    }
+
+    fn memory(mut self, memory: u64) -> Self {
+        self.memory = memory;
+        self
+    }
+}
+
+/// 打开成功的输入
+#[derive(Clone, Debug)]
+pub struct PlannedInput {
+    pub path: String,
+    /// 识别出的格式；标准输入为BAM
+    pub format: Format,
+    /// 头部（标准输入与打开失败时为None）
+    header: Option<sam::Header>,
+}
+
+impl PlannedInput {
+    fn sort_order(&self) -> Option<SortOrder> {
+        self.header.as_ref().map(SortOrder::from_header)
+    }
+}
+
+/// 解析后的执行计划
+#[derive(Clone, Debug)]
+pub struct Plan {
+    pub command: &'static str,
+    pub inputs: Vec<PlannedInput>,
+    /// 按运行顺序排列的收集器
+    pub steps: Vec<PlannedStep>,
+    /// 生效的记录过滤条件
+    pub filters: Vec<String>,
+    pub memory_budget: Option<u64>,
+    pub findings: Vec<Finding>,
+}
+
+impl Plan {
+    /// 每个输入读取记录的总遍数
+    pub fn passes(&self) -> u32 {
+        self.steps.iter().map(|step| step.passes).sum()
+    }
+
+    /// 估计的峰值内存：收集器依次运行，取其中最大者
+    pub fn estimated_memory(&self) -> u64 {
+        BASE_MEMORY + self.steps.iter().map(|step| step.memory).max().unwrap_or(0)
+    }
+
+    /// 是否有实际运行会失败的检查
+    pub fn has_errors(&self) -> bool {
+        self.findings.iter().any(|finding| finding.severity == Severity::Error)
+    }
+}
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "执行计划: {}", self.command)?;
+        if !self.inputs.is_empty() {
+            writeln!(f, "输入:")?;
+            for input in &self.inputs {
+                match &input.header {
+                    Some(header) => writeln!(
+                        f,
+                        "  {}\t{}\tSO:{}\t{}条参考序列",
+                        input.path,
+                        input.format,
+                        SortOrder::from_header(header),
+                        header.reference_sequences().len()
+                    )?,
+                    None => writeln!(f, "  {}\t{}", input.path, input.format)?,
+                }
+            }
+        }
+        if !self.steps.is_empty() {
+            writeln!(f, "步骤:")?;
+            for (i, step) in self.steps.iter().enumerate() {
+                writeln!(
+                    f,
+                    "  {}. {}\t读取{}遍\t排序: {}\t索引: {}\t内存: {}",
+                    i + 1,
+                    step.collector,
+                    step.passes,
+                    step.sort_order.map_or("不要求".to_string(), |order| order.to_string()),
+                    if step.needs_index { "需要" } else { "不需要" },
+                    Bytes(step.memory)
+                )?;
+            }
+        }
+        if !self.filters.is_empty() {
+            writeln!(f, "过滤条件:")?;
+            for filter in &self.filters {
+                writeln!(f, "  {}", filter)?;
+            }
+        }
+        write!(f, "共读取记录{}遍，预计内存 {}", self.passes(), Bytes(self.estimated_memory()))?;
+        match self.memory_budget {
+            Some(budget) => writeln!(f, "（预算 {}）", Bytes(budget))?,
+            None => writeln!(f)?,
+        }
+        writeln!(f, "检查:")?;
+        for finding in &self.findings {
+            writeln!(f, "  {}\t{}\t{}\t{}", finding.severity, finding.check, finding.subject, finding.message)?;
+        }
+        Ok(())
+    }
+}
+
+/// 以1024进制显示字节数
+struct Bytes(u64);
+
+impl fmt::Display for Bytes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+        let mut value = self.0 as f64;
+        let mut unit = 0;
+        while value >= 1024.0 && unit < UNITS.len() - 1 {
+            value /= 1024.0;
+            unit += 1;
+        }
+        if unit == 0 {
+            write!(f, "{} B", self.0)
+        } else {
+            write!(f, "{:.1} {}", value, UNITS[unit])
+        }
+    }
+}
+
+/// 组装执行计划并做运行前检查
+pub struct Planner<'a> {
+    cli: &'a Cli,
+    findings: Vec<Finding>,
+}
+
+impl<'a> Planner<'a> {
+    pub fn new(cli: &'a Cli) -> Self {
+        Self { cli, findings: Vec::new() }
+    }
+
+    /// 检查输入、附属文件与输出，得到执行计划；不读取任何记录
+    pub fn plan(mut self) -> Plan {
+        let command = &self.cli.command;
+        let inputs: Vec<PlannedInput> = command.inputs().into_iter().filter_map(|input| self.check_input(input)).collect();
+        let steps = self.steps(&inputs);
+        for step in &steps {
+            for input in &inputs {
+                self.check_sort_order(step, input);
+                if step.needs_index {
+                    self.check_index(step, input);
+                }
+            }
+        }
+        self.check_options();
+        self.check_side_files(&inputs);
+        self.check_reference(&inputs);
+        self.check_reference_dictionaries(&inputs);
+        self.check_outputs(&inputs);
+
+        let mut plan = Plan {
+            command: command.name(),
+            inputs,
+            steps,
+            filters: filters(command),
+            memory_budget: self.cli.memory_budget,
+            findings: Vec::new(),
+        };
+        if let Some(budget) = plan.memory_budget {
+            let estimate = plan.estimated_memory();
+            if estimate > budget {
+                self.error("memory", "--memory-budget", format!("预计内存 {} 超过预算 {}", Bytes(estimate), Bytes(budget)));
+            } else {
+                self.ok("memory", "--memory-budget", format!("预计内存 {} 在预算 {} 之内", Bytes(estimate), Bytes(budget)));
+            }
+        }
+        plan.findings = self.findings;
+        plan
+    }
+
+    fn push(&mut self, check: &'static str, severity: Severity, subject: impl Into<String>, message: String) {
+        self.findings.push(Finding { check, severity, subject: subject.into(), message });
+    }
+
+    fn ok(&mut self, check: &'static str, subject: impl Into<String>, message: String) {
+        self.push(check, Severity::Ok, subject, message);
+    }
+
+    fn warning(&mut self, check: &'static str, subject: impl Into<String>, message: String) {
+        self.push(check, Severity::Warning, subject, message);
+    }
+
+    fn error(&mut self, check: &'static str, subject: impl Into<String>, message: String) {
+        self.push(check, Severity::Error, subject, message);
+    }
+
+    /// 输入存在、格式可识别、BAM未被截断、头部可解析
+    fn check_input(&mut self, input: &str) -> Option<PlannedInput> {
+        if is_stdin(input) {
+            self.warning("input", input, "标准输入无法预先读取，打开时再检查".to_string());
+            return Some(PlannedInput { path: input.to_string(), format: Format::Bam, header: None });
+        }
+        if !Path::new(input).exists() {
+            self.error("input", input, "输入文件不存在".to_string());
+            return None;
+        }
+        // 显式指定--input-format时直接按该格式打开，不预先识别内容；内容与指定
+        // 格式不符由打开时的FormatMismatch报告
+        let format = match self.cli.input_format {
+            Format::Auto => match resolve_format(input, Format::Auto) {
+                Ok(format) => {
+                    tracing::debug!("{}: 按内容识别为{}", input, format);
+                    format
+                }
+                Err(e) => {
+                    self.error("input", input, e.to_string());
+                    return None;
+                }
+            },
+            format => format,
+        };
+        let header = match BamReader::from_path_with_format(input, format) {
+            Ok(reader) => {
+                self.ok(
+                    "input",
+                    input,
+                    format!(
+                        "{}，头部可解析（SO:{}，{}条参考序列）",
+                        format,
+                        reader.sort_order(),
+                        reader.header().reference_sequences().len()
+                    ),
+                );
+                reader.header().clone()
+            }
+            Err(e @ BamError::FormatMismatch { .. }) => {
+                self.error("input", input, e.to_string());
+                return None;
+            }
+            Err(e) => {
+                self.error("header", input, e.to_string());
+                return None;
+            }
+        };
+        // quick-check自己报告EOF标记是否缺失
+        if format == Format::Bam && !matches!(self.cli.command, Commands::QuickCheck { .. }) {
+            match has_bgzf_eof(input) {
+                Ok(true) => {}
+                Ok(false) => self.error("truncated", input, "缺少BGZF EOF标记块，文件可能被截断".to_string()),
+                Err(e) => self.error("truncated", input, e.to_string()),
+            }
+        }
+        Some(PlannedInput { path: input.to_string(), format, header: Some(header) })
+    }
+
+    /// 各子命令运行的收集器，按运行顺序排列
+    fn steps(&self, inputs: &[PlannedInput]) -> Vec<PlannedStep> {
+        let coordinate = SortRequirement::Preferred(SortOrder::Coordinate);
+        match &self.cli.command {
+            Commands::InsertSize { include_duplicates, pair_duplicate_policy, .. } => {
+                vec![insert_size_step(*include_duplicates, *pair_duplicate_policy).sorted(coordinate)]
+            }
+            Commands::Barcodes { top_k, .. } => {
+                vec![PlannedStep::new("barcodes", 1).memory(SMALL_COLLECTOR_MEMORY + *top_k as u64 * BARCODE_BYTES)]
+            }
+            Commands::Flagstat { region, regions_file, promote_best_secondary, .. } => {
+                let mut step = PlannedStep::new("flagstat", 1).indexed(region.is_some() || regions_file.is_some());
+                if *promote_best_secondary {
+                    step = step
+                        .sorted(SortRequirement::Preferred(SortOrder::QueryName))
+                        .memory(SMALL_COLLECTOR_MEMORY + MAX_NAME_GROUP_RECORDS as u64 * NAME_GROUP_RECORD_BYTES);
+                }
+                vec![step]
+            }
+            Commands::DiffHeader { .. } => vec![PlannedStep::new("diff-header", 0)],
+            Commands::Duplication { .. } => vec![PlannedStep::new("duplication", 1)],
+            Commands::DupSets { max_open_sets, .. } => vec![PlannedStep::new("dup-sets", 1)
+                .sorted(SortRequirement::Required(SortOrder::Coordinate))
+                .memory(SMALL_COLLECTOR_MEMORY + max_open_sets * DUP_SET_BYTES)],
+            Commands::Targets { targets, .. } => vec![targets_step(targets)],
+            Commands::CoverageCompare { targets, .. } => {
+                let mut step = targets_step(targets);
+                step.collector = "coverage-compare";
+                vec![step]
+            }
+            Commands::DetectKit { .. } => vec![PlannedStep::new("detect-kit", 1)],
+            Commands::GcDup { pairs, .. } => {
+                let unsorted = *pairs && inputs.iter().any(|input| input.header.as_ref().is_some_and(needs_name_grouping));
+                let step = PlannedStep::new("gc-dup", 1);
+                if unsorted {
+                    vec![step.memory(SMALL_COLLECTOR_MEMORY + DEFAULT_RUN_RECORDS as u64 * SORT_RECORD_BYTES)]
+                } else {
+                    vec![step]
+                }
+            }
+            Commands::QualityYield { .. } => vec![PlannedStep::new("quality-yield", 1)],
+            Commands::HomopolymerIndels { .. } => vec![PlannedStep::new("homopolymer-indels", 1)],
+            Commands::Clipping { .. } => vec![PlannedStep::new("clipping", 1)],
+            Commands::Coverage { depth_windows, region, targets, .. } => {
+                let genome: u64 = inputs
+                    .iter()
+                    .filter_map(|input| input.header.as_ref())
+                    .flat_map(|header| header.reference_sequences().values().map(|map| map.length().get() as u64))
+                    .sum();
+                vec![PlannedStep::new("coverage", 1)
+                    .indexed(region.is_some() || targets.is_some())
+                    .memory(SMALL_COLLECTOR_MEMORY + genome.div_ceil(*depth_windows) * DEPTH_WINDOW_BYTES)]
+            }
+            Commands::TagStats { .. } => vec![PlannedStep::new("tag-stats", 1)],
+            Commands::View { region, .. } => vec![PlannedStep::new("view", 1).indexed(region.is_some())],
+            Commands::Idxstats { from_index, .. } => {
+                vec![PlannedStep::new("idxstats", if *from_index { 0 } else { 1 }).indexed(*from_index)]
+            }
+            Commands::QuickCheck { .. } => vec![PlannedStep::new("quick-check", 0)],
+            Commands::Validate { .. } => vec![PlannedStep::new("validate", 1)],
+            Commands::Lims { preset, targets, .. } => {
+                let mut steps: Vec<PlannedStep> = preset
+                    .collectors()
+                    .iter()
+                    .map(|&collector| match collector {
+                        "quick-check" => PlannedStep::new(collector, 0),
+                        "insert-size" => insert_size_step(false, PairDuplicatePolicy::default()).sorted(coordinate),
+                        _ => PlannedStep::new(collector, 1),
+                    })
+                    .collect();
+                if let (LimsPreset::Wes, Some(targets)) = (preset, targets) {
+                    steps.push(targets_step(targets));
+                }
+                steps
+            }
+            Commands::GenerateTestData { .. } | Commands::Metrics { .. } => Vec::new(),
+        }
+    }
+
+    fn check_sort_order(&mut self, step: &PlannedStep, input: &PlannedInput) {
+        let (Some(requirement), Some(actual)) = (step.sort_order, input.sort_order()) else {
+            return;
+        };
+        let (SortRequirement::Required(expected) | SortRequirement::Preferred(expected)) = requirement;
+        let message = format!("{}要求SO:{}，头部声明为{}", step.collector, expected, actual);
+        match requirement {
+            _ if actual == expected => self.ok("sort_order", &input.path, message),
+            SortRequirement::Required(_) => self.error("sort_order", &input.path, message),
+            SortRequirement::Preferred(_) => self.warning("sort_order", &input.path, format!("{}，结果可能不可靠", message)),
+        }
+    }
+
+    fn check_index(&mut self, step: &PlannedStep, input: &PlannedInput) {
+        let subject = &input.path;
+        if is_stdin(subject) {
+            self.error("index", subject, format!("{}需要索引，标准输入不支持", step.collector));
+            return;
+        }
+        match input.format {
+            Format::Bam => match BamIndex::locate(subject) {
+                Some(path) => match BamIndex::open(&path) {
+                    Ok(index) => self.ok("index", subject, format!("{} {}", index.format, path.display())),
+                    Err(e) => self.error("index", subject, format!("{}: {}", path.display(), e)),
+                },
+                None => self.error("index", subject, format!("{}需要索引，未找到.bai或.csi（可用samtools index建立）", step.collector)),
+            },
+            Format::Cram => {
+                let candidates = [PathBuf::from(format!("{}.crai", subject)), Path::new(subject).with_extension("crai")];
+                match candidates.iter().find(|candidate| candidate.exists()) {
+                    Some(path) => self.ok("index", subject, format!("CRAI {}", path.display())),
+                    None => self.error("index", subject, format!("{}需要索引，未找到.crai", step.collector)),
+                }
+            }
+            _ => self.error("index", subject, format!("{}需要索引，{}输入不支持区域查询", step.collector, input.format)),
+        }
+    }
+
+    /// 选项之间的约束（clap无法表达的部分）
+    fn check_options(&mut self) {
+        if let Commands::Lims { preset, targets: Some(_), .. } = &self.cli.command {
+            if *preset != LimsPreset::Wes {
+                self.error("options", "--targets", format!("--targets只用于wes预设（当前为{}）", preset));
+            }
+        }
+        if matches!(self.cli.command, Commands::Lims { .. }) && self.cli.out_dir.is_none() {
+            self.error("options", "--out-dir", "lims需要--out-dir".to_string());
+        }
+    }
+
+    /// BED、cytoBand与试剂盒目录可解析，其中的参考序列名能对应到头部
+    fn check_side_files(&mut self, inputs: &[PlannedInput]) {
+        let resolvers: Vec<ContigAliasResolver> = inputs
+            .iter()
+            .filter_map(|input| input.header.as_ref().map(ContigAliasResolver::from_header))
+            .collect();
+        let check_contigs = |planner: &mut Self, check: &'static str, path: &str, contigs: Vec<String>| {
+            let mut unresolved: Vec<String> = contigs
+                .into_iter()
+                .filter(|contig| resolvers.iter().any(|resolver| resolver.resolve(contig).is_none()))
+                .collect();
+            unresolved.sort();
+            unresolved.dedup();
+            if unresolved.is_empty() {
+                planner.ok(check, path, "参考序列名都能对应到头部".to_string());
+            } else {
+                planner.warning(check, path, format!("以下参考序列不在头部中，将被忽略: {}", unresolved.join(", ")));
+            }
+        };
+
+        for (check, bed) in bed_files(&self.cli.command) {
+            match read_bed(bed) {
+                Ok(regions) => check_contigs(self, check, bed, regions.into_iter().map(|region| region.name).collect()),
+                Err(e) => self.error(check, bed, e.to_string()),
+            }
+        }
+        if let Commands::Coverage { cytobands: Some(path), .. } = &self.cli.command {
+            match read_cytobands(path) {
+                Ok(bands) => check_contigs(self, "cytobands", path, bands.into_iter().map(|band| band.contig).collect()),
+                Err(e) => self.error("cytobands", path, e.to_string()),
+            }
+        }
+        if let Commands::DetectKit { kit_library, .. } = &self.cli.command {
+            let beds: Vec<PathBuf> = match fs::read_dir(kit_library) {
+                Ok(entries) => entries
+                    .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                    .filter(|path| path.is_file() && path.extension().is_some_and(|ext| ext == "bed"))
+                    .collect(),
+                Err(e) => {
+                    self.error("kit_library", kit_library.as_str(), e.to_string());
+                    return;
+                }
+            };
+            if beds.is_empty() {
+                self.error("kit_library", kit_library.as_str(), "目录中没有.bed文件".to_string());
+            }
+            for bed in beds {
+                let path = bed.display().to_string();
+                match read_bed(&bed) {
+                    Ok(regions) => check_contigs(self, "kit_library", &path, regions.into_iter().map(|region| region.name).collect()),
+                    Err(e) => self.error("kit_library", path, e.to_string()),
+                }
+            }
+        }
+    }
+
+    /// 参考FASTA可读，头部中的每条参考序列都能在其中找到
+    fn check_reference(&mut self, inputs: &[PlannedInput]) {
+        let Some(reference) = &self.cli.reference else {
+            if let Some(input) = inputs.iter().find(|input| input.format == Format::Cram) {
+                self.warning("reference", &input.path, "未指定--reference，参考序列压缩的CRAM无法解码".to_string());
+            }
+            return;
+        };
+        let subject = reference.display().to_string();
+        let names = match fasta_names(reference) {
+            Ok(names) if names.is_empty() => {
+                self.error("reference", subject, "FASTA中没有序列".to_string());
+                return;
+            }
+            Ok(names) => names,
+            Err(e) => {
+                self.error("reference", subject, e);
+                return;
+            }
+        };
+        for input in inputs {
+            let Some(header) = &input.header else { continue };
+            let missing: Vec<String> = header
+                .reference_sequences()
+                .keys()
+                .map(|name| name.to_string())
+                .filter(|name| !names.contains(name))
+                .collect();
+            let message = format!("{}中的{}条参考序列不在参考FASTA中: {}", input.path, missing.len(), missing.join(", "));
+            match (missing.is_empty(), input.format) {
+                (true, _) => self.ok("reference", subject.as_str(), format!("{}条序列，覆盖{}的头部", names.len(), input.path)),
+                // 解码这些参考序列上的CRAM记录时会失败
+                (false, Format::Cram) => self.error("reference", subject.as_str(), message),
+                (false, _) => self.warning("reference", subject.as_str(), message),
+            }
+        }
+    }
+
+    /// 合并统计的多个输入（insert-size重复的`--input`）参考序列字典相同
+    fn check_reference_dictionaries(&mut self, inputs: &[PlannedInput]) {
+        if !matches!(self.cli.command, Commands::InsertSize { .. }) {
+            return;
+        }
+        let mut headers = inputs.iter().filter_map(|input| Some((input.path.as_str(), input.header.as_ref()?)));
+        let Some((first_path, first)) = headers.next() else { return };
+        for (path, header) in headers {
+            match reference_dictionary_diff(first, header) {
+                None => self.ok("reference_dictionary", path, format!("参考序列字典与{}相同", first_path)),
+                Some(diff) => self.error(
+                    "reference_dictionary",
+                    path,
+                    format!("参考序列字典与{}不同（名称、长度与顺序须完全一致），无法合并：\n{}", first_path, diff),
+                ),
+            }
+        }
+    }
+
+    /// --out-dir、显式输出路径与临时目录可写
+    fn check_outputs(&mut self, inputs: &[PlannedInput]) {
+        if let Some(dir) = &self.cli.out_dir {
+            self.check_writable_dir("out_dir", dir);
+        }
+        for output in output_files(&self.cli.command) {
+            let parent = match Path::new(output).parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => parent,
+                _ => Path::new("."),
+            };
+            if !parent.is_dir() {
+                self.error("output", output, format!("所在目录{}不存在", parent.display()));
+                continue;
+            }
+            match probe_writable(parent) {
+                Ok(()) => self.ok("output", output, "所在目录可写".to_string()),
+                Err(e) => self.error("output", output, format!("所在目录{}不可写: {}", parent.display(), e)),
+            }
+        }
+        if let Commands::GcDup { pairs: true, tmp_dir, .. } = &self.cli.command {
+            if inputs.iter().any(|input| input.header.as_ref().is_some_and(needs_name_grouping)) {
+                let dir = tmp_dir.clone().unwrap_or_else(std::env::temp_dir);
+                self.check_writable_dir("tmp_dir", &dir);
+            }
+        }
+    }
+
+    /// 目录可写；不存在时检查能否在最近的已有上级目录中创建
+    fn check_writable_dir(&mut self, check: &'static str, dir: &Path) {
+        let subject = dir.display().to_string();
+        let existing = dir.ancestors().find(|ancestor| ancestor.as_os_str().is_empty() || ancestor.exists());
+        let existing = match existing {
+            Some(ancestor) if ancestor.as_os_str().is_empty() => Path::new("."),
+            Some(ancestor) => ancestor,
+            None => Path::new("/"),
+        };
+        if !existing.is_dir() {
+            self.error(check, subject, format!("{}不是目录", existing.display()));
+            return;
+        }
+        match probe_writable(existing) {
+            Ok(()) if existing == dir => self.ok(check, subject, "目录可写".to_string()),
+            Ok(()) => self.ok(check, subject, format!("目录不存在，将在{}中创建", existing.display())),
+            Err(e) => self.error(check, subject, format!("{}不可写: {}", existing.display(), e)),
+        }
+    }
+}
+
+fn insert_size_step(include_duplicates: bool, policy: PairDuplicatePolicy) -> PlannedStep {
+    if !include_duplicates && policy == PairDuplicatePolicy::EitherMate {
+        // 第一遍把duplicate读名记入布隆过滤器
+        PlannedStep::new("insert-size", 2).memory(INSERT_SIZE_MEMORY + DEFAULT_BLOOM_BITS / 8)
+    } else {
+        PlannedStep::new("insert-size", 1).memory(INSERT_SIZE_MEMORY)
+    }
+}
+
+/// 逐靶区的收集器按靶区做索引查询；BED无法读取时由[`Planner::check_side_files`]报告
+fn targets_step(bed: &str) -> PlannedStep {
+    let targets = read_bed(bed).map_or(0, |regions| regions.len() as u64);
+    PlannedStep::new("targets", 1).indexed(true).memory(SMALL_COLLECTOR_MEMORY + targets * TARGET_BYTES)
+}
+
+/// 子命令引用的BED文件：(检查项, 路径)
+fn bed_files(command: &Commands) -> Vec<(&'static str, &str)> {
+    match command {
+        Commands::Flagstat { regions_file: Some(bed), .. } => vec![("regions", bed)],
+        Commands::Targets { targets, .. } | Commands::CoverageCompare { targets, .. } => vec![("targets", targets)],
+        Commands::Coverage { targets: Some(bed), .. } | Commands::Lims { targets: Some(bed), .. } => vec![("targets", bed)],
+        _ => Vec::new(),
+    }
+}
+
+/// 显式指定的输出文件
+fn output_files(command: &Commands) -> Vec<&str> {
+    let outputs: Vec<&Option<String>> = match command {
+        Commands::InsertSize { output, filter_report, histogram, .. } => vec![output, filter_report, histogram],
+        Commands::Coverage { output, tsv, arm_tsv, .. } => vec![output, tsv, arm_tsv],
+        Commands::TagStats { output, histogram, .. } => vec![output, histogram],
+        Commands::Barcodes { output, .. }
+        | Commands::Flagstat { output, .. }
+        | Commands::DiffHeader { output, .. }
+        | Commands::Duplication { output, .. }
+        | Commands::DupSets { output, .. }
+        | Commands::Targets { output, .. }
+        | Commands::CoverageCompare { output, .. }
+        | Commands::DetectKit { output, .. }
+        | Commands::GcDup { output, .. }
+        | Commands::QualityYield { output, .. }
+        | Commands::HomopolymerIndels { output, .. }
+        | Commands::Clipping { output, .. }
+        | Commands::View { output, .. }
+        | Commands::Idxstats { output, .. }
+        | Commands::QuickCheck { output, .. }
+        | Commands::Validate { output, .. }
+        | Commands::Metrics { output, .. } => vec![output],
+        Commands::GenerateTestData { out, .. } => return vec![out.as_str()],
+        Commands::Lims { .. } => Vec::new(),
+    };
+    outputs.into_iter().filter_map(|output| output.as_deref()).collect()
+}
+
+/// 生效的记录过滤条件，按收集器的选项描述
+fn filters(command: &Commands) -> Vec<String> {
+    let mut filters = Vec::new();
+    match command {
+        Commands::InsertSize {
+            include_duplicates,
+            pair_duplicate_policy,
+            require_proper_pair,
+            max_insert_size,
+            min_mapq,
+            strict_mapq,
+            ..
+        } => {
+            if !include_duplicates {
+                filters.push(match pair_duplicate_policy {
+                    PairDuplicatePolicy::LeftRecord => "排除duplicate（左端记录）".to_string(),
+                    PairDuplicatePolicy::EitherMate => "排除duplicate（任一端）".to_string(),
+                });
+            }
+            if *require_proper_pair {
+                filters.push("只统计proper pair".to_string());
+            }
+            if let Some(max) = max_insert_size {
+                filters.push(format!("|TLEN| ≤ {}", max));
+            }
+            if let Some(min_mapq) = min_mapq {
+                filters.push(format!("MAPQ ≥ {}{}", min_mapq, if *strict_mapq { "（MAPQ缺失视为未通过）" } else { "" }));
+            }
+        }
+        Commands::Flagstat { region: Some(region), .. } | Commands::View { region: Some(region), .. } => {
+            filters.push(format!("区域 {}", region.label()));
+        }
+        Commands::DetectKit { stop_after, subsample, .. } => {
+            if *stop_after > 0 {
+                filters.push(format!("抽取{}条读长后停止", stop_after));
+            }
+            if *subsample < 1.0 {
+                filters.push(format!("每条读长抽取概率 {}", subsample));
+            }
+        }
+        _ => {}
+    }
+    if let Commands::View { require_flags, exclude_flags, min_mapq, limit, .. } = command {
+        if *require_flags != 0 {
+            filters.push(format!("FLAG包含 0x{:x}", require_flags));
+        }
+        if *exclude_flags != 0 {
+            filters.push(format!("FLAG不含 0x{:x}", exclude_flags));
+        }
+        if *min_mapq > 0 {
+            filters.push(format!("MAPQ ≥ {}", min_mapq));
+        }
+        if let Some(limit) = limit {
+            filters.push(format!("最多{}条记录", limit));
+        }
+    }
+    filters
+}
+
+/// 参考FASTA中的序列名：有`.fai`时取自索引，否则扫描`>`行
+fn fasta_names(path: &Path) -> Result<Vec<String>, String> {
+    if !path.exists() {
+        return Err("文件不存在".to_string());
+    }
+    let mut fai = path.as_os_str().to_os_string();
+    fai.push(".fai");
+    let (source, from_index) = if Path::new(&fai).exists() { (PathBuf::from(fai), true) } else { (path.to_path_buf(), false) };
+    let reader = BufReader::new(File::open(&source).map_err(|e| e.to_string())?);
+    let mut names = Vec::new();
+    for line in reader.lines() {
+        let line = line.map_err(|e| format!("{}: {}", source.display(), e))?;
+        let name = if from_index {
+            line.split('\t').next()
+        } else {
+            line.strip_prefix('>').and_then(|header| header.split_whitespace().next())
+        };
+        if let Some(name) = name.filter(|name| !name.is_empty()) {
+            names.push(name.to_string());
+        }
+    }
+    Ok(names)
+}
+
+/// 在目录中创建并删除一个临时文件
+fn probe_writable(dir: &Path) -> std::io::Result<()> {
+    let probe = dir.join(format!(".bamqc-write-probe-{}", std::process::id()));
+    File::create(&probe)?;
+    fs::remove_file(&probe)
+}
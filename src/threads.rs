@@ -0,0 +1,107 @@
+//! 线程数解析
+//!
+//! `--threads auto`（默认）按实际可用的并行度取值：先取
+//! `std::thread::available_parallelism`，在Linux上再用cgroup的CPU配额
+//! （v2的`cpu.max`，v1的`cpu.cfs_quota_us`/`cpu.cfs_period_us`）加以限制。
+//! 容器中available_parallelism可能返回宿主机的核数，而不是容器实际分到的配额。
+//!
+//! 线程数只在这里解析一次，其余代码只接收解析后的数值。
+
+use std::fmt;
+use std::num::NonZeroUsize;
+use std::path::Path;
+use std::str::FromStr;
+
+/// `--threads`参数
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Threads {
+    /// 按可用并行度与cgroup配额自动确定
+    #[default]
+    Auto,
+    /// 指定线程数
+    Fixed(NonZeroUsize),
+}
+
+impl FromStr for Threads {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("auto") {
+            return Ok(Threads::Auto);
+        }
+        s.parse::<NonZeroUsize>()
+            .map(Threads::Fixed)
+            .map_err(|_| format!("无效的线程数（正整数或auto）: {}", s))
+    }
+}
+
+impl fmt::Display for Threads {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Threads::Auto => write!(f, "auto"),
+            Threads::Fixed(n) => write!(f, "{}", n),
+        }
+    }
+}
+
+/// 解析`--threads`，返回线程数以及确定该值的依据（用于日志）
+pub fn resolve(threads: Threads) -> (NonZeroUsize, String) {
+    match threads {
+        Threads::Fixed(n) => (n, "--threads指定".to_string()),
+        Threads::Auto => {
+            let available = std::thread::available_parallelism().unwrap_or(NonZeroUsize::MIN);
+            match cgroup_cpu_limit(Path::new("/sys/fs/cgroup")) {
+                Some(limit) if limit < available => (
+                    limit,
+                    format!("cgroup CPU配额限制（available_parallelism为 {}）", available),
+                ),
+                _ => (available, "available_parallelism".to_string()),
+            }
+        }
+    }
+}
+
+/// 读取cgroup的CPU配额，换算为线程数（向上取整）；没有配额或无法读取时返回None
+pub(crate) fn cgroup_cpu_limit(root: &Path) -> Option<NonZeroUsize> {
+    if let Ok(content) = std::fs::read_to_string(root.join("cpu.max")) {
+        return parse_cpu_max(&content);
+    }
+    for dir in ["cpu", "cpu,cpuacct"] {
+        let dir = root.join(dir);
+        if let (Ok(quota), Ok(period)) = (
+            std::fs::read_to_string(dir.join("cpu.cfs_quota_us")),
+            std::fs::read_to_string(dir.join("cpu.cfs_period_us")),
+        ) {
+            return parse_cfs_quota(&quota, &period);
+        }
+    }
+    None
+}
+
+/// 解析cgroup v2的`cpu.max`：`"<quota> <period>"`，quota为`max`表示不限
+fn parse_cpu_max(content: &str) -> Option<NonZeroUsize> {
+    let mut fields = content.split_whitespace();
+    let quota = fields.next()?;
+    let period = fields.next().unwrap_or("100000");
+    if quota == "max" {
+        return None;
+    }
+    quota_to_threads(quota.parse().ok()?, period.parse().ok()?)
+}
+
+/// 解析cgroup v1的`cpu.cfs_quota_us`与`cpu.cfs_period_us`，quota为-1表示不限
+fn parse_cfs_quota(quota: &str, period: &str) -> Option<NonZeroUsize> {
+    let quota: i64 = quota.trim().parse().ok()?;
+    if quota <= 0 {
+        return None;
+    }
+    quota_to_threads(quota as u64, period.trim().parse().ok()?)
+}
+
+/// 配额/周期向上取整，例如150000/100000得到2
+pub(crate) fn quota_to_threads(quota: u64, period: u64) -> Option<NonZeroUsize> {
+    if period == 0 {
+        return None;
+    }
+    NonZeroUsize::new(quota.div_ceil(period) as usize)
+}
@@ -0,0 +1,88 @@
+//! CRAM的quick-check与lims：检查CRAM EOF容器而不是BGZF EOF标记，查找`.crai`而不是`.bai`；
+//! 完整的CRAM不会因为缺少BGZF EOF标记被判定为截断。
+
+use std::path::{Path, PathBuf};
+
+use bamqc_core::{compute_lims, compute_quick_check, LimsOptions, LimsPreset, VerdictStatus};
+use bamqc_io::{BamReaderOptions, CramWriter, IndexFormat};
+use bamqc_io::test_support::temp_dir;
+use noodles::sam;
+
+/// 按坐标排序的CRAM与其参考FASTA
+fn fixture(name: &str) -> (PathBuf, PathBuf) {
+    let dir = temp_dir(&format!("cram-checks-{}", name));
+    let sequence: String = (0..10000).map(|i| b"ACGGTCAT"[i * 7 % 8] as char).collect();
+    let reference = dir.join("ref.fa");
+    std::fs::write(&reference, format!(">c1\n{}\n", sequence)).unwrap();
+
+    let mut text = String::from("@HD\tVN:1.6\tSO:coordinate\n@SQ\tSN:c1\tLN:10000\n@RG\tID:rg1\tSM:S1\tLB:lib1\n");
+    for i in 0..100 {
+        let (start, mate) = (i * 40 + 1, i * 40 + 251);
+        let read = |pos: usize| &sequence[pos - 1..pos + 49];
+        text.push_str(&format!("p{i}\t99\tc1\t{start}\t60\t50M\t=\t{mate}\t300\t{}\t{}\tRG:Z:rg1\n", read(start), "I".repeat(50)));
+        text.push_str(&format!("p{i}\t147\tc1\t{mate}\t60\t50M\t=\t{start}\t-300\t{}\t{}\tRG:Z:rg1\n", read(mate), "I".repeat(50)));
+    }
+    let mut sam_reader = sam::io::Reader::new(text.as_bytes());
+    let header = sam_reader.read_header().unwrap();
+    let mut records: Vec<_> = sam_reader.record_bufs(&header).map(Result::unwrap).collect();
+    records.sort_by_key(|record| record.alignment_start());
+    let cram = dir.join("s.cram");
+    let mut writer = CramWriter::from_path(&cram, &header, &reference).unwrap();
+    for record in &records {
+        writer.write_record_buf(record).unwrap();
+    }
+    writer.finish().unwrap();
+    (cram, reference)
+}
+
+fn reader(reference: &Path) -> BamReaderOptions {
+    BamReaderOptions::new().reference(reference)
+}
+
+#[test]
+fn quick_check_reads_the_cram_eof_container_and_crai() {
+    let (cram, reference) = fixture("quick-check");
+    let path = cram.to_string_lossy();
+    let report = compute_quick_check(&path, &reader(&reference)).unwrap();
+    assert!(report.eof_marker, "{}", report);
+    assert_eq!((report.index.as_deref(), report.past_contig_end), (None, None));
+    assert!(report.is_ok(), "{}", report);
+
+    let index = noodles::cram::fs::index(&cram).unwrap();
+    let crai = PathBuf::from(format!("{}.crai", path));
+    // crai::fs::write以只读方式打开目标文件，这里自己创建
+    let mut writer = noodles::cram::crai::io::Writer::new(std::fs::File::create(&crai).unwrap());
+    writer.write_index(&index).unwrap();
+    writer.finish().unwrap();
+    let report = compute_quick_check(&path, &reader(&reference)).unwrap();
+    assert_eq!(report.index.as_deref(), Some(crai.to_string_lossy().as_ref()));
+    assert_eq!(report.index_format, Some(IndexFormat::Crai));
+    assert!(report.index_problems.is_empty(), "{}", report);
+    assert_eq!(report.past_contig_end, Some(0));
+    assert!(report.to_string().contains("eof_marker: ok\n"), "{}", report);
+
+    // 去掉EOF容器
+    let bytes = std::fs::read(&cram).unwrap();
+    std::fs::write(&cram, &bytes[..bytes.len() - 38]).unwrap();
+    let report = compute_quick_check(&path, &reader(&reference)).unwrap();
+    assert!(!report.eof_marker && !report.is_ok(), "{}", report);
+    std::fs::remove_dir_all(cram.parent().unwrap()).unwrap();
+}
+
+#[test]
+fn lims_does_not_fail_a_complete_cram() {
+    let (cram, reference) = fixture("lims");
+    let options = LimsOptions {
+        preset: LimsPreset::Wgs,
+        sample: "S1".to_string(),
+        targets: None,
+        seed: 0,
+        reader: reader(&reference),
+    };
+    let summary = compute_lims(&cram.to_string_lossy(), &options).unwrap();
+    let eof = summary.verdicts.iter().find(|verdict| verdict.check == "eof_marker").unwrap();
+    assert_eq!(eof.status, VerdictStatus::Pass, "{}", eof.detail);
+    assert_ne!(summary.status, VerdictStatus::Fail, "{:?}", summary.verdicts);
+    assert_eq!(summary.collectors["quick-check"]["eof_marker"], true);
+    std::fs::remove_dir_all(cram.parent().unwrap()).unwrap();
+}
@@ -0,0 +1,30 @@
+//! `bamqc diff-header`的退出码：参考序列字典兼容时为0，不兼容时为1
+
+use std::process::Command;
+
+use bamqc_io::BamWriter;
+use bamqc_io::test_support::temp_dir;
+use noodles::sam;
+
+#[test]
+fn exits_nonzero_only_for_incompatible_dictionaries() {
+    let dir = temp_dir("diff-header-cli");
+    let write = |name: &str, sq: &str| {
+        let path = dir.join(name);
+        let header: sam::Header = format!("@HD\tVN:1.6\n{}", sq).parse().unwrap();
+        BamWriter::from_path(&path, &header).unwrap().finish().unwrap();
+        path.to_string_lossy().to_string()
+    };
+    let a = write("a.bam", "@SQ\tSN:chr1\tLN:1000\n@SQ\tSN:chr2\tLN:2000\n");
+    let reordered = write("reordered.bam", "@SQ\tSN:chr2\tLN:2000\n@SQ\tSN:chr1\tLN:1000\n");
+    let longer = write("longer.bam", "@SQ\tSN:chr1\tLN:1000\n@SQ\tSN:chr2\tLN:2500\n");
+
+    let run = |right: &str| Command::new(env!("CARGO_BIN_EXE_bamqc")).args(["diff-header", &a, right]).output().unwrap();
+    let output = run(&reordered);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("dictionary: reordered\nidentical: false"));
+    let output = run(&longer);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("@SQ\tchanged\tchr2\tLN\t2000\t2500"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,292 @@
+//! `--dry-run`：每种检查失败时在执行计划中给出对应的错误或警告，且不读取记录、不写出结果
+//!
+//! 直接调用构建出的`bamqc`可执行文件，检查输出中`<严重程度>\t<检查项>\t<对象>`的行。
+
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+use bamqc_core::{generate_test_data, SimulationParams};
+use bamqc_io::{BamReader, BamWriter};
+use bamqc_io::test_support::temp_dir;
+use noodles::sam;
+
+/// 每个测试独立的临时目录
+fn fixture_dir(name: &str) -> PathBuf {
+    let dir = temp_dir(&format!("dry-run-{}", name));
+    dir
+}
+
+/// 按坐标排序并建立索引的模拟BAM
+fn sorted_bam(dir: &Path) -> String {
+    let bam = dir.join("sim.bam").to_string_lossy().to_string();
+    generate_test_data(&bam, &SimulationParams { pairs: 200, ..Default::default() }).unwrap();
+    bam
+}
+
+/// 头部声明为`sort_order`的空BAM
+fn empty_bam(dir: &Path, sort_order: &str) -> String {
+    let bam = dir.join(format!("{}.bam", sort_order)).to_string_lossy().to_string();
+    let header: sam::Header = format!("@HD\tVN:1.6\tSO:{}\n@SQ\tSN:chr1\tLN:1000\n", sort_order).parse().unwrap();
+    BamWriter::from_path(&bam, &header).unwrap().finish().unwrap();
+    bam
+}
+
+fn contig_names(bam: &str) -> Vec<String> {
+    BamReader::from_path(bam).unwrap().references().map(|(name, _)| name.to_string()).collect()
+}
+
+fn dry_run(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_bamqc")).args(args).arg("--dry-run").output().unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+/// 计划中是否有`severity`级别的`check`检查
+fn has_finding(output: &Output, severity: &str, check: &str) -> bool {
+    let prefix = format!("  {}\t{}\t", severity, check);
+    stdout(output).lines().any(|line| line.starts_with(&prefix))
+}
+
+#[track_caller]
+fn assert_fails_with(output: &Output, check: &str) {
+    assert_eq!(output.status.code(), Some(1), "{}", stdout(output));
+    assert!(has_finding(output, "error", check), "缺少error {}:\n{}", check, stdout(output));
+}
+
+#[test]
+fn valid_plan_exits_zero_without_writing_outputs() {
+    let dir = fixture_dir("valid");
+    let bam = sorted_bam(&dir);
+    let out = dir.join("insert_size.txt").to_string_lossy().to_string();
+    let output = dry_run(&[
+        "insert-size", "-i", &bam, "-o", &out, "--pair-duplicate-policy", "either-mate", "--min-mapq", "20", "--memory-budget", "1G",
+    ]);
+    let text = stdout(&output);
+    assert_eq!(output.status.code(), Some(0), "{}", text);
+    assert!(text.contains("执行计划: insert-size"), "{}", text);
+    assert!(text.contains("1. insert-size\t读取2遍"), "{}", text);
+    assert!(text.contains("排除duplicate（任一端）") && text.contains("MAPQ ≥ 20"), "{}", text);
+    for check in ["input", "sort_order", "output", "memory"] {
+        assert!(has_finding(&output, "ok", check), "缺少ok {}:\n{}", check, text);
+    }
+    assert!(!text.contains("\terror\t") && !text.contains("  error"), "{}", text);
+    assert!(!Path::new(&out).exists());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn missing_input() {
+    let dir = fixture_dir("missing");
+    let missing = dir.join("missing.bam").to_string_lossy().to_string();
+    assert_fails_with(&dry_run(&["flagstat", "-i", &missing]), "input");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn truncated_bam() {
+    let dir = fixture_dir("truncated");
+    let bam = sorted_bam(&dir);
+    let mut data = std::fs::read(&bam).unwrap();
+    // 去掉末尾的28字节BGZF EOF标记块（可能不止一个）
+    let eof = data[data.len() - 28..].to_vec();
+    while data.ends_with(&eof) {
+        data.truncate(data.len() - 28);
+    }
+    std::fs::write(&bam, &data).unwrap();
+    assert_fails_with(&dry_run(&["flagstat", "-i", &bam]), "truncated");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn unparsable_header() {
+    let dir = fixture_dir("header");
+    let bam = dir.join("bad.bam");
+    // BAM magic之后的头部文本长度远超文件大小
+    let mut writer = noodles::bgzf::io::Writer::new(std::fs::File::create(&bam).unwrap());
+    writer.write_all(b"BAM\x01\xff\xff\xff\x7f@HD").unwrap();
+    writer.finish().unwrap();
+    assert_fails_with(&dry_run(&["flagstat", "-i", &bam.to_string_lossy()]), "header");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn sort_order_required_and_preferred() {
+    let dir = fixture_dir("sort-order");
+    let bam = empty_bam(&dir, "unsorted");
+    assert_fails_with(&dry_run(&["dup-sets", "-i", &bam]), "sort_order");
+
+    // insert-size只建议按坐标排序：给出警告但仍可运行
+    let output = dry_run(&["insert-size", "-i", &bam]);
+    assert_eq!(output.status.code(), Some(0), "{}", stdout(&output));
+    assert!(has_finding(&output, "warning", "sort_order"), "{}", stdout(&output));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn missing_index() {
+    let dir = fixture_dir("index");
+    let bam = sorted_bam(&dir);
+    let output = dry_run(&["flagstat", "-i", &bam, "--region", "chr1:1-100"]);
+    assert!(has_finding(&output, "ok", "index"), "{}", stdout(&output));
+
+    std::fs::remove_file(format!("{}.bai", bam)).unwrap();
+    assert_fails_with(&dry_run(&["flagstat", "-i", &bam, "--region", "chr1:1-100"]), "index");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn targets_bed_unparsable_or_unresolved() {
+    let dir = fixture_dir("targets");
+    let bam = sorted_bam(&dir);
+    let bed = dir.join("targets.bed");
+
+    std::fs::write(&bed, "chr1\tnot-a-number\t100\n").unwrap();
+    assert_fails_with(&dry_run(&["targets", "-i", &bam, "-t", &bed.to_string_lossy()]), "targets");
+
+    let contig = &contig_names(&bam)[0];
+    std::fs::write(&bed, format!("{}\t0\t100\nnot_in_header\t0\t100\n", contig)).unwrap();
+    let output = dry_run(&["targets", "-i", &bam, "-t", &bed.to_string_lossy()]);
+    assert_eq!(output.status.code(), Some(0), "{}", stdout(&output));
+    assert!(has_finding(&output, "warning", "targets"), "{}", stdout(&output));
+    assert!(stdout(&output).contains("not_in_header"), "{}", stdout(&output));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn unparsable_cytobands() {
+    let dir = fixture_dir("cytobands");
+    let bam = sorted_bam(&dir);
+    let cytobands = dir.join("cytoBand.txt");
+    std::fs::write(&cytobands, "chr1\tzero\t100\tp36.33\tgneg\n").unwrap();
+    let output = dry_run(&["coverage", "-i", &bam, "--depth-windows", "1000", "--cytobands", &cytobands.to_string_lossy()]);
+    assert_fails_with(&output, "cytobands");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn empty_kit_library() {
+    let dir = fixture_dir("kits");
+    let bam = sorted_bam(&dir);
+    let kits = dir.join("kits");
+    std::fs::create_dir_all(&kits).unwrap();
+    assert_fails_with(&dry_run(&["detect-kit", "-i", &bam, "--kit-library", &kits.to_string_lossy()]), "kit_library");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn reference_missing_or_incomplete() {
+    let dir = fixture_dir("reference");
+    let bam = sorted_bam(&dir);
+    let fasta = dir.join("ref.fa");
+
+    let missing = dir.join("missing.fa");
+    assert_fails_with(&dry_run(&["flagstat", "-i", &bam, "--reference", &missing.to_string_lossy()]), "reference");
+
+    let contigs = contig_names(&bam);
+    std::fs::write(&fasta, format!(">{} first contig\nACGT\n", contigs[0])).unwrap();
+    // BAM输入不需要参考解码，缺少的参考序列只是警告
+    let output = dry_run(&["flagstat", "-i", &bam, "--reference", &fasta.to_string_lossy()]);
+    assert_eq!(output.status.code(), Some(0), "{}", stdout(&output));
+    assert!(has_finding(&output, "warning", "reference"), "{}", stdout(&output));
+    assert!(stdout(&output).contains(&contigs[1]), "{}", stdout(&output));
+
+    let all: String = contigs.iter().map(|name| format!(">{}\nACGT\n", name)).collect();
+    std::fs::write(&fasta, all).unwrap();
+    let output = dry_run(&["flagstat", "-i", &bam, "--reference", &fasta.to_string_lossy()]);
+    assert!(has_finding(&output, "ok", "reference"), "{}", stdout(&output));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn insert_size_inputs_with_different_reference_dictionaries() {
+    let dir = fixture_dir("reference-dictionary");
+    let bam = sorted_bam(&dir);
+    let other = empty_bam(&dir, "coordinate");
+    let output = dry_run(&["insert-size", "-i", &bam, "-i", &bam]);
+    assert!(has_finding(&output, "ok", "reference_dictionary"), "{}", stdout(&output));
+
+    let output = dry_run(&["insert-size", "-i", &bam, "-i", &other]);
+    assert_fails_with(&output, "reference_dictionary");
+    assert!(stdout(&output).contains("+ #1 chr1 1000"), "{}", stdout(&output));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn out_dir_not_writable() {
+    let dir = fixture_dir("out-dir");
+    let bam = sorted_bam(&dir);
+    // 上级路径是普通文件，无法创建目录（以root运行时权限位不起作用）
+    let file = dir.join("file");
+    std::fs::write(&file, "").unwrap();
+    let out_dir = file.join("results");
+    assert_fails_with(&dry_run(&["flagstat", "-i", &bam, "--out-dir", &out_dir.to_string_lossy()]), "out_dir");
+    assert!(!out_dir.exists());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn output_directory_missing() {
+    let dir = fixture_dir("output");
+    let bam = sorted_bam(&dir);
+    let out = dir.join("missing").join("flagstat.txt");
+    assert_fails_with(&dry_run(&["flagstat", "-i", &bam, "-o", &out.to_string_lossy()]), "output");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn memory_budget_exceeded() {
+    let dir = fixture_dir("memory");
+    let bam = sorted_bam(&dir);
+    let output = dry_run(&["dup-sets", "-i", &bam, "--max-open-sets", "1000000", "--memory-budget", "64M"]);
+    assert_fails_with(&output, "memory");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn lims_option_conflicts() {
+    let dir = fixture_dir("lims");
+    let bam = sorted_bam(&dir);
+    let bed = dir.join("targets.bed");
+    std::fs::write(&bed, format!("{}\t0\t100\n", contig_names(&bam)[0])).unwrap();
+    let output = dry_run(&["lims", "-i", &bam, "--preset", "wgs", "--targets", &bed.to_string_lossy()]);
+    assert_fails_with(&output, "options");
+    assert!(stdout(&output).contains("lims需要--out-dir"), "{}", stdout(&output));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn flagstat_emit_interim_conflicts() {
+    let dir = fixture_dir("interim");
+    let bam = sorted_bam(&dir);
+    let bed = dir.join("targets.bed");
+    std::fs::write(&bed, format!("{}\t0\t100\n", contig_names(&bam)[0])).unwrap();
+    let interim = dir.join("interim.json").to_string_lossy().to_string();
+    let spec = format!("records=100 file={}", interim);
+    // 按区域或按参考序列统计没有阶段性输出，由参数解析直接拒绝
+    for scope in [&["--regions-file", &bed.to_string_lossy()][..], &["--by-contig"]] {
+        let mut args = vec!["flagstat", "-i", &bam, "--emit-interim", &spec];
+        args.extend_from_slice(scope);
+        let output = dry_run(&args);
+        assert_eq!(output.status.code(), Some(2), "{}", stdout(&output));
+        assert!(String::from_utf8_lossy(&output.stderr).contains("--emit-interim"));
+    }
+    assert!(!Path::new(&interim).exists());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn real_run_stops_before_reading_records() {
+    let dir = fixture_dir("real-run");
+    let bam = sorted_bam(&dir);
+    let out = dir.join("missing").join("flagstat.txt");
+    let output = Command::new(env!("CARGO_BIN_EXE_bamqc"))
+        .args(["flagstat", "-i", &bam, "-o", &out.to_string_lossy()])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(!stdout(&output).contains("开始统计"), "{}", stdout(&output));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,65 @@
+//! 插入片段直方图输出中的离群值：一个TLEN为3亿的嵌合读对不会让平滑后的直方图
+//! 逐值展开到3亿行，空白区间合并为一个计数为0的bin，总计数不变。
+
+use std::collections::HashMap;
+use std::process::Command;
+
+use bamqc_core::Histogram;
+use bamqc_io::test_support::{temp_dir, write_bam};
+
+const OUTLIER: i32 = 300_000_000;
+
+#[test]
+fn smoothing_an_extreme_outlier_stays_sparse() {
+    let mut counts: HashMap<i32, u32> = (250..=350).map(|v| (v, 10)).collect();
+    counts.insert(OUTLIER, 1);
+    let raw = Histogram::from_counts(&counts);
+
+    let smoothed = raw.smoothed(5);
+    // 250-352（最小值处截断）、中间一个空白bin、离群值及其前2个值
+    assert_eq!(smoothed.bins().len(), 103 + 1 + 3);
+    let gap = smoothed.bins()[103];
+    assert_eq!((gap.start, gap.end, gap.count), (353, OUTLIER - 3, 0.0));
+    assert!((smoothed.total() - raw.total()).abs() < 1e-6);
+    // 离群值在取值范围的末端，按实际覆盖的3个值归一化
+    let tail: Vec<(i32, f64)> = smoothed.bins()[104..].iter().map(|b| (b.start, b.count)).collect();
+    assert_eq!(tail, [(OUTLIER - 2, 1.0 / 3.0), (OUTLIER - 1, 1.0 / 3.0), (OUTLIER, 1.0 / 3.0)]);
+
+    let down = smoothed.downsampled(50);
+    assert!(down.bins().len() <= 50, "{}", down.bins().len());
+    assert!((down.total() - raw.total()).abs() < 1e-6);
+}
+
+#[test]
+fn histogram_output_with_a_chimeric_pair() {
+    let dir = temp_dir("histogram-outlier");
+    let bam = dir.join("chimeric.bam").to_string_lossy().to_string();
+    let mut text = String::from("@HD\tVN:1.6\tSO:unsorted\n@SQ\tSN:c1\tLN:400000000\n");
+    let read = format!("{}\t{}", "A".repeat(50), "I".repeat(50));
+    let mut pair = |name: &str, start: i32, tlen: i32| {
+        let mate = start + tlen - 50;
+        text.push_str(&format!("{name}\t99\tc1\t{start}\t60\t50M\t=\t{mate}\t{tlen}\t{read}\n"));
+        text.push_str(&format!("{name}\t147\tc1\t{mate}\t60\t50M\t=\t{start}\t-{tlen}\t{read}\n"));
+    };
+    for i in 0..200 {
+        pair(&format!("p{i}"), 1000 + i * 10, 300 + i % 21);
+    }
+    pair("chimeric", 5000, OUTLIER);
+    write_bam(&bam, &text);
+
+    let histogram = dir.join("histogram.tsv").to_string_lossy().to_string();
+    let output = Command::new(env!("CARGO_BIN_EXE_bamqc"))
+        .args(["insert-size", "-i", &bam, "--histogram", &histogram, "--histogram-smooth", "window=5"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let content = std::fs::read_to_string(&histogram).unwrap();
+    let rows: Vec<Vec<&str>> = content.lines().skip(1).map(|line| line.split('\t').collect()).collect();
+    // 300-322、空白bin与离群值的3个值
+    assert_eq!(rows.len(), 23 + 1 + 3, "{}", content);
+    assert_eq!(rows[23][1..], ["323", &(OUTLIER - 3).to_string(), "0"]);
+    let total: f64 = rows.iter().map(|row| row[3].parse::<f64>().unwrap()).sum();
+    assert!((total - 201.0).abs() < 1e-3, "{}", total);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
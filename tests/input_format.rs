@@ -0,0 +1,50 @@
+//! `--input-format`：显式指定的格式经由[`bamqc_io::BamReaderOptions::format`]传到收集器打开的读取器，
+//! 按该格式直接打开而不识别文件内容。
+
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+use bamqc_core::{generate_test_data, SimulationParams};
+use bamqc_io::test_support::temp_dir;
+
+/// 扩展名为`.sam`的BAM
+fn misnamed_bam(name: &str) -> PathBuf {
+    let dir = temp_dir(&format!("input-format-{}", name));
+    let path = dir.join("sim.sam");
+    generate_test_data(&path.to_string_lossy(), &SimulationParams { pairs: 100, ..Default::default() }).unwrap();
+    path
+}
+
+fn flagstat(input: &str, output: &str, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_bamqc"))
+        .args(["flagstat", "-i", input, "-o", output, "--verbose"])
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn explicit_bam_format_opens_a_sam_named_file_without_sniffing() {
+    let bam = misnamed_bam("explicit");
+    let input = bam.to_string_lossy();
+    let dir = bam.parent().unwrap();
+    let auto_out = dir.join("auto.txt").to_string_lossy().to_string();
+    let bam_out = dir.join("bam.txt").to_string_lossy().to_string();
+
+    // 自动识别时计划与收集器都按内容识别出BAM
+    let auto = flagstat(&input, &auto_out, &[]);
+    let log = String::from_utf8_lossy(&auto.stdout);
+    assert!(auto.status.success(), "{}", log);
+    assert!(log.contains("按内容识别为BAM"), "{}", log);
+
+    let explicit = flagstat(&input, &bam_out, &["--input-format", "bam"]);
+    let log = String::from_utf8_lossy(&explicit.stdout);
+    assert!(explicit.status.success(), "{}", log);
+    assert!(!log.contains("按内容识别"), "{}", log);
+    assert_eq!(std::fs::read_to_string(&bam_out).unwrap(), std::fs::read_to_string(&auto_out).unwrap());
+
+    // 指定的格式与内容不符时按指定格式打开失败
+    let sam = flagstat(&input, &dir.join("sam.txt").to_string_lossy(), &["--input-format", "sam"]);
+    assert!(!sam.status.success());
+    std::fs::remove_dir_all(dir).unwrap();
+}
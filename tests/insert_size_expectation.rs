@@ -0,0 +1,97 @@
+//! insert-size的期望断言：中位数满足`--expect`/`--tolerance`或`--expect-range`时正常退出，
+//! 偏高或偏低时以退出码3结束（输出照常写出），无效或上下限颠倒的范围是命令行参数错误；
+//! JSON过滤统计报告中包含断言结果`expectation`。
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+use bamqc_io::test_support;
+
+fn fixture_dir(name: &str) -> PathBuf {
+    let dir = test_support::temp_dir(&format!("expectation-{}", name));
+    dir
+}
+
+/// TLEN都为300的读对，中位数为300
+fn write_bam(path: &Path) -> String {
+    let mut text = String::from("@HD\tVN:1.6\tSO:unsorted\n@SQ\tSN:c1\tLN:100000\n");
+    let read = format!("{}\t{}", "A".repeat(50), "I".repeat(50));
+    for i in 0..50 {
+        let (start, mate) = (1000 + i * 10, 1250 + i * 10);
+        text.push_str(&format!("p{i}\t99\tc1\t{start}\t60\t50M\t=\t{mate}\t300\t{read}\n"));
+        text.push_str(&format!("p{i}\t147\tc1\t{mate}\t60\t50M\t=\t{start}\t-300\t{read}\n"));
+    }
+    test_support::write_bam(path, &text)
+}
+
+/// 运行insert-size，主输出与JSON过滤统计报告写到`dir`
+fn insert_size(dir: &Path, bam: &str, args: &[&str]) -> Output {
+    let _ = std::fs::remove_file(dir.join("insert_size.txt"));
+    let _ = std::fs::remove_file(dir.join("report.json"));
+    Command::new(env!("CARGO_BIN_EXE_bamqc"))
+        .args(["insert-size", "-i", bam, "-o", &dir.join("insert_size.txt").to_string_lossy()])
+        .args(["--filter-report", &dir.join("report.json").to_string_lossy(), "--report-format", "json"])
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn expectation(dir: &Path) -> serde_json::Value {
+    let report: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(dir.join("report.json")).unwrap()).unwrap();
+    report["expectation"].clone()
+}
+
+#[test]
+fn passing_and_failing_expectations_set_the_exit_code() {
+    let dir = fixture_dir("gate");
+    let bam = write_bam(&dir.join("s.bam"));
+    // (参数, 退出码, 偏差)
+    let cases: [(&[&str], i32, i64); 4] = [
+        (&["--expect", "310", "--tolerance", "10"], 0, -10),
+        (&["--expect-range", "250-350"], 0, 0),
+        // 偏高：中位数300在范围之上
+        (&["--expect-range", "200-250"], 3, 50),
+        // 偏低
+        (&["--expect", "400", "--tolerance", "20"], 3, -100),
+    ];
+    for (args, code, delta) in cases {
+        let output = insert_size(&dir, &bam, args);
+        let log = String::from_utf8_lossy(&output.stdout);
+        assert_eq!(output.status.code(), Some(code), "{:?}: {}", args, log);
+        // 未通过时输出照常写出
+        assert_eq!(std::fs::read_to_string(dir.join("insert_size.txt")).unwrap().trim(), "300", "{:?}", args);
+        let expectation = expectation(&dir);
+        assert!(expectation.is_object(), "{:?}: {}", args, expectation);
+        assert_eq!(expectation["observed"], 300, "{:?}: {}", args, expectation);
+        assert_eq!(expectation["delta"], delta, "{:?}: {}", args, expectation);
+        assert_eq!(expectation["pass"], code == 0, "{:?}: {}", args, expectation);
+        if code != 0 {
+            assert!(log.contains("QC门控未通过"), "{:?}: {}", args, log);
+        }
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn without_an_expectation_the_report_has_none() {
+    let dir = fixture_dir("none");
+    let bam = write_bam(&dir.join("s.bam"));
+    let output = insert_size(&dir, &bam, &[]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stdout));
+    assert!(expectation(&dir).is_null());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn malformed_or_reversed_ranges_are_usage_errors() {
+    let dir = fixture_dir("usage");
+    let bam = write_bam(&dir.join("s.bam"));
+    for (range, message) in [("450-300", "大于上限"), ("300", "无效的期望范围"), ("300-abc", "无效的期望范围")] {
+        let output = insert_size(&dir, &bam, &["--expect-range", range]);
+        assert_eq!(output.status.code(), Some(2), "{}", range);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains(message), "{}: {}", range, stderr);
+        assert!(!dir.join("insert_size.txt").exists(), "{}", range);
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
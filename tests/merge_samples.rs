@@ -0,0 +1,72 @@
+//! insert-size的多个输入：各输入头部共声明了多个样本时不会静默合并，
+//! 需要`--merge-samples`才合并统计。
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+use bamqc_io::test_support;
+
+fn fixture_dir(name: &str) -> PathBuf {
+    let dir = test_support::temp_dir(&format!("merge-samples-{}", name));
+    dir
+}
+
+/// 每个读组各有一批正常读对的BAM，读组`(ID, SM)`
+fn write_bam(path: &Path, read_groups: &[(&str, &str)]) -> String {
+    let mut text = String::from("@HD\tVN:1.6\tSO:unsorted\n@SQ\tSN:c1\tLN:100000\n");
+    for (id, sample) in read_groups {
+        text.push_str(&format!("@RG\tID:{id}\tSM:{sample}\n"));
+    }
+    let read = format!("{}\t{}", "A".repeat(50), "I".repeat(50));
+    for (id, _) in read_groups {
+        for i in 0..50 {
+            let (start, mate) = (1000 + i * 10, 1250 + i * 10);
+            text.push_str(&format!("{id}_{i}\t99\tc1\t{start}\t60\t50M\t=\t{mate}\t300\t{read}\tRG:Z:{id}\n"));
+            text.push_str(&format!("{id}_{i}\t147\tc1\t{mate}\t60\t50M\t=\t{start}\t-300\t{read}\tRG:Z:{id}\n"));
+        }
+    }
+    test_support::write_bam(path, &text)
+}
+
+fn insert_size(inputs: &[&str], output: &str, args: &[&str]) -> Output {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_bamqc"));
+    command.arg("insert-size");
+    for input in inputs {
+        command.args(["-i", input]);
+    }
+    command.args(["-o", output]).args(args).output().unwrap()
+}
+
+#[test]
+fn multiple_multi_sample_inputs_require_merge_samples() {
+    let dir = fixture_dir("multi");
+    let lane1 = write_bam(&dir.join("L1.bam"), &[("L1A", "A"), ("L1B", "B")]);
+    let lane2 = write_bam(&dir.join("L2.bam"), &[("L2A", "A"), ("L2B", "B")]);
+    let output = dir.join("insert_size.txt").to_string_lossy().to_string();
+
+    let rejected = insert_size(&[&lane1, &lane2], &output, &[]);
+    let log = String::from_utf8_lossy(&rejected.stdout);
+    assert_eq!(rejected.status.code(), Some(1), "{}", log);
+    assert!(log.contains("共声明了 2 个样本（A,B）") && log.contains("--merge-samples"), "{}", log);
+    assert!(!Path::new(&output).exists());
+
+    let merged = insert_size(&[&lane1, &lane2], &output, &["--merge-samples"]);
+    let log = String::from_utf8_lossy(&merged.stdout);
+    assert!(merged.status.success(), "{}", log);
+    assert!(log.contains("--merge-samples合并统计"), "{}", log);
+    assert_eq!(std::fs::read_to_string(&output).unwrap().trim(), "300");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn lanes_of_one_sample_are_merged_without_the_flag() {
+    let dir = fixture_dir("lanes");
+    let lane1 = write_bam(&dir.join("L1.bam"), &[("L1", "A")]);
+    let lane2 = write_bam(&dir.join("L2.bam"), &[("L2", "A")]);
+    let output = dir.join("insert_size.txt").to_string_lossy().to_string();
+
+    let merged = insert_size(&[&lane1, &lane2], &output, &[]);
+    assert!(merged.status.success(), "{}", String::from_utf8_lossy(&merged.stdout));
+    assert_eq!(std::fs::read_to_string(&output).unwrap().trim(), "300");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
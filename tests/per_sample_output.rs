@@ -0,0 +1,101 @@
+//! 按样本输出：insert-size、duplication与quality-yield对两个样本（SM）的BAM按样本写出
+//! 加后缀的文件（`A/x`与`A_x`清理后相同，后者加序号），输出到标准输出的JSON为
+//! `{"samples": [...]}`；单样本BAM的输出与`--merge-samples`逐字节相同，不加后缀。
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+use bamqc_io::test_support;
+
+const COMMANDS: [&str; 3] = ["insert-size", "duplication", "quality-yield"];
+
+fn fixture_dir(name: &str) -> PathBuf {
+    let dir = test_support::temp_dir(&format!("per-sample-{}", name));
+    dir
+}
+
+/// 每个样本一个读组的BAM，样本`i`的读对TLEN为`300 + 100 * i`
+fn write_bam(path: &Path, samples: &[&str]) -> String {
+    let mut text = String::from("@HD\tVN:1.6\tSO:unsorted\n@SQ\tSN:c1\tLN:100000\n");
+    for (i, sample) in samples.iter().enumerate() {
+        text.push_str(&format!("@RG\tID:rg{i}\tSM:{sample}\tLB:lib{i}\n"));
+    }
+    let read = format!("{}\t{}", "A".repeat(50), "I".repeat(50));
+    for i in 0..samples.len() {
+        let tlen = 300 + 100 * i;
+        for j in 0..30 {
+            let dup = if j < 5 * (i + 1) { 1024 } else { 0 };
+            let (start, mate) = (1000 + j * 10, 1000 + j * 10 + tlen - 50);
+            text.push_str(&format!("rg{i}_{j}\t{}\tc1\t{start}\t60\t50M\t=\t{mate}\t{tlen}\t{read}\tRG:Z:rg{i}\n", 99 + dup));
+            text.push_str(&format!("rg{i}_{j}\t{}\tc1\t{mate}\t60\t50M\t=\t{start}\t-{tlen}\t{read}\tRG:Z:rg{i}\n", 147 + dup));
+        }
+    }
+    test_support::write_bam(path, &text)
+}
+
+fn bamqc(command: &str, input: &str, args: &[&str]) -> Output {
+    let output = Command::new(env!("CARGO_BIN_EXE_bamqc")).args([command, "-i", input]).args(args).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stdout));
+    output
+}
+
+fn read(path: &Path) -> String {
+    std::fs::read_to_string(path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e))
+}
+
+#[test]
+fn colliding_sample_names_get_suffixed_files() {
+    let dir = fixture_dir("files");
+    let bam = write_bam(&dir.join("two.bam"), &["A/x", "A_x"]);
+    for command in COMMANDS {
+        let output = dir.join(format!("{}.txt", command));
+        bamqc(command, &bam, &["-o", &output.to_string_lossy()]);
+        assert!(!output.exists(), "{}", command);
+        let first = read(&dir.join(format!("{}.A_x.txt", command)));
+        let second = read(&dir.join(format!("{}.A_x-2.txt", command)));
+        match command {
+            // 主输出只有中位数
+            "insert-size" => assert_eq!((first.trim(), second.trim()), ("300", "400")),
+            "duplication" => assert!(first.starts_with("# sample: A/x\n") && second.starts_with("# sample: A_x\n")),
+            _ => assert!(first.starts_with("sample: A/x\n") && second.starts_with("sample: A_x\n")),
+        }
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn json_on_stdout_lists_samples() {
+    let dir = fixture_dir("json");
+    let bam = write_bam(&dir.join("two.bam"), &["A/x", "A_x"]);
+    for command in ["duplication", "quality-yield"] {
+        let stdout = String::from_utf8(bamqc(command, &bam, &["--format", "json"]).stdout).unwrap();
+        // 日志同样写到标准输出，JSON从单独的`{`行开始
+        let start = stdout.find("\n{\n").unwrap_or_else(|| panic!("{}", stdout)) + 1;
+        let json: serde_json::Value = serde_json::from_str(&stdout[start..]).unwrap();
+        let samples: Vec<&str> = json["samples"]
+            .as_array()
+            .unwrap_or_else(|| panic!("{}", json))
+            .iter()
+            .map(|report| report["sample"].as_str().unwrap())
+            .collect();
+        assert_eq!(samples, ["A/x", "A_x"], "{}", command);
+        assert!(json["input"].is_object(), "{}", json);
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn single_sample_output_is_unchanged() {
+    let dir = fixture_dir("single");
+    let bam = write_bam(&dir.join("one.bam"), &["S1"]);
+    for command in COMMANDS {
+        let split = dir.join(format!("{}.txt", command));
+        let merged = dir.join(format!("{}.merged.txt", command));
+        bamqc(command, &bam, &["-o", &split.to_string_lossy()]);
+        bamqc(command, &bam, &["-o", &merged.to_string_lossy(), "--merge-samples"]);
+        assert_eq!(std::fs::read(&split).unwrap(), std::fs::read(&merged).unwrap(), "{}", command);
+        assert!(!read(&split).contains("sample:"), "{}", command);
+        assert!(!dir.join(format!("{}.S1.txt", command)).exists(), "{}", command);
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,79 @@
+//! `--threads`不影响输出：同一输入在1、2、8个线程下运行各收集器与LIMS交付，
+//! TSV、文本与JSON输出逐字节相同。多线程时BAM由多线程BGZF解压读取。
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::process::Command;
+
+use bamqc_core::{generate_test_data, SimulationParams};
+use bamqc_io::test_support::temp_dir;
+
+/// 写文本标准文件到--out-dir的收集器
+const COLLECTORS: [&str; 8] =
+    ["flagstat", "insert-size", "duplication", "quality-yield", "clipping", "gc-dup", "idxstats", "homopolymer-indels"];
+
+/// 支持`--format json`的收集器
+const JSON_COLLECTORS: [&str; 5] = ["duplication", "quality-yield", "clipping", "gc-dup", "homopolymer-indels"];
+
+fn bamqc(args: &[&str]) {
+    let output = Command::new(env!("CARGO_BIN_EXE_bamqc")).args(args).output().unwrap();
+    assert!(output.status.success(), "bamqc {}:\n{}", args.join(" "), String::from_utf8_lossy(&output.stderr));
+}
+
+/// 目录下全部文件（相对路径 → 内容）
+fn read_tree(root: &Path) -> BTreeMap<String, Vec<u8>> {
+    let mut files = BTreeMap::new();
+    let mut pending = vec![root.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        for entry in std::fs::read_dir(&dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                pending.push(path);
+            } else {
+                let name = path.strip_prefix(root).unwrap().to_string_lossy().to_string();
+                files.insert(name, std::fs::read(&path).unwrap());
+            }
+        }
+    }
+    files
+}
+
+fn run_all(bam: &str, out: &Path, threads: usize) -> BTreeMap<String, Vec<u8>> {
+    let threads = threads.to_string();
+    let text_dir = out.join("collectors");
+    let text_dir = text_dir.to_string_lossy();
+    for collector in COLLECTORS {
+        bamqc(&[collector, "-i", bam, "--threads", &threads, "--out-dir", &text_dir]);
+    }
+    let json_dir = out.join("json");
+    std::fs::create_dir_all(&json_dir).unwrap();
+    for collector in JSON_COLLECTORS {
+        let output = json_dir.join(format!("{}.json", collector)).to_string_lossy().to_string();
+        bamqc(&[collector, "-i", bam, "--threads", &threads, "--format", "json", "-o", &output]);
+    }
+    let output = json_dir.join("insert-size.json").to_string_lossy().to_string();
+    bamqc(&["insert-size", "-i", bam, "--threads", &threads, "--report-format", "json", "-o", &output]);
+    let lims_dir = out.join("lims");
+    bamqc(&["lims", "-i", bam, "--preset", "wgs", "--sample-name", "S1", "--threads", &threads, "--out-dir", &lims_dir.to_string_lossy()]);
+    read_tree(out)
+}
+
+#[test]
+fn outputs_are_byte_identical_across_thread_counts() {
+    let dir = temp_dir("threads-sweep");
+    let bam = dir.join("sim.bam").to_string_lossy().to_string();
+    let params = SimulationParams { pairs: 20_000, unmapped_fraction: 0.02, secondary_rate: 0.1, ..Default::default() };
+    generate_test_data(&bam, &params).unwrap();
+
+    let baseline = run_all(&bam, &dir.join("threads-1"), 1);
+    assert!(baseline.keys().any(|name| name.ends_with(".json")) && baseline.keys().any(|name| name.ends_with(".tsv")));
+    assert!(baseline.len() > COLLECTORS.len() + JSON_COLLECTORS.len(), "{:?}", baseline.keys());
+    for threads in [2, 8] {
+        let outputs = run_all(&bam, &dir.join(format!("threads-{}", threads)), threads);
+        assert_eq!(outputs.keys().collect::<Vec<_>>(), baseline.keys().collect::<Vec<_>>(), "--threads {}", threads);
+        for (name, content) in &outputs {
+            assert!(content == &baseline[name], "--threads {}: {} 与单线程输出不同", threads, name);
+        }
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,80 @@
+//! `--out-dir`的结果复用：参考FASTA（路径或内容）与`--input-format`都参与运行键，
+//! CRAM换用不同的参考重新运行时重新计算，而不是复用按旧参考解码出的结果。
+
+use std::path::Path;
+use std::process::Command;
+
+use bamqc_io::CramWriter;
+use bamqc_io::test_support::temp_dir;
+use noodles::sam;
+
+const REUSED: &str = "复用";
+
+/// 返回(是否成功, 标准输出, 输出目录中gc-dup的结果)
+fn gc_dup(cram: &Path, reference: &Path, out_dir: &Path, extra: &[&str]) -> (bool, String, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_bamqc"))
+        .args(["gc-dup", "-i", &cram.to_string_lossy(), "--out-dir", &out_dir.to_string_lossy(), "--force"])
+        .args(["--reference", &reference.to_string_lossy()])
+        .args(extra)
+        .output()
+        .unwrap();
+    let mut results: Vec<String> = std::fs::read_dir(out_dir)
+        .map(|entries| entries.map(|entry| entry.unwrap().path()).collect::<Vec<_>>())
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|path| !path.to_string_lossy().ends_with(".manifest.json"))
+        .map(|path| std::fs::read_to_string(path).unwrap())
+        .collect();
+    results.sort();
+    (output.status.success(), String::from_utf8_lossy(&output.stdout).into_owned(), results.concat())
+}
+
+#[test]
+fn changing_the_reference_invalidates_the_cache() {
+    let dir = temp_dir("run-cache-reference");
+    let at_rich = format!(">c1\n{}\n", "AT".repeat(5000));
+    let gc_rich = format!(">c1\n{}\n", "GC".repeat(5000));
+    let reference = dir.join("ref.fa");
+    let other = dir.join("other.fa");
+    std::fs::write(&reference, &at_rich).unwrap();
+    std::fs::write(&other, &gc_rich).unwrap();
+
+    // 与参考完全相同的读长：CRAM中不存碱基，解码时从参考取回
+    let mut text = String::from("@HD\tVN:1.6\tSO:coordinate\n@SQ\tSN:c1\tLN:10000\n");
+    for i in 0..100 {
+        text.push_str(&format!("r{i}\t0\tc1\t{}\t60\t50M\t*\t0\t0\t{}\t{}\n", i * 50 + 1, "AT".repeat(25), "I".repeat(50)));
+    }
+    let mut sam_reader = sam::io::Reader::new(text.as_bytes());
+    let header = sam_reader.read_header().unwrap();
+    let cram = dir.join("reads.cram");
+    let mut writer = CramWriter::from_path(&cram, &header, &reference).unwrap();
+    for record in sam_reader.record_bufs(&header) {
+        writer.write_record_buf(&record.unwrap()).unwrap();
+    }
+    writer.finish().unwrap();
+
+    let out_dir = dir.join("out");
+    let (ok, log, result) = gc_dup(&cram, &reference, &out_dir, &[]);
+    assert!(ok && !log.contains(REUSED), "{}", log);
+    let (ok, log, cached) = gc_dup(&cram, &reference, &out_dir, &[]);
+    assert!(ok && log.contains(REUSED), "{}", log);
+    assert_eq!(cached, result);
+
+    // 换用另一个参考文件：不复用，重新解码时参考序列的校验和不符
+    let (ok, log, _) = gc_dup(&cram, &other, &out_dir, &[]);
+    assert!(!ok && !log.contains(REUSED) && log.contains("checksum mismatch"), "{}", log);
+
+    // 同一路径的参考内容被替换
+    std::fs::write(&reference, &gc_rich).unwrap();
+    let (ok, log, _) = gc_dup(&cram, &reference, &out_dir, &[]);
+    assert!(!ok && !log.contains(REUSED) && log.contains("checksum mismatch"), "{}", log);
+
+    // 恢复参考后重新计算；显式的--input-format也参与运行键
+    std::fs::write(&reference, &at_rich).unwrap();
+    let (ok, log, recomputed) = gc_dup(&cram, &reference, &out_dir, &[]);
+    assert!(ok && !log.contains(REUSED), "{}", log);
+    assert_eq!(recomputed, result);
+    let (ok, log, _) = gc_dup(&cram, &reference, &out_dir, &["--input-format", "cram"]);
+    assert!(ok && !log.contains(REUSED), "{}", log);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,72 @@
+//! `--threads auto`的cgroup配额检测：在临时目录中模拟cgroup v1/v2的文件内容
+
+#[allow(dead_code)]
+#[path = "../src/threads.rs"]
+mod threads;
+
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+
+use threads::{cgroup_cpu_limit, quota_to_threads};
+
+/// 以`files`（相对路径 -> 内容）构造的模拟cgroup根目录
+fn cgroup(name: &str, files: &[(&str, &str)]) -> PathBuf {
+    let root = std::env::temp_dir().join(format!("bamqc-cgroup-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    for (path, content) in files {
+        let path = root.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+    std::fs::create_dir_all(&root).unwrap();
+    root
+}
+
+fn limit(name: &str, files: &[(&str, &str)]) -> Option<usize> {
+    let root = cgroup(name, files);
+    let limit = cgroup_cpu_limit(&root).map(NonZeroUsize::get);
+    std::fs::remove_dir_all(&root).unwrap();
+    limit
+}
+
+#[test]
+fn cgroup_v2_cpu_max() {
+    assert_eq!(limit("v2-unlimited", &[("cpu.max", "max 100000\n")]), None);
+    assert_eq!(limit("v2-fraction", &[("cpu.max", "150000 100000\n")]), Some(2));
+    assert_eq!(limit("v2-exact", &[("cpu.max", "400000 100000\n")]), Some(4));
+    // 没有周期时按默认的100000
+    assert_eq!(limit("v2-no-period", &[("cpu.max", "50000")]), Some(1));
+}
+
+#[test]
+fn cgroup_v1_cfs_quota() {
+    let unlimited = [("cpu/cpu.cfs_quota_us", "-1\n"), ("cpu/cpu.cfs_period_us", "100000\n")];
+    assert_eq!(limit("v1-unlimited", &unlimited), None);
+    let below_period = [("cpu/cpu.cfs_quota_us", "50000\n"), ("cpu/cpu.cfs_period_us", "100000\n")];
+    assert_eq!(limit("v1-below-period", &below_period), Some(1));
+    let cpuacct = [("cpu,cpuacct/cpu.cfs_quota_us", "300000\n"), ("cpu,cpuacct/cpu.cfs_period_us", "100000\n")];
+    assert_eq!(limit("v1-cpuacct", &cpuacct), Some(3));
+}
+
+#[test]
+fn malformed_or_missing_files_mean_no_limit() {
+    assert_eq!(limit("missing", &[]), None);
+    assert_eq!(limit("v2-empty", &[("cpu.max", "")]), None);
+    assert_eq!(limit("v2-garbage", &[("cpu.max", "lots 100000\n")]), None);
+    assert_eq!(limit("v2-bad-period", &[("cpu.max", "150000 often\n")]), None);
+    assert_eq!(limit("v1-empty", &[("cpu/cpu.cfs_quota_us", ""), ("cpu/cpu.cfs_period_us", "100000\n")]), None);
+    assert_eq!(limit("v1-garbage", &[("cpu/cpu.cfs_quota_us", "x\n"), ("cpu/cpu.cfs_period_us", "100000\n")]), None);
+    // 只有quota没有period时不采用
+    assert_eq!(limit("v1-no-period", &[("cpu/cpu.cfs_quota_us", "200000\n")]), None);
+}
+
+#[test]
+fn quota_rounds_up_and_rejects_zero_period() {
+    let threads = |quota, period| quota_to_threads(quota, period).map(NonZeroUsize::get);
+    assert_eq!(threads(100000, 100000), Some(1));
+    assert_eq!(threads(100001, 100000), Some(2));
+    assert_eq!(threads(150000, 100000), Some(2));
+    assert_eq!(threads(1, 100000), Some(1));
+    assert_eq!(threads(0, 100000), None);
+    assert_eq!(threads(150000, 0), None);
+}
@@ -0,0 +1,56 @@
+//! `--verify-bgzf`：经由[`bamqc_io::BamReaderOptions::verify`]在收集器读取记录前自检，
+//! 损坏的块使命令失败并报告其压缩偏移。
+
+use std::path::{Path, PathBuf};
+
+use bamqc_io::test_support::{temp_path, write_bam};
+
+/// 写出跨越多个BGZF块的BAM，返回路径与各块的压缩偏移
+fn fixture(name: &str) -> (PathBuf, Vec<u64>) {
+    let path = temp_path(&format!("verify-bgzf-{}", name), "bam");
+    let mut text = String::from("@HD\tVN:1.6\tSO:unsorted\n@SQ\tSN:c1\tLN:100000\n");
+    for i in 0..3000 {
+        let seq: String = (0..100).map(|j| ['A', 'C', 'G', 'T'][(i * 7 + j * j) % 4]).collect();
+        text.push_str(&format!("r{i}\t0\tc1\t{}\t60\t100M\t*\t0\t0\t{seq}\t{}\n", i + 1, "I".repeat(100)));
+    }
+    write_bam(&path, &text);
+
+    // 块头第16-17字节为BSIZE（块长度减1）
+    let bytes = std::fs::read(&path).unwrap();
+    let mut offsets = Vec::new();
+    let mut offset = 0;
+    while offset < bytes.len() {
+        offsets.push(offset as u64);
+        offset += u16::from_le_bytes([bytes[offset + 16], bytes[offset + 17]]) as usize + 1;
+    }
+    (path, offsets)
+}
+
+/// 翻转`block`号块的CRC32（位于块末尾的ISIZE之前）中的一个字节
+fn corrupt_crc(path: &Path, offsets: &[u64], block: usize) {
+    let mut bytes = std::fs::read(path).unwrap();
+    let end = offsets[block + 1] as usize;
+    bytes[end - 8] ^= 0xff;
+    std::fs::write(path, bytes).unwrap();
+}
+
+#[test]
+fn verify_bgzf_flag_fails_the_command() {
+    let (path, offsets) = fixture("cli");
+    let input = path.to_string_lossy().to_string();
+    let flagstat = |extra: &[&str]| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_bamqc"))
+            .args(["flagstat", "-i", &input])
+            .args(extra)
+            .output()
+            .unwrap()
+    };
+    assert!(flagstat(&["--verify-bgzf"]).status.success());
+
+    corrupt_crc(&path, &offsets, 1);
+    let output = flagstat(&["--verify-bgzf"]);
+    let log = String::from_utf8_lossy(&output.stdout).into_owned() + &String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(log.contains(&format!("块的压缩偏移: {}", offsets[1])), "{}", log);
+    std::fs::remove_file(&path).unwrap();
+}